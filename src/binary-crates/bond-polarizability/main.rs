@@ -62,6 +62,7 @@ pub fn _main() -> FailResult<()> {
         let tensors = rsp2_bond_polarizability::Input {
             /// Kelvin.
             temperature,
+            branch: rsp2_bond_polarizability::StokesBranch::Stokes,
             /// Normal mode frequencies, in cm^-1.
             ev_frequencies: &frequencies,
             /// Normal mode eigenvectors, normalized.
@@ -93,10 +94,9 @@ fn read_dynmat(path: &str) -> FailResult<DynamicalMatrix> {
 
 fn element_mass(elem: Element) -> FailResult<f64>
 {Ok({
-    match elem {
-        Element::HYDROGEN => 1.00794,
-        Element::CARBON => 12.0107,
-        _ => failure::bail!("no default mass for element {}.", elem.symbol()),
+    match elem.mass() {
+        Some(mass) => mass,
+        None => failure::bail!("no default mass for element {}.", elem.symbol()),
     }
 })}
 
@@ -0,0 +1,5 @@
+// This file was autogenerated by `crates gen`. Do not edit!
+fn main() {
+    let version = rsp2::version::get();
+    rsp2_tasks::entry_points::elastic_constants("rsp2-elastic-constants", version);
+}
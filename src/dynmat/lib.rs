@@ -15,15 +15,17 @@
 #[macro_use] extern crate failure;
 #[macro_use] extern crate rsp2_newtype_indices;
 #[macro_use] extern crate rsp2_util_macros;
+#[cfg(test)] #[macro_use] extern crate rsp2_assert_close;
 
 use rsp2_array_types::{V3, M33, M3};
 use rsp2_soa_ops::{Perm, Permute};
-use rsp2_structure::{Coords};
+use rsp2_structure::{Coords, Lattice};
 use rsp2_structure::supercell::SupercellToken;
 use rsp2_newtype_indices::{Idx, Indexed, index_cast};
 use rsp2_sparse::{RawBee, RawCoo, RawCsr};
 use std::collections::{BTreeMap, BTreeSet};
 use slice_of_array::prelude::*;
+use rayon_cond::CondIterator;
 
 pub type FailResult<T> = Result<T, failure::Error>;
 
@@ -53,6 +55,12 @@ pub struct ForceConstants(
     pub RawCsr<M33, PrimI, SuperI>,
 );
 
+/// Dynamical matrix, stored as CSR in 3x3 blocks.
+///
+/// Only blocks for pairs of primitive sites that interact through the force constants are
+/// stored, so for a large primitive cell of `N` sites where each site has `k` neighbors this
+/// takes `O(N k)` blocks rather than the `N^2` of a dense matrix.  The sparse eigensolvers
+/// (e.g. `scipy_eigsh`) consume this directly; use `to_dense_parts` to densify it.
 #[derive(Debug, Clone)]
 pub struct DynamicalMatrix(
     pub RawCsr<Complex33, PrimI, PrimI>,
@@ -91,53 +99,169 @@ impl ForceConstants {
         sc: &SupercellToken,
     ) -> FailResult<ForceConstants>
     {
+        Self::compute_required_rows_maybe_parallel(super_displacements, force_sets, cart_rots, super_deperms, sc, false)
+    }
+
+    /// `compute_required_rows`, optionally parallelized over symmetry stars using rayon.
+    ///
+    /// The output is identical regardless of `parallel`.
+    pub fn compute_required_rows_maybe_parallel(
+        super_displacements: &[(usize, V3)],
+        force_sets: &[BTreeMap<usize, V3>],
+        cart_rots: &[M33],
+        super_deperms: &[Perm],
+        sc: &SupercellToken,
+        parallel: bool,
+    ) -> FailResult<ForceConstants>
+    {
+        let mut builder = ForceConstantsBuilder::new(cart_rots, super_deperms, sc);
+        builder.parallel(parallel);
+        for (&super_displacement, force_set) in zip_eq!(super_displacements, force_sets) {
+            builder.add_displacement(super_displacement, force_set.clone())?;
+        }
+        builder.build()
+    }
+}
+
+/// Accumulates displacements and their force sets one at a time, so that
+/// `ForceConstants` can be computed from whatever data has arrived so far.
+///
+/// This is what `ForceConstants::compute_required_rows` uses under the hood. Using it directly
+/// allows the forces from a lengthy computation to be checkpointed (see the `displacements` and
+/// `force_sets` accessors) and fed back in after a crash.
+#[derive(Debug, Clone)]
+pub struct ForceConstantsBuilder {
+    super_displacements: Vec<(usize, V3)>,
+    force_sets: Vec<BTreeMap<usize, V3>>,
+    cart_rots: Vec<M33>,
+    super_deperms: Vec<Perm>,
+    sc: SupercellToken,
+    parallel: bool,
+}
+
+impl ForceConstantsBuilder {
+    pub fn new(
+        cart_rots: &[M33],      // [sg_index] -> matrix
+        super_deperms: &[Perm], // [sg_index] -> permutation on supercell
+        sc: &SupercellToken,
+    ) -> Self {
+        assert_eq!(cart_rots.len(), super_deperms.len());
+        ForceConstantsBuilder {
+            super_displacements: vec![],
+            force_sets: vec![],
+            cart_rots: cart_rots.to_vec(),
+            super_deperms: super_deperms.to_vec(),
+            sc: sc.clone(),
+            parallel: false,
+        }
+    }
+
+    /// Solve for the rows of each symmetry star in parallel using rayon. (default: `false`)
+    ///
+    /// The output is identical regardless of this setting.
+    pub fn parallel(&mut self, parallel: bool) -> &mut Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Add the forces from a single displacement.
+    ///
+    /// The displaced atom must be an image in `ForceConstants::DESIGNATED_CELL`, and for each
+    /// symmetry star of sites in the primitive cell, only one of those sites may be displaced.
+    pub fn add_displacement(
+        &mut self,
+        super_displacement: (usize, V3),    // (super_displaced, cart_disp)
+        force_set: BTreeMap<usize, V3>,      // [affected] -> cart_force
+    ) -> FailResult<()> {
+        let (super_disp, _) = super_displacement;
+        let sc = SupercellWrapper::new(&self.sc);
+        let lattice_point = sc.atom_lattice_points()[SuperI(super_disp)];
+        if lattice_point != sc.designated_lattice_point {
+            // (NOTE: this requirement could easily be lifted by correcting the indices in
+            //        force sets by applying lattice point translations that move the displaced
+            //        atom to DESIGNATED_CELL.  I have not implemented this because I haven't
+            //        needed it.)
+            bail!(
+                "\
+                    ForceConstants currently requires input forces to use displaced atoms in \
+                    ForceConstants::DESIGNATED_CELL (= {:?}). (found atom with cell {:?})\
+                ",
+                ForceConstants::DESIGNATED_CELL,
+                sc.raw.cell_from_lattice_point(lattice_point),
+            )
+        }
+        self.super_displacements.push(super_displacement);
+        self.force_sets.push(force_set);
+        Ok(())
+    }
+
+    /// Number of displacements added so far.
+    pub fn len(&self) -> usize { self.super_displacements.len() }
+
+    pub fn is_empty(&self) -> bool { self.super_displacements.is_empty() }
+
+    /// The displacements added so far. `[displacement] -> (super_displaced, cart_disp)`
+    pub fn displacements(&self) -> &[(usize, V3)] { &self.super_displacements }
+
+    /// The force sets added so far. `[displacement][affected] -> cart_force`
+    pub fn force_sets(&self) -> &[BTreeMap<usize, V3>] { &self.force_sets }
+
+    /// Compute the force constants, failing if the displacements added so far are not
+    /// enough to determine every row.
+    pub fn build(&self) -> FailResult<ForceConstants> {
+        let (force_constants, undetermined) = self.build_partial()?;
+        if !undetermined.is_empty() {
+            bail!(
+                "the available forces do not determine the force constants for primitive sites {:?}",
+                undetermined,
+            );
+        }
+        Ok(force_constants)
+    }
+
+    /// Compute the force constants from the displacements added so far.
+    ///
+    /// Rows for primitive sites that cannot yet be determined (because their symmetry star has
+    /// no displacements, or not enough independent ones) are left empty, and the indices of
+    /// those sites are returned alongside the force constants.
+    pub fn build_partial(&self) -> FailResult<(ForceConstants, Vec<usize>)> {
         // wrap data with information about index type
         //
         // most type annotations in here are not strictly necessary, but serve as a stop-gap measure
         // to ensure that at least *something* close to the public interface stops compiling if the
         // newtyped indices in Context are changed (to remind you to check callers)
-        let super_displacements: &[(SuperI, V3)] = index_cast(super_displacements);
+        let super_displacements: &[(SuperI, V3)] = index_cast(&self.super_displacements);
         let super_displacements: &Indexed<DispI, [_]> = Indexed::from_raw_ref(super_displacements);
 
-        let force_sets: &[BTreeMap<SuperI, V3>] = index_cast(force_sets);
+        let force_sets: &[BTreeMap<SuperI, V3>] = index_cast(&self.force_sets);
         let force_sets: &Indexed<DispI, [_]> = Indexed::from_raw_ref(force_sets);
 
-        let cart_rots: &Indexed<OperI, [M33]> = Indexed::from_raw_ref(cart_rots);
-        let super_deperms: &Indexed<OperI, [Perm]> = Indexed::from_raw_ref(super_deperms);
+        let cart_rots: &Indexed<OperI, [M33]> = Indexed::from_raw_ref(&self.cart_rots);
+        let super_deperms: &Indexed<OperI, [Perm]> = Indexed::from_raw_ref(&self.super_deperms);
 
-        let sc = SupercellWrapper::new(sc);
+        let sc = SupercellWrapper::new(&self.sc);
 
         let primitive_atoms: Indexed<SuperI, Vec<PrimI>> = sc.atom_primitive_atoms();
         let lattice_points: Indexed<SuperI, Vec<V3<i32>>> = sc.atom_lattice_points();
         let primitive_atoms = &primitive_atoms[..];
         let lattice_points = &lattice_points[..];
 
+        // (`add_displacement` already checked that these are all in the designated cell)
         let displacements: Indexed<DispI, Vec<(PrimI, V3)>> = {
             super_displacements.iter()
-                .map(|&(super_disp, force)| {
-                    if lattice_points[super_disp] != sc.designated_lattice_point {
-                        // (NOTE: this requirement could easily be lifted by correcting the indices in
-                        //        force sets by applying lattice point translations that move the displaced
-                        //        atom to DESIGNATED_CELL.  I have not implemented this because I haven't
-                        //        needed it.)
-                        bail!(
-                            "\
-                                ForceConstants currently requires input forces to use displaced atoms in \
-                                ForceConstants::DESIGNATED_CELL (= {:?}). (found atom with cell {:?})\
-                            ",
-                            ForceConstants::DESIGNATED_CELL,
-                            sc.raw.cell_from_lattice_point(lattice_points[super_disp]),
-                        )
-                    }
-                    Ok((primitive_atoms[super_disp], force))
-                }).collect::<FailResult<_>>()?
+                .map(|&(super_disp, disp)| (primitive_atoms[super_disp], disp))
+                .collect()
         };
 
-        Context {
+        let (force_constants, undetermined) = Context {
             displacements: &displacements,
             sc, primitive_atoms, lattice_points, force_sets,
             cart_rots, super_deperms,
-        }.compute_force_constants()
+            parallel: self.parallel,
+        }.compute_force_constants()?;
+
+        let undetermined = undetermined.into_iter().map(|PrimI(prim)| prim).collect();
+        Ok((force_constants, undetermined))
     }
 }
 
@@ -153,24 +277,39 @@ struct Context<'ctx> {
     // Some data from `sc`
     primitive_atoms: &'ctx Indexed<SuperI, [PrimI]>,
     lattice_points:  &'ctx Indexed<SuperI, [V3<i32>]>,
+
+    // Whether to use rayon
+    parallel:        bool,
 }
 
 impl<'ctx> Context<'ctx> {
+    // Also returns the primitive sites whose rows could not be determined from the data.
+    // (these rows are left empty)
     fn compute_force_constants(
         &self,
-    ) -> FailResult<ForceConstants> {
+    ) -> FailResult<(ForceConstants, Vec<PrimI>)> {
         let (star_data, prim_data) = self.compute_symmetry_info();
 
         let representative_rows = self.compute_representative_rows(&star_data, &prim_data)?;
 
         let all_rows = self.derive_rows_by_symmetry(&star_data, &prim_data, representative_rows);
 
+        let undetermined = {
+            all_rows.iter_enumerated()
+                .filter(|(_, row)| row.is_none())
+                .map(|(prim, _)| prim)
+                .collect()
+        };
         let matrix = {
             let dim = (self.sc.raw.num_supercell_atoms(), self.sc.raw.num_supercell_atoms());
-            let map = all_rows.into_iter_enumerated().collect();
+            let map = {
+                all_rows.into_iter_enumerated()
+                    .map(|(prim, row)| (prim, row.unwrap_or_default()))
+                    .collect()
+            };
             RawBee { dim, map }.into_csr()
         };
-        Ok(ForceConstants(matrix))
+        Ok((ForceConstants(matrix), undetermined))
     }
 }
 
@@ -197,7 +336,9 @@ impl<'ctx> Context<'ctx> {
     // FIXME: It'd be nice if this used the new `Stars` type, but then the trouble is that
     //        the indices used in the displacements might not be the same indices chosen as
     //        representatives by `Stars`.
-    fn compute_symmetry_info(&self) -> (Indexed<StarI, Vec<StarData>>, Indexed<PrimI, Vec<PrimData>>) {
+    //
+    // Primitive sites that are not equivalent to any displaced site have no `PrimData`.
+    fn compute_symmetry_info(&self) -> (Indexed<StarI, Vec<StarData>>, Indexed<PrimI, Vec<Option<PrimData>>>) {
 
         // Gather displacements for each star of sites.
         //
@@ -225,7 +366,7 @@ impl<'ctx> Context<'ctx> {
         };
 
         // Gather data relating each primitive atom to its site-symmetry representative.
-        let prim_data: Indexed<PrimI, Vec<PrimData>> = {
+        let prim_data: Indexed<PrimI, Vec<Option<PrimData>>> = {
             let mut data = Indexed::<PrimI, _>::from_elem_n(None, self.sc.raw.num_primitive_atoms());
             for (star, star_data) in star_data.iter_enumerated() {
                 let representative_atom = self.sc.atom_from_lattice_point(star_data.representative, self.sc.designated_lattice_point);
@@ -245,14 +386,7 @@ impl<'ctx> Context<'ctx> {
                     existing.opers_from_rep.push(oper)
                 }
             }
-            data.into_iter_enumerated().map(|(prim, d)| d.unwrap_or_else(|| {
-                panic!(
-                    "\
-                        None of the displaced sites are equivalent under symmetry \
-                        to primitive site {}!!\
-                    ", prim,
-                );
-            })).collect()
+            data
         };
         (star_data, prim_data)
     }
@@ -261,14 +395,16 @@ impl<'ctx> Context<'ctx> {
     fn compute_representative_rows(
         &self,
         star_data: &Indexed<StarI, [StarData]>,
-        prim_data: &Indexed<PrimI, [PrimData]>,
+        prim_data: &Indexed<PrimI, [Option<PrimData>]>,
     ) -> FailResult<BTreeMap<PrimI, BTreeMap<SuperI, M3<V3<f64>>>>> {
-        let mut computed_rows: BTreeMap<PrimI, BTreeMap<SuperI, M33>> = Default::default();
-        for (star, data) in star_data.iter_enumerated() {
+        // Each star is solved independently, so this part can be done in parallel.
+        // The rows are gathered in order of `StarI` before merging, so the output
+        // does not depend on thread scheduling.
+        let compute_star_row = |star: StarI| -> FailResult<Option<(PrimI, BTreeMap<SuperI, M33>)>> {
             let StarData {
                 representative,
                 displacements: ref disp_indices,
-            } = *data;
+            } = star_data[star];
 
             // Expand the available data using symmetry to ensure we have enough
             // independent equations for pseudoinversion.
@@ -276,7 +412,8 @@ impl<'ctx> Context<'ctx> {
             // Ignore symmetry operators that map the representative to another
             // primitive site. (after we solve for the representative's force
             // constants, the others within its star are related by symmetry)
-            assert_eq!(star, prim_data[representative].star, "BUG!");
+            let representative_data = prim_data[representative].as_ref().expect("BUG!");
+            assert_eq!(star, representative_data.star, "BUG!");
 
             let (
                 row_displacements,
@@ -284,9 +421,15 @@ impl<'ctx> Context<'ctx> {
             ) = self.build_all_equations_for_representative_row(
                 representative,
                 &disp_indices,
-                &prim_data[representative].opers_from_rep,
+                &representative_data.opers_from_rep,
             );
 
+            // Not enough data has been provided for this star yet. (this is only possible
+            // when building force constants from a partial set of forces)
+            if !displacements_span_space(&row_displacements.raw) {
+                return Ok(None);
+            }
+
             use rsp2_linalg::{CMatrix, dot, left_pseudoinverse};
 
             // all forces we just computed use the same displacements,
@@ -304,11 +447,22 @@ impl<'ctx> Context<'ctx> {
                     })
                     .collect()
             };
+            Ok(Some((representative, force_constants_row)))
+        };
+
+        let stars = star_data.indices().collect::<Vec<StarI>>();
+        let star_rows: Vec<_> = {
+            CondIterator::new(stars, self.parallel)
+                .map(compute_star_row)
+                .collect::<FailResult<_>>()?
+        };
 
+        let mut computed_rows: BTreeMap<PrimI, BTreeMap<SuperI, M33>> = Default::default();
+        for (representative, force_constants_row) in star_rows.into_iter().flatten() {
             if let Some(_) = computed_rows.insert(representative, force_constants_row) {
                 panic!("(BUG) computed same row of FCs twice!?");
             }
-        } // for star
+        }
         Ok(computed_rows)
     }
 
@@ -401,22 +555,23 @@ impl<'ctx> Context<'ctx> {
     }
 
     // Input: Rows for each symmetry-star representative.
-    // Output: Rows for every primitive atom.
+    // Output: Rows for every primitive atom. (`None` for those whose representative has no row)
     fn derive_rows_by_symmetry(
         &self,
         star_data: &Indexed<StarI, [StarData]>,
-        prim_data: &Indexed<PrimI, [PrimData]>,
+        prim_data: &Indexed<PrimI, [Option<PrimData>]>,
         computed_rows: BTreeMap<PrimI, BTreeMap<SuperI, M3<V3<f64>>>>,
-    ) -> Indexed<PrimI, Vec<BTreeMap<SuperI, M3<V3<f64>>>>>
+    ) -> Indexed<PrimI, Vec<Option<BTreeMap<SuperI, M3<V3<f64>>>>>>
     {
         prim_data.iter_enumerated().map(|(prim, data)| {
-            let &PrimData { star, ref opers_from_rep } = data;
+            let &PrimData { star, ref opers_from_rep } = data.as_ref()?;
             let representative = star_data[star].representative;
-            if computed_rows.contains_key(&prim) {
-                assert_eq!(prim, representative, "(BUG!) bad input to derive_rows_by_symmetry");
-                computed_rows[&prim].clone()
+            if !computed_rows.contains_key(&representative) {
+                None
+            } else if prim == representative {
+                Some(computed_rows[&prim].clone())
             } else {
-                assert_ne!(prim, representative, "(BUG!) bad input to derive_rows_by_symmetry");
+                assert!(!computed_rows.contains_key(&prim), "(BUG!) bad input to derive_rows_by_symmetry");
 
                 // any operator will do; all should produce the same data.
                 //
@@ -445,15 +600,17 @@ impl<'ctx> Context<'ctx> {
                 // or equivalently: Phi_new = R Phi_old R.T
                 let cart_rot = self.cart_rots[oper];
 
-                computed_rows[&representative].iter()
-                    .map(|(&affected, fc_matrix)| {
-                        let affected = apply_deperm(affected);
-                        // FIXME: This could use a test where cart_rot != cart_rot.t()
-                        // (for AB blg, cart_rot is currently always able to be E or i)
-                        let fc_matrix = cart_rot * fc_matrix * cart_rot.t();
-                        (affected, fc_matrix)
-                    })
-                    .collect()
+                Some({
+                    computed_rows[&representative].iter()
+                        .map(|(&affected, fc_matrix)| {
+                            let affected = apply_deperm(affected);
+                            // FIXME: This could use a test where cart_rot != cart_rot.t()
+                            // (for AB blg, cart_rot is currently always able to be E or i)
+                            let fc_matrix = cart_rot * fc_matrix * cart_rot.t();
+                            (affected, fc_matrix)
+                        })
+                        .collect()
+                })
             }
         }).collect()
     }
@@ -531,11 +688,21 @@ impl<'ctx> Context<'ctx> {
     }
 }
 
+// Do the displacements include enough independent directions to solve for a row of
+// force constants?
+fn displacements_span_space(displacements: &[V3]) -> bool {
+    let gram = M33::from_fn(|r, c| displacements.iter().map(|d| d[r] * d[c]).sum::<f64>());
+    let scale = (gram[0][0] + gram[1][1] + gram[2][2]) / 3.0;
+    scale > 0.0 && gram.det() > 1e-8 * scale.powi(3)
+}
+
 impl ForceConstants {
     /// Compute the dynamical matrix at a q-point.
     ///
     /// The force constants do not need to contain data for rows outside the
     /// designated cell. (but if they do, it won't hurt)
+    ///
+    /// The output is built directly in CSR format and never densified.
     pub fn dynmat_at_cart_q(
         &self,
         super_coords: &Coords,
@@ -656,6 +823,93 @@ impl ForceConstants {
 
         DynamicalMatrix(matrix)
     }
+
+    /// Compute the dynamical matrix at a q-point given in fractional coordinates of the
+    /// primitive cell's reciprocal lattice.
+    ///
+    /// This sums the force constants over all images in the supercell, weighted by Bloch
+    /// phases.  `qpoint_frac = [0, 0, 0]` gives the dynamical matrix at gamma.
+    ///
+    /// The supercell matrix must be diagonal, as the primitive lattice is recovered by
+    /// dividing the supercell lattice by the periods of `sc`.
+    pub fn dynmat_at_q(
+        &self,
+        super_coords: &Coords,
+        qpoint_frac: V3,
+        sc: &SupercellToken,
+        masses: &[f64],
+    ) -> DynamicalMatrix {
+        let prim_reciprocal = primitive_lattice(super_coords, sc).reciprocal();
+        self.dynmat_at_cart_q(super_coords, qpoint_frac * &prim_reciprocal, sc, masses)
+    }
+
+    /// Compute the phonon density of states by diagonalizing the dynamical matrix
+    /// on a Γ-centered mesh of q-points, and broadening each mode into a gaussian
+    /// of standard deviation `sigma`.
+    ///
+    /// Returns `(frequencies, dos)`, where `frequencies` is an evenly spaced grid that
+    /// covers all of the modes, and `dos` is normalized to integrate to the number of
+    /// modes in the primitive cell (`3 * num_primitive_atoms`).
+    ///
+    /// "Frequencies" here are the square roots of the eigenvalues of the dynamical matrix,
+    /// in whatever units those have.  Imaginary frequencies are represented as negative.
+    pub fn phonon_dos(
+        &self,
+        super_coords: &Coords,
+        sc: &SupercellToken,
+        masses: &[f64],
+        mesh: [u32; 3],
+        sigma: f64,
+    ) -> (Vec<f64>, Vec<f64>) {
+        assert!(sigma > 0.0, "bad DOS smearing width: {}", sigma);
+        assert!(mesh.iter().all(|&n| n > 0), "bad q-point mesh: {:?}", mesh);
+
+        let mut mode_frequencies = vec![];
+        for a in 0..mesh[0] {
+            for b in 0..mesh[1] {
+                for c in 0..mesh[2] {
+                    let qpoint_frac = V3::from_fn(|k| f64::from([a, b, c][k]) / f64::from(mesh[k]));
+                    let dynmat = self.dynmat_at_q(super_coords, qpoint_frac, sc, masses);
+                    let Eigenvalues { eigenvalues } = dynmat.hermitianize().compute_eigenvalues_dense();
+                    mode_frequencies.extend(eigenvalues.into_iter().map(|x| x.signum() * x.abs().sqrt()));
+                }
+            }
+        }
+
+        // extend the grid far enough past each mode to contain virtually all of its gaussian
+        const SIGMAS_OF_PADDING: f64 = 5.0;
+        const POINTS_PER_SIGMA: f64 = 10.0;
+        let step = sigma / POINTS_PER_SIGMA;
+        let min = mode_frequencies.iter().cloned().fold(f64::INFINITY, f64::min) - SIGMAS_OF_PADDING * sigma;
+        let max = mode_frequencies.iter().cloned().fold(f64::NEG_INFINITY, f64::max) + SIGMAS_OF_PADDING * sigma;
+        let num_points = ((max - min) / step).ceil() as usize + 1;
+        let frequencies: Vec<_> = (0..num_points).map(|i| min + i as f64 * step).collect();
+
+        let num_qpoints = mesh.iter().product::<u32>() as f64;
+        let norm = 1.0 / (num_qpoints * sigma * f64::sqrt(2.0 * std::f64::consts::PI));
+        let mut dos = vec![0.0; num_points];
+        for &mode_frequency in &mode_frequencies {
+            // only visit points where the gaussian is non-negligible
+            let lo = ((mode_frequency - min) / step - SIGMAS_OF_PADDING * POINTS_PER_SIGMA).floor().max(0.0) as usize;
+            let hi = usize::min(num_points, ((mode_frequency - min) / step + SIGMAS_OF_PADDING * POINTS_PER_SIGMA).ceil() as usize + 1);
+            for i in lo..hi {
+                let x = (frequencies[i] - mode_frequency) / sigma;
+                dos[i] += norm * f64::exp(-0.5 * x * x);
+            }
+        }
+        (frequencies, dos)
+    }
+}
+
+// Recover the primitive lattice from a supercell built with a diagonal supercell matrix.
+fn primitive_lattice(super_coords: &Coords, sc: &SupercellToken) -> Lattice {
+    Lattice::from_vectors(&{
+        let mut vectors = *super_coords.lattice().vectors();
+        for (vector, &period) in vectors.iter_mut().zip(&sc.periods()) {
+            *vector /= f64::from(period);
+        }
+        vectors
+    })
 }
 
 // ------------------------------------------------------
@@ -685,6 +939,91 @@ impl ForceConstants {
     }
 }
 
+const TEXT_HEADER: &str = "# rsp2 force constants";
+
+impl ForceConstants {
+    /// Write a human-readable listing of the nonzero blocks, for debugging and for
+    /// interop with tools that can't read phonopy's HDF5 files.
+    ///
+    /// The format is a header recording the supercell periods and atom counts, followed
+    /// by one line per block of the form `prim super  xx xy xz  yx yy yz  zx zy zz`, where
+    /// `prim` is a primitive site index and `super` is a supercell site index. Values are
+    /// written with enough digits to be read back exactly.
+    pub fn write_text<W: std::io::Write>(&self, sc: &SupercellToken, mut w: W) -> FailResult<()> {
+        self.0.validate().expect("(BUG!) invalid sparse data");
+        assert_eq!(self.0.dim, (sc.num_primitive_atoms(), sc.num_supercell_atoms()));
+
+        let [a, b, c] = sc.periods();
+        writeln!(w, "{}", TEXT_HEADER)?;
+        writeln!(w, "periods {} {} {}", a, b, c)?;
+        writeln!(w, "num-primitive-atoms {}", sc.num_primitive_atoms())?;
+        writeln!(w, "num-supercell-atoms {}", sc.num_supercell_atoms())?;
+        for (PrimI(r), range) in self.0.row_ranges().into_iter_enumerated() {
+            for (&SuperI(c), m) in zip_eq!(&self.0.col[range.clone()], &self.0.val[range]) {
+                if m == &M33::zero() {
+                    continue;
+                }
+                write!(w, "{} {}", r, c)?;
+                for row in &m.0 {
+                    write!(w, "  {:?} {:?} {:?}", row[0], row[1], row[2])?;
+                }
+                writeln!(w)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the output of `write_text`.
+    ///
+    /// The header must agree with the supercell.
+    pub fn read_text<R: std::io::BufRead>(sc: &SupercellToken, r: R) -> FailResult<Self> {
+        let mut lines = r.lines();
+        let mut next_line = || -> FailResult<String> {
+            lines.next().ok_or_else(|| format_err!("unexpected end of force constants file"))?.map_err(Into::into)
+        };
+
+        ensure!(next_line()?.trim() == TEXT_HEADER, "not an rsp2 force constants file");
+        let mut read_header = |key: &str| -> FailResult<Vec<usize>> {
+            let line = next_line()?;
+            let mut words = line.split_whitespace();
+            ensure!(words.next() == Some(key), "expected '{}' in force constants header", key);
+            words.map(|word| word.parse().map_err(Into::into)).collect()
+        };
+        let periods = read_header("periods")?;
+        let num_prim = read_header("num-primitive-atoms")?;
+        let num_super = read_header("num-supercell-atoms")?;
+        ensure!(
+            periods == sc.periods().iter().map(|&x| x as usize).collect::<Vec<_>>(),
+            "force constants were written for a supercell with periods {:?}", periods,
+        );
+        ensure!(
+            num_prim == [sc.num_primitive_atoms()] && num_super == [sc.num_supercell_atoms()],
+            "force constants were written for a structure with a different number of atoms",
+        );
+
+        let dim = (sc.num_primitive_atoms(), sc.num_supercell_atoms());
+        let (mut row, mut col, mut val) = (vec![], vec![], vec![]);
+        for line in lines {
+            let line = line?;
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            if words.is_empty() {
+                continue;
+            }
+            ensure!(words.len() == 11, "bad force constants line: {:?}", line);
+
+            let r: usize = words[0].parse()?;
+            let c: usize = words[1].parse()?;
+            ensure!(r < dim.0 && c < dim.1, "force constants index out of bounds: {:?}", line);
+            let data = words[2..].iter().map(|word| word.parse()).collect::<Result<Vec<f64>, _>>()?;
+
+            row.push(PrimI(r));
+            col.push(SuperI(c));
+            val.push(M33::from_fn(|i, j| data[3 * i + j]));
+        }
+        Ok(ForceConstants(RawCoo { dim, row, col, val }.into_csr()))
+    }
+}
+
 impl SuperForceConstants {
     /// Take `SuperForceConstants` where `row_atom` is always in `DISPLACED_CELL`
     /// and generate all the other rows.
@@ -959,6 +1298,13 @@ impl DynamicalMatrix {
     pub fn num_atoms(&self) -> usize
     { self.0.dim.0 }
 
+    /// Densify into separate real and imaginary matrices of 3x3 blocks.
+    pub fn to_dense_parts(&self) -> (Vec<Vec<M33>>, Vec<Vec<M33>>) {
+        let real = self.0.clone().map(|c| c.0).into_dense();
+        let imag = self.0.clone().map(|c| c.1).into_dense();
+        (real, imag)
+    }
+
     pub fn hermitianize(&self) -> Self {
         let coo_1 = self.0.to_coo();
         let coo_2 = self.conj_t().0.into_coo();
@@ -1006,22 +1352,260 @@ impl DynamicalMatrix {
         Some(out)
     }
 
+    /// Compute all eigensolutions of a real matrix, such as one computed at gamma.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the matrix is not real.  (see `compute_eigensolutions_dense`)
     pub fn compute_eigensolutions_dense_gamma(&self) -> (Eigenvalues, Vec<Vec<V3>>) {
+        self.compute_eigensolutions_dense_gamma_filtered(|_| true)
+    }
+
+    /// `compute_eigensolutions_dense_gamma`, but only produces the eigensolutions whose
+    /// eigenvalues satisfy a predicate.
+    ///
+    /// All eigensolutions are still computed, but the eigenvectors that are not kept
+    /// are discarded before they are copied into the (much less compact) output.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the matrix is not real.
+    pub fn compute_eigensolutions_dense_gamma_filtered(
+        &self,
+        mut keep: impl FnMut(f64) -> bool,
+    ) -> (Eigenvalues, Vec<Vec<V3>>) {
         trace!("Computing all eigensolutions.");
         let mut flat = self.to_dense_flat_real().expect("(BUG!) expected real matrix!");
-        let mut eigenvalues = vec![f64::NAN; 3 * self.num_atoms()];
+        let mut all_eigenvalues = vec![f64::NAN; 3 * self.num_atoms()];
         let mut eigenvectors_flat = vec![f64::NAN; flat.len()];
 
-        rsp2_linalg::dynmat::diagonalize_real(&mut flat, &mut eigenvalues, &mut eigenvectors_flat);
+        rsp2_linalg::dynmat::diagonalize_real(&mut flat, &mut all_eigenvalues, &mut eigenvectors_flat);
 
         // save that precious memory!
         drop(flat);
 
-        let eigenvectors = eigenvectors_flat.chunks(3 * self.num_atoms())
-            .map(|data| data.nest().to_vec()).collect();
+        let (eigenvalues, eigenvectors) = {
+            zip_eq!(all_eigenvalues, eigenvectors_flat.chunks(3 * self.num_atoms()))
+                .filter(|&(value, _)| keep(value))
+                .map(|(value, data)| (value, data.nest().to_vec()))
+                .unzip()
+        };
 
         (Eigenvalues { eigenvalues }, eigenvectors)
     }
+
+    /// Compute all eigensolutions of a hermitian matrix, which need not be real.
+    ///
+    /// Each eigenvector is returned as a pair of its real and imaginary parts.  Unlike
+    /// `compute_eigensolutions_dense_gamma`, this may be used at any q-point.
+    pub fn compute_eigensolutions_dense(&self) -> (Eigenvalues, Vec<(Vec<V3>, Vec<V3>)>) {
+        if self.is_real() {
+            let (eigenvalues, eigenvectors) = self.compute_eigensolutions_dense_gamma();
+            let eigenvectors = {
+                eigenvectors.into_iter()
+                    .map(|real| {
+                        let imag = vec![V3::zero(); real.len()];
+                        (real, imag)
+                    })
+                    .collect()
+            };
+            return (eigenvalues, eigenvectors);
+        }
+
+        trace!("Computing all eigensolutions.");
+        let dim = 3 * self.num_atoms();
+        let mut flat = self.to_dense_flat_real_embedding();
+        let mut embedded_values = vec![f64::NAN; 2 * dim];
+        let mut embedded_vectors = vec![f64::NAN; flat.len()];
+
+        rsp2_linalg::dynmat::diagonalize_real(&mut flat, &mut embedded_values, &mut embedded_vectors);
+        drop(flat);
+
+        // Each eigenvalue appears twice in the embedding, with the two eigenvectors spanning
+        // `(x, y)` and `(-y, x)` for a single complex eigenvector `x + iy`.  We can't simply take
+        // every other vector, because a degenerate eigenvalue of the hermitian matrix produces a
+        // larger eigenspace where the pairs may be arbitrarily mixed.  Instead, each vector is
+        // orthogonalized (in the complex sense) against those already taken from the same
+        // eigenspace, and only kept if something remains.
+        let tol = 1e-9 * embedded_values.iter().fold(1.0, |acc, x| f64::max(acc, x.abs()));
+        let mut eigenvalues = Vec::with_capacity(dim);
+        let mut eigenvectors: Vec<(Vec<f64>, Vec<f64>)> = Vec::with_capacity(dim);
+        for (&value, data) in zip_eq!(&embedded_values, embedded_vectors.chunks(2 * dim)) {
+            let mut real = data[..dim].to_vec();
+            let mut imag = data[dim..].to_vec();
+
+            let same_space = eigenvalues.iter().rposition(|&prev: &f64| (value - prev).abs() > tol);
+            let same_space = same_space.map_or(0, |i| i + 1);
+            for (prev_real, prev_imag) in &eigenvectors[same_space..] {
+                // <prev|v>
+                let mut dot_real = 0.0;
+                let mut dot_imag = 0.0;
+                for i in 0..dim {
+                    dot_real += prev_real[i] * real[i] + prev_imag[i] * imag[i];
+                    dot_imag += prev_real[i] * imag[i] - prev_imag[i] * real[i];
+                }
+                for i in 0..dim {
+                    real[i] -= dot_real * prev_real[i] - dot_imag * prev_imag[i];
+                    imag[i] -= dot_real * prev_imag[i] + dot_imag * prev_real[i];
+                }
+            }
+
+            let sqnorm: f64 = real.iter().chain(&imag).map(|x| x * x).sum();
+            if sqnorm < 0.5 {
+                continue; // the partner of an eigenvector we already have
+            }
+            let norm = sqnorm.sqrt();
+            real.iter_mut().chain(&mut imag).for_each(|x| *x /= norm);
+
+            eigenvalues.push(value);
+            eigenvectors.push((real, imag));
+        }
+        assert_eq!(eigenvectors.len(), dim, "(BUG!) wrong number of complex eigenvectors");
+
+        let eigenvectors = {
+            eigenvectors.into_iter()
+                .map(|(real, imag)| (real.nest().to_vec(), imag.nest().to_vec()))
+                .collect()
+        };
+        (Eigenvalues { eigenvalues }, eigenvectors)
+    }
+
+    /// Compute all eigenvalues of a hermitian matrix, which need not be real.
+    pub fn compute_eigenvalues_dense(&self) -> Eigenvalues {
+        let dim = 3 * self.num_atoms();
+        let (mut flat, flat_dim) = match self.to_dense_flat_real() {
+            Some(flat) => (flat, dim),
+            None => (self.to_dense_flat_real_embedding(), 2 * dim),
+        };
+        let mut eigenvalues = vec![f64::NAN; flat_dim];
+        let mut eigenvectors_flat = vec![f64::NAN; flat.len()];
+
+        rsp2_linalg::dynmat::diagonalize_real(&mut flat, &mut eigenvalues, &mut eigenvectors_flat);
+
+        if flat_dim != dim {
+            // each eigenvalue appears twice in the embedding
+            eigenvalues = eigenvalues.into_iter().step_by(2).collect();
+        }
+        Eigenvalues { eigenvalues }
+    }
+
+    /// Produce a flat `Vec` representation of the real symmetric matrix `[[A, -B], [B, A]]`,
+    /// where `A + iB` is this (hermitian) matrix.
+    ///
+    /// The eigenvalues of this matrix are those of `A + iB`, each appearing twice.
+    fn to_dense_flat_real_embedding(&self) -> Vec<f64> {
+        let DynamicalMatrix(RawCsr { dim, val, col, row_ptr }) = self;
+        let n = 3 * dim.0;
+
+        let mut out = vec![0.0; 2 * n * 2 * n];
+        let row_los = &row_ptr.raw[..row_ptr.len() - 1];
+        let row_his = &row_ptr.raw[1..];
+        for (block_row, (&lo, &hi)) in zip_eq!(row_los, row_his).enumerate() {
+            for (block, &PrimI(block_col)) in zip_eq!(&val[lo..hi], &col[lo..hi]) {
+                let Complex33(real, imag) = block;
+                for r in 0..3 {
+                    for c in 0..3 {
+                        let out_r = 3 * block_row + r;
+                        let out_c = 3 * block_col + c;
+                        out[out_r * 2 * n + out_c] = real[r][c];
+                        out[out_r * 2 * n + n + out_c] = -imag[r][c];
+                        out[(n + out_r) * 2 * n + out_c] = imag[r][c];
+                        out[(n + out_r) * 2 * n + n + out_c] = real[r][c];
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+/// The curvature of a gamma-point dynamical matrix along a few modes.
+///
+/// See `DynamicalMatrix::check_curvature`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurvatureCheck {
+    /// The expectation value `<v|D|v>` along each mode.
+    pub curvatures: Vec<f64>,
+    /// The curvature along each mode according to finite differences of the gradient.
+    pub fd_curvatures: Vec<f64>,
+}
+
+impl CurvatureCheck {
+    /// Trace of the dynamical matrix restricted to the span of the modes.
+    ///
+    /// (this assumes the modes were orthonormal)
+    pub fn trace(&self) -> f64
+    { self.curvatures.iter().sum() }
+
+    /// Whether any mode has a negative curvature beyond `tolerance` according to the
+    /// finite differences, indicating that the structure is at a saddle point rather
+    /// than a local minimum.
+    pub fn is_saddle(&self, tolerance: f64) -> bool
+    { self.fd_curvatures.iter().any(|&c| c < -tolerance) }
+
+    /// The largest absolute difference between the two measures of curvature.
+    pub fn max_discrepancy(&self) -> f64 {
+        zip_eq!(&self.curvatures, &self.fd_curvatures)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max)
+    }
+}
+
+impl DynamicalMatrix {
+    /// Compare the curvature of this gamma-point dynamical matrix along each of the given
+    /// (real, mass-weighted) modes against finite differences of the gradient.
+    ///
+    /// This is meant as a sanity check that the force constants agree with the potential
+    /// about whether a relaxed structure sits at a local minimum.  (`<v|D|v>` on its own
+    /// would be useless for this, as the modes are typically eigenvectors of the same matrix)
+    ///
+    /// `grad_at` receives cartesian displacements of every atom and must return the gradient
+    /// of the potential at the displaced structure.  Each mode is displaced by `step` in
+    /// either direction, in mass-weighted units.
+    pub fn check_curvature<E, F>(
+        &self,
+        modes: &[Vec<V3>],
+        masses: &[f64],
+        step: f64,
+        mut grad_at: F,
+    ) -> Result<CurvatureCheck, E>
+    where
+        F: FnMut(&[V3]) -> Result<Vec<V3>, E>,
+    {
+        assert!(self.is_real(), "check_curvature requires a gamma-point dynamical matrix");
+        assert_eq!(masses.len(), self.0.dim.1);
+
+        let DynamicalMatrix(RawCsr { dim, val, col, row_ptr }) = self;
+        let row_los = &row_ptr.raw[..row_ptr.len() - 1];
+        let row_his = &row_ptr.raw[1..];
+
+        let mut curvatures = vec![];
+        let mut fd_curvatures = vec![];
+        for mode in modes {
+            assert_eq!(mode.len(), dim.1);
+
+            let mut curvature = 0.0;
+            for (block_row, (&lo, &hi)) in zip_eq!(row_los, row_his).enumerate() {
+                for (Complex33(real, _), &PrimI(block_col)) in zip_eq!(&val[lo..hi], &col[lo..hi]) {
+                    curvature += V3::dot(&mode[block_row], &(real * mode[block_col]));
+                }
+            }
+            curvatures.push(curvature);
+
+            // the cartesian direction corresponding to the mass-weighted mode
+            let direction: Vec<V3> = zip_eq!(mode, masses).map(|(&v, &m)| v / m.sqrt()).collect();
+            let slope_at = |grad_at: &mut F, sign: f64| -> Result<f64, E> {
+                let disps: Vec<V3> = direction.iter().map(|&v| v * (sign * step)).collect();
+                let grad = grad_at(&disps)?;
+                Ok(zip_eq!(&grad, &direction).map(|(g, v)| V3::dot(g, v)).sum::<f64>())
+            };
+            let slope_plus = slope_at(&mut grad_at, 1.0)?;
+            let slope_minus = slope_at(&mut grad_at, -1.0)?;
+            fd_curvatures.push((slope_plus - slope_minus) / (2.0 * step));
+        }
+
+        Ok(CurvatureCheck { curvatures, fd_curvatures })
+    }
 }
 
 /// Reading and writing NPZ.
@@ -1320,6 +1904,131 @@ mod tests {
         assert_eq!(expected.to_dense_matrix(), actual.to_dense_matrix());
     }
 
+    #[test]
+    fn fc_text_round_trip() {
+        let (orig, sc) = make_fc_test_data();
+
+        let mut text = vec![];
+        orig.write_text(&sc, &mut text).unwrap();
+        let read = ForceConstants::read_text(&sc, &text[..]).unwrap();
+
+        let orig = orig.to_super_force_constants_with_zeroed_rows(&sc).to_dense_matrix();
+        let read = read.to_super_force_constants_with_zeroed_rows(&sc).to_dense_matrix();
+        for (orig_row, read_row) in zip_eq!(&orig, &read) {
+            for (orig, read) in zip_eq!(orig_row, read_row) {
+                assert!((orig - read).0.iter().flat_map(|v| v.0.iter()).all(|x| x.abs() <= 1e-12));
+            }
+        }
+
+        // header must match the supercell
+        let prim_coords = Coords::new(Lattice::eye(), CoordsKind::Carts(vec![V3::zero(); 2]));
+        let other_sc = supercell::diagonal([3, 2, 1]).build(&prim_coords).1;
+        assert!(ForceConstants::read_text(&other_sc, &text[..]).is_err());
+    }
+
+    #[test]
+    fn dynmat_at_frac_q() {
+        // A linear chain along x with one atom per cell, whose springs to the left and right
+        // neighbors have different stiffnesses (unphysical, but it makes the matrix complex).
+        //
+        // D_xx(q) = (k_self - k_right e^{iθ} - k_left e^{-iθ}) / m,  where θ = 2π q a.
+        let a = 1.5;
+        let prim_coords = Coords::new(
+            Lattice::orthorhombic(a, 10.0, 10.0),
+            CoordsKind::Fracs(vec![V3::zero()]),
+        );
+        let (super_coords, sc) = supercell::diagonal([5, 1, 1]).build(&prim_coords);
+        let mass = 2.0;
+        let (k_right, k_left) = (1.0, 0.25);
+        let k_self = k_right + k_left;
+
+        let xx = |k: f64| M33::from_fn(|r, c| if (r, c) == (0, 0) { k } else { 0.0 });
+        let wrapper = SupercellWrapper::new(&sc);
+        let SuperI(designated) = wrapper.designated_super(PrimI(0));
+        let designated_point = sc.atom_lattice_points()[designated];
+        let neighbor = |offset: i32| sc.atom_from_lattice_point(0, designated_point + V3([offset, 0, 0]));
+        let row = vec![
+            (SuperI(designated), xx(k_self)),
+            (SuperI(neighbor(1)), xx(-k_right)),
+            (SuperI(neighbor(-1)), xx(-k_left)),
+        ];
+        let map = vec![(PrimI(0), row.into_iter().collect())].into_iter().collect();
+        let dim = (sc.num_primitive_atoms(), sc.num_supercell_atoms());
+        let fcs = ForceConstants(RawBee { map, dim }.to_csr());
+
+        for &q in &[0.0, 0.2, 0.4] {
+            let dynmat = fcs.dynmat_at_q(&super_coords, V3([q, 0.0, 0.0]), &sc, &[mass]);
+            let blocks = dynmat.0.to_dense();
+            let Complex33(real, imag) = blocks[0][0];
+
+            let theta = 2.0 * std::f64::consts::PI * q;
+            let expected_real = (k_self - (k_right + k_left) * theta.cos()) / mass;
+            let expected_imag = (k_left - k_right) * theta.sin() / mass;
+            assert_close!(abs=1e-12, real[0][0], expected_real);
+            assert_close!(abs=1e-12, imag[0][0], expected_imag);
+            for r in 0..3 {
+                for c in 0..3 {
+                    if (r, c) != (0, 0) {
+                        assert_eq!((real[r][c], imag[r][c]), (0.0, 0.0));
+                    }
+                }
+            }
+            assert_eq!(dynmat.is_real(), q == 0.0);
+        }
+    }
+
+    #[test]
+    fn curvature_check() {
+        // a diatomic "molecule" bound by a spring along x, which is repulsive
+        // when the stiffness is negative (i.e. a saddle point)
+        let prim_coords = Coords::new(Lattice::eye(), CoordsKind::Carts(vec![V3::zero(), V3([0.5, 0.0, 0.0])]));
+        let (super_coords, sc) = supercell::diagonal([1, 1, 1]).build(&prim_coords);
+        let masses = [1.0, 1.0];
+
+        let dynmat_for_stiffness = |k: f64| {
+            let xx = M33::from_fn(|r, c| if (r, c) == (0, 0) { k } else { 0.0 });
+            let map = (0..2).map(|prim_r| {
+                let row = (0..2).map(|super_c| {
+                    let block = if prim_r == super_c { xx } else { -xx };
+                    (SuperI(super_c), block)
+                }).collect();
+                (PrimI(prim_r), row)
+            }).collect();
+            let fcs = ForceConstants(RawBee { map, dim: (2, 2) }.to_csr());
+            fcs.dynmat_at_cart_q(&super_coords, V3::zero(), &sc, &masses)
+        };
+
+        // the gradient of the spring potential `k/2 (x1 - x0 - 0.5)^2` after displacing the atoms
+        let grad_for_stiffness = |k: f64| move |disps: &[V3]| -> Result<Vec<V3>, ()> {
+            let stretch = disps[1][0] - disps[0][0];
+            Ok(vec![V3([-k * stretch, 0.0, 0.0]), V3([k * stretch, 0.0, 0.0])])
+        };
+
+        let h = f64::sqrt(0.5);
+        let modes = vec![
+            vec![V3([h, 0.0, 0.0]), V3([-h, 0.0, 0.0])], // stretching
+            vec![V3([0.0, h, 0.0]), V3([0.0, -h, 0.0])], // (rotation, no curvature)
+        ];
+        let step = 1e-3;
+
+        let check = dynmat_for_stiffness(1.0).check_curvature(&modes, &masses, step, grad_for_stiffness(1.0)).unwrap();
+        assert_close!(abs=1e-12, check.curvatures.clone(), vec![2.0, 0.0]);
+        assert_close!(abs=1e-9, check.fd_curvatures.clone(), vec![2.0, 0.0]);
+        assert_close!(abs=1e-12, check.trace(), 2.0);
+        assert_close!(abs=1e-9, check.max_discrepancy(), 0.0);
+        assert!(!check.is_saddle(1e-8));
+
+        let check = dynmat_for_stiffness(-1.0).check_curvature(&modes, &masses, step, grad_for_stiffness(-1.0)).unwrap();
+        assert_close!(abs=1e-12, check.curvatures.clone(), vec![-2.0, 0.0]);
+        assert_close!(abs=1e-9, check.fd_curvatures.clone(), vec![-2.0, 0.0]);
+        assert!(check.is_saddle(1e-8));
+
+        // force constants that disagree with the potential
+        let check = dynmat_for_stiffness(1.0).check_curvature(&modes, &masses, step, grad_for_stiffness(-1.0)).unwrap();
+        assert_close!(abs=1e-9, check.max_discrepancy(), 4.0);
+        assert!(check.is_saddle(1e-8));
+    }
+
     #[test]
     fn fc_impose_matrix_symmetry() {
         let (orig, sc) = make_fc_test_data();
@@ -1343,6 +2052,254 @@ mod tests {
         assert_eq!(expected.to_dense_matrix(), actual.to_dense_matrix());
     }
 
+    #[test]
+    fn builder_incremental() {
+        // no symmetry, so that each row requires three displacements of its own
+        let prim_coords = Coords::new(Lattice::eye(), CoordsKind::Carts(vec![V3::zero(), V3([0.5; 3])]));
+        let sc = supercell::diagonal([2, 2, 1]).build(&prim_coords).1;
+        let cart_rots = vec![M33::eye()];
+        let super_deperms = vec![Perm::eye(sc.num_supercell_atoms())];
+
+        // generate forces from random force constants
+        let mut rng = rand::thread_rng();
+        let expected: Vec<Vec<M33>> = (0..sc.num_primitive_atoms()).map(|_| {
+            (0..sc.num_supercell_atoms()).map(|_| M33::from_fn(|_, _| 2.0 * rng.next_f64() - 1.0)).collect()
+        }).collect();
+
+        let wrapper = SupercellWrapper::new(&sc);
+        let mut super_displacements = vec![];
+        let mut force_sets = vec![];
+        for prim in 0..sc.num_primitive_atoms() {
+            let SuperI(displaced) = wrapper.designated_super(PrimI(prim));
+            for k in 0..3 {
+                let disp = V3::from_fn(|i| if i == k { 1e-2 } else { 0.0 });
+                super_displacements.push((displaced, disp));
+                force_sets.push(expected[prim].iter().map(|&phi| -(disp * phi)).enumerate().collect());
+            }
+        }
+
+        let batch = ForceConstants::compute_required_rows(
+            &super_displacements, &force_sets, &cart_rots, &super_deperms, &sc,
+        ).unwrap();
+        let parallel = ForceConstants::compute_required_rows_maybe_parallel(
+            &super_displacements, &force_sets, &cart_rots, &super_deperms, &sc, true,
+        ).unwrap();
+
+        let mut builder = ForceConstantsBuilder::new(&cart_rots, &super_deperms, &sc);
+        for (i, (&disp, force_set)) in zip_eq!(&super_displacements, &force_sets).enumerate() {
+            builder.add_displacement(disp, force_set.clone()).unwrap();
+
+            let (_, undetermined) = builder.build_partial().unwrap();
+            let expected_undetermined = match i + 1 {
+                1..=2 => vec![0, 1],
+                3..=5 => vec![1],
+                _ => vec![],
+            };
+            assert_eq!(undetermined, expected_undetermined);
+            assert_eq!(builder.build().is_ok(), expected_undetermined.is_empty());
+        }
+        let incremental = builder.build().unwrap();
+
+        let batch = batch.to_super_force_constants_with_zeroed_rows(&sc).to_dense_matrix();
+        let incremental = incremental.to_super_force_constants_with_zeroed_rows(&sc).to_dense_matrix();
+        let parallel = parallel.to_super_force_constants_with_zeroed_rows(&sc).to_dense_matrix();
+        assert_eq!(batch, incremental);
+        assert_eq!(batch, parallel);
+
+        for prim in 0..sc.num_primitive_atoms() {
+            let SuperI(displaced) = wrapper.designated_super(PrimI(prim));
+            for (actual, expected) in zip_eq!(&incremental[displaced], &expected[prim]) {
+                for r in 0..3 {
+                    for c in 0..3 {
+                        assert!((actual[r][c] - expected[r][c]).abs() < 1e-10, "{:?} vs {:?}", actual, expected);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn overdetermined_custom_displacements() {
+        // e.g. a list supplied by the user: arbitrary directions, more than three per atom,
+        // and not in the star representatives chosen by symmetry.  (so no symmetry is used)
+        let prim_coords = Coords::new(Lattice::eye(), CoordsKind::Carts(vec![V3::zero(), V3([0.5; 3])]));
+        let sc = supercell::diagonal([2, 2, 1]).build(&prim_coords).1;
+        let cart_rots = vec![M33::eye()];
+        let super_deperms = vec![Perm::eye(sc.num_supercell_atoms())];
+
+        let mut rng = rand::thread_rng();
+        let expected: Vec<Vec<M33>> = (0..sc.num_primitive_atoms()).map(|_| {
+            (0..sc.num_supercell_atoms()).map(|_| M33::from_fn(|_, _| 2.0 * rng.next_f64() - 1.0)).collect()
+        }).collect();
+
+        let prim_displacements = vec![
+            (0, V3([1e-2, 0.0, 0.0])),
+            (0, V3([-1e-2, 0.0, 0.0])),
+            (0, V3([0.0, 7e-3, 7e-3])),
+            (0, V3([0.0, -7e-3, 7e-3])),
+            (0, V3([4e-3, 3e-3, -5e-3])),
+            (1, V3([6e-3, 6e-3, 6e-3])),
+            (1, V3([0.0, 1e-2, 0.0])),
+            (1, V3([0.0, 0.0, -2e-2])),
+            (1, V3([0.0, 0.0, 2e-2])),
+        ];
+
+        let wrapper = SupercellWrapper::new(&sc);
+        let mut super_displacements = vec![];
+        let mut force_sets = vec![];
+        for &(prim, disp) in &prim_displacements {
+            let SuperI(displaced) = wrapper.designated_super(PrimI(prim));
+            super_displacements.push((displaced, disp));
+            force_sets.push(expected[prim].iter().map(|&phi| -(disp * phi)).enumerate().collect());
+        }
+
+        let actual = ForceConstants::compute_required_rows(
+            &super_displacements, &force_sets, &cart_rots, &super_deperms, &sc,
+        ).unwrap();
+        let actual = actual.to_super_force_constants_with_zeroed_rows(&sc).to_dense_matrix();
+
+        for prim in 0..sc.num_primitive_atoms() {
+            let SuperI(displaced) = wrapper.designated_super(PrimI(prim));
+            for (actual, expected) in zip_eq!(&actual[displaced], &expected[prim]) {
+                for r in 0..3 {
+                    for c in 0..3 {
+                        assert!((actual[r][c] - expected[r][c]).abs() < 1e-10, "{:?} vs {:?}", actual, expected);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn complex_eigenvalues() {
+        let mut real = M33::eye();
+        let mut imag = M33::zero();
+        real[0][0] = 2.0;
+        real[1][1] = 2.0;
+        imag[0][1] = 1.0;
+        imag[1][0] = -1.0;
+        let dynmat = DynamicalMatrix(RawCsr {
+            dim: (1, 1),
+            val: vec![Complex33(real, imag)],
+            col: vec![PrimI(0)],
+            row_ptr: Indexed::from_raw(vec![0, 1]),
+        });
+        let Eigenvalues { eigenvalues } = dynmat.compute_eigenvalues_dense();
+        for (actual, expected) in zip_eq!(eigenvalues, vec![1.0, 1.0, 3.0]) {
+            assert!((actual - expected).abs() < 1e-12, "{} vs {}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn complex_eigensolutions() {
+        // same matrix as above; note that eigenvalue 1 is degenerate
+        let mut real = M33::eye();
+        let mut imag = M33::zero();
+        real[0][0] = 2.0;
+        real[1][1] = 2.0;
+        imag[0][1] = 1.0;
+        imag[1][0] = -1.0;
+        let dynmat = DynamicalMatrix(RawCsr {
+            dim: (1, 1),
+            val: vec![Complex33(real, imag)],
+            col: vec![PrimI(0)],
+            row_ptr: Indexed::from_raw(vec![0, 1]),
+        });
+        let (Eigenvalues { eigenvalues }, eigenvectors) = dynmat.compute_eigensolutions_dense();
+        for (&actual, expected) in zip_eq!(&eigenvalues, vec![1.0, 1.0, 3.0]) {
+            assert!((actual - expected).abs() < 1e-12, "{} vs {}", actual, expected);
+        }
+
+        let complex_dot = |(ar, ai): &(Vec<V3>, Vec<V3>), (br, bi): &(Vec<V3>, Vec<V3>)| {
+            let (ar, ai, br, bi) = (ar.flat(), ai.flat(), br.flat(), bi.flat());
+            let re: f64 = (0..3).map(|i| ar[i] * br[i] + ai[i] * bi[i]).sum();
+            let im: f64 = (0..3).map(|i| ar[i] * bi[i] - ai[i] * br[i]).sum();
+            (re, im)
+        };
+        for (i, a) in eigenvectors.iter().enumerate() {
+            for (j, b) in eigenvectors.iter().enumerate() {
+                let (re, im) = complex_dot(a, b);
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((re - expected).abs() < 1e-10 && im.abs() < 1e-10, "{} {}: {} + {}i", i, j, re, im);
+            }
+        }
+
+        // check that H v = λ v
+        for (&value, (v_real, v_imag)) in zip_eq!(&eigenvalues, &eigenvectors) {
+            let v_real = v_real[0];
+            let v_imag = v_imag[0];
+            let hv_real = real * v_real - imag * v_imag;
+            let hv_imag = real * v_imag + imag * v_real;
+            for k in 0..3 {
+                assert!((hv_real[k] - value * v_real[k]).abs() < 1e-10);
+                assert!((hv_imag[k] - value * v_imag[k]).abs() < 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn filtered_eigensolutions() {
+        let mut real = M33::eye();
+        real[0][0] = 2.0;
+        real[0][1] = 0.5;
+        real[1][0] = 0.5;
+        real[2][2] = -1.0;
+        let dynmat = DynamicalMatrix(RawCsr {
+            dim: (1, 1),
+            val: vec![Complex33(real, M33::zero())],
+            col: vec![PrimI(0)],
+            row_ptr: Indexed::from_raw(vec![0, 1]),
+        });
+        let (all_values, all_vectors) = dynmat.compute_eigensolutions_dense_gamma();
+        let (Eigenvalues { eigenvalues }, eigenvectors) = {
+            dynmat.compute_eigensolutions_dense_gamma_filtered(|value| value < 1.5)
+        };
+        assert_eq!(eigenvalues.len(), 2);
+        assert!(eigenvalues.iter().all(|&value| value < 1.5));
+
+        // a subset of the full set, in the same order
+        let mut full = zip_eq!(&all_values.eigenvalues, &all_vectors);
+        for (value, vector) in zip_eq!(&eigenvalues, &eigenvectors) {
+            assert!(full.any(|(v, ev)| (v, ev) == (value, vector)));
+        }
+    }
+
+    #[test]
+    fn phonon_dos_normalization() {
+        // simple cubic lattice with springs of stiffness 1 between nearest neighbors,
+        // whose modes have `ω^2 = 2 (1 - cos(2π q_k))` and thus range from 0 to 2.
+        let prim_coords = Coords::new(Lattice::eye(), CoordsKind::Carts(vec![V3::zero()]));
+        let (super_coords, sc) = supercell::diagonal([4, 4, 4]).build(&prim_coords);
+
+        let wrapper = SupercellWrapper::new(&sc);
+        let center = wrapper.designated_super(PrimI(0));
+        let mut row = BTreeMap::new();
+        row.insert(center, M33::eye() * 2.0);
+        for k in 0..3 {
+            for &sign in &[-1, 1] {
+                let mut lattice_point = wrapper.designated_lattice_point;
+                lattice_point[k] += sign;
+                let mut fc = M33::zero();
+                fc[k][k] = -1.0;
+                row.insert(wrapper.atom_from_lattice_point(PrimI(0), lattice_point), fc);
+            }
+        }
+        let map = vec![(PrimI(0), row)].into_iter().collect();
+        let dim = (sc.num_primitive_atoms(), sc.num_supercell_atoms());
+        let fcs = ForceConstants(RawBee { map, dim }.to_csr());
+
+        let sigma = 0.05;
+        let (frequencies, dos) = fcs.phonon_dos(&super_coords, &sc, &[1.0], [4, 4, 4], sigma);
+        assert_eq!(frequencies.len(), dos.len());
+        assert!((frequencies[0] + 5.0 * sigma).abs() < 1e-9, "{}", frequencies[0]);
+        assert!(frequencies[frequencies.len() - 1] >= 2.0 + 5.0 * sigma - 1e-9);
+
+        let step = frequencies[1] - frequencies[0];
+        let integral: f64 = dos.iter().sum::<f64>() * step;
+        let expected = 3.0 * sc.num_primitive_atoms() as f64;
+        assert!((integral - expected).abs() < 1e-4 * expected, "{} vs {}", integral, expected);
+    }
+
     #[test]
     #[cfg(feature = "npz")]
     fn npz_real() {
@@ -67,6 +67,9 @@ lazy_static! {
     /// Guarantees that only one instance of Lammps may exist on a process,
     /// if constructed through safe APIs.
     pub static ref INSTANCE_LOCK: Mutex<InstanceLock> = Mutex::new(InstanceLock(()));
+
+    // Running totals of the stats of every instance ever built on this process.
+    static ref TOTAL_NEIGHBOR_STATS: Mutex<NeighborStats> = Mutex::new(Default::default());
 }
 
 /// Proof that no instance of Lammps currently exists within the current process.
@@ -107,6 +110,11 @@ pub struct Lammps<P: Potential> {
     // Determines the next command for updating.
     update_fsm: UpdateFsm,
 
+    // The value of LAMMPS' `nbuild` after the most recent run.
+    last_nbuild: u64,
+    neighbor_stats: NeighborStats,
+    warn_neighbor_build_fraction: Option<f64>,
+
     data_trace_dir: Option<PathBuf>,
 
     debug_dir: Option<PathBuf>,
@@ -124,6 +132,7 @@ pub struct Builder {
     processors: [Option<u32>; 3],
     auto_adjust_lattice: bool,
     update_style: UpdateStyle,
+    warn_neighbor_build_fraction: Option<f64>,
     data_trace_dir: Option<PathBuf>,
     debug_dir: Option<PathBuf>,
     stdout: bool
@@ -223,6 +232,42 @@ struct UpdateAction {
 #[derive(Debug, Copy, Clone)]
 enum UpdatePositions { Relative, Absolute }
 
+/// Statistics about how often LAMMPS rebuilt its neighbor lists.
+///
+/// With `pre no`, LAMMPS only rebuilds neighbor lists when an atom has moved far enough
+/// to require it, which is what makes `UpdateStyle::fast` fast.  If a rebuild happens on
+/// most runs anyways, then `pre no` is not buying anything.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NeighborStats {
+    /// Number of `run` commands sent to LAMMPS.
+    pub runs: u64,
+    /// Number of neighbor list builds that occurred during those runs.
+    pub builds: u64,
+}
+
+impl NeighborStats {
+    /// Neighbor list builds per run. (`0.0` if nothing has been run)
+    pub fn build_fraction(&self) -> f64 {
+        match self.runs {
+            0 => 0.0,
+            runs => self.builds as f64 / runs as f64,
+        }
+    }
+
+    fn record_run(&mut self, builds: u64) {
+        self.runs += 1;
+        self.builds += builds;
+    }
+}
+
+/// Neighbor list statistics summed over all `Lammps` instances that have existed
+/// on this process.
+pub fn total_neighbor_stats() -> NeighborStats
+{ *TOTAL_NEIGHBOR_STATS.lock().unwrap() }
+
+// Don't bother warning about instances that were barely used.
+const MIN_RUNS_FOR_NEIGHBOR_WARNING: u64 = 10;
+
 impl UpdateStyle {
     fn initial_fsm(&self) -> UpdateFsm {
         UpdateFsm { iter: 0, style: self.clone() }
@@ -273,6 +318,7 @@ impl Builder {
         append_log: None,
         openmp_threads: None,
         update_style: UpdateStyle::safe(),
+        warn_neighbor_build_fraction: None,
         auto_adjust_lattice: true,
         data_trace_dir: None,
         debug_dir: None,
@@ -330,6 +376,14 @@ impl Builder {
     pub fn update_style(&mut self, value: UpdateStyle) -> &mut Self
     { self.update_style = value; self }
 
+    /// Log a warning when a `Lammps` is dropped if neighbor lists were rebuilt on more than
+    /// this fraction of its runs.
+    ///
+    /// This only applies to update styles with `pre no`, since `pre yes` always rebuilds.
+    /// See `Lammps::neighbor_stats`.
+    pub fn warn_neighbor_build_fraction(&mut self, value: Option<f64>) -> &mut Self
+    { self.warn_neighbor_build_fraction = value; self }
+
     // FIXME: The Builder is a really awkward and inappropriate location for this.
     // Ideally it would be on LammpsOnDemand, but it was embedded into the builder so
     // that rsp2_tasks doesn't need to carry around an extra piece of environment.
@@ -571,6 +625,9 @@ impl<P: Potential> Lammps<P>
             original_molecule_ids,
             auto_adjust_lattice: builder.auto_adjust_lattice,
             update_fsm: builder.update_style.initial_fsm(),
+            last_nbuild: 0,
+            neighbor_stats: Default::default(),
+            warn_neighbor_build_fraction: builder.warn_neighbor_build_fraction,
             data_trace_dir: builder.data_trace_dir.clone(),
             debug_dir: builder.debug_dir.clone(),
             _lock: lock,
@@ -705,6 +762,9 @@ impl<P: Potential> Lammps<P>
             &format!("compute RSP2_Pressure all pressure NULL virial"),
         ])?;
 
+        // for neighbor list statistics
+        lmp.command("variable RSP2_NBuild equal nbuild".to_string())?;
+
         lmp
     })}
 }
@@ -812,12 +872,26 @@ impl<P: Potential> Lammps<P> {
         }
 
         self.ptr.borrow_mut().command(command.into())?;
+        self.record_neighbor_builds()?;
 
         if let Some(dir) = &self.data_trace_dir {
             self.write_data_trace_fileset(dir, &format!("{:04}-b", iter));
         }
     })}
 
+    fn record_neighbor_builds(&mut self) -> FailResult<()>
+    {Ok({
+        let nbuild = unsafe {
+            self.ptr.borrow_mut().extract_variable_0d("RSP2_NBuild".into())
+        }? as u64;
+
+        let builds = nbuild.saturating_sub(self.last_nbuild);
+        self.last_nbuild = nbuild;
+
+        self.neighbor_stats.record_run(builds);
+        TOTAL_NEIGHBOR_STATS.lock().unwrap().record_run(builds);
+    })}
+
     fn send_lmp_types(&mut self) -> FailResult<()>
     {Ok({
         let types = {
@@ -1061,6 +1135,37 @@ impl<P: Potential> Lammps<P> {
     })}
 }
 
+//-------------------------------------------
+// diagnostics
+
+impl<P: Potential> Lammps<P> {
+    /// Get statistics about neighbor list builds during all computations so far.
+    ///
+    /// Unlike the `compute_*` methods, this does not perform any computations.
+    pub fn neighbor_stats(&self) -> NeighborStats
+    { self.neighbor_stats }
+}
+
+impl<P: Potential> Drop for Lammps<P> {
+    fn drop(&mut self) {
+        let stats = self.neighbor_stats;
+        debug!("LAMMPS neighbor lists were built {} times in {} runs", stats.builds, stats.runs);
+
+        if let Some(threshold) = self.warn_neighbor_build_fraction {
+            if !self.update_fsm.style.pre
+                && stats.runs >= MIN_RUNS_FOR_NEIGHBOR_WARNING
+                && stats.build_fraction() > threshold
+            {
+                warn!(
+                    "LAMMPS rebuilt neighbor lists in {} of {} runs despite `pre no`. \
+                     This update style is likely slower than `run 0` for this system.",
+                    stats.builds, stats.runs,
+                );
+            }
+        }
+    }
+}
+
 /// Pre-packaged potentials.
 pub mod potential {
     use super::*;
@@ -160,6 +160,12 @@ pub(crate) trait LowLevelApi {
         len: usize,
     ) -> FailResult<Vec<f64>>;
 
+    /// Evaluate an equal-style variable.
+    ///
+    /// NOTE: Like computes, variables are evaluated on the spot, and some of the
+    ///       quantities they can reference may only be valid between runs.
+    unsafe fn extract_variable_0d(&mut self, name: String) -> FailResult<f64>;
+
     /// Gather an integer property across all atoms.
     ///
     /// Unsafe because an incorrect 'count' or a non-integer field may cause an out-of-bounds read.
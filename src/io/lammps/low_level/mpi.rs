@@ -143,6 +143,7 @@ c_enums!{
         ScatterAtomsF = 8,
         ExtractCompute0d = 9,
         ExtractCompute1d = 10,
+        ExtractVariable0d = 12,
     }
 }
 
@@ -160,6 +161,7 @@ pub(crate) enum Input {
     ScatterAtomsF(InputScatterAtomsF),
     ExtractCompute0d(InputExtractCompute0d),
     ExtractCompute1d(InputExtractCompute1d),
+    ExtractVariable0d(InputExtractVariable0d),
 }
 
 pub(crate) enum Output {
@@ -175,6 +177,7 @@ pub(crate) enum Output {
     ScatterAtomsF(OutputScatterAtomsF),
     ExtractCompute0d(OutputExtractCompute0d),
     ExtractCompute1d(OutputExtractCompute1d),
+    ExtractVariable0d(OutputExtractVariable0d),
 }
 
 // Generates broadcast impls that broadcast each field,
@@ -279,6 +282,9 @@ derive_low_level_api! {
 
     #[(unsafe) fn extract_compute_1d/ExtractCompute1d() -> OutputExtractCompute1d]
     pub(crate) struct InputExtractCompute1d { name: String, style: ComputeStyle, len: usize }
+
+    #[(unsafe) fn extract_variable_0d/ExtractVariable0d() -> OutputExtractVariable0d]
+    pub(crate) struct InputExtractVariable0d { name: String }
 }
 
 // New and Drop are special.
@@ -341,6 +347,8 @@ pub(crate) type OutputScatterAtomsF = FailResult<()>;
 pub(crate) type OutputExtractCompute0d = FailResult<f64>;
 /// This type exists to facilitate codegen.
 pub(crate) type OutputExtractCompute1d = FailResult<Vec<f64>>;
+/// This type exists to facilitate codegen.
+pub(crate) type OutputExtractVariable0d = FailResult<f64>;
 
 //------------------------------------------------
 
@@ -360,6 +368,7 @@ impl Broadcast for Input {
             Input::ScatterAtomsF { .. } => Method::ScatterAtomsF,
             Input::ExtractCompute0d { .. } => Method::ExtractCompute0d,
             Input::ExtractCompute1d { .. } => Method::ExtractCompute1d,
+            Input::ExtractVariable0d { .. } => Method::ExtractVariable0d,
         } as u32);
         let method = Broadcast::broadcast(root, method);
         let method = Method::from_int(method).unwrap();
@@ -395,6 +404,7 @@ impl Broadcast for Input {
             GatherAtomsI, GatherAtomsF,
             ScatterAtomsI, ScatterAtomsF,
             ExtractCompute0d, ExtractCompute1d,
+            ExtractVariable0d,
         }
     }
 }
@@ -476,6 +486,7 @@ impl DispatchMultiProcess for LammpsDispatch {
                 Input::ScatterAtomsF(input) => Output::ScatterAtomsF(input.invoke_method(root, lammps)),
                 Input::ExtractCompute0d(input) => Output::ExtractCompute0d(input.invoke_method(root, lammps)),
                 Input::ExtractCompute1d(input) => Output::ExtractCompute1d(input.invoke_method(root, lammps)),
+                Input::ExtractVariable0d(input) => Output::ExtractVariable0d(input.invoke_method(root, lammps)),
             }
         }
     }
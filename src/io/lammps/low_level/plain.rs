@@ -213,6 +213,9 @@ impl LowLevelApi for LammpsOwner {
     ) -> FailResult<Vec<f64>>
     { self.impl_extract_compute_1d(&name, style, len) }
 
+    unsafe fn extract_variable_0d(&mut self, name: String) -> FailResult<f64>
+    { self.impl_extract_variable_0d(&name) }
+
     unsafe fn gather_atoms_i(&mut self, name: String, count: usize) -> FailResult<Vec<i64>>
     { self.impl_gather_atoms_i(&name, count) }
 
@@ -571,6 +574,35 @@ impl LammpsOwner {
     })}
 }
 
+/// # Variables
+impl LammpsOwner {
+    // Evaluate an equal-style variable.
+    //
+    // NOTE: Equal-style variables are evaluated on the spot, so this is
+    //       potentially as dangerous as extract_compute.
+    unsafe fn impl_extract_variable_0d(&mut self, name: &str) -> FailResult<f64>
+    {Ok({
+        api_trace!("lammps_extract_variable({:p}, {}, NULL)", self.ptr, name);
+
+        let out_ptr = with_temporary_c_str(name, |name| {
+            unsafe { lammps_sys::lammps_extract_variable(self.ptr, name, std::ptr::null_mut()) }
+        }) as *mut c_double;
+
+        self.pop_error_as_result()?;
+
+        // NOTE: Known cases where the pointer is NULL:
+        // * Name provided does not belong to an equal-style variable.
+        let out = out_ptr.as_ref()
+            .unwrap_or_else(|| panic!("Could not extract variable {:?}", name))
+            .clone();
+
+        // For equal-style variables, lammps allocates a fresh double for us to free.
+        api_trace!("lammps_free({:p})", out_ptr);
+        lammps_sys::lammps_free(out_ptr as *mut c_void);
+        out
+    })}
+}
+
 //--------------------------------------
 // ffi utilz
 
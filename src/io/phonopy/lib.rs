@@ -161,6 +161,202 @@ pub mod conf {
     })}
 }
 
+pub use band_path::BandPath;
+pub mod band_path {
+    use super::*;
+    use rsp2_structure::Lattice;
+
+    /// Tolerance for deciding whether two consecutive segments share an endpoint.
+    const CONTINUITY_TOL: f64 = 1e-8;
+
+    /// A band structure path through reciprocal space, sampled the way phonopy samples
+    /// the `BAND` and `BAND_POINTS` settings.
+    ///
+    /// Each segment is sampled at `points_per_segment` evenly spaced points, including both
+    /// endpoints. (so the shared endpoint of two consecutive segments appears twice)
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct BandPath {
+        /// Sampled q-points, in fractional coordinates of the reciprocal lattice.
+        pub q_positions: Vec<V3>,
+        /// Cumulative cartesian distance along the path, suitable for the x-axis of a plot.
+        ///
+        /// This does not increase across a discontinuous jump between segments (i.e. when
+        /// one segment does not begin where the previous one ended), so that the jump appears
+        /// as a single vertical line rather than being interpolated across.
+        pub q_distance: Vec<f64>,
+        /// Index into `q_positions` of the first point of each segment.
+        pub segment_starts: Vec<usize>,
+    }
+
+    impl BandPath {
+        /// Sample a path through the reciprocal space of the (primitive) `lattice`.
+        ///
+        /// Segments are given as pairs of fractional q-points.  Distances are measured
+        /// without a factor of `2π`, consistent with `Lattice::reciprocal`.
+        pub fn new(lattice: &Lattice, segments: &[(V3, V3)], points_per_segment: usize) -> Self {
+            assert!(points_per_segment >= 2, "need at least two points per segment");
+
+            let reciprocal = lattice.reciprocal();
+            let mut q_positions = vec![];
+            let mut q_distance = vec![];
+            let mut segment_starts = vec![];
+            let mut distance = 0.0;
+            for &(start, end) in segments {
+                let cart_length = ((end - start) * &reciprocal).norm();
+                segment_starts.push(q_positions.len());
+                for i in 0..points_per_segment {
+                    let t = i as f64 / (points_per_segment - 1) as f64;
+                    // (the endpoint is stored exactly so that `conf` can reproduce it)
+                    q_positions.push(match i + 1 == points_per_segment {
+                        true => end,
+                        false => start + (end - start) * t,
+                    });
+                    q_distance.push(distance + cart_length * t);
+                }
+                distance += cart_length;
+            }
+            BandPath { q_positions, q_distance, segment_starts }
+        }
+
+        /// Phonopy settings that describe the same path.
+        ///
+        /// Runs of segments that each begin where the last one ended are written as a single
+        /// path in `BAND`, and discontinuous jumps are written as separate paths. (separated by
+        /// commas)
+        pub fn conf(&self) -> Conf {
+            let fmt_point = |V3([a, b, c]): V3| format!("{} {} {}", a, b, c);
+
+            // every segment has the same number of points
+            let points_per_segment = self.segment_starts.get(1).cloned().unwrap_or(self.q_positions.len());
+            let segments = self.segment_starts.iter().map(|&start| {
+                (self.q_positions[start], self.q_positions[start + points_per_segment - 1])
+            });
+
+            let mut paths: Vec<Vec<String>> = vec![];
+            let mut prev_end: Option<V3> = None;
+            for (start, end) in segments {
+                let continues = match prev_end {
+                    Some(prev_end) => (start - prev_end).norm() < CONTINUITY_TOL,
+                    None => false,
+                };
+                if !continues {
+                    paths.push(vec![fmt_point(start)]);
+                }
+                paths.last_mut().expect("BUG").push(fmt_point(end));
+                prev_end = Some(end);
+            }
+
+            let band = paths.iter().map(|path| path.join("  ")).collect::<Vec<_>>().join(", ");
+            let mut conf = Conf::new();
+            conf.insert("BAND".to_string(), band);
+            conf.insert("BAND_POINTS".to_string(), points_per_segment.to_string());
+            conf
+        }
+
+        /// Phonon group velocities `dω/dq` from finite differences of the frequencies along
+        /// each segment, indexed by q-point and then by band.
+        ///
+        /// `frequencies` must be indexed the same way. `lattice` must be the one the path was
+        /// constructed with.  Units are those of the frequencies times length (e.g. `cm^-1 Å`),
+        /// without a factor of `2π`, consistent with `q_distance`.
+        ///
+        /// Only the component along each segment can be obtained this way, and the error is
+        /// second-order in the point spacing (first-order at segment endpoints, and entirely
+        /// so for a segment with only two points); decrease the spacing via `BAND_POINTS` if this
+        /// matters.  Bands are assumed to be sorted by frequency, so the velocity will be wrong
+        /// near band crossings.  Velocities on zero-length segments are zero.
+        pub fn group_velocities(&self, lattice: &Lattice, frequencies: &[Vec<f64>]) -> Vec<Vec<V3>> {
+            assert_eq!(frequencies.len(), self.q_positions.len());
+            let reciprocal = lattice.reciprocal();
+
+            let mut segment_ends = self.segment_starts[1..].to_vec();
+            segment_ends.push(self.q_positions.len());
+
+            let mut out = Vec::with_capacity(self.q_positions.len());
+            for (&start, &end) in self.segment_starts.iter().zip(&segment_ends) {
+                let cart_delta = (self.q_positions[end - 1] - self.q_positions[start]) * &reciprocal;
+                let cart_length = cart_delta.norm();
+                for i in start..end {
+                    let num_bands = frequencies[i].len();
+                    if cart_length == 0.0 {
+                        out.push(vec![V3::zero(); num_bands]);
+                        continue;
+                    }
+                    let direction = cart_delta / cart_length;
+
+                    let prev = if i > start { i - 1 } else { i };
+                    let next = if i + 1 < end { i + 1 } else { i };
+                    let dq = self.q_distance[next] - self.q_distance[prev];
+                    assert_eq!(frequencies[prev].len(), frequencies[next].len());
+                    out.push({
+                        frequencies[prev].iter().zip(&frequencies[next])
+                            .map(|(&freq_prev, &freq_next)| direction * ((freq_next - freq_prev) / dq))
+                            .collect()
+                    });
+                }
+            }
+            out
+        }
+    }
+
+    #[test]
+    fn path_with_jump() {
+        let lattice = Lattice::orthorhombic(1.0, 2.0, 1.0);
+        let gamma = V3([0.0, 0.0, 0.0]);
+        let x = V3([0.5, 0.0, 0.0]);
+        let y = V3([0.0, 0.5, 0.0]);
+        let segments = [(gamma, x), (x, y), (gamma, y)];
+
+        let path = BandPath::new(&lattice, &segments, 3);
+        assert_eq!(path.segment_starts, vec![0, 3, 6]);
+        assert_eq!(path.q_positions[3], x);
+        assert_eq!(path.q_positions[4], V3([0.25, 0.25, 0.0]));
+
+        let x_y_length = f64::sqrt(0.5 * 0.5 + 0.25 * 0.25);
+        let expected_distance = vec![
+            0.0, 0.25, 0.5,
+            0.5, 0.5 + 0.5 * x_y_length, 0.5 + x_y_length,
+            // the jump back to gamma does not increase the distance
+            0.5 + x_y_length, 0.625 + x_y_length, 0.75 + x_y_length,
+        ];
+        for (actual, expected) in path.q_distance.iter().zip(expected_distance) {
+            assert!((actual - expected).abs() < 1e-12, "{} vs {}", actual, expected);
+        }
+
+        let conf = path.conf();
+        assert_eq!(conf["BAND"], "0 0 0  0.5 0 0  0 0.5 0, 0 0 0  0 0.5 0");
+        assert_eq!(conf["BAND_POINTS"], "3");
+    }
+
+    #[test]
+    fn group_velocities() {
+        let lattice = Lattice::orthorhombic(1.0, 2.0, 1.0);
+        let gamma = V3([0.0, 0.0, 0.0]);
+        let y = V3([0.0, 0.5, 0.0]);
+        let path = BandPath::new(&lattice, &[(gamma, y), (y, y)], 5);
+
+        // one linear band and one quadratic band, as functions of cartesian |q|
+        let frequencies: Vec<_> = path.q_positions.iter().map(|&q| {
+            let q_norm = (q * &lattice.reciprocal()).norm();
+            vec![3.0 * q_norm, 10.0 + 8.0 * q_norm * q_norm]
+        }).collect();
+
+        let velocities = path.group_velocities(&lattice, &frequencies);
+        assert_eq!(velocities.len(), 10);
+        for (i, (velocity, &q_norm)) in velocities.iter().zip(&path.q_distance).take(5).enumerate() {
+            assert!((velocity[0] - V3([0.0, 3.0, 0.0])).norm() < 1e-10);
+            // central differences are exact for a quadratic away from the endpoints
+            if 0 < i && i < 4 {
+                assert!((velocity[1] - V3([0.0, 16.0 * q_norm, 0.0])).norm() < 1e-10);
+            }
+        }
+        // zero-length segment
+        for velocity in &velocities[5..] {
+            assert_eq!(velocity, &vec![V3::zero(); 2]);
+        }
+    }
+}
+
 pub use symmetry_yaml::SymmetryYaml;
 pub mod symmetry_yaml {
     use super::*;
@@ -300,3 +496,138 @@ pub mod force_sets {
         assert_eq!(forces, force_sets.force_sets);
     }
 }
+
+#[cfg(feature = "hdf5-support")]
+pub use band_hdf5::BandHdf5;
+#[cfg(feature = "hdf5-support")]
+pub mod band_hdf5 {
+    use super::*;
+    use std::path::Path;
+
+    // h5py (and therefore phonopy) writes complex numbers as a compound type with these fields.
+    #[derive(hdf5::H5Type, Debug, Copy, Clone, PartialEq)]
+    #[repr(C)]
+    struct Complex {
+        r: f64,
+        i: f64,
+    }
+
+    /// Band structure data read from phonopy's `band.hdf5`.
+    ///
+    /// Everything is indexed by q-point, in order along the path.  (phonopy groups the
+    /// q-points by path segment; that grouping is flattened away here)
+    pub struct BandHdf5 {
+        /// Sampled q-points, in fractional coordinates of the reciprocal lattice.
+        pub q_positions: Vec<V3>,
+        /// Cumulative distance along the path, as computed by phonopy.
+        pub q_distance: Vec<f64>,
+        /// Frequencies in THz, indexed by q-point and then by band.
+        pub frequencies: Vec<Vec<f64>>,
+        /// Eigenvectors as `(real, imag)`, indexed by q-point and then by band.
+        ///
+        /// `None` unless phonopy was run with `EIGENVECTORS = .TRUE.`
+        pub eigenvectors: Option<Vec<Vec<(Vec<V3>, Vec<V3>)>>>,
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> FailResult<BandHdf5>
+    { _read(path.as_ref()) }
+
+    fn _read(path: &Path) -> FailResult<BandHdf5>
+    {
+        let file = hdf5::File::open(path)?;
+
+        let (q_axes, q_positions) = read_dataset::<f64>(&file, "path", &[3])?;
+        let num_q: usize = q_axes.iter().product();
+        let q_positions = q_positions.chunks(3).map(|v| V3([v[0], v[1], v[2]])).collect();
+
+        let (axes, q_distance) = read_dataset::<f64>(&file, "distance", &[])?;
+        ensure!(axes == q_axes, "band.hdf5: 'distance' does not match 'path'");
+
+        let num_bands = file.dataset("frequency")?.shape().last().cloned().unwrap_or(0);
+        let (axes, frequencies) = read_dataset::<f64>(&file, "frequency", &[num_bands])?;
+        ensure!(axes == q_axes, "band.hdf5: 'frequency' does not match 'path'");
+        let frequencies = frequencies.chunks(num_bands).map(|x| x.to_vec()).collect();
+
+        let eigenvectors = match file.link_exists("eigenvector") {
+            false => None,
+            true => {
+                // Each eigenvector is a column of a (3N x 3N) matrix.
+                let (axes, data) = read_dataset::<Complex>(&file, "eigenvector", &[num_bands, num_bands])?;
+                ensure!(axes == q_axes, "band.hdf5: 'eigenvector' does not match 'path'");
+                ensure!(num_bands % 3 == 0, "band.hdf5: number of bands is not a multiple of 3");
+
+                let matrix_len = num_bands * num_bands;
+                Some((0..num_q).map(|q| {
+                    let matrix = &data[q * matrix_len..(q + 1) * matrix_len];
+                    (0..num_bands).map(|band| {
+                        let column = (0..num_bands).map(|row| matrix[row * num_bands + band]).collect::<Vec<_>>();
+                        let real = column.chunks(3).map(|c| V3([c[0].r, c[1].r, c[2].r])).collect();
+                        let imag = column.chunks(3).map(|c| V3([c[0].i, c[1].i, c[2].i])).collect();
+                        (real, imag)
+                    }).collect()
+                }).collect())
+            },
+        };
+
+        Ok(BandHdf5 { q_positions, q_distance, frequencies, eigenvectors })
+    }
+
+    // Read a dataset whose shape ends in `trailing_axes`.  All axes before those index
+    // the q-points (whether phonopy writes one axis or two), and are returned alongside
+    // the flattened data.
+    fn read_dataset<T: hdf5::H5Type>(
+        file: &hdf5::File,
+        name: &str,
+        trailing_axes: &[usize],
+    ) -> FailResult<(Vec<usize>, Vec<T>)>
+    {
+        let dataset = file.dataset(name)?;
+        let shape = dataset.shape();
+        ensure!(
+            shape.len() >= trailing_axes.len() && shape.ends_with(trailing_axes),
+            "band.hdf5: expected '{}' to have shape [..., {:?}], got {:?}", name, trailing_axes, shape,
+        );
+        let leading_axes = shape[..shape.len() - trailing_axes.len()].to_vec();
+        Ok((leading_axes, dataset.read_raw::<T>()?))
+    }
+
+    #[test]
+    fn it_reads_band_hdf5() {
+        // Write a file with the layout used by phonopy: two segments of two q-points each,
+        // for a single atom (so 3 bands).
+        let dir = std::env::temp_dir().join(format!("rsp2-band-hdf5-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("band.hdf5");
+        {
+            let file = hdf5::File::create(&path).unwrap();
+            let q_positions = [
+                0.0, 0.0, 0.0,  0.5, 0.0, 0.0,
+                0.5, 0.0, 0.0,  0.5, 0.5, 0.0,
+            ];
+            file.new_dataset::<f64>().create("path", (2, 2, 3)).unwrap().write_raw(&q_positions).unwrap();
+            file.new_dataset::<f64>().create("distance", (2, 2)).unwrap().write_raw(&[0.0, 0.1, 0.1, 0.3]).unwrap();
+
+            let frequencies = (0..12).map(|x| x as f64).collect::<Vec<_>>();
+            file.new_dataset::<f64>().create("frequency", (2, 2, 3)).unwrap().write_raw(&frequencies).unwrap();
+
+            // at each q-point, the eigenvector matrix has `q + i*band` in column `band`
+            let eigenvectors = (0..4).flat_map(|q| {
+                (0..3).flat_map(move |_row| (0..3).map(move |band| Complex { r: q as f64, i: band as f64 }))
+            }).collect::<Vec<_>>();
+            file.new_dataset::<Complex>().create("eigenvector", (2, 2, 3, 3)).unwrap().write_raw(&eigenvectors).unwrap();
+        }
+
+        let band = read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(band.q_positions.len(), 4);
+        assert_eq!(band.q_positions[3], V3([0.5, 0.5, 0.0]));
+        assert_eq!(band.q_distance, vec![0.0, 0.1, 0.1, 0.3]);
+        assert_eq!(band.frequencies[2], vec![6.0, 7.0, 8.0]);
+
+        let eigenvectors = band.eigenvectors.unwrap();
+        assert_eq!(eigenvectors.len(), 4);
+        assert_eq!(eigenvectors[1].len(), 3);
+        assert_eq!(eigenvectors[1][2], (vec![V3([1.0; 3])], vec![V3([2.0; 3])]));
+    }
+}
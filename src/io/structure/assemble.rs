@@ -48,6 +48,10 @@ impl Assemble {
     pub fn layer_seps(&mut self) -> &mut [f64]
     { &mut self.layer_seps }
 
+    /// The current layer separations (as center-center distances)
+    pub fn get_layer_seps(&self) -> &[f64]
+    { &self.layer_seps }
+
     pub fn num_layer_seps(&self) -> usize
     { self.layer_seps.len() }
 
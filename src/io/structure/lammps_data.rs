@@ -0,0 +1,482 @@
+/* ************************************************************************ **
+** This file is part of rsp2, and is licensed under EITHER the MIT license  **
+** or the Apache 2.0 license, at your option.                               **
+**                                                                          **
+**     http://www.apache.org/licenses/LICENSE-2.0                           **
+**     http://opensource.org/licenses/MIT                                   **
+**                                                                          **
+** Be aware that not all of rsp2 is provided under this permissive license, **
+** and that the project as a whole is licensed under the GPL 3.0.           **
+** ************************************************************************ */
+
+//! Reader and writer for LAMMPS data files (as read by the `read_data` command).
+//!
+//! Only `atom_style atomic` is supported.
+
+use crate::FailResult;
+
+use rsp2_array_types::{V3};
+use rsp2_structure::{Coords, CoordsKind, Element, Lattice};
+
+use std::io::prelude::*;
+
+/// A LAMMPS atom type.
+///
+/// When a list of these is given to `dump`, the first is atom type 1,
+/// the second is atom type 2, and so on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtomType {
+    pub element: Element,
+    pub mass: f64,
+}
+
+/// Writes a LAMMPS data file in `atom_style atomic` format.
+///
+/// The lattice is rotated into the lower-triangular form required by LAMMPS,
+/// so the written cartesian positions may differ from those of `coords` by a rotation.
+/// If a tilt factor exceeds LAMMPS' limits (e.g. `|xy| > xx / 2`), the lattice is also
+/// replaced by an equivalent one (made by adding lattice vectors to each other) whose tilt
+/// factors are in range.  The positions are not wrapped into the new box, which LAMMPS
+/// takes care of in `read_data`.
+/// Every element in `elements` must appear exactly once in `atom_types`.
+pub fn dump(
+    mut w: impl Write,
+    title: &str,
+    coords: &Coords,
+    elements: &[Element],
+    atom_types: &[AtomType],
+) -> FailResult<()> {
+    if title.contains('\r') || title.contains('\n') {
+        bail!("LAMMPS data file header comment cannot contain newline.")
+    }
+    assert_eq!(coords.len(), elements.len());
+
+    for (i, a) in atom_types.iter().enumerate() {
+        if atom_types[..i].iter().any(|b| b.element == a.element) {
+            bail!("element {} appears twice in the LAMMPS atom types", a.element);
+        }
+    }
+    let type_indices = elements.iter().map(|&element| {
+        match atom_types.iter().position(|ty| ty.element == element) {
+            Some(index) => Ok(index + 1),
+            None => bail!("element {} has no LAMMPS atom type", element),
+        }
+    }).collect::<FailResult<Vec<_>>>()?;
+
+    let lattice = coords.lattice().rotate_to_lower_triangular();
+    {
+        let &[[xx, _, _], [_, yy, _], [_, _, zz]] = lattice.matrix().as_array();
+        if !(xx > 0.0 && yy > 0.0 && zz > 0.0) {
+            bail!("LAMMPS data files cannot represent a left-handed cell");
+        }
+    }
+    let carts = Coords::new(lattice.clone(), CoordsKind::Fracs(coords.to_fracs())).to_carts();
+
+    let lattice = reduce_tilt_factors(&lattice);
+    let &[
+        [xx,  _0,  _1],
+        [xy,  yy,  _2],
+        [xz,  yz,  zz],
+    ] = lattice.matrix().as_array();
+
+    writeln!(w, "{}", title)?;
+    writeln!(w)?;
+    writeln!(w, "{} atoms", coords.len())?;
+    writeln!(w, "{} atom types", atom_types.len())?;
+    writeln!(w)?;
+    writeln!(w, "0.0 {} xlo xhi", xx)?;
+    writeln!(w, "0.0 {} ylo yhi", yy)?;
+    writeln!(w, "0.0 {} zlo zhi", zz)?;
+    if (xy, xz, yz) != (0.0, 0.0, 0.0) {
+        writeln!(w, "{} {} {} xy xz yz", xy, xz, yz)?;
+    }
+
+    writeln!(w)?;
+    writeln!(w, "Masses")?;
+    writeln!(w)?;
+    for (i, AtomType { element, mass }) in atom_types.iter().enumerate() {
+        writeln!(w, "{} {} # {}", i + 1, mass, element)?;
+    }
+
+    writeln!(w)?;
+    writeln!(w, "Atoms # atomic")?;
+    writeln!(w)?;
+    for (i, (V3([x, y, z]), ty)) in zip_eq!(carts, type_indices).enumerate() {
+        writeln!(w, "{} {} {} {} {}", i + 1, ty, x, y, z)?;
+    }
+    Ok(())
+}
+
+// Bring the tilt factors of a lower-triangular lattice within the limits accepted by LAMMPS
+// (`|xy| <= xx / 2`, `|xz| <= xx / 2`, `|yz| <= yy / 2`) by adding integer multiples of
+// earlier lattice vectors to later ones.  This describes the same periodic lattice.
+// A tilt of exactly half the box length is left alone.
+fn reduce_tilt_factors(lattice: &Lattice) -> Lattice {
+    let multiple = |tilt: f64, length: f64| match tilt / length {
+        ratio if ratio.abs() > 0.5 => ratio.round(),
+        _ => 0.0,
+    };
+    let &[a, mut b, mut c] = lattice.vectors();
+    c -= b * multiple(c[1], b[1]);
+    c -= a * multiple(c[0], a[0]);
+    b -= a * multiple(b[0], a[0]);
+    Lattice::from_vectors(&[a, b, c])
+}
+
+/// The contents of a LAMMPS data file, as read by `load`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LammpsData {
+    pub title: String,
+    pub coords: Coords,
+    pub elements: Vec<Element>,
+    /// Per-site masses, from the `Masses` section.
+    pub masses: Vec<f64>,
+}
+
+/// Reads a LAMMPS data file in `atom_style atomic` format.
+///
+/// The element of each atom type is read from the comment after its entry in `Masses`
+/// (as written by `dump`), or else guessed from its mass.  Sites are ordered by atom ID,
+/// and sections other than `Masses` and `Atoms` (e.g. `Velocities`) are ignored.
+pub fn load(r: impl BufRead) -> FailResult<LammpsData> {
+    let mut lines = r.lines();
+    let title = match lines.next() {
+        Some(line) => line?.trim().to_string(),
+        None => bail!("empty LAMMPS data file"),
+    };
+
+    let mut num_atoms = None;
+    let mut bounds = [None; 3];
+    let mut tilts = [0.0; 3];
+    let mut type_info = vec![]; // [(type id, mass, Option<element>)]
+    let mut atoms = vec![]; // [(atom id, type id, cart)]
+    let mut section = None;
+    for line in lines {
+        let line = line?;
+        let mut parts = line.splitn(2, '#');
+        let words: Vec<&str> = parts.next().unwrap().split_whitespace().collect();
+        let comment = parts.next().map(str::trim);
+        if words.is_empty() {
+            continue;
+        }
+
+        let float = |i: usize| -> FailResult<f64> {
+            words[i].parse().map_err(|_| format_err!("expected a number, got {:?}", words[i]))
+        };
+        let int = |i: usize| -> FailResult<usize> {
+            words[i].parse().map_err(|_| format_err!("expected an integer, got {:?}", words[i]))
+        };
+
+        // A line that does not begin with a number is a section header.
+        if words[0].parse::<f64>().is_err() {
+            section = Some(words[0].to_string());
+            if words[0] == "Atoms" {
+                match comment {
+                    None | Some("atomic") => {},
+                    Some(style) => bail!("unsupported atom style in LAMMPS data file: {}", style),
+                }
+            }
+            continue;
+        }
+
+        match (section.as_ref().map(|s| &s[..]), &words[..]) {
+            (None, [_, "atoms"]) => num_atoms = Some(int(0)?),
+            (None, [_, _, "xlo", "xhi"]) => bounds[0] = Some((float(0)?, float(1)?)),
+            (None, [_, _, "ylo", "yhi"]) => bounds[1] = Some((float(0)?, float(1)?)),
+            (None, [_, _, "zlo", "zhi"]) => bounds[2] = Some((float(0)?, float(1)?)),
+            (None, [_, _, _, "xy", "xz", "yz"]) => tilts = [float(0)?, float(1)?, float(2)?],
+            // other counts, e.g. "1 atom types" or "0 bonds"
+            (None, _) => {},
+            (Some("Masses"), [_, _]) => {
+                let element = match comment {
+                    Some(symbol) => Some(Element::from_symbol(symbol)?),
+                    None => None,
+                };
+                type_info.push((int(0)?, float(1)?, element));
+            },
+            // (optionally followed by image flags, which do not matter to us)
+            (Some("Atoms"), [_, _, _, _, _]) |
+            (Some("Atoms"), [_, _, _, _, _, _, _, _]) => {
+                atoms.push((int(0)?, int(1)?, V3([float(2)?, float(3)?, float(4)?])));
+            },
+            (Some("Masses"), _) |
+            (Some("Atoms"), _) => bail!("unexpected line in LAMMPS data file: {:?}", line),
+            (Some(_), _) => {},
+        }
+    }
+
+    let bounds = match bounds {
+        [Some(x), Some(y), Some(z)] => [x, y, z],
+        _ => bail!("LAMMPS data file is missing box bounds"),
+    };
+    match num_atoms {
+        Some(n) if n == atoms.len() => {},
+        Some(n) => bail!("LAMMPS data file has {} atoms, but its header says {}", atoms.len(), n),
+        None => bail!("LAMMPS data file is missing the number of atoms"),
+    }
+
+    let [xy, xz, yz] = tilts;
+    let lattice = Lattice::from([
+        [bounds[0].1 - bounds[0].0, 0.0, 0.0],
+        [xy, bounds[1].1 - bounds[1].0, 0.0],
+        [xz, yz, bounds[2].1 - bounds[2].0],
+    ]);
+    let origin = V3([bounds[0].0, bounds[1].0, bounds[2].0]);
+
+    atoms.sort_by_key(|&(id, _, _)| id);
+    let mut carts = vec![];
+    let mut elements = vec![];
+    let mut masses = vec![];
+    for (_, ty, cart) in atoms {
+        let (_, mass, element) = match type_info.iter().find(|&&(id, _, _)| id == ty) {
+            Some(&info) => info,
+            None => bail!("atom type {} has no entry in Masses", ty),
+        };
+        let element = match element {
+            Some(element) => element,
+            None => element_from_mass(mass)?,
+        };
+        carts.push(cart - origin);
+        elements.push(element);
+        masses.push(mass);
+    }
+    let coords = Coords::new(lattice, CoordsKind::Carts(carts));
+    Ok(LammpsData { title, coords, elements, masses })
+}
+
+// The element whose standard atomic weight is closest to `mass`.
+fn element_from_mass(mass: f64) -> FailResult<Element> {
+    const MAX_DIFFERENCE: f64 = 0.1;
+
+    let closest = {
+        (1..).map(Element::from_atomic_number)
+            .take_while(|elem| elem.is_some())
+            .flatten()
+            .filter_map(|elem| elem.mass().map(|m| (elem, (m - mass).abs())))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+    };
+    match closest {
+        Some((elem, diff)) if diff < MAX_DIFFERENCE => Ok(elem),
+        _ => bail!("cannot determine the element for mass {}; please label it with a comment", mass),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsp2_structure::consts::{CARBON, HYDROGEN};
+
+    #[derive(Debug, PartialEq)]
+    struct Parsed {
+        bounds: [f64; 3],
+        tilts: [f64; 3],
+        masses: Vec<f64>,
+        atoms: Vec<(usize, V3)>,
+    }
+
+    // Just enough of a parser to read back the output of `dump`.
+    fn parse(text: &str) -> Parsed {
+        let mut bounds = [0.0; 3];
+        let mut tilts = [0.0; 3];
+        let mut masses = vec![];
+        let mut atoms = vec![];
+        let mut section = None;
+        for line in text.lines().skip(1) {
+            let line = line.split('#').next().unwrap().trim();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let float = |i: usize| words[i].parse::<f64>().unwrap();
+            match (section, &words[..]) {
+                (_, []) => {},
+                (_, ["Masses"]) => section = Some("Masses"),
+                (_, ["Atoms"]) => section = Some("Atoms"),
+                (None, [_, "atoms"]) => {},
+                (None, [_, "atom", "types"]) => {},
+                (None, [_, _, "xlo", "xhi"]) => bounds[0] = float(1),
+                (None, [_, _, "ylo", "yhi"]) => bounds[1] = float(1),
+                (None, [_, _, "zlo", "zhi"]) => bounds[2] = float(1),
+                (None, [_, _, _, "xy", "xz", "yz"]) => tilts = [float(0), float(1), float(2)],
+                (Some("Masses"), [_, _]) => masses.push(float(1)),
+                (Some("Atoms"), [_, ty, _, _, _]) => {
+                    atoms.push((ty.parse().unwrap(), V3([float(2), float(3), float(4)])));
+                },
+                _ => panic!("unexpected line: {:?}", line),
+            }
+        }
+        Parsed { bounds, tilts, masses, atoms }
+    }
+
+    fn assert_all_close(actual: &[V3], expected: &[V3]) {
+        for (a, b) in zip_eq!(actual, expected) {
+            for k in 0..3 {
+                assert!((a[k] - b[k]).abs() < 1e-12, "{:?} {:?}", actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        // already lower-triangular, so that positions are unchanged.
+        let lattice = Lattice::from([
+            [2.5, 0.0, 0.0],
+            [-1.25, 2.0, 0.0],
+            [0.5, 0.25, 10.0],
+        ]);
+        let carts = vec![
+            V3([0.0, 0.0, 5.0]),
+            V3([1.0, 0.5, 5.0]),
+            V3([0.0, 1.5, 6.0]),
+        ];
+        let coords = Coords::new(lattice, CoordsKind::Carts(carts.clone()));
+        let elements = vec![CARBON, HYDROGEN, CARBON];
+        let atom_types = [
+            AtomType { element: HYDROGEN, mass: 1.00794 },
+            AtomType { element: CARBON, mass: 12.0107 },
+        ];
+
+        let mut buf = vec![];
+        dump(&mut buf, "test structure", &coords, &elements, &atom_types).unwrap();
+        let parsed = parse(&String::from_utf8(buf).unwrap());
+
+        assert_eq!(parsed.bounds, [2.5, 2.0, 10.0]);
+        assert_eq!(parsed.tilts, [-1.25, 0.5, 0.25]);
+        assert_eq!(parsed.masses, vec![1.00794, 12.0107]);
+        assert_eq!(parsed.atoms.iter().map(|&(ty, _)| ty).collect::<Vec<_>>(), vec![2, 1, 2]);
+        for ((_, actual), expected) in zip_eq!(&parsed.atoms, &carts) {
+            for k in 0..3 {
+                assert!((actual[k] - expected[k]).abs() < 1e-12, "{:?} {:?}", actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn rotates_lattice() {
+        // a cell whose first vector lies along y
+        let lattice = Lattice::from([
+            [0.0, 3.0, 0.0],
+            [-2.0, 0.0, 0.0],
+            [0.0, 0.0, 4.0],
+        ]);
+        let coords = Coords::new(lattice, CoordsKind::Fracs(vec![V3([0.5, 0.25, 0.5])]));
+        let atom_types = [AtomType { element: CARBON, mass: 12.0 }];
+
+        let mut buf = vec![];
+        dump(&mut buf, "", &coords, &[CARBON], &atom_types).unwrap();
+        let parsed = parse(&String::from_utf8(buf).unwrap());
+
+        assert_eq!(parsed.bounds, [3.0, 2.0, 4.0]);
+        assert_eq!(parsed.tilts, [0.0, 0.0, 0.0]);
+        let (_, cart) = parsed.atoms[0];
+        for (k, &expected) in [1.5, 0.5, 2.0].iter().enumerate() {
+            assert!((cart[k] - expected).abs() < 1e-12, "{:?}", cart);
+        }
+    }
+
+    #[test]
+    fn large_tilt_factors() {
+        // xy, xz and yz are all too large for LAMMPS
+        let lattice = Lattice::from([
+            [2.0, 0.0, 0.0],
+            [1.5, 2.0, 0.0],
+            [-3.25, 2.5, 4.0],
+        ]);
+        let carts = vec![V3([0.5, 0.5, 1.0]), V3([3.0, 2.5, 3.5])];
+        let coords = Coords::new(lattice, CoordsKind::Carts(carts.clone()));
+        let atom_types = [AtomType { element: CARBON, mass: 12.0 }];
+
+        let mut buf = vec![];
+        dump(&mut buf, "", &coords, &[CARBON, CARBON], &atom_types).unwrap();
+        let parsed = parse(&String::from_utf8(buf.clone()).unwrap());
+
+        assert_eq!(parsed.bounds, [2.0, 2.0, 4.0]);
+        // c - b = [-4.75, 0.5, 4.0];  then + 2a = [-0.75, 0.5, 4.0];  b - a = [-0.5, 2.0, 0.0]
+        assert_eq!(parsed.tilts, [-0.5, -0.75, 0.5]);
+        // positions are left alone
+        assert_all_close(&parsed.atoms.iter().map(|&(_, v)| v).collect::<Vec<_>>(), &carts);
+
+        // ...and it is still the same periodic structure
+        let data = load(&buf[..]).unwrap();
+        let unimodular = coords.lattice().matrix() * data.coords.lattice().inverse_matrix();
+        for row in &unimodular.0 {
+            for &x in &row.0 {
+                assert!((x - x.round()).abs() < 1e-12, "{:?}", unimodular);
+            }
+        }
+    }
+
+    #[test]
+    fn load_round_trip() {
+        let lattice = Lattice::from([
+            [2.5, 0.0, 0.0],
+            [-1.25, 2.0, 0.0],
+            [0.5, 0.25, 10.0],
+        ]);
+        let carts = vec![
+            V3([0.0, 0.0, 5.0]),
+            V3([1.0, 0.5, 5.0]),
+            V3([0.0, 1.5, 6.0]),
+        ];
+        let coords = Coords::new(lattice.clone(), CoordsKind::Carts(carts.clone()));
+        let elements = vec![CARBON, HYDROGEN, CARBON];
+        let atom_types = [
+            AtomType { element: HYDROGEN, mass: 1.00794 },
+            AtomType { element: CARBON, mass: 12.0107 },
+        ];
+
+        let mut buf = vec![];
+        dump(&mut buf, "test structure", &coords, &elements, &atom_types).unwrap();
+        let data = load(&buf[..]).unwrap();
+
+        assert_eq!(data.title, "test structure");
+        assert_eq!(data.elements, elements);
+        assert_eq!(data.masses, vec![12.0107, 1.00794, 12.0107]);
+        assert_all_close(&data.coords.lattice().matrix().0, &lattice.matrix().0);
+        assert_all_close(&data.coords.to_carts(), &carts);
+    }
+
+    #[test]
+    fn load_other_writers() {
+        // Shuffled atom IDs, a shifted box, image flags, no element comments,
+        // and sections that we don't care about.
+        let text = "\
+LAMMPS data file via write_data
+
+2 atoms
+1 atom types
+
+-1.0 2.0 xlo xhi
+0.0 3.0 ylo yhi
+1.0 5.0 zlo zhi
+
+Masses
+
+1 12.011
+
+Atoms # atomic
+
+2 1 0.5 1.5 2.0 0 0 0
+1 1 -1.0 0.0 1.0 0 0 1
+
+Velocities
+
+1 0.0 0.0 0.0
+2 0.0 0.0 0.0
+";
+        let data = load(text.as_bytes()).unwrap();
+        assert_eq!(data.elements, vec![CARBON, CARBON]);
+        let expected_lattice = Lattice::diagonal(&[3.0, 3.0, 4.0]);
+        assert_all_close(&data.coords.lattice().matrix().0, &expected_lattice.matrix().0);
+        assert_all_close(&data.coords.to_carts(), &[V3([0.0, 0.0, 0.0]), V3([1.5, 1.5, 1.0])]);
+
+        let bad_style = text.replace("Atoms # atomic", "Atoms # full");
+        assert!(load(bad_style.as_bytes()).is_err());
+        let unknown_mass = text.replace("1 12.011", "1 1000.0");
+        assert!(load(unknown_mass.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn missing_atom_type() {
+        let coords = Coords::new(Lattice::eye(), CoordsKind::Fracs(vec![V3([0.0; 3])]));
+        let atom_types = [AtomType { element: CARBON, mass: 12.0 }];
+        assert!(dump(&mut vec![], "", &coords, &[HYDROGEN], &atom_types).is_err());
+    }
+}
@@ -22,9 +22,11 @@ pub fn FailOk<T>(x: T) -> Result<T, failure::Error> { Ok(x) }
 pub use crate::poscar::Poscar;
 mod poscar;
 
-pub use crate::xyz::Xyz;
+pub use crate::xyz::{Xyz, ExtendedXyz};
+pub use crate::xyz::Property as XyzProperty;
 mod xyz;
 
 pub mod v_sim;
+pub mod lammps_data;
 pub mod layers_yaml;
 pub mod assemble;
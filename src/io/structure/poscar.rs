@@ -15,14 +15,14 @@ use std::io::prelude::*;
 use std::borrow::Borrow;
 
 use rsp2_structure::{Element, Coords as Coords, Lattice, CoordsKind};
-use rsp2_array_types::{Envee, Unvee};
+use rsp2_array_types::{V3, Envee, Unvee};
 
 use vasp_poscar as imp;
 
 //--------------------------------------------------------------------------------------
 // public API
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Poscar<
     Comment = String,
     Coord = Coords,
@@ -31,6 +31,11 @@ pub struct Poscar<
     pub comment: Comment,
     pub coords: Coord,
     pub elements: Elements,
+    /// Per-site selective dynamics flags, indicating which cartesian components
+    /// of each position are allowed to move.
+    ///
+    /// `None` when the file has no "Selective dynamics" line.
+    pub dynamics: Option<Vec<[bool; 3]>>,
 }
 
 impl<Comment, Coord, Elements> Poscar<Comment, Coord, Elements>
@@ -41,7 +46,13 @@ where
 {
     /// Writes a POSCAR to an open file.
     pub fn to_writer(&self, mut w: impl Write) -> FailResult<()> {
-        dump(&mut w, self.comment.as_ref(), self.coords.borrow(), self.elements.as_ref())
+        dump(
+            &mut w,
+            self.comment.as_ref(),
+            self.coords.borrow(),
+            self.elements.as_ref(),
+            self.dynamics.as_ref().map(|d| &d[..]),
+        )
     }
 }
 
@@ -60,6 +71,16 @@ impl Poscar {
     pub fn from_buf_reader(f: impl BufRead) -> FailResult<Self> {
         load_txt(&mut std::io::BufReader::new(f))
     }
+
+    /// Reads a POSCAR from an open file, one line at a time.
+    ///
+    /// Unlike `from_buf_reader`, this never holds more than a single line of text
+    /// in memory, which makes a difference for very large supercells.  The output
+    /// is the same.  Reading stops after the positions; anything that follows them
+    /// (e.g. velocities) is not read.
+    pub fn load_streaming(f: impl BufRead) -> FailResult<Self> {
+        load_streaming(&mut LineReader::new(f))
+    }
 }
 
 //--------------------------------------------------------------------------------------
@@ -71,16 +92,26 @@ fn dump(
     title: &str,
     coords: &Coords,
     elements: &[Element],
+    dynamics: Option<&[[bool; 3]]>,
 ) -> FailResult<()>
 {
-    write!(w, "{}",
-        vasp_poscar::Builder::new()
-            .comment(title)
-            .lattice_vectors(coords.lattice().matrix().as_array())
-            .positions(vasp_poscar::Coords::Cart(coords.to_carts().unvee()))
-            .site_symbols(elements.iter().map(|&elem| elem.symbol()))
-            .build()?,
-    )?;
+    let mut builder = vasp_poscar::Builder::new();
+    builder
+        .comment(title)
+        .lattice_vectors(coords.lattice().matrix().as_array())
+        .positions(vasp_poscar::Coords::Cart(coords.to_carts().unvee()))
+        .site_symbols(elements.iter().map(|&elem| elem.symbol()));
+
+    if let Some(dynamics) = dynamics {
+        ensure!(
+            dynamics.len() == coords.len(),
+            "selective dynamics flags are for {} sites, but the structure has {}",
+            dynamics.len(), coords.len(),
+        );
+        builder.dynamics(dynamics.iter().cloned());
+    }
+
+    write!(w, "{}", builder.build()?)?;
     Ok(())
 }
 
@@ -97,17 +128,33 @@ fn load_txt(f: &mut dyn BufRead) -> FailResult<Poscar>
         imp::Coords::Frac(p) => CoordsKind::Fracs(p.to_vec().envee()),
     };
 
+    let group_symbols = poscar.group_symbols().map(|iter| iter.map(|s| s.to_string()).collect());
+    let group_counts = poscar.group_counts().collect::<Vec<_>>();
+    let elements = elements_from_groups(&comment, group_symbols, &group_counts)?;
+
+    assert_eq!(elements.len(), coords.len());
+    let coords = Coords::new(lattice, coords);
+    let dynamics = poscar.dynamics().map(|d| d.to_vec());
+    Ok(Poscar { comment, coords, elements, dynamics })
+}
+
+fn elements_from_groups(
+    comment: &str,
+    group_symbols: Option<Vec<String>>,
+    group_counts: &[usize],
+) -> FailResult<Vec<Element>>
+{
     let group_elems = {
         // we need symbols, but prior to VASP 5 they were not even part of
         // the format, so some programs don't write them where they belong.
         // Sometimes they are used as the title comment (by phonopy, ASE...).
-        let group_symbols = match poscar.group_symbols() {
-            Some(iter) => iter.map(|s| s.to_string()).collect(),
+        let group_symbols = match group_symbols {
+            Some(symbols) => symbols,
             None => {
-                let words = poscar.comment().split_whitespace();
+                let words = comment.split_whitespace();
                 let symbols = words.map(|s| s.to_string()).collect::<Vec<_>>();
                 ensure!(
-                    symbols.len() == poscar.group_counts().len(),
+                    symbols.len() == group_counts.len(),
                     "Symbols must be given either in the standard location or the POSCAR comment."
                 );
                 // pray for the best.  If they're not the group symbols, it is at least
@@ -120,11 +167,327 @@ fn load_txt(f: &mut dyn BufRead) -> FailResult<Poscar>
             .collect::<Result<Vec<Element>, _>>()?
     };
 
-    let elements = zip_eq!(poscar.group_counts(), group_elems)
-        .flat_map(|(c, elem)| std::iter::repeat(elem).take(c))
-        .collect::<Vec<_>>();
+    Ok(zip_eq!(group_counts, group_elems)
+        .flat_map(|(&c, elem)| std::iter::repeat(elem).take(c))
+        .collect())
+}
+
+//--------------------------------------------------------------------------------------
+// streaming implementation
 
-    assert_eq!(elements.len(), coords.len());
+/// Reads a file one line at a time, reusing a single buffer.
+struct LineReader<R> {
+    reader: R,
+    buf: String,
+    line_number: usize,
+}
+
+impl<R: BufRead> LineReader<R> {
+    fn new(reader: R) -> Self {
+        LineReader { reader, buf: String::new(), line_number: 0 }
+    }
+
+    /// Read the next line, without its line terminator.  Also returns the line number.
+    fn next_line(&mut self, what: &str) -> FailResult<(usize, &str)> {
+        self.buf.clear();
+        self.line_number += 1;
+        if self.reader.read_line(&mut self.buf)? == 0 {
+            bail!("line {}: unexpected end of POSCAR while reading {}", self.line_number, what);
+        }
+        Ok((self.line_number, self.buf.trim_end_matches(|c| c == '\n' || c == '\r')))
+    }
+}
+
+fn load_streaming<R: BufRead>(r: &mut LineReader<R>) -> FailResult<Poscar>
+{
+    // (Fortran-style exponents like `1.0D-3` are accepted, as written by some programs)
+    fn parse_float(word: &str) -> Option<f64> {
+        match word.parse() {
+            Ok(x) => Some(x),
+            Err(_) => word.replace(|c| c == 'd' || c == 'D', "e").parse().ok(),
+        }
+    }
+
+    fn parse_v3<'a>(words: &mut impl Iterator<Item=&'a str>) -> Option<V3> {
+        let mut out = V3::zero();
+        for k in 0..3 {
+            out[k] = parse_float(words.next()?)?;
+        }
+        Some(out)
+    }
+
+    fn parse_flag(word: &str) -> Option<bool> {
+        match word.chars().next()? {
+            'T' | 't' => Some(true),
+            'F' | 'f' => Some(false),
+            _ => None,
+        }
+    }
+
+    fn first_char(line: &str) -> Option<char> {
+        line.trim_start().chars().next()
+    }
+
+    let comment = r.next_line("comment")?.1.to_string();
+
+    // Each cartesian component of the lattice vectors (and cartesian positions)
+    // gets multiplied by a factor.  VASP allows a single scale factor, three
+    // factors, or a negative number giving the cell volume.
+    let (scale_line_number, scale_values) = {
+        let (line_number, line) = r.next_line("scale")?;
+        match line.split_whitespace().map(parse_float).collect::<Option<Vec<_>>>() {
+            Some(values) => (line_number, values),
+            None => bail!("line {}: invalid scale line: {:?}", line_number, line),
+        }
+    };
+
+    let unscaled_vectors = {
+        let mut rows = [V3::zero(); 3];
+        for row in &mut rows {
+            let (line_number, line) = r.next_line("lattice")?;
+            *row = match parse_v3(&mut line.split_whitespace()) {
+                Some(v) => v,
+                None => bail!("line {}: invalid lattice vector: {:?}", line_number, line),
+            };
+        }
+        rows
+    };
+
+    let scale_factors = match scale_values[..] {
+        [x] if x > 0.0 => V3([x; 3]),
+        [x] if x < 0.0 => {
+            let volume = Lattice::from_vectors(&unscaled_vectors).volume();
+            V3([f64::cbrt(-x / volume); 3])
+        },
+        [x, y, z] if x > 0.0 && y > 0.0 && z > 0.0 => V3([x, y, z]),
+        _ => bail!("line {}: invalid scale factor(s): {:?}", scale_line_number, scale_values),
+    };
+    let scale_vector = |v: V3| V3::from_fn(|k| v[k] * scale_factors[k]);
+    let lattice = Lattice::from_vectors(&{
+        let mut rows = unscaled_vectors;
+        for row in &mut rows {
+            *row = scale_vector(*row);
+        }
+        rows
+    });
+
+    // The symbols line is optional; if present, it is followed by the counts.
+    let (group_symbols, group_counts): (_, Vec<usize>) = {
+        let (line_number, line) = r.next_line("counts")?;
+        let is_symbols = match line.split_whitespace().next() {
+            Some(word) => word.parse::<usize>().is_err(),
+            None => bail!("line {}: expected symbols or counts", line_number),
+        };
+        let group_symbols = match is_symbols {
+            true => Some(line.split_whitespace().map(|s| s.to_string()).collect::<Vec<_>>()),
+            false => None,
+        };
+
+        let (line_number, line) = match is_symbols {
+            true => r.next_line("counts")?,
+            false => (line_number, line),
+        };
+        let group_counts = match line.split_whitespace().map(|w| w.parse().ok()).collect() {
+            Some(counts) => counts,
+            None => bail!("line {}: invalid counts: {:?}", line_number, line),
+        };
+        (group_symbols, group_counts)
+    };
+    if let Some(symbols) = &group_symbols {
+        ensure!(
+            symbols.len() == group_counts.len(),
+            "line {}: there are {} symbols but {} counts",
+            r.line_number, symbols.len(), group_counts.len(),
+        );
+    }
+    let num_atoms = group_counts.iter().sum::<usize>();
+
+    let (_, mut line) = r.next_line("coordinate system")?;
+    let has_dynamics = match first_char(line) {
+        Some('S') | Some('s') => true,
+        _ => false,
+    };
+    if has_dynamics {
+        line = r.next_line("coordinate system")?.1;
+    }
+    let is_cart = match first_char(line) {
+        Some('C') | Some('c') | Some('K') | Some('k') => true,
+        _ => false,
+    };
+
+    let mut positions = Vec::with_capacity(num_atoms);
+    let mut dynamics = Vec::with_capacity(if has_dynamics { num_atoms } else { 0 });
+    for _ in 0..num_atoms {
+        let (line_number, line) = r.next_line("positions")?;
+        let mut words = line.split_whitespace();
+        match parse_v3(&mut words) {
+            Some(v) => positions.push(v),
+            None => bail!("line {}: invalid position: {:?}", line_number, line),
+        }
+        if has_dynamics {
+            let mut next_flag = || words.next().and_then(parse_flag);
+            match (next_flag(), next_flag(), next_flag()) {
+                (Some(a), Some(b), Some(c)) => dynamics.push([a, b, c]),
+                _ => bail!("line {}: invalid selective dynamics flags: {:?}", line_number, line),
+            }
+        }
+    }
+
+    let coords = match is_cart {
+        true => {
+            for v in &mut positions {
+                *v = scale_vector(*v);
+            }
+            CoordsKind::Carts(positions)
+        },
+        false => CoordsKind::Fracs(positions),
+    };
+
+    let elements = elements_from_groups(&comment, group_symbols, &group_counts)?;
     let coords = Coords::new(lattice, coords);
-    Ok(Poscar { comment, coords, elements })
+    let dynamics = match has_dynamics {
+        true => Some(dynamics),
+        false => None,
+    };
+    Ok(Poscar { comment, coords, elements, dynamics })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsp2_structure::consts::{CARBON, HYDROGEN};
+
+    fn example(dynamics: Option<Vec<[bool; 3]>>) -> Poscar {
+        Poscar {
+            comment: "test".into(),
+            coords: Coords::new(
+                Lattice::cubic(3.0),
+                CoordsKind::Fracs(vec![[0.0, 0.0, 0.0], [0.5, 0.5, 0.25]].envee()),
+            ),
+            elements: vec![CARBON; 2],
+            dynamics,
+        }
+    }
+
+    fn round_trip(poscar: &Poscar) -> Poscar {
+        let mut buf = vec![];
+        poscar.to_writer(&mut buf).unwrap();
+        Poscar::from_buf_reader(&buf[..]).unwrap()
+    }
+
+    #[test]
+    fn selective_dynamics_round_trip() {
+        let poscar = example(Some(vec![[true, true, false], [false, false, true]]));
+        let text = {
+            let mut buf = vec![];
+            poscar.to_writer(&mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert!(text.to_lowercase().contains("selective dynamics"));
+        assert_eq!(round_trip(&poscar).dynamics, poscar.dynamics);
+    }
+
+    #[test]
+    fn no_selective_dynamics() {
+        let poscar = example(None);
+        assert_eq!(round_trip(&poscar).dynamics, None);
+    }
+
+    #[test]
+    fn streaming_matches_buffered() {
+        for dynamics in vec![None, Some(vec![[true, true, false], [false, false, true]])] {
+            let poscar = example(dynamics);
+            let mut buf = vec![];
+            poscar.to_writer(&mut buf).unwrap();
+            assert_eq!(
+                Poscar::load_streaming(&buf[..]).unwrap(),
+                Poscar::from_buf_reader(&buf[..]).unwrap(),
+            );
+        }
+    }
+
+    // A supercell of graphene-like sites, written by `to_writer`.
+    fn large_poscar_text(num_atoms: usize) -> Vec<u8> {
+        let side = (num_atoms as f64).sqrt().ceil();
+        let fracs = (0..num_atoms).map(|i| {
+            let (a, b) = ((i as f64 % side) / side, (i as f64 / side).floor() / side);
+            // (irrational-ish offsets so that no digits are trivially round)
+            V3([a + 1e-3 * f64::sin(i as f64), b, 0.5 + 1e-2 * f64::cos(i as f64)])
+        }).collect();
+        let lattice = Lattice::from_vectors(&[
+            V3([2.46 * side, 0.0, 0.0]),
+            V3([-1.23 * side, 2.13 * side, 0.0]),
+            V3([0.0, 0.0, 15.0]),
+        ]);
+        let poscar: Poscar = Poscar {
+            comment: "large".into(),
+            coords: Coords::new(lattice, CoordsKind::Fracs(fracs)),
+            elements: (0..num_atoms).map(|i| if i < num_atoms / 2 { CARBON } else { HYDROGEN }).collect(),
+            dynamics: None,
+        };
+        let mut buf = vec![];
+        poscar.to_writer(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    #[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
+    fn streaming_benchmark_100k_atoms() {
+        // (use `--nocapture` to see the timings)
+        let text = large_poscar_text(100_000);
+
+        let time = |name: &str, load: &dyn Fn() -> Poscar| {
+            let start = std::time::Instant::now();
+            let out = load();
+            println!("{:>14}: {:?}", name, start.elapsed());
+            out
+        };
+        let streaming = time("load_streaming", &|| Poscar::load_streaming(&text[..]).unwrap());
+        let buffered = time("from_buf_reader", &|| Poscar::from_buf_reader(&text[..]).unwrap());
+
+        assert_eq!(streaming.coords.num_atoms(), 100_000);
+        assert_eq!(streaming, buffered);
+    }
+
+    #[test]
+    fn streaming_old_style() {
+        // symbols in the comment, volume for the scale, and velocities at the end
+        let text = "\
+C H
+  -8.0
+     1.0  0.0  0.0
+     0.0  1.0  0.0
+     0.0  0.0  1.0
+   1   1
+Cartesian
+  0.5  0.0  0.0
+  0.0  0.0  0.25
+
+  1.0  1.0  1.0
+  1.0  1.0  1.0
+";
+        let poscar = Poscar::load_streaming(text.as_bytes()).unwrap();
+        assert_eq!(poscar.coords.lattice(), &Lattice::cubic(2.0));
+        assert_eq!(poscar.coords.to_carts(), vec![[1.0, 0.0, 0.0], [0.0, 0.0, 0.5]].envee());
+        assert_eq!(poscar.elements, vec![CARBON, HYDROGEN]);
+        assert_eq!(poscar.dynamics, None);
+    }
+
+    #[test]
+    fn streaming_truncated() {
+        let poscar = example(None);
+        let mut buf = vec![];
+        poscar.to_writer(&mut buf).unwrap();
+        buf.truncate(buf.len() - 2);
+        while buf.last() != Some(&b'\n') {
+            buf.pop();
+        }
+        assert!(Poscar::load_streaming(&buf[..]).is_err());
+    }
+
+    #[test]
+    fn selective_dynamics_wrong_length() {
+        let poscar = example(Some(vec![[true, true, false]]));
+        assert!(poscar.to_writer(&mut vec![]).is_err());
+    }
 }
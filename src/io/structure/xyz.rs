@@ -11,9 +11,9 @@
 
 use crate::FailResult;
 use std::io::prelude::*;
-use std::io::{Lines};
+use std::io::{self, Lines};
 
-use rsp2_structure::{Element};
+use rsp2_structure::{Element, Coords, Lattice};
 
 use rsp2_array_types::V3;
 
@@ -48,6 +48,13 @@ where
 }
 
 impl Xyz {
+    /// Read a single-frame XYZ file.
+    ///
+    /// This forcibly reads to EOF because it must construct a BufReader.
+    pub fn from_reader(r: impl Read) -> FailResult<Self> {
+        Self::from_buf_reader(io::BufReader::new(r))
+    }
+
     /// Read a single-frame XYZ file.
     pub fn from_buf_reader(r: impl BufRead) -> FailResult<Self> {
         let mut frames = Self::anim_from_buf_reader(r)?;
@@ -65,9 +72,134 @@ impl Xyz {
     }
 
     /// Read a multiple-frame XYZ file.
-    pub fn anim_from_buf_reader(mut r: impl BufRead) -> FailResult<Vec<Self>> {
-        let r: &mut dyn BufRead = &mut r;
-        load(r.lines())
+    pub fn anim_from_buf_reader(r: impl BufRead) -> FailResult<Vec<Self>> {
+        Self::frames(r).collect()
+    }
+
+    /// Lazily read the frames of an XYZ file, one at a time.
+    ///
+    /// The iterator stops after producing the first error.
+    pub fn frames<R: BufRead>(r: R) -> Frames<R> {
+        Frames { lines: r.lines(), line_number: 0, done: false }
+    }
+}
+
+/// Builder for a frame in the extended XYZ format, which can additionally
+/// record the lattice and arbitrary per-atom properties such as forces.
+///
+/// The comment line is written as `key=value` pairs, e.g.
+/// `Lattice="..." Properties=species:S:1:pos:R:3:forces:R:3`, which is understood
+/// by tools such as OVITO and ASE.  Like plain XYZ frames, these can be concatenated
+/// into an animation.
+#[derive(Debug, Clone)]
+pub struct ExtendedXyz {
+    title: Option<String>,
+    lattice: Option<Lattice>,
+    carts: Vec<V3>,
+    elements: Vec<Element>,
+    properties: Vec<(String, Property)>,
+}
+
+/// Data for a per-atom property of an `ExtendedXyz`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Property {
+    Real(Vec<f64>),
+    Integer(Vec<i64>),
+    Vector(Vec<V3>),
+}
+
+impl ExtendedXyz {
+    pub fn new(carts: &[V3], elements: &[Element]) -> Self {
+        assert_eq!(carts.len(), elements.len());
+        ExtendedXyz {
+            title: None,
+            lattice: None,
+            carts: carts.to_vec(),
+            elements: elements.to_vec(),
+            properties: vec![],
+        }
+    }
+
+    /// Constructs a frame with the lattice of `coords`.
+    pub fn from_coords(coords: &Coords, elements: &[Element]) -> Self {
+        let mut out = Self::new(&coords.to_carts(), elements);
+        out.lattice(coords.lattice());
+        out
+    }
+
+    /// Free-form text, written to the comment line as `comment="..."`.
+    pub fn title(&mut self, title: &str) -> &mut Self
+    { self.title = Some(title.to_string()); self }
+
+    pub fn lattice(&mut self, lattice: &Lattice) -> &mut Self
+    { self.lattice = Some(lattice.clone()); self }
+
+    /// Shorthand for a vector property named `forces`.
+    pub fn forces(&mut self, forces: &[V3]) -> &mut Self
+    { self.property("forces", Property::Vector(forces.to_vec())) }
+
+    /// Add a column for a per-atom property.  Columns are written in the order they are added.
+    pub fn property(&mut self, name: &str, data: Property) -> &mut Self
+    { self.properties.push((name.to_string(), data)); self }
+
+    pub fn to_writer(&self, mut w: impl Write) -> FailResult<()> {
+        dump_extended(&mut w, self)
+    }
+}
+
+impl Property {
+    fn len(&self) -> usize {
+        match self {
+            Property::Real(data) => data.len(),
+            Property::Integer(data) => data.len(),
+            Property::Vector(data) => data.len(),
+        }
+    }
+
+    // the type code and column count in the "Properties" key
+    fn descriptor(&self) -> &'static str {
+        match self {
+            Property::Real(_) => "R:1",
+            Property::Integer(_) => "I:1",
+            Property::Vector(_) => "R:3",
+        }
+    }
+
+    fn write_columns(&self, w: &mut dyn Write, atom: usize) -> FailResult<()> {
+        match self {
+            Property::Real(data) => write!(w, " {}", data[atom])?,
+            Property::Integer(data) => write!(w, " {}", data[atom])?,
+            Property::Vector(data) => {
+                let V3([x, y, z]) = data[atom];
+                write!(w, " {} {} {}", x, y, z)?
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the frames of an XYZ file.  Created by `Xyz::frames`.
+pub struct Frames<R> {
+    lines: Lines<R>,
+    line_number: usize,
+    done: bool,
+}
+
+impl<R: BufRead> Iterator for Frames<R> {
+    type Item = FailResult<Xyz>;
+
+    fn next(&mut self) -> Option<FailResult<Xyz>> {
+        if self.done {
+            return None;
+        }
+        match load_frame_or_eof(&mut LineReader {
+            lines: &mut self.lines,
+            line_number: &mut self.line_number,
+        }) {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => { self.done = true; None },
+            Err(e) => { self.done = true; Some(Err(e)) },
+        }
     }
 }
 
@@ -89,38 +221,121 @@ fn dump(w: &mut dyn Write, title: &str, carts: &[V3], types: &[Element]) -> Fail
     Ok(())
 }
 
-fn load(mut r: Lines<&mut dyn BufRead>) -> FailResult<Vec<Xyz>> {
-    let mut out = vec![];
-    while let Some(frame) = load_frame_or_eof(&mut r)? {
-        out.push(frame);
+fn dump_extended(w: &mut dyn Write, xyz: &ExtendedXyz) -> FailResult<()>
+{
+    let ExtendedXyz { title, lattice, carts, elements, properties } = xyz;
+
+    for (i, (name, data)) in properties.iter().enumerate() {
+        let bad_char = |c: char| c.is_whitespace() || c == ':' || c == '=' || c == '"';
+        if name.is_empty() || name.contains(bad_char) {
+            bail!("invalid name for extended XYZ property: {:?}", name);
+        }
+        if name == "species" || name == "pos" || properties[..i].iter().any(|(other, _)| other == name) {
+            bail!("extended XYZ property {:?} appears twice", name);
+        }
+        if data.len() != carts.len() {
+            bail!(
+                "extended XYZ property {:?} has {} values, but there are {} atoms",
+                name, data.len(), carts.len(),
+            );
+        }
+    }
+
+    let mut header = vec![];
+    if let Some(lattice) = lattice {
+        let numbers = lattice.vectors().iter().flat_map(|v| v.0.iter()).map(|x| x.to_string());
+        header.push(format!("Lattice=\"{}\"", numbers.collect::<Vec<_>>().join(" ")));
+        header.push("pbc=\"T T T\"".to_string());
+    }
+    header.push({
+        let mut props = String::from("Properties=species:S:1:pos:R:3");
+        for (name, data) in properties {
+            props += &format!(":{}:{}", name, data.descriptor());
+        }
+        props
+    });
+    if let Some(title) = title {
+        if title.contains('\n') || title.contains('\r') {
+            bail!("XYZ title cannot contain newline.");
+        }
+        let escaped = title.replace('\\', "\\\\").replace('"', "\\\"");
+        header.push(format!("comment=\"{}\"", escaped));
+    }
+
+    writeln!(w, "{}", carts.len())?;
+    writeln!(w, "{}", header.join(" "))?;
+    for (atom, (V3([x, y, z]), typ)) in carts.iter().zip(elements).enumerate() {
+        write!(w, " {:>2} {} {} {}", typ.symbol(), x, y, z)?;
+        for (_, data) in properties {
+            data.write_columns(w, atom)?;
+        }
+        writeln!(w)?;
     }
-    Ok(out)
+
+    Ok(())
+}
+
+// Keeps track of line numbers for error messages.
+struct LineReader<'a> {
+    lines: &'a mut dyn Iterator<Item=io::Result<String>>,
+    line_number: &'a mut usize,
 }
 
-fn load_frame_or_eof(r: &mut Lines<&mut dyn BufRead>) -> FailResult<Option<Xyz>>
+impl<'a> LineReader<'a> {
+    fn next(&mut self) -> FailResult<Option<String>> {
+        match self.lines.next() {
+            None => Ok(None),
+            Some(line) => {
+                *self.line_number += 1;
+                Ok(Some(line?))
+            },
+        }
+    }
+}
+
+fn load_frame_or_eof(r: &mut LineReader<'_>) -> FailResult<Option<Xyz>>
 {
-    let count = match r.next() {
-        None => return Ok(None), // eof
-        Some(line) => line?.trim().parse::<usize>()?,
+    // Tolerate blank lines between frames and at EOF.
+    let count_line = loop {
+        match r.next()? {
+            None => return Ok(None), // eof
+            Some(line) => match line.trim() {
+                "" => continue,
+                _ => break line,
+            },
+        }
     };
-    let title = r.next().ok_or_else(|| format_err!("unexpected EOF!"))??;
+    let count = count_line.trim().parse::<usize>().map_err(|_| {
+        format_err!(
+            "line {}: expected an atom count at the beginning of an XYZ frame, found {:?} \
+            (does the atom count of the previous frame match its number of rows?)",
+            *r.line_number, count_line,
+        )
+    })?;
+    let title = r.next()?.ok_or_else(|| format_err!("unexpected EOF! (expected title line)"))?;
 
     let mut elements = Vec::with_capacity(count);
     let mut carts = Vec::with_capacity(count);
     for read_so_far in 0..count {
-        let line = r.next().ok_or_else(|| {
+        let line = r.next()?.ok_or_else(|| {
             format_err!("unexpected EOF! (expected {} atoms, found {})", count, read_so_far)
-        })??;
+        })?;
+        let line_number = *r.line_number;
 
         let mut words = line.split_whitespace();
-        elements.push(Element::from_symbol(words.next().ok_or_else(|| {
-            format_err!("unexpected empty line when reading atom from XYZ file")
-        })?)?);
+        let symbol = words.next().ok_or_else(|| {
+            format_err!(
+                "line {}: unexpected empty line when reading atom from XYZ file \
+                (expected {} atoms, found {})",
+                line_number, count, read_so_far,
+            )
+        })?;
+        elements.push(Element::from_symbol(symbol)?);
 
         carts.push({
             V3::try_from_fn(|_| Ok::<_, failure::Error>({
                 words.next()
-                    .ok_or_else(|| format_err!("expected 3 coordinates after species name"))?
+                    .ok_or_else(|| format_err!("line {}: expected 3 coordinates after species name", line_number))?
                     .parse()?
             }))?
         });
@@ -135,3 +350,115 @@ fn load_frame_or_eof(r: &mut Lines<&mut dyn BufRead>) -> FailResult<Option<Xyz>>
 
     Ok(Some(Xyz { title, carts, elements }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_FRAMES: &str = "\
+2
+first frame
+C 0.0 0.0 0.0
+H 1.0 0.0 0.0 \t
+1
+  second frame
+ N  0.0 0.5 1.5
+
+";
+
+    #[test]
+    fn frames() {
+        let frames = Xyz::frames(TWO_FRAMES.as_bytes()).collect::<FailResult<Vec<_>>>().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].title, "first frame");
+        assert_eq!(frames[0].elements, vec![Element::CARBON, Element::HYDROGEN]);
+        assert_eq!(frames[0].carts, vec![V3([0.0, 0.0, 0.0]), V3([1.0, 0.0, 0.0])]);
+        assert_eq!(frames[1].title, "  second frame");
+        assert_eq!(frames[1].elements, vec![Element::NITROGEN]);
+        assert_eq!(frames[1].carts, vec![V3([0.0, 0.5, 1.5])]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let xyz = Xyz {
+            title: "hello".to_string(),
+            carts: vec![V3([0.25, 1.0, -3.0]), V3([1e-3, 2.0, 4.0])],
+            elements: vec![Element::CARBON, Element::HYDROGEN],
+        };
+        let mut buf = vec![];
+        xyz.to_writer(&mut buf).unwrap();
+        xyz.to_writer(&mut buf).unwrap();
+
+        assert_eq!(Xyz::from_reader(&buf[..]).unwrap(), xyz);
+        assert_eq!(Xyz::anim_from_buf_reader(&buf[..]).unwrap(), vec![xyz.clone(), xyz]);
+    }
+
+    #[test]
+    fn extended() {
+        let lattice = Lattice::from([[2.0, 0.0, 0.0], [-1.0, 1.5, 0.0], [0.0, 0.0, 10.0]]);
+        let carts = vec![V3([0.0, 0.0, 0.0]), V3([1.0, 0.5, 0.25])];
+        let elements = vec![Element::CARBON, Element::HYDROGEN];
+
+        let mut buf = vec![];
+        ExtendedXyz::new(&carts, &elements)
+            .lattice(&lattice)
+            .title("say \"hi\"")
+            .forces(&[V3([0.5, -1.0, 0.0]), V3([-0.5, 1.0, 0.0])])
+            .property("layer", Property::Integer(vec![0, 1]))
+            .property("energy", Property::Real(vec![-7.5, -2.25]))
+            .to_writer(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+
+        assert_eq!(text, "\
+2
+Lattice=\"2 0 0 -1 1.5 0 0 0 10\" pbc=\"T T T\" \
+Properties=species:S:1:pos:R:3:forces:R:3:layer:I:1:energy:R:1 comment=\"say \\\"hi\\\"\"
+  C 0 0 0 0.5 -1 0 0 -7.5
+  H 1 0.5 0.25 -0.5 1 0 1 -2.25
+");
+
+        // readable as a plain XYZ file, ignoring the extra columns
+        let plain = Xyz::from_reader(text.as_bytes()).unwrap();
+        assert_eq!(plain.carts, carts);
+        assert_eq!(plain.elements, elements);
+    }
+
+    #[test]
+    fn extended_bad_property() {
+        let carts = vec![V3([0.0; 3]); 2];
+        let elements = vec![Element::CARBON; 2];
+        let check_err = |builder: &ExtendedXyz| builder.to_writer(&mut vec![]).unwrap_err();
+
+        check_err(ExtendedXyz::new(&carts, &elements).property("q", Property::Real(vec![1.0])));
+        check_err(ExtendedXyz::new(&carts, &elements).property("has space", Property::Real(vec![1.0; 2])));
+        check_err(ExtendedXyz::new(&carts, &elements).property("pos", Property::Real(vec![1.0; 2])));
+        check_err({
+            ExtendedXyz::new(&carts, &elements)
+                .property("q", Property::Real(vec![1.0; 2]))
+                .property("q", Property::Integer(vec![1; 2]))
+        });
+    }
+
+    #[test]
+    fn count_mismatch() {
+        // too few rows
+        let text = "3\ntitle\nC 0 0 0\nC 0 0 1\n";
+        let err = Xyz::from_reader(text.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("expected 3 atoms, found 2"), "{}", err);
+
+        // too many rows
+        let text = "1\ntitle\nC 0 0 0\nC 0 0 1\n";
+        let err = Xyz::from_reader(text.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("line 4"), "{}", err);
+
+        // a blank line in place of an atom
+        let text = "2\ntitle\nC 0 0 0\n\nC 0 0 1\n";
+        let err = Xyz::from_reader(text.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("expected 2 atoms, found 1"), "{}", err);
+
+        // the iterator stops after an error
+        let mut frames = Xyz::frames("1\ntitle\n".as_bytes());
+        assert!(frames.next().unwrap().is_err());
+        assert!(frames.next().is_none());
+    }
+}
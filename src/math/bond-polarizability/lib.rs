@@ -17,7 +17,10 @@
 #[macro_use] extern crate rsp2_util_macros;
 #[macro_use] extern crate log;
 
-use enum_map::{EnumMap, enum_map};
+use std::collections::HashMap;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
 use rsp2_array_types::{dot, V3, M33};
 use rsp2_structure::Element;
 use rsp2_structure::bonds::{CartBond, CartBonds};
@@ -33,6 +36,8 @@ where
 {
     /// Kelvin.
     pub temperature: f64,
+    /// Whether to compute Stokes or anti-Stokes intensities.
+    pub branch: StokesBranch,
     /// Normal mode frequencies, in cm^-1.
     pub ev_frequencies: &'a [f64],
     /// Normal mode eigenvectors, normalized.
@@ -51,24 +56,33 @@ where
     pub fn compute_ev_raman_tensors(self) -> Result<Vec<RamanTensor>, BondPolError> {
         let Input {
             ev_frequencies, ev_eigenvectors,
-            temperature, site_elements, site_masses, bonds,
+            temperature, branch, site_elements, site_masses, bonds,
         } = self;
         let mut ev_eigenvectors = ev_eigenvectors.into_iter();
 
         let pol_constants = default_CH_pol_constants();
 
+        // Resolve the constants for each bond up front, rather than hashing
+        // the element pair once per bond per mode.
+        let bond_pol_constants = bonds.into_iter().map(|CartBond { from, to, .. }| {
+            let bond_type = BondType::from_elements(site_elements[from], site_elements[to]);
+            match pol_constants.get(&bond_type) {
+                Some(pc) => Ok(pc.as_ref()),
+                None => Err(BondPolError::UnsupportedBond(site_elements[from], site_elements[to])),
+            }
+        }).collect::<Result<Vec<_>, BondPolError>>()?;
+
         let out = ev_frequencies.into_iter().zip(ev_eigenvectors.by_ref())
             .map(|(&frequency, eigs)| {
-                let prefactor = raman_prefactor(frequency, temperature);
+                let prefactor = raman_prefactor(branch, frequency, temperature);
                 let tensor = raman_tensor(
                     eigs,
                     site_masses,
                     bonds,
-                    site_elements,
-                    &pol_constants,
-                )?;
-                Ok(RamanTensor { prefactor, tensor })
-            }).collect::<Result<Vec<_>, BondPolError>>()?;
+                    &bond_pol_constants,
+                );
+                RamanTensor { prefactor, tensor }
+            }).collect::<Vec<_>>();
 
         assert!(ev_eigenvectors.next().is_none(), "more eigenvectors than frequencies!");
         assert_eq!(out.len(), ev_frequencies.len(), "more frequencies than eigenvectors!");
@@ -87,9 +101,23 @@ pub struct PolConstant {
     pub max_len: f64,
 }
 
+/// Selects which side of the Rayleigh line to compute intensities for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StokesBranch {
+    /// Scattered light has lost energy to a phonon. (occupation factor `n + 1`)
+    Stokes,
+    /// Scattered light has gained energy from a phonon. (occupation factor `n`)
+    AntiStokes,
+}
+
+impl Default for StokesBranch {
+    fn default() -> Self { StokesBranch::Stokes }
+}
+
 // NOTE: there are also constant factors out front based on input light frequency
 //       and stuff, so this only gives proportional intensities
 fn raman_prefactor(
+    branch: StokesBranch,
     mode_frequency: f64,
     temperature: f64,
 ) -> f64 {
@@ -102,14 +130,24 @@ fn raman_prefactor(
         // but acoustic modes are obviously not raman active.
         0.0
     } else {
-        let bose_occupation = 1.0 + 1.0 / expm1;
-        bose_occupation / mode_frequency
+        // (computed directly rather than subtracting 1 from the stokes factor,
+        //  which would lose all precision when the mode is barely populated)
+        let bose_einstein = 1.0 / expm1;
+        let occupation_factor = match branch {
+            StokesBranch::Stokes => 1.0 + bose_einstein,
+            // (this is negative for imaginary modes, where it has no meaning)
+            StokesBranch::AntiStokes => f64::max(0.0, bose_einstein),
+        };
+        occupation_factor / mode_frequency
     }
 }
 
-#[derive(enum_map::Enum)]
+/// An unordered pair of elements.
+///
+/// The elements are always stored in sorted order, so that e.g. C-H and H-C
+/// bonds produce the same key.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum BondType { CC, CH, HH }
+pub struct BondType(Element, Element);
 
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
@@ -119,43 +157,52 @@ pub enum BondPolError {
 }
 
 impl BondType {
-    fn from_elements(a: Element, b: Element) -> Result<BondType, BondPolError> {
-        Ok(match (a, b) {
-            (Element::CARBON, Element::CARBON) => BondType::CC,
-            (Element::CARBON, Element::HYDROGEN) => BondType::CH,
-            (Element::HYDROGEN, Element::CARBON) => BondType::CH,
-            (Element::HYDROGEN, Element::HYDROGEN) => BondType::HH,
-            _ => return Err(BondPolError::UnsupportedBond(a, b)),
-        })
+    pub fn from_elements(a: Element, b: Element) -> BondType {
+        match a <= b {
+            true => BondType(a, b),
+            false => BondType(b, a),
+        }
     }
+
+    pub fn elements(&self) -> (Element, Element) { (self.0, self.1) }
 }
 
-pub type PolConstants = EnumMap<BondType, Option<PolConstant>>;
+/// Polarization constants for each bond type.
+///
+/// A value of `None` means that bonds of that type are knowingly ignored.
+/// Bond types that are missing from the map entirely produce
+/// `BondPolError::UnsupportedBond`.
+pub type PolConstants = HashMap<BondType, Option<PolConstant>>;
 #[allow(bad_style)]
 pub fn default_CH_pol_constants() -> PolConstants {
-    enum_map!{
-        BondType::CC => Some(PolConstant {
-            c1: 0.32, c2: 2.60, c3: 7.55,
-            max_len: 1.6,
-        }),
-        BondType::CH => Some(PolConstant {
-            c1: 0.32, c2: 2.60, c3: 7.55,
-            max_len: 1.3,
-        }),
-        _ => None,
-    }
+    use Element as E;
+
+    let mut map = HashMap::new();
+    map.insert(BondType::from_elements(E::CARBON, E::CARBON), Some(PolConstant {
+        c1: 0.32, c2: 2.60, c3: 7.55,
+        max_len: 1.6,
+    }));
+    map.insert(BondType::from_elements(E::CARBON, E::HYDROGEN), Some(PolConstant {
+        c1: 0.32, c2: 2.60, c3: 7.55,
+        max_len: 1.3,
+    }));
+    map.insert(BondType::from_elements(E::HYDROGEN, E::HYDROGEN), None);
+    map
 }
 
 #[allow(bad_style)]
 #[allow(unused)] // FIXME
 pub fn nanotube_CC_pol_constants() -> PolConstants {
-    enum_map!{
-        BondType::CC => Some(PolConstant {
-            c1: 0.04, c2: 4.0, c3: 4.7,
-            max_len: 1.6,
-        }),
-        _ => None,
-    }
+    use Element as E;
+
+    let mut map = HashMap::new();
+    map.insert(BondType::from_elements(E::CARBON, E::CARBON), Some(PolConstant {
+        c1: 0.04, c2: 4.0, c3: 4.7,
+        max_len: 1.6,
+    }));
+    map.insert(BondType::from_elements(E::CARBON, E::HYDROGEN), None);
+    map.insert(BondType::from_elements(E::HYDROGEN, E::HYDROGEN), None);
+    map
 }
 
 pub struct RamanTensor {
@@ -239,20 +286,57 @@ impl RamanTensor {
             },
             LightPolarization::Average => sq_sum_submatrix(0..3) / 9.0,
             LightPolarization::BackscatterZ => sq_sum_submatrix(0..2) / 4.0,
+            LightPolarization::MonteCarloAverage { samples, seed } => {
+                monte_carlo_average(tensor, 3, *samples, *seed)
+            },
+            LightPolarization::MonteCarloBackscatterZ { samples, seed } => {
+                monte_carlo_average(tensor, 2, *samples, *seed)
+            },
         };
 
         prefactor * value
     }
 }
 
+/// Numerically average `(u^T T v)^2` over independent random unit vectors `u` and `v`
+/// which lie in the span of the first `ndim` cartesian axes.
+///
+/// This is far slower and less accurate than the closed forms in `integrate_intensity`,
+/// and only exists to validate them.
+fn monte_carlo_average(tensor: &M33, ndim: usize, samples: usize, seed: u64) -> f64 {
+    assert!(samples > 0, "no samples");
+
+    // (XorShiftRng requires a seed that is not all zeros)
+    let mut rng = XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, 0x9e37_79b9, 0x7f4a_7c15]);
+    let mut random_unit_vector = || {
+        // a vector of gaussian components has a uniformly random direction
+        use rand::distributions::normal::StandardNormal;
+        let v = V3::from_fn(|k| match k < ndim {
+            true => rng.gen::<StandardNormal>().0,
+            false => 0.0,
+        });
+        v / v.norm()
+    };
+
+    let mut sum = 0.0;
+    for _ in 0..samples {
+        let incident = random_unit_vector();
+        let scattered = random_unit_vector();
+        let value = dot(&incident, &(tensor * &scattered));
+        sum += value * value;
+    }
+    sum / samples as f64
+}
+
 /// NOTE: Matrix is column-based.
+///
+/// `bond_pol_constants` holds the (already looked up) constants for each bond.
 fn raman_tensor(
     eigenvector: &[V3],
     masses: &[Mass],
     bonds: &CartBonds,
-    types: &[Element],
-    pol_constants: &PolConstants,
-) -> Result<M33, BondPolError> {
+    bond_pol_constants: &[Option<&PolConstant>],
+) -> M33 {
     // kronecker delta value
     let kdelta = <M33>::eye();
 
@@ -260,9 +344,7 @@ fn raman_tensor(
     let mut ignored_by_type = 0;
     let mut ignored_by_distance = 0;
     let mut ignored_distance = 0.0_f64;
-    for CartBond { from, to, cart_vector: bond_vector } in bonds {
-        let bond_type = BondType::from_elements(types[from], types[to])?;
-
+    for (CartBond { from, to: _, cart_vector: bond_vector }, &pc) in zip_eq!(bonds, bond_pol_constants) {
         // phonon eigenvector for this atom, need to mass normalize
         let eig: V3 = eigenvector[from] / f64::sqrt(masses[from]);
 
@@ -270,7 +352,7 @@ fn raman_tensor(
         let distance: f64 = bond_vector.norm();
         let rhat: V3 = bond_vector / distance;
 
-        let pc = match &pol_constants[bond_type] {
+        let pc = match pc {
             Some(pc) => pc,
             // ignore bonds which have no corresponding polarization constants
             // FIXME: This seems confusing together with BondPolError::UnsupportedBond.
@@ -317,12 +399,11 @@ fn raman_tensor(
         );
     }
 
-    Ok(tensor)
+    tensor
 }
 
 pub enum LightPolarization {
     // previously:  avg = false, backscatter = (ignored)
-    #[allow(unused)]
     Polarized {
         incident: V3,
         scattered: V3,
@@ -331,4 +412,75 @@ pub enum LightPolarization {
     Average,
     // previously:  avg = true, backscatter = true,
     BackscatterZ,
+    /// Like `Average`, but computed by sampling random polarization vectors.
+    /// Only meant for checking the analytic average.
+    MonteCarloAverage {
+        samples: usize,
+        seed: u64,
+    },
+    /// Like `BackscatterZ`, but computed by sampling random polarization vectors.
+    /// Only meant for checking the analytic average.
+    MonteCarloBackscatterZ {
+        samples: usize,
+        seed: u64,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anti_stokes_ratio() {
+        // (hbar / k_b) in [K] per [cm-1]
+        let hk = 0.22898852319;
+
+        for &temperature in &[10.0, 300.0, 2000.0] {
+            for &frequency in &[50.0, 400.0, 1580.0] {
+                let stokes = raman_prefactor(StokesBranch::Stokes, frequency, temperature);
+                let anti_stokes = raman_prefactor(StokesBranch::AntiStokes, frequency, temperature);
+                let expected = f64::exp(-hk * frequency / temperature);
+                assert!(
+                    (anti_stokes / stokes - expected).abs() <= 1e-10 * expected,
+                    "T = {}, freq = {}: {} vs {}", temperature, frequency, anti_stokes / stokes, expected,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn anti_stokes_nonnegative() {
+        // no thermal population at zero temperature
+        assert_eq!(raman_prefactor(StokesBranch::AntiStokes, 400.0, 0.0), 0.0);
+        // imaginary modes
+        assert_eq!(raman_prefactor(StokesBranch::AntiStokes, -400.0, 300.0), 0.0);
+        assert_eq!(raman_prefactor(StokesBranch::AntiStokes, -400.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn monte_carlo_polarization_average() {
+        let raman = RamanTensor {
+            prefactor: 1.0,
+            tensor: rsp2_array_types::mat::from_array([
+                [1.0, 2.0, -3.0],
+                [0.5, -1.0, 0.0],
+                [4.0, 0.0, 2.0],
+            ]),
+        };
+
+        // The standard error is well under 1%, so this leaves plenty of leeway.
+        let samples = 100_000;
+        let rel_tol = 0.04;
+        for (analytic, sampled) in &[
+            (LightPolarization::Average, LightPolarization::MonteCarloAverage { samples, seed: 1 }),
+            (LightPolarization::BackscatterZ, LightPolarization::MonteCarloBackscatterZ { samples, seed: 2 }),
+        ] {
+            let expected = raman.integrate_intensity(analytic);
+            let actual = raman.integrate_intensity(sampled);
+            assert!(
+                (actual - expected).abs() <= rel_tol * expected,
+                "{} vs {}", actual, expected,
+            );
+        }
+    }
 }
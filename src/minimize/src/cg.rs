@@ -75,6 +75,46 @@ pub mod settings {
         Fail,
     }
 
+    /// How the initial guess for each linesearch is derived from the previous iteration.
+    ///
+    /// On the first iteration, and after a failed linesearch, every strategy simply uses
+    /// the previous step size (or `alpha_guess_first`).  The result is always clipped to
+    /// `alpha_guess_max`.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum AlphaGuess {
+        /// The step size taken by the previous linesearch.
+        ///
+        /// Very stable, since the direction is normalized and the curvature usually changes
+        /// slowly, but the guess can only grow as fast as the linesearch itself lets it.
+        /// This is the default.
+        Previous,
+        /// A fixed multiple of the previous step size.
+        ///
+        /// A factor above 1 lets the guess grow quickly in stiff systems where the natural
+        /// step size is large, at the cost of extra evaluations whenever the guess overshoots.
+        Scaled(f64),
+        /// Quadratic interpolation of the previous decrease in value.
+        /// (`2 * (f_k - f_{k-1}) / slope`, eq. 3.60 of Nocedal & Wright)
+        ///
+        /// This is exact on a quadratic when each iteration decreases the value by the same
+        /// amount, and adapts immediately to changes in the slope.  It relies on the value,
+        /// so it is unreliable when rounding errors in the value rival the decrease; in that
+        /// case (or when the estimate is not positive) it falls back to the previous step size.
+        Quadratic,
+    }
+
+    impl Default for AlphaGuess {
+        fn default() -> Self { AlphaGuess::Previous }
+    }
+
+    impl AlphaGuess {
+        pub fn validate(&self) {
+            if let AlphaGuess::Scaled(factor) = *self {
+                assert!(0.0 < factor);
+            }
+        }
+    }
+
     impl Beta {
         pub fn validate(&self) {
             match *self {
@@ -409,9 +449,25 @@ fn calc_beta_acgsd(gradient: &[f64], delta_x: &[f64], delta_g: &[f64]) -> f64 {
 use crate::strong_ls::LinesearchError;
 #[derive(Debug, Fail)]
 pub enum AcgsdError {
+    /// The linesearch failed twice in a row, the second time along steepest descent.
+    ///
+    /// Only produced under `OnLsFailure::Fail`.  `Failure::best_position` is always
+    /// available for this error.
+    #[fail(display = "ACGSD Failed: linesearch failure (second)")]
+    LineSearchFailed,
+
+    /// The ACGSD linesearch rejected its input.
     #[fail(display = "Linesearch failed: {}", _0)]
     Linesearch(#[fail(cause)] LinesearchError),
 
+    /// The function produced a value that is infinite or NaN.
+    #[fail(display = "ACGSD Failed: non-finite value: {}", value)]
+    NonFiniteValue { value: f64 },
+
+    /// The function produced a gradient with an infinite or NaN component.
+    #[fail(display = "ACGSD Failed: non-finite gradient (component {} is {})", index, component)]
+    NonFiniteGradient { index: usize, component: f64 },
+
     #[doc(hidden)]
     #[fail(display = "impossible!")]
     _Hidden,
@@ -479,6 +535,7 @@ pub struct Builder {
     on_ls_failure: settings::OnLsFailure,
     alpha_guess_first: f64,
     alpha_guess_max: f64,
+    alpha_guess: settings::AlphaGuess,
     build_output_fns: Vec<Box<dyn BuildAlgorithmStateFn<Output=()>>>,
 }
 
@@ -491,6 +548,7 @@ impl Builder {
             on_ls_failure: settings::OnLsFailure::Fail,
             alpha_guess_first: 1.0,
             alpha_guess_max: std::f64::INFINITY,
+            alpha_guess: Default::default(),
             build_output_fns: vec![],
         }
     }
@@ -542,6 +600,11 @@ impl Builder {
         self.alpha_guess_max = value; self
     }
 
+    /// Set how the initial linesearch guess is updated between iterations.
+    pub fn alpha_guess(&mut self, value: settings::AlphaGuess) -> &mut Self {
+        self.alpha_guess = value; self
+    }
+
     /// Set up an arbitrary function for logging output each iteration.
     ///
     /// This will exist alongside any previously existing output functions.
@@ -573,6 +636,7 @@ impl Clone for Builder {
             on_ls_failure: self.on_ls_failure.clone(),
             alpha_guess_first: self.alpha_guess_first.clone(),
             alpha_guess_max: self.alpha_guess_max.clone(),
+            alpha_guess: self.alpha_guess.clone(),
             build_stop_condition: self.build_stop_condition.as_ref().map(|x| objekt::clone_box(&**x)),
         }
     }
@@ -878,6 +942,7 @@ fn cg<F: DiffFn>(
     };
     let beta_settings = builder.beta.clone().expect("'beta' was not supplied to the CG Builder!");
     let ls_settings = builder.linesearch.clone().expect("'linesearch' was not supplied to the CG Builder!");
+    builder.alpha_guess.validate();
 
     let mut output_functions: Vec<_> = {
         builder.build_output_fns.iter().map(|x| x.build()).collect()
@@ -886,7 +951,13 @@ fn cg<F: DiffFn>(
     let compute_point = |diff_fn: &mut dyn DiffFn<Error=F::Error>, position: &[f64]| {
         let position = position.to_vec();
         let (value, gradient) = diff_fn.compute(&position).map_err(ComputeError)?;
-        Ok(Point {position, value, gradient})
+        if !value.is_finite() {
+            Err(AcgsdError::NonFiniteValue { value })?;
+        }
+        if let Some((index, &component)) = gradient.iter().enumerate().find(|(_, x)| !x.is_finite()) {
+            Err(AcgsdError::NonFiniteGradient { index, component })?;
+        }
+        Ok::<_, Failure<F::Error>>(Point {position, value, gradient})
     };
 
 // /////////////////////////////////////////////////////////////////////////////
@@ -932,19 +1003,6 @@ fn cg<F: DiffFn>(
             warn!(" Grad Norm: {}", vnorm(&point.gradient));
         };
 
-        // use as 'return fatal(...);'
-        // this will return or throw based on the 'except_on_fail' setting
-        let fatal = |msg: &str, alpha, point| {
-            let msg = format!("ACGSD Failed: {}", msg);
-            warning(&msg, alpha, point);
-            panic!("ACGSD Failed: {}", msg);
-            // FIXME
-            //
-            // if (settings.except_on_fail)
-            //     throw runtime_error(msg);
-            // return point.position;
-        };
-
         // use as 'return success(...);'
         // Constructs a successful return value.
         let success = |Point { position, value, gradient }| {
@@ -1069,7 +1127,7 @@ fn cg<F: DiffFn>(
             //         is currently a bit messy and sometimes asks for a point
             //         more than once.  These are really issues with the linesearch,
             //         and ought not to be the caller's concern)
-            let mut memoized: Box<dyn FnMut(f64) -> Result<(f64, f64), ComputeError<F::Error>>>
+            let mut memoized: Box<dyn FnMut(f64) -> Result<(f64, f64), Failure<F::Error>>>
                 = crate::util::cache::hash_memoize_result_by_key(
                     |&alpha| ordered_float::NotNan::new(alpha).unwrap(),
                     |alpha| {
@@ -1090,7 +1148,9 @@ fn cg<F: DiffFn>(
 
             // NOTE: Under our scheme where direction is normalized,
             //       the previous alpha itself is a suitable guess.
-            let guess_alpha = saved.alpha.min(builder.alpha_guess_max);
+            let slope = vdot(&saved.gradient, &direction);
+            let guess_alpha = initial_alpha_guess(&builder.alpha_guess, saved.alpha, last.as_ref(), slope);
+            let guess_alpha = guess_alpha.min(builder.alpha_guess_max);
 
             match &ls_settings {
                 settings::Linesearch::Acgsd(settings) => {
@@ -1125,7 +1185,11 @@ fn cg<F: DiffFn>(
                         return success(saved.to_point());
                     },
                     settings::OnLsFailure::Fail => {
-                        return fatal("linesearch failure (second)", saved.alpha, saved.to_point());
+                        warning("ACGSD Failed: linesearch failure (second)", saved.alpha, saved.to_point());
+                        return Err(Failure {
+                            best_position: Some(saved.position.clone()),
+                            error: Left(AcgsdError::LineSearchFailed),
+                        });
                     },
                 }
             } else {
@@ -1161,7 +1225,35 @@ fn cg<F: DiffFn>(
 
         value_history.push(next_point.value);
     }
-    panic!("too many iterations")
+    unreachable!("the iteration count is unbounded")
+}
+
+/// Initial guess for the upcoming linesearch, before clipping to `alpha_guess_max`.
+///
+/// `slope` is the directional derivative of the value at the current point along the
+/// (normalized) direction about to be searched.
+fn initial_alpha_guess(
+    strategy: &settings::AlphaGuess,
+    saved_alpha: f64,
+    last: Option<&internal_types::Last>,
+    slope: f64,
+) -> f64 {
+    let last = match last {
+        Some(last) if !last.ls_failed => last,
+        _ => return saved_alpha,
+    };
+
+    match *strategy {
+        settings::AlphaGuess::Previous => saved_alpha,
+        settings::AlphaGuess::Scaled(factor) => factor * saved_alpha,
+        settings::AlphaGuess::Quadratic => {
+            let guess = 2.0 * last.d_value / slope;
+            match guess.is_finite() && guess > 0.0 {
+                true => guess,
+                false => saved_alpha,
+            }
+        },
+    }
 }
 
 fn max_norm(v: &[f64]) -> f64 {
@@ -1207,6 +1299,84 @@ mod tests {
         }
     }
 
+    #[test]
+    fn alpha_guess_strategies() {
+        use super::settings::AlphaGuess;
+        use super::internal_types::Last;
+        use super::initial_alpha_guess;
+
+        // V(x) = (x - 3)^2, currently at x = 0, about to search along +x.
+        // The exact minimizer along the line is at alpha = 3.
+        let slope = -6.0;
+        // The previous iteration decreased the value by as much as this one will.
+        let last = Last {
+            direction: vec![1.0],
+            d_value: -9.0,
+            d_position: vec![0.5],
+            d_gradient: vec![1.0],
+            ls_failed: false,
+        };
+        let saved_alpha = 0.5;
+
+        let guess = |strategy: &AlphaGuess, last: Option<&Last>| {
+            initial_alpha_guess(strategy, saved_alpha, last, slope)
+        };
+        assert_eq!(guess(&AlphaGuess::Previous, Some(&last)), 0.5);
+        assert_eq!(guess(&AlphaGuess::Scaled(1.5), Some(&last)), 0.75);
+        assert_close!(guess(&AlphaGuess::Quadratic, Some(&last)), 3.0);
+
+        // the first iteration and failed linesearches always reuse the previous alpha
+        let failed = Last { ls_failed: true, d_value: 0.0, ..last.clone() };
+        for strategy in &[AlphaGuess::Previous, AlphaGuess::Scaled(1.5), AlphaGuess::Quadratic] {
+            assert_eq!(guess(strategy, None), saved_alpha);
+            assert_eq!(guess(strategy, Some(&failed)), saved_alpha);
+        }
+
+        // an uphill step gives a nonsensical quadratic estimate
+        let uphill = Last { d_value: 1.0, ..last.clone() };
+        assert_eq!(guess(&AlphaGuess::Quadratic, Some(&uphill)), saved_alpha);
+    }
+
+    #[test]
+    fn error_variants() {
+        use super::{AcgsdError, Builder, settings::OnLsFailure};
+        use either::Either::Left;
+
+        let stop_condition = from_json!({"grad-max": 1e-11});
+        let run = |builder: Builder, f: fn(&[f64]) -> NoFailResult| {
+            let mut builder = builder;
+            builder.stop_condition(super::StopCondition::to_function(&stop_condition));
+            builder.on_ls_failure(OnLsFailure::Fail);
+            builder.run(&[1.0, 2.0], f)
+        };
+
+        for builder in vec![Builder::new_acgsd(), Builder::new_hager()] {
+            let err = run(builder.clone(), |_| Ok((std::f64::NAN, vec![1.0, 1.0]))).unwrap_err();
+            match err.error {
+                Left(AcgsdError::NonFiniteValue { .. }) => {},
+                e => panic!("wrong error: {:?}", e),
+            }
+
+            let err = run(builder.clone(), |_| Ok((1.0, vec![1.0, std::f64::INFINITY]))).unwrap_err();
+            match err.error {
+                Left(AcgsdError::NonFiniteGradient { index: 1, .. }) => {},
+                e => panic!("wrong error: {:?}", e),
+            }
+
+            // The gradient points the wrong way, so every step is uphill.
+            let err = run(builder.clone(), |x| Ok((x[0] + x[1], vec![-1.0, -1.0]))).unwrap_err();
+            match err.error {
+                Left(AcgsdError::LineSearchFailed) => {},
+                e => panic!("wrong error: {:?}", e),
+            }
+            assert_eq!(err.best_position, Some(vec![1.0, 2.0]));
+            assert_eq!(
+                AcgsdError::LineSearchFailed.to_string(),
+                "ACGSD Failed: linesearch failure (second)",
+            );
+        }
+    }
+
     // A high-level "it works" test.
     #[test]
     fn simple_quadratic() {
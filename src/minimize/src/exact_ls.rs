@@ -170,6 +170,70 @@ fn bisect<E>(
 }
 
 
+/// Find three points that bracket a minimum of a function, for use with `Golden`.
+///
+/// Starting from `from` and `from + initial_step`, this takes geometrically increasing
+/// steps downhill until the value increases again.  The output `(a, b, c)` has `b` lying
+/// between `a` and `c` with a value no larger than either endpoint, so that
+/// `(a.alpha, c.alpha)` is an interval suitable for `Golden::run`.  (the alphas may be
+/// in decreasing order)
+///
+/// This only requires values, and does not assume anything about the scale of the
+/// function; the initial step merely needs to be small enough not to skip over the
+/// minimum.  If the function keeps decreasing until the step becomes infinite, then
+/// it has no minimum and an error is returned.
+pub fn bracket_minimum<E, F>(
+    from: f64,
+    initial_step: f64,
+    mut compute: F,
+) -> Result<Result<(ValueBound, ValueBound, ValueBound), E>, GoldenSearchError>
+where F: FnMut(f64) -> Result<Value, E>
+{
+    nest_err(|| {
+        // early wrapping:
+        //  - ValueBound for internal use
+        //  - Result<Value, Result<TheirError, OurError>> for easy short-circuiting
+        let mut compute = move |alpha: f64| {
+            if !alpha.is_finite() {
+                return Err(Err(ErrorKind::NoMinimum.into()));
+            }
+            let value = compute(alpha).map_err(Ok)?;
+            if !value.0.is_finite() {
+                return Err(Err(ErrorKind::FunctionOutput(value.0).into()));
+            }
+            trace!("BR-iter:  a: {:<23e}  v: {:<23e}", alpha, value.0);
+            Ok(ValueBound { alpha, value: value.0 })
+        };
+
+        let (mut a, mut b) = {
+            let a = compute(from)?;
+            let b = compute(from + initial_step)?;
+            match b.value > a.value {
+                false => (a, b),
+                true => {
+                    // Uphill; try the other side.
+                    let c = compute(from - initial_step)?;
+                    if c.value >= a.value {
+                        return Ok((c, a, b));
+                    }
+                    (a, c)
+                },
+            }
+        };
+
+        // `b` is now downhill of `a`. Keep going, doubling the step each time.
+        loop {
+            let c = compute(b.alpha + 2.0 * (b.alpha - a.alpha))?;
+            if c.value >= b.value {
+                trace!("BR-exit:  ({:e}, {:e}, {:e})", a.alpha, b.alpha, c.alpha);
+                return Ok((a, b, c));
+            }
+            a = b;
+            b = c;
+        }
+    })
+}
+
 pub mod golden {
     pub use self::stop_condition::Rpn as StopCondition;
 
@@ -261,10 +325,30 @@ impl Golden {
     pub fn run<E, F>(
         &self,
         interval: (f64, f64),
-        mut compute: F,
+        compute: F,
     // NOTE: cannot return a bound due to issue mentioned in body
+    //       (but see `run_with_bracket`)
     ) -> Result<Result<f64, E>, GoldenSearchError>
     where F: FnMut(f64) -> Result<Value, E>
+    {
+        self.run_with_bracket(interval, compute)
+            .map(|result| result.map(|(alpha, _bracket)| alpha))
+    }
+
+    /// `run`, but also returns the final bracketing interval `(a, d)`.
+    ///
+    /// The endpoints of the interval are points at which the function was actually
+    /// evaluated (unlike the bounds used internally, whose alphas can drift from their
+    /// values; see the body of this function). Its width serves as a conservative
+    /// estimate of the error in the location of the minimizer.
+    ///
+    /// The endpoints may be in either order.
+    pub fn run_with_bracket<E, F>(
+        &self,
+        interval: (f64, f64),
+        mut compute: F,
+    ) -> Result<Result<(f64, (f64, f64)), E>, GoldenSearchError>
+    where F: FnMut(f64) -> Result<Value, E>
     {
         nest_err(|| {
             // early wrapping:
@@ -297,6 +381,10 @@ impl Golden {
                 ((a, b, d), history)
             };
 
+            // The alphas at which each member of `state` was actually computed.
+            // These are kept synchronized with the values, unlike `state`.
+            let mut computed_alphas = (state.0.alpha, state.1.alpha, state.2.alpha);
+
             let mut iterations = 0;
             let stop_reason = loop {
                 // Golden search will usually stop long before this,
@@ -348,18 +436,22 @@ impl Golden {
                 let c = compute(c_alpha)?;
 
                 history.push(c);
-                state = match b.value < c.value {
-                    true => (c, b, a),
-                    false => (b, c, d),
+                let (computed_a, computed_b, computed_d) = computed_alphas;
+                let (new_state, new_computed_alphas) = match b.value < c.value {
+                    true => ((c, b, a), (c_alpha, computed_b, computed_a)),
+                    false => ((b, c, d), (computed_b, c_alpha, computed_d)),
                 };
+                state = new_state;
+                computed_alphas = new_computed_alphas;
 
                 iterations += 1;
             }; // let stop_reason = { ... }
 
             //history.sort_on_key(|bound| NotNan::new(bound.alpha).unwrap());
             let (_a, b, _d) = state;
+            let (computed_a, _, computed_d) = computed_alphas;
             trace!("GS-stop:  a: {:<23e}  ({})", b.alpha, stop_reason);
-            Ok(b.alpha)
+            Ok((b.alpha, (computed_a, computed_d)))
         })
 
     }
@@ -375,3 +467,69 @@ where F: FnOnce() -> Result<A, Result<B, C>>
         Err(Err(e)) => Err(e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bracket_quadratics() {
+        let check = |from: f64, initial_step: f64, center: f64, curvature: f64| {
+            let f = |x: f64| Ok::<_, ()>(Value(curvature * (x - center) * (x - center)));
+            let (a, b, c) = bracket_minimum(from, initial_step, f).unwrap().unwrap();
+            let (lo, hi) = (a.alpha.min(c.alpha), a.alpha.max(c.alpha));
+            assert!(lo < b.alpha && b.alpha < hi, "{:?}", (a, b, c));
+            assert!(b.value <= a.value && b.value <= c.value, "{:?}", (a, b, c));
+            assert!(lo <= center && center <= hi, "{:?}", (a, b, c));
+
+            let alpha = {
+                Golden::new()
+                    .stop_condition(&from_json!({"interval-size": 1e-9}))
+                    .run((a.alpha, c.alpha), f)
+                    .unwrap().unwrap()
+            };
+            assert_close!(abs=1e-6, alpha, center);
+        };
+
+        // stiff and soft
+        check(0.0, 1e-4, 0.5, 1e4);
+        check(0.0, 1e-4, 0.5, 1e-4);
+        // far away, and in the negative direction
+        check(0.0, 1e-4, 300.0, 1.0);
+        check(0.0, 1e-4, -3.0, 1.0);
+        check(0.0, 1e-4, -3.0, 1e-4);
+        // initial step larger than the distance to the minimum
+        check(0.0, 1.0, 0.1, 1.0);
+        check(0.0, 1.0, -0.1, 1.0);
+        // starting at the minimum
+        check(2.0, 1e-4, 2.0, 1.0);
+    }
+
+    #[test]
+    fn bracket_monotonic() {
+        let f = |x: f64| Ok::<_, ()>(Value(-x));
+        assert!(bracket_minimum(0.0, 1e-4, f).is_err());
+        let f = |x: f64| Ok::<_, ()>(Value(x));
+        assert!(bracket_minimum(0.0, 1e-4, f).is_err());
+    }
+
+    #[test]
+    fn golden_bracket() {
+        let f = |x: f64| Ok::<_, ()>(Value((x - 0.3) * (x - 0.3)));
+
+        let golden = Golden::new();
+        let (alpha, (a, d)) = golden.run_with_bracket((0.0, 1.0), f).unwrap().unwrap();
+        assert_eq!(alpha, golden.run((0.0, 1.0), f).unwrap().unwrap());
+        assert!(a.min(d) <= alpha && alpha <= a.max(d), "{} not in {:?}", alpha, (a, d));
+        assert!((a - d).abs() < 1e-6);
+        assert!((alpha - 0.3).abs() <= (a - d).abs().max(1e-8));
+
+        // reversed interval, stopping early
+        let golden = Golden::new().stop_condition(&from_json!({"interval-size": 1e-2})).clone();
+        let (alpha, (a, d)) = golden.run_with_bracket((1.0, 0.0), f).unwrap().unwrap();
+        let (lo, hi) = (a.min(d), a.max(d));
+        assert!(hi - lo <= 1e-2);
+        assert!(lo <= 0.3 && 0.3 <= hi, "{:?}", (a, d));
+        assert!(lo <= alpha && alpha <= hi);
+    }
+}
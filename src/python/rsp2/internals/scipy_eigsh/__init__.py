@@ -9,6 +9,7 @@
 #  and that the project as a whole is licensed under the GPL 3.0.
 ###########################################################################
 
+import numpy as np
 import scipy.sparse
 import scipy.sparse.linalg as spla
 from scipy.sparse.linalg.eigen.arpack import ArpackNoConvergence
@@ -29,11 +30,20 @@ def eigsh_custom(A,
                  #
                  # Defaults to match the setting of `allow_fewer_solutions`.
                  auto_adjust_k=None,
+
+                 # Derive the starting vector from this seed (and `attempt`)
+                 # instead of letting ARPACK choose one at random.
+                 # Ignored if 'v0' is given.
+                 seed=None,
+                 attempt=0,
                  **kw):
 
     if auto_adjust_k is None:
         auto_adjust_k = allow_fewer_solutions
 
+    if seed is not None and 'v0' not in kw:
+        kw['v0'] = seeded_initial_vector(A.shape[0], seed, attempt)
+
     if 'k' not in kw:
         kw['k'] = 6 # Scipy's own default
 
@@ -57,6 +67,14 @@ def eigsh_custom(A,
         else:
             raise
 
+# A deterministic starting vector for ARPACK.
+#
+# RandomState only accepts 32-bit seeds, so the (up to 64-bit) seed is
+# split into words alongside the attempt number.
+def seeded_initial_vector(n, seed, attempt):
+    words = [seed & 0xFFFFFFFF, seed >> 32, attempt]
+    return np.random.RandomState(words).uniform(-1, 1, n)
+
 # precompute OPinv for faster repeated shift-invert calls
 def get_OPinv(A, sigma, tol=0):
     # FIXME usage of scipy implementation detail
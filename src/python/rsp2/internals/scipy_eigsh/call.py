@@ -21,6 +21,7 @@ from rsp2.io import eigensols
 def main(d):
     kw = d.pop('kw')
     kw['allow_fewer_solutions'] = d.pop('allow-fewer-solutions')
+    kw['seed'] = d.pop('seed')
     m = dynmat.from_dict(d.pop('matrix'))
     assert not d
 
@@ -46,6 +46,7 @@ def main_from_rust():
     shift_invert_attempts = d.pop('shift-invert-attempts')
     dense = d.pop('dense')
     max_solutions = d.pop('max-solutions')
+    seed = d.pop('seed')
     assert not d
 
     out = run(m,
@@ -56,6 +57,7 @@ def main_from_rust():
               use_fallback=True, # FIXME add to input json from rust
               max_solutions=max_solutions,
               search_solutions=None, # FIXME add to input json from rust
+              seed=seed,
               )
 
     info('trace: sending eigensolutions from python to rust')
@@ -101,6 +103,11 @@ def main_from_cli():
              "faster when a few hundred solutions are requested rather than "
              "just 12."
     )
+    p.add_argument(
+        '--seed', type=int, default=None,
+        help="seed for ARPACK's starting vectors, for reproducible output. "
+             "By default, ARPACK chooses them at random."
+    )
     args = p.parse_args()
 
     if (not args.dense
@@ -119,6 +126,7 @@ def main_from_cli():
         max_solutions=args.max_solutions,
         use_fallback=args.use_fallback,
         search_solutions=args.search_solutions,
+        seed=args.seed,
     )
     eigensols.to_path(args.output, out)
 
@@ -130,9 +138,13 @@ def run(m: scipy.sparse.bsr_matrix,
         max_solutions: tp.Optional[int],
         use_fallback: bool,
         search_solutions: tp.Optional[int],
+        seed: tp.Optional[int] = None,
         ):
     """
     A suitable entry point from pure python code.
+
+    When ``seed`` is not None, each call to ARPACK uses a starting vector
+    derived from it, making the output reproducible.
     """
     if max_solutions is None:
         if dense:
@@ -153,6 +165,7 @@ def run(m: scipy.sparse.bsr_matrix,
                                      max_solutions=search_solutions,
                                      shift_invert_attempts=shift_invert_attempts,
                                      ncv=shift_invert_ncv,
+                                     seed=seed,
                                      )
             if not all(acousticness(v) > 1. - 1e-3 for v in esols[1]):
                 return esols
@@ -161,6 +174,9 @@ def run(m: scipy.sparse.bsr_matrix,
             return try_regular(m,
                                max_solutions=search_solutions,
                                ncv=plain_ncv,
+                               seed=seed,
+                               # distinct from any shift-invert attempt
+                               attempt=shift_invert_attempts,
                                )
         else:
             raise RuntimeError('Failed to diagonalize matrix!')
@@ -174,7 +190,7 @@ def run(m: scipy.sparse.bsr_matrix,
 # As an optimization, begin by using shift-invert mode, which can converge
 # in **significantly** fewer iterations than regular mode.
 # noinspection PyUnreachableCode
-def try_shift_invert(m, *, shift_invert_attempts, max_solutions, ncv):
+def try_shift_invert(m, *, shift_invert_attempts, max_solutions, ncv, seed=None):
     info('trace: precomputing OPinv for shift-invert')
 
     # From what I have seen, shift_invert mode tends to find most of its
@@ -209,6 +225,8 @@ def try_shift_invert(m, *, shift_invert_attempts, max_solutions, ncv):
             allow_fewer_solutions=True,
             auto_adjust_k=True,
             auto_adjust_ncv=True,
+            seed=seed,
+            attempt=call_i,
         )
         evecs = np.array(list(map(normalize, evecs)))
 
@@ -360,7 +378,7 @@ def lazy_all(it): return all(pred() for pred in it)
 # If shift-invert hasn't produced anything satisfactory, try regular mode.
 # From what I've seen, this always produces legitimate solutions, but generally
 # takes long to converge onto anything.
-def try_regular(m, *, max_solutions, ncv):
+def try_regular(m, *, max_solutions, ncv, seed=None, attempt=0):
     info('trace: trying non-shift-invert')
 
     return eigsh_custom(
@@ -372,6 +390,8 @@ def try_regular(m, *, max_solutions, ncv):
         allow_fewer_solutions=True,
         auto_adjust_k=True,
         auto_adjust_ncv=True,
+        seed=seed,
+        attempt=attempt,
     )
 
 def try_dense(m, max_solutions: int):
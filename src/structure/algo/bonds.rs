@@ -17,6 +17,7 @@ use std::ops::{Deref};
 use rsp2_soa_ops::{Perm, Permute};
 use rsp2_array_types::{V3};
 use failure::Error;
+use rayon_cond::CondIterator;
 
 /// Bond data in a more widely-reusable form than `CartBonds`.
 ///
@@ -69,6 +70,7 @@ pub struct FracBonds {
 ///
 /// * replacing a coordinate with an image
 /// * unimodular transformations of the lattice
+/// * reordering of sites, if the bonds are permuted in the same way (see `Permute`)
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature="serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature="serde", serde(rename_all = "kebab-case"))]
@@ -249,9 +251,20 @@ impl FracBonds {
     pub fn compute(
         original_coords: &Coords,
         range: f64,
+    ) -> Result<Self, Error> {
+        Self::compute_maybe_parallel(original_coords, range, false)
+    }
+
+    /// `compute`, optionally parallelized over atoms using rayon.
+    ///
+    /// The output is identical regardless of `parallel`.
+    pub fn compute_maybe_parallel(
+        original_coords: &Coords,
+        range: f64,
+        parallel: bool,
     ) -> Result<Self, Error> {
         let fake_meta = vec![(); original_coords.len()];
-        Self::compute_with_meta(original_coords, &fake_meta, |(), ()| Some(range))
+        Self::compute_with_meta_maybe_parallel(original_coords, &fake_meta, |(), ()| Some(range), parallel)
     }
 
     /// Compute bonds, using different bond lengths for different types.
@@ -267,7 +280,20 @@ impl FracBonds {
         original_coords: &Coords,
         meta: impl IntoIterator<Item=M>,
         // Range for different atom types. This will affect membership of bonds in the output.
+        meta_range: impl FnMut(&M, &M) -> Option<f64>,
+    ) -> Result<Self, Error> {
+        Self::compute_with_meta_maybe_parallel(original_coords, meta, meta_range, false)
+    }
+
+    /// `compute_with_meta`, optionally parallelized over atoms using rayon.
+    ///
+    /// The output is identical regardless of `parallel`.  (`meta_range` is always called
+    /// serially, before the parallel part begins)
+    pub fn compute_with_meta_maybe_parallel<M: Ord>(
+        original_coords: &Coords,
+        meta: impl IntoIterator<Item=M>,
         mut meta_range: impl FnMut(&M, &M) -> Option<f64>,
+        parallel: bool,
     ) -> Result<Self, Error> {
         let meta = meta.into_iter().collect::<Vec<_>>();
 
@@ -286,7 +312,7 @@ impl FracBonds {
             meta_range(unique_meta[r], unique_meta[c]).map(|dist| dist * dist)
         });
 
-        let out = Self::_from_brute_force_with_meta(original_coords, &meta_indices, &dense_ranges_sq)?;
+        let out = Self::_from_brute_force_with_meta(original_coords, &meta_indices, &dense_ranges_sq, parallel)?;
         if cfg!(debug_assertions) {
             let full_range = f64::sqrt(max_value_present(&dense_ranges_sq.flat));
             out.sanity_check(original_coords, full_range, &meta, meta_range);
@@ -299,6 +325,7 @@ impl FracBonds {
         original_coords: &Coords,
         original_meta: &[u32],
         meta_range_sq: &Dense<Option<f64>>,
+        parallel: bool,
     ) -> Result<Self, Error> {
         // Construct a supercell large enough to contain all atoms that interact with an atom
         // in the centermost unit cell, assuming they're all reduced.
@@ -343,29 +370,34 @@ impl FracBonds {
                 .collect()
         };
 
-        let mut num_visited = 0;
-        let mut bond_from = vec![];
-        let mut bond_to = vec![];
-        let mut bond_image_diff = vec![];
-        let mut bond_sort_keys = vec![];
-        let mut nearby_indices = vec![];
-        for (index_from, &latt_from, &site_from, &cart_from, &bin_from) in izip!(0.., &sc_latts, &sc_sites, &sc_carts, &sc_bins) {
-            // The supercell is large enough that we can disregard its periodicity, and consider
-            // interactions between its centermost cell and any other atom.
-            if latt_from != sc_centermost_latt {
-                continue;
-            }
-            num_visited += 1;
+        // Each atom in the centermost cell can be processed independently.
+        //
+        // (The supercell is large enough that we can disregard its periodicity, and consider
+        //  interactions between its centermost cell and any other atom.)
+        let center_indices: Vec<usize> = {
+            sc_latts.iter().enumerate()
+                .filter(|&(_, &latt)| latt == sc_centermost_latt)
+                .map(|(index, _)| index)
+                .collect()
+        };
+        assert_eq!(center_indices.len(), num_atoms, "(BUG) wrong # atoms in center cell?");
+
+        let bonds_from_atom = |index_from: usize| {
+            let latt_from = sc_latts[index_from];
+            let site_from = sc_sites[index_from];
+            let cart_from = sc_carts[index_from];
+            let bin_from = sc_bins[index_from];
             let meta_from = original_meta[site_from];
 
             // gather indices from nearby bins
-            nearby_indices.clear();
+            let mut nearby_indices = vec![];
             for &bin_diff in &nearby_bin_diffs {
                 if let Some(neighbors) = bin_sites.get(&(bin_from + bin_diff)) {
                     nearby_indices.extend(neighbors.iter().cloned());
                 }
             }
 
+            let mut out = vec![];
             for &index_to in &nearby_indices {
                 let latt_to = sc_latts[index_to];
                 let site_to = sc_sites[index_to];
@@ -386,21 +418,34 @@ impl FracBonds {
                     if (site_from, latt_from) == (site_to, latt_to) {
                         continue;
                     }
-                    bond_from.push(site_from);
-                    bond_to.push(site_to);
 
                     // `latt_to - latt_from` would give us the image diff between the images in our
                     // supercell, but that's computed from the reduced positions. We actually want
                     // the image diffs for the original positions.
                     let adjusted_latt_to = latt_to - original_latts[site_to];
                     let adjusted_latt_from = latt_from - original_latts[site_from];
-                    bond_image_diff.push(adjusted_latt_to - adjusted_latt_from);
-                    bond_sort_keys.push((index_from, index_to));
+                    let image_diff = adjusted_latt_to - adjusted_latt_from;
+                    out.push((site_from, site_to, image_diff, (index_from, index_to)));
                 }
             }
+            out
+        };
+        let bonds_by_atom: Vec<Vec<_>> = {
+            CondIterator::new(center_indices, parallel)
+                .map(bonds_from_atom)
+                .collect()
+        };
+
+        let mut bond_from = vec![];
+        let mut bond_to = vec![];
+        let mut bond_image_diff = vec![];
+        let mut bond_sort_keys = vec![];
+        for (site_from, site_to, image_diff, sort_key) in bonds_by_atom.into_iter().flatten() {
+            bond_from.push(site_from);
+            bond_to.push(site_to);
+            bond_image_diff.push(image_diff);
+            bond_sort_keys.push(sort_key);
         }
-        let num_atoms = original_coords.num_atoms();
-        assert_eq!(num_visited, num_atoms, "(BUG) wrong # atoms in center cell?");
 
         // Give a consistent ordering.
         //
@@ -570,7 +615,7 @@ fn sufficiently_large_centered_supercell(
         ensure!(!lattice.is_large_skew(1e-4), "cell is too skewed for bond search");
 
         // Return the plane distances from the origin.
-        Ok(V3::from_fn(|axis| 0.5 * lattice.plane_spacing(V3::axis_unit(axis))))
+        Ok(0.5 * lattice.perpendicular_widths())
     };
     let distances = check_plane_distances(lattice)?;
 
@@ -723,6 +768,18 @@ impl Permute for FracBonds {
     }
 }
 
+impl Permute for CartBonds {
+    fn permuted_by(self, perm: &Perm) -> Self {
+        assert_eq!(self.num_atoms, perm.len());
+        CartBonds {
+            num_atoms: self.num_atoms,
+            from: self.from.into_iter().map(|x| perm.permute_index(x)).collect(),
+            to: self.to.into_iter().map(|x| perm.permute_index(x)).collect(),
+            cart_vector: self.cart_vector,
+        }
+    }
+}
+
 //=================================================================
 
 #[cfg(test)]
@@ -928,6 +985,33 @@ mod tests {
             ].into_iter().collect::<BTreeSet<_>>(),
         }
     }
+
+    #[test]
+    fn cart_bonds_permute() {
+        let coords = Coords::new(
+            Lattice::orthorhombic(4.0, 4.0, 10.0),
+            CoordsKind::Carts(vec![
+                V3([0.0, 0.0, 0.0]),
+                V3([1.0, 0.0, 0.0]),
+                V3([1.0, 1.5, 0.0]),
+                V3([3.0, 0.5, 0.0]),
+            ]),
+        );
+        let range = 1.6;
+        let perm = Perm::from_vec(vec![2, 0, 3, 1]).unwrap();
+
+        let sorted = |bonds: &CartBonds| {
+            let mut vec = bonds.into_iter().map(|b| (b.from, b.to, b.cart_vector.0)).collect::<Vec<_>>();
+            vec.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            vec
+        };
+
+        let permuted_coords = coords.clone().permuted_by(&perm);
+        let expected = FracBonds::compute(&permuted_coords, range).unwrap().to_cart_bonds(&permuted_coords);
+        let actual = FracBonds::compute(&coords, range).unwrap().to_cart_bonds(&coords).permuted_by(&perm);
+        assert_eq!(sorted(&actual), sorted(&expected));
+        assert!(!sorted(&actual).is_empty());
+    }
 }
 
 //==================================================================================================
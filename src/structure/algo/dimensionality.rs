@@ -0,0 +1,172 @@
+/* ************************************************************************ **
+** This file is part of rsp2, and is licensed under EITHER the MIT license  **
+** or the Apache 2.0 license, at your option.                               **
+**                                                                          **
+**     http://www.apache.org/licenses/LICENSE-2.0                           **
+**     http://opensource.org/licenses/MIT                                   **
+**                                                                          **
+** Be aware that not all of rsp2 is provided under this permissive license, **
+** and that the project as a whole is licensed under the GPL 3.0.           **
+** ************************************************************************ */
+
+//! Classification of structures by the number of directions in which they are periodic.
+//!
+//! A structure in rsp2 is always periodic along all three lattice vectors, but low-dimensional
+//! materials are modeled by adding vacuum along some of them.  The functions in this module
+//! detect that vacuum.
+
+use crate::Coords;
+
+use ordered_float::NotNan;
+use rsp2_array_types::V3;
+
+/// The number of lattice directions along which a structure is effectively periodic.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Dimensionality {
+    /// Separated by vacuum in all directions. (e.g. a molecule)
+    Molecule,
+    /// Periodic along one lattice vector. (e.g. a chain or a nanotube)
+    Chain,
+    /// Periodic along two lattice vectors. (e.g. a sheet of graphene)
+    Sheet,
+    /// Periodic along all three lattice vectors.
+    Bulk,
+}
+
+impl Dimensionality {
+    pub fn from_num_periodic_axes(n: usize) -> Self {
+        match n {
+            0 => Dimensionality::Molecule,
+            1 => Dimensionality::Chain,
+            2 => Dimensionality::Sheet,
+            3 => Dimensionality::Bulk,
+            _ => panic!("bad number of periodic axes: {}", n),
+        }
+    }
+
+    pub fn num_periodic_axes(self) -> usize {
+        match self {
+            Dimensionality::Molecule => 0,
+            Dimensionality::Chain => 1,
+            Dimensionality::Sheet => 2,
+            Dimensionality::Bulk => 3,
+        }
+    }
+}
+
+/// For each lattice vector, find the largest gap between consecutive planes of atoms
+/// along that direction.
+///
+/// Concretely, for lattice vector `k`, atoms are projected onto the normal of the plane spanned
+/// by the other two lattice vectors, and the result is the largest cartesian distance between
+/// consecutive projections (including the one that crosses the periodic boundary).  For a
+/// structure with only one atom, this is simply the interplanar spacing.
+pub fn largest_vacuum_gaps(coords: &Coords) -> [f64; 3] {
+    let fracs = coords.to_fracs();
+    let lattice = coords.lattice();
+
+    let mut out = [0.0; 3];
+    for (axis, out) in out.iter_mut().enumerate() {
+        let reduce = |x: f64| (x.fract() + 1.0).fract();
+        let mut positions: Vec<_> = fracs.iter().map(|v| NotNan::new(reduce(v[axis])).unwrap()).collect();
+        positions.sort();
+
+        let largest_frac_gap = match (positions.first(), positions.last()) {
+            (Some(&first), Some(&last)) => {
+                let wrapping_gap = first.into_inner() + 1.0 - last.into_inner();
+                positions.windows(2)
+                    .map(|w| w[1].into_inner() - w[0].into_inner())
+                    .fold(wrapping_gap, f64::max)
+            },
+            _ => 1.0,
+        };
+
+        let miller = V3::from_fn(|k| (k == axis) as i32);
+        *out = largest_frac_gap * lattice.plane_spacing(miller);
+    }
+    out
+}
+
+/// Determine which lattice vectors the structure is periodic along, i.e. those
+/// for which no gap found by `largest_vacuum_gaps` exceeds `vacuum_threshold`.
+///
+/// The threshold should be comfortably larger than the longest interatomic distance
+/// in the material, and smaller than the amount of vacuum that was added.
+pub fn periodic_axes(coords: &Coords, vacuum_threshold: f64) -> [bool; 3] {
+    let gaps = largest_vacuum_gaps(coords);
+    [
+        gaps[0] <= vacuum_threshold,
+        gaps[1] <= vacuum_threshold,
+        gaps[2] <= vacuum_threshold,
+    ]
+}
+
+/// Classify a structure by its number of periodic directions. (see `periodic_axes`)
+pub fn dimensionality(coords: &Coords, vacuum_threshold: f64) -> Dimensionality {
+    let periodic = periodic_axes(coords, vacuum_threshold);
+    Dimensionality::from_num_periodic_axes(periodic.iter().filter(|&&p| p).count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoordsKind, Lattice};
+
+    const THRESHOLD: f64 = 4.0;
+
+    #[test]
+    fn chain() {
+        // a zigzag chain along the third lattice vector
+        let lattice = Lattice::orthorhombic(15.0, 15.0, 2.5);
+        let coords = Coords::new(lattice, CoordsKind::Carts(vec![
+            V3([7.0, 7.5, 0.0]),
+            V3([8.0, 7.5, 1.25]),
+        ]));
+        assert_eq!(periodic_axes(&coords, THRESHOLD), [false, false, true]);
+        assert_eq!(dimensionality(&coords, THRESHOLD), Dimensionality::Chain);
+        assert_close!(abs=1e-10, largest_vacuum_gaps(&coords), [14.0, 15.0, 1.25]);
+    }
+
+    #[test]
+    fn sheet() {
+        // graphene, straddling the periodic boundary along z
+        let a = 2.46;
+        let lattice = Lattice::from([
+            [a, 0.0, 0.0],
+            [-0.5 * a, 0.5 * 3f64.sqrt() * a, 0.0],
+            [0.0, 0.0, 10.0],
+        ]);
+        let coords = Coords::new(lattice, CoordsKind::Fracs(vec![
+            V3([0.0, 0.0, 0.99]),
+            V3([1.0 / 3.0, 2.0 / 3.0, 0.01]),
+        ]));
+        assert_eq!(periodic_axes(&coords, THRESHOLD), [true, true, false]);
+        assert_eq!(dimensionality(&coords, THRESHOLD), Dimensionality::Sheet);
+        assert_close!(abs=1e-10, largest_vacuum_gaps(&coords)[2], 9.8);
+    }
+
+    #[test]
+    fn bulk() {
+        // diamond
+        let lattice = Lattice::from([
+            [0.0, 1.785, 1.785],
+            [1.785, 0.0, 1.785],
+            [1.785, 1.785, 0.0],
+        ]);
+        let coords = Coords::new(lattice, CoordsKind::Fracs(vec![
+            V3([0.0, 0.0, 0.0]),
+            V3([0.25, 0.25, 0.25]),
+        ]));
+        assert_eq!(periodic_axes(&coords, THRESHOLD), [true, true, true]);
+        assert_eq!(dimensionality(&coords, THRESHOLD), Dimensionality::Bulk);
+    }
+
+    #[test]
+    fn molecule() {
+        let coords = Coords::new(Lattice::cubic(20.0), CoordsKind::Carts(vec![
+            V3([10.0, 10.0, 10.0]),
+            V3([11.1, 10.0, 10.0]),
+        ]));
+        assert_eq!(dimensionality(&coords, THRESHOLD), Dimensionality::Molecule);
+    }
+}
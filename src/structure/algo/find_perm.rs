@@ -10,7 +10,7 @@
 ** ************************************************************************ */
 
 use crate::{Lattice, Coords, CoordsKind};
-use crate::{CartOp};
+use crate::{CartOp, IntRot};
 use super::group::GroupTree;
 
 use rsp2_array_types::V3;
@@ -83,6 +83,21 @@ pub fn spacegroup_deperms(
 ) -> Result<Vec<Perm>, Error>
 { spacegroup_coperms(coords, ops, tol).map(invert_each) }
 
+/// `spacegroup_deperms`, optionally computing the permutations of the generators in
+/// parallel using rayon.
+///
+/// The output is identical regardless of `parallel`.
+pub fn spacegroup_deperms_maybe_parallel(
+    coords: &Coords,
+    ops: &[CartOp],
+    tol: f64,
+    parallel: bool,
+) -> Result<Vec<Perm>, Error>
+{
+    let dummy_meta = vec![(); coords.num_atoms()];
+    spacegroup_coperms_with_meta_maybe_parallel(coords, &dummy_meta, ops, tol, parallel).map(invert_each)
+}
+
 // NOTE: This version uses the metadata to group the atoms and potentially
 //       elide even more comparisons. Is it effective? No idea! But it comes at
 //       zero extra cost for `M = ()` and hasn't been hurting anyone, so I
@@ -101,6 +116,41 @@ pub fn spacegroup_coperms_with_meta<M: Ord>(
 
     tol: f64,
 ) -> Result<Vec<Perm>, Error>
+{ spacegroup_coperms_with_meta_maybe_parallel(coords, metadata, cart_ops, tol, false) }
+
+/// `spacegroup_coperms_with_meta`, optionally computing the permutations of the generators
+/// in parallel using rayon.
+///
+/// The output is identical regardless of `parallel`.
+pub fn spacegroup_coperms_with_meta_maybe_parallel<M: Ord>(
+    coords: &Coords,
+    metadata: &[M],
+    cart_ops: &[CartOp],
+    tol: f64,
+    parallel: bool,
+) -> Result<Vec<Perm>, Error>
+{
+    // Go from meta of arbitrary M type to a fixed set of indices, so that it can be shared
+    // between threads.  (the sort trick only cares about the ordering, which is preserved)
+    let mut unique_meta = metadata.iter().collect::<Vec<_>>();
+    unique_meta.sort();
+    unique_meta.dedup();
+
+    let map: std::collections::BTreeMap<&M, u32> = {
+        unique_meta.iter().cloned().enumerate().map(|(i, x)| (x, i as u32)).collect()
+    };
+    let meta_indices = metadata.iter().map(|x| map[x]).collect::<Vec<_>>();
+
+    _spacegroup_coperms_with_meta(coords, &meta_indices, cart_ops, tol, parallel)
+}
+
+fn _spacegroup_coperms_with_meta(
+    coords: &Coords,
+    metadata: &[u32],
+    cart_ops: &[CartOp],
+    tol: f64,
+    parallel: bool,
+) -> Result<Vec<Perm>, Error>
 {Ok({
     let lattice = coords.lattice();
     let from_fracs = coords.to_fracs();
@@ -131,28 +181,33 @@ pub fn spacegroup_coperms_with_meta<M: Ord>(
     // as verified by the unit tests.
     let adjusted_tol = tol + 1e-12 * f64::cbrt(lattice.volume());
 
-    tree.try_compute_homomorphism(
-        // Generators: Do a (very expensive!) brute force search.
-        |op_ind, _int_op| {
-            let to_fracs = cart_ops[op_ind].transform_fracs(lattice, &from_fracs);
-            brute_force_with_sort_trick(
-                lattice,
-                metadata, CoordsKind::Fracs(&from_fracs),
-                metadata, CoordsKind::Fracs(&to_fracs[..]),
-                adjusted_tol,
-            )
-        },
-        // Other operators: Quickly compose the results from other operators.
-        |a, b| Ok({
-            // Flip the order, because the permutations we seek
-            //  actually come from the opposite group.
-            //
-            // i.e.  given P_a X = X R_a
-            //         and P_b X = X R_b,
-            //  one can easily show that  X R_a R_b = P_a P_b X
-            b.clone().permuted_by(a)
-        }),
-    )?
+    // Generators: Do a (very expensive!) brute force search.
+    //             These are independent, so they may be done in parallel.
+    let compute = |op_ind: usize, _int_op: &IntRot| {
+        let to_fracs = cart_ops[op_ind].transform_fracs(lattice, &from_fracs);
+        brute_force_with_sort_trick(
+            lattice,
+            metadata, CoordsKind::Fracs(&from_fracs),
+            metadata, CoordsKind::Fracs(&to_fracs[..]),
+            adjusted_tol,
+        )
+    };
+    // Other operators: Quickly compose the results from other operators.
+    let compose = |a: &Perm, b: &Perm| Ok({
+        // Flip the order, because the permutations we seek
+        //  actually come from the opposite group.
+        //
+        // i.e.  given P_a X = X R_a
+        //         and P_b X = X R_b,
+        //  one can easily show that  X R_a R_b = P_a P_b X
+        b.clone().permuted_by(a)
+    });
+
+    if parallel {
+        tree.par_try_compute_homomorphism(compute, compose)?
+    } else {
+        tree.try_compute_homomorphism(compute, compose)?
+    }
 })}
 
 pub fn spacegroup_deperms_with_meta<M: Ord>(
@@ -171,9 +226,166 @@ pub fn spacegroup_deperms_with_meta<M: Ord>(
 ) -> Result<Vec<Perm>, Error>
 { spacegroup_coperms_with_meta(coords, metadata, cart_ops, tol).map(invert_each) }
 
+/// Compute depermutations for pure translations of a superstructure.
+///
+/// Unlike `spacegroup_deperms`, the translations need not form a group.  Each one is found
+/// by an independent brute force search (performed in parallel).
+///
+/// The output deperms satisfy `translated.permuted_by(&deperm) ~ original`, where
+/// `translated` are the coords after adding the translation. (equal up to images under
+/// the lattice)
+pub fn translation_deperms(
+    coords: &Coords,
+    // Cartesian translations.  Each must be a symmetry of the structure.
+    translations: &[V3],
+    tol: f64,
+) -> Result<Vec<Perm>, Error>
+{
+    use rayon::prelude::*;
+
+    let lattice = coords.lattice();
+    let from_fracs = coords.to_fracs();
+    let dummy_meta = vec![(); coords.num_atoms()];
+    let adjusted_tol = tol + 1e-12 * f64::cbrt(lattice.volume());
+
+    translations.par_iter()
+        .map(|&translation| {
+            let frac_translation = translation / lattice;
+            let to_fracs: Vec<_> = from_fracs.iter().map(|v| v + frac_translation).collect();
+            let coperm = brute_force_with_sort_trick(
+                lattice,
+                &dummy_meta, CoordsKind::Fracs(&from_fracs),
+                &dummy_meta, CoordsKind::Fracs(&to_fracs[..]),
+                adjusted_tol,
+            )?;
+            Ok(coperm.inverted())
+        })
+        .collect()
+}
+
 fn invert_each(perms: impl IntoIterator<Item=Perm>) -> Vec<Perm>
 { perms.into_iter().map(|p| p.inverted()).collect() }
 
+/// Memoizes `spacegroup_deperms` for structures that are analyzed repeatedly.
+///
+/// This is intended for cases like the ev-loop, where the structure only relaxes slightly between
+/// calls but its symmetry does not change.  Entries are looked up by the number of atoms and
+/// operators, and are reused as long as each cached depermutation is still a valid
+/// representation of the corresponding operator on the new structure (within `tol`).
+/// Checking this is only `O(n)` per operator, which is far cheaper than finding the
+/// permutations from scratch.
+///
+/// If the structure is bitwise identical to the one last seen (according to a hash
+/// of the lattice and coordinates), even this check is skipped.
+#[derive(Debug, Clone, Default)]
+pub struct SymmetryCache {
+    entries: Vec<SymmetryCacheEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct SymmetryCacheEntry {
+    fingerprint: u64,
+    cart_ops: Vec<CartOp>,
+    tol: f64,
+    deperms: Vec<Perm>,
+}
+
+impl SymmetryCache {
+    pub fn new() -> Self
+    { Self::default() }
+
+    /// Forget all cached permutations.
+    ///
+    /// This should be called if the structure may have changed in a way that breaks its symmetry
+    /// without moving any atom by more than the tolerance, or if the atoms have been reordered.
+    pub fn invalidate(&mut self)
+    { self.entries.clear(); }
+
+    /// Equivalent to the free function `spacegroup_deperms`, but may reuse a previous result.
+    pub fn spacegroup_deperms(
+        &mut self,
+        coords: &Coords,
+        ops: &[CartOp],
+        tol: f64,
+    ) -> Result<Vec<Perm>, Error>
+    { self.spacegroup_deperms_maybe_parallel(coords, ops, tol, false) }
+
+    /// Equivalent to the free function `spacegroup_deperms_maybe_parallel`, but may reuse a
+    /// previous result.
+    pub fn spacegroup_deperms_maybe_parallel(
+        &mut self,
+        coords: &Coords,
+        ops: &[CartOp],
+        tol: f64,
+        parallel: bool,
+    ) -> Result<Vec<Perm>, Error>
+    {Ok({
+        let fingerprint = structure_fingerprint(coords);
+
+        let found = self.entries.iter_mut().position(|entry| {
+            entry.tol == tol
+                && entry.cart_ops.len() == ops.len()
+                && entry.deperms.iter().all(|p| p.len() == coords.num_atoms())
+        });
+        if let Some(index) = found {
+            let entry = &mut self.entries[index];
+            let is_valid = {
+                (entry.fingerprint == fingerprint && &entry.cart_ops[..] == ops)
+                    || deperms_are_valid(coords, ops, &entry.deperms, tol)
+            };
+            if is_valid {
+                trace!("Reusing cached spacegroup permutations");
+                entry.fingerprint = fingerprint;
+                entry.cart_ops = ops.to_vec();
+                return Ok(entry.deperms.clone());
+            }
+            self.entries.remove(index);
+        }
+
+        let deperms = spacegroup_deperms_maybe_parallel(coords, ops, tol, parallel)?;
+        self.entries.push(SymmetryCacheEntry {
+            fingerprint,
+            cart_ops: ops.to_vec(),
+            tol,
+            deperms: deperms.clone(),
+        });
+        deperms
+    })}
+}
+
+// (the coordinates are deliberately hashed in their original order rather than sorted,
+//  because the permutations depend on the order of the atoms)
+fn structure_fingerprint(coords: &Coords) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for row in coords.lattice().matrix() {
+        for x in row {
+            x.to_bits().hash(&mut hasher);
+        }
+    }
+    for v in coords.to_fracs() {
+        for x in &v {
+            x.to_bits().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+// Check that each depermutation maps the structure onto its image under the operator.
+fn deperms_are_valid(coords: &Coords, ops: &[CartOp], deperms: &[Perm], tol: f64) -> bool {
+    let lattice = coords.lattice();
+    let from_fracs = coords.to_fracs();
+    // (see spacegroup_coperms_with_meta)
+    let adjusted_tol = tol + 1e-12 * f64::cbrt(lattice.volume());
+
+    ops.iter().zip(deperms).all(|(op, deperm)| {
+        let to_fracs = op.transform_fracs(lattice, &from_fracs);
+        let permuted = from_fracs.to_vec().permuted_by(&deperm.inverted());
+        permuted.iter().zip(&to_fracs).all(|(&a, &b)| fracs_within(lattice, a, b, adjusted_tol))
+    })
+}
+
 pub(crate) fn brute_force_with_sort_trick<M: Ord>(
     lattice: &Lattice,
     from_meta: &[M],
@@ -323,24 +535,30 @@ fn brute_force_near_identity(
     // computations down to O(n), it is worth it.
     let mut search_start = 0;
 
-    'from: for from in 0..n {
+    for from in 0..n {
 
         // Skip through things filled out of order.
         while perm[search_start] != UNSET {
             search_start += 1;
         }
 
+        // Take the nearest candidate rather than the first one within tolerance, so that
+        // the result for tight clusters of atoms does not depend on their order.
+        let mut best: Option<(usize, f64)> = None;
         for to in search_start..n {
             if perm[to] != UNSET {
                 continue;
             }
 
-            if fracs_within(lattice, from_fracs[from], to_fracs[to], tol) {
-                perm[to] = from;
-                continue 'from;
+            let sqdist = fracs_sqdist(lattice, from_fracs[from], to_fracs[to]);
+            if sqdist < tol * tol && best.map_or(true, |(_, best_sqdist)| sqdist < best_sqdist) {
+                best = Some((to, sqdist));
             }
         }
-        return Err(PositionMatchError::NoMatch(Backtrace::new()));
+        match best {
+            Some((to, _)) => perm[to] = from,
+            None => return Err(PositionMatchError::NoMatch(Backtrace::new())),
+        }
     }
 
     if perm.iter().any(|&x| x == UNSET) {
@@ -403,9 +621,16 @@ fn brute_force_set_difference(
 // unit cells around the origin.
 #[inline(always)] // hopefully lift the `tol * tol` out of a loop
 fn fracs_within(lattice: &Lattice, a: V3, b: V3, tol: f64) -> bool {
+    fracs_sqdist(lattice, a, b) < tol * tol
+}
+
+// Squared cartesian distance between the nearest images of two fractional points,
+// under the same assumption as `fracs_within`.
+#[inline(always)]
+fn fracs_sqdist(lattice: &Lattice, a: V3, b: V3) -> f64 {
     let shortest_diff = (a - b).map(|x| x - x.round());
     let shortest_cart = shortest_diff * lattice;
-    shortest_cart.sqnorm() < tol * tol
+    shortest_cart.sqnorm()
 }
 
 #[cfg(test)]
@@ -447,6 +672,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn brute_force_prefers_nearest() {
+        // Two atoms spaced just above the tolerance, where the image of the second
+        // also lies within tolerance of the first.  Taking the first match would
+        // leave nothing for the second atom.
+        let lattice = Lattice::cubic(1.0);
+        let original = vec![V3([0.0, 0.0, 0.0]), V3([0.11, 0.0, 0.0])];
+        let permuted = vec![V3([0.06, 0.0, 0.0]), V3([0.0, 0.0, 0.0])];
+
+        let output = super::brute_force_near_identity(
+            &lattice, &original, &permuted, 0.1,
+        ).unwrap();
+
+        assert_eq!(output, Perm::from_vec(vec![1, 0]).unwrap());
+    }
+
     #[test]
     fn sort_trick_works() {
         for _ in 0..10 {
@@ -494,6 +735,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn translations() {
+        // 1D chain of three distinguishable-by-position sites in a 3x supercell
+        let lattice = Lattice::diagonal(&[3.0, 1.0, 1.0]);
+        let coords = Coords::new(lattice, CoordsKind::Carts(vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [2.0, 0.0, 0.0],
+        ].envee()));
+
+        let deperms = translation_deperms(&coords, &[
+            V3([0.0, 0.0, 0.0]),
+            V3([1.0, 0.0, 0.0]),
+            V3([2.0, 0.0, 0.0]),
+        ], 1e-5).unwrap();
+
+        for (&distance, deperm) in [0.0, 1.0, 2.0].iter().zip(&deperms) {
+            let translated = coords.to_carts().iter().map(|&v| v + V3([distance, 0.0, 0.0])).collect::<Vec<_>>();
+            let permuted = translated.permuted_by(deperm);
+            for (a, b) in permuted.into_iter().zip(coords.to_carts()) {
+                // equal up to a superlattice vector
+                let diff = (a - b) / 3.0;
+                assert!((diff[0] - diff[0].round()).abs() < 1e-10, "{:?}", deperm);
+            }
+        }
+        assert_eq!(deperms[1], Perm::from_vec(vec![2, 0, 1]).unwrap());
+    }
+
     #[test]
     fn spacegroup_identity_at_tol_zero() {
         //let n_test = 20000; // stress test
@@ -514,6 +783,85 @@ mod tests {
             });
     }
 
+    #[test]
+    fn symmetry_cache() {
+        use rsp2_array_types::M33;
+
+        // four atoms related by a 4-fold rotation about z
+        // (chosen so that the permutations are not involutions)
+        let lattice = Lattice::cubic(4.0);
+        let fracs = vec![
+            [0.1, 0.2, 0.3],
+            [-0.2, 0.1, 0.3],
+            [-0.1, -0.2, 0.3],
+            [0.2, -0.1, 0.3],
+        ].envee();
+        let coords = Coords::new(lattice.clone(), CoordsKind::Fracs(fracs.clone()));
+        let rot: M33 = rsp2_array_types::mat::from_array([[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+        let ops = vec![
+            CartOp::eye(),
+            CartOp::new(&rot, V3::zero()),
+            CartOp::new(&(rot * rot), V3::zero()),
+            CartOp::new(&(rot * rot * rot), V3::zero()),
+        ];
+
+        let fresh = spacegroup_deperms(&coords, &ops, 1e-6).unwrap();
+        assert_ne!(fresh[1], fresh[1].inverted());
+        assert!(deperms_are_valid(&coords, &ops, &fresh, 1e-6));
+
+        let mut cache = SymmetryCache::new();
+        assert_eq!(cache.spacegroup_deperms(&coords, &ops, 1e-6).unwrap(), fresh);
+        assert_eq!(cache.spacegroup_deperms(&coords, &ops, 1e-6).unwrap(), fresh);
+
+        // relax slightly, preserving the symmetry
+        let scaled = Coords::new(lattice.clone(), CoordsKind::Fracs(fracs.iter().map(|&v| v * 1.01).collect()));
+        assert_eq!(
+            cache.spacegroup_deperms(&scaled, &ops, 1e-6).unwrap(),
+            spacegroup_deperms(&scaled, &ops, 1e-6).unwrap(),
+        );
+
+        // reorder the atoms; the cached permutations are no longer valid
+        let perm = Perm::from_vec(vec![2, 0, 3, 1]).unwrap();
+        let permuted = coords.clone().permuted_by(&perm);
+        assert!(!deperms_are_valid(&permuted, &ops, &fresh, 1e-6));
+        assert_eq!(
+            cache.spacegroup_deperms(&permuted, &ops, 1e-6).unwrap(),
+            spacegroup_deperms(&permuted, &ops, 1e-6).unwrap(),
+        );
+
+        cache.invalidate();
+        assert_eq!(cache.spacegroup_deperms(&coords, &ops, 1e-6).unwrap(), fresh);
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        use rsp2_array_types::M33;
+
+        // same as symmetry_cache, with metadata that distinguishes two pairs of atoms
+        let lattice = Lattice::cubic(4.0);
+        let coords = Coords::new(lattice, CoordsKind::Fracs(vec![
+            [0.1, 0.2, 0.3],
+            [-0.2, 0.1, 0.3],
+            [-0.1, -0.2, 0.3],
+            [0.2, -0.1, 0.3],
+        ].envee()));
+        let rot: M33 = rsp2_array_types::mat::from_array([[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+        let ops = vec![CartOp::eye(), CartOp::new(&(rot * rot), V3::zero())];
+
+        // (`Rc` is not `Sync`; the serial path must not require it)
+        let meta = vec!["A", "B", "A", "B"].into_iter().map(std::rc::Rc::new).collect::<Vec<_>>();
+
+        let serial = spacegroup_coperms_with_meta_maybe_parallel(&coords, &meta, &ops, 1e-6, false).unwrap();
+        let parallel = spacegroup_coperms_with_meta_maybe_parallel(&coords, &meta, &ops, 1e-6, true).unwrap();
+        assert_eq!(serial, parallel);
+        assert_eq!(serial[1], Perm::from_vec(vec![2, 3, 0, 1]).unwrap());
+
+        assert_eq!(
+            spacegroup_deperms_maybe_parallel(&coords, &ops, 1e-6, true).unwrap(),
+            spacegroup_deperms(&coords, &ops, 1e-6).unwrap(),
+        );
+    }
+
     // FIXME known failure
 //    #[test]
 //    fn meta_mismatch() {
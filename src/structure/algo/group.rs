@@ -107,6 +107,45 @@ impl<G> GroupTree<G>
         }
         out
     })}
+
+    /// `try_compute_homomorphism`, with the calls to `compute` performed in parallel.
+    ///
+    /// All of the generators are computed up front using rayon, after which the
+    /// remaining members are composed sequentially.  The output is identical to
+    /// that of `try_compute_homomorphism`.  If multiple calls fail, the error
+    /// returned is the one that `try_compute_homomorphism` would have returned.
+    pub fn par_try_compute_homomorphism<E, H>(
+        &self,
+        compute: impl Fn(usize, &G) -> Result<H, E> + Sync,
+        mut compose: impl FnMut(&H, &H) -> Result<H, E>,
+    ) -> Result<Vec<H>, E>
+    where G: Sync, H: Send, E: Send,
+    {Ok({
+        use rayon::prelude::*;
+
+        let len = self.members.len();
+        let generator_indices: Vec<_> = {
+            (0..len).filter(|&index| self.decomps[index].is_none()).collect()
+        };
+
+        // Don't short-circuit here, so that error selection is deterministic.
+        let mut generator_values = {
+            generator_indices.par_iter()
+                .map(|&index| compute(index, &self.members[index]))
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+
+        let mut out = Vec::with_capacity(len);
+        for decomp in &self.decomps {
+            let value = match *decomp {
+                None => generator_values.next().expect("(BUG) too few generators!")?,
+                Some((a, b)) => compose(&out[a], &out[b])?,
+            };
+            out.push(value);
+        }
+        out
+    })}
 }
 
 /// Generates a finite group from a non-empty set of generators.
@@ -10,7 +10,7 @@
 ** ************************************************************************ */
 
 use failure::Error;
-use crate::{Coords, Lattice};
+use crate::{Coords, CoordsKind, Lattice};
 use rsp2_soa_ops::{Permute, Perm, Part, Partition};
 
 use std::mem;
@@ -49,12 +49,15 @@ pub enum Layers {
 ///   "next" group is the first group)
 /// * The indices within each group are arranged to appear contiguous
 ///   when projected along the axis.
-/// * The groups are sorted by the location of the *last* atom in the group,
-///   mapped into the unit cell.
-/// * This should be implied by some of the above points, but just to be clear:
-///   If a layer happens to cross the periodic boundary, then it will be
-///   the first layer listed in `groups`, and it will list the atoms whose
-///   reduced coordinates are closer to 1 before those closer to zero.
+/// * The groups are sorted by the mean position of their atoms along the normal,
+///   taking the images of the atoms that are contiguous with the *last* atom in
+///   the group, mapped into the unit cell.  Layers with equal mean positions
+///   are ordered by their labels (for `find_layers_with_labels`).
+///   The numbering is therefore independent of the order of the input atoms.
+///   (when the layers do not overlap, this is the same as sorting by the last atom)
+/// * If a layer happens to cross the periodic boundary, then it will list the
+///   atoms whose reduced coordinates are closer to 1 before those closer to zero.
+///   If the layers do not overlap, it will also be the first layer listed in `groups`.
 /// * **Gaps are allowed to be zero or even negative.**  However, if constructed
 ///   using the variant of `find_layers` that does not accept labels, they will
 ///   all be strictly positive.
@@ -84,6 +87,15 @@ impl Layers {
         Layers::NoAtoms => vec![],
     }}
 
+    /// `by_atom`, along with the separations between adjacent layers.
+    ///
+    /// See `LayersPerUnitCell::layer_seps`.  The edge cases have no separations.
+    pub fn by_atom_with_layer_seps(&self) -> (Vec<usize>, Vec<f64>)
+    { match self {
+        Layers::PerUnitCell(layers) => layers.by_atom_with_layer_seps(),
+        _ => (self.by_atom(), vec![]),
+    }}
+
     #[must_use = "not an in-place operation"]
     pub fn scale_gaps(self, factor: f64) -> Self
     { match self {
@@ -110,6 +122,19 @@ impl LayersPerUnitCell {
         out
     }
 
+    /// The separations between adjacent layers.
+    ///
+    /// `layer_seps()[i]` is the distance along the normal from layer `i` to layer `i + 1`
+    /// (in cartesian units, when produced by `find_layers`).  Unlike `gaps`, this omits the
+    /// gap between the last layer and the periodic image of the first (which is usually
+    /// vacuum), so that it lines up with the layer seps of `Assemble`.
+    pub fn layer_seps(&self) -> &[f64]
+    { &self.gaps[..self.gaps.len().saturating_sub(1)] }
+
+    /// `by_atom`, along with `layer_seps`.
+    pub fn by_atom_with_layer_seps(&self) -> (Vec<usize>, Vec<f64>)
+    { (self.by_atom(), self.layer_seps().to_vec()) }
+
     #[must_use = "not an in-place operation"]
     pub fn scale_gaps(self, factor: f64) -> Self
     { LayersPerUnitCell {
@@ -343,6 +368,7 @@ fn assign_layers_impl_frac_1d_with_labels<L: Ord>(
     // Track which ones cross the PBC boundary.
     let mut found_layer_without_gap = false;
     let mut parted_crosses_boundary = vec![];
+    let mut parted_mean_positions = vec![];
     for part_indices in &mut parted_indices {
         let perm = argsort_floats(part_indices.iter().map(|&i| positions[i]));
         let new_indices = replace(part_indices, vec![]).permuted_by(&perm);
@@ -376,6 +402,12 @@ fn assign_layers_impl_frac_1d_with_labels<L: Ord>(
                     unable to determine where it starts!\
                 ");
             }
+            // the atoms that get rotated to the front are the ones that need to be
+            // imaged to be contiguous with the last atom
+            let num_imaged = part_indices.len() - split;
+            let sum = part_indices.iter().map(|&i| positions[i]).sum::<f64>() - num_imaged as f64;
+            parted_mean_positions.push(sum / part_indices.len() as f64);
+
             parted_crosses_boundary.push(split != part_indices.len());
             part_indices.rotate_left(split);
         } else {
@@ -394,11 +426,10 @@ fn assign_layers_impl_frac_1d_with_labels<L: Ord>(
         return Ok(Layers::NoDistinctLayers { sorted_indices });
     }
 
-    // arrange the layers to increase in their terminal positions
+    // arrange the layers to increase in their mean positions.
+    // (the sort is stable, so ties are broken by label)
     {
-        let perm = argsort_floats({
-            parted_indices.iter().map(|idxs| positions[*idxs.last().unwrap()])
-        });
+        let perm = argsort_floats(parted_mean_positions);
         parted_indices = parted_indices.permuted_by(&perm);
         parted_crosses_boundary = parted_crosses_boundary.permuted_by(&perm);
     }
@@ -437,6 +468,10 @@ fn argsort_floats(xs: impl IntoIterator<Item=f64>) -> Perm {
 
 // -------------------------------------------------------------
 
+/// Get the axis of a layer normal that lies along a lattice vector.
+///
+/// This fails unless `normal` is `[1, 0, 0]`, `[0, 1, 0]`, or `[0, 0, 1]`, and the
+/// corresponding lattice vector is perpendicular to the other two.
 pub fn require_simple_axis_normal(normal: V3<i32>, lattice: &Lattice) -> Result<usize, Error> {
     let axis = {
         let mut sorted = normal;
@@ -452,8 +487,17 @@ pub fn require_simple_axis_normal(normal: V3<i32>, lattice: &Lattice) -> Result<
     for k in 0..3 {
         if k != axis {
             let cos = dot(&vecs[k], &vecs[axis]) / (norms[k] * norms[axis]);
-            ensure!(cos.abs() < 1e-7,
-                "the normal must be perpendicular to the other two lattice vectors.");
+            if cos.abs() >= 1e-7 {
+                let angle = f64::acos(cos).to_degrees();
+                bail!(
+                    "the layer normal must be perpendicular to the other two lattice vectors, \
+                    but lattice vectors {} and {} meet at an angle of {} degrees ({:+e} from 90). \
+                    Try re-orienting the structure so that lattice vector {} is normal to the layers \
+                    (for layers separated by vacuum, replacing it with its component perpendicular \
+                    to the other two lattice vectors should be harmless).",
+                    k + 1, axis + 1, angle, angle - 90.0, axis + 1,
+                );
+            }
         }
     }
     Ok(axis)
@@ -566,6 +610,60 @@ impl LayersPerUnitCell {
 
 // -------------------------------------------------------------
 
+/// Extract a single layer as its own periodic structure, e.g. for studying an isolated layer.
+///
+/// Layers are found using `find_layers` with the given `normal` and `cart_threshold`, and
+/// `layer` is an index into its groups.  The lattice vector along the normal is shortened so
+/// that the layer is separated from its periodic images by exactly `vacuum` (measured between
+/// the outermost atoms), and the layer is translated to lie in the middle of the new cell.
+///
+/// Also returns the indices of the extracted atoms in the input structure, so that metadata
+/// can be extracted along with them.
+pub fn extract_layer(
+    coords: &Coords,
+    normal: V3<i32>,
+    cart_threshold: f64,
+    layer: usize,
+    vacuum: f64,
+) -> Result<(Coords, Vec<usize>), Error>
+{Ok({
+    ensure!(vacuum > 0.0, "vacuum must be positive (got {})", vacuum);
+    let axis = require_simple_axis_normal(normal, coords.lattice())?;
+
+    let layers = match find_layers(coords, normal, cart_threshold)? {
+        Layers::PerUnitCell(layers) => layers,
+        Layers::NoDistinctLayers { .. } => bail!("the structure has no distinct layers"),
+        Layers::NoAtoms => bail!("the structure has no atoms"),
+    };
+    ensure!(
+        layer < layers.len(),
+        "cannot extract layer {} from a structure with {} layers", layer, layers.len(),
+    );
+
+    let indices = layers.groups[layer].clone();
+    let layer_coords = {
+        layers.partition_into_contiguous_layers(normal, coords.clone())
+            .into_iter().nth(layer).unwrap()
+    };
+
+    let unit_normal = coords.lattice().vectors()[axis].unit();
+    let mut carts = layer_coords.to_carts();
+    let heights = carts.iter().map(|v| dot(v, &unit_normal)).collect_vec();
+    let lo = heights.iter().cloned().fold(std::f64::INFINITY, f64::min);
+    let hi = heights.iter().cloned().fold(std::f64::NEG_INFINITY, f64::max);
+    for v in &mut carts {
+        *v -= unit_normal * (lo - 0.5 * vacuum);
+    }
+
+    let mut vectors = *coords.lattice().vectors();
+    vectors[axis] = unit_normal * (hi - lo + vacuum);
+
+    let coords = Coords::new(Lattice::from_vectors(&vectors), CoordsKind::Carts(carts));
+    (coords, indices)
+})}
+
+// -------------------------------------------------------------
+
 #[cfg(test)]
 #[deny(unused)]
 mod tests {
@@ -583,6 +681,28 @@ mod tests {
 
     fn scale(v: &[f64], fac: f64) -> Vec<f64> { v.iter().map(|&x| x * fac).collect() }
 
+    #[test]
+    fn simple_axis_normal() {
+        let lattice = Lattice::from([
+            [2.5, 0.0, 0.0],
+            [-1.25, 2.0, 0.0],
+            [0.0, 0.0, 10.0],
+        ]);
+        assert_eq!(require_simple_axis_normal(V3([0, 0, 1]), &lattice).unwrap(), 2);
+        assert!(require_simple_axis_normal(V3([1, 1, 0]), &lattice).is_err());
+
+        // tilt the third vector very slightly along the first.
+        let tilt = 10.0 * f64::tan(1e-3_f64.to_radians());
+        let lattice = Lattice::from([
+            [2.5, 0.0, 0.0],
+            [-1.25, 2.0, 0.0],
+            [tilt, 0.0, 10.0],
+        ]);
+        let message = require_simple_axis_normal(V3([0, 0, 1]), &lattice).unwrap_err().to_string();
+        assert!(message.contains("lattice vectors 1 and 3 meet at an angle of 89.999"), "{}", message);
+        assert!(message.contains("re-orienting"), "{}", message);
+    }
+
     #[test]
     fn layer_separation_eq_one() {
         // Perhaps surprisingly, fractional layer separations are
@@ -615,6 +735,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn bilayer_layer_seps() {
+        // an AB-stacked bilayer with a spacing of 3.4, in a cell with plenty of vacuum
+        let lattice = Lattice::from(&[
+            [2.46, 0.0, 0.0],
+            [-1.23, 2.46 * 0.75f64.sqrt(), 0.0],
+            [0.0, 0.0, 20.0],
+        ]);
+        let coords = Coords::new(lattice, CoordsKind::Fracs(vec![
+            [2.0/3.0, 1.0/3.0, 0.5 + 3.4 / 20.0],
+            [0.0, 0.0, 0.5],
+            [1.0/3.0, 2.0/3.0, 0.5],
+            [0.0, 0.0, 0.5 + 3.4 / 20.0],
+        ].envee()));
+
+        let layers = super::find_layers(&coords, V3([0, 0, 1]), 0.25).unwrap();
+        let (by_atom, seps) = layers.by_atom_with_layer_seps();
+        assert_eq!(by_atom, vec![1, 0, 0, 1]);
+        assert_close!(abs=1e-12, seps, vec![3.4]);
+
+        let layers = layers.per_unit_cell().unwrap();
+        assert_close!(abs=1e-12, layers.gaps.clone(), vec![3.4, 20.0 - 3.4]);
+        assert_eq!(layers.layer_seps().len(), layers.len() - 1);
+
+        // one layer has no separations
+        let monolayer = super::find_layers(&coords, V3([0, 0, 1]), 5.0).unwrap();
+        assert_eq!(monolayer.by_atom_with_layer_seps(), (vec![0; 4], vec![]));
+    }
+
     #[test]
     fn find_layers_impl() {
         let fracs = vec![
@@ -771,6 +920,85 @@ mod tests {
         );
     }
 
+    #[test]
+    fn layer_numbering_is_stable() {
+        let lattice = Lattice::orthorhombic(2.0, 2.0, 10.0);
+        // (find_layers is only checked if `overlapping == false`)
+        let check = |overlapping: bool, points: &[(char, f64)], frac_tol: f64, expected_by_atom: Vec<usize>| {
+            let (labels, zs): (Vec<_>, Vec<_>) = points.iter().cloned().unzip();
+            let coords = Coords::new(
+                lattice.clone(),
+                CoordsKind::Fracs(zs.iter().map(|&z| V3([0.0, 0.0, z])).collect()),
+            );
+            for _ in 0..8 {
+                let perm = Perm::random(points.len());
+                let coords = coords.clone().permuted_by(&perm);
+                let labels = labels.clone().permuted_by(&perm);
+                let expected_by_atom = expected_by_atom.clone().permuted_by(&perm);
+
+                let cart_tol = frac_tol * 10.0;
+                let layers = find_layers_with_labels(&labels, &coords, V3([0, 0, 1]), cart_tol).unwrap();
+                assert_eq!(layers.by_atom(), expected_by_atom);
+                if !overlapping {
+                    let layers = find_layers(&coords, V3([0, 0, 1]), cart_tol).unwrap();
+                    assert_eq!(layers.by_atom(), expected_by_atom);
+                }
+            }
+        };
+
+        // A has the lower mean position even though B ends first.
+        check(
+            true, &[('A', 0.10), ('A', 0.50), ('B', 0.35), ('B', 0.40)], 0.45,
+            vec![0, 0, 1, 1],
+        );
+        // Ties in the mean position are broken by label.
+        check(
+            true, &[('B', 0.30), ('B', 0.50), ('A', 0.35), ('A', 0.45)], 0.45,
+            vec![1, 1, 0, 0],
+        );
+        // Layers that don't overlap, including one that crosses the boundary.
+        check(
+            false, &[('A', 0.95), ('A', 0.02), ('B', 0.40), ('B', 0.45), ('C', 0.70), ('C', 0.72)], 0.1,
+            vec![0, 0, 1, 1, 2, 2],
+        );
+    }
+
+    #[test]
+    fn extract_layer() {
+        // AB-stacked graphite
+        let a = 2.46;
+        let c = 6.7;
+        let lattice = Lattice::from([
+            [a, 0.0, 0.0],
+            [-0.5 * a, 0.5 * f64::sqrt(3.0) * a, 0.0],
+            [0.0, 0.0, c],
+        ]);
+        let coords = Coords::new(lattice, CoordsKind::Fracs(vec![
+            [0.0, 0.0, 0.25],
+            [1.0/3.0, 2.0/3.0, 0.25],
+            [0.0, 0.0, 0.75],
+            [2.0/3.0, 1.0/3.0, 0.75],
+        ].envee()));
+
+        let vacuum = 12.0;
+        let (layer, indices) = super::extract_layer(&coords, V3([0, 0, 1]), 0.25 * c, 1, vacuum).unwrap();
+        assert_eq!(indices, vec![2, 3]);
+        assert_eq!(layer.num_atoms(), 2);
+
+        // the layer is flat, so the cell is entirely vacuum along the normal
+        assert_close!(abs=1e-10, layer.lattice().norms()[2], vacuum);
+        assert_close!(abs=1e-10, layer.lattice().norms()[0], a);
+        let original_carts = coords.to_carts();
+        for (&i, cart) in indices.iter().zip(layer.to_carts()) {
+            assert_close!(abs=1e-10, cart[2], 0.5 * vacuum);
+            assert_close!(abs=1e-10, cart[0], original_carts[i][0]);
+            assert_close!(abs=1e-10, cart[1], original_carts[i][1]);
+        }
+
+        assert!(super::extract_layer(&coords, V3([0, 0, 1]), 0.25 * c, 2, vacuum).is_err());
+        assert!(super::extract_layer(&coords, V3([1, 1, 0]), 0.25 * c, 0, vacuum).is_err());
+    }
+
     // FIXME: This also tests arbitrary directions
     #[test]
     fn overlapping_layers() {
@@ -1,8 +1,11 @@
 pub mod bonds;
+pub mod dimensionality;
 pub mod layer;
 pub mod supercell;
 pub mod find_perm;
 pub mod nearest_image;
+pub mod rdf;
+pub mod symmetrize;
 
 // these are tested but not yet part of public APIs
 #[cfg_attr(not(test), allow(unused))]
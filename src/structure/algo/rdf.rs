@@ -0,0 +1,121 @@
+/* ************************************************************************ **
+** This file is part of rsp2, and is licensed under EITHER the MIT license  **
+** or the Apache 2.0 license, at your option.                               **
+**                                                                          **
+**     http://www.apache.org/licenses/LICENSE-2.0                           **
+**     http://opensource.org/licenses/MIT                                   **
+**                                                                          **
+** Be aware that not all of rsp2 is provided under this permissive license, **
+** and that the project as a whole is licensed under the GPL 3.0.           **
+** ************************************************************************ */
+
+//! Radial distribution functions.
+
+use crate::Coords;
+use crate::bonds::FracBonds;
+
+use failure::Error;
+
+/// A histogram of interatomic distances, normalized as a radial distribution function `g(r)`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature="serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature="serde", serde(rename_all = "kebab-case"))]
+pub struct RadialDistribution {
+    /// Width of each bin.  Bin `i` covers distances in `[i * bin_width, (i + 1) * bin_width)`.
+    pub bin_width: f64,
+    /// The number of (ordered) pairs of atoms at a distance within each bin, per atom.
+    /// Each pair of distinct images counts separately.
+    pub counts: Vec<f64>,
+    /// The counts, normalized by those of an ideal gas of the same density, so that `g(r)`
+    /// tends to 1 at large `r` in a bulk material.
+    pub g: Vec<f64>,
+}
+
+/// Compute the radial distribution function up to `r_max`, using `num_bins` bins.
+///
+/// Neighbors are found using the same search as `FracBonds`, which can optionally be
+/// parallelized over atoms.  The output is identical regardless of `parallel`.
+pub fn radial_distribution(
+    coords: &Coords,
+    r_max: f64,
+    num_bins: usize,
+    parallel: bool,
+) -> Result<RadialDistribution, Error>
+{Ok({
+    ensure!(r_max > 0.0, "r_max must be positive (got {})", r_max);
+    ensure!(num_bins > 0, "num_bins must be positive");
+
+    let num_atoms = coords.num_atoms();
+    let bin_width = r_max / num_bins as f64;
+
+    let bonds = FracBonds::compute_maybe_parallel(coords, r_max, parallel)?;
+    let mut hist = vec![0usize; num_bins];
+    for bond in &bonds.to_cart_bonds(coords) {
+        // (distances exactly equal to r_max belong to the last bin)
+        let index = (bond.cart_vector.norm() / bin_width) as usize;
+        hist[usize::min(index, num_bins - 1)] += 1;
+    }
+
+    let density = num_atoms as f64 / coords.lattice().volume();
+    let counts: Vec<_> = hist.iter().map(|&n| n as f64 / num_atoms as f64).collect();
+    let g = {
+        counts.iter().enumerate()
+            .map(|(i, &count)| {
+                let (lo, hi) = (i as f64 * bin_width, (i + 1) as f64 * bin_width);
+                let shell_volume = 4.0 / 3.0 * std::f64::consts::PI * (hi.powi(3) - lo.powi(3));
+                count / (density * shell_volume)
+            })
+            .collect()
+    };
+    RadialDistribution { bin_width, counts, g }
+})}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoordsKind, Lattice};
+    use rsp2_array_types::V3;
+
+    #[test]
+    fn simple_cubic() {
+        let coords = Coords::new(Lattice::cubic(2.0), CoordsKind::Carts(vec![V3::zero()]));
+        let rdf = radial_distribution(&coords, 3.0, 30, false).unwrap();
+
+        // 6 nearest neighbors at 2.0, 12 at 2.83
+        assert_eq!(rdf.counts[20], 6.0);
+        assert_eq!(rdf.counts[28], 12.0);
+        assert_eq!(rdf.counts.iter().sum::<f64>(), 18.0);
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        // a few hundred atoms in a slightly distorted cell
+        let lattice = Lattice::from([
+            [12.0, 0.0, 0.0],
+            [1.0, 11.0, 0.0],
+            [0.5, -0.5, 13.0],
+        ]);
+        let fracs = {
+            (0..400)
+                .map(|i| {
+                    let x = i as f64;
+                    V3([
+                        (0.618_033_988 * x).fract(),
+                        (0.414_213_562 * x + 0.1 * f64::sin(x)).fract(),
+                        (0.732_050_808 * x).fract(),
+                    ])
+                })
+                .collect()
+        };
+        let coords = Coords::new(lattice, CoordsKind::Fracs(fracs));
+
+        assert_eq!(
+            FracBonds::compute_maybe_parallel(&coords, 4.0, true).unwrap(),
+            FracBonds::compute_maybe_parallel(&coords, 4.0, false).unwrap(),
+        );
+        let serial = radial_distribution(&coords, 6.0, 60, false).unwrap();
+        let parallel = radial_distribution(&coords, 6.0, 60, true).unwrap();
+        assert_eq!(serial, parallel);
+        assert!(serial.counts.iter().sum::<f64>() > 0.0);
+    }
+}
@@ -23,6 +23,28 @@ pub struct Builder {
     // add a lattice point to the entire output.  This will be reflected
     // in `supercell_indices()` as well.
     offset: V3<i32>,
+    order: AtomOrder,
+}
+
+/// Ordering of the atoms in a supercell.
+///
+/// Only `Unspecified` is free to change between versions of rsp2.  Every other
+/// variant is a documented convention that can be relied upon, e.g. to compare
+/// files produced by different runs without having to permute them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AtomOrder {
+    /// Whatever rsp2 finds convenient.  This is the default.
+    ///
+    /// (it currently coincides with `PrimitiveMajor`, but that is not a promise)
+    Unspecified,
+    /// All images of primitive atom 0, followed by all images of primitive atom 1,
+    /// and so on.  The images of each atom are sorted lexicographically by their
+    /// cell indices `[a, b, c]`, so that `c` varies fastest.
+    PrimitiveMajor,
+}
+
+impl Default for AtomOrder {
+    fn default() -> Self { AtomOrder::Unspecified }
 }
 
 pub fn diagonal(dims: [u32; 3]) -> Builder {
@@ -32,6 +54,7 @@ pub fn diagonal(dims: [u32; 3]) -> Builder {
     Builder {
         diagonal: dims,
         offset: V3([0; 3]),
+        order: AtomOrder::default(),
     }
 }
 
@@ -42,6 +65,7 @@ pub fn centered_diagonal(extra_images: [u32; 3]) -> Builder {
     Builder {
         diagonal: (extra_images * 2 + V3([1; 3])).0,
         offset: extra_images.map(|x| -(x as i32)),
+        order: AtomOrder::default(),
     }
 }
 
@@ -55,6 +79,11 @@ impl Builder {
         self.offset = center - natural_center; self
     }
 
+    /// Request a specific ordering of the atoms in the output.
+    pub fn atom_order(mut self, order: AtomOrder) -> Builder {
+        self.order = order; self
+    }
+
     pub fn build(&self, coords: &Coords) -> (Coords, SupercellToken) {
         _make_supercell(self.clone(), coords)
     }
@@ -65,9 +94,9 @@ impl Builder {
 impl Builder {
     // convert into a data structure with precomputed info for general supercell matrices
     fn into_sc_token(self, num_primitive_atoms: usize) -> SupercellToken {
-        let Builder { offset, diagonal: periods } = self;
+        let Builder { offset, diagonal: periods, order } = self;
         let integer_lattice = Lattice::diagonal(&V3(periods).map(|x| x as f64));
-        SupercellToken { offset, periods, integer_lattice, num_primitive_atoms }
+        SupercellToken { offset, periods, integer_lattice, num_primitive_atoms, order }
     }
 }
 
@@ -75,6 +104,10 @@ impl Builder {
 
 // !!! This function affects the supercell convention !!! (SUPERCELL-CONV)
 // When modifying it, you must modify all functions that have this label.
+//
+// The convention implemented by the other functions with this label is the one produced
+// for `AtomOrder::Unspecified`, which currently coincides with `AtomOrder::PrimitiveMajor`.
+// If the former is ever changed, those functions will need to match on `sc.order`.
 fn _make_supercell(builder: Builder, coords: &Coords) -> (Coords, SupercellToken)
 {
     let Coords { lattice, coords } = coords;
@@ -83,13 +116,34 @@ fn _make_supercell(builder: Builder, coords: &Coords) -> (Coords, SupercellToken
     // works for general supercell matrices
     let sc = builder.into_sc_token(coords.len());
 
-    let image_offset_carts = image_lattice_vecs(sc.periods, sc.offset, lattice);
-
     let mut new_carts = Vec::with_capacity(sc.num_supercell_atoms());
-    for atom_cart in coords.to_carts(&lattice) {
-        let old_len = new_carts.len();
-        new_carts.extend_from_slice(&image_offset_carts);
-        crate::util::translate_mut_n3_3(&mut new_carts[old_len..], &atom_cart);
+    match sc.order {
+        AtomOrder::Unspecified => {
+            let image_offset_carts = image_lattice_vecs(sc.periods, sc.offset, lattice);
+
+            for atom_cart in coords.to_carts(&lattice) {
+                let old_len = new_carts.len();
+                new_carts.extend_from_slice(&image_offset_carts);
+                crate::util::translate_mut_n3_3(&mut new_carts[old_len..], &atom_cart);
+            }
+        },
+
+        // Deliberately spelled out without the help of the `image_*` functions,
+        // so that this can't change along with the unspecified order.
+        AtomOrder::PrimitiveMajor => {
+            let [na, nb, nc] = sc.periods;
+            for atom_cart in coords.to_carts(lattice) {
+                for ia in 0..na {
+                    for ib in 0..nb {
+                        for ic in 0..nc {
+                            let lattice_point = V3([ia, ib, ic]).map(|x| x as i32) + sc.offset;
+                            let image_offset_cart = lattice_point.map(|x| x as f64) * lattice;
+                            new_carts.push(image_offset_cart + atom_cart);
+                        }
+                    }
+                }
+            }
+        },
     }
 
     let coords = Coords::new(
@@ -103,8 +157,9 @@ fn _make_supercell(builder: Builder, coords: &Coords) -> (Coords, SupercellToken
 /// Contains enough information to deconstruct a supercell produced by this library.
 ///
 /// **The order of the atoms in the supercell is unspecified** and may change with
-/// the needs of rsp2. You must use the methods on this type if you need to convert
-/// between index representations, or to work in the subgroup of cell images.
+/// the needs of rsp2, unless a specific `AtomOrder` was requested from the `Builder`.
+/// You must use the methods on this type if you need to convert between index
+/// representations, or to work in the subgroup of cell images.
 ///
 /// It provides a variety of methods for converting between various forms of indices,
 /// summarized below along with the terms that frequently appear in method names:
@@ -135,13 +190,18 @@ pub struct SupercellToken {
     num_primitive_atoms: usize,
     // supercell in units of primitive cell vectors.  Elements are integral
     integer_lattice: Lattice,
+    order: AtomOrder,
 }
 
 #[derive(Debug, Fail)]
-#[fail(display = "Suspiciously large movement between supercell images: {:e}", magnitude)]
+#[fail(
+    display = "Suspiciously large movement between supercell images: {:e} (validation radius: {:e})",
+    magnitude, validation_radius,
+)]
 pub struct BigDisplacement {
     backtrace: failure::Backtrace,
     magnitude: f64,
+    validation_radius: f64,
 }
 
 pub type OwnedMetas<'a, T> = std::vec::Drain<'a, T>;
@@ -158,6 +218,12 @@ impl SupercellToken {
         self.periods.iter().product::<u32>() as _
     }
 
+    /// The ordering convention requested when the supercell was built.
+    #[inline]
+    pub fn atom_order(&self) -> AtomOrder {
+        self.order
+    }
+
     #[inline]
     pub fn num_primitive_atoms(&self) -> usize {
         self.num_primitive_atoms
@@ -202,6 +268,10 @@ impl SupercellToken {
     /// * Reordering of atoms
     /// * Wrapping of positions (FIXME unnecessary limitation)
     /// * Images of an atom did not move by equal amounts (within `validation_radius`)
+    ///
+    /// More precisely, the check fails if, along any cartesian axis, the images of an atom
+    /// span a range wider than `2 * validation_radius`.  Large cells that are deformed
+    /// by large amounts may need a larger radius.
     #[inline]
     pub fn deconstruct(&self, validation_radius: f64, coords: Coords)
     -> Result<Coords, BigDisplacement>
@@ -212,7 +282,7 @@ impl SupercellToken {
         );
 
         let num_cells = self.num_cells();
-        let SupercellToken { periods, offset, ref integer_lattice, num_primitive_atoms } = *self;
+        let SupercellToken { periods, offset, ref integer_lattice, num_primitive_atoms, order: _ } = *self;
         let Coords { lattice, coords } = coords;
 
         let primitive_lattice = integer_lattice.inverse_matrix() * &lattice;
@@ -252,7 +322,7 @@ impl SupercellToken {
                     if max - min > 2.0 * validation_radius {
                         let backtrace = failure::Backtrace::new();
                         let magnitude = max - min;
-                        return Err(BigDisplacement { backtrace, magnitude });
+                        return Err(BigDisplacement { backtrace, magnitude, validation_radius });
                     }
 
                     let sum = this_axis().sum::<f64>();
@@ -475,6 +545,7 @@ mod tests {
     use rsp2_soa_ops::{Permute, Perm};
     use crate::{Coords, CoordsKind, Lattice};
     use rsp2_array_types::{V3, Envee};
+    use slice_of_array::prelude::*;
 
     use rand::Rng;
 
@@ -529,6 +600,28 @@ mod tests {
         assert!(sc_token.deconstruct(1e-10, supercell.clone()).is_err());
     }
 
+    #[test]
+    fn deconstruct_validation_radius() {
+        let coords = CoordsKind::Fracs(vec![[0.0, 0.0, 0.0]].envee());
+        let original = Coords::new(Lattice::cubic(10.0), coords);
+        let (supercell, sc_token) = crate::supercell::diagonal([2, 1, 1]).build(&original);
+
+        let radius = 0.05;
+        let displaced = |distance: f64| {
+            let mut carts = supercell.to_carts();
+            carts[1][1] += distance;
+            supercell.clone().with_carts(carts)
+        };
+
+        // images may span up to twice the radius
+        assert!(sc_token.deconstruct(radius, displaced(0.099)).is_ok());
+
+        let err = sc_token.deconstruct(radius, displaced(0.101)).unwrap_err();
+        assert_close!(err.magnitude, 0.101);
+        assert_eq!(err.validation_radius, radius);
+        assert!(err.to_string().contains(&format!("{:e}", radius)));
+    }
+
     #[test]
     fn test_centered_diagonal_supercell() {
         // nondiagonal lattice so that matrix multiplication order matters
@@ -564,6 +657,67 @@ mod tests {
         assert!(crate::util::eq_unordered_n3(&expected_carts, &actual_carts), "{:?} {:?}", expected_carts, actual_carts);
     }
 
+    #[test]
+    fn primitive_major_order() {
+        use crate::supercell::AtomOrder;
+
+        let original = Coords::new(Lattice::eye(), CoordsKind::Carts(vec![
+            [0.0, 0.0, 0.0],
+            [0.5, 0.5, 0.5],
+        ].envee()));
+        let (supercell, sc_token) = {
+            crate::supercell::diagonal([2, 2, 1])
+                .atom_order(AtomOrder::PrimitiveMajor)
+                .build(&original)
+        };
+        assert_eq!(sc_token.atom_order(), AtomOrder::PrimitiveMajor);
+
+        // This order is a promise; do not change this test!
+        assert_eq!(supercell.to_carts(), vec![
+            [0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+            [0.5, 0.5, 0.5], [0.5, 1.5, 0.5], [1.5, 0.5, 0.5], [1.5, 1.5, 0.5],
+        ].envee());
+        assert_eq!(sc_token.atom_primitive_atoms(), vec![0, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(sc_token.atom_cells(), vec![
+            [0, 0, 0], [0, 1, 0], [1, 0, 0], [1, 1, 0],
+            [0, 0, 0], [0, 1, 0], [1, 0, 0], [1, 1, 0],
+        ]);
+    }
+
+    #[test]
+    fn primitive_major_order_matches_token() {
+        use crate::supercell::AtomOrder;
+
+        let mut rng = rand::thread_rng();
+        let original = Coords::new(
+            Lattice::random_uniform(3.0),
+            CoordsKind::Fracs((0..3).map(|_| V3::from_fn(|_| rng.gen::<f64>())).collect()),
+        );
+        let builder = crate::supercell::centered_diagonal([1, 0, 2]);
+        let (supercell, sc_token) = builder.clone().atom_order(AtomOrder::PrimitiveMajor).build(&original);
+        let (unspecified, _) = builder.build(&original);
+
+        // The token's index conventions describe the requested order...
+        let prim_carts = original.to_carts();
+        let super_carts = supercell.to_carts();
+        let prims = sc_token.atom_primitive_atoms();
+        let cells = sc_token.atom_cells();
+        let lattice_points = sc_token.atom_lattice_points();
+        for atom in 0..supercell.num_atoms() {
+            assert_eq!(sc_token.atom_from_cell(prims[atom], cells[atom]), atom);
+            let expected = prim_carts[prims[atom]] + lattice_points[atom].map(|x| x as f64) * original.lattice();
+            assert_close!(abs=1e-10, super_carts[atom].0, expected.0);
+        }
+        // ...and primitive-major order sorts by primitive atom, then lexicographically by cell.
+        let keys = izip!(&prims, &cells).collect::<Vec<_>>();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+
+        // (for now, the unspecified order happens to be the same)
+        assert_eq!(unspecified.to_carts(), super_carts);
+        let deconstructed = sc_token.deconstruct(1e-10, supercell).unwrap().to_carts();
+        assert_close!(abs=1e-10, deconstructed.flat(), prim_carts.flat());
+    }
+
     #[test]
     fn cell_index_conversions() {
         let sc_token = crate::supercell::diagonal([2, 5, 3]).into_sc_token(7);
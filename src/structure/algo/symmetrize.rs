@@ -0,0 +1,146 @@
+/* ************************************************************************ **
+** This file is part of rsp2, and is licensed under EITHER the MIT license  **
+** or the Apache 2.0 license, at your option.                               **
+**                                                                          **
+**     http://www.apache.org/licenses/LICENSE-2.0                           **
+**     http://opensource.org/licenses/MIT                                   **
+**                                                                          **
+** Be aware that not all of rsp2 is provided under this permissive license, **
+** and that the project as a whole is licensed under the GPL 3.0.           **
+** ************************************************************************ */
+
+//! Measuring and removing small violations of a spacegroup's symmetry.
+
+use crate::{Coords, CoordsKind, CartOp};
+use super::find_perm::spacegroup_deperms;
+
+use rsp2_array_types::V3;
+use rsp2_soa_ops::Permute;
+
+use failure::Error;
+
+/// Output of `symmetrize_structure`.
+#[derive(Debug, Clone)]
+pub struct Symmetrized {
+    /// The input structure, with each site averaged over its images under the spacegroup.
+    ///
+    /// The lattice is left unchanged.
+    pub coords: Coords,
+
+    /// For each operator, the largest cartesian distance between a site of the input
+    /// structure and the image of the site that the operator maps onto it.
+    pub deviations: Vec<f64>,
+}
+
+impl Symmetrized {
+    /// The largest deviation over all operators.
+    ///
+    /// This measures how badly the input structure broke the symmetry.
+    pub fn max_deviation(&self) -> f64
+    { self.deviations.iter().cloned().fold(0.0, f64::max) }
+}
+
+/// Project a structure onto the subspace that is invariant under a spacegroup.
+///
+/// Each site is replaced with the average of the images of the sites that each operator
+/// maps onto it (choosing the image nearest to the original site).  Provided that the
+/// operators are exact symmetries of the lattice, the output is invariant under `ops`
+/// up to rounding error.
+///
+/// `ops` and `tol` have the same requirements as in `spacegroup_deperms`.  In particular,
+/// `tol` must be large enough to tolerate the symmetry breaking that is being removed.
+pub fn symmetrize_structure(
+    coords: &Coords,
+    ops: &[CartOp],
+    tol: f64,
+) -> Result<Symmetrized, Error>
+{Ok({
+    let deperms = spacegroup_deperms(coords, ops, tol)?;
+    let lattice = coords.lattice();
+    let fracs = coords.to_fracs();
+
+    let mut total_shifts = vec![V3::zero(); fracs.len()];
+    let mut deviations = Vec::with_capacity(ops.len());
+    for (op, deperm) in izip!(ops, &deperms) {
+        // images[i] is the image of the site that `op` maps onto site i
+        let images = op.transform_fracs(lattice, &fracs).permuted_by(deperm);
+
+        let mut deviation = 0.0;
+        for (shift, &image, &frac) in izip!(&mut total_shifts, &images, &fracs) {
+            let diff = (image - frac).map(|x| x - x.round());
+            deviation = f64::max(deviation, (diff * lattice).norm());
+            *shift += diff;
+        }
+        deviations.push(deviation);
+    }
+
+    let num_ops = ops.len() as f64;
+    let fracs = {
+        izip!(fracs, total_shifts)
+            .map(|(frac, shift)| frac + shift / num_ops)
+            .collect()
+    };
+    let coords = Coords::new(lattice.clone(), CoordsKind::Fracs(fracs));
+    Symmetrized { coords, deviations }
+})}
+
+#[cfg(test)]
+#[deny(unused)]
+mod tests {
+    use super::*;
+    use crate::Lattice;
+
+    use rsp2_array_types::{mat, M33};
+
+    #[test]
+    fn perturbed_graphene() {
+        let half_r3 = 0.5 * f64::sqrt(3.0);
+        let lattice = Lattice::new(&mat::from_array([
+            [ 2.46,           0.0,  0.0],
+            [-1.23, 2.46 * half_r3,  0.0],
+            [  0.0,           0.0, 10.0],
+        ]));
+        let ideal_fracs = vec![
+            V3([1./3., 2./3., 0.5]),
+            V3([2./3., 1./3., 0.5]),
+        ];
+        let ideal = Coords::new(lattice.clone(), CoordsKind::Fracs(ideal_fracs.clone()));
+
+        // D6h, generated by a sixfold rotation about z and a mirror plane normal to z
+        let c6: M33 = mat::from_array([
+            [    0.5, -half_r3, 0.0],
+            [half_r3,      0.5, 0.0],
+            [    0.0,      0.0, 1.0],
+        ]);
+        let mirror: M33 = mat::from_array([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]]);
+        let mut ops = vec![];
+        let mut rot = M33::eye();
+        for _ in 0..6 {
+            ops.push(CartOp::new(&rot, V3::zero()));
+            ops.push(CartOp::new(&(mirror * rot), V3::zero()));
+            rot = c6 * rot;
+        }
+
+        let mut perturbed = ideal.clone();
+        {
+            let carts = perturbed.carts_mut();
+            carts[0] += V3([1e-3, -2e-3, 5e-4]);
+            carts[1] += V3([-3e-4, 1e-3, 2e-3]);
+        }
+
+        let symmetrized = symmetrize_structure(&perturbed, &ops, 1e-2).unwrap();
+        assert!(symmetrized.max_deviation() > 1e-3);
+        assert!(symmetrized.max_deviation() < 1e-2);
+        for (actual, expected) in izip!(symmetrized.coords.to_fracs(), &ideal_fracs) {
+            assert_close!(abs=1e-10, actual.0, expected.0);
+        }
+
+        // the output is a fixed point
+        let again = symmetrize_structure(&symmetrized.coords, &ops, 1e-2).unwrap();
+        assert!(again.max_deviation() < 1e-10);
+
+        // ...as is the ideal structure
+        let ideal_out = symmetrize_structure(&ideal, &ops, 1e-2).unwrap();
+        assert!(ideal_out.max_deviation() < 1e-10);
+    }
+}
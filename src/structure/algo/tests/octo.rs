@@ -553,4 +553,32 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn group_tree_par_matches_serial()
+    {
+        use crate::algo::group::GroupTree;
+
+        let tree = GroupTree::from_all_members(
+            ROTATION_DATA.members.clone(),
+            ROTATION_DATA.compose,
+        );
+        let compute = |_: usize, g: &IntRot| Ok::<_, usize>(vertex_perm_from_rot(g));
+        let compose = |a: &Perm, b: &Perm| Ok::<_, usize>(VERTEX_PERM_OP_DATA.compose(a, b));
+
+        assert_eq!(
+            tree.par_try_compute_homomorphism(compute, compose),
+            tree.try_compute_homomorphism(compute, compose),
+        );
+
+        // both report the first failing generator
+        let failing = |index: usize, g: &IntRot| match index {
+            0 => compute(index, g),
+            _ => Err(index),
+        };
+        assert_eq!(
+            tree.par_try_compute_homomorphism(failing, compose),
+            tree.try_compute_homomorphism(failing, compose),
+        );
+    }
 }
@@ -147,6 +147,14 @@ impl Lattice {
     pub fn plane_spacing(&self, miller: V3<i32>) -> f64
     { self.miller_to_recip_cart(miller).norm().recip() }
 
+    /// Get the distance between the opposite faces of the cell along each lattice vector.
+    ///
+    /// This is the spacing of the `(100)`, `(010)`, and `(001)` planes, and is the quantity
+    /// that matters when asking whether a sphere of some radius fits inside the cell.
+    /// For a skewed cell, it is smaller than the length of the corresponding lattice vector.
+    pub fn perpendicular_widths(&self) -> V3
+    { V3::from_fn(|axis| self.plane_spacing(V3::axis_unit(axis))) }
+
     #[inline(always)]
     fn miller_to_recip_cart(&self, miller: V3<i32>) -> V3 {
         assert_ne!(miller, V3::zero());
@@ -486,6 +494,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn perpendicular_widths() {
+        // orthogonal: widths are the lengths
+        let lattice = Lattice::orthorhombic(2.0, 3.0, 5.0);
+        assert_close!(lattice.perpendicular_widths().0, [2.0, 3.0, 5.0]);
+
+        // sheared along a: the a-b faces are still 5 apart, but the others grow closer
+        let lattice = Lattice::from(&[
+            [2.0, 0.0, 0.0],
+            [2.0, 2.0, 0.0],
+            [0.0, 0.0, 5.0],
+        ]);
+        let widths = lattice.perpendicular_widths();
+        let lengths = lattice.norms();
+        assert_close!(widths.0, [f64::sqrt(2.0), 2.0, 5.0]);
+        assert!(widths[0] < lengths[0]);
+        assert!(widths[1] < lengths[1]);
+
+        // a sphere of diameter `min(widths)` fits inside the cell, so the volume is at least
+        // the product of widths (with equality only for orthogonal cells)
+        for _ in 0..20 {
+            let lattice = Lattice::random_uniform(10.0);
+            let widths = lattice.perpendicular_widths();
+            for k in 0..3 {
+                assert!(widths[k] <= lattice.norms()[k] * (1.0 + 1e-10));
+            }
+            assert!(widths[0] * widths[1] * widths[2] <= lattice.volume() * (1.0 + 1e-10));
+        }
+    }
+
     #[test]
     fn rotation_to_lower_triangular()  {
         for _ in 0..30 {
@@ -12,6 +12,9 @@
 use crate::{Lattice, CoordsKind, Missing};
 use rsp2_soa_ops::{Perm, Permute};
 use rsp2_soa_ops::{Part, Partition, Unlabeled};
+use rsp2_soa_ops::helper::composite_perm_for_part_lifo;
+use crate::Element;
+use std::collections::BTreeMap;
 use rsp2_array_types::{M33, V3, Unvee};
 pub use failure::Error as Error;
 
@@ -113,6 +116,15 @@ impl Coords {
         let lattice = &Lattice::diagonal(scale) * &self.lattice;
         self.set_lattice(&lattice);
     }
+
+    /// Deform the lattice by a cartesian strain, so that each lattice vector `a`
+    /// becomes `(I + strain) a`.
+    ///
+    /// The strain need not be symmetric; an antisymmetric part produces a rotation
+    /// (to first order).  Undo it with the strain `inv(I + strain) - I`.
+    pub fn apply_strain(&mut self, strain: &M33) {
+        self.transform(&(M33::eye() + strain));
+    }
 }
 
 //---------------------------------------
@@ -454,6 +466,50 @@ fn dumb_nearest_distance(
         .unwrap()
 }
 
+//---------------------------------------
+
+/// # Partitioning by element
+impl Coords {
+    /// Split the structure into one sub-structure per element.
+    ///
+    /// Within each sub-structure, atoms appear in their original relative order.
+    /// The `Part` is also returned so that the pieces can be put back together with
+    /// `Coords::from_element_partitions`.
+    pub fn partition_by_element(self, elements: &[Element]) -> (BTreeMap<Element, Coords>, Part<Element>)
+    {
+        assert_eq!(elements.len(), self.num_atoms(), "wrong number of elements");
+
+        let part = Part::from_ord_keys(elements.iter().cloned());
+        let map = self.into_partitions(&part).into_iter().collect();
+        (map, part)
+    }
+
+    /// Recombine the output of `partition_by_element`, restoring the original order of the atoms.
+    ///
+    /// The lattices of the pieces are ignored in favor of `lattice`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an element of `part` has no piece, or if a piece has the wrong number of atoms.
+    pub fn from_element_partitions(
+        lattice: &Lattice,
+        mut pieces: BTreeMap<Element, Coords>,
+        part: &Part<Element>,
+    ) -> Coords
+    {
+        // Concatenating the pieces in reverse produces the order
+        // that `Vec`'s `Partition` impl drains them from.
+        let mut fracs = vec![];
+        for (element, indices) in izip!(part.region_keys(), part.region_indices()).rev() {
+            let piece = pieces.remove(element).expect("missing element in partitions");
+            assert_eq!(piece.num_atoms(), indices.len(), "partition has wrong number of atoms");
+            fracs.extend(piece.to_fracs());
+        }
+        let lifo_perm = composite_perm_for_part_lifo(part);
+        Coords::new(lattice.clone(), CoordsKind::Fracs(fracs)).permuted_by(&lifo_perm.inverted())
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // trait impls
 
@@ -557,6 +613,59 @@ mod compiletest {
         let _ = coords;
     }
 
+    #[test]
+    fn apply_strain() {
+        let lattice = Lattice::from([
+            [2.5, 0.0, 0.0],
+            [-1.25, 2.1, 0.0],
+            [0.0, 0.0, 10.0],
+        ]);
+        let fracs = vec![[0.0, 0.0, 0.5], [0.25, 0.75, 0.5]].envee();
+        let original = Coords::new(lattice, CoordsKind::Fracs(fracs.clone()));
+
+        // simple shear: x += 0.1 * y
+        let strain = M33::from_fn(|r, c| if (r, c) == (0, 1) { 0.1 } else { 0.0 });
+        let mut coords = original.clone();
+        coords.apply_strain(&strain);
+        assert_eq!(coords.to_fracs(), fracs);
+        assert_close!(abs=1e-12, coords.lattice().vectors()[1].0, [-1.25 + 0.21, 2.1, 0.0]);
+        assert_close!(abs=1e-12, coords.lattice().vectors()[2].0, [0.0, 0.0, 10.0]);
+
+        // now a general strain, and its inverse
+        let strain = M33::from_fn(|r, c| 1e-2 * (1.0 + r as f64 - 0.5 * c as f64));
+        let mut coords = original.clone();
+        coords.apply_strain(&strain);
+        assert!(coords.lattice() != original.lattice());
+        coords.apply_strain(&((M33::eye() + &strain).inv() - M33::eye()));
+        assert_close!(abs=1e-12, coords.lattice().matrix().unvee(), original.lattice().matrix().unvee());
+        assert_eq!(coords.to_fracs(), fracs);
+    }
+
+    #[test]
+    fn partition_by_element() {
+        use crate::consts::{CARBON, HYDROGEN};
+
+        let lattice = Lattice::diagonal(&[10.0, 10.0, 10.0]);
+        let fracs = vec![
+            [0.1, 0.0, 0.0],
+            [0.2, 0.0, 0.0],
+            [0.3, 0.0, 0.0],
+            [0.4, 0.0, 0.0],
+            [0.5, 0.0, 0.0],
+        ].envee();
+        let elements = vec![CARBON, HYDROGEN, CARBON, HYDROGEN, HYDROGEN];
+        let original = Coords::new(lattice.clone(), CoordsKind::Fracs(fracs.clone()));
+
+        let (pieces, part) = original.clone().partition_by_element(&elements);
+        assert_eq!(pieces.keys().cloned().collect::<Vec<_>>(), vec![HYDROGEN, CARBON]);
+        assert_eq!(pieces[&CARBON].to_fracs(), vec![fracs[0], fracs[2]]);
+        assert_eq!(pieces[&HYDROGEN].to_fracs(), vec![fracs[1], fracs[3], fracs[4]]);
+
+        let recombined = Coords::from_element_partitions(&lattice, pieces, &part);
+        assert_eq!(recombined.to_fracs(), fracs);
+        assert_eq!(recombined.lattice(), original.lattice());
+    }
+
     #[test]
     #[cfg(feature = "serde-support")]
     fn serde() {
@@ -74,6 +74,13 @@ impl Element {
 
     pub fn name(&self) -> &'static str
     { NUMBER_TO_AMERICAN[&self.0] }
+
+    /// The standard atomic weight, in amu.
+    ///
+    /// `None` for elements that have no stable isotopes (and thus no standard
+    /// atomic weight), and for all elements beyond bismuth.
+    pub fn mass(&self) -> Option<f64>
+    { NUMBER_TO_MASS.get(&self.0).cloned() }
 }
 
 impl fmt::Display for Element {
@@ -240,6 +247,96 @@ const SPECIAL_NAMES: &'static [(u16, &'static str, &'static str)] = &[
     (112, "Cn", "Copernicium"),
 ];
 
+// Standard atomic weights, as published by IUPAC in 2007.
+//
+// (these are the values that phonopy uses, and changing them would change
+//  the frequencies computed by rsp2)
+const STANDARD_ATOMIC_WEIGHTS: &'static [(u16, f64)] = &[
+    (001, 1.00794),
+    (002, 4.002602),
+    (003, 6.941),
+    (004, 9.012182),
+    (005, 10.811),
+    (006, 12.0107),
+    (007, 14.0067),
+    (008, 15.9994),
+    (009, 18.9984032),
+    (010, 20.1797),
+    (011, 22.98976928),
+    (012, 24.3050),
+    (013, 26.9815386),
+    (014, 28.0855),
+    (015, 30.973762),
+    (016, 32.065),
+    (017, 35.453),
+    (018, 39.948),
+    (019, 39.0983),
+    (020, 40.078),
+    (021, 44.955912),
+    (022, 47.867),
+    (023, 50.9415),
+    (024, 51.9961),
+    (025, 54.938045),
+    (026, 55.845),
+    (027, 58.933195),
+    (028, 58.6934),
+    (029, 63.546),
+    (030, 65.38),
+    (031, 69.723),
+    (032, 72.64),
+    (033, 74.92160),
+    (034, 78.96),
+    (035, 79.904),
+    (036, 83.798),
+    (037, 85.4678),
+    (038, 87.62),
+    (039, 88.90585),
+    (040, 91.224),
+    (041, 92.90638),
+    (042, 95.96),
+    // (043) Technetium has no stable isotopes
+    (044, 101.07),
+    (045, 102.90550),
+    (046, 106.42),
+    (047, 107.8682),
+    (048, 112.411),
+    (049, 114.818),
+    (050, 118.710),
+    (051, 121.760),
+    (052, 127.60),
+    (053, 126.90447),
+    (054, 131.293),
+    (055, 132.9054519),
+    (056, 137.327),
+    (057, 138.90547),
+    (058, 140.116),
+    (059, 140.90765),
+    (060, 144.242),
+    // (061) Promethium has no stable isotopes
+    (062, 150.36),
+    (063, 151.964),
+    (064, 157.25),
+    (065, 158.92535),
+    (066, 162.500),
+    (067, 164.93032),
+    (068, 167.259),
+    (069, 168.93421),
+    (070, 173.054),
+    (071, 174.9668),
+    (072, 178.49),
+    (073, 180.94788),
+    (074, 183.84),
+    (075, 186.207),
+    (076, 190.23),
+    (077, 192.217),
+    (078, 195.084),
+    (079, 196.966569),
+    (080, 200.59),
+    (081, 204.3833),
+    (082, 207.2),
+    (083, 208.98040),
+];
+
 lazy_static!{
     static ref SHORT_TO_NUMBER: HashMap<&'static str, u16> =
     {
@@ -267,6 +364,11 @@ lazy_static!{
             .collect()
     };
 
+    static ref NUMBER_TO_MASS: HashMap<u16, f64> =
+    {
+        STANDARD_ATOMIC_WEIGHTS.iter().cloned().collect()
+    };
+
     static ref DWIM_STR_TO_NUMBER: DwimMap =
     {
         let mut map = DwimMap::new();
@@ -440,3 +542,38 @@ define_consts! {
         pub const COPERNICIUM: Element = Element(112);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atomic_numbers() {
+        for &(n, symbol) in &[(1, "H"), (6, "C"), (8, "O"), (42, "Mo"), (83, "Bi")] {
+            let element = Element::from_atomic_number(n).unwrap();
+            assert_eq!(element.atomic_number(), n);
+            assert_eq!(element.symbol(), symbol);
+        }
+        assert_eq!(Element::from_atomic_number(6), Some(Element::CARBON));
+        assert_eq!(Element::from_atomic_number(0), None);
+        assert_eq!(Element::from_atomic_number(MAX_ATOMIC_NUMBER + 1), None);
+    }
+
+    #[test]
+    fn masses() {
+        // these are the values rsp2 has always used for hydrocarbons
+        assert_eq!(Element::HYDROGEN.mass(), Some(1.00794));
+        assert_eq!(Element::CARBON.mass(), Some(12.0107));
+        assert_eq!(Element::NITROGEN.mass(), Some(14.0067));
+        assert_eq!(Element::MOLYBDENUM.mass(), Some(95.96));
+        assert_eq!(Element::SULFUR.mass(), Some(32.065));
+
+        assert_eq!(Element::TECHNETIUM.mass(), None);
+        assert_eq!(Element::URANIUM.mass(), None);
+
+        // sorted, and increasing in mass (except for a few famous inversions)
+        let inversions = STANDARD_ATOMIC_WEIGHTS.windows(2).filter(|w| w[0].1 > w[1].1).count();
+        assert!(STANDARD_ATOMIC_WEIGHTS.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(inversions, 3); // Ar/K, Co/Ni, Te/I
+    }
+}
@@ -37,6 +37,9 @@ pub use crate::algo::bonds;
 pub use crate::algo::supercell;
 pub use crate::algo::find_perm;
 pub use crate::algo::layer;
+pub use crate::algo::dimensionality;
+pub use crate::algo::rdf;
+pub use crate::algo::symmetrize;
 
 mod core;
 mod algo;
@@ -98,15 +98,24 @@ impl Default for CartOp {
 pub struct LatticeSymmetryError {
     lattice: Lattice,
     cart_rot: M33,
+    /// The rotation in fractional units, which was expected to be integral.
+    frac_rot: M33,
+    /// Row and column of the entry of `frac_rot` furthest from an integer.
+    entry: (usize, usize),
+    /// Distance of that entry from the nearest integer.
+    residual: f64,
     #[cause]
     cause: crate::IntPrecisionError,
 }
 
 impl fmt::Display for LatticeSymmetryError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (r, c) = self.entry;
         write!(
-            f, "{:?} is not in the point group of the lattice {:?}",
+            f, "{:?} is not in the point group of the lattice {:?} \
+            (in fractional units, entry [{}][{}] = {} is off from an integer by {:e})",
             self.cart_rot, self.lattice.matrix(),
+            r, c, self.frac_rot[r][c], self.residual,
         )
     }
 }
@@ -145,12 +154,24 @@ impl IntRot {
 
     fn from_cart_t(lattice: &Lattice, cart_t: &M33) -> Result<IntRot, LatticeSymmetryError>
     {
-        Self::from_frac_t(&frac_t_from_cart_t(lattice, *cart_t))
+        let frac_t = frac_t_from_cart_t(lattice, *cart_t);
+        Self::from_frac_t(&frac_t)
             .map_err(|cause| {
+                let frac_rot = frac_t.t();
+                let residual_at = |(r, c): (usize, usize)| {
+                    let x: f64 = frac_rot[r][c];
+                    (x - x.round()).abs()
+                };
+                let entry = {
+                    iproduct!(0..3, 0..3)
+                        .max_by(|&a, &b| residual_at(a).partial_cmp(&residual_at(b)).unwrap())
+                        .unwrap()
+                };
                 LatticeSymmetryError {
                     lattice: lattice.clone(),
                     cart_rot: cart_t.t(),
-                    cause,
+                    residual: residual_at(entry),
+                    frac_rot, entry, cause,
                 }
             })
     }
@@ -214,6 +235,26 @@ impl IntRot {
     /// Conventional group operator.
     pub fn of(&self, other: &IntRot) -> IntRot
     { other.then(self) }
+
+    /// The inverse operator, such that `op.of(&op.inverse()) == IntRot::eye()`.
+    pub fn inverse(&self) -> IntRot
+    {
+        // the inverse of a unimodular integer matrix is integral, and
+        // the float inverse is exact enough to round
+        let inv = self.t.map(|x| x as f64).inv();
+        IntRot { t: inv.map(|x| x.round() as i32) }
+    }
+}
+
+/// Conventional group operator; `a * b` is `a.of(&b)`.
+///
+/// That is to say, `a * b` is the operator that performs `b` *first*, then `a`,
+/// consistent with `(a * b) * v == a * (b * v)`.
+impl std::ops::Mul<IntRot> for IntRot {
+    type Output = IntRot;
+
+    fn mul(self, other: IntRot) -> Self::Output
+    { self.of(&other) }
 }
 
 impl std::ops::Mul<V3<i32>> for IntRot {
@@ -278,6 +319,24 @@ impl CartOp {
     /// Conventional group operator.
     pub fn of(&self, other: &CartOp) -> CartOp
     { other.then(self) }
+
+    /// The inverse operator, such that `op.of(&op.inverse())` is the identity
+    /// (up to precision loss).
+    pub fn inverse(&self) -> CartOp
+    {
+        let rot_t = self.rot_t.inv();
+        CartOp { rot_t, trans: -(self.trans * rot_t) }
+    }
+}
+
+/// Conventional group operator; `a * b` is `a.of(&b)`.
+///
+/// That is to say, `a * b` is the operator that performs `b` *first*, then `a`.
+impl std::ops::Mul<CartOp> for CartOp {
+    type Output = CartOp;
+
+    fn mul(self, other: CartOp) -> Self::Output
+    { self.of(&other) }
 }
 
 impl IntRot {
@@ -498,6 +557,74 @@ mod tests {
         check_cart_ops_close(op.then(&op), square, &Lattice::eye());
     }
 
+    // The point group of graphene's lattice, generated by brute force.
+    fn graphene_point_group() -> Vec<IntRot> {
+        let generators = vec![
+            // threefold rotation
+            IntRot::from(&[[-1, 1, 0], [-1, 0, 0], [0, 0, 1]]),
+            // mirrors
+            IntRot::from(&[[0, 1, 0], [1, 0, 0], [0, 0, 1]]),
+            IntRot::from(&[[1, 0, 0], [0, 1, 0], [0, 0, -1]]),
+            // inversion
+            IntRot::from(&[[-1, 0, 0], [0, -1, 0], [0, 0, -1]]),
+        ];
+        let mut group = vec![IntRot::eye()];
+        let mut i = 0;
+        while i < group.len() {
+            for g in &generators {
+                let new = *g * group[i];
+                if !group.contains(&new) {
+                    group.push(new);
+                }
+            }
+            i += 1;
+        }
+        group
+    }
+
+    #[test]
+    fn inverse()
+    {
+        let lattice = graphene_lattice();
+        let group = graphene_point_group();
+        assert_eq!(group.len(), 24);
+
+        for &rot in &group {
+            assert_eq!(rot * rot.inverse(), IntRot::eye());
+            assert_eq!(rot.inverse() * rot, IntRot::eye());
+
+            let op = rot.to_cart_op_with_frac_trans(V3([1./3., 2./3., 0.25]), &lattice);
+            check_cart_ops_close(op * op.inverse(), CartOp::eye(), &lattice);
+            check_cart_ops_close(op.inverse() * op, CartOp::eye(), &lattice);
+        }
+    }
+
+    #[test]
+    fn mul_is_sequential_application()
+    {
+        let lattice = graphene_lattice();
+        let group = graphene_point_group();
+        let fracs = vec![[0.1, 0.2, 0.3], [1./3., 2./3., 0.0]].envee();
+
+        for &a in &group {
+            for &b in &group {
+                // `a * b` performs `b` first
+                assert_eq!(
+                    (a * b).transform_fracs(&fracs),
+                    a.transform_fracs(&b.transform_fracs(&fracs)),
+                );
+
+                let a = a.to_cart_op_with_frac_trans(V3([0.5, 0.0, 0.0]), &lattice);
+                let b = b.to_cart_op_with_frac_trans(V3([1./3., 2./3., 0.0]), &lattice);
+                assert_close!(
+                    abs=1e-12,
+                    (a * b).transform_fracs(&lattice, &fracs).unvee(),
+                    a.transform_fracs(&lattice, &b.transform_fracs(&lattice, &fracs)).unvee(),
+                );
+            }
+        }
+    }
+
     #[test]
     fn int_rot_to_cart_from_cart()
     {
@@ -530,4 +657,20 @@ mod tests {
         let op = rot.to_cart_op_with_frac_trans(V3([-1./3., 1./3., 0.0]), &lattice);
         assert_eq!(rot, IntRot::from_cart(&lattice, &op.cart_rot()).unwrap());
     }
+
+    #[test]
+    fn int_rot_on_distorted_lattice()
+    {
+        // a lattice that is *almost* square
+        let lattice = Lattice::orthorhombic(1.0, 1.01, 1.0);
+        let fourfold = mat::from_array([
+            [0.0, -1.0, 0.0],
+            [1.0,  0.0, 0.0],
+            [0.0,  0.0, 1.0],
+        ]);
+        let err = IntRot::from_cart(&lattice, &fourfold).unwrap_err();
+        assert_ne!(err.entry.0, err.entry.1);
+        assert_close!(rel=1e-10, err.residual, 0.01);
+        assert!(err.to_string().contains("off from an integer"));
+    }
 }
@@ -15,18 +15,22 @@ use crate::potential::{PotentialBuilder};
 use crate::meta::{self, prelude::*};
 use rsp2_tasks_config as cfg;
 
-use crate::math::basis::{GammaBasis3, EvDirection};
+use crate::math::basis::{GammaBasis3, GammaKet3, EvDirection};
+use crate::math::gruneisen::sq_overlap;
 
 use rsp2_slice_math::{v, V, vdot, vnormalize, BadNorm};
 
 use slice_of_array::prelude::*;
-use rsp2_structure::{Coords};
+use rsp2_structure::{Coords, CartOp};
+use rsp2_soa_ops::{Perm, Permute};
 use crate::hlist_aliases::*;
 
 use std::fmt;
 use std::rc::Rc;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum ModeKind {
     /// Uniform translations of the entire structure.
     ///
@@ -49,6 +53,17 @@ pub enum ModeKind {
     /// An imaginary mode that is not acoustic! Bad!
     Imaginary,
 
+    /// An imaginary mode that could not be identified as either rotational or imaginary,
+    /// because the changes in force along it were neither anti-parallel nor parallel enough.
+    ///
+    /// These are frequently just noise.  Whether they are chased during the ev-loop is
+    /// controlled by `acoustic-search.chase-suspicious`.
+    Suspicious,
+
+    /// An imaginary mode that matches `ev-loop.ignored-modes`, and is therefore
+    /// believed to be spurious.  These are never chased.
+    Ignored,
+
     /// An acoustic mode identified as such only because we were
     /// told that there might be some acoustic modes that are hard
     /// to spot.
@@ -57,6 +72,21 @@ pub enum ModeKind {
     Vibrational,
 }
 
+impl ModeKind {
+    /// Whether the ev-loop should try to relax along a mode of this kind.
+    pub fn should_chase(self, settings: &cfg::AcousticSearch) -> bool {
+        match self {
+            ModeKind::Imaginary => true,
+            ModeKind::Suspicious => settings.chase_suspicious,
+            ModeKind::Ignored |
+            ModeKind::Translational |
+            ModeKind::Rotational |
+            ModeKind::OtherAcoustic |
+            ModeKind::Vibrational => false,
+        }
+    }
+}
+
 pub struct Colorful(pub ModeKind);
 
 impl fmt::Display for ModeKind {
@@ -65,6 +95,8 @@ impl fmt::Display for ModeKind {
             ModeKind::Translational => "T",
             ModeKind::Rotational    => "R",
             ModeKind::Imaginary     => "‼",
+            ModeKind::Suspicious    => "?",
+            ModeKind::Ignored       => "x",
             ModeKind::OtherAcoustic => "A",
             ModeKind::Vibrational   => "-",
         })
@@ -77,6 +109,8 @@ impl fmt::Display for Colorful {
             ModeKind::Translational => ansi_term::Colour::Yellow.bold(),
             ModeKind::Rotational    => ansi_term::Colour::Purple.bold(),
             ModeKind::Imaginary     => ansi_term::Colour::Red.bold(),
+            ModeKind::Suspicious    => ansi_term::Colour::Red.normal(),
+            ModeKind::Ignored       => ansi_term::Colour::Blue.normal(),
             ModeKind::OtherAcoustic => ansi_term::Colour::Green.bold(),
             ModeKind::Vibrational   => ansi_term::Colour::White.normal(),
         };
@@ -84,6 +118,9 @@ impl fmt::Display for Colorful {
     }
 }
 
+/// Minimum acousticness for a mode to be identified as translational.
+const TRANSLATIONAL_ACOUSTICNESS: f64 = 0.95;
+
 pub(crate) fn perform_acoustic_search(
     pot: &dyn PotentialBuilder,
     frequencies: &[f64],
@@ -108,8 +145,20 @@ pub(crate) fn perform_acoustic_search(
         displacement_distance,
         rotational_fdot_threshold,
         imaginary_fdot_threshold,
+        chase_suspicious: _,
+        max_acoustic_frequency,
+        fail_on_large_acoustic_frequency,
     } = settings;
 
+    if let Some(threshold) = max_acoustic_frequency {
+        let translational_frequencies = {
+            zip_eq!(frequencies, ev_directions())
+                .filter(|(_, direction)| direction.acousticness() >= TRANSLATIONAL_ACOUSTICNESS)
+                .map(|(&freq, _)| freq)
+        };
+        check_acoustic_frequencies(translational_frequencies, threshold, fail_on_large_acoustic_frequency)?;
+    }
+
     let zero_index = frequencies.iter().position(|&x| x >=  0.0).unwrap_or(frequencies.len());
 
     let mut kinds = vec![None; frequencies.len()];
@@ -126,7 +175,7 @@ pub(crate) fn perform_acoustic_search(
 
         let mut t_end = zero_index;
         for (i, direction) in ev_directions().take(stop_index).enumerate() {
-            if direction.acousticness() >= 0.95 {
+            if direction.acousticness() >= TRANSLATIONAL_ACOUSTICNESS {
                 t_end = i + 1;
                 kinds[i] = Some(ModeKind::Translational);
             }
@@ -158,6 +207,7 @@ pub(crate) fn perform_acoustic_search(
 
     let mut rotational_count = 0;
     let mut uncertain_indices = vec![];
+    let mut suspicious_indices = vec![];
     for (i, direction) in ev_directions().take(t_end).enumerate() {
         if kinds[i].is_some() {
             continue;
@@ -202,22 +252,24 @@ pub(crate) fn perform_acoustic_search(
                         "Could not classify mode at frequency {} (fdot = {:.6})!",
                         frequencies[i], dot,
                     );
+                    suspicious_indices.push(i);
                 }
                 uncertain_indices.push(i);
             },
         }
     }
 
-    let fill = {
-        if let Some(expected) = expected_non_translations {
-            if expected - rotational_count >= uncertain_indices.len() {
-                ModeKind::OtherAcoustic
-            } else { ModeKind::Imaginary }
-        } else { ModeKind::Imaginary }
-    };
+    let fill = classify_uncertain_modes(
+        expected_non_translations,
+        rotational_count,
+        uncertain_indices.len(),
+    )?;
 
     for i in uncertain_indices {
-        kinds[i] = Some(fill);
+        kinds[i] = Some(match fill {
+            ModeKind::Imaginary if suspicious_indices.contains(&i) => ModeKind::Suspicious,
+            fill => fill,
+        });
     }
 
     kinds.into_iter()
@@ -225,3 +277,234 @@ pub(crate) fn perform_acoustic_search(
         .collect::<Vec<_>>()
         .into()
 })}
+
+/// Complain if any of the translational modes have a frequency larger in magnitude
+/// than `acoustic-search.max-acoustic-frequency`.
+///
+/// Before a sum rule is imposed, these will generally be slightly nonzero, but a large value
+/// indicates that something is wrong with the force constants.
+fn check_acoustic_frequencies(
+    translational_frequencies: impl IntoIterator<Item=f64>,
+    threshold: f64,
+    fail: bool,
+) -> FailResult<()> {
+    let worst = {
+        translational_frequencies.into_iter()
+            .fold(None, |acc: Option<f64>, freq| match acc {
+                Some(acc) if acc.abs() >= freq.abs() => Some(acc),
+                _ => Some(freq),
+            })
+    };
+
+    if let Some(worst) = worst {
+        if worst.abs() > threshold {
+            let msg = format!(
+                "Found a translational mode at frequency {}, which exceeds \
+                acoustic-search.max-acoustic-frequency ({}). This may indicate a supercell \
+                that is too small, or a problem with the potential.",
+                worst, threshold,
+            );
+            if fail {
+                bail!("{}", msg);
+            }
+            warn!("{}", msg);
+        }
+    }
+    Ok(())
+}
+
+/// Minimum squared overlap for a mode to be considered the same as an ignored mode
+/// from the previous ev-loop iteration.
+const IGNORED_MODE_MIN_OVERLAP: f64 = 0.8;
+
+/// The spacegroup operators of a structure, for computing the symmetry characters of its modes.
+pub(crate) struct ModeSymmetry {
+    pub cart_ops: Vec<CartOp>,
+    pub deperms: Vec<Perm>,
+}
+
+impl ModeSymmetry {
+    /// The character `<v|g v>` of a real gamma-point mode under each operator `g`.
+    pub fn characters(&self, evec: &GammaKet3) -> Vec<f64> {
+        let norm_sqr: f64 = evec.0.iter().map(|v| v.sqnorm()).sum();
+        zip_eq!(&self.cart_ops, &self.deperms)
+            .map(|(op, deperm)| {
+                // (see conventions.md; translations do not affect displacements)
+                let rotated: Vec<V3> = evec.0.iter().map(|v| v * op.cart_rot_t()).collect();
+                let image = rotated.permuted_by(deperm);
+                zip_eq!(&evec.0, &image).map(|(a, b)| a.dot(b)).sum::<f64>() / norm_sqr
+            })
+            .collect()
+    }
+}
+
+/// Reclassify the imaginary modes that match `ev-loop.ignored-modes` as `ModeKind::Ignored`.
+///
+/// `symmetry` is only needed if some of the fingerprints specify `characters`.
+///
+/// `previously_ignored` holds the eigenvectors of the modes ignored in the previous iteration,
+/// and is updated to hold the ones ignored in this iteration.
+pub(crate) fn apply_ignored_modes(
+    kinds: Rc<[ModeKind]>,
+    frequencies: &[f64],
+    eigenvectors: &GammaBasis3,
+    symmetry: Option<&ModeSymmetry>,
+    ignored_modes: &[cfg::IgnoredMode],
+    previously_ignored: &mut Vec<GammaKet3>,
+) -> Rc<[ModeKind]> {
+    if ignored_modes.is_empty() {
+        return kinds;
+    }
+
+    let characters: Vec<Option<Vec<f64>>> = {
+        zip_eq!(&kinds[..], &eigenvectors.0[..])
+            .map(|(&kind, evec)| match (kind, symmetry) {
+                (ModeKind::Imaginary, Some(symmetry)) |
+                (ModeKind::Suspicious, Some(symmetry)) => Some(symmetry.characters(evec)),
+                _ => None,
+            })
+            .collect()
+    };
+    for (i, characters) in characters.iter().enumerate() {
+        if let Some(characters) = characters {
+            let rounded: Vec<_> = characters.iter().map(|x| (x * 1e3).round() / 1e3).collect();
+            info!("Mode {} at frequency {} has characters {:?}", i + 1, frequencies[i], rounded);
+        }
+    }
+
+    let is_ignored = find_ignored_modes(
+        &kinds, frequencies, &characters, &eigenvectors.0,
+        ignored_modes, previously_ignored,
+    );
+
+    let mut kinds = kinds.to_vec();
+    previously_ignored.clear();
+    for (i, _) in is_ignored.iter().enumerate().filter(|&(_, &x)| x) {
+        info!("Ignoring mode {} at frequency {} as spurious.", i + 1, frequencies[i]);
+        kinds[i] = ModeKind::Ignored;
+        previously_ignored.push(eigenvectors.0[i].clone());
+    }
+    kinds.into()
+}
+
+/// Find the imaginary modes that either match a fingerprint in `ignored_modes`,
+/// or can be tracked by overlap to a mode in `previously_ignored`.
+///
+/// `characters` is `None` for modes whose characters were not computed.
+fn find_ignored_modes(
+    kinds: &[ModeKind],
+    frequencies: &[f64],
+    characters: &[Option<Vec<f64>>],
+    eigenvectors: &[GammaKet3],
+    ignored_modes: &[cfg::IgnoredMode],
+    previously_ignored: &[GammaKet3],
+) -> Vec<bool> {
+    let matches_fingerprint = |freq: f64, characters: Option<&[f64]>, fingerprint: &cfg::IgnoredMode| {
+        let characters_ok = match (&fingerprint.characters, characters) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(expected), Some(actual)) => {
+                expected.len() == actual.len()
+                    && zip_eq!(actual, expected).all(|(a, b)| (a - b).abs() <= fingerprint.character_tol)
+            },
+        };
+        (freq - fingerprint.frequency).abs() <= fingerprint.frequency_tol && characters_ok
+    };
+
+    zip_eq!(kinds, frequencies, characters, eigenvectors)
+        .map(|(&kind, &freq, characters, evec)| {
+            match kind {
+                ModeKind::Imaginary | ModeKind::Suspicious => {},
+                _ => return false,
+            }
+            let characters = characters.as_ref().map(|x| &x[..]);
+            ignored_modes.iter().any(|fingerprint| matches_fingerprint(freq, characters, fingerprint))
+                || previously_ignored.iter().any(|prev| sq_overlap(prev, evec) >= IGNORED_MODE_MIN_OVERLAP)
+        })
+        .collect()
+}
+
+/// Decide what to do with the modes that could be neither identified as rotational nor
+/// ruled out as acoustic, by comparing against `expected_non_translations` (if set).
+///
+/// Finding more rotational modes than expected is an error, since it means the classification
+/// cannot be trusted.  Finding fewer non-translational acoustic modes than expected only produces
+/// a warning, since some of them may have been too stiff to show up among the negative modes.
+fn classify_uncertain_modes(
+    expected_non_translations: Option<usize>,
+    rotational_count: usize,
+    uncertain_count: usize,
+) -> FailResult<ModeKind> {
+    let expected = match expected_non_translations {
+        None => return Ok(ModeKind::Imaginary),
+        Some(expected) => expected,
+    };
+
+    ensure!(
+        rotational_count <= expected,
+        "Found {} rotational modes, but acoustic-search.expected-non-translations is {}!",
+        rotational_count, expected,
+    );
+
+    if rotational_count + uncertain_count <= expected {
+        if rotational_count + uncertain_count < expected {
+            warn!(
+                "Only found {} non-translational acoustic modes, but expected {}.",
+                rotational_count + uncertain_count, expected,
+            );
+        }
+        Ok(ModeKind::OtherAcoustic)
+    } else {
+        Ok(ModeKind::Imaginary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expected_non_translations() {
+        // not set; anything we could not identify is imaginary
+        assert_eq!(classify_uncertain_modes(None, 0, 2).unwrap(), ModeKind::Imaginary);
+        assert_eq!(classify_uncertain_modes(None, 3, 0).unwrap(), ModeKind::Imaginary);
+
+        // the unidentified modes account for the remaining acoustic modes
+        assert_eq!(classify_uncertain_modes(Some(3), 1, 2).unwrap(), ModeKind::OtherAcoustic);
+        // too few were found; this warns, but is not fatal
+        assert_eq!(classify_uncertain_modes(Some(3), 1, 0).unwrap(), ModeKind::OtherAcoustic);
+        // too many unidentified modes to all be acoustic
+        assert_eq!(classify_uncertain_modes(Some(1), 0, 2).unwrap(), ModeKind::Imaginary);
+
+        // more rotational modes than there should be
+        assert!(classify_uncertain_modes(Some(1), 2, 0).is_err());
+        assert!(classify_uncertain_modes(Some(0), 1, 3).is_err());
+    }
+
+    #[test]
+    fn large_acoustic_frequency() {
+        // nearly zero, as they should be after a sum rule
+        assert!(check_acoustic_frequencies(vec![-1e-4, 2e-5, 3e-4], 1.0, true).is_ok());
+        // far from zero; the sign does not matter
+        assert!(check_acoustic_frequencies(vec![-1e-4, 2e-5, 3e-4, 45.0], 1.0, true).is_err());
+        assert!(check_acoustic_frequencies(vec![-45.0, 2e-5, 3e-4], 1.0, true).is_err());
+        assert!(check_acoustic_frequencies(vec![0.9, -1.0], 1.0, true).is_ok());
+        // only a warning
+        assert!(check_acoustic_frequencies(vec![-45.0, 2e-5, 3e-4], 1.0, false).is_ok());
+        // nothing to check
+        assert!(check_acoustic_frequencies(vec![], 1.0, true).is_ok());
+    }
+
+    #[test]
+    fn should_chase() {
+        let mut settings = cfg::AcousticSearch::default();
+        assert!(settings.chase_suspicious);
+        assert!(ModeKind::Imaginary.should_chase(&settings));
+        assert!(ModeKind::Suspicious.should_chase(&settings));
+        assert!(!ModeKind::Rotational.should_chase(&settings));
+
+        settings.chase_suspicious = false;
+        assert!(ModeKind::Imaginary.should_chase(&settings));
+        assert!(!ModeKind::Suspicious.should_chase(&settings));
+    }
+}
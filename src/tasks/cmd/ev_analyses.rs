@@ -13,10 +13,11 @@ use crate::FailResult;
 use crate::ui::color::{ColorByRange, PaintAs, NullPainter};
 use crate::ui::cfg_merging::{no_summary, merge_summaries, make_nested_mapping};
 use crate::math::basis::{GammaBasis3, EvDirection};
-use crate::math::bands::{GammaUnfolder};
+use crate::math::bands::{GammaUnfolder, AllenUnfolder};
 #[allow(unused)] // compiler bug
 use itertools::Itertools;
-use rsp2_tasks_config::Settings;
+use rsp2_tasks_config::{self as cfg, Settings};
+use rsp2_array_types::V3;
 
 #[allow(unused)] // compiler bug
 use rsp2_soa_ops::{Part, Partition};
@@ -45,10 +46,14 @@ pub use crate::meta::LayerScMatrices;
 #[derive(Debug, Clone)] pub struct EvFrequencies(pub Vec<f64>);
 #[derive(Debug, Clone)] pub struct EvEigenvectors(pub GammaBasis3);
 #[derive(Debug, Clone)] pub struct Bonds(pub rsp2_structure::bonds::CartBonds);
+/// Which modes lie inside the frequency window, for analyses that are restricted to it.
+#[derive(Debug, Clone)] pub struct EvInWindow(pub Vec<bool>);
+/// Point charge of each site (in units of the elementary charge), for IR intensities.
+#[derive(Debug, Clone)] pub struct SiteCharges(pub Vec<f64>);
 
 // Band unfolding is seriously expensive, and not at all useful for the sparse diagonalizer
-// during relaxation.
-#[derive(Debug, Clone)] pub struct RequestToUnfoldBands;
+// during relaxation.  The request carries the method to use.
+#[derive(Debug, Clone)] pub struct RequestToUnfoldBands(pub cfg::UnfoldBands);
 
 pub use self::gamma_system_analysis::GammaSystemAnalysis;
 pub mod gamma_system_analysis {
@@ -75,6 +80,8 @@ pub mod gamma_system_analysis {
         pub ev_frequencies:     Option<EvFrequencies>,
         pub ev_eigenvectors:    Option<EvEigenvectors>,
         pub bonds:              Option<Bonds>,
+        pub ev_in_window:       Option<EvInWindow>,
+        pub site_charges:       Option<SiteCharges>,
         pub request_to_unfold_bands: Option<RequestToUnfoldBands>,
     }
 
@@ -85,6 +92,9 @@ pub mod gamma_system_analysis {
         pub ev_polarization:       Option<EvPolarization>,
         pub ev_layer_acousticness: Option<EvLayerAcousticness>,
         pub ev_raman_tensors:      Option<EvRamanTensors>,
+        pub ev_ir_intensities:     Option<EvIrIntensities>,
+        pub ev_atom_contributions: Option<EvAtomContributions>,
+        pub site_layers:           Option<SiteLayers>,
         pub layer_sc_mats:         Option<LayerScMatrices>,
         pub unfold_probs:          Option<UnfoldProbs>,
     }
@@ -95,7 +105,7 @@ pub mod gamma_system_analysis {
             let Input {
                 site_coords, site_layers, site_elements, site_masses,
                 layer_sc_mats, ev_frequencies, ev_eigenvectors, bonds,
-                ev_classifications, request_to_unfold_bands,
+                ev_classifications, ev_in_window, site_charges, request_to_unfold_bands,
             } = self;
 
             // since our inputs are all uniquely typed, we can let HList
@@ -103,7 +113,7 @@ pub mod gamma_system_analysis {
             let grab_bag = hlist![
                 site_coords, site_layers, site_elements, site_masses,
                 layer_sc_mats, ev_frequencies, ev_eigenvectors, bonds,
-                request_to_unfold_bands,
+                ev_in_window, site_charges, request_to_unfold_bands,
             ];
 
             let (args, _) = grab_bag.sculpt();
@@ -116,14 +126,26 @@ pub mod gamma_system_analysis {
             let ev_layer_acousticness = ev_layer_acousticness::maybe_compute(args)?;
 
             let (args, _) = grab_bag.sculpt();
-            let unfold_probs = unfold_probs::maybe_compute(args)?;
+            // (e.g. Allen's method requires a structure with the symmetry of the primitive cell;
+            //  failing that, there is no reason to lose the rest of the analysis)
+            let unfold_probs = unfold_probs::maybe_compute(args).unwrap_or_else(|e| {
+                warn!("Unable to unfold bands; skipping. ({})", e);
+                None
+            });
 
             let (args, _) = grab_bag.sculpt();
             let ev_raman_tensors = ev_raman_tensors::maybe_compute(args)?;
 
+            let (args, _) = grab_bag.sculpt();
+            let ev_ir_intensities = ev_ir_intensities::maybe_compute(args)?;
+
+            let (args, _) = grab_bag.sculpt();
+            let ev_atom_contributions = ev_atom_contributions::maybe_compute(args)?;
+
             let ev_frequencies = ev_frequencies.clone();
             let ev_classifications = ev_classifications.clone();
             let layer_sc_mats = layer_sc_mats.clone();
+            let site_layers = site_layers.clone();
 
             GammaSystemAnalysis {
                 ev_classifications,
@@ -134,6 +156,9 @@ pub mod gamma_system_analysis {
                 unfold_probs,
                 ev_layer_acousticness,
                 ev_raman_tensors,
+                ev_ir_intensities,
+                ev_atom_contributions,
+                site_layers,
             }
         })}
     }
@@ -301,7 +326,10 @@ wrap_maybe_compute! {
 
 wrap_maybe_compute! {
     pub struct UnfoldProbs {
-        pub layer_unfolders: Vec<GammaUnfolder>,
+        /// Indices of the unfolded points for each layer, relative to the sampled point.
+        /// These are in units of the layer's supercell reciprocal lattice, on the grid
+        /// `0..periods` (the same points as `gpoint_sfracs` in `scripts/unfold.py`).
+        pub layer_q_indices: Vec<Vec<[u32; 3]>>,
         pub layer_ev_q_probs: Vec<Vec<Vec<f64>>>,
     }
     fn unfold_probs(
@@ -316,12 +344,12 @@ wrap_maybe_compute! {
 
 impl UnfoldProbs {
     fn layer_ev_gamma_probs(&self) -> Vec<Vec<f64>> {
-        let UnfoldProbs { layer_unfolders, layer_ev_q_probs } = self;
+        let UnfoldProbs { layer_q_indices, layer_ev_q_probs } = self;
 
-        zip_eq!(layer_unfolders, layer_ev_q_probs)
-            .map(|(unfolder, ev_q_probs)| {
+        zip_eq!(layer_q_indices, layer_ev_q_probs)
+            .map(|(q_indices, ev_q_probs)| {
                 ev_q_probs.iter().map(|probs| {
-                    zip_eq!(unfolder.q_indices(), probs.iter().cloned())
+                    zip_eq!(q_indices, probs.iter().cloned())
                         .find(|&(idx, _)| idx == &[0, 0, 0])
                         .unwrap().1
                 }).collect()
@@ -334,7 +362,7 @@ fn _unfold_probs(
     site_coords: &SiteCoordinates,
     layer_sc_mats: &LayerScMatrices,
     ev_eigenvectors: &EvEigenvectors,
-    _: &RequestToUnfoldBands,
+    RequestToUnfoldBands(method): &RequestToUnfoldBands,
 ) -> FailResult<UnfoldProbs> {
     let part = Part::from_ord_keys(site_layers.iter());
     let layer_partial_coords = site_coords
@@ -347,10 +375,13 @@ fn _unfold_probs(
     });
     let layer_partial_evs = crate::util::transpose_iter_to_vec(ev_layer_partial_evs);
 
-    let (layer_unfolders, layer_ev_q_probs) = {
-        zip_eq!(layer_partial_coords, layer_partial_evs, &layer_sc_mats[..])
-            .map(|(partial_structure, partial_evs, sc_mat)| {
-                // precompute data applicable to all kets
+    let mut layer_q_indices = vec![];
+    let mut layer_ev_q_probs = vec![];
+    let layer_iter = zip_eq!(layer_partial_coords, layer_partial_evs, &layer_sc_mats[..]);
+    for (partial_structure, partial_evs, sc_mat) in layer_iter {
+        // precompute data applicable to all kets
+        let (q_indices, ev_q_probs) = match *method {
+            cfg::UnfoldBands::Zheng {} => {
                 let unfolder = GammaUnfolder::from_config(
                     &from_json!({
                         "fbz": "reciprocal-cell",
@@ -361,24 +392,58 @@ fn _unfold_probs(
                     sc_mat,
                 );
 
+                let q_indices = unfolder.q_indices().to_vec();
                 let ev_q_probs = partial_evs.into_iter().map(|ket| {
                     unfolder.unfold_phonon(Threading::Parallel, ket.to_ket().as_ref())
                 }).collect();
-                (unfolder, ev_q_probs)
-            }).unzip()
-    };
+                (q_indices, ev_q_probs)
+            },
+            cfg::UnfoldBands::Allen { translation_tolerance } => {
+                let unfolder = AllenUnfolder::new(
+                    &partial_structure, sc_mat, &V3::zero(), translation_tolerance,
+                )?;
+
+                let q_indices = unfolder.gpoint_indices().to_vec();
+                let ev_q_probs = partial_evs.into_iter().map(|ket| {
+                    unfolder.unfold_phonon(Threading::Parallel, &ket.to_complex())
+                }).collect();
+                (q_indices, ev_q_probs)
+            },
+        };
+        layer_q_indices.push(q_indices);
+        layer_ev_q_probs.push(ev_q_probs);
+    }
 
-    Ok(UnfoldProbs { layer_unfolders, layer_ev_q_probs })
+    Ok(UnfoldProbs { layer_q_indices, layer_ev_q_probs })
 }
 
 wrap_maybe_compute! {
-    pub struct EvRamanTensors(pub Vec<crate::math::bond_polarizability::RamanTensor>);
+    // (`None` for modes that are not imaginary)
+    pub struct EvAtomContributions(pub Vec<Option<Vec<(usize, f64)>>>);
+    fn ev_atom_contributions(
+        ev_frequencies: &EvFrequencies,
+        ev_eigenvectors: &EvEigenvectors,
+    ) -> FailResult<_> {
+        Ok(EvAtomContributions({
+            zip_eq!(&ev_frequencies.0, (ev_eigenvectors.0).0.iter())
+                .map(|(&freq, evec)| match freq < 0.0 {
+                    true => Some(evec.to_complex().atom_contributions()),
+                    false => None,
+                }).collect()
+        }))
+    }
+}
+
+wrap_maybe_compute! {
+    // (`None` for modes outside of the frequency window)
+    pub struct EvRamanTensors(pub Vec<Option<crate::math::bond_polarizability::RamanTensor>>);
     fn ev_raman_tensors(
         bonds: &Bonds,
         site_masses: &SiteMasses,
         site_elements: &SiteElements,
         ev_frequencies: &EvFrequencies,
         ev_eigenvectors: &EvEigenvectors,
+        ev_in_window: &EvInWindow,
     ) -> FailResult<_>
     = _ev_raman_tensors;
 }
@@ -389,18 +454,57 @@ fn _ev_raman_tensors(
     site_elements: &SiteElements,
     ev_frequencies: &EvFrequencies,
     ev_eigenvectors: &EvEigenvectors,
+    ev_in_window: &EvInWindow,
 ) -> FailResult<EvRamanTensors> {
-    use crate::math::bond_polarizability::{Input};
+    use crate::math::bond_polarizability::{Input, StokesBranch};
+
+    // Only hand the modes inside the window to the implementation, since that
+    // is where all of the time is spent.
+    let ev_indices = {
+        ev_in_window.0.iter().enumerate()
+            .filter(|&(_, &in_window)| in_window)
+            .map(|(i, _)| i)
+            .collect_vec()
+    };
+    let window_frequencies = ev_indices.iter().map(|&i| ev_frequencies.0[i]).collect_vec();
+    let window_eigenvectors = GammaBasis3(std::sync::Arc::new({
+        ev_indices.iter().map(|&i| (ev_eigenvectors.0).0[i].clone()).collect()
+    }));
 
-    Input {
+    let window_tensors = Input {
         temperature: 0.0,
+        branch: StokesBranch::Stokes,
         site_masses: &site_masses,
         site_elements: &site_elements,
-        ev_eigenvectors: &ev_eigenvectors.0,
-        ev_frequencies: &ev_frequencies.0,
+        ev_eigenvectors: &window_eigenvectors,
+        ev_frequencies: &window_frequencies,
         bonds: &bonds.0,
-    }.compute_ev_raman_tensors()
-        .map(EvRamanTensors)
+    }.compute_ev_raman_tensors()?;
+
+    let mut ev_tensors = ev_frequencies.0.iter().map(|_| None).collect_vec();
+    for (i, tensor) in zip_eq!(ev_indices, window_tensors) {
+        ev_tensors[i] = Some(tensor);
+    }
+    Ok(EvRamanTensors(ev_tensors))
+}
+
+wrap_maybe_compute! {
+    pub struct EvIrIntensities(pub Vec<f64>);
+    fn ev_ir_intensities(
+        site_masses: &SiteMasses,
+        site_charges: &SiteCharges,
+        ev_eigenvectors: &EvEigenvectors,
+    ) -> FailResult<_> {
+        use crate::math::ir_intensity::{Input, point_charges};
+
+        Ok(EvIrIntensities({
+            Input {
+                ev_eigenvectors: &(ev_eigenvectors.0).0[..],
+                site_masses: &site_masses[..],
+                born_charges: &point_charges(&site_charges.0),
+            }.compute_ev_intensities()
+        }))
+    }
 }
 
 macro_rules! format_columns {
@@ -474,48 +578,53 @@ impl GammaSystemAnalysis {
             })
         };
 
+        let intensity_column = |name: &str, data: &[f64]| {
+            // NOTE: intensity can be "negative" for negative modes.  This clearly is
+            //       not physical, but we also don't care about intensities of such modes,
+            //       so simply set them to zero.
+            let data = data.iter().map(|&x| f64::max(0.0, x)).collect_vec();
+            // NOTE: raman_intensities are currently missing some scale factors
+            //       so they are just normalized for now.  (IR intensities are
+            //       normalized too, for the sake of consistency)
+            let max = data.iter().fold(0.0, |acc, &x| f64::max(acc, x));
+            let data = if max == 0.0 {
+                vec![0.0; data.len()]
+            } else {
+                data.into_iter().map(|x| x / max).collect_vec()
+            };
+            let painter: Box<dyn PaintAs<_, f64>> = match mode {
+                ColumnsMode::ForHumans => Box::new({
+                    use ansi_term::Colour::*;
+                    ColorByRange::new(vec![
+                        ( 1e-0, Cyan.bold()),
+                        ( 1e-1, Cyan.normal()),
+                        ( 1e-5, Yellow.normal()),
+                        (1e-10, Red.bold()),
+                        (1e-25, Red.normal()),
+                    ],          Black.normal()) // make zeros "disappear"
+                }),
+                ColumnsMode::ForMachines => Box::new(NullPainter),
+            };
+            quick_column(
+                &*painter, name, &data, 5,
+                |&value| ShortExp { value, cutoff_exp: -45 },
+            )
+        };
+
         if let Some(EvRamanTensors(tensors)) = &self.ev_raman_tensors {
-            let raman_column = |name: &str, data: &[f64]| {
-                // NOTE: intensity can be "negative" for negative modes.  This clearly is
-                //       not physical, but we also don't care about intensities of such modes,
-                //       so simply set them to zero.
-                let data = data.iter().map(|&x| f64::max(0.0, x)).collect_vec();
-                // NOTE: raman_intensities are currently missing some scale factors
-                //       so they are just normalized for now.
-                let max = data.iter().fold(0.0, |acc, &x| f64::max(acc, x));
-                let data = if max == 0.0 {
-                    vec![0.0; data.len()]
-                } else {
-                    data.into_iter().map(|x| x / max).collect_vec()
-                };
-                let painter: Box<dyn PaintAs<_, f64>> = match mode {
-                    ColumnsMode::ForHumans => Box::new({
-                        use ansi_term::Colour::*;
-                        ColorByRange::new(vec![
-                            ( 1e-0, Cyan.bold()),
-                            ( 1e-1, Cyan.normal()),
-                            ( 1e-5, Yellow.normal()),
-                            (1e-10, Red.bold()),
-                            (1e-25, Red.normal()),
-                        ],          Black.normal()) // make zeros "disappear"
-                    }),
-                    ColumnsMode::ForMachines => Box::new(NullPainter),
-                };
-                quick_column(
-                    &*painter, name, &data, 5,
-                    |&value| ShortExp { value, cutoff_exp: -45 },
-                )
+            // modes outside the frequency window are displayed as zero.
+            use crate::math::bond_polarizability::LightPolarization::{self, *};
+            let intensities = |polarization: &LightPolarization| {
+                tensors.iter()
+                    .map(|t| t.as_ref().map_or(0.0, |t| t.integrate_intensity(polarization)))
+                    .collect_vec()
             };
+            columns.push(intensity_column("RamnA", &intensities(&Average)));
+            columns.push(intensity_column("RamnB", &intensities(&BackscatterZ)));
+        };
 
-            use crate::math::bond_polarizability::LightPolarization::*;
-            columns.push(raman_column(
-                "RamnA",
-                &tensors.iter().map(|t| t.integrate_intensity(&Average)).collect_vec(),
-            ));
-            columns.push(raman_column(
-                "RamnB",
-                &tensors.iter().map(|t| t.integrate_intensity(&BackscatterZ)).collect_vec(),
-            ));
+        if let Some(data) = &self.ev_ir_intensities {
+            columns.push(intensity_column("IR", &data.0));
         };
 
         if let Some(data) = &self.ev_layer_acousticness {
@@ -575,6 +684,47 @@ impl GammaSystemAnalysis {
     }
 }
 
+/// Fraction of an imaginary mode's squared amplitude that must be accounted for by the
+/// atoms listed as its localization.
+const LOCALIZATION_FRACTION: f64 = 0.5;
+/// Maximum number of atoms listed as the localization of an imaginary mode.
+const MAX_LOCALIZATION_ATOMS: usize = 8;
+
+impl GammaSystemAnalysis {
+    /// Lines for humans describing where each imaginary mode is localized,
+    /// e.g. `mode 3 (-12.5 cm-1) localized on atoms [12, 13, 45]`.
+    pub fn make_localization_lines(&self) -> Vec<String> {
+        let (contributions, frequencies) = match (&self.ev_atom_contributions, &self.ev_frequencies) {
+            (Some(contributions), Some(frequencies)) => (&contributions.0, &frequencies.0),
+            _ => return vec![],
+        };
+
+        izip!(1.., contributions, frequencies)
+            .filter_map(|(mode, contributions, &frequency)| {
+                let contributions = contributions.as_ref()?;
+                let total = contributions.iter().map(|&(_, x)| x).sum::<f64>();
+
+                // take the fewest atoms that account for enough of the mode
+                let mut accounted = 0.0;
+                let atoms = {
+                    contributions.iter()
+                        .take_while(|&&(_, x)| {
+                            let done = accounted >= LOCALIZATION_FRACTION * total;
+                            accounted += x;
+                            !done
+                        })
+                        .take(MAX_LOCALIZATION_ATOMS)
+                        .map(|&(atom, _)| match &self.site_layers {
+                            Some(layers) => format!("{} (layer {})", atom, layers[atom].0),
+                            None => format!("{}", atom),
+                        })
+                        .join(", ")
+                };
+                Some(format!("mode {} ({:.1} cm-1) localized on atoms [{}]", mode, frequency, atoms))
+            }).collect()
+    }
+}
+
 impl GammaSystemAnalysis {
     pub fn make_summary(&self, settings: &Settings) -> YamlValue {
         let GammaSystemAnalysis {
@@ -582,7 +732,10 @@ impl GammaSystemAnalysis {
             ev_frequencies, unfold_probs,
             ev_layer_acousticness,
             ev_raman_tensors: _,
+            ev_ir_intensities: _,
+            ev_atom_contributions: _,
             ev_classifications: _,
+            site_layers: _,
             layer_sc_mats: _,
         } = self;
 
@@ -12,14 +12,23 @@
 pub type Point = (usize, usize);
 use std::ops::Range;
 use std::hash::Hash;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::io::prelude::*;
 
+use crate::FailResult;
 use slice_of_array::prelude::*;
 use rsp2_slice_math::{v, V, vdot};
 use rsp2_array_types::{V3};
 
 // Note: directions do not need to be normalized.
 // (The ranges are in units of these norms)
+//
+// If a checkpoint path is given, the gradient at each grid point is appended to that file as
+// soon as it is computed, and points already recorded there by a previous (interrupted) call
+// with the same grid are not recomputed.  The value paired with the path should identify
+// anything else that the gradients depend on (e.g. the potential); a checkpoint is only
+// resumed if it matches.
 pub fn integrate_two_directions<E, F>(
     // dims must be greater than 1
     dims: [usize; 2],
@@ -27,11 +36,31 @@ pub fn integrate_two_directions<E, F>(
     ranges: [Range<f64>; 2],
     extend_borders: [bool; 2],
     directions: [&[V3]; 2],
+    checkpoint: Option<(&Path, serde_json::Value)>,
     mut compute_grad: F,
 ) -> Result<TwoDeeIntegrated, E>
 where
     F: FnMut(&[V3]) -> Result<Vec<V3>, E>,
+    E: From<failure::Error>,
 {
+    let mut checkpoint = match checkpoint {
+        None => None,
+        Some((path, context)) => {
+            let header = CheckpointHeader {
+                dims,
+                ranges: [
+                    [ranges[0].start, ranges[0].end],
+                    [ranges[1].start, ranges[1].end],
+                ],
+                extend_borders,
+                init_pos: init_pos.to_vec(),
+                directions: [directions[0].to_vec(), directions[1].to_vec()],
+                context,
+            };
+            Some(GridCheckpoint::open(path, header)?)
+        },
+    };
+
     let (ixs, xs) = linspace(ranges[0].clone(), dims[0], extend_borders[0]);
     let (iys, ys) = linspace(ranges[1].clone(), dims[1], extend_borders[1]);
 
@@ -46,8 +75,17 @@ where
                 + ys[y] * v(directions[1].flat());
 
             let pos = pos.to_vec();
-            let grad = compute_grad(pos.nest())?.flat().to_vec();
-            (pos, grad)
+            let grad = match checkpoint.as_mut().and_then(|c| c.take((x, y))) {
+                Some(grad) => grad,
+                None => {
+                    let grad = compute_grad(pos.nest())?;
+                    if let Some(checkpoint) = checkpoint.as_mut() {
+                        checkpoint.record((x, y), &grad)?;
+                    }
+                    grad
+                },
+            };
+            (pos, grad.flat().to_vec())
         })},
 
         |(_, (pos1, grad1)), (_, (pos2, grad2))| {Ok({
@@ -61,6 +99,118 @@ where
     Ok(TwoDeeIntegrated { indices, values, coords })
 }
 
+/// Identifies the scan of a checkpoint file, so that a checkpoint is never resumed
+/// with a different grid, structure, or potential.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+struct CheckpointHeader {
+    dims: [usize; 2],
+    ranges: [[f64; 2]; 2],
+    extend_borders: [bool; 2],
+    init_pos: Vec<V3>,
+    directions: [Vec<V3>; 2],
+    context: serde_json::Value,
+}
+
+impl CheckpointHeader {
+    fn differing_fields(&self, other: &CheckpointHeader) -> Vec<&'static str> {
+        let directions_close = |a: &[Vec<V3>; 2], b: &[Vec<V3>; 2]| {
+            floats_close(a[0].flat(), b[0].flat()) && floats_close(a[1].flat(), b[1].flat())
+        };
+
+        let mut out = vec![];
+        if self.dims != other.dims { out.push("dims"); }
+        if !floats_close(self.ranges.flat(), other.ranges.flat()) { out.push("ranges"); }
+        if self.extend_borders != other.extend_borders { out.push("extend-borders"); }
+        if !floats_close(self.init_pos.flat(), other.init_pos.flat()) { out.push("init-pos"); }
+        if !directions_close(&self.directions, &other.directions) { out.push("directions"); }
+        if self.context != other.context { out.push("context"); }
+        out
+    }
+}
+
+// serde_json does not guarantee that floats survive a round trip exactly, so the
+// header read back from a checkpoint may be off by an ulp or so.
+fn floats_close(a: &[f64], b: &[f64]) -> bool {
+    const REL_TOL: f64 = 1e-12;
+    a.len() == b.len() && izip!(a, b).all(|(&a, &b)| {
+        (a - b).abs() <= REL_TOL * f64::max(a.abs(), b.abs())
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone)]
+struct CheckpointEntry {
+    point: [usize; 2],
+    grad: Vec<V3>,
+}
+
+/// Append-only file of gradients at grid points.
+///
+/// The first line is a `CheckpointHeader`, and each following line is a `CheckpointEntry`,
+/// all in JSON.
+struct GridCheckpoint {
+    file: std::fs::File,
+    done: HashMap<Point, Vec<V3>>,
+}
+
+impl GridCheckpoint {
+    fn open(path: &Path, header: CheckpointHeader) -> FailResult<Self> {
+        let mut file = {
+            std::fs::OpenOptions::new()
+                .read(true).append(true).create(true)
+                .open(path)?
+        };
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+
+        // A line without a newline was interrupted while being written; throw it away.
+        let complete_len = text.rfind('\n').map_or(0, |i| i + 1);
+        if complete_len < text.len() {
+            file.set_len(complete_len as u64)?;
+        }
+        let mut lines = text[..complete_len].lines();
+
+        let mut done = HashMap::new();
+        match lines.next() {
+            None => {
+                writeln!(file, "{}", serde_json::to_string(&header)?)?;
+            },
+            Some(line) => {
+                let found: CheckpointHeader = serde_json::from_str(line)?;
+                let differing = header.differing_fields(&found);
+                if !differing.is_empty() {
+                    bail!(
+                        "Checkpoint {} was written for a different scan (differs in: {}). \
+                        Delete it to start over.",
+                        path.display(), differing.join(", "),
+                    );
+                }
+                for line in lines {
+                    let CheckpointEntry { point: [x, y], grad } = serde_json::from_str(line)?;
+                    ensure!(x < header.dims[0] && y < header.dims[1], "Checkpoint point out of bounds.");
+                    ensure!(grad.len() == header.init_pos.len(), "Checkpoint gradient has wrong length.");
+                    done.insert((x, y), grad);
+                }
+                info!("Resuming from {}: {} points already computed.", path.display(), done.len());
+            },
+        }
+        file.flush()?;
+        Ok(GridCheckpoint { file, done })
+    }
+
+    fn take(&mut self, point: Point) -> Option<Vec<V3>>
+    { self.done.remove(&point) }
+
+    fn record(&mut self, (x, y): Point, grad: &[V3]) -> FailResult<()> {
+        let entry = CheckpointEntry { point: [x, y], grad: grad.to_vec() };
+        writeln!(self.file, "{}", serde_json::to_string(&entry)?)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TwoDeeIntegrated {
     pub indices: Vec<[i32; 2]>,
@@ -142,7 +292,8 @@ pub fn integrate_grid_random<M, E>(
     // with the expectation that this will reduce some forms of bias.
     let Tree { root, edges } = random_tree(vertices.clone(), out_edges);
 
-    let index = |(x, y)| (y * n_x + x);
+    // (same order as `vertices`)
+    let index = |(x, y)| x * n_y + y;
     let metas = vertices.iter()
         .map(|&v| compute_meta(v))
         .collect::<Result<Vec<_>, E>>()?;
@@ -183,3 +334,212 @@ fn linspace(r: Range<f64>, n: usize, extend_borders: bool) -> (Vec<i32>, Vec<f64
     (indices, values)
 }
 
+#[cfg(test)]
+#[deny(unused)]
+mod tests {
+    use super::*;
+
+    // an anisotropic harmonic well
+    const STIFFNESS: V3 = V3([1.0, 3.0, 5.0]);
+
+    fn harmonic_value(pos: &[V3]) -> f64 {
+        pos.iter()
+            .map(|p| (0..3).map(|k| 0.5 * STIFFNESS[k] * p[k] * p[k]).sum::<f64>())
+            .sum()
+    }
+
+    fn harmonic_grad(pos: &[V3]) -> Vec<V3>
+    { pos.iter().map(|p| V3::from_fn(|k| STIFFNESS[k] * p[k])).collect() }
+
+    // two orthogonal directions that each move a different atom
+    fn init_pos_and_directions() -> (Vec<V3>, [Vec<V3>; 2]) {
+        let init_pos = vec![V3([0.5, 0.0, 0.0]), V3([0.0, -1.0, 2.0])];
+        let directions = [
+            vec![V3([1.0, 0.0, 0.0]), V3([0.0, 0.0, 0.0])],
+            vec![V3([0.0, 0.0, 0.0]), V3([0.0, 2.0, 0.0])],
+        ];
+        (init_pos, directions)
+    }
+
+    // (the integrated values are relative to an arbitrary point)
+    fn assert_values_close_up_to_offset(actual: &[f64], expected: &[f64]) {
+        let offset = actual[0] - expected[0];
+        for (&actual, &expected) in izip!(actual, expected) {
+            assert_close!(abs=1e-10, actual - offset, expected);
+        }
+    }
+
+    #[test]
+    fn orthogonal_directions() {
+        let (init_pos, directions) = init_pos_and_directions();
+        let data = integrate_two_directions(
+            [5, 7],
+            &init_pos,
+            [-1.0..1.0, 0.0..0.5],
+            [false, false],
+            [&directions[0], &directions[1]],
+            None,
+            |pos| Ok::<_, failure::Error>(harmonic_grad(pos)),
+        ).unwrap();
+        assert_eq!(data.indices.len(), 35);
+        assert_eq!(data.values.len(), 35);
+
+        let expected: Vec<_> = {
+            data.coords.iter()
+                .map(|&[a, b]| {
+                    let pos: Vec<_> = {
+                        izip!(&init_pos, &directions[0], &directions[1])
+                            .map(|(&p, &da, &db)| p + a * da + b * db)
+                            .collect()
+                    };
+                    harmonic_value(&pos)
+                })
+                .collect()
+        };
+        for (&[ix, iy], &[a, b]) in izip!(&data.indices, &data.coords) {
+            assert_close!(abs=1e-12, a, -1.0 + 0.5 * ix as f64);
+            assert_close!(abs=1e-12, b, 0.5 * iy as f64 / 6.0);
+        }
+        assert_values_close_up_to_offset(&data.values, &expected);
+    }
+
+    #[test]
+    fn resume_from_checkpoint() -> FailResult<()> {
+        let tmp = rsp2_fs_util::TempDir::new_labeled("rsp2", "integrate_2d checkpoint test")?;
+        let checkpoint = tmp.path().join("checkpoint");
+        let (init_pos, directions) = init_pos_and_directions();
+        let context = serde_json::json!({"potential": "harmonic"});
+
+        // Runs the scan, failing after `max_calls` calls to the potential.
+        // Returns the result and the number of calls made.
+        let run_with = |
+            dims: [usize; 2],
+            init_pos: &[V3],
+            directions: [&[V3]; 2],
+            checkpoint: Option<(&Path, serde_json::Value)>,
+            max_calls: usize,
+        | {
+            let mut calls = 0;
+            let result: FailResult<_> = integrate_two_directions(
+                dims,
+                init_pos,
+                [-1.0..1.0, 0.0..0.5],
+                [false, false],
+                directions,
+                checkpoint,
+                |pos| {
+                    if calls == max_calls {
+                        bail!("interrupted!");
+                    }
+                    calls += 1;
+                    Ok(harmonic_grad(pos))
+                },
+            );
+            (result, calls)
+        };
+        let run = |dims: [usize; 2], checkpoint: Option<&Path>, max_calls: usize| {
+            let checkpoint = checkpoint.map(|path| (path, context.clone()));
+            run_with(dims, &init_pos, [&directions[0], &directions[1]], checkpoint, max_calls)
+        };
+
+        let num_points = 4 * 6;
+        let (result, calls) = run([4, 6], Some(&checkpoint), num_points / 2);
+        assert!(result.is_err());
+        assert_eq!(calls, num_points / 2);
+
+        // a different grid must not reuse the checkpoint
+        let (result, calls) = run([6, 4], Some(&checkpoint), num_points);
+        assert!(result.is_err());
+        assert_eq!(calls, 0);
+
+        // ...nor may a different structure, direction, or potential
+        let moved_pos: Vec<_> = init_pos.iter().map(|&p| p + V3([0.0, 0.0, 0.1])).collect();
+        let other_context = serde_json::json!({"potential": "anharmonic"});
+        let mismatches = vec![
+            (&moved_pos[..], [&directions[0][..], &directions[1][..]], context.clone()),
+            (&init_pos[..], [&directions[1][..], &directions[0][..]], context.clone()),
+            (&init_pos[..], [&directions[0][..], &directions[1][..]], other_context),
+        ];
+        for (init_pos, directions, context) in mismatches {
+            let (result, calls) = run_with([4, 6], init_pos, directions, Some((&checkpoint, context)), num_points);
+            assert!(result.is_err());
+            assert_eq!(calls, 0);
+        }
+
+        let (resumed, calls) = run([4, 6], Some(&checkpoint), num_points);
+        let resumed = resumed?;
+        assert_eq!(calls, num_points - num_points / 2);
+
+        let (fresh, _) = run([4, 6], None, num_points);
+        let fresh = fresh?;
+        assert_eq!(resumed.indices, fresh.indices);
+        assert_eq!(resumed.coords, fresh.coords);
+        assert_values_close_up_to_offset(&resumed.values, &fresh.values);
+        Ok(())
+    }
+
+    // Floats that aren't exactly representable in decimal must not prevent resuming,
+    // even if they don't survive the trip through JSON exactly.
+    #[test]
+    fn resume_with_inexact_floats() -> FailResult<()> {
+        let tmp = rsp2_fs_util::TempDir::new_labeled("rsp2", "integrate_2d checkpoint test")?;
+        let checkpoint = tmp.path().join("checkpoint");
+        let context = serde_json::json!({"potential": "harmonic"});
+
+        let num_atoms = 20;
+        let arbitrary = |seed: f64| (0..num_atoms).map(|i| V3::from_fn(|k| {
+            f64::sin(seed + 0.1 * i as f64 + k as f64 / 3.0) / 7.0
+        })).collect::<Vec<_>>();
+        let init_pos = arbitrary(1.0);
+        let directions = [arbitrary(2.0), arbitrary(3.0)];
+
+        let run = |max_calls: usize| {
+            let mut calls = 0;
+            let result: FailResult<_> = integrate_two_directions(
+                [3, 3],
+                &init_pos,
+                [-0.1..0.3, 1.0 / 3.0..0.7],
+                [false, false],
+                [&directions[0], &directions[1]],
+                Some((&checkpoint, context.clone())),
+                |pos| {
+                    if calls == max_calls {
+                        bail!("interrupted!");
+                    }
+                    calls += 1;
+                    Ok(harmonic_grad(pos))
+                },
+            );
+            (result, calls)
+        };
+
+        let (result, calls) = run(4);
+        assert!(result.is_err());
+        assert_eq!(calls, 4);
+
+        let (result, calls) = run(9);
+        result?;
+        assert_eq!(calls, 9 - 4);
+
+        // exercise the tolerance directly, since serde_json might happen to round-trip
+        // all of the above exactly
+        let header = CheckpointHeader {
+            dims: [3, 3],
+            ranges: [[-0.1, 0.3], [1.0 / 3.0, 0.7]],
+            extend_borders: [false, false],
+            init_pos: init_pos.clone(),
+            directions: directions.clone(),
+            context: context.clone(),
+        };
+        let mut off_by_an_ulp = header.clone();
+        for x in off_by_an_ulp.init_pos.flat_mut() {
+            *x = f64::from_bits(x.to_bits() + 1);
+        }
+        assert!(header.differing_fields(&off_by_an_ulp).is_empty());
+
+        let mut moved = header.clone();
+        moved.init_pos[0][0] += 1e-6;
+        assert_eq!(header.differing_fields(&moved), vec!["init-pos"]);
+        Ok(())
+    }
+}
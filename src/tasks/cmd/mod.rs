@@ -21,7 +21,7 @@ use self::ev_analyses::GammaSystemAnalysis;
 use self::param_optimization::ScalableCoords;
 mod ev_analyses;
 
-use self::trial::TrialDir;
+use self::trial::{TrialDir, NewTrialDirArgs};
 pub(crate) mod trial;
 
 pub(crate) use crate::filetypes::stored_structure::StoredStructure;
@@ -44,7 +44,7 @@ use rsp2_dynmat::{ForceConstants, DynamicalMatrix};
 use crate::meta::{self, prelude::*};
 use crate::util::ext_traits::{OptionResultExt, PathNiceExt};
 use crate::math::{
-    basis::{GammaBasis3, EvDirection},
+    basis::{GammaBasis3, EvDirection, Ket3},
     bands::{ScMatrix},
 };
 use self::acoustic_search::ModeKind;
@@ -63,7 +63,7 @@ use rsp2_structure::{
 use rsp2_fs_util::{create, rm_rf, hard_link};
 
 use std::{
-    path::{PathBuf},
+    path::{Path, PathBuf},
     io::{Write},
     ffi::{OsStr, OsString},
     collections::{BTreeMap},
@@ -117,75 +117,188 @@ pub(crate) struct StoppedEarly;
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum StopAfter { Cg, Dynmat, DontStop }
 
+fn check_settings_for_stop_after(settings: &Settings, stop_after: StopAfter) -> FailResult<()> {
+    match (stop_after, &settings.phonons) {
+        (StopAfter::Dynmat, None) |
+        (StopAfter::DontStop, None) => bail!("`phonons` config section is required"),
+        (StopAfter::Cg, None) => {},
+        (_, Some(_)) => {},
+    }
+    Ok(())
+}
+
+/// Implementation of `--dry-run` for `run_relax_with_eigenvectors`.
+///
+/// Performs the checks of a real run that can be done without a trial directory or a potential,
+/// and logs a summary of the system.  Anything that would abort the real run is an error.
+pub(crate) fn dry_run_relax_with_eigenvectors(
+    settings: &Settings,
+    file_format: StructureFileType,
+    input: &PathAbs,
+    mass_scales: &[(meta::Element, f64)],
+    stop_after: StopAfter,
+) -> FailResult<()>
+{Ok({
+    check_settings_for_stop_after(settings, stop_after)?;
+
+    // (this also performs the layer search, if enabled)
+    let (optimizable_coords, meta) = {
+        read_optimizable_structure(
+            settings.layer_search.as_ref(),
+            settings.masses.as_ref(),
+            settings.site_masses.as_ref(),
+            file_format, input,
+        )?
+    };
+    // (this logs the scale factors, and warns about elements missing from the structure)
+    let _ = scale_masses(mass_scales, &meta.pick(), &meta.pick());
+    let coords = optimizable_coords.construct();
+    let num_atoms = coords.num_atoms();
+
+    info!("Dry run of {}", input.nice());
+    info!("           atoms: {}", num_atoms);
+
+    let layers: Option<meta::SiteLayers> = meta.pick();
+    if let Some(layers) = layers {
+        let num_layers = layers.iter().map(|&meta::Layer(layer)| layer + 1).max().unwrap_or(0);
+        info!("          layers: {}", num_layers);
+    }
+
+    if let Some(phonons) = &settings.phonons {
+        // NOTE: The lattice may still change during lattice parameter optimization,
+        //       which can affect a supercell given by target lengths.
+        let sc_dim = phonons.supercell.dim_for_unitcell(coords.lattice());
+        let sc_size = sc_dim.iter().product::<u32>() as usize;
+        ensure!(sc_size > 0, "supercell has zero volume: {:?}", sc_dim);
+        info!("       supercell: {:?} ({} atoms)", sc_dim, sc_size * num_atoms);
+
+        if phonons.analytic_hessian {
+            info!("   displacements: none (analytic hessian)");
+        } else {
+            // without symmetry, each atom must be displaced along three independent directions
+            info!("   displacements: at most {} (fewer with symmetry)", 3 * num_atoms);
+        }
+    }
+
+    info!("Dry run found no problems.");
+})}
+
+/// Where `run_relax_with_eigenvectors` gets its initial structure.
+pub(crate) enum EvLoopStart<'a> {
+    /// Read a structure file.
+    Input(&'a PathAbs, StructureFileType),
+    /// Continue from the ev-loop checkpoint in the trial directory.
+    Resume,
+}
+
 impl TrialDir {
     pub(crate) fn run_relax_with_eigenvectors(
         self,
         on_demand: Option<LammpsOnDemand>,
         settings: &Settings,
-        file_format: StructureFileType,
-        input: &PathAbs,
+        start: EvLoopStart<'_>,
+        // factors from `--scale-mass`
+        mass_scales: &[(meta::Element, f64)],
         // shameful HACK
         stop_after: StopAfter,
+        write_trajectory: bool,
     ) -> FailResult<()>
-    {Ok({
-        match (stop_after, &settings.phonons) {
-            (StopAfter::Dynmat, None) |
-            (StopAfter::DontStop, None) => bail!("`phonons` config section is required"),
-            (StopAfter::Cg, None) => {},
-            (_, Some(_)) => {},
-        }
+    {
+        check_settings_for_stop_after(settings, stop_after)?;
 
         let pot = PotentialBuilder::from_root_config(Some(&self), on_demand, &settings)?;
 
-        let (optimizable_coords, mut meta) = {
-            read_optimizable_structure(
-                settings.layer_search.as_ref(),
-                settings.masses.as_ref(),
-                file_format, input,
-            )?
-        };
+        self.run_relax_with_eigenvectors_using_pot(
+            &*pot, settings, start, mass_scales, stop_after, write_trajectory,
+        )
+    }
 
-        let original_coords = {
-            // (can't reliably get bonds until the lattice parameter is correct)
-            crate::cmd::param_optimization::optimize_layer_parameters(
-                &settings.scale_ranges,
-                &pot,
-                optimizable_coords,
-                meta.sift(),
-            )?.construct()
-        };
+    /// The body of `run_relax_with_eigenvectors`, for a potential that has already been
+    /// constructed.  (`check_settings_for_stop_after` is the caller's responsibility)
+    fn run_relax_with_eigenvectors_using_pot(
+        &self,
+        pot: &dyn PotentialBuilder,
+        settings: &Settings,
+        start: EvLoopStart<'_>,
+        mass_scales: &[(meta::Element, f64)],
+        stop_after: StopAfter,
+        write_trajectory: bool,
+    ) -> FailResult<()>
+    {Ok({
+        let (original_coords, meta, resume_from) = match start {
+            EvLoopStart::Resume => {
+                let checkpoint_path = self.ev_loop_checkpoint_path();
+                if !checkpoint_path.exists() {
+                    bail!("{}: does not exist; there is nothing to resume", checkpoint_path.nice());
+                }
+                let Json(checkpoint): Json<relaxation::EvLoopCheckpoint> = Load::load(checkpoint_path)?;
+                trace!("Resuming ev-loop at iteration {}", checkpoint.state.iteration);
 
-        // Compute the bonds only if they were not part of the input.
-        trace!{"Computing intralayer bonds..."}
-        let bonds: &mut Option<meta::FracBonds> = meta.get_mut();
-        if bonds.is_none() {
-            *bonds = settings.bond_radius.map(|bond_radius| FailOk({
-                Rc::new(FracBonds::compute(&original_coords, bond_radius)?)
-            })).fold_ok()?
-        }
+                let (coords, meta) = self.read_stored_structure_data(&checkpoint.structure)?;
+                (coords, meta, Some(checkpoint))
+            },
+            EvLoopStart::Input(input, file_format) => {
+                let (optimizable_coords, mut meta) = {
+                    read_optimizable_structure(
+                        settings.layer_search.as_ref(),
+                        settings.masses.as_ref(),
+                        settings.site_masses.as_ref(),
+                        file_format, input,
+                    )?
+                };
+                if !mass_scales.is_empty() {
+                    let scaled = scale_masses(mass_scales, &meta.pick(), &meta.pick());
+                    let masses: &mut meta::SiteMasses = meta.get_mut();
+                    *masses = scaled;
+                }
 
-        self.write_stored_structure(
-            &self.structure_path(EvLoopStructureKind::Initial),
-            "Initial structure (after lattice optimization)",
-            &original_coords, meta.sift(),
-        )?;
+                let original_coords = {
+                    // (can't reliably get bonds until the lattice parameter is correct)
+                    crate::cmd::param_optimization::optimize_layer_parameters(
+                        &settings.scale_ranges,
+                        pot,
+                        optimizable_coords,
+                        meta.sift(),
+                    )?.construct()
+                };
+
+                // Compute the bonds only if they were not part of the input.
+                trace!{"Computing intralayer bonds..."}
+                let bonds: &mut Option<meta::FracBonds> = meta.get_mut();
+                if bonds.is_none() {
+                    *bonds = settings.bond_radius.map(|bond_radius| FailOk({
+                        Rc::new(FracBonds::compute_maybe_parallel(&original_coords, bond_radius, use_rayon_for_bonds(settings))?)
+                    })).fold_ok()?
+                }
+
+                self.write_stored_structure(
+                    &self.structure_path(EvLoopStructureKind::Initial),
+                    "Initial structure (after lattice optimization)",
+                    &original_coords, meta.sift(),
+                )?;
+                (original_coords, meta, None)
+            },
+        };
+
+        // (resolved only after lattice optimization, since it may affect the symmetry)
+        let settings = &self.resolve_symmetry_tolerance(settings, &original_coords, meta.sift())?;
 
         let (coords, ev_analysis) = {
             let (coords, stuff) = {
                 self.do_main_ev_loop(
-                    settings, &*pot, original_coords, meta.sift(),
-                    stop_after,
+                    settings, pot, original_coords, resume_from, meta.sift(),
+                    stop_after, write_trajectory,
                 )?
             };
 
             // HACK: Put last gamma dynmat at a predictable path.
             rm_rf(self.join("gamma-dynmat.json"))?;
-            if let Some((ev_analysis, final_iteration)) = stuff {
+            if let Some((ev_analysis, final_iteration, converged)) = stuff {
                 hard_link(
                     self.gamma_dynmat_path(final_iteration),
                     self.final_gamma_dynmat_path(),
                 )?;
-                (coords, Some(ev_analysis))
+                (coords, Some((ev_analysis, final_iteration, converged)))
             } else {
                 (coords, None)
             }
@@ -197,18 +310,190 @@ impl TrialDir {
             &coords, meta.sift(),
         )?;
 
-        if let Some(ev_analysis) = ev_analysis {
+        if let Some((ev_analysis, final_iteration, converged)) = ev_analysis {
             write_eigen_info_for_machines(&ev_analysis, self.create_file("eigenvalues.final")?)?;
 
-            write_ev_analysis_output_files(&self, &ev_analysis)?;
-            self.write_summary_file(settings, &*pot, &ev_analysis)?;
+            write_ev_analysis_output_files(self, &ev_analysis, &settings.raman)?;
+            self.write_summary_file(settings, pot, &ev_analysis)?;
+            self.write_json_summary_file(pot, &ev_analysis, final_iteration, converged)?;
+
+            if let Some(gruneisen_settings) = &settings.gruneisen {
+                self.write_mode_gruneisen(
+                    settings, gruneisen_settings, pot, &coords, meta.sift(), &ev_analysis,
+                )?;
+            }
+            if let Some(thermal_settings) = &settings.thermal {
+                self.write_thermal_properties(thermal_settings, &ev_analysis)?;
+            }
+            if let Some(peak_settings) = &settings.peak_assignment {
+                self.write_peak_assignment(peak_settings, &ev_analysis)?;
+            }
         }
     })}
 }
 
+//=================================================================
+
+/// One structure from the input to a batch run.
+pub(crate) struct BatchFrame {
+    /// Name of the frame's trial directory, inside the batch output directory.
+    pub(crate) name: String,
+    input: BatchFrameInput,
+}
+
+enum BatchFrameInput {
+    // A frame of a multi-frame XYZ file.  (it is written into the trial directory to be read)
+    Xyz(rsp2_structure_io::Xyz),
+    // A structure file (or structure directory) inside the input directory.
+    Path(PathAbs, StructureFileType),
+}
+
+/// Split the input to a batch run into frames.
+///
+/// The input may be either a multi-frame XYZ file, or a directory that contains one
+/// structure per entry.  Entries of a directory are taken in sorted order, skipping
+/// hidden files.
+pub(crate) fn read_batch_frames(
+    input: &PathAbs,
+    guess_file_format: impl Fn(&PathAbs) -> StructureFileType,
+) -> FailResult<Vec<BatchFrame>>
+{Ok({
+    let frames = if input.as_path().is_dir() {
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(input)? {
+            let path = entry?.path();
+            match path.file_name().and_then(|s| s.to_str()) {
+                Some(name) if name.starts_with('.') => {},
+                _ => paths.push(path),
+            }
+        }
+        paths.sort();
+
+        paths.into_iter()
+            .map(|path| FailOk({
+                let name = path.file_name().expect("(BUG) read_dir gave no filename").to_string_lossy().into_owned();
+                let path = PathAbs::new(path)?;
+                let file_format = guess_file_format(&path);
+                BatchFrame { name, input: BatchFrameInput::Path(path, file_format) }
+            }))
+            .collect::<FailResult<Vec<_>>>()?
+    } else {
+        if guess_file_format(input) != StructureFileType::Xyz {
+            bail!("{}: the input to a batch run must be a directory or an XYZ file", input.nice());
+        }
+
+        let file = std::io::BufReader::new(FileRead::open(input)?);
+        rsp2_structure_io::Xyz::frames(file)
+            .enumerate()
+            .map(|(index, frame)| FailOk({
+                let name = format!("frame-{:04}", index);
+                BatchFrame { name, input: BatchFrameInput::Xyz(frame?) }
+            }))
+            .collect::<FailResult<Vec<_>>>()?
+    };
+
+    if frames.is_empty() {
+        bail!("{}: no structures found for the batch run", input.nice());
+    }
+    frames
+})}
+
+/// Create the output directory of a batch run, which will hold one trial directory per frame.
+pub(crate) fn create_batch_dir(dir_args: &NewTrialDirArgs) -> FailResult<PathDir>
+{Ok({
+    let NewTrialDirArgs { ref trial_dir, err_if_existing, .. } = *dir_args;
+    if !err_if_existing {
+        rm_rf(trial_dir)?;
+    }
+    if trial_dir.exists() {
+        bail!(
+            "'{}': Output directory already exists! \
+            Use --force if you really want to replace it.",
+            trial_dir.nice(),
+        )
+    }
+    PathDir::create(trial_dir)?
+})}
+
+/// Run `run_relax_with_eigenvectors` on each frame of a batch run, each in its own trial
+/// directory inside `batch_dir`.  The potential is shared between all of the frames.
+///
+/// A failure on one frame does not prevent the others from running.  Once every frame
+/// has been attempted, the outcome of each one is written to `batch-summary.json`, and
+/// an error is returned if any of them failed.
+pub(crate) fn run_batch_relax_with_eigenvectors(
+    on_demand: Option<LammpsOnDemand>,
+    settings: &Settings,
+    batch_dir: &PathDir,
+    dir_args: NewTrialDirArgs,
+    frames: Vec<BatchFrame>,
+    // factors from `--scale-mass`
+    mass_scales: &[(meta::Element, f64)],
+    stop_after: StopAfter,
+    write_trajectory: bool,
+) -> FailResult<()>
+{Ok({
+    check_settings_for_stop_after(settings, stop_after)?;
+
+    let pot = PotentialBuilder::from_root_config(None, on_demand, &settings)?;
+
+    let NewTrialDirArgs { config_sources, strict_config, .. } = dir_args;
+    let run_frame = |frame: BatchFrame| FailOk({
+        let mut trial = TrialDir::create_new(NewTrialDirArgs {
+            trial_dir: batch_dir.join(&frame.name),
+            config_sources: config_sources.clone(),
+            err_if_existing: true,
+            strict_config,
+        })?;
+        let cfg::ValidatedSettings(settings) = trial.read_base_settings()?;
+
+        let (file_format, input) = match frame.input {
+            BatchFrameInput::Path(path, file_format) => (file_format, path),
+            BatchFrameInput::Xyz(xyz) => {
+                xyz.to_writer(trial.create_file("input.xyz")?)?;
+                (StructureFileType::Xyz, PathAbs::new(trial.join("input.xyz"))?)
+            },
+        };
+        trial.run_relax_with_eigenvectors_using_pot(
+            &*pot, &settings, EvLoopStart::Input(&input, file_format), mass_scales, stop_after, write_trajectory,
+        )?;
+    });
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct FrameOutcome {
+        name: String,
+        /// `None` if the frame succeeded.
+        error: Option<String>,
+    }
+
+    let num_frames = frames.len();
+    let mut outcomes = vec![];
+    for (index, frame) in frames.into_iter().enumerate() {
+        let name = frame.name.clone();
+        info!("Batch frame {} of {}: {}", index + 1, num_frames, name);
+
+        let error = match run_frame(frame) {
+            Ok(()) => None,
+            Err(e) => {
+                error!("Batch frame {} failed: {}", name, e);
+                Some(e.to_string())
+            },
+        };
+        outcomes.push(FrameOutcome { name, error });
+    }
+    Json(&outcomes).save(batch_dir.join("batch-summary.json"))?;
+
+    let failed = outcomes.iter().filter(|x| x.error.is_some()).map(|x| &x.name[..]).collect::<Vec<_>>();
+    if !failed.is_empty() {
+        bail!("{} of {} frames failed: {}", failed.len(), num_frames, failed.join(", "));
+    }
+})}
+
 pub(crate) fn write_ev_analysis_output_files(
     dir: &PathDir,
     eva: &GammaSystemAnalysis,
+    raman_settings: &cfg::Raman,
 ) -> FailResult<()>
 {Ok({
     use path_abs::FileWrite;
@@ -217,17 +502,50 @@ pub(crate) fn write_ev_analysis_output_files(
         #[derive(Serialize)]
         #[serde(rename_all = "kebab-case")]
         struct Output {
+            ev_index: Vec<usize>,
             frequency: Vec<f64>,
             raman_tensor: Vec<M33>,
             average_3d: Vec<f64>,
             backscatter: Vec<f64>,
+            // using `raman.polarization`
+            intensity: Vec<f64>,
         }
+
+        // modes outside the frequency window have no tensor, and are left out entirely.
+        let (ev_indices, tensors): (Vec<_>, Vec<_>) = {
+            raman.0.iter().enumerate()
+                .filter_map(|(i, t)| t.as_ref().map(|t| (i, t)))
+                .unzip()
+        };
+        let frequency = ev_indices.iter().map(|&i| frequency.0[i]).collect_vec();
+
         use crate::math::bond_polarizability::LightPolarization::*;
+        let polarization = crate::math::bond_polarizability::light_polarization(&raman_settings.polarization);
         serde_json::to_writer(FileWrite::create(dir.join("raman.json"))?, &Output {
-            frequency: frequency.0.to_vec(),
-            raman_tensor: raman.0.iter().map(|t| t.tensor().clone()).collect(),
-            average_3d: raman.0.iter().map(|t| t.integrate_intensity(&Average)).collect(),
-            backscatter: raman.0.iter().map(|t| t.integrate_intensity(&BackscatterZ)).collect(),
+            ev_index: ev_indices.clone(),
+            frequency: frequency.clone(),
+            raman_tensor: tensors.iter().map(|t| t.tensor().clone()).collect(),
+            average_3d: tensors.iter().map(|t| t.integrate_intensity(&Average)).collect(),
+            backscatter: tensors.iter().map(|t| t.integrate_intensity(&BackscatterZ)).collect(),
+            intensity: tensors.iter().map(|t| t.integrate_intensity(&polarization)).collect(),
+        })?;
+
+        write_raman_active_modes(dir, &ev_indices, &frequency, &tensors)?;
+        write_raman_spectrum(dir, &frequency, &tensors)?;
+    }
+
+    if let (Some(frequency), Some(ir_intensities)) = (&eva.ev_frequencies, &eva.ev_ir_intensities) {
+        #[derive(Serialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Output<'a> {
+            frequency: &'a [f64],
+            // (e^2 / AMU)
+            intensity: &'a [f64],
+        }
+
+        serde_json::to_writer(FileWrite::create(dir.join("ir.json"))?, &Output {
+            frequency: &frequency.0,
+            intensity: &ir_intensities.0,
         })?;
     }
 
@@ -242,16 +560,301 @@ pub(crate) fn write_ev_analysis_output_files(
 
         serde_json::to_writer(FileWrite::create(dir.join("unfold.json"))?, &Output {
             layer_sc_dims: sc_mats.iter().map(|m| m.periods).collect(),
-            layer_q_indices: {
-                unfold_probs.layer_unfolders.iter()
-                    .map(|u| u.q_indices().to_vec())
-                    .collect()
-            },
+            layer_q_indices: unfold_probs.layer_q_indices.clone(),
             layer_ev_q_probs: unfold_probs.layer_ev_q_probs.clone(),
         })?;
     }
 })}
 
+/// Number of modes listed in `raman-active-modes.json`.
+const RAMAN_ACTIVE_MODE_COUNT: usize = 10;
+/// Frequency difference (cm^-1) below which modes are considered degenerate
+/// when ranking raman activity.
+const RAMAN_DEGENERACY_TOL: f64 = 1e-2;
+
+// `ev_indices` gives the index of each listed mode among all eigenvectors.
+fn write_raman_active_modes(
+    dir: &PathDir,
+    ev_indices: &[usize],
+    ev_frequencies: &[f64],
+    ev_tensors: &[&crate::math::bond_polarizability::RamanTensor],
+) -> FailResult<()>
+{Ok({
+    use path_abs::FileWrite;
+    use crate::math::bond_polarizability::{most_active_modes, ActiveMode};
+    use crate::math::bond_polarizability::LightPolarization::{self, *};
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct Output {
+        average_3d: Vec<ActiveMode>,
+        backscatter: Vec<ActiveMode>,
+    }
+
+    let ranked = |polarization: &LightPolarization| {
+        let intensities = ev_tensors.iter().map(|t| t.integrate_intensity(polarization)).collect_vec();
+        let mut modes = most_active_modes(ev_frequencies, &intensities, RAMAN_DEGENERACY_TOL, RAMAN_ACTIVE_MODE_COUNT);
+        for mode in &mut modes {
+            for index in &mut mode.ev_indices {
+                *index = ev_indices[*index];
+            }
+        }
+        modes
+    };
+    let output = Output {
+        average_3d: ranked(&Average),
+        backscatter: ranked(&BackscatterZ),
+    };
+
+    info!("Most raman-active modes (3D average):");
+    for mode in &output.average_3d {
+        info!(
+            " {:>10.4} cm-1  intensity {:>9.3e}  (x{})",
+            mode.frequency, mode.intensity, mode.ev_indices.len(),
+        );
+    }
+
+    serde_json::to_writer(FileWrite::create(dir.join("raman-active-modes.json"))?, &output)?;
+})}
+
+/// Full width at half maximum (cm^-1) of the peaks in `raman-spectrum.json`.
+const RAMAN_SPECTRUM_LINEWIDTH: f64 = 10.0;
+/// Number of samples in `raman-spectrum.json`.
+const RAMAN_SPECTRUM_POINTS: usize = 4001;
+
+fn write_raman_spectrum(
+    dir: &PathDir,
+    ev_frequencies: &[f64],
+    ev_tensors: &[&crate::math::bond_polarizability::RamanTensor],
+) -> FailResult<()>
+{Ok({
+    use path_abs::FileWrite;
+    use crate::math::raman_spectrum::{raman_spectrum, Normalization};
+    use crate::math::bond_polarizability::LightPolarization::{self, *};
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct Output {
+        linewidth: f64,
+        frequency: Vec<f64>,
+        average_3d: Vec<f64>,
+        backscatter: Vec<f64>,
+    }
+
+    // leave some room past the highest mode for its tail
+    let max_frequency = ev_frequencies.iter().fold(0.0, |acc, &x| f64::max(acc, x));
+    let range = (0.0, max_frequency + 10.0 * RAMAN_SPECTRUM_LINEWIDTH);
+
+    let spectrum = |polarization: &LightPolarization| {
+        // NOTE: intensities of imaginary modes are not meaningful, so they are excluded.
+        let modes = zip_eq!(ev_frequencies, ev_tensors)
+            .filter(|&(&frequency, _)| frequency > 0.0)
+            .map(|(&frequency, t)| (frequency, f64::max(0.0, t.integrate_intensity(polarization))))
+            .collect_vec();
+        raman_spectrum(
+            &modes, RAMAN_SPECTRUM_LINEWIDTH, Normalization::Area,
+            range, RAMAN_SPECTRUM_POINTS,
+        )
+    };
+    let average_3d = spectrum(&Average);
+    let backscatter = spectrum(&BackscatterZ);
+
+    serde_json::to_writer(FileWrite::create(dir.join("raman-spectrum.json"))?, &Output {
+        linewidth: RAMAN_SPECTRUM_LINEWIDTH,
+        frequency: average_3d.iter().map(|&(x, _)| x).collect(),
+        average_3d: average_3d.iter().map(|&(_, y)| y).collect(),
+        backscatter: backscatter.iter().map(|&(_, y)| y).collect(),
+    })?;
+})}
+
+/// Frequency difference (cm^-1) below which modes are considered degenerate
+/// when matching modes for Grüneisen parameters.
+const GRUNEISEN_DEGENERACY_TOL: f64 = 1e-2;
+/// Largest mode Grüneisen parameter (in magnitude) that can be found.  This limits how far
+/// in frequency the partner of each mode is searched for at the perturbed volumes.
+const GRUNEISEN_MAX_ABS: f64 = 20.0;
+/// Extra width (cm^-1) of the frequency window searched for the partner of each mode.
+/// (acoustic modes can wobble by this much from numerical noise alone)
+const GRUNEISEN_FREQUENCY_SLACK: f64 = 5.0;
+
+impl TrialDir {
+    fn write_mode_gruneisen(
+        &self,
+        settings: &Settings,
+        gruneisen_settings: &cfg::Gruneisen,
+        pot: &dyn PotentialBuilder,
+        coords: &Coords,
+        meta: HList4<
+            meta::SiteElements,
+            meta::SiteMasses,
+            Option<meta::SiteLayers>,
+            Option<meta::FracBonds>,
+        >,
+        ev_analysis: &GammaSystemAnalysis,
+    ) -> FailResult<()>
+    {Ok({
+        use path_abs::FileWrite;
+        use crate::math::gruneisen::{mode_gruneisen, Modes, MatchSettings};
+
+        let phonons_settings = settings.phonons.as_ref().expect("(BUG) validation should require phonons!");
+        let &cfg::Gruneisen { volume_step, min_overlap } = gruneisen_settings;
+
+        info!("Computing mode Grüneisen parameters");
+
+        // the final dynamical matrix was already computed during the ev loop
+        let final_dynmat = Load::load(self.final_gamma_dynmat_path())?;
+        let (freqs, evecs) = pot.eco_mode(|eco_proof| {
+            do_diagonalize_dynmat(phonons_settings, final_dynmat, eco_proof)
+        })?;
+
+        // (the scaled structures are deliberately not relaxed; see `math::gruneisen`)
+        let diagonalize_at_volume = |volume_scale: f64| -> FailResult<_> {
+            let scale = volume_scale.cbrt();
+            let mut coords = coords.clone();
+            coords.scale_vecs(&[scale; 3]);
+
+            // (no trial dir, so that displacement-symmetry.json keeps describing the real structure)
+            let qpoint = V3::zero();
+            let dynmat = do_compute_dynmat(
+                None, settings, phonons_settings, pot, qpoint, &coords, meta.clone(),
+                &mut SymmetryCache::new(),
+            )?;
+            pot.eco_mode(|eco_proof| do_diagonalize_dynmat(phonons_settings, dynmat, eco_proof))
+        };
+        let (freqs_minus, evecs_minus) = diagonalize_at_volume(1.0 - volume_step)?;
+        let (freqs_plus, evecs_plus) = diagonalize_at_volume(1.0 + volume_step)?;
+
+        let mut gruneisen = mode_gruneisen(
+            volume_step,
+            Modes { frequencies: &freqs, eigenvectors: &evecs.0 },
+            Modes { frequencies: &freqs_minus, eigenvectors: &evecs_minus.0 },
+            Modes { frequencies: &freqs_plus, eigenvectors: &evecs_plus.0 },
+            MatchSettings {
+                min_overlap,
+                degeneracy_tol: GRUNEISEN_DEGENERACY_TOL,
+                max_abs_gruneisen: GRUNEISEN_MAX_ABS,
+                frequency_slack: GRUNEISEN_FREQUENCY_SLACK,
+            },
+        );
+
+        let num_unmatched = {
+            zip_eq!(&freqs, &gruneisen)
+                .filter(|&(&freq, gamma)| freq > 0.0 && gamma.is_none())
+                .count()
+        };
+        if num_unmatched > 0 {
+            warn!("\
+                {} modes could not be reliably matched at the perturbed volumes, \
+                and have no Grüneisen parameter.\
+            ", num_unmatched);
+        }
+
+        // Acoustic modes have no meaningful Grüneisen parameter.
+        if let Some(classifications) = &ev_analysis.ev_classifications {
+            for (gamma, kind) in zip_eq!(&mut gruneisen, &classifications.0) {
+                match kind {
+                    ModeKind::Translational |
+                    ModeKind::Rotational |
+                    ModeKind::OtherAcoustic => *gamma = None,
+                    ModeKind::Imaginary |
+                    ModeKind::Suspicious |
+                    ModeKind::Ignored |
+                    ModeKind::Vibrational => {},
+                }
+            }
+        }
+
+        #[derive(Serialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Output {
+            volume_step: f64,
+            frequency: Vec<f64>,
+            gruneisen: Vec<Option<f64>>,
+        }
+        serde_json::to_writer(FileWrite::create(self.join("gruneisen.json"))?, &Output {
+            volume_step,
+            frequency: freqs,
+            gruneisen,
+        })?;
+    })}
+
+    fn write_thermal_properties(
+        &self,
+        thermal_settings: &cfg::Thermal,
+        ev_analysis: &GammaSystemAnalysis,
+    ) -> FailResult<()>
+    {Ok({
+        use crate::math::thermal::{thermal_properties, ThermoPoint};
+
+        let frequencies = match &ev_analysis.ev_frequencies {
+            Some(frequencies) => frequencies.0.clone(),
+            None => bail!("(BUG) thermal properties require frequencies"),
+        };
+        let points = thermal_properties(&[frequencies], &thermal_settings.temperatures);
+
+        #[derive(Serialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Output {
+            // Γ-only, per cell of the final structure
+            points: Vec<ThermoPoint>,
+        }
+        Json(Output { points }).save(self.join("thermal.json"))?;
+    })}
+
+    fn write_peak_assignment(
+        &self,
+        peak_settings: &cfg::PeakAssignment,
+        ev_analysis: &GammaSystemAnalysis,
+    ) -> FailResult<()>
+    {Ok({
+        use crate::math::peak_assignment::assign_peaks;
+
+        let frequencies = match &ev_analysis.ev_frequencies {
+            Some(frequencies) => &frequencies.0,
+            None => bail!("(BUG) peak assignment requires frequencies"),
+        };
+        // Only vibrational modes can be assigned to peaks.
+        let ev_indices: Vec<usize> = match &ev_analysis.ev_classifications {
+            Some(classifications) => {
+                classifications.0.iter().enumerate()
+                    .filter(|&(_, &kind)| kind == ModeKind::Vibrational)
+                    .map(|(i, _)| i)
+                    .collect()
+            },
+            None => (0..frequencies.len()).filter(|&i| frequencies[i] > 0.0).collect(),
+        };
+        let computed = ev_indices.iter().map(|&i| frequencies[i]).collect_vec();
+        let assignment = assign_peaks(&computed, &peak_settings.reference);
+        info!(
+            "Assigned {} reference peaks with an RMS deviation of {:.2} cm-1",
+            assignment.pairs.len(), assignment.rms_deviation,
+        );
+
+        #[derive(Serialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Pair {
+            ev_index: usize,
+            frequency: f64,
+            reference: f64,
+        }
+
+        #[derive(Serialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Output {
+            pairs: Vec<Pair>,
+            rms_deviation: f64,
+        }
+        Json(Output {
+            pairs: assignment.pairs.iter().map(|&(c, r)| Pair {
+                ev_index: ev_indices[c],
+                frequency: computed[c],
+                reference: peak_settings.reference[r],
+            }).collect(),
+            rms_deviation: assignment.rms_deviation,
+        }).save(self.join("peak-assignment.json"))?;
+    })}
+}
+
 impl TrialDir {
     // log when writing stored structures, especially during loops
     // (to remove any doubt about the iteration number)
@@ -310,6 +913,78 @@ impl TrialDir {
     { Load::load(self.join(dir.as_path())) }
 }
 
+/// Replace `phonons.symmetry-tolerance: auto` with the tolerance that it chooses for `coords`.
+///
+/// This is done once at the beginning of a run, so that every dynamical matrix computed
+/// during the run (each ev-loop iteration, gruneisen, etc.) uses the same tolerance.
+fn resolve_symmetry_tolerance(
+    settings: &Settings,
+    coords: &Coords,
+    meta: HList1<meta::SiteElements>,
+) -> FailResult<Settings>
+{Ok({
+    let mut settings = settings.clone();
+    if let Some(phonons_settings) = &mut settings.phonons {
+        if let Some(cfg::SymmetryTolerance::Auto) = phonons_settings.symmetry_tolerance {
+            let atom_types: Vec<u32> = {
+                let elements: meta::SiteElements = meta.pick();
+                elements.iter().map(|e| e.atomic_number()).collect()
+            };
+
+            trace!("Choosing symmetry-tolerance");
+            let (_, auto) = self::python::SpgDataset::compute_auto(coords, &atom_types)?;
+            info!(
+                "Chose symmetry-tolerance = {:e} ({} operators, stable from {:e} to {:e}; {:.1} decades)",
+                auto.symprec, auto.num_ops, auto.plateau.0, auto.plateau.1, auto.plateau_width(),
+            );
+            phonons_settings.symmetry_tolerance = Some(cfg::SymmetryTolerance::Fixed(auto.symprec));
+        }
+    }
+    settings
+})}
+
+impl TrialDir {
+    /// `resolve_symmetry_tolerance`, but the choice is recorded in the trial directory, so that
+    /// later commands on the same trial (`--resume`, `rsp2-after-diagonalization`) reuse it.
+    fn resolve_symmetry_tolerance(
+        &self,
+        settings: &Settings,
+        coords: &Coords,
+        meta: HList1<meta::SiteElements>,
+    ) -> FailResult<Settings>
+    {Ok({
+        let is_auto = match &settings.phonons {
+            Some(cfg::Phonons { symmetry_tolerance: Some(cfg::SymmetryTolerance::Auto), .. }) => true,
+            _ => false,
+        };
+        if !is_auto {
+            return Ok(settings.clone());
+        }
+
+        let path = self.symmetry_tolerance_path();
+        if path.exists() {
+            let Json(symprec): Json<f64> = Load::load(&path)?;
+            trace!("Using symmetry-tolerance = {:e} from '{}'", symprec, path.nice());
+
+            let mut settings = settings.clone();
+            if let Some(phonons_settings) = &mut settings.phonons {
+                phonons_settings.symmetry_tolerance = Some(cfg::SymmetryTolerance::Fixed(symprec));
+            }
+            settings
+        } else {
+            let settings = resolve_symmetry_tolerance(settings, coords, meta)?;
+            let symprec = {
+                settings.phonons.as_ref()
+                    .and_then(|x| x.symmetry_tolerance)
+                    .and_then(|tol| tol.fixed())
+                    .expect("(BUG!) symmetry-tolerance was just resolved")
+            };
+            Json(symprec).save(&path)?;
+            settings
+        }
+    })}
+}
+
 fn do_compute_dynmat(
     trial_dir: Option<&TrialDir>,
     settings: &Settings,
@@ -323,6 +998,8 @@ fn do_compute_dynmat(
         Option<meta::SiteLayers>,
         Option<meta::FracBonds>,
     >,
+    // lets the spacegroup permutations be reused for repeated calls on similar structures
+    symmetry_cache: &mut SymmetryCache,
 ) -> FailResult<DynamicalMatrix>
 {
     if phonons_settings.analytic_hessian {
@@ -332,7 +1009,16 @@ fn do_compute_dynmat(
     }
 
     let displacement_distance = phonons_settings.displacement_distance.expect("missing displacement-distance should have been caught sooner");
-    let symprec = phonons_settings.symmetry_tolerance.expect("missing symmetry-tolerance should have been caught sooner");
+    let atom_types: Vec<u32> = {
+        let elements: meta::SiteElements = prim_meta.pick();
+        elements.iter().map(|e| e.atomic_number()).collect()
+    };
+
+    let symprec = {
+        phonons_settings.symmetry_tolerance
+            .expect("missing symmetry-tolerance should have been caught sooner")
+            .fixed().expect("(BUG!) symmetry-tolerance: auto should have been resolved earlier")
+    };
 
     // Here exists a great deal of logic for dealing with supercells.
     // Ideally it would be factored out somehow to be less in your face,
@@ -343,8 +1029,12 @@ fn do_compute_dynmat(
     // For now, we just make heavy use of scoping to keep the number of names in scope smallish.
     let (ref super_coords, ref sc) = {
         let sc_dim = phonons_settings.supercell.dim_for_unitcell(prim_coords.lattice());
+        check_supercell_size(settings, phonons_settings, pot, prim_coords.lattice(), sc_dim)?;
         trace!("Constructing supercell (dim: {:?})", sc_dim);
-        rsp2_structure::supercell::diagonal(sc_dim).build(prim_coords)
+        // (a stable order, so that force constants from different runs can be compared directly)
+        rsp2_structure::supercell::diagonal(sc_dim)
+            .atom_order(AtomOrder::PrimitiveMajor)
+            .build(prim_coords)
     };
 
     let cart_ops = if symprec == 0.0 {
@@ -352,15 +1042,14 @@ fn do_compute_dynmat(
         info!(" Spacegroup: P1 (1)");
         info!("Point group: 1");
         vec![CartOp::eye()]
+    } else if let cfg::PhononDispFinder::File { .. } = phonons_settings.disp_finder {
+        // a user-supplied list need not contain only star representatives
+        trace!("Not using symmetry (displacements are read from a file)");
+        vec![CartOp::eye()]
     } else {
         use self::python::SpgDataset;
 
         trace!("Computing symmetry");
-        let atom_types: Vec<u32> = {
-            let elements: meta::SiteElements = prim_meta.pick();
-            elements.iter().map(|e| e.atomic_number()).collect()
-        };
-
         let spg = SpgDataset::compute(prim_coords, &atom_types, symprec)?;
         info!(" Spacegroup: {} ({})", spg.international_symbol, spg.spacegroup_number);
         info!("Point group: {}", spg.point_group);
@@ -370,7 +1059,7 @@ fn do_compute_dynmat(
 
     let mut phonopy_info = None;
     let prim_displacements = match phonons_settings.disp_finder {
-        cfg::PhononDispFinder::Phonopy { diag: _ } => {
+        cfg::PhononDispFinder::Phonopy { .. } => {
             let the_phonopy_info = self::phonopy::phonopy_displacements(
                 &phonons_settings, prim_coords, prim_meta.sift(), sc, super_coords,
             )?;
@@ -401,21 +1090,48 @@ fn do_compute_dynmat(
         cfg::PhononDispFinder::Rsp2 { ref directions } => {
             trace!("Computing deperms in primitive cell");
 
-            let prim_deperms = do_compute_deperms(&phonons_settings, &prim_coords, &cart_ops)?;
+            let prim_deperms = do_compute_deperms(&phonons_settings, &prim_coords, &cart_ops, use_rayon_for_bonds(settings), symmetry_cache)?;
             let prim_stars = crate::math::stars::compute_stars(&prim_deperms);
 
+            let prim_int_rots = {
+                cart_ops.iter()
+                    .map(|c| c.int_rot(prim_coords.lattice()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format_err!(
+                        "an operator from spglib does not fit the primitive lattice; \
+                        consider adjusting the symmetry tolerance: {}", e,
+                    ))?
+            };
             let prim_displacements = crate::math::displacements::compute_displacements(
                 directions,
-                cart_ops.iter().map(|c| {
-                    c.int_rot(prim_coords.lattice()).expect("bad operator from spglib!?")
-                }),
+                prim_int_rots.iter().cloned(),
                 &prim_stars,
                 &prim_coords,
                 displacement_distance,
             );
+            let prim_displacements = scale_displacements_by_element(
+                &phonons_settings.displacement_distance_by_element,
+                displacement_distance,
+                &prim_meta.pick(),
+                prim_displacements,
+            );
+
+            if let Some(trial_dir) = trial_dir {
+                let report = crate::math::displacements::DisplacementSymmetryReport::new(
+                    &cart_ops, &prim_deperms, &prim_stars, &prim_coords, &prim_displacements,
+                )?;
+                info!(
+                    "Symmetry reduced {} displacements to {} (factor of {:.1})",
+                    report.num_full_displacements, report.num_displacements, report.reduction_factor,
+                );
+                Json(report).save(trial_dir.join("displacement-symmetry.json"))?;
+            }
 
             prim_displacements
         },
+        cfg::PhononDispFinder::File { ref path } => {
+            read_displacements_file(path, prim_coords.num_atoms())?
+        },
     };
 
     let ref rsp2_displaced_site_cells = vec![ForceConstants::DESIGNATED_CELL; prim_displacements.len()];
@@ -431,7 +1147,7 @@ fn do_compute_dynmat(
     let super_meta = replicate_meta_for_force_constants(settings, &super_coords, &sc, prim_meta.sift())?;
 
     trace!("Computing deperms in supercell");
-    let super_deperms = do_compute_deperms(&phonons_settings, &super_coords, &cart_ops)?;
+    let super_deperms = do_compute_deperms(&phonons_settings, &super_coords, &cart_ops, use_rayon_for_bonds(settings), symmetry_cache)?;
 
     trace!("num spacegroup ops: {}", cart_ops.len());
     trace!("num displacements:  {}", super_displacements.len());
@@ -496,12 +1212,13 @@ fn do_compute_dynmat(
     }
 
     trace!("Computing sparse force constants");
-    let force_constants = ForceConstants::compute_required_rows(
+    let force_constants = ForceConstants::compute_required_rows_maybe_parallel(
         &super_displacements,
         &force_sets,
         &cart_rots,
         &super_deperms,
         &sc,
+        use_rayon_for_bonds(settings),
     )?;
     let force_constants = impose_sum_rule(phonons_settings, &sc, force_constants);
 
@@ -554,6 +1271,42 @@ fn do_compute_dynmat(
     Ok(dynmat)
 }
 
+/// Implements `phonons.displacement-distance-by-element` by rescaling displacements
+/// that were generated with the global `displacement-distance`.
+///
+/// (this is fine to do after symmetry reduction because every site in a star has the
+///  same element)
+fn scale_displacements_by_element(
+    distances: &BTreeMap<String, f64>,
+    default_distance: f64,
+    elements: &meta::SiteElements,
+    displacements: Vec<(usize, V3)>,
+) -> Vec<(usize, V3)> {
+    displacements.into_iter()
+        .map(|(atom, disp)| match distances.get(elements[atom].symbol()) {
+            Some(&distance) => (atom, disp * (distance / default_distance)),
+            None => (atom, disp),
+        })
+        .collect()
+}
+
+/// Implements `disp-finder: file`.
+fn read_displacements_file(path: &str, num_atoms: usize) -> FailResult<Vec<(usize, V3)>>
+{Ok({
+    trace!("Reading displacements from {}", path);
+    let displacements: Vec<(usize, V3)> = {
+        serde_yaml::from_reader(FileRead::open(path)?)
+            .map_err(|e| format_err!("while reading displacements from {}: {}", path, e))?
+    };
+
+    for &(atom, disp) in &displacements {
+        ensure!(atom < num_atoms, "{}: atom {} is out of range ({} atoms)", path, atom, num_atoms);
+        ensure!(disp != V3::zero(), "{}: zero displacement for atom {}", path, atom);
+    }
+    info!("Read {} displacements from {}", displacements.len(), path);
+    displacements
+})}
+
 // Vastly simpler than do_compute_dynmat
 fn do_compute_dynmat_with_hessian(
     settings: &Settings,
@@ -572,7 +1325,11 @@ fn do_compute_dynmat_with_hessian(
     trace!("Constructing supercell");
     let (ref super_coords, ref sc) = {
         let sc_dim = phonons_settings.supercell.dim_for_unitcell(prim_coords.lattice());
-        rsp2_structure::supercell::diagonal(sc_dim).build(prim_coords)
+        check_supercell_size(settings, phonons_settings, pot, prim_coords.lattice(), sc_dim)?;
+        // (same order as do_compute_dynmat)
+        rsp2_structure::supercell::diagonal(sc_dim)
+            .atom_order(AtomOrder::PrimitiveMajor)
+            .build(prim_coords)
     };
 
     let super_meta = replicate_meta_for_force_constants(settings, &super_coords, &sc, prim_meta.sift())?;
@@ -620,7 +1377,7 @@ fn replicate_meta_for_force_constants(
     // deriving these from the primitive cell bonds is not worth the trouble
     trace!("Computing bonds in supercell");
     let super_bonds = settings.bond_radius.map(|bond_radius| FailOk({
-        Rc::new(FracBonds::compute(&super_coords, bond_radius)?)
+        Rc::new(FracBonds::compute_maybe_parallel(&super_coords, bond_radius, use_rayon_for_bonds(settings))?)
     })).fold_ok()?;
 
     Ok(prim_meta.clone().map(hlist![
@@ -650,12 +1407,26 @@ fn impose_sum_rule(
     }
 }
 
+/// Whether the search for bonds (which is parallelizable over atoms) should use rayon.
+///
+/// This also decides whether to solve for force constants in parallel over symmetry stars,
+/// and whether to search for the permutations of the spacegroup generators in parallel.
+fn use_rayon_for_bonds(settings: &Settings) -> bool {
+    match settings.threading {
+        cfg::Threading::Rayon(_) => true,
+        cfg::Threading::Lammps |
+        cfg::Threading::Serial => false,
+    }
+}
+
 fn do_compute_deperms(
     phonon_settings: &cfg::Phonons,
     coords: &Coords,
     cart_ops: &[CartOp],
+    parallel: bool,
+    symmetry_cache: &mut SymmetryCache,
 ) -> FailResult<Vec<Perm>> {
-    rsp2_structure::find_perm::spacegroup_deperms(
+    symmetry_cache.spacegroup_deperms_maybe_parallel(
         coords,
         cart_ops,
         // larger than SYMPREC because the coords we see may may be slightly
@@ -663,10 +1434,48 @@ fn do_compute_deperms(
         // the wrong atoms
         //
         // the case of symmetry_tolerance = 0 is explicitly supported by the method
-        phonon_settings.symmetry_tolerance.expect("(BUG!) should have been caught earlier") * 3.0,
+        phonon_settings.symmetry_tolerance.and_then(|tol| tol.fixed()).expect("(BUG!) should have been resolved earlier") * 3.0,
+        parallel,
     )
 }
 
+/// The spacegroup operators of `coords`, for the symmetry characters used by
+/// `ev-loop.ignored-modes`.  `None` if no fingerprint there specifies `characters`.
+fn do_compute_mode_symmetry(
+    settings: &Settings,
+    coords: &Coords,
+    meta: HList1<meta::SiteElements>,
+    symmetry_cache: &mut SymmetryCache,
+) -> FailResult<Option<acoustic_search::ModeSymmetry>>
+{Ok({
+    use self::python::SpgDataset;
+
+    if settings.ev_loop.ignored_modes.iter().all(|mode| mode.characters.is_none()) {
+        return Ok(None);
+    }
+    let phonons_settings = match &settings.phonons {
+        Some(x) => x,
+        None => bail!("`phonons:` config section is required by the `characters` of ev-loop.ignored-modes"),
+    };
+    let atom_types: Vec<u32> = {
+        let elements: meta::SiteElements = meta.pick();
+        elements.iter().map(|e| e.atomic_number()).collect()
+    };
+
+    let symprec = match phonons_settings.symmetry_tolerance {
+        Some(tol) => tol.fixed().expect("(BUG!) symmetry-tolerance: auto should have been resolved earlier"),
+        None => bail!("phonons.symmetry-tolerance is required by the `characters` of ev-loop.ignored-modes"),
+    };
+    let cart_ops = if symprec == 0.0 {
+        vec![CartOp::eye()]
+    } else {
+        SpgDataset::compute(coords, &atom_types, symprec)?.cart_ops()
+    };
+    // (same tolerance as do_compute_deperms)
+    let deperms = symmetry_cache.spacegroup_deperms_maybe_parallel(coords, &cart_ops, symprec * 3.0, use_rayon_for_bonds(settings))?;
+    Some(acoustic_search::ModeSymmetry { cart_ops, deperms })
+})}
+
 fn do_diagonalize_dynmat(
     phonons_settings: &cfg::Phonons,
     dynmat: DynamicalMatrix,
@@ -685,17 +1494,38 @@ fn do_diagonalize_dynmat(
         match phonons_settings.eigensolver {
             cfg::PhononEigensolver::Phonopy(cfg::AlwaysFail(never, _)) => match never {},
             cfg::PhononEigensolver::Rsp2 { .. } => panic!("(BUG!) setting phonons.eigensolver is not normalized!"),
-            cfg::PhononEigensolver::Dense {} => {
+            cfg::PhononEigensolver::Dense { ref frequency_window } => {
                 // FIXME: the location of this function is misleading;
                 //        it doesn't actually use eigsh.
-                python::scipy_eigsh::compute_eigensolutions_dense_gamma(&dynmat)
+                match frequency_window {
+                    None => python::scipy_eigsh::compute_eigensolutions_dense_gamma(&dynmat),
+                    Some(window) => {
+                        python::scipy_eigsh::compute_eigensolutions_dense_gamma_in_window(&dynmat, window)
+                    },
+                }
             },
-            cfg::PhononEigensolver::Sparse { how_many, shift_invert_attempts } => {
-                python::scipy_eigsh::compute_negative_eigensolutions_gamma(
-                    &dynmat,
-                    how_many,
-                    shift_invert_attempts,
-                )?
+            cfg::PhononEigensolver::Sparse { how_many, shift_invert_attempts, which, seed } => {
+                match which {
+                    cfg::EigensolverWhich::MostNegative => {
+                        python::scipy_eigsh::compute_negative_eigensolutions_gamma(
+                            &dynmat,
+                            how_many,
+                            shift_invert_attempts,
+                            seed,
+                        )?
+                    },
+                    cfg::EigensolverWhich::Largest => {
+                        python::scipy_eigsh::compute_largest_eigensolutions_gamma(&dynmat, how_many, seed)?
+                    },
+                    cfg::EigensolverWhich::NearShift(frequency) => {
+                        python::scipy_eigsh::compute_eigensolutions_near_gamma(
+                            &dynmat,
+                            how_many,
+                            crate::filetypes::eigensols::frequency_to_eigenvalue(frequency),
+                            seed,
+                        )?
+                    },
+                }
             },
         }
     };
@@ -712,6 +1542,7 @@ impl TrialDir {
             meta::SiteElements,
             meta::SiteMasses,
         >,
+        normalization: Option<&cfg::EvNormalization>,
         iteration: Iteration,
         bad_directions: impl IntoIterator<Item=(f64, EvDirection)>,
         all_directions: impl IntoIterator<Item=(f64, EvDirection)>,
@@ -736,11 +1567,12 @@ impl TrialDir {
 
                 let mut metadata = v_sim::AsciiMetadata::new();
                 for &(frequency, ref direction) in mode_info {
+                    let ket = normalize_ev_direction(direction, normalization, meta.sift());
                     metadata.add_phonon(v_sim::Phonon {
                         qpoint_frac: V3::zero(),
                         energy: frequency,
                         displacements: {
-                            zip_eq!(&direction.real, &direction.imag)
+                            zip_eq!(&ket.real, &ket.imag)
                                 .map(|(re, im)| V3::from_fn(|i| Complex64::new(re[i], im[i])))
                                 .collect()
                         },
@@ -757,11 +1589,61 @@ impl TrialDir {
             },
         }
     })}
+
+    /// Implements `write-eigenvectors`.
+    fn write_eigenvectors(
+        &self,
+        normalization: Option<&cfg::EvNormalization>,
+        meta: HList1<meta::SiteMasses>,
+        iteration: Iteration,
+        directions: impl IntoIterator<Item=(f64, EvDirection)>,
+    ) -> FailResult<()> {Ok({
+        #[derive(Serialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Output<'a> {
+            normalization: Option<&'a cfg::EvNormalization>,
+            frequency: Vec<f64>,
+            // [ev][site]
+            real: Vec<Vec<V3>>,
+            imag: Vec<Vec<V3>>,
+        }
+
+        let mut output = Output { normalization, frequency: vec![], real: vec![], imag: vec![] };
+        for (frequency, direction) in directions {
+            let Ket3 { real, imag } = normalize_ev_direction(&direction, normalization, meta.sift());
+            output.frequency.push(frequency);
+            output.real.push(real);
+            output.imag.push(imag);
+        }
+        Json(output).save(self.eigenvectors_path(iteration))?;
+    })}
+}
+
+/// Apply a normalization convention from the config to an eigenvector's displacement direction.
+fn normalize_ev_direction(
+    direction: &EvDirection,
+    normalization: Option<&cfg::EvNormalization>,
+    meta: HList1<meta::SiteMasses>,
+) -> Ket3 {
+    let normalization = match normalization {
+        None => return (**direction).clone(),
+        Some(normalization) => normalization,
+    };
+
+    let ket = match normalization.basis {
+        cfg::EvBasis::Cartesian => (**direction).clone(),
+        cfg::EvBasis::MassWeighted => direction.to_eigenvector(meta),
+    };
+    match normalization.unit {
+        cfg::EvUnit::Mode => ket.normalized(),
+        cfg::EvUnit::Atom => ket.normalized_per_atom(),
+    }
 }
 
 use rsp2_soa_ops::{Perm, Permute};
 use rsp2_structure::CartOp;
-use rsp2_structure::supercell::SupercellToken;
+use rsp2_structure::find_perm::SymmetryCache;
+use rsp2_structure::supercell::{SupercellToken, AtomOrder};
 
 // FIXME incorrect for nontrivial supercells. Should use primitive stars and translate
 //       the displaced atom to the correct image after rotation. (this would be easiest to
@@ -816,6 +1698,7 @@ fn visualize_sparse_force_sets(
                 comment: format!("displacement {}, sg operator {}", disp_i, oper_i),
                 coords,
                 elements,
+                dynamics: None,
             }.save(disp_dir.join(format!("{:03}.vasp", oper_i)))?;
         }
     }
@@ -869,10 +1752,8 @@ fn save_force_sets_for_tests(
         super_displacements: super_displacements.to_vec(),
     };
 
-    let dense_dynmat = DenseDynmat {
-        real: dynmat.0.to_coo().map(|c| c.0).into_dense(),
-        imag: dynmat.0.to_coo().map(|c| c.1).into_dense(),
-    };
+    let (real, imag) = dynmat.to_dense_parts();
+    let dense_dynmat = DenseDynmat { real, imag };
 
     if let Err(e) = Json(primitive).save(debug_files_root.join("primitive.json")) {
         warn!("{}", e);
@@ -898,9 +1779,11 @@ fn do_gamma_system_analysis(
     freqs: &[f64],
     evecs: &GammaBasis3,
     mode_classifications: Option<Rc<[ModeKind]>>,
-    // can set to false to forcibly disable this expensive operation even
+    // can set to None to forcibly disable this expensive operation even
     // if all necessary data is available
-    unfold_bands: bool,
+    unfold_bands: Option<&cfg::UnfoldBands>,
+    frequency_window: Option<&cfg::FrequencyWindow>,
+    ir: Option<&cfg::Ir>,
 ) -> FailResult<GammaSystemAnalysis> {
     use self::ev_analyses::*;
 
@@ -911,6 +1794,18 @@ fn do_gamma_system_analysis(
     ] = meta;
 
     let cart_bonds = frac_bonds.as_ref().map(|b| b.to_cart_bonds(coords));
+    let ev_in_window = freqs.iter().map(|&freq| {
+        frequency_window.map_or(true, |window| window.contains(freq))
+    }).collect();
+    let site_charges = match ir {
+        None => None,
+        Some(cfg::Ir { charges }) => Some(SiteCharges({
+            site_elements.iter().map(|element| match charges.get(element.symbol()) {
+                Some(&charge) => Ok(charge),
+                None => Err(format_err!("ir.charges has no charge for {}", element.symbol())),
+            }).collect::<FailResult<Vec<_>>>()?
+        })),
+    };
 
     gamma_system_analysis::Input {
         site_layers: site_layers,
@@ -922,7 +1817,9 @@ fn do_gamma_system_analysis(
         ev_frequencies: Some(EvFrequencies(freqs.to_vec())),
         ev_eigenvectors: Some(EvEigenvectors(evecs.clone())),
         bonds: cart_bonds.map(Bonds),
-        request_to_unfold_bands: if unfold_bands { Some(RequestToUnfoldBands) } else { None },
+        ev_in_window: Some(EvInWindow(ev_in_window)),
+        site_charges,
+        request_to_unfold_bands: unfold_bands.map(|x| RequestToUnfoldBands(x.clone())),
     }.compute()
 }
 
@@ -933,6 +1830,9 @@ fn write_eigen_info_for_humans(
 {
     analysis.make_columns(ev_analyses::ColumnsMode::ForHumans)
         .expect("(bug) no columns, not even frequency?")
+        .into_iter().map(&mut *writeln).collect::<FailResult<()>>()?;
+
+    analysis.make_localization_lines()
         .into_iter().map(writeln).collect()
 }
 
@@ -990,15 +1890,87 @@ impl TrialDir {
         let summary = out.into_iter().fold(no_summary(), merge_summaries);
         serde_yaml::to_writer(self.create_file("summary.yaml")?, &summary)?;
     })}
+
+    fn write_json_summary_file(
+        &self,
+        pot: &dyn PotentialBuilder,
+        ev_analysis: &GammaSystemAnalysis,
+        final_iteration: Iteration,
+        converged: bool,
+    ) -> FailResult<()> {Ok({
+        let (coords, meta) = self.read_stored_structure_data(&self.structure_path(EvLoopStructureKind::Final))?;
+        let final_energy = pot.one_off().compute_value(&coords, meta.sift())?;
+
+        let frequencies = &ev_analysis.ev_frequencies.as_ref().expect("(bug) always computed!").0;
+        let kinds = &ev_analysis.ev_classifications.as_ref().expect("(bug) always computed!").0;
+        let modes = {
+            zip_eq!(frequencies, kinds)
+                .map(|(&frequency, &kind)| ModeSummary { frequency, kind })
+                .collect()
+        };
+
+        Json(RunSummary {
+            final_energy,
+            final_energy_per_atom: final_energy / coords.num_atoms() as f64,
+            ev_loop_iterations: final_iteration.0,
+            converged,
+            modes,
+            lammps_neighbor_stats: crate::potential::lammps::neighbor_stats().map(|stats| {
+                LammpsNeighborSummary { runs: stats.runs, builds: stats.builds }
+            }),
+        }).save(self.join("summary.json"))?;
+    })}
+}
+
+/// Contents of `summary.json`, a machine-readable summary of a completed run.
+///
+/// Unlike `summary.yaml` (whose contents depend on which analyses were enabled),
+/// this always has the same fields.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct RunSummary {
+    /// Potential energy of the final structure (eV).
+    pub final_energy: f64,
+    pub final_energy_per_atom: f64,
+    /// Number of ev-loop iterations that were performed.
+    pub ev_loop_iterations: u32,
+    /// `false` if the ev-loop gave up after `ev-loop.max-iter` iterations.
+    pub converged: bool,
+    /// Modes of the final structure at gamma, in the same order as `eigenvalues.final`.
+    pub modes: Vec<ModeSummary>,
+    /// How often LAMMPS rebuilt its neighbor lists over the entire run.
+    /// (absent if LAMMPS was not used)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lammps_neighbor_stats: Option<LammpsNeighborSummary>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct LammpsNeighborSummary {
+    /// Number of times LAMMPS was told to update.
+    pub runs: u64,
+    /// Number of neighbor list builds during those updates.
+    pub builds: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ModeSummary {
+    /// Frequency in cm^-1. (negative for imaginary modes)
+    pub frequency: f64,
+    pub kind: ModeKind,
 }
 
 // FIXME: rename.
 //
 // Also notice that the rsp2 eigensolver apparently never did disps in parallel,
 // because I wanted to make it reuse the DispFn.
+//
+// (the exception is `threading: {rayon: {max-concurrent-displacements: N}}`, which
+//  splits the displacements into N contiguous chunks, each with its own DispFn)
 fn do_force_sets_at_disps_for_sparse(
     pot: &dyn PotentialBuilder,
-    _threading: &cfg::Threading,
+    threading: &cfg::Threading,
     displacements: &[(usize, V3)],
     coords: &Coords,
     meta: CommonMeta,
@@ -1008,22 +1980,59 @@ fn do_force_sets_at_disps_for_sparse(
 
     trace!("Computing forces at displacements");
 
-    let mut disp_fn = pot.initialize_disp_fn(&coords, meta.sift())?;
+    let max_concurrent = match threading {
+        cfg::Threading::Rayon(cfg::RayonThreading { max_concurrent_displacements }) => {
+            max_concurrent_displacements.unwrap_or(1)
+        },
+        _ => 1,
+    };
 
-    // this no longer has the option of using rayon because the speed gain from
+    // this no longer has the option of using unbounded rayon because the speed gain from
     // disabling neighbor list updates in LAMMPS is far greater
-    let force_sets = {
-        displacements.iter()
-            .enumerate()
-            .map(|(i, &disp)| {
-                eprint!("\rdisp {} of {}", i + 1, displacements.len());
-                std::io::stderr().flush().unwrap();
+    let force_sets = match max_concurrent {
+        0 | 1 => {
+            let mut disp_fn = pot.initialize_disp_fn(&coords, meta.sift())?;
 
-                disp_fn.compute_sparse_force_delta(disp)
-            })
-            .collect::<Result<_, _>>()?
+            let force_sets = displacements.iter()
+                .enumerate()
+                .map(|(i, &disp)| {
+                    eprint!("\rdisp {} of {}", i + 1, displacements.len());
+                    std::io::stderr().flush().unwrap();
+
+                    disp_fn.compute_sparse_force_delta(disp)
+                })
+                .collect::<Result<_, _>>()?;
+            eprintln!();
+            force_sets
+        },
+        max_concurrent => {
+            use rayon::prelude::*;
+
+            // Rc-laden metadata can't cross threads; each chunk makes its own copy.
+            let get_meta = meta.sendable();
+            let chunk_size = (displacements.len() + max_concurrent - 1) / max_concurrent;
+
+            info!(
+                "Computing {} displacements, up to {} at a time",
+                displacements.len(), max_concurrent,
+            );
+            // There are at most `max_concurrent` chunks, so that is all it takes to bound the
+            // concurrency.  (a dedicated thread pool would also limit the parallelism used by
+            // the potential itself, which should have the global pool at its disposal)
+            let chunks = {
+                displacements.par_chunks(usize::max(chunk_size, 1))
+                    .with_max_len(1) // one task per chunk, so that all of them can run at once
+                    .map(|chunk| {
+                        let mut disp_fn = pot.initialize_disp_fn(&coords, get_meta().sift())?;
+                        chunk.iter()
+                            .map(|&disp| disp_fn.compute_sparse_force_delta(disp))
+                            .collect::<FailResult<Vec<_>>>()
+                    })
+                    .collect::<FailResult<Vec<_>>>()?
+            };
+            chunks.into_iter().flatten().collect()
+        },
     };
-    eprintln!();
     trace!("Done computing forces at displacements");
     force_sets
 })}
@@ -1083,6 +2092,9 @@ pub struct EnergySurfaceArgs {
     density: usize,
     extend_border: bool,
     layer: meta::Layer,
+    directions: Option<String>,
+    xlim: std::ops::Range<f64>,
+    ylim: std::ops::Range<f64>,
 }
 
 impl crate::ui::cli_deserialize::CliDeserialize for EnergySurfaceArgs {
@@ -1095,14 +2107,31 @@ impl crate::ui::cli_deserialize::CliDeserialize for EnergySurfaceArgs {
                 at all points along the edges if the data is to be resampled in some way.\
             "),
             arg!( layer [--layer]=LAYER "select which layer moves"),
+            arg!( directions [--directions]=FILE "\
+                JSON file containing two arrays of cartesian displacements (one per atom) to scan \
+                along, instead of translating a layer along the lattice vectors. \
+                See `direction-normalization` in the config.\
+            "),
+            arg!( xlim [--xlim]=MIN_MAX "range along the first direction, as MIN,MAX. (default: 0,1)"),
+            arg!( ylim [--ylim]=MIN_MAX "range along the second direction, as MIN,MAX. (default: 0,1)"),
         ])
     }
 
     fn _resolve_args(m: &clap::ArgMatches<'_>) -> FailResult<Self> {
+        let parse_lim = |s: &str| FailOk({
+            let parts = s.split(',').map(|x| x.trim().parse()).collect::<Result<Vec<f64>, _>>()?;
+            match parts[..] {
+                [min, max] if min < max => min..max,
+                _ => bail!("expected MIN,MAX with MIN < MAX, got {:?}", s),
+            }
+        });
         Ok(EnergySurfaceArgs {
             density: m.value_of("density").unwrap_or("100").parse()?,
             extend_border: m.is_present("extend_border"),
             layer: meta::Layer(m.value_of("layer").unwrap_or("0").parse()?),
+            directions: m.value_of("directions").map(|s| s.to_string()),
+            xlim: parse_lim(m.value_of("xlim").unwrap_or("0,1"))?,
+            ylim: parse_lim(m.value_of("ylim").unwrap_or("0,1"))?,
         })
     }
 }
@@ -1120,58 +2149,98 @@ pub(crate) fn run_shear_plot(
     let meta = structure.meta();
     let coords = structure.coords;
 
-    let EnergySurfaceArgs { density, extend_border, layer: translated_layer } = plot_args;
+    let EnergySurfaceArgs {
+        density, extend_border, layer: translated_layer, directions: directions_path, xlim, ylim,
+    } = plot_args;
 
     let pot = PotentialBuilder::from_config_parts(
         None,
         on_demand,
         &settings.threading,
         &settings.lammps,
+        None,
         &settings.potential,
     )?;
 
-    let lattice_matrix_22 = {
-        let matrix = coords.lattice().matrix();
-        if (0..2).any(|k| matrix[2][k] != 0.0 || matrix[k][2] != 0.0) {
-            bail!("Structure must be planar in xy plane.");
-        }
+    // (the lattice is only recorded when translating a layer, where it gives meaning to the
+    //  coordinates of the grid)
+    let (directions, lattice_matrix_22) = match directions_path {
+        Some(path) => {
+            let Json(directions): Json<[Vec<V3>; 2]> = Load::load(path)?;
+            for direction in &directions {
+                if direction.len() != coords.len() {
+                    bail!("Each direction must have {} vectors (one per atom), not {}.", coords.len(), direction.len());
+                }
+            }
 
-        M22::from_fn(|r, c| matrix[r][c])
-    };
+            let normalize = |direction: &Vec<V3>| {
+                let ket = Ket3 { real: direction.clone(), imag: vec![V3::zero(); direction.len()] };
+                let ket = match settings.direction_normalization {
+                    None => ket,
+                    Some(cfg::EvUnit::Mode) => ket.normalized(),
+                    Some(cfg::EvUnit::Atom) => ket.normalized_per_atom(),
+                };
+                ket.real
+            };
+            let [ref a, ref b] = directions;
+            ([normalize(a), normalize(b)], None)
+        },
+        None => {
+            let lattice_matrix_22 = {
+                let matrix = coords.lattice().matrix();
+                if (0..2).any(|k| matrix[2][k] != 0.0 || matrix[k][2] != 0.0) {
+                    bail!("Structure must be planar in xy plane.");
+                }
 
-    let site_layers: meta::SiteLayers = match meta.pick() {
-        None => bail!("The structure for a shear plot must store layers."),
-        Some(x) => x,
-    };
+                M22::from_fn(|r, c| matrix[r][c])
+            };
 
-    let mask: Vec<bool> = {
-        site_layers.iter().map(|&x| x == translated_layer).collect()
-    };
+            let site_layers: meta::SiteLayers = match meta.pick() {
+                None => bail!("The structure for a shear plot must store layers."),
+                Some(x) => x,
+            };
+
+            let mask: Vec<bool> = {
+                site_layers.iter().map(|&x| x == translated_layer).collect()
+            };
 
-    // Vector that translates a layer by a lattice basis vector.
-    let get_translation_vector = |k: usize| {
-        let lattice_vector = coords.lattice().vectors()[k];
+            // Vector that translates a layer by a lattice basis vector.
+            let get_translation_vector = |k: usize| {
+                let lattice_vector = coords.lattice().vectors()[k];
 
-        let mut out = vec![V3::zero(); coords.len()];
-        for (i, &mask_bit) in mask.iter().enumerate() {
-            if mask_bit {
-                out[i] = lattice_vector;
-            }
-        }
-        out
+                let mut out = vec![V3::zero(); coords.len()];
+                for (i, &mask_bit) in mask.iter().enumerate() {
+                    if mask_bit {
+                        out[i] = lattice_vector;
+                    }
+                }
+                out
+            };
+            ([get_translation_vector(0), get_translation_vector(1)], Some(lattice_matrix_22))
+        },
+    };
+
+    // Points are recorded here as they are computed, so that an interrupted scan can be
+    // resumed by running the same command again.  (the checkpoint also records the potential,
+    // so that it is not resumed after the potential is changed)
+    let checkpoint_path = {
+        let mut path = output_path.as_path().as_os_str().to_owned();
+        path.push(".checkpoint");
+        PathBuf::from(path)
     };
 
     let a_density = density;
     let b_density = density;
-    let a_range = 0.0..1.0;
-    let b_range = 0.0..1.0;
+    let a_range = xlim;
+    let b_range = ylim;
     let data = {
         crate::cmd::integrate_2d::integrate_two_directions(
             [a_density, b_density],
             &coords.to_carts(),
             [a_range, b_range],
             [extend_border, extend_border],
-            [&get_translation_vector(0), &get_translation_vector(1)],
+            [&directions[0], &directions[1]],
+            Some((&checkpoint_path, serde_json::to_value(&settings.potential)?)),
             {
                 use std::sync::atomic::{AtomicUsize, Ordering};
                 let coords = coords.clone();
@@ -1197,28 +2266,42 @@ pub(crate) fn run_shear_plot(
     #[derive(Serialize)]
     #[serde(rename_all = "kebab-case")]
     struct Output {
-        lattice: M22,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        lattice: Option<M22>,
         index: Vec<[i32; 2]>,
-        cart_translation: Vec<[f64; 2]>,
+        /// Coefficients of the two directions, when they were given by `--directions`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        coefficient: Option<Vec<[f64; 2]>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cart_translation: Option<Vec<[f64; 2]>>,
         energy_per_atom: Vec<f64>,
     }
 
+    let (coefficient, cart_translation) = match lattice_matrix_22 {
+        None => (Some(data.coords), None),
+        Some(_) => {
+            let cart_translation = {
+                data.coords.iter()
+                    .map(|&[a, b]| {
+                        let V3([x, y, z]) = V3([a, b, 0.0]) * coords.lattice();
+                        assert_eq!(z, 0.0);
+                        [x, y]
+                    })
+                    .collect()
+            };
+            (None, Some(cart_translation))
+        },
+    };
     let output = Output {
         lattice: lattice_matrix_22,
         index: data.indices,
-        cart_translation: {
-            data.coords.iter()
-                .map(|&[a, b]| {
-                    let V3([x, y, z]) = V3([a, b, 0.0]) * coords.lattice();
-                    assert_eq!(z, 0.0);
-                    [x, y]
-                })
-                .collect()
-        },
+        coefficient,
+        cart_translation,
         energy_per_atom: data.values.iter().map(|v| v / (coords.len() as f64)).collect(),
     };
 
     Json(&output).save(output_path)?;
+    rm_rf(&checkpoint_path)?;
 })}
 
 //=================================================================
@@ -1240,6 +2323,51 @@ extension_trait!{
     }
 }
 
+/// Check that the supercell is thick enough along each axis that an atom can interact with
+/// at most one image of any other atom.  (see the docs on `Phonons::supercell`)
+fn check_supercell_size(
+    settings: &Settings,
+    phonons_settings: &cfg::Phonons,
+    pot: &dyn PotentialBuilder,
+    prim_lattice: &Lattice,
+    sc_dim: [u32; 3],
+) -> FailResult<()>
+{Ok({
+    let cutoff = match (pot.interaction_cutoff(), settings.bond_radius) {
+        (Some(a), Some(b)) => f64::max(a, b),
+        (Some(r), None) | (None, Some(r)) => r,
+        (None, None) => {
+            trace!("Interaction cutoff unknown; not checking supercell size");
+            return Ok(());
+        },
+    };
+    let required = 2.0 * cutoff;
+
+    let prim_widths = prim_lattice.perpendicular_widths();
+    let mut problems = vec![];
+    for k in 0..3 {
+        let width = prim_widths[k] * sc_dim[k] as f64;
+        if width < required {
+            let min_images = (required / prim_widths[k]).ceil() as u32;
+            problems.push(format!(
+                "axis {}: thickness {:.4} is {:.4} short of {:.4} (use at least {} images, or a target of {:.4})",
+                k, width, required - width, required, min_images, min_images as f64 * prim_lattice.norms()[k],
+            ));
+        }
+    }
+
+    if !problems.is_empty() {
+        let msg = format!(
+            "The phonon supercell {:?} is too small for an interaction cutoff of {}!\n  {}",
+            sc_dim, cutoff, problems.join("\n  "),
+        );
+        if phonons_settings.fail_on_small_supercell {
+            bail!("{}", msg);
+        }
+        warn!("{}\nForce constants will likely be incorrect.", msg);
+    }
+})}
+
 //=================================================================
 
 impl TrialDir {
@@ -1252,6 +2380,7 @@ impl TrialDir {
     {Ok({
         let pot = PotentialBuilder::from_root_config(Some(&self), on_demand, &settings)?;
 
+        let settings = &resolve_symmetry_tolerance(settings, &stored.coords, stored.meta().sift())?;
         let phonons_settings = match &settings.phonons {
             Some(x) => x,
             None => bail!("`phonons` settings is required to do EV analysis"),
@@ -1261,6 +2390,7 @@ impl TrialDir {
         let dynmat = do_compute_dynmat(
             Some(&self), settings, phonons_settings,
             &*pot, qpoint, &stored.coords, stored.meta().sift(),
+            &mut SymmetryCache::new(),
         )?;
         // Don't write the dynamical matrix; unclear where to put it.
         let (freqs, evecs) = pot.eco_mode(|eco_proof| {
@@ -1274,18 +2404,28 @@ impl TrialDir {
         let classifications = acoustic_search::perform_acoustic_search(
             &pot, &freqs, &evecs, &stored.coords, stored.meta().sift(), &settings.acoustic_search,
         )?;
+        let mode_symmetry = do_compute_mode_symmetry(
+            settings, &stored.coords, stored.meta().sift(), &mut SymmetryCache::new(),
+        )?;
+        let classifications = acoustic_search::apply_ignored_modes(
+            classifications, &freqs, &evecs, mode_symmetry.as_ref(),
+            &settings.ev_loop.ignored_modes, &mut vec![],
+        );
 
         trace!("Computing eigensystem info");
 
         let ev_analysis = do_gamma_system_analysis(
             &stored.coords, stored.meta().sift(),
             &freqs, &evecs, Some(classifications),
-            true, // unfold bands
+            // (always unfold here, defaulting to Zheng's method)
+            Some(settings.unfold_bands.as_ref().unwrap_or(&cfg::UnfoldBands::Zheng {})),
+            settings.frequency_window.as_ref(),
+            settings.ir.as_ref(),
         )?;
 
         write_eigen_info_for_humans(&ev_analysis, &mut |s| FailOk(info!("{}", s)))?;
 
-        write_ev_analysis_output_files(&self, &ev_analysis)?;
+        write_ev_analysis_output_files(&self, &ev_analysis, &settings.raman)?;
     })}
 }
 
@@ -1303,7 +2443,9 @@ pub(crate) fn run_sparse_analysis(
         structure.meta().sift(),
         &freqs, &evecs,
         None, // ev_classifications
-        true, // unfold_bands
+        Some(&cfg::UnfoldBands::Zheng {}), // unfold_bands
+        None, // frequency_window
+        None, // ir
     )?;
 
     write_eigen_info_for_humans(&ev_analysis, &mut |s| FailOk(info!("{}", s)))?;
@@ -1331,8 +2473,10 @@ pub(crate) fn run_dynmat_analysis(
             &structure.coords,
             structure.meta().sift(),
             &freqs, &evecs,
-            None,  // ev_classifications
-            false, // unfold_bands
+            None, // ev_classifications
+            None, // unfold_bands
+            settings.frequency_window.as_ref(),
+            settings.ir.as_ref(),
         )?;
 
         write_eigen_info_for_humans(&ev_analysis, &mut |s| FailOk(info!("{}", s)))?;
@@ -1354,10 +2498,10 @@ pub(crate) fn run_plot_vdw(
     let threading = cfg::Threading::Lammps;
 
     let lammps = cfg::Lammps {
-        update_style: cfg::LammpsUpdateStyle::Fast { sync_positions_every: 1 }.into(),
-        processor_axis_mask: [true; 3].into(),
+        update_style: cfg::LammpsUpdateStyle::Fast { sync_positions_every: 1, warn_rebuild_fraction: 0.5 }.into(),
+        processor_axis_mask: cfg::ProcessorAxisMask::Fixed([true; 3]).into(),
     };
-    let pot = PotentialBuilder::from_config_parts(None, on_demand, &threading, &lammps, pot)?;
+    let pot = PotentialBuilder::from_config_parts(None, on_demand, &threading, &lammps, None, pot)?;
 
     let lattice = {
         let a = rs.iter().fold(0.0, |a, &b| f64::max(a, b)) + 20.0;
@@ -1407,10 +2551,10 @@ pub(crate) fn run_converge_vdw(
     let threading = cfg::Threading::Lammps;
 
     let lammps = cfg::Lammps {
-        update_style: cfg::LammpsUpdateStyle::Fast { sync_positions_every: 1 }.into(),
-        processor_axis_mask: [true; 3].into(),
+        update_style: cfg::LammpsUpdateStyle::Fast { sync_positions_every: 1, warn_rebuild_fraction: 0.5 }.into(),
+        processor_axis_mask: cfg::ProcessorAxisMask::Fixed([true; 3]).into(),
     };
-    let pot = PotentialBuilder::from_config_parts(None, on_demand, &threading, &lammps, pot)?;
+    let pot = PotentialBuilder::from_config_parts(None, on_demand, &threading, &lammps, None, pot)?;
 
     let lattice = Lattice::orthorhombic(40.0, 40.0, 40.0);
     let direction = {
@@ -1462,14 +2606,15 @@ pub(crate) fn run_single_force_computation(
     poscar: rsp2_structure_io::Poscar,
 ) -> FailResult<Vec<V3>>
 {Ok({
-    let rsp2_structure_io::Poscar { comment: _, coords, elements } = poscar;
+    let rsp2_structure_io::Poscar { coords, elements, .. } = poscar;
     let elements: meta::SiteElements = elements.into();
     let masses = masses_by_config(settings.masses.as_ref(), elements.clone())?;
+    let masses = masses_by_site_config(settings.site_masses.as_ref(), masses)?;
 
     let meta = hlist![elements, masses];
     let meta = meta.prepend({
         settings.bond_radius.map(|bond_radius| FailOk({
-            Rc::new(FracBonds::compute(&coords, bond_radius)?)
+            Rc::new(FracBonds::compute_maybe_parallel(&coords, bond_radius, use_rayon_for_bonds(settings))?)
         })).fold_ok()?
     });
 
@@ -1586,19 +2731,149 @@ pub(crate) fn run_dynmat_at_q(
     settings: &Settings,
     qpoint_frac: V3,
     structure: StoredStructure,
+    // factors from `--scale-mass`
+    mass_scales: &[(meta::Element, f64)],
 ) -> FailResult<DynamicalMatrix> {
     let pot = PotentialBuilder::from_root_config(None, on_demand, &settings)?;
 
+    let mut meta = structure.meta();
+    let coords = structure.coords;
+    {
+        let scaled = {
+            let masses = masses_by_site_config(settings.site_masses.as_ref(), meta.pick())?;
+            scale_masses(mass_scales, &meta.pick(), &masses)
+        };
+        let masses: &mut meta::SiteMasses = meta.get_mut();
+        *masses = scaled;
+    }
+
+    let settings = &resolve_symmetry_tolerance(settings, &coords, meta.sift())?;
     let phonons_settings = match &settings.phonons {
         Some(x) => x,
         None => bail!("`phonons` config section is required to compute dynamical matrices"),
     };
 
+    do_compute_dynmat(
+        None, settings, phonons_settings, &pot, qpoint_frac, &coords, meta.sift(),
+        &mut SymmetryCache::new(),
+    )
+}
+
+//=================================================================
+
+/// Conversion factor from eV/Å³ to GPa.
+const EV_PER_CUBIC_ANGSTROM_IN_GPA: f64 = 160.217_662_08;
+
+/// Voigt index to cartesian index pair.
+const VOIGT_PAIRS: [(usize, usize); 6] = [(0, 0), (1, 1), (2, 2), (1, 2), (0, 2), (0, 1)];
+
+/// Estimate the elastic tensor (in Voigt notation) from the curvature of the energy
+/// with respect to small strains.
+///
+/// The structure is first relaxed at fixed lattice.  Then, for every pair of Voigt
+/// components, the lattice is deformed by `±step` strains (engineering strains for the
+/// shear components) and the atoms are relaxed again with the lattice held fixed.
+///
+/// The volume used for normalization is that of the full cell, so for layered materials
+/// the output depends on the amount of vacuum.
+///
+/// The lattice of the input is used as-is.  If the input is under stress, the curvature of
+/// the energy differs from the stress-strain elastic constants by terms linear in the
+/// residual stress, so the lattice should be relaxed beforehand.
+pub(crate) fn run_elastic_constants(
+    on_demand: Option<LammpsOnDemand>,
+    settings: &Settings,
+    structure: StoredStructure,
+    step: f64,
+    output_path: impl AsPath,
+) -> FailResult<()>
+{Ok({
+    ensure!(step > 0.0, "strain step must be positive (got {})", step);
+
+    let pot = PotentialBuilder::from_root_config(None, on_demand, &settings)?;
+
     let meta = structure.meta();
-    let coords = structure.coords;
+    let relax = |coords: Coords| {
+        relaxation::do_cg_relax_with_fixed_lattice(&*pot, &settings.cg, coords, meta.clone())
+    };
 
-    do_compute_dynmat(None, settings, phonons_settings, &pot, qpoint_frac, &coords, meta.sift())
-}
+    info!("Relaxing unstrained structure");
+    let reference = relax(structure.coords)?;
+    let volume = reference.lattice().volume();
+
+    let mut matrix = energy_curvature_wrt_voigt_strain(step, |strain| FailOk({
+        let mut coords = reference.clone();
+        coords.apply_strain(strain);
+        if strain != &M33::zero() {
+            coords = relax(coords)?;
+        }
+        pot.one_off().compute_value(&coords, meta.sift())?
+    }))?;
+
+    for row in &mut matrix {
+        for x in row {
+            *x *= EV_PER_CUBIC_ANGSTROM_IN_GPA / volume;
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct Output {
+        strain_step: f64,
+        volume: f64,
+        /// Elastic constants in GPa, in Voigt notation (xx, yy, zz, yz, xz, xy).
+        elastic_constants: [[f64; 6]; 6],
+    }
+
+    Json(Output { strain_step: step, volume, elastic_constants: matrix }).save(output_path)?;
+})}
+
+/// Second derivatives of `energy_at_strain` with respect to the six Voigt components of
+/// strain (with engineering strains for the shear components), by central differences.
+///
+/// `energy_at_strain` receives the symmetric cartesian strain tensor.
+fn energy_curvature_wrt_voigt_strain(
+    step: f64,
+    mut energy_at_strain: impl FnMut(&M33) -> FailResult<f64>,
+) -> FailResult<[[f64; 6]; 6]>
+{Ok({
+    // Energy at a strain that is a sum of the given Voigt components.
+    let mut strained_energy = |components: &[(usize, f64)]| {
+        let mut strain = M33::zero();
+        for &(voigt, amount) in components {
+            let (r, c) = VOIGT_PAIRS[voigt];
+            match r == c {
+                true => strain[r][c] += amount,
+                false => {
+                    strain[r][c] += amount / 2.0;
+                    strain[c][r] += amount / 2.0;
+                },
+            }
+        }
+        energy_at_strain(&strain)
+    };
+
+    let energy_0 = strained_energy(&[])?;
+    let mut matrix = [[0.0; 6]; 6];
+    for i in 0..6 {
+        info!("Voigt component {} of 6", i + 1);
+        let energy_plus = strained_energy(&[(i, step)])?;
+        let energy_minus = strained_energy(&[(i, -step)])?;
+        matrix[i][i] = (energy_plus - 2.0 * energy_0 + energy_minus) / (step * step);
+
+        for j in 0..i {
+            info!("Voigt components {} and {} of 6", j + 1, i + 1);
+            let energy_pp = strained_energy(&[(i, step), (j, step)])?;
+            let energy_pm = strained_energy(&[(i, step), (j, -step)])?;
+            let energy_mp = strained_energy(&[(i, -step), (j, step)])?;
+            let energy_mm = strained_energy(&[(i, -step), (j, -step)])?;
+            let value = (energy_pp - energy_pm - energy_mp + energy_mm) / (4.0 * step * step);
+            matrix[i][j] = value;
+            matrix[j][i] = value;
+        }
+    }
+    matrix
+})}
 
 //=================================================================
 
@@ -1639,15 +2914,17 @@ impl TrialDir {
         use crate::cmd::EvLoopStructureKind::*;
         use crate::filetypes::Eigensols;
 
-        let phonons_settings = match &settings.phonons {
-            Some(x) => x,
-            None => bail!("`rsp2-run-after-diagonalization` cannot be used without a `phonons:` config section"),
-        };
+        if settings.phonons.is_none() {
+            bail!("`rsp2-run-after-diagonalization` cannot be used without a `phonons:` config section");
+        }
 
         let pot = PotentialBuilder::from_root_config(Some(&self), on_demand, &settings)?;
 
         let (coords, meta) = self.read_stored_structure_data(&self.structure_path(PreEvChase(prev_iteration)))?;
 
+        let settings = &self.resolve_symmetry_tolerance(settings, &coords, meta.sift())?;
+        let phonons_settings = settings.phonons.as_ref().expect("(BUG!) checked above");
+
         let (freqs, evecs) = {
             if will_diagonalize {
                 trace!("Diagonalizing due to --diagonalize.");
@@ -1668,9 +2945,10 @@ impl TrialDir {
             }
         };
 
+        // (each run of this only sees one iteration, so modes can only be ignored by fingerprint)
         let (_, mut coords, did_ev_chasing) = self.do_ev_loop_stuff_after_diagonalization(
             settings, &pot, meta.sift(), prev_iteration,
-            coords, &freqs, &evecs,
+            coords, &freqs, &evecs, &mut vec![], &mut SymmetryCache::new(),
         )?;
 
         {
@@ -1689,7 +2967,10 @@ impl TrialDir {
         }
 
         let qpoint = V3::zero();
-        let dynmat = do_compute_dynmat(Some(self), settings, phonons_settings, &pot, qpoint, &coords, meta.sift())?;
+        let dynmat = do_compute_dynmat(
+            Some(self), settings, phonons_settings, &pot, qpoint, &coords, meta.sift(),
+            &mut SymmetryCache::new(),
+        )?;
         dynmat.save(self.gamma_dynmat_path(next_iteration))?;
 
         Ok(did_ev_chasing)
@@ -1784,6 +3065,129 @@ fn parse_sc_dims_argument(arg: &str) -> FailResult<[u32; 3]> {
 
 //=================================================================
 
+/// Structure formats understood by `rsp2-convert`.
+///
+/// This is deliberately separate from `StructureFileType`, which also includes
+/// formats that can only be read (and that usually require further optimization).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ConvertFormat { Poscar, Xyz, StoredStructure, LammpsData }
+
+impl ConvertFormat {
+    fn from_path(path: &Path) -> FailResult<Self> {
+        let file_name = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+        let extension = path.extension().and_then(|s| s.to_str());
+        Ok(match extension {
+            Some("vasp") | Some("poscar") => ConvertFormat::Poscar,
+            _ if file_name.starts_with("POSCAR") => ConvertFormat::Poscar,
+            _ if file_name.starts_with("CONTCAR") => ConvertFormat::Poscar,
+            Some("xyz") => ConvertFormat::Xyz,
+            Some("structure") => ConvertFormat::StoredStructure,
+            Some("data") | Some("lmp") => ConvertFormat::LammpsData,
+            _ if path.is_dir() => ConvertFormat::StoredStructure,
+            _ => bail!(
+                "cannot determine structure format of {}. \
+                (supported: .vasp, POSCAR, .xyz, .structure, .data, .lmp)",
+                path.nice(),
+            ),
+        })
+    }
+}
+
+pub fn run_convert(input: impl AsPath, output: impl AsPath) -> FailResult<()> {
+    use rsp2_structure_io::{Poscar, Xyz};
+
+    let input = input.as_path();
+    let output = output.as_path();
+    let in_format = ConvertFormat::from_path(input)?;
+    let out_format = ConvertFormat::from_path(output)?;
+
+    let mut dynamics = None;
+    let (title, coords, elements, extra_meta) = match in_format {
+        ConvertFormat::Poscar => {
+            let poscar: Poscar = Load::load(input)?;
+            dynamics = poscar.dynamics;
+            (poscar.comment, poscar.coords, poscar.elements, None)
+        },
+        ConvertFormat::Xyz => {
+            let Xyz { title, carts, elements } = Load::load(input)?;
+            warn!("\
+                {} has no lattice; the structure will be placed in a box with {} A of vacuum.\
+            ", input.nice(), CONVERT_XYZ_VACUUM);
+            (title, Coords::from_molecule(&carts, CONVERT_XYZ_VACUUM), elements, None)
+        },
+        ConvertFormat::StoredStructure => {
+            let stored = StoredStructure::load(input)?;
+            let meta = (stored.masses, stored.layers, stored.layer_sc_matrices, stored.frac_bonds);
+            (stored.title, stored.coords, stored.elements.to_vec(), Some(meta))
+        },
+        ConvertFormat::LammpsData => {
+            use rsp2_structure_io::lammps_data::{self, LammpsData};
+
+            let LammpsData { title, coords, elements, masses } = {
+                lammps_data::load(rsp2_fs_util::open_text(input)?)?
+            };
+            let masses: meta::SiteMasses = masses.into_iter().map(meta::Mass).collect::<Vec<_>>().into();
+            (title, coords, elements, Some((masses, None, None, None)))
+        },
+    };
+
+    if let Some((_, layers, sc_mats, bonds)) = &extra_meta {
+        let has_extra = layers.is_some() || sc_mats.is_some() || bonds.is_some();
+        if has_extra && out_format != ConvertFormat::StoredStructure {
+            warn!("\
+                Layers, layer supercell matrices, and bonds from {} cannot be stored in {}, \
+                and will be discarded.\
+            ", input.nice(), output.nice());
+        }
+    }
+    if dynamics.is_some() && out_format != ConvertFormat::Poscar {
+        warn!("Selective dynamics flags from {} will be discarded.", input.nice());
+    }
+
+    match out_format {
+        ConvertFormat::Poscar => {
+            Poscar { comment: &title, coords: &coords, elements: &elements, dynamics }.save(output)?;
+        },
+        ConvertFormat::Xyz => {
+            warn!("{} will not contain a lattice.", output.nice());
+            let carts = coords.to_carts();
+            Xyz { title: &title, carts: &carts, elements: &elements }.save(output)?;
+        },
+        ConvertFormat::StoredStructure => {
+            let (masses, layers, layer_sc_matrices, frac_bonds) = match extra_meta {
+                Some(meta) => meta,
+                None => (masses_by_config(None, elements.clone().into())?, None, None, None),
+            };
+            StoredStructure {
+                title, coords, layers, masses, layer_sc_matrices, frac_bonds,
+                elements: elements.into(),
+            }.save(output)?;
+        },
+        ConvertFormat::LammpsData => {
+            use rsp2_structure_io::lammps_data::{self, AtomType};
+
+            let masses = match extra_meta {
+                Some((masses, ..)) => masses,
+                None => masses_by_config(None, elements.clone().into())?,
+            };
+            // atom types are numbered in order of first appearance
+            let mut atom_types = vec![];
+            for (&element, &meta::Mass(mass)) in zip_eq!(&elements, &masses[..]) {
+                if atom_types.iter().all(|ty: &AtomType| ty.element != element) {
+                    atom_types.push(AtomType { element, mass });
+                }
+            }
+            let file = std::io::BufWriter::new(create(output)?);
+            lammps_data::dump(file, &title, &coords, &elements, &atom_types)?;
+        },
+    }
+    Ok(())
+}
+
+const CONVERT_XYZ_VACUUM: f64 = 30.0;
+
+//=================================================================
+
 // Reads a POSCAR or layers.yaml into an intermediate form which can have its
 // parameters optimized before producing a structure. (it also returns some other
 // layer-related data).
@@ -1794,6 +3198,7 @@ fn parse_sc_dims_argument(arg: &str) -> FailResult<[u32; 3]> {
 pub(crate) fn read_optimizable_structure(
     layer_cfg: Option<&cfg::LayerSearch>,
     mass_cfg: Option<&cfg::Masses>,
+    site_mass_cfg: Option<&cfg::SiteMasses>,
     file_format: StructureFileType,
     input: impl AsPath,
 ) -> FailResult<(
@@ -1916,6 +3321,9 @@ pub(crate) fn read_optimizable_structure(
             }
         },
     }
+    // (this takes precedence even over masses from a .structure directory)
+    let out_masses = masses_by_site_config(site_mass_cfg, out_masses)?;
+
     Ok((out_coords, hlist![out_elements, out_masses, out_layers, out_sc_mats, out_bonds]))
 }
 
@@ -1971,6 +3379,69 @@ pub(crate) fn masses_by_config(
         .collect::<Result<Vec<_>, _>>()?.into()
 })}
 
+/// Implements the behavior of the `"site-masses"` config section.
+///
+/// Overrides the masses of the listed sites, which must exist.
+pub(crate) fn masses_by_site_config(
+    cfg_site_masses: Option<&cfg::SiteMasses>,
+    masses: meta::SiteMasses,
+) -> FailResult<meta::SiteMasses>
+{Ok({
+    use crate::meta::Mass;
+
+    let map = match cfg_site_masses {
+        Some(cfg::SiteMasses(map)) => map,
+        None => return Ok(masses),
+    };
+
+    let mut masses = masses.to_vec();
+    for (&index, &mass) in map {
+        ensure!(
+            index < masses.len(),
+            "site-masses has index {}, but the structure only has {} atoms", index, masses.len(),
+        );
+        trace!("Overriding the mass of site {} ({} => {})", index, masses[index].0, mass);
+        masses[index] = Mass(mass);
+    }
+    masses.into()
+})}
+
+/// Multiply the masses of elements by factors given on the command line. (`--scale-mass`)
+///
+/// Factors given for the same element are multiplied together.
+///
+/// This is applied to the final masses, after `masses` and `site-masses`.  Masses given
+/// explicitly in the config (or stored in a `.structure` directory) are therefore scaled
+/// just like the default masses.
+pub(crate) fn scale_masses(
+    mass_scales: &[(meta::Element, f64)],
+    elements: &meta::SiteElements,
+    masses: &meta::SiteMasses,
+) -> meta::SiteMasses
+{
+    use crate::meta::Mass;
+
+    for &(element, factor) in mass_scales {
+        if elements.contains(&element) {
+            info!("Scaling the mass of {} by {}", element.symbol(), factor);
+        } else {
+            warn!("--scale-mass was given for {}, but the structure has no {} atoms", element.symbol(), element.symbol());
+        }
+    }
+
+    zip_eq!(elements.iter(), masses.iter())
+        .map(|(&element, &Mass(mass))| {
+            let factor = {
+                mass_scales.iter()
+                    .filter(|&&(scaled, _)| scaled == element)
+                    .map(|&(_, factor)| factor)
+                    .product::<f64>()
+            };
+            Mass(mass * factor)
+        })
+        .collect::<Vec<_>>().into()
+}
+
 // Run a callback in eco mode without needing to create a PotentialBuilder.
 fn eco_mode_without_potential<B, F>(
     settings: &Settings,
@@ -2001,7 +3472,7 @@ where F: FnOnce(EcoModeProof<'_>) -> FailResult<B>,
     let trial_dir = None;
     let pot: &dyn PotentialBuilder = &crate::potential::lammps::Builder::new(
         trial_dir, on_demand, &settings.threading, &settings.lammps,
-        NoPotential,
+        settings.parameters.as_ref(), None, NoPotential,
     )?;
 
     pot.eco_mode(|eco_proof| continuation(eco_proof))
@@ -2042,6 +3513,21 @@ impl TrialDir {
     pub fn snapshot_structure_path(&self) -> PathBuf
     { self.join("snapshot.structure") }
 
+    pub fn relaxation_trajectory_path(&self) -> PathBuf
+    { self.join("relaxation.xyz") }
+
+    pub fn ev_loop_checkpoint_path(&self) -> PathBuf
+    { self.join("ev-loop-checkpoint.json") }
+
+    pub fn ev_loop_convergence_path(&self) -> PathBuf
+    { self.join("convergence.json") }
+
+    pub fn ev_loop_energies_path(&self) -> PathBuf
+    { self.join("ev-loop-energies.csv") }
+
+    pub fn symmetry_tolerance_path(&self) -> PathBuf
+    { self.join("symmetry-tolerance.json") }
+
     pub fn eigensols_path(&self, iteration: Iteration) -> PathBuf
     { self.join(format!("ev-loop-modes-{:02}.json", iteration)) }
 
@@ -2054,8 +3540,163 @@ impl TrialDir {
     pub fn final_gamma_dynmat_path(&self) -> PathBuf
     { self.join("gamma-dynmat.npz") }
 
+    pub fn eigenvectors_path(&self, iteration: Iteration) -> PathBuf
+    { self.join(format!("eigenvectors.{:02}.json", iteration)) }
+
     pub fn animation_path(&self, iteration: Iteration, format: &cfg::AnimateFormat) -> PathBuf
     { match format {
         cfg::AnimateFormat::VSim {} => self.join(format!("ev-loop-modes-{:02}.ascii", iteration)),
     }}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displacements_are_scaled_by_element() {
+        let symbol = |s| meta::Element::from_symbol(s).unwrap();
+        let elements: meta::SiteElements = vec![symbol("C"), symbol("H"), symbol("C")].into();
+        let distances: BTreeMap<_, _> = vec![("H".to_string(), 0.005)].into_iter().collect();
+
+        let displacements = vec![
+            (0, V3([0.01, 0.0, 0.0])),
+            (1, V3([0.0, 0.01, 0.0])),
+            (2, V3([0.0, 0.0, -0.01])),
+        ];
+        let scaled = scale_displacements_by_element(&distances, 0.01, &elements, displacements.clone());
+
+        assert_eq!(scaled.len(), 3);
+        assert_eq!(scaled[0], displacements[0]);
+        assert_eq!(scaled[2], displacements[2]);
+        assert_eq!(scaled[1].0, 1);
+        assert_close!(abs=1e-15, scaled[1].1[1], 0.005);
+        assert_eq!((scaled[1].1[0], scaled[1].1[2]), (0.0, 0.0));
+    }
+
+    #[test]
+    fn scale_masses_after_explicit_masses() -> FailResult<()> {
+        use crate::meta::Mass;
+
+        let symbol = |s| meta::Element::from_symbol(s).unwrap();
+        let hydrogen = symbol("H");
+        let elements: meta::SiteElements = vec![symbol("C"), hydrogen, hydrogen].into();
+        let masses = masses_by_config(None, elements.clone())?;
+        let Mass(default_c) = masses[0];
+        let Mass(default_h) = masses[1];
+
+        // site 2 is explicitly given the mass of deuterium; it still gets scaled
+        let site_masses = cfg::SiteMasses(vec![(2, 2.014)].into_iter().collect());
+        let masses = masses_by_site_config(Some(&site_masses), masses)?;
+        let scaled = scale_masses(&[(hydrogen, 4.0), (hydrogen, 0.5)], &elements, &masses);
+
+        assert_eq!(scaled[0], Mass(default_c));
+        assert_close!(scaled[1].0, 2.0 * default_h);
+        assert_close!(scaled[2].0, 2.0 * 2.014);
+        Ok(())
+    }
+
+    #[test]
+    fn energy_curvature_of_quadratic() -> FailResult<()> {
+        let c = [
+            [9.0, 2.0, 1.0, 0.5, 0.0, 0.0],
+            [2.0, 8.0, 1.5, 0.0, 0.3, 0.0],
+            [1.0, 1.5, 7.0, 0.0, 0.0, 0.2],
+            [0.5, 0.0, 0.0, 4.0, 0.1, 0.0],
+            [0.0, 0.3, 0.0, 0.1, 3.0, 0.4],
+            [0.0, 0.0, 0.2, 0.0, 0.4, 2.0],
+        ];
+        let curvature = energy_curvature_wrt_voigt_strain(1e-3, |strain| FailOk({
+            let voigt = VOIGT_PAIRS.iter().map(|&(r, c)| match r == c {
+                true => strain[r][c],
+                false => strain[r][c] + strain[c][r],
+            }).collect::<Vec<_>>();
+
+            let mut energy = 0.0;
+            for i in 0..6 {
+                for j in 0..6 {
+                    energy += 0.5 * c[i][j] * voigt[i] * voigt[j];
+                }
+            }
+            energy
+        }))?;
+        assert_close!(abs=1e-8, curvature.to_vec(), c.to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn elastic_constants_of_morse_cubic() -> FailResult<()> {
+        use rsp2_structure::CoordsKind;
+
+        // Simple cubic, with only nearest neighbors in range of a Morse potential at its
+        // minimum.  Only the bonds along an axis resist a normal strain along that axis,
+        // and central forces with no tension give no resistance to shear at all, so
+        //
+        //     C11 = k / a  (with k = 2 D_e alpha^2)
+        //
+        // and every other component vanishes.
+        let (d_e, alpha, a) = (6.3, 2.0, 1.24);
+        let pot = PotentialBuilder::from_config_parts(
+            None,
+            None,
+            &cfg::Threading::Serial,
+            &from_json!({ }),
+            None,
+            &from_json!({"morse": {"pairs": [
+                {"elements": ["C", "C"], "d-e": d_e, "a": alpha, "r-e": a, "cutoff": 1.5},
+            ]}}),
+        )?;
+        // (a supercell, so that no atom is within the cutoff of its own images)
+        let prim = Coords::new(Lattice::cubic(a), CoordsKind::Carts(vec![V3::zero()]));
+        let (coords, _) = rsp2_structure::supercell::diagonal([2, 2, 2]).build(&prim);
+        let elements: meta::SiteElements = vec![meta::Element::CARBON; 8].into();
+        let masses: meta::SiteMasses = vec![meta::Mass(12.0); 8].into();
+        let meta = hlist![elements, masses, None::<meta::FracBonds>];
+
+        // (nothing to relax; every atom sits at an inversion center)
+        let curvature = energy_curvature_wrt_voigt_strain(1e-3, |strain| FailOk({
+            let mut coords = coords.clone();
+            coords.apply_strain(strain);
+            pot.one_off().compute_value(&coords, meta.clone())?
+        }))?;
+        let volume = coords.lattice().volume();
+
+        let k = 2.0 * d_e * alpha * alpha;
+        for i in 0..6 {
+            for j in 0..6 {
+                let expected = if i == j && i < 3 { k / a } else { 0.0 };
+                assert_close!(abs=1e-4 * k / a, curvature[i][j] / volume, expected, "{} {}", i, j);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn ev_normalization() {
+        let masses: meta::SiteMasses = vec![meta::Mass(4.0), meta::Mass(1.0)].into();
+        let evec = Ket3 {
+            real: vec![V3([0.6, 0.0, 0.0]), V3([0.0, 0.0, 0.0])],
+            imag: vec![V3([0.0, 0.0, 0.0]), V3([0.0, 0.8, 0.0])],
+        };
+        let direction = EvDirection::from_eigenvector(&evec, hlist![masses.clone()]);
+        let normalize = |basis, unit| {
+            let normalization = cfg::EvNormalization { basis, unit };
+            normalize_ev_direction(&direction, Some(&normalization), hlist![masses.clone()])
+        };
+
+        // no normalization gives the cartesian displacements
+        let ket = normalize_ev_direction(&direction, None, hlist![masses.clone()]);
+        assert_close!(abs=1e-15, ket.real[0].0, [0.3, 0.0, 0.0]);
+        assert_close!(abs=1e-15, ket.imag[1].0, [0.0, 0.8, 0.0]);
+
+        // the original eigenvector is recovered
+        let ket = normalize(cfg::EvBasis::MassWeighted, cfg::EvUnit::Mode);
+        assert_close!(abs=1e-15, ket.real[0].0, evec.real[0].0);
+        assert_close!(abs=1e-15, ket.imag[1].0, evec.imag[1].0);
+
+        // the largest atom has unit norm
+        let ket = normalize(cfg::EvBasis::Cartesian, cfg::EvUnit::Atom);
+        assert_close!(abs=1e-15, ket.real[0].0, [0.375, 0.0, 0.0]);
+        assert_close!(abs=1e-15, ket.imag[1].0, [0.0, 1.0, 0.0]);
+    }
+}
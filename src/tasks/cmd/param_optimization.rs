@@ -10,7 +10,7 @@
 ** ************************************************************************ */
 
 use crate::{FailResult};
-use crate::potential::{CommonMeta, PotentialBuilder, DiffFn, BondGrad, BondDiffFn};
+use crate::potential::{CommonMeta, PotentialBuilder, DiffFn, BondGrad, BondDiffFn, stress_from_bond_grad};
 use crate::meta::{prelude::*};
 
 use rsp2_tasks_config::MaskBit;
@@ -18,7 +18,7 @@ use rsp2_minimize::exact_ls::{Value, Golden};
 use rsp2_structure::{Lattice, CoordsKind, Coords};
 use rsp2_structure::layer::{LayersPerUnitCell, require_simple_axis_normal};
 use rsp2_structure_io::assemble::{Assemble, RawAssemble};
-use rsp2_array_types::{V3, M22};
+use rsp2_array_types::{V3, M22, M33};
 use rsp2_tasks_config as cfg;
 use stack::{ArrayVec, Vector as StackVector};
 use slice_of_array::prelude::*;
@@ -44,15 +44,8 @@ pub(crate) fn optimize_layer_parameters(
     };
 
     // Set reasonable values for first iteration.
-    for Scalable { spec, setter, .. } in &scalables {
-        match *spec {
-            cfg::ScalableRange::Exact { value } |
-            cfg::ScalableRange::Search { range: _, guess: Some(value) } => {
-                setter(&mut coords_builder, value);
-            },
-            // no guess ==> use whatever the structure had when we got it
-            cfg::ScalableRange::Search { range: _, guess: None } => {},
-        }
+    for Scalable { spec, setter, measured, .. } in &scalables {
+        setter(&mut coords_builder, spec.initial_value(*measured));
     }
 
     // optimize them one-by-one.
@@ -62,7 +55,7 @@ pub(crate) fn optimize_layer_parameters(
     // relaxed may be set to different, better values, which may in turn
     // cause different values to be chosen for the earlier parameters.
     for _ in 0..settings.repeat_count {
-        for &Scalable { ref name, ref spec, ref setter } in &scalables {
+        for &Scalable { ref name, ref spec, ref setter, .. } in &scalables {
             let best = match *spec {
                 cfg::ScalableRange::Exact { value } => {
                     trace!("Fixing {} at {}", name, value);
@@ -124,6 +117,8 @@ struct Scalable {
     setter: Box<dyn Fn(&mut ScalableCoords, f64)>,
     name: String,
     spec: cfg::ScalableRange,
+    /// The value found in the input structure, if meaningful.
+    measured: Option<f64>,
 }
 
 pub enum ScalableCoords {
@@ -178,9 +173,11 @@ fn add_scalables(
 
     // obtain data to be used by some scalables
     let mut n_layer_seps = None;
+    let mut initial_layer_seps = None;
     match structure {
         ScalableCoords::KnownLayers { ref layer_builder, .. } => {
             n_layer_seps = Some(layer_builder.num_layer_seps());
+            initial_layer_seps = Some(layer_builder.get_layer_seps().to_vec());
         },
         _ => {},
     }
@@ -203,6 +200,7 @@ fn add_scalables(
                     }
                 }),
                 spec: range.clone(),
+                measured: None,
             });
         },
 
@@ -210,6 +208,19 @@ fn add_scalables(
             // one scalable for all layers
             let n_layer_seps = n_layer_seps.expect("BUG!");
             let mask = mask.clone().unwrap_or(vec![MaskBit(true); n_layer_seps]);
+            let measured = {
+                let initial_layer_seps = initial_layer_seps.as_ref().expect("BUG!");
+                let masked = {
+                    zip_eq!(initial_layer_seps, &mask)
+                        .filter(|&(_, bit)| bit.0)
+                        .map(|(&sep, _)| sep)
+                        .collect::<Vec<_>>()
+                };
+                match masked.len() {
+                    0 => None,
+                    n => Some(masked.iter().sum::<f64>() / n as f64),
+                }
+            };
             emit(Scalable {
                 name: format!("a uniform layer separation"),
                 setter: Box::new(move |s, val| match s {
@@ -223,6 +234,7 @@ fn add_scalables(
                     },
                 }),
                 spec: range.clone(),
+                measured,
             });
         },
 
@@ -243,6 +255,7 @@ fn add_scalables(
                         },
                     }),
                     spec: range.clone(),
+                    measured: Some(initial_layer_seps.as_ref().expect("BUG!")[k]),
                 });
             }
         },
@@ -333,6 +346,7 @@ impl ScalableCoords {
 pub enum RelaxationOptimizationHelper {
     ParamBased(ParamOptimizationHelper),
     LatticeBased(LatticeOptimizationHelper),
+    StrainBased(StrainOptimizationHelper),
 }
 
 /// Maps coordinates into a flat representation that includes up to three elements representing
@@ -352,12 +366,25 @@ pub struct LatticeOptimizationHelper {
     original_lattice: Lattice,
 }
 
+/// Maps coordinates into a flat representation that includes up to six elements representing
+/// the independent components of a symmetric strain, for variable-cell relaxation.
+#[derive(Clone)]
+pub struct StrainOptimizationHelper {
+    // (row, col) of each strain component that is optimized.
+    components: Vec<(usize, usize)>,
+    original_lattice: Lattice,
+}
+
+/// Independent components of a symmetric strain, in Voigt order.
+const VOIGT_COMPONENTS: [(usize, usize); 6] = [(0, 0), (1, 1), (2, 2), (1, 2), (0, 2), (0, 1)];
+
 impl RelaxationOptimizationHelper {
     /// Produce a flat representation of the coords, with lattice params included as elements.
     pub fn flatten_coords(&self, coords: &Coords) -> Vec<f64> {
         match self {
             RelaxationOptimizationHelper::ParamBased(helper) => helper.flatten_coords(coords),
             RelaxationOptimizationHelper::LatticeBased(helper) => helper.flatten_coords(coords),
+            RelaxationOptimizationHelper::StrainBased(helper) => helper.flatten_coords(coords),
         }
     }
 
@@ -366,6 +393,7 @@ impl RelaxationOptimizationHelper {
         match self {
             RelaxationOptimizationHelper::ParamBased(helper) => helper.unflatten_coords(flat),
             RelaxationOptimizationHelper::LatticeBased(helper) => helper.unflatten_coords(flat),
+            RelaxationOptimizationHelper::StrainBased(helper) => helper.unflatten_coords(flat),
         }
     }
 
@@ -375,15 +403,17 @@ impl RelaxationOptimizationHelper {
         match self {
             RelaxationOptimizationHelper::ParamBased(helper) => helper.flatten_grad(flat, bond_grads),
             RelaxationOptimizationHelper::LatticeBased(helper) => helper.flatten_grad(flat, bond_grads),
+            RelaxationOptimizationHelper::StrainBased(helper) => helper.flatten_grad(flat, bond_grads),
         }
     }
 
     /// Get the gradient with respect to positions in cartesian space,
     /// and with respect to each lattice parameter.
-    pub fn unflatten_grad(&self, coords: &[f64], grad: &[f64]) -> (Vec<V3>, ArrayVec<[f64; 4]>) {
+    pub fn unflatten_grad(&self, coords: &[f64], grad: &[f64]) -> (Vec<V3>, ArrayVec<[f64; 6]>) {
         match self {
             RelaxationOptimizationHelper::ParamBased(helper) => helper.unflatten_grad(coords, grad),
             RelaxationOptimizationHelper::LatticeBased(helper) => helper.unflatten_grad(coords, grad),
+            RelaxationOptimizationHelper::StrainBased(helper) => helper.unflatten_grad(coords, grad),
         }
     }
 }
@@ -501,7 +531,7 @@ impl ParamOptimizationHelper {
 
     /// Get the gradient with respect to positions in cartesian space,
     /// and with respect to each lattice parameter.
-    pub fn unflatten_grad(&self, coords: &[f64], grad: &[f64]) -> (Vec<V3>, ArrayVec<[f64; 4]>) {
+    pub fn unflatten_grad(&self, coords: &[f64], grad: &[f64]) -> (Vec<V3>, ArrayVec<[f64; 6]>) {
         let (_, params) = self.split(coords);
         let (d_fracs, d_params) = self.split(grad);
 
@@ -621,7 +651,7 @@ impl LatticeOptimizationHelper {
 
     /// Get the gradient with respect to positions in cartesian space,
     /// and with respect to each lattice parameter.
-    pub fn unflatten_grad(&self, coords: &[f64], flat_grad: &[f64]) -> (Vec<V3>, ArrayVec<[f64; 4]>) {
+    pub fn unflatten_grad(&self, coords: &[f64], flat_grad: &[f64]) -> (Vec<V3>, ArrayVec<[f64; 6]>) {
         let (_, params) = self.split(coords);
         let (d_fracs, d_params) = self.split(flat_grad);
 
@@ -660,6 +690,143 @@ impl LatticeOptimizationHelper {
     }
 }
 
+impl StrainOptimizationHelper {
+    /// If `params` is supplied, only strain components between axes with a named
+    /// parameter are optimized.
+    pub fn new(params: Option<&cfg::Parameters>, original_lattice: &Lattice) -> Self {
+        let is_free = |axis: usize| match params.map(|params| params[axis]) {
+            None => true,
+            Some(cfg::Parameter::Param(_)) => true,
+            Some(cfg::Parameter::One) |
+            Some(cfg::Parameter::NotPeriodic) => false,
+        };
+        let components = {
+            VOIGT_COMPONENTS.iter().cloned()
+                .filter(|&(r, c)| is_free(r) && is_free(c))
+                .collect()
+        };
+        let original_lattice = original_lattice.clone();
+        StrainOptimizationHelper { components, original_lattice }
+    }
+
+    pub fn num_params(&self) -> usize { self.components.len() }
+
+    /// Split a slice into the parts corresponding to fractional coords, and the part corresponding
+    /// to the strain components.
+    pub fn split<'b, T>(&self, slice: &'b [T]) -> (&'b [V3<T>], &'b [T]) {
+        let (frac, params) = slice.split_at(slice.len() - self.num_params());
+        (frac.nest(), params)
+    }
+
+    pub fn split_mut<'b, T>(&self, slice: &'b mut [T]) -> (&'b mut [V3<T>], &'b mut [T]) {
+        let div = slice.len() - self.num_params();
+        let (frac, params) = slice.split_at_mut(div);
+        (frac.nest_mut(), params)
+    }
+
+    /// Produce a flat representation of the coords, with strain components included as elements.
+    pub fn flatten_coords(&self, coords: &Coords) -> Vec<f64> {
+        let mut out = coords.to_fracs().flat().to_vec();
+        out.extend(self.read_strain_params(coords.lattice()));
+        out
+    }
+
+    /// Recover a Coords from the flattened representation
+    pub fn unflatten_coords(&self, flat: &[f64]) -> Coords {
+        let (flat_fracs, params) = flat.split_at(flat.len() - self.num_params());
+
+        Coords::new(
+            self.lattice_with_params(params),
+            CoordsKind::Fracs(flat_fracs.nest().to_vec()),
+        )
+    }
+
+    /// Get the effective gradient on the coordinates in the flattened representation
+    /// given gradients with respect to the cartesian bond vectors.
+    pub fn flatten_grad(&self, flat: &[f64], bond_grads: &[BondGrad]) -> Vec<f64> {
+        let (fracs, _) = self.split(flat);
+        let mut cart_grad = vec![V3::zero(); fracs.len()];
+        for item in bond_grads {
+            cart_grad[item.plus_site] += item.grad;
+            cart_grad[item.minus_site] -= item.grad;
+        }
+        let lattice = self.unflatten_coords(flat).lattice().clone();
+        let stress = stress_from_bond_grad(&lattice, bond_grads.iter().cloned());
+        self.flatten_grad_from_stress(flat, &cart_grad, &stress)
+    }
+
+    /// Get the effective gradient on the coordinates in the flattened representation
+    /// given the cartesian gradient and the stress tensor.
+    pub fn flatten_grad_from_stress(&self, flat: &[f64], cart_grad: &[V3], stress: &M33) -> Vec<f64> {
+        let mut out = vec![0.0; flat.len()];
+        let (_, params) = self.split(flat);
+        let (d_site_frac, d_param) = self.split_mut(&mut out);
+
+        let strain = self.strain_with_params(params);
+        let ref lattice = self.lattice_with_params(params);
+        let ref recip_lattice = lattice.reciprocal();
+
+        // gradients transform by the reciprocal lattice
+        for (d_frac, &d_cart) in zip_eq!(d_site_frac, cart_grad) {
+            *d_frac = d_cart / recip_lattice;
+        }
+
+        // The stress describes the response to a strain applied on top of the current
+        // structure, while our strain is measured against the original lattice:
+        //
+        //   (I + strain + d_strain) = (I + d_strain * inv(I + strain)) (I + strain)
+        //
+        // so the gradient with respect to our strain is  V * stress * inv(I + strain).
+        let d_strain = &(stress * lattice.volume()) * &(M33::eye() + &strain).inv();
+        for (d_param, &(r, c)) in zip_eq!(d_param, &self.components[..]) {
+            *d_param = if r == c {
+                d_strain[r][c]
+            } else {
+                // the parameter appears in two places in the symmetric matrix
+                d_strain[r][c] + d_strain[c][r]
+            };
+        }
+
+        out
+    }
+
+    /// Get the gradient with respect to positions in cartesian space,
+    /// and with respect to each strain component.
+    pub fn unflatten_grad(&self, coords: &[f64], grad: &[f64]) -> (Vec<V3>, ArrayVec<[f64; 6]>) {
+        let (_, params) = self.split(coords);
+        let (d_fracs, d_params) = self.split(grad);
+
+        // gradients transform as reciprocal vectors
+        let recip_lattice = self.lattice_with_params(params).reciprocal();
+        let d_carts = CoordsKind::Fracs(d_fracs).to_carts(&recip_lattice);
+        (d_carts, d_params.iter().cloned().collect())
+    }
+
+    fn read_strain_params(&self, lattice: &Lattice) -> ArrayVec<[f64; 6]> {
+        // each lattice vector is  (I + strain) a0,  so (as row vectors)
+        //   lattice = original * (I + strain).t()
+        let deformation = (self.original_lattice.inverse_matrix() * lattice.matrix()).t();
+        let strain = deformation - M33::eye();
+        self.components.iter().map(|&(r, c)| 0.5 * (strain[r][c] + strain[c][r])).collect()
+    }
+
+    fn strain_with_params(&self, params: &[f64]) -> M33 {
+        assert_eq!(params.len(), self.num_params());
+
+        let mut strain = M33::zero();
+        for (&param, &(r, c)) in zip_eq!(params, &self.components[..]) {
+            strain[r][c] = param;
+            strain[c][r] = param;
+        }
+        strain
+    }
+
+    fn lattice_with_params(&self, params: &[f64]) -> Lattice {
+        let strain = self.strain_with_params(params);
+        self.original_lattice.transformed_by(&(M33::eye() + &strain))
+    }
+}
+
 //---------------------------
 
 pub struct OptimizingDiffFn {
@@ -686,6 +853,33 @@ impl rsp2_minimize::cg::DiffFn for OptimizingDiffFn {
     }
 }
 
+/// Variable-cell relaxation.  Unlike `OptimizingDiffFn`, this works with any potential that
+/// can compute the stress tensor.
+pub struct VariableCellDiffFn {
+    pub helper: std::rc::Rc<StrainOptimizationHelper>,
+    pub diff_fn: Box<dyn DiffFn<CommonMeta>>,
+    pub meta: CommonMeta,
+}
+
+impl rsp2_minimize::cg::DiffFn for VariableCellDiffFn {
+    type Error = failure::Error;
+
+    fn compute(&mut self, flat_coords: &[f64]) -> Result<(f64, Vec<f64>), failure::Error> {
+        let VariableCellDiffFn { ref helper, ref mut diff_fn, ref meta } = *self;
+        let ref coords = helper.unflatten_coords(flat_coords);
+        let (value, cart_grad) = diff_fn.compute(coords, meta.clone())?;
+        let stress = diff_fn.compute_stress(coords, meta.clone())?;
+        let flat_grad = helper.flatten_grad_from_stress(flat_coords, &cart_grad, &stress);
+        Ok((value, flat_grad))
+    }
+
+    fn check(&mut self, flat_coords: &[f64]) -> Result<(), failure::Error> {
+        let VariableCellDiffFn { ref helper, ref mut diff_fn, ref meta } = *self;
+        let ref coords = helper.unflatten_coords(flat_coords);
+        diff_fn.check(coords, meta.clone())
+    }
+}
+
 //-----------------------------------------------------------------------------
 
 #[cfg(test)]
@@ -749,12 +943,22 @@ mod tests {
         test_helper(helper, coords, meta)
     }
 
+    #[test]
+    fn strain_optimization_helper() {
+        let (coords, meta) = modified_graphene();
+        let helper = RelaxationOptimizationHelper::StrainBased({
+            StrainOptimizationHelper::new(None, coords.lattice())
+        });
+        test_helper(helper, coords, meta)
+    }
+
     fn test_helper(helper: RelaxationOptimizationHelper, coords: Coords, meta: CommonMeta) {
         let pot = PotentialBuilder::from_config_parts(
             None,
             None,
             &cfg::Threading::Serial,
             &from_json!({ }),
+            None,
             &from_json!({ "rebo-nonreactive": {"params": "brenner"} }),
         ).unwrap();
         let bond_diff_fn = pot.initialize_bond_diff_fn(&coords, meta.sift()).unwrap().unwrap();
@@ -167,6 +167,8 @@ mod builder {
     pub struct Builder {
         symprec: Option<f64>,
         conf: Conf,
+        retries: u32,
+        retry_delay: f64,
     }
 
     impl Default for Builder {
@@ -174,6 +176,8 @@ mod builder {
             Builder {
                 symprec: None,
                 conf: Default::default(),
+                retries: 0,
+                retry_delay: 0.0,
             }
         }
     }
@@ -208,6 +212,11 @@ mod builder {
         pub fn diagonal_disps(self, value: bool) -> Self
         { self.conf("DIAG", fortran_bool(value)) }
 
+        /// Retry up to `retries` times if phonopy fails, waiting `delay` seconds before the
+        /// first retry and doubling the wait after each one.
+        pub fn retries(mut self, retries: u32, delay: f64) -> Self
+        { self.retries = retries; self.retry_delay = delay; self }
+
         fn args_from_settings(&self) -> Args
         {
             let mut out = vec![];
@@ -253,9 +262,29 @@ mod builder {
                 meta::SiteMasses,
             >,
         ) -> FailResult<DirWithDisps>
+        {
+            // Every attempt gets a fresh directory, so that output half-written by a
+            // failed attempt can't affect the next one.
+            retry_on_phonopy_failure(self.retries, self.retry_delay, || {
+                self._displacements_attempt(coords, meta.clone())
+            })
+        }
+
+        fn _displacements_attempt(
+            &self,
+            coords: &Coords,
+            meta: HList2<
+                meta::SiteElements,
+                meta::SiteMasses,
+            >,
+        ) -> FailResult<DirWithDisps>
         {Ok({
             let elements: meta::SiteElements = meta.pick();
 
+            // look for these up front, rather than failing on a cryptic io error later
+            let phonopy = crate::env::phonopy_executable()?;
+            crate::env::python_executable()?;
+
             let dir = TempDir::new_labeled("rsp2", "phonopy")?;
             {
                 let dir = dir.path();
@@ -264,13 +293,13 @@ mod builder {
                 let extra_args = self.args_from_settings();
                 self.conf.save(dir.join(FNAME_CONF_DISPS))?;
                 Poscar {
-                    comment: "blah", coords, elements,
+                    comment: "blah", coords, elements, dynamics: None,
                 }.save(dir.join("POSCAR"))?;
                 extra_args.save(dir.join(FNAME_SETTINGS_ARGS))?;
 
                 {
                     trace!("Calling phonopy for displacements...");
-                    let mut command = Command::new("phonopy");
+                    let mut command = Command::new(&phonopy);
                     command
                         .args(&extra_args.0)
                         .arg(FNAME_CONF_DISPS)
@@ -282,7 +311,7 @@ mod builder {
 
                 {
                     trace!("Producing {}...", FNAME_OUT_SYMMETRY);
-                    let mut command = Command::new("phonopy");
+                    let mut command = Command::new(&phonopy);
                     command
                         .args(&extra_args.0)
                         .arg(FNAME_CONF_DISPS)
@@ -456,7 +485,7 @@ pub fn phonopy_displacements(
     our_super_coords: &Coords,
 ) -> FailResult<PhonopyDisplacements> {
     let displacement_distance = settings.displacement_distance.expect("(bug) missing displacement-distance should have been caught earlier");
-    let symmetry_tolerance = settings.symmetry_tolerance.expect("(bug) missing symmetry-tolerance should have been caught earlier");
+    let symmetry_tolerance = settings.symmetry_tolerance.and_then(|tol| tol.fixed()).expect("(bug) symmetry-tolerance should have been resolved earlier");
     let dir = {
         let mut builder = {
             builder::Builder::new()
@@ -467,8 +496,8 @@ pub fn phonopy_displacements(
                 .conf("DISPLACEMENT_DISTANCE", format!("{:e}", displacement_distance))
                 .supercell_dim(settings.supercell.dim_for_unitcell(prim_coords.lattice()))
         };
-        if let cfg::PhononDispFinder::Phonopy { diag } = settings.disp_finder {
-            builder = builder.diagonal_disps(diag);
+        if let cfg::PhononDispFinder::Phonopy { diag, retries, retry_delay } = settings.disp_finder {
+            builder = builder.diagonal_disps(diag).retries(retries, retry_delay);
         }
         builder.displacements(prim_coords, prim_meta.sift())?
     };
@@ -674,4 +703,83 @@ fn check_status(status: std::process::ExitStatus) -> Result<(), PhonopyFailed>
     }
 }
 
+/// Call `attempt` until it succeeds, retrying up to `retries` times if it fails with
+/// `PhonopyFailed`.  The first retry waits `delay` seconds, and the wait doubles after each.
+fn retry_on_phonopy_failure<T>(
+    retries: u32,
+    delay: f64,
+    mut attempt: impl FnMut() -> FailResult<T>,
+) -> FailResult<T>
+{
+    let mut retries_left = retries;
+    let mut delay = delay;
+    loop {
+        match attempt() {
+            Err(e) => {
+                if retries_left == 0 || e.downcast_ref::<PhonopyFailed>().is_none() {
+                    return Err(e);
+                }
+                warn!("{}. Retrying in {}s. ({} retries left)", e, delay, retries_left);
+                std::thread::sleep(std::time::Duration::from_millis((delay * 1e3) as u64));
+                retries_left -= 1;
+                delay *= 2.0;
+            },
+            result => return result,
+        }
+    }
+}
+
 //-----------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FailOk;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn retry_after_phonopy_failure() -> FailResult<()> {
+        // A fake phonopy that records its working directory and fails on its first two runs.
+        let tmp = TempDir::new("rsp2-test")?;
+        let log = tmp.path().join("runs");
+        let fake_phonopy = tmp.path().join("phonopy");
+        std::fs::write(&fake_phonopy, format!(
+            "#!/bin/sh\npwd -P >> '{log}'\ntest $(wc -l < '{log}') -gt 2\n",
+            log = log.display(),
+        ))?;
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&fake_phonopy, std::fs::Permissions::from_mode(0o755))?;
+        }
+
+        // (like _displacements_attempt, each attempt runs in a new directory)
+        let delay = 0.1;
+        let mut start_times = vec![];
+        let last_dir = retry_on_phonopy_failure(3, delay, || FailOk({
+            start_times.push(Instant::now());
+            let dir = TempDir::new_labeled("rsp2", "phonopy")?;
+            check_status(Command::new(&fake_phonopy).current_dir(dir.path()).status()?)?;
+            dir.path().canonicalize()?
+        }))?;
+
+        let dirs: Vec<_> = std::fs::read_to_string(&log)?.lines().map(|line| Path::new(line).to_owned()).collect();
+        assert_eq!(dirs.len(), 3);
+        assert_eq!(dirs[2], last_dir);
+        assert!(dirs[0] != dirs[1] && dirs[1] != dirs[2] && dirs[0] != dirs[2], "{:?}", dirs);
+
+        // the delay doubles after each retry
+        assert_eq!(start_times.len(), 3);
+        let secs = |d: Duration| d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9;
+        assert!(secs(start_times[1] - start_times[0]) >= delay);
+        assert!(secs(start_times[2] - start_times[1]) >= 2.0 * delay);
+
+        // ...and the error comes through once the retries are used up
+        std::fs::remove_file(&log)?;
+        let err = retry_on_phonopy_failure(1, 0.0, || {
+            FailOk(check_status(Command::new(&fake_phonopy).status()?)?)
+        }).unwrap_err();
+        assert!(err.downcast_ref::<PhonopyFailed>().is_some());
+        assert_eq!(std::fs::read_to_string(&log)?.lines().count(), 2);
+        Ok(())
+    }
+}
@@ -32,7 +32,7 @@ mod raw_stdout_log {
 }
 
 #[derive(Debug, Fail)]
-#[fail(display = "an error occurred running the most trivial python script")]
+#[fail(display = "an error occurred running the most trivial python script (the interpreter can be changed through RSP2_PYTHON)")]
 pub struct PythonExecutionError;
 
 pub fn check_availability() -> FailResult<()> {
@@ -42,6 +42,11 @@ pub fn check_availability() -> FailResult<()> {
     use self::spglib::PY_CHECK_SPGLIB_AVAILABILITY;
     use self::spglib::SpglibAvailabilityError;
 
+    info!(
+        "python: {} ({})",
+        crate::env::python_executable()?.display(),
+        crate::env::PYTHON_EXECUTABLE,
+    );
     call_script_and_check_success(PY_NOOP, PythonExecutionError)?;
     call_script_and_check_success(PY_CHECK_SCIPY_AVAILABILITY, ScipyAvailabilityError)?;
     call_script_and_check_success(PY_CHECK_SPGLIB_AVAILABILITY, SpglibAvailabilityError)?;
@@ -59,7 +64,7 @@ fn call_script_and_check_success<E: failure::Fail>(
     let tmp = fsx::TempDir::new_labeled("rsp2", "python script")?;
     let script = ReifiedScript::new(script, tmp.path().join("script.py"))?;
 
-    let mut cmd = process::Command::new("python3");
+    let mut cmd = process::Command::new(crate::env::python_executable()?);
     script.add_args(&mut cmd);
 
     cmd.stdout(Stdio::piped());
@@ -116,7 +121,7 @@ where
 
     let script = ReifiedScript::new(script, tmp.path().join("script.py"))?;
 
-    let mut cmd = process::Command::new("python3");
+    let mut cmd = process::Command::new(crate::env::python_executable()?);
     script.add_args(&mut cmd);
     add_args(&mut cmd);
 
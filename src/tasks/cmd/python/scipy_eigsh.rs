@@ -45,6 +45,7 @@
 
 use crate::FailResult;
 use crate::math::basis::{Basis3, GammaBasis3};
+use rsp2_tasks_config as cfg;
 use std::sync::Arc;
 
 #[allow(unused)] // rustc bug
@@ -77,6 +78,7 @@ mod scripts {
         pub(super) kw: PyKw,
         // permits non-convergence exceptions
         pub(super) allow_fewer_solutions: bool,
+        pub(super) seed: Option<u64>,
     }
 
     #[allow(unused)]
@@ -99,6 +101,7 @@ mod scripts {
         pub(super) max_solutions: usize,
         pub(super) shift_invert_attempts: u32,
         pub(super) dense: bool,
+        pub(super) seed: Option<u64>,
     }
 
     #[allow(unused)]
@@ -190,10 +193,14 @@ use rsp2_dynmat::DynamicalMatrix;
 ///
 /// If none of the modes produced are negative, then it is safe (-ish) to assume that the matrix
 /// has no such eigenmodes.  (At least, that is the intent!)
+///
+/// With a `seed`, the starting vectors of every ARPACK call are derived from it, making the
+/// output reproducible.  With `None`, ARPACK chooses them at random.
 pub fn compute_negative_eigensolutions_gamma(
     dynmat: &DynamicalMatrix,
     max_solutions: usize,
     shift_invert_attempts: u32,
+    seed: Option<u64>,
 ) -> FailResult<(Vec<f64>, GammaBasis3)> {
     trace!("Computing most negative eigensolutions.");
     scripts::Negative {
@@ -201,6 +208,54 @@ pub fn compute_negative_eigensolutions_gamma(
         max_solutions,
         shift_invert_attempts,
         dense: false,
+        seed,
+    }.invoke_gamma()
+}
+
+/// Seek the eigensolutions of largest magnitude (i.e. the top of the spectrum).
+///
+/// Fewer than `max_solutions` may be produced if ARPACK fails to converge on some of them.
+pub fn compute_largest_eigensolutions_gamma(
+    dynmat: &DynamicalMatrix,
+    max_solutions: usize,
+    seed: Option<u64>,
+) -> FailResult<(Vec<f64>, GammaBasis3)> {
+    trace!("Computing largest eigensolutions.");
+    scripts::Eigsh {
+        matrix: dynmat.cereal(),
+        kw: PyKw {
+            how_many: Some(max_solutions),
+            which: Some(Which::LargestMagnitude),
+            ..Default::default()
+        },
+        allow_fewer_solutions: true,
+        seed,
+    }.invoke_gamma()
+}
+
+/// Seek the eigensolutions whose eigenvalues are closest to `shift`, using shift-invert mode.
+///
+/// This is numerically unreliable if `shift` is very close to an actual eigenvalue.
+/// Fewer than `max_solutions` may be produced if ARPACK fails to converge on some of them.
+pub fn compute_eigensolutions_near_gamma(
+    dynmat: &DynamicalMatrix,
+    max_solutions: usize,
+    shift: f64,
+    seed: Option<u64>,
+) -> FailResult<(Vec<f64>, GammaBasis3)> {
+    trace!("Computing eigensolutions near {:e}.", shift);
+    scripts::Eigsh {
+        matrix: dynmat.cereal(),
+        kw: PyKw {
+            how_many: Some(max_solutions),
+            shift_invert_target: Some(shift),
+            shift_invert_mode: Some(ShiftInvertMode::Normal),
+            // (in shift-invert mode, this refers to the transformed eigenvalues)
+            which: Some(Which::LargestMagnitude),
+            ..Default::default()
+        },
+        allow_fewer_solutions: true,
+        seed,
     }.invoke_gamma()
 }
 
@@ -214,3 +269,44 @@ pub fn compute_eigensolutions_dense_gamma(dynmat: &DynamicalMatrix) -> (Vec<f64>
     let eigenvectors = GammaBasis3(Arc::new(eigenvectors.into_iter().map(GammaKet3).collect()));
     (frequencies, eigenvectors)
 }
+
+/// Produce the eigensolutions of a dynamical matrix at gamma whose frequencies lie in a window.
+///
+/// All eigensolutions are still computed, but the rest are discarded early on.
+pub fn compute_eigensolutions_dense_gamma_in_window(
+    dynmat: &DynamicalMatrix,
+    window: &cfg::FrequencyWindow,
+) -> (Vec<f64>, GammaBasis3) {
+    use crate::math::basis::GammaKet3;
+    use crate::filetypes::eigensols::eigenvalue_to_frequency;
+
+    let (eigenvalues, eigenvectors) = {
+        dynmat.compute_eigensolutions_dense_gamma_filtered(|value| {
+            window.contains(eigenvalue_to_frequency(value))
+        })
+    };
+    trace!("Kept {} eigensolutions in the frequency window.", eigenvalues.eigenvalues.len());
+
+    let frequencies = eigenvalues.eigenvalues.into_iter().map(eigenvalue_to_frequency).collect();
+    let eigenvectors = GammaBasis3(Arc::new(eigenvectors.into_iter().map(GammaKet3).collect()));
+    (frequencies, eigenvectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_is_reproducible() -> FailResult<()> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/resources/gamma-dynmat-out/gamma-dynmat-rust.npz");
+        let dynmat = DynamicalMatrix::read_npz(std::fs::File::open(path)?)?;
+
+        // bitwise identical, not merely close
+        let negative = |seed| compute_negative_eigensolutions_gamma(&dynmat, 4, 3, seed).map(|(freqs, _)| freqs);
+        assert_eq!(negative(Some(42))?, negative(Some(42))?);
+
+        let largest = |seed| compute_largest_eigensolutions_gamma(&dynmat, 4, seed).map(|(freqs, _)| freqs);
+        assert_eq!(largest(Some(42))?, largest(Some(42))?);
+        Ok(())
+    }
+}
@@ -15,6 +15,7 @@
 use crate::FailResult;
 use rsp2_array_types::{V3, M33};
 use rsp2_structure::{Coords, Lattice, IntRot, CartOp};
+use std::ops::Range;
 
 use super::{call_script_and_communicate, Script};
 
@@ -42,6 +43,43 @@ pub struct SpglibAvailabilityError;
 #[fail(display = "{}", _0)]
 pub struct SpglibError(String);
 
+/// Smallest tolerance tried by `SpgDataset::compute_auto`.
+const AUTO_SYMPREC_MIN: f64 = 1e-6;
+const AUTO_SYMPREC_NUM_DECADES: u32 = 5;
+const AUTO_SYMPREC_STEPS_PER_DECADE: u32 = 3;
+
+/// The tolerance chosen by `SpgDataset::compute_auto`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoSymprec {
+    pub symprec: f64,
+    /// The number of operators found at `symprec`.
+    pub num_ops: usize,
+    /// The smallest and largest tolerances tried that found the same number of operators.
+    pub plateau: (f64, f64),
+}
+
+impl AutoSymprec {
+    /// Width of the plateau, in decades.
+    pub fn plateau_width(&self) -> f64
+    { f64::log10(self.plateau.1 / self.plateau.0) }
+}
+
+// The longest run of equal, non-`None` values.  Ties go to the earliest run.
+fn widest_plateau(counts: &[Option<usize>]) -> Option<Range<usize>> {
+    let mut best: Option<Range<usize>> = None;
+    let mut start = 0;
+    for end in 1..=counts.len() {
+        if end < counts.len() && counts[end] == counts[start] {
+            continue;
+        }
+        if counts[start].is_some() && best.as_ref().map_or(true, |best| end - start > best.len()) {
+            best = Some(start..end);
+        }
+        start = end;
+    }
+    best
+}
+
 impl SpgDataset {
     pub fn compute(coords: &Coords, types: &[u32], symprec: f64) -> FailResult<Self> {
         let lattice = coords.lattice().clone();
@@ -58,6 +96,49 @@ impl SpgDataset {
             .map_err(|e| SpglibError(e).into())
     }
 
+    /// Compute the dataset at a tolerance chosen by scanning a geometric sequence of
+    /// tolerances for the widest range over which spglib finds the same number of operators.
+    ///
+    /// (the chosen tolerance is the one in the middle of that range)
+    pub fn compute_auto(coords: &Coords, types: &[u32]) -> FailResult<(Self, AutoSymprec)> {
+        let symprecs: Vec<f64> = {
+            (0..=AUTO_SYMPREC_NUM_DECADES * AUTO_SYMPREC_STEPS_PER_DECADE)
+                .map(|i| AUTO_SYMPREC_MIN * 10f64.powf(i as f64 / AUTO_SYMPREC_STEPS_PER_DECADE as f64))
+                .collect()
+        };
+
+        let mut datasets = vec![];
+        for &symprec in &symprecs {
+            match SpgDataset::compute(coords, types, symprec) {
+                Ok(spg) => datasets.push(Some(spg)),
+                // spglib may give up at some tolerances; this merely breaks up the plateaus
+                Err(e) => match e.downcast::<SpglibError>() {
+                    Ok(e) => {
+                        trace!("spglib failed at symprec = {:e}: {}", symprec, e);
+                        datasets.push(None);
+                    },
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+        let counts: Vec<_> = datasets.iter().map(|spg| spg.as_ref().map(|spg| spg.rotations.len())).collect();
+        for (symprec, count) in zip_eq!(&symprecs, &counts) {
+            trace!("symprec = {:e}: {:?} operators", symprec, count);
+        }
+
+        let plateau = match widest_plateau(&counts) {
+            Some(plateau) => plateau,
+            None => bail!("spglib failed at every tolerance from {:e} to {:e}", symprecs[0], symprecs.last().unwrap()),
+        };
+        let index = (plateau.start + plateau.end - 1) / 2;
+        let auto = AutoSymprec {
+            symprec: symprecs[index],
+            num_ops: counts[index].unwrap(),
+            plateau: (symprecs[plateau.start], symprecs[plateau.end - 1]),
+        };
+        Ok((datasets.swap_remove(index).unwrap(), auto))
+    }
+
     pub fn lattice(&self) -> &Lattice {
         self.lattice.as_ref()
             .expect("(BUG!) should have been added on construction")
@@ -142,3 +223,41 @@ pub struct SpgDataset {
     pub std_positions: Vec<V3>,
     pub std_mapping_to_primitive: Vec<usize>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsp2_structure::CoordsKind;
+
+    #[test]
+    fn plateaus() {
+        assert_eq!(widest_plateau(&[]), None);
+        assert_eq!(widest_plateau(&[None, None]), None);
+        assert_eq!(widest_plateau(&[Some(2), Some(4), Some(4), None, Some(2)]), Some(1..3));
+        assert_eq!(widest_plateau(&[Some(2), Some(2), Some(4), Some(4)]), Some(0..2));
+        assert_eq!(widest_plateau(&[None, None, None, Some(1)]), Some(3..4));
+    }
+
+    #[test]
+    fn graphene_auto() -> FailResult<()> {
+        // graphene with one atom nudged just enough to break symmetry at the tightest tolerances
+        let half_r3 = 0.5 * f64::sqrt(3.0);
+        let lattice = Lattice::new(&(rsp2_array_types::mat::from_array([
+            [ 2.46,           0.0,  0.0],
+            [-1.23, 2.46 * half_r3,  0.0],
+            [  0.0,           0.0, 10.0],
+        ])));
+        let mut coords = Coords::new(lattice, CoordsKind::Fracs(vec![
+            V3([0.0, 0.0, 0.0]),
+            V3([1.0 / 3.0, 2.0 / 3.0, 0.0]),
+        ]));
+        coords.carts_mut()[1][0] += 1e-5;
+
+        let (spg, auto) = SpgDataset::compute_auto(&coords, &[6, 6])?;
+        assert_eq!(auto.num_ops, 24);
+        assert_eq!(spg.rotations.len(), 24);
+        assert!(auto.plateau.0 > 1e-5);
+        assert!(auto.plateau_width() >= 3.0, "{:?}", auto);
+        Ok(())
+    }
+}
@@ -16,8 +16,9 @@ use crate::{FailResult, FailOk};
 use crate::potential::{PotentialBuilder, DiffFn, DynCgDiffFn, CommonMeta};
 use crate::meta::{self, prelude::*};
 use crate::hlist_aliases::*;
-use crate::math::basis::{GammaBasis3, EvDirection};
-use crate::traits::{Save, AsPath};
+use crate::math::basis::{GammaBasis3, GammaKet3, EvDirection};
+use crate::math::structure_change::StructureChange;
+use crate::traits::{Save, Load, AsPath, save::Json};
 use crate::util::ext_traits::PathNiceExt;
 
 use super::trial::TrialDir;
@@ -26,6 +27,7 @@ use super::{write_eigen_info_for_humans, write_eigen_info_for_machines};
 use super::{EvLoopStructureKind, Iteration};
 use super::StopAfter;
 use super::param_optimization::{RelaxationOptimizationHelper, ParamOptimizationHelper, LatticeOptimizationHelper};
+use super::param_optimization::{StrainOptimizationHelper, VariableCellDiffFn, OptimizingDiffFn};
 
 use rsp2_tasks_config::{self as cfg, Settings};
 
@@ -33,20 +35,27 @@ use slice_of_array::prelude::*;
 use rsp2_slice_math::{v, V, vdot};
 use rsp2_array_types::{V3};
 use rsp2_structure::{Coords};
+use rsp2_structure::find_perm::SymmetryCache;
 use rsp2_minimize::{cg};
 use rsp2_fs_util as fsx;
 
 use std::rc::Rc;
+use std::path::PathBuf;
 use crate::filetypes::stored_structure;
 
 impl TrialDir {
     /// NOTE: This writes to fixed filepaths in the trial directory
     ///       and is not designed to be called multiple times.
+    ///
+    /// After each iteration that does not finish the loop, an `EvLoopCheckpoint` is
+    /// written.  Supplying this checkpoint (along with the structure it points to as
+    /// `original_coords`) continues the loop from where it left off.
     pub(crate) fn do_main_ev_loop(
         &self,
         settings: &Settings,
         pot: &dyn PotentialBuilder,
         original_coords: Coords,
+        resume_from: Option<EvLoopCheckpoint>,
         meta: HList5<
             meta::SiteElements,
             meta::SiteMasses,
@@ -55,7 +64,8 @@ impl TrialDir {
             Option<meta::FracBonds>,
         >,
         stop_after: StopAfter, // HACK
-    ) -> FailResult<(Coords, Option<(GammaSystemAnalysis, Iteration)>)>
+        write_trajectory: bool,
+    ) -> FailResult<(Coords, Option<(GammaSystemAnalysis, Iteration, bool)>)>
     {
         // `stop_after`, augmented with config sections required by those steps
         enum StopAfterPlus<'a> {
@@ -77,20 +87,50 @@ impl TrialDir {
             let coords = self.do_ev_loop_stuff_before_dynmat(
                 &settings, pot, meta.sift(), iteration, original_coords,
             )?;
+            if write_trajectory {
+                self.append_relaxation_frame("after CG", &coords, meta.sift())?;
+            }
             return Ok((coords, None));
         }
 
         let mut from_coords = original_coords;
-        let mut loop_state = EvLoopFsm::new(&settings.ev_loop);
+        // An interrupted iteration may have written some output before it could write a
+        // checkpoint.  That iteration will be redone, so its output is discarded.
+        if let Some(checkpoint) = &resume_from {
+            self.truncate_ev_loop_logs(checkpoint.state.iteration)?;
+        }
+        let mut convergence: Vec<EvLoopConvergenceEntry> = match &resume_from {
+            Some(checkpoint) if self.ev_loop_convergence_path().exists() => {
+                let Json(convergence): Json<Vec<EvLoopConvergenceEntry>> = Load::load(self.ev_loop_convergence_path())?;
+                convergence.into_iter()
+                    .filter(|entry| entry.iteration < checkpoint.state.iteration)
+                    .collect()
+            },
+            _ => vec![],
+        };
+        let (mut loop_state, mut ignored_evecs) = match resume_from {
+            None => (EvLoopFsm::new(&settings.ev_loop), vec![]),
+            Some(checkpoint) => (
+                EvLoopFsm::from_snapshot(&settings.ev_loop, checkpoint.state),
+                checkpoint.ignored_evecs.into_iter().map(GammaKet3).collect(),
+            ),
+        };
+        // the symmetry is not expected to change as the structure relaxes
+        let mut symmetry_cache = SymmetryCache::new();
         loop {
             // move out of from_coords so that Rust's control-flow analysis
             // will make sure we put something back.
             let coords = from_coords;
             let iteration = loop_state.iteration;
+            let start_coords = coords.clone();
 
             let coords = self.do_ev_loop_stuff_before_dynmat(
                 &settings, pot, meta.sift(), Some(iteration), coords,
             )?;
+            if write_trajectory {
+                let title = format!("ev-loop iteration {}: after CG", iteration);
+                self.append_relaxation_frame(&title, &coords, meta.sift())?;
+            }
 
             // rsp2-acgsd stops here
             let phonon_settings = match stop_after {
@@ -102,6 +142,7 @@ impl TrialDir {
             let qpoint = V3::zero();
             let dynmat = super::do_compute_dynmat(
                 Some(self), settings, phonon_settings, pot, qpoint, &coords, meta.sift(),
+                &mut symmetry_cache,
             )?;
             dynmat.save(self.gamma_dynmat_path(iteration))?;
 
@@ -117,19 +158,69 @@ impl TrialDir {
             trace!("============================");
             trace!("Finished diagonalization");
 
+            let cg_energy = pot.one_off().compute_value(&coords, meta.sift())?;
             let (ev_analysis, coords, did_chasing) = {
                 self.do_ev_loop_stuff_after_diagonalization(
                     &settings, pot, meta.sift(), iteration, coords, &freqs, &evecs,
+                    &mut ignored_evecs, &mut symmetry_cache,
                 )?
             };
+            if write_trajectory && did_chasing.0 {
+                let title = format!("ev-loop iteration {}: after eigenvector chasing", iteration);
+                self.append_relaxation_frame(&title, &coords, meta.sift())?;
+            }
+
+            {
+                let classifications = ev_analysis.ev_classifications.as_ref().expect("(bug) always computed!");
+                let bad_freqs: Vec<_> = {
+                    zip_eq!(&freqs, &classifications.0)
+                        .filter(|&(_, kind)| kind.should_chase(&settings.acoustic_search))
+                        .map(|(&freq, _)| freq)
+                        .collect()
+                };
+                let max_imaginary = bad_freqs.iter().fold(0.0, |acc: f64, &freq| acc.max(-freq));
+                let modes = Some((max_imaginary, bad_freqs.len()));
+                self.append_ev_loop_energy(iteration, "cg", cg_energy, modes)?;
+            }
+            if did_chasing.0 {
+                let chase_energy = pot.one_off().compute_value(&coords, meta.sift())?;
+                self.append_ev_loop_energy(iteration, "chase", chase_energy, None)?;
+            }
+
+            let structure_change = StructureChange::between(&start_coords, &coords);
+            info!(
+                "Iteration {} moved atoms by {:.3e} (RMS), {:.3e} (max)",
+                iteration, structure_change.rms, structure_change.max,
+            );
+            if did_chasing.0 && structure_change.rms < STUCK_RMS_CHANGE {
+                warn!("\
+                    The structure barely changed during iteration {}, yet there are still \
+                    modes to chase.  The ev-loop may be stuck.\
+                ", iteration);
+            }
+            convergence.push(EvLoopConvergenceEntry {
+                iteration: iteration.0,
+                structure_change,
+                did_chasing: did_chasing.0,
+            });
+            Json(&convergence).save(self.ev_loop_convergence_path())?;
 
             match loop_state.step(did_chasing) {
                 EvLoopStatus::KeepGoing => {
+                    // (the structure was already written by do_ev_loop_stuff_after_diagonalization)
+                    let structure = self.structure_path(EvLoopStructureKind::PostEvChase(iteration));
+                    Json(EvLoopCheckpoint {
+                        structure: structure.file_name().expect("(BUG) no file name").into(),
+                        state: loop_state.snapshot(),
+                        ignored_evecs: ignored_evecs.iter().map(|ket| ket.0.clone()).collect(),
+                    }).save(self.ev_loop_checkpoint_path())?;
+
                     from_coords = coords;
                     continue;
                 },
                 EvLoopStatus::Done => {
-                    return Ok((coords, Some((ev_analysis, iteration))));
+                    self.check_final_curvature(pot, iteration, &coords, meta.sift(), &evecs, &ev_analysis)?;
+                    return Ok((coords, Some((ev_analysis, iteration, loop_state.converged()))));
                 },
                 EvLoopStatus::ItsBadGuys(msg) => {
                     bail!("{}", msg);
@@ -139,6 +230,138 @@ impl TrialDir {
         }
     }
 
+    /// Append a frame to the XYZ trajectory of the relaxation.
+    fn append_relaxation_frame(
+        &self,
+        title: &str,
+        coords: &Coords,
+        meta: HList1<meta::SiteElements>,
+    ) -> FailResult<()>
+    {Ok({
+        let hlist_pat![site_elements] = meta;
+        let path = self.relaxation_trajectory_path();
+        let file = path_abs::PathFile::create(&path)?.open_append()?;
+        rsp2_structure_io::Xyz {
+            title,
+            carts: coords.to_carts(),
+            elements: &site_elements[..],
+        }.to_writer(file)?;
+    })}
+
+    /// Remove the rows of the energies CSV file and the frames of the trajectory that
+    /// were written by iterations at or after `next_iteration`.
+    fn truncate_ev_loop_logs(&self, next_iteration: u32) -> FailResult<()>
+    {Ok({
+        let truncate = |path: PathBuf, len_before: &dyn Fn(&str) -> FailResult<usize>| FailOk({
+            if path.exists() {
+                let text = std::fs::read_to_string(&path)?;
+                let len = len_before(&text)?;
+                if len < text.len() {
+                    trace!("Discarding output of an interrupted iteration from {}", path.nice());
+                    std::fs::OpenOptions::new().write(true).open(&path)?.set_len(len as u64)?;
+                }
+            }
+        });
+        truncate(self.ev_loop_energies_path(), &|text| Ok(energies_csv_len_before(text, next_iteration)))?;
+        truncate(self.relaxation_trajectory_path(), &|text| trajectory_len_before(text, next_iteration))?;
+    })}
+
+    /// Append a row to the CSV file of energies at each stage of the ev-loop, writing the
+    /// header first if the file does not yet exist.
+    ///
+    /// The file is reopened for each row so that a killed run still leaves behind the
+    /// rows it has written so far.  `modes` holds the magnitude of the largest imaginary
+    /// frequency among the modes to be chased, and the number of those modes; these are
+    /// only known for the structure that was diagonalized. (i.e. the one after CG)
+    fn append_ev_loop_energy(
+        &self,
+        iteration: Iteration,
+        stage: &str,
+        energy: f64,
+        modes: Option<(f64, usize)>,
+    ) -> FailResult<()>
+    {Ok({
+        use std::io::Write;
+
+        let path = self.ev_loop_energies_path();
+        let needs_header = !path.exists();
+        let mut file = path_abs::PathFile::create(&path)?.open_append()?;
+        if needs_header {
+            writeln!(file, "iteration,stage,energy,max_imaginary_frequency,num_bad_modes")?;
+        }
+        match modes {
+            Some((max_imaginary, count)) => {
+                writeln!(file, "{},{},{:e},{},{}", iteration, stage, energy, max_imaginary, count)?;
+            },
+            None => writeln!(file, "{},{},{:e},,", iteration, stage, energy)?,
+        }
+    })}
+
+    /// Sanity check that the force constants agree with the potential that the relaxed
+    /// structure is at a local minimum, by comparing the curvature of the gamma dynamical
+    /// matrix along the lowest non-acoustic modes against finite differences of the gradient.
+    /// This only warns, since the ev-loop has already judged the structure by its frequencies.
+    fn check_final_curvature(
+        &self,
+        pot: &dyn PotentialBuilder,
+        iteration: Iteration,
+        coords: &Coords,
+        meta: CommonMeta,
+        evecs: &GammaBasis3,
+        ev_analysis: &GammaSystemAnalysis,
+    ) -> FailResult<()>
+    {Ok({
+        use super::acoustic_search::ModeKind;
+
+        // the eigenvalue of a mode at -1 cm^-1
+        const TOLERANCE: f64 = 3.7e-6;
+        // the eigenvalue of a mode at 16 cm^-1
+        const MAX_DISCREPANCY: f64 = 1e-3;
+        // step along each mode, in mass-weighted units (sqrt(amu) * Angstrom)
+        const STEP: f64 = 1e-3;
+        const MAX_MODES: usize = 12;
+
+        let classifications = ev_analysis.ev_classifications.as_ref().expect("(bug) always computed!");
+        let modes: Vec<Vec<V3>> = {
+            zip_eq!(&*evecs.0, &classifications.0)
+                .filter(|&(_, kind)| match kind {
+                    ModeKind::Imaginary | ModeKind::Suspicious | ModeKind::Vibrational => true,
+                    // (ignored modes are known to be saddles)
+                    ModeKind::Ignored |
+                    ModeKind::Translational | ModeKind::Rotational | ModeKind::OtherAcoustic => false,
+                })
+                .map(|(evec, _)| evec.0.clone())
+                .take(MAX_MODES)
+                .collect()
+        };
+
+        let masses: meta::SiteMasses = meta.pick();
+        let masses = masses.iter().map(|&meta::Mass(m)| m).collect::<Vec<_>>();
+
+        let dynmat: rsp2_dynmat::DynamicalMatrix = Load::load(self.gamma_dynmat_path(iteration))?;
+        let mut diff_fn = pot.initialize_diff_fn(coords, meta.clone())?;
+        let check = dynmat.check_curvature(&modes, &masses, STEP, |disps| {
+            let mut coords = coords.clone();
+            let carts = zip_eq!(coords.to_carts(), disps).map(|(x, &d)| x + d).collect();
+            coords.set_carts(carts);
+            diff_fn.compute_grad(&coords, meta.clone())
+        })?;
+        trace!("Trace of low-frequency dynmat block: {:e}", check.trace());
+        if check.max_discrepancy() > MAX_DISCREPANCY {
+            warn!("\
+                The force constants disagree with finite differences of the potential about \
+                the curvature along a low-frequency mode (by as much as {:e})!\
+            ", check.max_discrepancy());
+        }
+        if check.is_saddle(TOLERANCE) {
+            warn!("\
+                The potential shows negative curvature along a non-acoustic mode \
+                (trace of low-frequency dynmat block: {:e}); the relaxed structure may be at a \
+                saddle point!\
+            ", check.trace());
+        }
+    })}
+
     pub(in crate::cmd) fn do_ev_loop_stuff_before_dynmat(
         &self,
         settings: &Settings,
@@ -161,11 +384,19 @@ impl TrialDir {
         }
 
         let snapshot_fn = SnapshotFn::new(self.snapshot_structure_path(), meta.sift(), &settings.snapshot);
+        let frozen = frozen_atom_mask(&settings.relax, meta.pick())?;
         let coords = do_cg_relax_with_param_optimization_if_supported(
             pot, &settings.cg, snapshot_fn,
             settings.parameters.as_ref(), settings.lattice_relax_22.as_ref(),
+            settings.variable_cell.as_ref(),
+            frozen.as_ref().map(|x| &x[..]),
             coords, meta.sift(),
         )?;
+        check_force_balance(pot, &settings.relax, frozen.as_ref().map(|x| &x[..]), &coords, meta.sift())?;
+        let coords = match &settings.relax.symmetrize {
+            Some(symmetrize_settings) => symmetrize_relaxed_structure(symmetrize_settings, coords, meta.sift())?,
+            None => coords,
+        };
 
         trace!("============================");
 
@@ -195,6 +426,9 @@ impl TrialDir {
         coords: Coords,
         freqs: &Vec<f64>,
         evecs: &GammaBasis3,
+        // eigenvectors of the modes ignored by the previous iteration; this gets updated.
+        ignored_evecs: &mut Vec<GammaKet3>,
+        symmetry_cache: &mut SymmetryCache,
     ) -> FailResult<(GammaSystemAnalysis, Coords, DidEvChasing)>
     {Ok({
         let classifications = super::acoustic_search::perform_acoustic_search(
@@ -202,16 +436,16 @@ impl TrialDir {
             &coords, meta.sift(),
             &settings.acoustic_search,
         )?;
+        let mode_symmetry = super::do_compute_mode_symmetry(settings, &coords, meta.sift(), symmetry_cache)?;
+        let classifications = super::acoustic_search::apply_ignored_modes(
+            classifications, freqs, evecs, mode_symmetry.as_ref(),
+            &settings.ev_loop.ignored_modes, ignored_evecs,
+        );
         trace!("Computing eigensystem info");
 
-        let unfold_bands = match settings.unfold_bands {
-            None => false,
-            Some(cfg::UnfoldBands::Zheng {}) => true,
-        };
-
         let ev_analysis = super::do_gamma_system_analysis(
             &coords, meta.sift(), freqs, evecs, Some(classifications),
-            unfold_bands,
+            settings.unfold_bands.as_ref(), settings.frequency_window.as_ref(), settings.ir.as_ref(),
         )?;
         {
             let file = self.create_file(format!("eigenvalues.{:02}", iteration))?;
@@ -222,7 +456,7 @@ impl TrialDir {
         let bad_directions: Vec<_> = {
             let classifications = ev_analysis.ev_classifications.as_ref().expect("(bug) always computed!");
             izip!(1.., freqs, &*evecs.0, &classifications.0)
-                .filter(|&(_, _, _, kind)| kind == &super::acoustic_search::ModeKind::Imaginary)
+                .filter(|&(_, _, _, kind)| kind.should_chase(&settings.acoustic_search))
                 .map(|(i, &freq, evec, _)| {
                     let name = format!("band {} ({})", i, freq);
                     let direction = EvDirection::from_eigenvector(&evec.to_complex(), meta.sift());
@@ -241,7 +475,7 @@ impl TrialDir {
             };
             let result = self.write_animations(
                 animate_settings,
-                &coords, meta.sift(), iteration,
+                &coords, meta.sift(), settings.ev_normalization.as_ref(), iteration,
                 bad_guys, all_guys,
             );
 
@@ -251,13 +485,23 @@ impl TrialDir {
             }
         }
 
+        if settings.write_eigenvectors {
+            let directions = {
+                zip_eq!(freqs, &*evecs.0)
+                    .map(|(&freq, evec)| (freq, EvDirection::from_eigenvector(&evec.to_complex(), meta.sift())))
+            };
+            self.write_eigenvectors(settings.ev_normalization.as_ref(), meta.sift(), iteration, directions)?;
+        }
+
         let (coords, did_chasing) = {
             match bad_directions.len() {
                 0 => (coords, DidEvChasing(false)),
                 n => {
                     trace!("Chasing {} bad eigenvectors...", n);
+                    let frozen = frozen_atom_mask(&settings.relax, meta.pick())?;
                     let structure = do_eigenvector_chase(
                         pot, &settings.ev_chase, coords, meta.sift(), &bad_directions[..],
+                        frozen.as_ref().map(|x| &x[..]),
                     )?;
                     (structure, DidEvChasing(true))
                 },
@@ -273,12 +517,103 @@ impl TrialDir {
     })}
 }
 
+// Each line of `text`, along with its byte offset.
+fn lines_with_offsets(text: &str) -> impl Iterator<Item=(usize, &str)> {
+    text.split_terminator('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len() + 1;
+        Some((start, line))
+    })
+}
+
+// Length of the part of the energies CSV file that was written before `next_iteration`.
+fn energies_csv_len_before(text: &str, next_iteration: u32) -> usize {
+    lines_with_offsets(text)
+        .skip(1) // header
+        .find(|&(_, line)| match line.split(',').next().map(str::parse::<u32>) {
+            Some(Ok(iteration)) => iteration >= next_iteration,
+            _ => false,
+        })
+        .map_or(text.len(), |(offset, _)| offset)
+}
+
+// Length of the part of the trajectory that was written before `next_iteration`,
+// judging by the titles written by `do_main_ev_loop`.
+fn trajectory_len_before(text: &str, next_iteration: u32) -> FailResult<usize> {
+    const TITLE_PREFIX: &str = "ev-loop iteration ";
+
+    let mut lines = lines_with_offsets(text);
+    while let Some((frame_start, count_line)) = lines.next() {
+        let count: usize = count_line.trim().parse()?;
+        let title = match lines.next() {
+            Some((_, title)) => title,
+            None => bail!("trajectory ends in the middle of a frame"),
+        };
+        if title.starts_with(TITLE_PREFIX) {
+            let iteration = title[TITLE_PREFIX.len()..].split(':').next().and_then(|s| s.parse::<u32>().ok());
+            if let Some(iteration) = iteration {
+                if iteration >= next_iteration {
+                    return Ok(frame_start);
+                }
+            }
+        }
+        for _ in 0..count {
+            lines.next();
+        }
+    }
+    Ok(text.len())
+}
+
+// RMS change (in angstroms) below which an iteration that chased modes is considered
+// to have made no progress.
+const STUCK_RMS_CHANGE: f64 = 1e-6;
+
+/// An entry of `convergence.json`, which is written after each iteration of the ev-loop.
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct EvLoopConvergenceEntry {
+    pub iteration: u32,
+    /// Change from the start of the iteration to the end. (after CG and eigenvector chasing)
+    pub structure_change: StructureChange,
+    /// Whether there were any modes to chase.
+    pub did_chasing: bool,
+}
+
 struct EvLoopFsm {
     config: cfg::EvLoop,
     iteration: Iteration,
     all_ok_count: u32,
 }
 
+/// The state of an `EvLoopFsm` between iterations, excluding the config.
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct EvLoopFsmSnapshot {
+    /// The next iteration to be performed.
+    pub iteration: u32,
+    pub all_ok_count: u32,
+}
+
+/// Contents of `ev-loop-checkpoint.json`, which allows a killed run to be resumed.
+///
+/// The stored structure is written with full precision, so given a deterministic potential,
+/// a resumed run performs the same computations as one that was never interrupted.
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct EvLoopCheckpoint {
+    /// Structure to begin the next iteration from, relative to the trial directory.
+    pub structure: PathBuf,
+    pub state: EvLoopFsmSnapshot,
+    /// Eigenvectors of the modes ignored by the last iteration, which the next iteration
+    /// tracks by overlap.  (see `ev-loop.ignored-modes`)
+    #[serde(default)]
+    pub ignored_evecs: Vec<Vec<V3>>,
+}
+
+#[derive(Debug, PartialEq)]
 pub enum EvLoopStatus {
     KeepGoing,
     Done,
@@ -295,6 +630,24 @@ impl EvLoopFsm {
         all_ok_count: 0,
     }}
 
+    pub fn from_snapshot(config: &cfg::EvLoop, snapshot: EvLoopFsmSnapshot) -> Self
+    { EvLoopFsm {
+        config: config.clone(),
+        iteration: Iteration(snapshot.iteration),
+        all_ok_count: snapshot.all_ok_count,
+    }}
+
+    pub fn snapshot(&self) -> EvLoopFsmSnapshot
+    { EvLoopFsmSnapshot {
+        iteration: self.iteration.0,
+        all_ok_count: self.all_ok_count,
+    }}
+
+    /// Whether the loop finished because the structure was found to be stable,
+    /// as opposed to giving up at `max-iter`.
+    pub fn converged(&self) -> bool
+    { self.all_ok_count >= self.config.min_positive_iter }
+
     pub fn step(&mut self, did: DidEvChasing) -> EvLoopStatus {
         self.iteration.0 += 1;
         match did {
@@ -329,8 +682,8 @@ fn cg_builder_from_config(
     cg_settings: &cfg::Cg,
 ) -> (cg::Builder, cg::StopCondition) {
     let cfg::Cg {
-        ref stop_condition, ref flavor, ref on_ls_failure,
-        alpha_guess_first, alpha_guess_max,
+        ref stop_condition, ref flavor, on_ls_failure: _,
+        alpha_guess_first, alpha_guess_max, ref alpha_guess,
     } = *cg_settings;
 
     let mut builder = match *flavor {
@@ -347,14 +700,16 @@ fn cg_builder_from_config(
     };
     builder.alpha_guess_first(alpha_guess_first);
     builder.alpha_guess_max(alpha_guess_max);
-
-    // FIXME XXX should not be a responsibility of the builder
-    builder.on_ls_failure(match on_ls_failure {
-        cfg::CgOnLsFailure::Succeed => cg::settings::OnLsFailure::Succeed,
-        cfg::CgOnLsFailure::Fail => cg::settings::OnLsFailure::Fail,
-        cfg::CgOnLsFailure::Warn => cg::settings::OnLsFailure::Warn,
+    builder.alpha_guess(match *alpha_guess {
+        cfg::CgAlphaGuess::Previous => cg::settings::AlphaGuess::Previous,
+        cfg::CgAlphaGuess::Scaled(factor) => cg::settings::AlphaGuess::Scaled(factor),
+        cfg::CgAlphaGuess::Quadratic => cg::settings::AlphaGuess::Quadratic,
     });
 
+    // Linesearch failures are always reported back to us, so that `cg_output_position`
+    // can apply the `on-ls-failure` policy.
+    builder.on_ls_failure(cg::settings::OnLsFailure::Fail);
+
     (builder, stop_condition.clone())
 }
 
@@ -362,12 +717,16 @@ fn do_cg_relax(
     pot: &dyn PotentialBuilder,
     cg_settings: &cfg::Cg,
     snapshot_fn: SnapshotFn,
+    frozen: Option<&[bool]>,
     // NOTE: takes ownership of coords because it is likely an accident to reuse them
     coords: Coords,
     meta: CommonMeta,
 ) -> FailResult<Coords>
 {Ok({
     let mut flat_diff_fn = pot.parallel(true).initialize_cg_diff_fn(&coords, meta.sift())?;
+    if let Some(frozen) = frozen {
+        flat_diff_fn = frozen_atoms_diff_fn(flat_diff_fn, frozen);
+    }
     let unflatten_coords = {
         let coords = coords.clone();
         move |flat: &[f64]| coords.with_carts(flat.nest().to_vec())
@@ -375,7 +734,7 @@ fn do_cg_relax(
 
     let relaxed_flat = {
         let (mut cg, stop_condition) = cg_builder_from_config(cg_settings);
-        cg.stop_condition(stop_condition.to_function())
+        let result = cg.stop_condition(stop_condition.to_function())
             .basic_output_fn(log_cg_output)
             .output_fn({
                 let unflatten_coords = unflatten_coords.clone();
@@ -383,14 +742,55 @@ fn do_cg_relax(
                     snapshot_fn.maybe_save_snapshot(&state, unflatten_coords(state.position))
                 }
             })
-            .run(coords.to_carts().flat(), &mut *flat_diff_fn)
-            .unwrap().position
+            .run(coords.to_carts().flat(), &mut *flat_diff_fn);
+        cg_output_position(cg_settings, result)?
     };
     unflatten_coords(&relaxed_flat)
 })}
 
 fn log_cg_output(args: std::fmt::Arguments<'_>) { trace!("{}", args) }
 
+/// Get the final position from a CG run, applying the `cg.on-ls-failure` policy.
+fn cg_output_position<E: std::fmt::Display>(
+    cg_settings: &cfg::Cg,
+    result: Result<cg::Output, cg::Failure<E>>,
+) -> FailResult<Vec<f64>>
+{
+    use rsp2_minimize::cg::AcgsdError::LineSearchFailed;
+    use either::Either::Left;
+
+    match result {
+        Ok(output) => Ok(output.position),
+        Err(cg::Failure { best_position: Some(position), error: Left(LineSearchFailed) }) => {
+            match cg_settings.on_ls_failure {
+                cfg::CgOnLsFailure::Succeed => Ok(position),
+                cfg::CgOnLsFailure::Warn => {
+                    warn!("{}; keeping the best position found", LineSearchFailed);
+                    Ok(position)
+                },
+                cfg::CgOnLsFailure::Fail => bail!("{}", LineSearchFailed),
+            }
+        },
+        Err(e) => bail!("{}", e),
+    }
+}
+
+/// Relax only the atomic positions, holding the lattice fixed.  No snapshots are written.
+pub(super) fn do_cg_relax_with_fixed_lattice(
+    pot: &dyn PotentialBuilder,
+    cg_settings: &cfg::Cg,
+    coords: Coords,
+    meta: stored_structure::Meta,
+) -> FailResult<Coords>
+{
+    let snapshot_fn = SnapshotFn {
+        path: PathBuf::new(),
+        settings: cfg::Snapshot { every: None },
+        meta: meta.clone(),
+    };
+    do_cg_relax(pot, cg_settings, snapshot_fn, None, coords, meta.sift())
+}
+
 //------------------
 
 fn do_cg_relax_with_param_optimization_if_supported(
@@ -399,13 +799,23 @@ fn do_cg_relax_with_param_optimization_if_supported(
     snapshot_fn: SnapshotFn,
     parameters: Option<&cfg::Parameters>,
     lattice_relax_settings: Option<&cfg::LatticeRelax>,
+    variable_cell_settings: Option<&cfg::VariableCell>,
+    frozen: Option<&[bool]>,
     // NOTE: takes ownership of coords because it is likely an accident to reuse them
     coords: Coords,
     meta: CommonMeta,
 ) -> FailResult<Coords>
 {
+    if let Some(variable_cell_settings) = variable_cell_settings {
+        // (forbidden by config validation)
+        assert!(frozen.is_none(), "(BUG) frozen atoms with variable-cell relaxation");
+        return do_cg_relax_with_variable_cell(pot, cg_settings, snapshot_fn, parameters, variable_cell_settings, coords, meta);
+    }
+
     //if let Some(parameters) = parameters {
     if parameters.is_some() || lattice_relax_settings.is_some() {
+        // (forbidden by config validation)
+        assert!(frozen.is_none(), "(BUG) frozen atoms with parameter optimization");
         if let Some(x) = do_cg_relax_with_param_optimization(pot, cg_settings, snapshot_fn.clone(), parameters, lattice_relax_settings, &coords, meta.sift())? {
             return Ok(x);
         } else {
@@ -414,7 +824,7 @@ fn do_cg_relax_with_param_optimization_if_supported(
     } else {
         trace!("Not relaxing with parameters because 'parameters' was not supplied.");
     }
-    do_cg_relax(pot, cg_settings, snapshot_fn, coords, meta)
+    do_cg_relax(pot, cg_settings, snapshot_fn, frozen, coords, meta)
 }
 
 /// Returns Ok(None) if the potential does not support this method.
@@ -445,6 +855,44 @@ fn do_cg_relax_with_param_optimization(
         (None, None) => unreachable!("checked beforehand"),
     };
 
+    trace!("Incorporating parameter optimization into relaxation");
+    let diff_fn = OptimizingDiffFn { helper: param_helper.clone(), bond_diff_fn, meta };
+    Some(do_cg_relax_with_optimization_helper(cg_settings, snapshot_fn, param_helper, coords, diff_fn)?)
+})}
+
+/// Relax the positions and the lattice together, with the lattice gradient given by the stress.
+fn do_cg_relax_with_variable_cell(
+    pot: &dyn PotentialBuilder,
+    cg_settings: &cfg::Cg,
+    snapshot_fn: SnapshotFn,
+    parameters: Option<&cfg::Parameters>,
+    variable_cell_settings: &cfg::VariableCell,
+    // NOTE: takes ownership of coords because it is likely an accident to reuse them
+    coords: Coords,
+    meta: CommonMeta,
+) -> FailResult<Coords>
+{
+    // no config for now
+    let cfg::VariableCell {} = variable_cell_settings;
+
+    let diff_fn = pot.parallel(true).initialize_diff_fn(&coords, meta.sift())?;
+    let strain_helper = StrainOptimizationHelper::new(parameters, coords.lattice());
+    let param_helper = Rc::new(RelaxationOptimizationHelper::StrainBased(strain_helper.clone()));
+
+    trace!("Incorporating variable-cell optimization into relaxation");
+    let diff_fn = VariableCellDiffFn { helper: Rc::new(strain_helper), diff_fn, meta };
+    do_cg_relax_with_optimization_helper(cg_settings, snapshot_fn, param_helper, &coords, diff_fn)
+}
+
+/// Run CG on the flattened representation of a `RelaxationOptimizationHelper`.
+fn do_cg_relax_with_optimization_helper(
+    cg_settings: &cfg::Cg,
+    snapshot_fn: SnapshotFn,
+    param_helper: Rc<RelaxationOptimizationHelper>,
+    coords: &Coords,
+    diff_fn: impl cg::DiffFn<Error=failure::Error>,
+) -> FailResult<Coords>
+{Ok({
     let (mut cg, stop_condition_cereal) = cg_builder_from_config(cg_settings);
 
     // Make the stop condition and output representative of the cartesian forces.
@@ -488,17 +936,11 @@ fn do_cg_relax_with_param_optimization(
         }
     });
 
-    trace!("Incorporating parameter optimization into relaxation");
     let relaxed_flat = {
-         cg.run(
-            &param_helper.flatten_coords(&coords),
-            {
-                let helper = param_helper.clone();
-                crate::cmd::param_optimization::OptimizingDiffFn { helper, bond_diff_fn, meta }
-            },
-        ).unwrap().position
+        let result = cg.run(&param_helper.flatten_coords(&coords), diff_fn);
+        cg_output_position(cg_settings, result)?
     };
-    Some(param_helper.unflatten_coords(&relaxed_flat[..]))
+    param_helper.unflatten_coords(&relaxed_flat[..])
 })}
 
 pub fn get_param_opt_output_fn(
@@ -593,13 +1035,30 @@ fn do_eigenvector_chase(
     mut coords: Coords,
     meta: CommonMeta,
     bad_directions: &[(String, f64, EvDirection)],
+    frozen: Option<&[bool]>,
 ) -> FailResult<Coords>
 {Ok({
+    // Frozen atoms must not move, so their components are removed from each direction.
+    let bad_directions: Vec<(&str, Vec<V3>)> = {
+        bad_directions.iter()
+            .map(|(name, _, dir)| (&name[..], without_frozen_atoms(dir.as_real_checked(), frozen)))
+            .filter(|(name, dir)| {
+                let moves_anything = dir.iter().any(|v| v != &V3::zero());
+                if !moves_anything {
+                    trace!("Not chasing {}, which only involves frozen atoms", name);
+                }
+                moves_anything
+            })
+            .collect()
+    };
+    if bad_directions.is_empty() {
+        return Ok(coords);
+    }
+
     match chase_settings {
         cfg::EigenvectorChase::OneByOne => {
-            for (name, _, dir) in bad_directions {
-                let dir = dir.as_real_checked();
-                let (alpha, new_coords) = do_minimize_along_evec(pot, coords, meta.sift(), dir)?;
+            for (name, dir) in bad_directions {
+                let (alpha, new_coords) = do_minimize_along_evec(pot, coords, meta.sift(), &dir)?;
                 info!("Optimized along {}, a = {:e}", name, alpha);
 
                 coords = new_coords;
@@ -607,7 +1066,7 @@ fn do_eigenvector_chase(
             coords
         },
         cfg::EigenvectorChase::Cg(cg_settings) => {
-            let bad_directions = bad_directions.iter().map(|(_, _, dir)| dir.clone());
+            let bad_directions = bad_directions.into_iter().map(|(_, dir)| dir);
             do_cg_along_evecs(pot, cg_settings, coords, meta.sift(), bad_directions)?
         },
     }
@@ -618,7 +1077,7 @@ fn do_cg_along_evecs(
     cg_settings: &cfg::Cg,
     coords: Coords,
     meta: CommonMeta,
-    directions: impl IntoIterator<Item=EvDirection>,
+    directions: impl IntoIterator<Item=Vec<V3>>,
 ) -> FailResult<Coords>
 {Ok({
     let directions = directions.into_iter().collect::<Vec<_>>();
@@ -630,21 +1089,22 @@ fn _do_cg_along_evecs(
     cg_settings: &cfg::Cg,
     coords: Coords,
     meta: CommonMeta,
-    evecs: &[EvDirection],
+    evecs: &[Vec<V3>],
 ) -> FailResult<Coords>
 {Ok({
-    let flat_evecs: Vec<_> = evecs.iter().map(|ev| ev.as_real_checked().flat()).collect();
+    let flat_evecs: Vec<_> = evecs.iter().map(|ev| ev.flat()).collect();
     let init_pos = coords.to_carts();
 
     let mut flat_diff_fn = pot.parallel(true).initialize_cg_diff_fn(&coords, meta.sift())?;
     let relaxed_coeffs = {
         let (mut cg, stop_condition) = cg_builder_from_config(cg_settings);
-        cg.stop_condition(stop_condition.to_function())
+        let result = cg.stop_condition(stop_condition.to_function())
             .basic_output_fn(log_cg_output)
             .run(
                 &vec![0.0; evecs.len()],
                 &mut *constrained_diff_fn(&mut *flat_diff_fn, init_pos.flat(), &flat_evecs),
-            ).unwrap().position
+            );
+        cg_output_position(cg_settings, result)?
     };
 
     let final_flat_pos = flat_constrained_position(init_pos.flat(), &relaxed_coeffs, &flat_evecs);
@@ -666,7 +1126,19 @@ fn do_minimize_along_evec(
         let V(pos) = v(from_pos.flat()) + alpha * v(direction.flat());
         pos
     };
-    let alpha = rsp2_minimize::exact_ls(0.0, 1e-4, |alpha| {
+
+    // Stiff eigenvectors can make a fixed step size a poor choice, so first
+    // expand outwards from a small step to find an interval containing the minimum.
+    let (lo, hi) = {
+        let (a, _, c) = rsp2_minimize::exact_ls::bracket_minimum(0.0, 1e-4, |alpha| {
+            let value = diff_fn.compute(&pos_at_alpha(alpha))?.0;
+            FailOk(::rsp2_minimize::exact_ls::Value(value))
+        })??;
+        (a.alpha.min(c.alpha), a.alpha.max(c.alpha))
+    };
+
+    // Then find the zero of the slope within that interval.
+    let alpha = rsp2_minimize::exact_ls(lo, hi - lo, |alpha| {
         let gradient = diff_fn.compute(&pos_at_alpha(alpha))?.1;
         let slope = vdot(&gradient[..], direction.flat());
         FailOk(::rsp2_minimize::exact_ls::Slope(slope))
@@ -767,6 +1239,160 @@ fn constrained_diff_fn<'a>(
     Box::new(Adapter { flat_init_pos, flat_3n_diff_fn, flat_evs })
 }
 
+//----------------------
+
+/// Get a mask of the atoms belonging to `relax.frozen-layers`.
+///
+/// `None` if no layers are frozen.
+fn frozen_atom_mask(
+    relax_settings: &cfg::Relax,
+    layers: Option<meta::SiteLayers>,
+) -> FailResult<Option<Vec<bool>>>
+{Ok({
+    let frozen_layers = &relax_settings.frozen_layers;
+    if frozen_layers.is_empty() {
+        return Ok(None);
+    }
+
+    let layers = match layers {
+        Some(layers) => layers,
+        None => bail!("relax.frozen-layers requires layers (from layer-search or layers.yaml)"),
+    };
+    let num_layers = layers.iter().map(|&meta::Layer(layer)| layer + 1).max().unwrap_or(0);
+    for &layer in frozen_layers {
+        ensure!(
+            layer < num_layers,
+            "relax.frozen-layers: no layer {} (there are {} layers)", layer, num_layers,
+        );
+    }
+    Some(layers.iter().map(|&meta::Layer(layer)| frozen_layers.contains(&layer)).collect())
+})}
+
+/// Check that the forces on the relaxed (non-frozen) atoms sum to zero, per `relax.max-net-force`.
+fn check_force_balance(
+    pot: &dyn PotentialBuilder,
+    relax_settings: &cfg::Relax,
+    frozen: Option<&[bool]>,
+    coords: &Coords,
+    meta: CommonMeta,
+) -> FailResult<()>
+{Ok({
+    let max_net_force = match relax_settings.max_net_force {
+        None => return Ok(()),
+        Some(x) => x,
+    };
+
+    let forces: Vec<V3> = {
+        let grad = pot.one_off().compute_grad(coords, meta)?;
+        without_frozen_atoms(&grad, frozen).into_iter().map(|g| -g).collect()
+    };
+    let carts = coords.to_carts();
+    let centroid = carts.iter().sum::<V3>() / carts.len() as f64;
+
+    let net_force = forces.iter().sum::<V3>();
+    let net_torque = {
+        zip_eq!(&carts, &forces)
+            .map(|(&cart, force)| (cart - centroid).cross(force))
+            .sum::<V3>()
+    };
+    let message = format!(
+        "net force after relaxation: {:e} (vector {:?}); net torque: {:e} (vector {:?})",
+        net_force.norm(), net_force, net_torque.norm(), net_torque,
+    );
+
+    if net_force.norm() > max_net_force {
+        match relax_settings.on_net_force {
+            cfg::RelaxOnNetForce::Warn => warn!("{} exceeds relax.max-net-force = {:e}", message, max_net_force),
+            cfg::RelaxOnNetForce::Fail => bail!("{} exceeds relax.max-net-force = {:e}", message, max_net_force),
+        }
+    } else {
+        trace!("{}", message);
+    }
+})}
+
+/// Report the symmetry breaking of a relaxed structure, and remove it if requested.
+fn symmetrize_relaxed_structure(
+    symmetrize_settings: &cfg::RelaxSymmetrize,
+    coords: Coords,
+    meta: HList1<meta::SiteElements>,
+) -> FailResult<Coords>
+{Ok({
+    use super::python::SpgDataset;
+    use rsp2_array_types::M33;
+
+    let cfg::RelaxSymmetrize { tolerance, project } = *symmetrize_settings;
+    let atom_types: Vec<u32> = {
+        let elements: meta::SiteElements = meta.pick();
+        elements.iter().map(|e| e.atomic_number()).collect()
+    };
+
+    trace!("Computing symmetry of relaxed structure");
+    let spg = SpgDataset::compute(&coords, &atom_types, tolerance)?;
+    info!("Relaxed spacegroup: {} ({})", spg.international_symbol, spg.spacegroup_number);
+
+    let num_translations = spg.rotations.iter().filter(|&&rot| rot == M33::eye()).count();
+    if num_translations > 1 {
+        warn!("Cannot symmetrize a structure with pure translational symmetry; is it a supercell?");
+        return Ok(coords);
+    }
+
+    let symmetrized = {
+        rsp2_structure::symmetrize::symmetrize_structure(&coords, &spg.cart_ops(), 3.0 * tolerance)?
+    };
+    info!("Max deviation from symmetry after relaxation: {:e}", symmetrized.max_deviation());
+
+    if project {
+        symmetrized.coords
+    } else {
+        coords
+    }
+})}
+
+fn without_frozen_atoms(direction: &[V3], frozen: Option<&[bool]>) -> Vec<V3> {
+    match frozen {
+        None => direction.to_vec(),
+        Some(frozen) => {
+            zip_eq!(direction, frozen)
+                .map(|(&v, &is_frozen)| if is_frozen { V3::zero() } else { v })
+                .collect()
+        },
+    }
+}
+
+// cg differential function whose gradient is zero for the coordinates of frozen atoms.
+//
+// Every search direction in CG is built from gradients, so this keeps the frozen atoms
+// exactly where they started.
+fn frozen_atoms_diff_fn(
+    flat_diff_fn: Box<DynCgDiffFn<'static>>,
+    frozen: &[bool],
+) -> Box<DynCgDiffFn<'static>>
+{
+    struct Adapter {
+        flat_diff_fn: Box<DynCgDiffFn<'static>>,
+        frozen: Vec<bool>,
+    }
+
+    impl cg::DiffFn for Adapter {
+        type Error = failure::Error;
+
+        fn compute(&mut self, flat_pos: &[f64]) -> FailResult<(f64, Vec<f64>)>
+        {Ok({
+            let (value, mut flat_grad) = self.flat_diff_fn.compute(flat_pos)?;
+            for (grad, &is_frozen) in zip_eq!(flat_grad.nest_mut::<[f64; 3]>(), &self.frozen) {
+                if is_frozen {
+                    *grad = [0.0; 3];
+                }
+            }
+            (value, flat_grad)
+        })}
+
+        fn check(&mut self, flat_pos: &[f64]) -> FailResult<()>
+        { self.flat_diff_fn.check(flat_pos) }
+    }
+    Box::new(Adapter { flat_diff_fn, frozen: frozen.to_vec() })
+}
+
 //----------------------
 // a slice of slices is a really dumb representation for a matrix
 // but we do not require performance where this is used, so whatever
@@ -785,3 +1411,214 @@ fn dot_mat_vec_dumb(mat: &[&[f64]], vec: &[f64]) -> Vec<f64>
 { mat.iter().map(|row| vdot(vec, row)).collect() }
 
 //-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ev_loop_log_truncation() {
+        let csv = "\
+            iteration,stage,energy,max_imaginary_frequency,num_bad_modes\n\
+            1,cg,-1e0,20,1\n\
+            1,chase,-2e0,,\n\
+            2,cg,-3e0,0,0\n\
+        ";
+        let through_1 = csv.find("2,cg").unwrap();
+        assert_eq!(energies_csv_len_before(csv, 3), csv.len());
+        assert_eq!(energies_csv_len_before(csv, 2), through_1);
+        assert_eq!(energies_csv_len_before(csv, 1), csv.find("1,cg").unwrap());
+
+        let mut xyz = vec![];
+        let frame = |xyz: &mut Vec<u8>, title: &str| {
+            rsp2_structure_io::Xyz {
+                title,
+                carts: vec![V3([0.0, 1.0, 2.0]), V3([1.5, 1.0, 0.0])],
+                elements: &[meta::Element::CARBON; 2][..],
+            }.to_writer(&mut *xyz).unwrap();
+            xyz.len()
+        };
+        frame(&mut xyz, "ev-loop iteration 1: after CG");
+        let through_1 = frame(&mut xyz, "ev-loop iteration 1: after eigenvector chasing");
+        frame(&mut xyz, "ev-loop iteration 2: after CG");
+        let xyz = String::from_utf8(xyz).unwrap();
+        assert_eq!(trajectory_len_before(&xyz, 3).unwrap(), xyz.len());
+        assert_eq!(trajectory_len_before(&xyz, 2).unwrap(), through_1);
+        assert_eq!(trajectory_len_before(&xyz, 1).unwrap(), 0);
+    }
+
+    #[test]
+    fn ev_loop_fsm_resume() {
+        let config = cfg::EvLoop { min_positive_iter: 2, max_iter: 5, ..Default::default() };
+        let steps = [true, false, true, false, false];
+
+        let mut uninterrupted = EvLoopFsm::new(&config);
+        let expected = steps.iter().map(|&did| uninterrupted.step(DidEvChasing(did))).collect::<Vec<_>>();
+        assert_eq!(expected.last(), Some(&EvLoopStatus::Done));
+
+        // interrupt after each possible iteration, sending the state through a checkpoint file
+        for num_before in 1..steps.len() {
+            let mut fsm = EvLoopFsm::new(&config);
+            for &did in &steps[..num_before] {
+                fsm.step(DidEvChasing(did));
+            }
+            let checkpoint = EvLoopCheckpoint {
+                structure: "ev-loop-01.2.structure".into(),
+                state: fsm.snapshot(),
+                ignored_evecs: vec![vec![V3([0.0, 0.6, 0.8])]],
+            };
+            let json = serde_json::to_string(&checkpoint).unwrap();
+            let checkpoint: EvLoopCheckpoint = serde_json::from_str(&json).unwrap();
+
+            let mut fsm = EvLoopFsm::from_snapshot(&config, checkpoint.state);
+            let actual = steps[num_before..].iter().map(|&did| fsm.step(DidEvChasing(did))).collect::<Vec<_>>();
+            assert_eq!(actual, &expected[num_before..]);
+            assert_eq!(fsm.snapshot(), uninterrupted.snapshot());
+        }
+    }
+
+    #[test]
+    fn variable_cell_relaxes_prestrained_cell() {
+        use crate::meta::Element;
+        use rsp2_array_types::{mat, Unvee};
+        use rsp2_structure::{CoordsKind, Lattice};
+
+        let half_r3 = 0.5 * f64::sqrt(3.0);
+        let ideal = Coords::new(
+            Lattice::new(&mat::from_array([
+                [ 2.46,           0.0,  0.0],
+                [-1.23, 2.46 * half_r3,  0.0],
+                [  0.0,           0.0, 20.0],
+            ])),
+            CoordsKind::Fracs(vec![
+                V3([0.0, 0.0, 0.5]),
+                V3([2./3., 1./3., 0.5]),
+            ]),
+        );
+
+        let elements: Rc<[_]> = vec![Element::CARBON; 2].into();
+        let masses: Rc<[_]> = vec![crate::common::default_element_mass(Element::CARBON).unwrap(); 2].into();
+        let bonds = Rc::new(rsp2_structure::bonds::FracBonds::compute(&ideal, 1.8).unwrap());
+        let meta: stored_structure::Meta = hlist![elements, masses, None, None, Some(bonds)];
+
+        let pot = PotentialBuilder::from_config_parts(
+            None,
+            None,
+            &cfg::Threading::Serial,
+            &from_json!({ }),
+            None,
+            &from_json!({ "rebo-nonreactive": {"params": "brenner"} }),
+        ).unwrap();
+        let cg_settings: cfg::Cg = from_json!({
+            "stop-condition": {"any": [
+                {"grad-max": 1e-7},
+                {"value-delta": {"rel-greater-than": 0, "steps-ago": 10}},
+                {"iterations": 1000}
+            ]},
+            "on-ls-failure": "succeed"
+        });
+        let parameters = [cfg::Parameter::Param('a'), cfg::Parameter::Param('a'), cfg::Parameter::One];
+        let snapshot_fn = SnapshotFn {
+            path: PathBuf::new(),
+            settings: cfg::Snapshot { every: None },
+            meta: meta.clone(),
+        };
+        let relax = |coords: Coords| do_cg_relax_with_variable_cell(
+            &*pot, &cg_settings, snapshot_fn.clone(),
+            Some(&parameters), &cfg::VariableCell {},
+            coords, meta.sift(),
+        ).unwrap();
+
+        let expected = relax(ideal.clone());
+
+        let mut strained = ideal.clone();
+        strained.apply_strain(&mat::from_array([
+            [ 0.03,  0.01, 0.0],
+            [ 0.01, -0.02, 0.0],
+            [  0.0,   0.0, 0.0],
+        ]));
+        let actual = relax(strained);
+
+        assert_close!(abs=1e-4, actual.lattice().matrix().unvee(), expected.lattice().matrix().unvee());
+
+        let stress = {
+            pot.initialize_diff_fn(&actual, meta.sift()).unwrap()
+                .compute_stress(&actual, meta.sift()).unwrap()
+        };
+        assert_close!(abs=1e-4, stress.unvee(), [[0.0; 3]; 3]);
+    }
+
+    #[test]
+    fn ev_loop_ignored_modes() {
+        use super::super::acoustic_search::{apply_ignored_modes, ModeSymmetry, ModeKind::*};
+        use rsp2_array_types::mat;
+        use rsp2_soa_ops::Perm;
+        use rsp2_structure::CartOp;
+
+        // two atoms exchanged by a mirror plane normal to x
+        let symmetry = ModeSymmetry {
+            cart_ops: vec![
+                CartOp::eye(),
+                CartOp::new(&mat::from_array([[-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]), V3::zero()),
+            ],
+            deperms: vec![Perm::eye(2), Perm::from_vec(vec![1, 0]).unwrap()],
+        };
+        let ket = |v: [f64; 3]| GammaKet3(vec![V3(v), -V3(v)]);
+        let spurious = ket([0.0, 0.1, 1.0]);
+        let real = ket([1.0, 0.0, 0.0]);
+        assert_close!(abs=1e-12, symmetry.characters(&spurious), vec![1.0, -1.0]);
+        assert_close!(abs=1e-12, symmetry.characters(&real), vec![1.0, 1.0]);
+
+        let config = cfg::EvLoop {
+            min_positive_iter: 1,
+            max_iter: 5,
+            ignored_modes: vec![cfg::IgnoredMode {
+                frequency: -20.0,
+                frequency_tol: 1.0,
+                characters: Some(vec![1.0, -1.0]),
+                character_tol: 0.1,
+            }],
+            ..Default::default()
+        };
+        let acoustic_search = cfg::AcousticSearch::default();
+
+        // Classify imaginary modes and report what the ev-loop would do next.
+        let step = |freqs: &[f64], kets: &[GammaKet3], ignored_evecs: &mut Vec<GammaKet3>| {
+            let kinds = apply_ignored_modes(
+                vec![Imaginary; freqs.len()].into(), freqs,
+                &GammaBasis3(std::sync::Arc::new(kets.to_vec())), Some(&symmetry),
+                &config.ignored_modes, ignored_evecs,
+            );
+            let did_chasing = kinds.iter().any(|kind| kind.should_chase(&acoustic_search));
+            let status = EvLoopFsm::new(&config).step(DidEvChasing(did_chasing));
+            (kinds.to_vec(), status)
+        };
+
+        // the spurious mode alone does not stop the loop from finishing
+        let mut ignored_evecs = vec![];
+        let (kinds, status) = step(&[-20.3], &[spurious.clone()], &mut ignored_evecs);
+        assert_eq!(kinds, vec![Ignored]);
+        assert_eq!(status, EvLoopStatus::Done);
+
+        // ...but a real imaginary mode does, even at the same frequency
+        let (kinds, status) = step(&[-20.3, -20.1], &[spurious.clone(), real.clone()], &mut vec![]);
+        assert_eq!(kinds, vec![Ignored, Imaginary]);
+        assert_eq!(status, EvLoopStatus::KeepGoing);
+
+        // once ignored, a mode is tracked even if its frequency drifts out of tolerance,
+        // including across a checkpoint
+        let checkpoint = EvLoopCheckpoint {
+            structure: "ev-loop-01.2.structure".into(),
+            state: EvLoopFsm::new(&config).snapshot(),
+            ignored_evecs: ignored_evecs.iter().map(|ket| ket.0.clone()).collect(),
+        };
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        let checkpoint: EvLoopCheckpoint = serde_json::from_str(&json).unwrap();
+        let mut resumed_evecs = checkpoint.ignored_evecs.into_iter().map(GammaKet3).collect();
+
+        let (kinds, _) = step(&[-25.0], &[spurious.clone()], &mut resumed_evecs);
+        assert_eq!(kinds, vec![Ignored]);
+        let (kinds, _) = step(&[-25.0], &[spurious.clone()], &mut vec![]);
+        assert_eq!(kinds, vec![Imaginary]);
+    }
+}
@@ -19,7 +19,7 @@ use crate::ui::cfg_merging::ConfigSources;
 
 use std::path::{Path, PathBuf};
 use path_abs::{PathDir, PathFile, FileRead, FileWrite};
-use rsp2_tasks_config::YamlRead;
+use rsp2_tasks_config::{YamlRead, UnusedConfigKeysError};
 use rsp2_fs_util::rm_rf;
 
 pub struct TrialDir {
@@ -27,22 +27,26 @@ pub struct TrialDir {
     _lock: LockfileGuard,
     // it is a logic error to read the settings more than once
     settings_were_read: bool,
+    // fail instead of warning when the settings contain unused keys
+    strict_config: bool,
 }
 
 impl AsPath for TrialDir {
     fn as_path(&self) -> &Path { self.path.as_path() }
 }
 
+#[derive(Debug, Clone)]
 pub struct NewTrialDirArgs {
     pub trial_dir: PathBuf,
     pub config_sources: ConfigSources,
     pub err_if_existing: bool,
+    pub strict_config: bool,
 }
 
 impl TrialDir {
     pub fn create_new(args: NewTrialDirArgs) -> FailResult<TrialDir> {
         let NewTrialDirArgs {
-            trial_dir, config_sources, err_if_existing,
+            trial_dir, config_sources, err_if_existing, strict_config,
         } = args;
 
         if !err_if_existing {
@@ -62,6 +66,7 @@ impl TrialDir {
         // Obtain a lock before writing anything to the directory.
         let trial_dir = TrialDir {
             settings_were_read: false,
+            strict_config,
             _lock: match Self::lockfile_path(&trial_dir).try_lock()? {
                 None => bail!("the lockfile was stolen from under our feet!"),
                 Some(g) => g,
@@ -86,6 +91,31 @@ impl TrialDir {
         trial_dir.validate()
     }
 
+    /// Check the arguments to `create_new` and read the settings they describe, without
+    /// creating anything.
+    pub fn dry_run_settings<T>(args: NewTrialDirArgs) -> FailResult<T>
+    where T: YamlRead,
+    {
+        let NewTrialDirArgs {
+            trial_dir, config_sources, err_if_existing, strict_config,
+        } = args;
+
+        if err_if_existing && trial_dir.exists() {
+            bail!(
+                "'{}': Output directory already exists! \
+                Use --force if you really want to replace it.",
+                trial_dir.nice(),
+            )
+        }
+
+        // (better error messages for type errors if we reparse from a string)
+        let s = serde_yaml::to_string(&config_sources.into_effective_yaml())?;
+        match strict_config {
+            true => YamlRead::from_reader_strict(s.as_bytes()),
+            false => YamlRead::from_reader(s.as_bytes()),
+        }
+    }
+
     fn lockfile_path(dir: &PathDir) -> LockfilePath
     { LockfilePath(dir.join("rsp2.lock").into()) }
 
@@ -99,6 +129,7 @@ impl TrialDir {
         let path = PathDir::new(path.canonicalize()?)?;
         TrialDir {
             settings_were_read: false,
+            strict_config: false,
             _lock: match Self::lockfile_path(&path).try_lock()? {
                 None => bail!("the trial directory is already in use"),
                 Some(g) => g,
@@ -107,6 +138,12 @@ impl TrialDir {
         }.validate()
     }
 
+    /// Make unused keys in the settings a hard error when they are read.
+    ///
+    /// (`create_new` takes this from its args; this is for trial dirs opened by other means)
+    pub fn set_strict_config(&mut self, strict: bool)
+    { self.strict_config = strict; }
+
     pub fn validate(self) -> FailResult<Self> {
         // Double-check that these files exist.
         let _ = self.base_settings_path()?;
@@ -165,7 +202,9 @@ impl TrialDir {
     {
         self.mark_settings_read();
         let file = FileRead::open(self.base_settings_path()?)?;
-        Ok(YamlRead::from_reader(file)?)
+        let (settings, unused) = YamlRead::from_reader_with_unused(file)?;
+        self.report_unused_config_keys(&unused)?;
+        Ok(settings)
     }
 
     pub fn read_modified_settings<T>(
@@ -189,7 +228,38 @@ impl TrialDir {
 
         // (better error messages for type errors if we reparse from a string)
         let s = serde_yaml::to_string(&yaml)?;
-        YamlRead::from_reader(s.as_bytes())
+        let (settings, unused) = YamlRead::from_reader_with_unused(s.as_bytes())?;
+        self.report_unused_config_keys(&unused)?;
+        Ok(settings)
+    }
+
+    fn unused_config_keys_path(&self) -> PathBuf
+    { self.join("unused-config-keys.txt") }
+
+    /// Record the unused keys found while reading the settings, so that typos don't get
+    /// lost in the noise of the log.  The file is always written (possibly empty) so that
+    /// it never describes a stale config.
+    ///
+    /// In strict mode, any unused keys are an error.
+    fn report_unused_config_keys(&self, unused: &[String]) -> FailResult<()> {
+        use std::io::Write;
+
+        let mut file = self.create_file(self.unused_config_keys_path())?;
+        for path in unused {
+            writeln!(file, "{}", path)?;
+        }
+
+        if self.strict_config {
+            UnusedConfigKeysError::check(unused.to_vec())?;
+        }
+
+        if !unused.is_empty() {
+            warn!(
+                "{} unused config item(s) (possible typos?); see {}",
+                unused.len(), self.unused_config_keys_path().nice(),
+            );
+        }
+        Ok(())
     }
 }
 
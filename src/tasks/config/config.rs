@@ -22,7 +22,7 @@ use serde::de::{self, IntoDeserializer};
 
 pub const MAX_VERSION: u32 = 1;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use failure::Error;
 use crate::option_aliases::{OrDefault, Nullable, Filled};
@@ -58,6 +58,10 @@ pub struct Settings {
     /// Specifies the potential to be used.
     ///
     /// See [`PotentialKind`] for the list of possibilities.
+    ///
+    /// When several potentials are listed, they are summed.  Any entry written as a mapping
+    /// may also contain `enabled: false` to leave it out of the sum without deleting it
+    /// (at least one entry must remain enabled).
     pub potential: ValidatedPotential,
 
     // (FIXME: weird name)
@@ -107,6 +111,28 @@ pub struct Settings {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub lattice_relax_22: Nullable<LatticeRelax>,
 
+    /// Relax the lattice together with the atomic positions, using the stress tensor.
+    ///
+    /// If supplied (an empty mapping will do), the six independent components of a
+    /// symmetric cartesian strain are optimized jointly with the positions during CG.
+    /// The potential must support computing the stress.
+    ///
+    /// When `parameters` is also supplied, only strain components between cartesian axes
+    /// whose entry in `parameters` is a named parameter are relaxed.  (e.g. `[a, a, ~]` only
+    /// relaxes the xx, yy, and xy components)  Note that the relation between parameters
+    /// is not enforced; `[a, a, c]` is treated the same as `[a, b, c]`.
+    ///
+    /// Cannot be used together with `lattice-relax-22`.
+    ///
+    /// # Example:
+    ///
+    /// ```yaml
+    /// variable-cell: {}
+    /// ```
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variable_cell: Nullable<VariableCell>,
+
     /// See the type for documentation.
     #[serde(default)]
     pub acoustic_search: AcousticSearch,
@@ -144,6 +170,11 @@ pub struct Settings {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub masses: Nullable<Masses>,
 
+    /// See the type for documentation.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site_masses: Nullable<SiteMasses>,
+
     /// See the type for documentation.
     #[serde(default)]
     pub ev_loop: EvLoop,
@@ -158,10 +189,57 @@ pub struct Settings {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub animate: Option<Animate>,
 
+    /// Write all of the eigenvectors from each iteration of the ev loop to
+    /// `eigenvectors.NN.json`.
+    #[serde(default)]
+    pub write_eigenvectors: bool,
+
+    /// Normalization convention for all eigenvectors written to files.  (both animations
+    /// and `write-eigenvectors`)
+    ///
+    /// When null, the cartesian displacement directions are written without
+    /// any normalization.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ev_normalization: Option<EvNormalization>,
+
+    /// `None` disables computation of mode Grüneisen parameters.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gruneisen: Option<Gruneisen>,
+
+    /// `None` disables computation of thermodynamic properties.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thermal: Option<Thermal>,
+
+    /// `None` disables computation of infrared intensities.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ir: Option<Ir>,
+
+    /// `None` disables the comparison of frequencies against reference peaks.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_assignment: Option<PeakAssignment>,
+
+    /// `None` performs per-mode analyses on all modes.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_window: Option<FrequencyWindow>,
+
+    /// See the type for documentation.
+    #[serde(default)]
+    pub raman: Raman,
+
     /// See the type for documentation.
     #[serde(default)]
     pub snapshot: Snapshot,
 
+    /// See the type for documentation.
+    #[serde(default)]
+    pub relax: Relax,
+
     #[serde(default)]
     #[serde(flatten)]
     pub _deprecated_lammps_settings: DeprecatedLammpsSettings,
@@ -246,6 +324,11 @@ impl Default for ScaleRanges {
 #[serde(rename_all = "kebab-case")]
 pub struct LatticeRelax {}
 
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct VariableCell {}
+
 pub type Parameters = [Parameter; 3];
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Parameter {
@@ -378,6 +461,10 @@ pub enum ScalableRange {
         range: (f64, f64),
         /// A "reasonable value" that might be used while another
         ///  parameter is optimized.
+        ///
+        /// When omitted, layer separations use the separation measured in the
+        /// input structure, and anything else uses the midpoint of `range`.
+        /// (see `ScalableRange::initial_value`)
         #[serde(default)]
         guess: OrDefault<f64>,
     },
@@ -387,6 +474,26 @@ pub enum ScalableRange {
     },
 }
 
+impl ScalableRange {
+    /// The value to use for this parameter before it has been optimized.
+    ///
+    /// In order of precedence, this is:
+    ///
+    /// * `value` for `Exact`, or an explicitly provided `guess`.
+    /// * `measured`, which is the value currently found in the structure,
+    ///   if one is meaningful. (e.g. for layer separations)
+    /// * The midpoint of `range`.
+    pub fn initial_value(&self, measured: Option<f64>) -> f64 {
+        match *self {
+            ScalableRange::Exact { value } |
+            ScalableRange::Search { range: _, guess: Some(value) } => value,
+            ScalableRange::Search { range: (lo, hi), guess: None } => {
+                measured.unwrap_or(0.5 * (lo + hi))
+            },
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 #[serde(rename_all="kebab-case")]
@@ -396,12 +503,25 @@ pub enum UnfoldBands {
     ///   Mendeley Data, v1 http://dx.doi.org/10.17632/3hpx6zmxhg.1
     ///
     /// This has not been used much lately, and I lack confidence in the correctness of its
-    /// implementation. My honest suggestion is: don't bother.
+    /// implementation. My honest suggestion is: don't bother.  Use `allen` instead.
+    Zheng {},
+
+    /// Use the method of P. B. Allen et al. (2013), "Recovering hidden Bloch character:
+    /// Unfolding electrons, phonons, and slabs", Phys Rev B, 87, 085322.
     ///
-    /// Allen's method (2013) is vastly superior, but is not currently integrated into the rsp2
-    /// binaries. See the standalone script `scripts/unfold.py` in the rsp2 source root.
-    Zheng {}
+    /// This is the same method used by the standalone script `scripts/unfold.py` in the rsp2
+    /// source root.  Each layer must have the translational symmetry of its primitive cell
+    /// (to within `translation-tolerance`), so you probably want to use it on the initial
+    /// structure rather than a relaxed one.
+    #[serde(rename_all = "kebab-case")]
+    Allen {
+        /// Cartesian distance within which a site must land on another site when translated
+        /// by a primitive lattice vector.
+        #[serde(default = "unfold_bands__allen__translation_tolerance")]
+        translation_tolerance: f64,
+    },
 }
+fn unfold_bands__allen__translation_tolerance() -> f64 { 1e-2 }
 
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
@@ -424,6 +544,10 @@ pub struct Cg {
     /// Initial guess for linesearch on the very first iteration.
     #[serde(default = "cg__alpha_guess_max")]
     pub alpha_guess_max: f64,
+
+    /// How the initial guess for linesearch is updated between iterations.
+    #[serde(default)]
+    pub alpha_guess: CgAlphaGuess,
 }
 // Been using these values for a while on structures of arbitrary size.
 fn cg__alpha_guess_first() -> f64 { 0.01 }
@@ -450,6 +574,36 @@ impl Default for CgOnLsFailure {
     fn default() -> Self { CgOnLsFailure::Succeed }
 }
 
+/// How the initial guess for each linesearch is derived from the previous iteration.
+///
+/// All strategies use `alpha-guess-first` on the first iteration, and all guesses are
+/// clipped to `alpha-guess-max`.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all="kebab-case")]
+pub enum CgAlphaGuess {
+    /// `alpha-guess: previous`
+    ///
+    /// Use the step size taken by the previous linesearch.  This is the most stable
+    /// choice, but the guess grows slowly in very stiff systems.
+    Previous,
+    /// `alpha-guess: {scaled: 1.5}`
+    ///
+    /// Use a multiple of the previous step size.  A factor above 1 lets the guess grow
+    /// faster, at the cost of extra evaluations whenever it overshoots.
+    Scaled(f64),
+    /// `alpha-guess: quadratic`
+    ///
+    /// Interpolate a parabola from the previous decrease in value and the current slope.
+    /// Adapts the fastest, but trusts the value, so it may misbehave when the value has
+    /// significant rounding error (e.g. very large systems). Falls back to `previous` when
+    /// the estimate is nonsensical.
+    Quadratic,
+}
+impl Default for CgAlphaGuess {
+    fn default() -> Self { CgAlphaGuess::Previous }
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 #[serde(rename_all="kebab-case")]
@@ -508,6 +662,13 @@ pub struct EnergyPlotSettings {
 
     #[serde(default)]
     pub lammps: Lammps,
+
+    /// Normalization applied to the directions given to `rsp2-shear-plot --directions`.
+    ///
+    /// When null, the directions are used exactly as written, so that `--xlim` and `--ylim`
+    /// are in units of the directions.
+    #[serde(default)]
+    pub direction_normalization: Option<EvUnit>,
 }
 derive_yaml_read!{ValidatedEnergyPlotSettings}
 
@@ -538,11 +699,16 @@ derive_yaml_read!{ValidatedPotential}
 
 #[derive(Serialize)]
 #[derive(Debug, Clone, PartialEq)]
-pub struct Potential(pub Vec<PotentialKind>);
+pub struct Potential(pub Vec<PotentialEntry>);
 
 impl Potential {
-    pub fn into_vec(self) -> Vec<PotentialKind> { self.0 }
-    pub fn as_slice(&self) -> &[PotentialKind] { &self.0 }
+    pub fn into_vec(self) -> Vec<PotentialEntry> { self.0 }
+    pub fn as_slice(&self) -> &[PotentialEntry] { &self.0 }
+
+    /// The kinds of all entries that are not disabled.
+    pub fn enabled_kinds(&self) -> impl Iterator<Item=&PotentialKind> {
+        self.0.iter().filter(|entry| entry.enabled).map(|entry| &entry.kind)
+    }
 }
 
 // Manual impl, because #[derive(Deserialize)] on untagged enums discard
@@ -581,6 +747,161 @@ impl<'de> de::Deserialize<'de> for Potential {
     }
 }
 
+/// A single term of a `Potential`.
+///
+/// This is written exactly like a `PotentialKind`, except that the mapping form may
+/// additionally contain an `enabled` key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PotentialEntry {
+    pub kind: PotentialKind,
+
+    /// Disabled entries are left out of the sum.  Defaults to `true`.
+    pub enabled: bool,
+}
+
+impl From<PotentialKind> for PotentialEntry {
+    fn from(kind: PotentialKind) -> Self { PotentialEntry { kind, enabled: true } }
+}
+
+// Manual impl, because the `enabled` key sits next to the externally-tagged
+// PotentialKind, and the unit variants can also be written as plain strings.
+impl<'de> de::Deserialize<'de> for PotentialEntry {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MyVisitor;
+
+        impl<'de> de::Visitor<'de> for MyVisitor {
+            type Value = PotentialEntry;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a potential")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                de::Deserialize::deserialize(s.into_deserializer())
+                    .map(|kind: PotentialKind| PotentialEntry::from(kind))
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut enabled = None;
+                let mut kind = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    if key == "enabled" {
+                        if enabled.is_some() {
+                            return Err(de::Error::duplicate_field("enabled"));
+                        }
+                        enabled = Some(map.next_value()?);
+                    } else {
+                        if kind.is_some() {
+                            return Err(de::Error::custom(format_args!(
+                                "unexpected key `{}` after the potential (use an array to sum multiple potentials)",
+                                key,
+                            )));
+                        }
+                        kind = Some(de::Deserialize::deserialize(TaggedMapValue { tag: key, map: &mut map })?);
+                    }
+                }
+                match kind {
+                    Some(kind) => Ok(PotentialEntry { kind, enabled: enabled.unwrap_or(true) }),
+                    None => Err(de::Error::custom("missing the kind of potential")),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(MyVisitor)
+    }
+}
+
+// Deserializes an externally-tagged enum whose tag was just read as a key of `map`,
+// with the content taken from the map's next value.
+//
+// The content is read directly from the original deserializer (rather than being buffered)
+// so that serde_ignored can still see and report unused keys inside it.
+struct TaggedMapValue<'a, A> {
+    tag: String,
+    map: &'a mut A,
+}
+
+impl<'de, 'a, A: de::MapAccess<'de>> de::Deserializer<'de> for TaggedMapValue<'a, A> {
+    type Error = A::Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, A::Error> {
+        visitor.visit_enum(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de, 'a, A: de::MapAccess<'de>> de::EnumAccess<'de> for TaggedMapValue<'a, A> {
+    type Error = A::Error;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), A::Error> {
+        let tag: de::value::StrDeserializer<'_, A::Error> = self.tag.as_str().into_deserializer();
+        let variant = seed.deserialize(tag)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a, A: de::MapAccess<'de>> de::VariantAccess<'de> for TaggedMapValue<'a, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.map.next_value()
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, A::Error> {
+        self.map.next_value_seed(seed)
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        struct Seed<V>(usize, V);
+        impl<'de, V: de::Visitor<'de>> de::DeserializeSeed<'de> for Seed<V> {
+            type Value = V::Value;
+            fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+                deserializer.deserialize_tuple(self.0, self.1)
+            }
+        }
+        self.map.next_value_seed(Seed(len, visitor))
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, A::Error> {
+        struct Seed<V>(V);
+        impl<'de, V: de::Visitor<'de>> de::DeserializeSeed<'de> for Seed<V> {
+            type Value = V::Value;
+            fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+                deserializer.deserialize_map(self.0)
+            }
+        }
+        self.map.next_value_seed(Seed(visitor))
+    }
+}
+
+impl serde::Serialize for PotentialEntry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        if self.enabled {
+            return self.kind.serialize(serializer);
+        }
+
+        let mut map = match serde_json::to_value(&self.kind).map_err(S::Error::custom)? {
+            serde_json::Value::Object(map) => map,
+            serde_json::Value::String(name) => {
+                let mut map = serde_json::Map::new();
+                map.insert(name, serde_json::Value::Null);
+                map
+            },
+            _ => unreachable!("PotentialKind is externally tagged"),
+        };
+        map.insert("enabled".to_string(), serde_json::Value::Bool(false));
+        map.serialize(serializer)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum PotentialKind {
@@ -613,6 +934,12 @@ pub enum PotentialKind {
     /// It also supports a variety of parameters.
     #[serde(rename = "rebo-nonreactive")] ReboNonreactive(PotentialReboNonreactive),
 
+    /// An analytic Morse pair potential between chosen pairs of elements.
+    ///
+    /// This is implemented directly in rsp2.  It is mainly intended for testing on simple
+    /// systems such as dimers, whose vibrational frequencies are known analytically.
+    #[serde(rename = "morse")] Morse(PotentialMorse),
+
     /// Use potentials implemented in Lammps.  Only a few specific potentials are supported.
     ///
     /// This potential cannot be listed multiple times.
@@ -630,6 +957,27 @@ pub enum PotentialKind {
     #[serde(rename = "test-func-chainify")] TestChainify,
 }
 
+impl PotentialKind {
+    /// The name under which this potential is written in the config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PotentialKind::OldLammpsRebo(_) => "rebo",
+            PotentialKind::OldLammpsAirebo(_) => "airebo",
+            PotentialKind::OldLammpsKolmogorovCrespiZ(_) => "kc-z",
+            PotentialKind::OldLammpsKolmogorovCrespiFull(_) => "kc-full",
+            PotentialKind::OldKolmogorovCrespiZ(_) => "kc-z-new",
+            PotentialKind::OldReboNew(_) => "rebo-new",
+            PotentialKind::KolmogorovCrespi(_) => "kc-layered",
+            PotentialKind::ReboNonreactive(_) => "rebo-nonreactive",
+            PotentialKind::Morse(_) => "morse",
+            PotentialKind::Lammps(_) => "lammps",
+            PotentialKind::DftbPlus(_) => "dftb+",
+            PotentialKind::TestZero => "test-func-zero",
+            PotentialKind::TestChainify => "test-func-chainify",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
@@ -666,6 +1014,15 @@ pub struct LammpsPotentialAirebo {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub omp: OrDefault<bool>,
+    /// Number of OpenMP threads used by the `/omp` pair style.  When null, LAMMPS uses
+    /// `OMP_NUM_THREADS`.
+    ///
+    /// Like LAMMPS' MPI parallelism, this is governed by `threading`: whenever rsp2 asks for
+    /// the potential to run in serial (e.g. for most things under `threading: serial`),
+    /// a single thread is used instead.  Requires `omp` to be enabled.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub omp_threads: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -674,6 +1031,15 @@ pub struct LammpsPotentialAirebo {
 pub struct LammpsPotentialRebo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub omp: OrDefault<bool>,
+    /// Number of OpenMP threads used by the `/omp` pair style.  When null, LAMMPS uses
+    /// `OMP_NUM_THREADS`.
+    ///
+    /// Like LAMMPS' MPI parallelism, this is governed by `threading`: whenever rsp2 asks for
+    /// the potential to run in serial (e.g. for most things under `threading: serial`),
+    /// a single thread is used instead.  Requires `omp` to be enabled.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub omp_threads: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -911,6 +1277,33 @@ pub enum PotentialReboNewParams {
     Lindsay,
 }
 
+/// `V(r) = D_e [(1 - exp(-a (r - r_e)))^2 - 1]`, summed over all pairs of atoms within the cutoff.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct PotentialMorse {
+    /// Parameters for each pair of elements.  Pairs that are not listed do not interact.
+    pub pairs: Vec<MorsePair>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct MorsePair {
+    /// Element symbols, e.g. `[C, H]`.  (order does not matter)
+    pub elements: [String; 2],
+    /// Well depth, in eV.
+    pub d_e: f64,
+    /// Stiffness of the well, in inverse Angstroms.
+    pub a: f64,
+    /// Equilibrium distance, in Angstroms.
+    pub r_e: f64,
+    /// Pairs further apart than this (in Angstroms) do not interact.
+    ///
+    /// This is a sharp cutoff, so it should be placed where the potential is negligible.
+    pub cutoff: f64,
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
@@ -949,7 +1342,9 @@ pub struct Phonons {
     ///
     /// If a value of 0 is used, symmetry will not be sought.  This is necessary sometimes
     /// to work around limitations that prevent rsp2 from working on non-primitive cells.
-    pub symmetry_tolerance: Nullable<f64>,
+    ///
+    /// This may also be `auto`.  See `SymmetryTolerance::Auto`.
+    pub symmetry_tolerance: Nullable<SymmetryTolerance>,
 
     /// How far atoms are displaced when numerically computing the force constants.
     ///
@@ -957,6 +1352,17 @@ pub struct Phonons {
     /// `analytic-hessian: true`)
     pub displacement_distance: Nullable<f64>,
 
+    /// Overrides `displacement-distance` for atoms of specific elements, keyed by element
+    /// symbol.  e.g. stiff C-H bonds may call for a smaller step than soft interlayer modes.
+    ///
+    /// Only supported by the `rsp2` disp-finder.  (phonopy only accepts a single distance,
+    /// and displacements read from a `file` already have their own lengths)
+    ///
+    /// The force constants are computed from the actual displacement vectors, so each
+    /// displacement is normalized by the distance that was used for it.
+    #[serde(default)]
+    pub displacement_distance_by_element: BTreeMap<String, f64>,
+
     /// Use an analytically-computed hessian for the force constants.
     ///
     /// If true, `symmetry_tolerance`, and `displacement_distance` are allowed to be null.
@@ -1009,11 +1415,21 @@ pub struct Phonons {
     /// structures.  For instance, in REBO computed on the primitive unit cell of graphite,
     /// displacing one atom will also displace the atoms two bonds away, which would have an
     /// undesirable impact on the bond angle terms.
+    ///
+    /// rsp2 checks the thickness of the supercell along each axis against twice the
+    /// interaction cutoff of the potential (or `bond-radius`, whichever is larger) and
+    /// warns if it is too thin.  See `fail-on-small-supercell`.
     pub supercell: SupercellSpec,
+
+    /// Make it an error (rather than a warning) for `supercell` to be too thin for the
+    /// interaction cutoff.
+    #[serde(default = "phonons__fail_on_small_supercell")]
+    pub fail_on_small_supercell: bool,
 }
 fn phonons__analytic_hessian() -> bool { false }
+fn phonons__fail_on_small_supercell() -> bool { false }
 fn phonons__eigensolver() -> PhononEigensolver {
-    PhononEigensolver::Dense {}
+    PhononEigensolver::Dense { frequency_window: None }
 }
 fn phonons__disp_finder() -> PhononDispFinder {
     PhononDispFinder::Rsp2 {
@@ -1021,6 +1437,73 @@ fn phonons__disp_finder() -> PhononDispFinder {
     }
 }
 
+/// Either a number or the string `auto`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymmetryTolerance {
+    Fixed(f64),
+    /// Try a geometric sequence of tolerances between `1e-6` and `1e-1`, count the
+    /// spacegroup operators found at each, and use the tolerance in the middle of the widest
+    /// range over which the count does not change.
+    ///
+    /// This is chosen once per run, on the input structure (after lattice optimization), and
+    /// then used for the rest of the run.  For runs in a trial directory, the choice is saved in
+    /// `symmetry-tolerance.json` and reused by later commands on the same directory.
+    Auto,
+}
+
+impl SymmetryTolerance {
+    /// The value, if it was given explicitly.
+    pub fn fixed(&self) -> Option<f64> {
+        match *self {
+            SymmetryTolerance::Fixed(x) => Some(x),
+            SymmetryTolerance::Auto => None,
+        }
+    }
+}
+
+impl serde::Serialize for SymmetryTolerance {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            SymmetryTolerance::Fixed(x) => serializer.serialize_f64(x),
+            SymmetryTolerance::Auto => serializer.serialize_str("auto"),
+        }
+    }
+}
+
+// Manual impl, because #[derive(Deserialize)] on untagged enums discard
+// all error messages.
+impl<'de> de::Deserialize<'de> for SymmetryTolerance {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MyVisitor;
+
+        impl<'de> de::Visitor<'de> for MyVisitor {
+            type Value = SymmetryTolerance;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a number or \"auto\"")
+            }
+
+            fn visit_f64<E: de::Error>(self, x: f64) -> Result<Self::Value, E>
+            { Ok(SymmetryTolerance::Fixed(x)) }
+
+            fn visit_i64<E: de::Error>(self, x: i64) -> Result<Self::Value, E>
+            { Ok(SymmetryTolerance::Fixed(x as f64)) }
+
+            fn visit_u64<E: de::Error>(self, x: u64) -> Result<Self::Value, E>
+            { Ok(SymmetryTolerance::Fixed(x as f64)) }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                match s {
+                    "auto" => Ok(SymmetryTolerance::Auto),
+                    _ => Err(E::invalid_value(de::Unexpected::Str(s), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(MyVisitor)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
@@ -1066,10 +1549,26 @@ pub enum PhononEigensolver {
         ///
         /// The sparse eigensolver is incapable of producing all eigensolutions.
         ///
-        /// The most negative eigenvalues will be sought first.
+        /// The ones nearest the end of the spectrum chosen by `which` will be sought first.
         /// Fewer will be sought if the number of atoms is insufficient.
         #[serde(default = "phonon_eigen_solver__sparse__how_many")]
         how_many: usize,
+
+        /// Which part of the spectrum the sparse eigensolver should look in.
+        #[serde(default)]
+        which: EigensolverWhich,
+
+        /// Seed for the starting vectors given to ARPACK.
+        ///
+        /// When this is set, each attempt made by the sparse eigensolver derives its starting
+        /// vector deterministically from the seed and the attempt number, so that two runs
+        /// on identical input produce identical eigensolutions.
+        ///
+        /// `None` (the default) leaves the starting vectors to ARPACK, which picks them at
+        /// random.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>,
     },
 
     /// Diagonalize the dynamical matrix using dense matrix methods in LAPACKe.
@@ -1077,7 +1576,18 @@ pub enum PhononEigensolver {
     /// This can be more reliable than the sparse eigensolver,
     /// and may even be faster (if you can afford the memory!).
     #[serde(rename_all = "kebab-case")]
-    Dense {},
+    Dense {
+        /// Discard all eigensolutions outside of this window of frequencies
+        /// as soon as they are computed, to save memory.
+        ///
+        /// Every eigensolution is still computed, since LAPACK computes them all regardless.
+        /// Unlike the top-level `frequency-window`, modes outside of this window are not
+        /// available to *anything*, including the acoustic search; generally, it should
+        /// only have a `max`.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        frequency_window: Option<FrequencyWindow>,
+    },
 
     /// Deprecated.  Use either 'sparse' or 'dense'.
     #[serde(rename_all = "kebab-case")]
@@ -1090,12 +1600,47 @@ pub enum PhononEigensolver {
 
         #[serde(default = "phonon_eigen_solver__sparse__how_many")]
         how_many: usize,
+
+        #[serde(default)]
+        which: EigensolverWhich,
+
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>,
     },
 }
 fn phonon_eigen_solver__sparse__shift_invert_attempts() -> u32 { 4 }
 fn phonon_eigen_solver__sparse__how_many() -> usize { 12 }
 fn phonon_eigen_solver__rsp2__dense() -> bool { false }
 
+/// Selects which eigensolutions the sparse eigensolver produces.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EigensolverWhich {
+    /// The most negative eigenvalues.  These are the modes that matter for relaxation.
+    ///
+    /// This is the only setting under which `shift-invert-attempts` is used.
+    MostNegative,
+
+    /// The eigenvalues of largest magnitude.  For a reasonably relaxed structure,
+    /// these are the highest frequency (optical) modes.
+    Largest,
+
+    /// The eigenvalues closest to a target frequency (in cm^-1, negative for imaginary),
+    /// found using shift-invert mode.
+    ///
+    /// The caveats of shift-invert mode apply; its results are numerically unreliable when
+    /// the target lies very close to an actual eigenvalue (such as the acoustic modes near
+    /// zero), since the shifted matrix is then nearly singular.  Choosing a target slightly
+    /// away from the modes of interest is safer.
+    NearShift(f64),
+}
+
+impl Default for EigensolverWhich {
+    fn default() -> Self { EigensolverWhich::MostNegative }
+}
+
 #[derive(Serialize)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct MessagePhononEigensolverPhonopy;
@@ -1120,9 +1665,33 @@ pub enum PhononDispFinder {
         /// Corresponds to phonopy's DIAG option.
         #[serde(default = "phonon_disp_finder__phonopy__diag")]
         diag: bool,
+        /// Number of times to retry if phonopy exits unsuccessfully.
+        ///
+        /// On busy filesystems, phonopy has been seen to occasionally fail to read a file that
+        /// it just wrote.  Each attempt starts over in a fresh temporary directory.
+        #[serde(default)]
+        retries: u32,
+        /// Seconds to wait before the first retry.  This doubles after each failed retry.
+        #[serde(rename = "retry-delay", default = "phonon_disp_finder__phonopy__retry_delay")]
+        retry_delay: f64,
+    },
+    /// Read an explicit list of displacements from a file, bypassing the disp-finder.
+    ///
+    /// The file is YAML (or JSON) containing a list of `[atom, [x, y, z]]`, where `atom` is
+    /// the index of a site in the structure and `[x, y, z]` is a cartesian displacement in
+    /// angstroms.  (`displacement-distance` is not used)  A relative path is resolved against
+    /// the directory of the config file it appears in.  (or the current directory, when given
+    /// as a literal `--config` argument)
+    ///
+    /// Symmetry is not used to compute the force constants, so every atom must be displaced
+    /// in at least three linearly independent directions.  Any number of additional
+    /// displacements may be given, in which case the force constants are fit by least squares.
+    File {
+        path: String,
     },
 }
 fn phonon_disp_finder__phonopy__diag() -> bool { true }
+fn phonon_disp_finder__phonopy__retry_delay() -> f64 { 5.0 }
 fn phonon_disp_finder__rsp2__directions() -> PhononDispFinderRsp2Directions { PhononDispFinderRsp2Directions::Diag }
 
 #[derive(Serialize, Deserialize)]
@@ -1145,49 +1714,223 @@ pub enum PhononDispFinderRsp2Directions {
     /// (Experimental) Diagonal displacements with fractional coords up to 2.
     #[serde(rename = "diag-2")]
     Diag2,
-    /// (Debug) Try all three of them and report how many they find, in an attempt
-    /// to answer the question "is diag-2 worthless?"
+    /// (Experimental) Diagonal displacements with fractional coords up to 3.
+    #[serde(rename = "diag-3")]
+    Diag3,
+    /// (Debug) Try all of them and report how many they find, in an attempt to answer
+    /// the question "are diag-2 and diag-3 worthless?"  Uses the output of diag-2.
     Survey,
 }
 
-/// Output normal modes each iteration of the ev loop, for visualization purposes.
+/// Compute mode Grüneisen parameters of the final structure by finite differences.
+///
+/// This requires two additional phonon computations, at slightly smaller and larger volumes.
+/// The structure is **not** relaxed at these volumes; the lattice is scaled with fixed
+/// fractional coordinates.  Modes at the perturbed volumes are matched to the original modes
+/// by eigenvector overlap, considering only modes of nearby frequency.  (as a result,
+/// Grüneisen parameters larger than 20 in magnitude cannot be found)
+/// Results are written to `gruneisen.json`.
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
-pub struct Animate {
-    #[serde(default = "animate__which")]
-    pub which: AnimateWhich,
-    #[serde(default = "animate__format")]
-    pub format: AnimateFormat,
-    /// Include no more than this many eigenvectors.  This is a safeguard against
-    /// the possibility of an unexpectedly large number of negative eigenvalues
-    /// being found.
+pub struct Gruneisen {
+    /// Relative change in volume used for the finite difference.
     ///
-    /// When null, unlimited.
-    #[serde(default)]
-    pub max_count: Option<usize>,
+    /// The lattice is scaled uniformly along all three lattice vectors.
+    #[serde(default = "gruneisen__volume_step")]
+    pub volume_step: f64,
+
+    /// Minimum squared overlap between a mode and its counterpart at a perturbed
+    /// volume. Modes with a worse match will have no Grüneisen parameter.
+    #[serde(default = "gruneisen__min_overlap")]
+    pub min_overlap: f64,
 }
-fn animate__which() -> AnimateWhich { AnimateWhich::Negative }
-fn animate__format() -> AnimateFormat { AnimateFormat::VSim {} }
+fn gruneisen__volume_step() -> f64 { 1e-2 }
+fn gruneisen__min_overlap() -> f64 { 0.8 }
 
+/// Compute the harmonic free energy, entropy, and heat capacity of the final structure.
+///
+/// Only the frequencies at Γ are used, so the results are per cell of the input structure,
+/// and converge as the input structure is made larger.  Imaginary modes and the acoustic
+/// modes at Γ are skipped.  Results are written to `thermal.json`.
+///
+/// Requires the dense eigensolver (without a `frequency-window`), so that every mode is known.
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
-pub enum AnimateWhich {
-    /// Write only those non-acoustic modes with negative eigenvalues.
-    Negative,
-
-    /// Write all modes.
-    ///
-    /// Be careful; for large structures, this could easily consume gigabytes of data.
-    All,
+pub struct Thermal {
+    /// Temperatures (Kelvin) at which to compute the properties.
+    pub temperatures: Vec<f64>,
 }
 
+/// Compute infrared intensities of the gamma modes, using a rigid-ion model in which
+/// each atom carries a fixed point charge.
+///
+/// Intensities are shown alongside the eigenvalues, and written to `ir.json`.
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
-pub enum AnimateFormat {
-    /// `V_sim`'s `.ascii` text format.
+pub struct Ir {
+    /// Charge of each element (in units of the elementary charge), keyed by element symbol.
+    ///
+    /// Every element in the structure must be listed.
+    pub charges: BTreeMap<String, f64>,
+}
+
+/// Compare the frequencies of the final structure against the peaks of a reference
+/// (e.g. experimental) spectrum, for judging the quality of a potential.
+///
+/// Each reference peak is assigned to a distinct vibrational mode so as to minimize the
+/// sum of squared deviations.  The assignment and its RMS deviation are written to
+/// `peak-assignment.json`.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct PeakAssignment {
+    /// Frequencies of the reference peaks (cm^-1).
+    pub reference: Vec<f64>,
+}
+
+/// Restrict expensive per-mode analyses (currently, raman tensors) to a range of frequencies.
+///
+/// Modes outside the window are omitted from `raman.json` and the files derived from it.
+/// The classification of acoustic and imaginary modes is not affected, and always
+/// considers every mode.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct FrequencyWindow {
+    /// Minimum frequency (cm^-1), inclusive.  `None` means no lower bound.
+    #[serde(default)]
+    pub min: Option<f64>,
+
+    /// Maximum frequency (cm^-1), inclusive.  `None` means no upper bound.
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+impl FrequencyWindow {
+    pub fn contains(&self, frequency: f64) -> bool {
+        self.min.map_or(true, |min| min <= frequency)
+            && self.max.map_or(true, |max| frequency <= max)
+    }
+}
+
+/// Options for raman intensities.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Raman {
+    /// Light polarization for the `intensity` field of `raman.json`.
+    ///
+    /// (the `average-3d` and `backscatter` fields are written regardless of this setting)
+    ///
+    /// # Example:
+    ///
+    /// ```yaml
+    /// raman:
+    ///   polarization:
+    ///     polarized:
+    ///       incident: [1, 0, 0]
+    ///       scattered: [0, 1, 0]
+    /// ```
+    #[serde(default)]
+    pub polarization: RamanPolarization,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RamanPolarization {
+    /// Average over all incident and scattered polarizations.
+    Average,
+
+    /// Backscattering along the z axis, averaged over polarizations in the xy plane.
+    BackscatterZ,
+
+    /// Fixed polarization vectors.  These are normalized when the config is loaded.
+    Polarized {
+        incident: [f64; 3],
+        scattered: [f64; 3],
+    },
+}
+
+impl Default for RamanPolarization {
+    fn default() -> Self { RamanPolarization::Average }
+}
+
+/// Output normal modes each iteration of the ev loop, for visualization purposes.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Animate {
+    #[serde(default = "animate__which")]
+    pub which: AnimateWhich,
+    #[serde(default = "animate__format")]
+    pub format: AnimateFormat,
+    /// Include no more than this many eigenvectors.  This is a safeguard against
+    /// the possibility of an unexpectedly large number of negative eigenvalues
+    /// being found.
+    ///
+    /// When null, unlimited.
+    #[serde(default)]
+    pub max_count: Option<usize>,
+}
+fn animate__which() -> AnimateWhich { AnimateWhich::Negative }
+fn animate__format() -> AnimateFormat { AnimateFormat::VSim {} }
+
+/// Normalization convention for eigenvectors written to files, since different
+/// tools expect different things.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct EvNormalization {
+    pub basis: EvBasis,
+    #[serde(default = "ev_normalization__unit")]
+    pub unit: EvUnit,
+}
+fn ev_normalization__unit() -> EvUnit { EvUnit::Mode }
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EvBasis {
+    /// Cartesian displacements of each atom. (the eigenvector divided by `sqrt(mass)`)
+    Cartesian,
+
+    /// The actual eigenvectors of the dynamical matrix.
+    MassWeighted,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EvUnit {
+    /// The vector for each mode has unit norm.
+    Mode,
+
+    /// The largest vector for any single atom in each mode has unit norm.
+    Atom,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnimateWhich {
+    /// Write only those non-acoustic modes with negative eigenvalues.
+    Negative,
+
+    /// Write all modes.
+    ///
+    /// Be careful; for large structures, this could easily consume gigabytes of data.
+    All,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnimateFormat {
+    /// `V_sim`'s `.ascii` text format.
     VSim {},
 }
 
@@ -1209,7 +1952,7 @@ pub enum SupercellSpec {
 /// A high-level control of how multiple cores are used.
 ///
 /// This flag was highly ill-conceived and will hopefully one day be replaced.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize)]
 #[derive(Debug, Clone, PartialEq)]
 #[serde(rename_all="kebab-case")]
 pub enum Threading {
@@ -1217,18 +1960,88 @@ pub enum Threading {
     /// allowing LAMMPS to use as many cores as it pleases.
     Lammps,
 
-    /// This currently enables parallel code in `rebo-new` and `kc-z-new`
-    Rayon,
+    /// This currently enables parallel code in `rebo-new` and `kc-z-new`,
+    /// as well as in the search for bonds.
+    ///
+    /// May be written as simply `rayon`, or as a mapping with further options.
+    Rayon(RayonThreading),
 
     /// Everything (or almost everything) should run in serial.
     Serial,
 }
 
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Default)]
+#[serde(rename_all="kebab-case")]
+pub struct RayonThreading {
+    /// Compute the forces at up to this many displacements at once while computing
+    /// force sets, using a separate instance of the potential for each.
+    ///
+    /// This does not affect any parallelism inside the potential itself.  Because only one
+    /// LAMMPS instance may exist at a time in a process (see `rsp2_lammps_wrap::INSTANCE_LOCK`),
+    /// values greater than 1 are forbidden when any LAMMPS potential is used.
+    ///
+    /// When null, the displacements are done one at a time, which allows a single instance
+    /// of the potential to be reused for all of them.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_displacements: Option<usize>,
+}
+
+// Manual impl, so that the plain string `rayon` is still accepted.
+impl<'de> de::Deserialize<'de> for Threading {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename_all="kebab-case")]
+        enum Repr {
+            Lammps,
+            Rayon(RayonThreading),
+            Serial,
+        }
+
+        impl From<Repr> for Threading {
+            fn from(repr: Repr) -> Threading {
+                match repr {
+                    Repr::Lammps => Threading::Lammps,
+                    Repr::Rayon(x) => Threading::Rayon(x),
+                    Repr::Serial => Threading::Serial,
+                }
+            }
+        }
+
+        struct MyVisitor;
+
+        impl<'de> de::Visitor<'de> for MyVisitor {
+            type Value = Threading;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a threading mode")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                match s {
+                    "rayon" => Ok(Threading::Rayon(Default::default())),
+                    _ => <Repr as de::Deserialize>::deserialize(s.into_deserializer()).map(Into::into),
+                }
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                <Repr as de::Deserialize>::deserialize(de::value::MapAccessDeserializer::new(map)).map(Into::into)
+            }
+        }
+
+        deserializer.deserialize_any(MyVisitor)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub struct AcousticSearch {
     /// Known number of non-translational acoustic modes.
+    ///
+    /// When set, it is an error to find more rotational modes than this, and a
+    /// warning is emitted if fewer non-translational acoustic modes are found.
     #[serde(default)]
     pub expected_non_translations: Nullable<usize>,
 
@@ -1247,10 +2060,35 @@ pub struct AcousticSearch {
     /// as imaginary.
     #[serde(default = "acoustic_search__imaginary_fdot_threshold")]
     pub imaginary_fdot_threshold: f64,
+
+    /// Whether the ev-loop should chase modes that meet neither of the above thresholds.
+    ///
+    /// Such modes are often just numerical noise, in which case chasing them is a waste of time.
+    /// When `false`, they are still reported (as suspicious), but they do not prevent the
+    /// ev-loop from finishing.
+    #[serde(default = "acoustic_search__chase_suspicious")]
+    pub chase_suspicious: bool,
+
+    /// Threshold on the magnitude of the frequencies of the translational modes.
+    ///
+    /// Unless an acoustic sum rule is imposed, the translational modes will generally have
+    /// small, nonzero frequencies.  A large value usually indicates a problem, such as a
+    /// supercell that is too small or a broken potential.  When any translational mode is
+    /// found with a frequency larger in magnitude than this, a warning is emitted at the start
+    /// of the acoustic search.  (see also `fail-on-large-acoustic-frequency`)
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_acoustic_frequency: Nullable<f64>,
+
+    /// Make it an error (rather than a warning) to exceed `max-acoustic-frequency`.
+    #[serde(default = "acoustic_search__fail_on_large_acoustic_frequency")]
+    pub fail_on_large_acoustic_frequency: bool,
 }
 fn acoustic_search__displacement_distance() -> f64 { 1e-5 }
 fn acoustic_search__imaginary_fdot_threshold() -> f64 { 0.80 }
 fn acoustic_search__rotational_fdot_threshold() -> f64 { 0.80 }
+fn acoustic_search__chase_suspicious() -> bool { true }
+fn acoustic_search__fail_on_large_acoustic_frequency() -> bool { false }
 
 /// Options describing the ev-loop.
 ///
@@ -1283,6 +2121,31 @@ pub struct EvLoop {
     #[serde(default = "ev_loop__enable")]
     #[serde(skip_serializing_if = "ev_loop__enable__skip")]
     pub enable: bool,
+
+    /// Imaginary modes that are known to be spurious (e.g. artifacts of a supercell that
+    /// is too small), which should not prevent the ev-loop from finishing.
+    ///
+    /// Matching modes are classified as "ignored" and are not chased.  Once a mode has been
+    /// ignored, it continues to be ignored in later iterations as long as it can be tracked
+    /// by eigenvector overlap, even if its frequency drifts out of tolerance.  (this tracking
+    /// is saved in the ev-loop checkpoint, so it survives `--resume`)
+    ///
+    /// When some fingerprint specifies `characters`, the symmetry characters of each imaginary
+    /// mode are logged, so that they can be copied from there.  (`characters: []` matches
+    /// nothing, and can be used for this purpose)  Computing the characters requires
+    /// `phonons.symmetry-tolerance`.
+    ///
+    /// # Example:
+    ///
+    /// ```yaml
+    /// ev-loop:
+    ///   ignored-modes:
+    ///     - frequency: -12.0
+    ///       characters: [1, -1, -1, 1]
+    /// ```
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ignored_modes: Vec<IgnoredMode>,
 }
 fn ev_loop__min_positive_iter() -> u32 { 3 }
 fn ev_loop__max_iter() -> u32 { 15 }
@@ -1290,6 +2153,34 @@ fn ev_loop__fail() -> bool { true }
 fn ev_loop__enable() -> bool { true }
 fn ev_loop__enable__skip(&x: &bool) -> bool { x == ev_loop__enable() }
 
+/// Fingerprint of a mode for `ev-loop.ignored-modes`.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct IgnoredMode {
+    /// Frequency in cm^-1. (negative, since only imaginary modes can be ignored)
+    pub frequency: f64,
+
+    /// Maximum difference in frequency (cm^-1).
+    #[serde(default = "ignored_mode__frequency_tol")]
+    pub frequency_tol: f64,
+
+    /// The symmetry character `<v|g v>` of the mode under each operator `g` of the spacegroup,
+    /// in the order reported by spglib for the structure (using `phonons.symmetry-tolerance`).
+    /// For a nondegenerate mode, each of these is `1` or `-1`.
+    ///
+    /// `null` matches any mode with the right frequency.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub characters: Nullable<Vec<f64>>,
+
+    /// Maximum difference in each component of `characters`.
+    #[serde(default = "ignored_mode__character_tol")]
+    pub character_tol: f64,
+}
+fn ignored_mode__frequency_tol() -> f64 { 1.0 }
+fn ignored_mode__character_tol() -> f64 { 0.1 }
+
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 /// Masses by element.
@@ -1299,6 +2190,19 @@ fn ev_loop__enable__skip(&x: &bool) -> bool { x == ev_loop__enable() }
 /// When a `.structure` directory provides masses, those take precedence over this setting.
 pub struct Masses(pub HashMap<String, f64>);
 
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+/// Masses of individual sites, by index into the input structure.
+///
+/// This is for isotope substitution of individual atoms (e.g. a single deuterium among
+/// many hydrogens).  It is applied last, so that masses are resolved with the following
+/// precedence (highest first):
+///
+/// * `site-masses`
+/// * masses stored in a `.structure` directory
+/// * `masses`, or the default masses for each element
+pub struct SiteMasses(pub HashMap<usize, f64>);
+
 // --------------------------------------------------------
 
 #[derive(Serialize, Deserialize)]
@@ -1315,12 +2219,92 @@ fn snapshot__every() -> Nullable<u32> { Some(5) }
 
 // --------------------------------------------------------
 
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Relax {
+    /// Indices of layers whose atoms are held fixed during relaxation.
+    /// (e.g. a substrate beneath an adsorbate)
+    ///
+    /// Frozen atoms are left untouched by both CG and eigenvector chasing.
+    /// They are still included in the dynamical matrix, so the modes that get
+    /// reported (and chased) may involve them.
+    ///
+    /// Requires layers to be known (from `layer-search` or a `layers.yaml` input),
+    /// and cannot be used together with `parameters`, `lattice-relax-22`, or
+    /// `variable-cell`, since scaling the lattice would move the frozen atoms anyway.
+    #[serde(default)]
+    pub frozen_layers: Vec<usize>,
+
+    /// After CG, the forces on the (non-frozen) atoms are summed, and their net
+    /// magnitude is compared against this threshold (in eV/Å).
+    ///
+    /// A net force that survives relaxation usually means the potential is not
+    /// translationally invariant, which would silently corrupt the phonons.
+    /// The net torque about the centroid is reported alongside it.
+    ///
+    /// `null` disables the check.
+    #[serde(default = "relax__max_net_force")]
+    pub max_net_force: Nullable<f64>,
+
+    /// What to do when the net force exceeds `max-net-force`.
+    #[serde(default)]
+    pub on_net_force: RelaxOnNetForce,
+
+    /// After CG, detect the spacegroup of the structure and report how badly numerical
+    /// noise has broken it.  Optionally, the structure can then be symmetrized.
+    ///
+    /// `null` (the default) disables this.  See the type for documentation.
+    #[serde(default)]
+    pub symmetrize: Nullable<RelaxSymmetrize>,
+}
+fn relax__max_net_force() -> Nullable<f64> { Some(1e-3) }
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct RelaxSymmetrize {
+    /// Tolerance (in Å) used by spglib to detect the spacegroup.
+    ///
+    /// Sites are matched up under each operator using three times this tolerance.
+    /// The structure must be primitive, since spglib would otherwise report pure
+    /// translations.
+    #[serde(default = "relax_symmetrize__tolerance")]
+    pub tolerance: f64,
+
+    /// If `true`, each site of the relaxed structure is replaced with its average over
+    /// the images of its symmetry star.  (the lattice is not modified)
+    ///
+    /// Otherwise, the symmetry breaking is only reported.
+    #[serde(default)]
+    pub project: bool,
+}
+fn relax_symmetrize__tolerance() -> f64 { 1e-3 }
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(rename_all="kebab-case")]
+pub enum RelaxOnNetForce {
+    /// Log a warning and continue.
+    Warn,
+    /// Complain loudly and exit with a nonzero exit code.
+    Fail,
+}
+impl Default for RelaxOnNetForce {
+    fn default() -> Self { RelaxOnNetForce::Warn }
+}
+
+// --------------------------------------------------------
+
 #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub struct Lammps {
+    /// Which axes of the processor grid may be divided between MPI processes.
+    ///
+    /// Default is `[true, true, true]`. See `ProcessorAxisMask`.
     #[serde(default = "Filled::default")]
-    pub processor_axis_mask: Filled<[bool; 3]>,
+    pub processor_axis_mask: Filled<ProcessorAxisMask>,
     #[serde(default = "Filled::default")]
     pub update_style: Filled<LammpsUpdateStyle>,
 }
@@ -1337,9 +2321,15 @@ pub enum LammpsUpdateStyle {
     /// (However, if an atom is not at the same image where LAMMPS would prefer to find it,
     /// then the optimization is defeated, and neighbor lists will end up being built
     /// every step...)
+    ///
+    /// Because of this, the number of neighbor list builds is counted, and a warning is
+    /// logged if they occur on more than `warn-rebuild-fraction` of all steps.
+    /// The totals are also recorded in `summary.json`.
     #[serde(rename_all="kebab-case")]
     Fast {
         sync_positions_every: u32,
+        #[serde(default = "lammps_update_style__fast__warn_rebuild_fraction")]
+        warn_rebuild_fraction: f64,
     },
     /// (Debug) Use a custom `run _ pre _ post _` to notify LAMMPS of updates.
     #[serde(rename_all="kebab-case")]
@@ -1350,6 +2340,63 @@ pub enum LammpsUpdateStyle {
 impl Default for LammpsUpdateStyle {
     fn default() -> Self { LammpsUpdateStyle::Safe }
 }
+fn lammps_update_style__fast__warn_rebuild_fraction() -> f64 { 0.5 }
+
+/// Either a list of three booleans or the string `auto`.
+///
+/// An axis marked `false` is never divided up between MPI processes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessorAxisMask {
+    Fixed([bool; 3]),
+    /// Don't divide axes that are marked as non-periodic in `parameters` (these are
+    /// usually mostly vacuum), or which are too short to be worth dividing.
+    ///
+    /// This is decided anew from the lattice each time LAMMPS is initialized.
+    Auto,
+}
+
+impl Default for ProcessorAxisMask {
+    fn default() -> Self { ProcessorAxisMask::Fixed([true; 3]) }
+}
+
+impl serde::Serialize for ProcessorAxisMask {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            ProcessorAxisMask::Fixed(mask) => mask.serialize(serializer),
+            ProcessorAxisMask::Auto => serializer.serialize_str("auto"),
+        }
+    }
+}
+
+// Manual impl, because #[derive(Deserialize)] on untagged enums discard
+// all error messages.
+impl<'de> de::Deserialize<'de> for ProcessorAxisMask {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MyVisitor;
+
+        impl<'de> de::Visitor<'de> for MyVisitor {
+            type Value = ProcessorAxisMask;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a list of three booleans or \"auto\"")
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                let mask = de::Deserialize::deserialize(de::value::SeqAccessDeserializer::new(seq))?;
+                Ok(ProcessorAxisMask::Fixed(mask))
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                match s {
+                    "auto" => Ok(ProcessorAxisMask::Auto),
+                    _ => Err(E::invalid_value(de::Unexpected::Str(s), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(MyVisitor)
+    }
+}
 
 // --------------------------------------------------------
 
@@ -1393,6 +2440,14 @@ impl Default for Snapshot {
     fn default() -> Self { from_empty_mapping().unwrap() }
 }
 
+impl Default for Relax {
+    fn default() -> Self { from_empty_mapping().unwrap() }
+}
+
+impl Default for Raman {
+    fn default() -> Self { from_empty_mapping().unwrap() }
+}
+
 #[test]
 fn test_defaults()
 {
@@ -1405,6 +2460,244 @@ fn test_defaults()
     let _ = AcousticSearch::default();
     let _ = Lammps::default();
     let _ = Snapshot::default();
+    let _ = Relax::default();
+}
+
+#[test]
+fn test_unused_keys_are_collected()
+{
+    use crate::YamlRead;
+
+    let yaml = "{normal: [0, 0, 1], threshold: 0.25, cuont: 2, thresold: 1.0}";
+    let (search, unused) = LayerSearch::from_reader_with_unused(yaml.as_bytes()).unwrap();
+    assert_eq!(search.threshold, 0.25);
+    assert_eq!(unused, vec!["cuont".to_string(), "thresold".to_string()]);
+
+    let err = LayerSearch::from_reader_strict(yaml.as_bytes()).unwrap_err();
+    let err = err.downcast::<crate::UnusedConfigKeysError>().unwrap();
+    assert_eq!(err.paths, unused);
+
+    // type errors are still reported as such
+    let yaml = "{normal: [0, 0, 1], threshold: lots, cuont: 2}";
+    let err = LayerSearch::from_reader_strict(yaml.as_bytes()).unwrap_err();
+    assert!(err.downcast::<crate::UnusedConfigKeysError>().is_err());
+}
+
+#[test]
+fn test_threading_forms()
+{
+    let parse = |s: &str| serde_yaml::from_str::<Threading>(s).unwrap();
+
+    assert_eq!(parse("lammps"), Threading::Lammps);
+    assert_eq!(parse("serial"), Threading::Serial);
+    assert_eq!(parse("rayon"), Threading::Rayon(RayonThreading { max_concurrent_displacements: None }));
+    assert_eq!(parse("rayon: {}"), Threading::Rayon(RayonThreading { max_concurrent_displacements: None }));
+    assert_eq!(
+        parse("rayon: {max-concurrent-displacements: 4}"),
+        Threading::Rayon(RayonThreading { max_concurrent_displacements: Some(4) }),
+    );
+    assert!(serde_yaml::from_str::<Threading>("threads").is_err());
+}
+
+#[test]
+fn test_omp_threads_round_trip()
+{
+    let pot: LammpsPotentialKind = serde_yaml::from_str("airebo: {omp-threads: 8}").unwrap();
+    match &pot {
+        LammpsPotentialKind::Airebo(cfg) => assert_eq!(cfg.omp_threads, Some(8)),
+        _ => panic!("wrong potential: {:?}", pot),
+    }
+    let text = serde_yaml::to_string(&pot).unwrap();
+    assert_eq!(serde_yaml::from_str::<LammpsPotentialKind>(&text).unwrap(), pot);
+
+    // omitted when not set
+    let pot: LammpsPotentialKind = serde_yaml::from_str("rebo: {}").unwrap();
+    assert!(!serde_yaml::to_string(&pot).unwrap().contains("omp-threads"));
+}
+
+#[test]
+fn test_scalable_range_initial_value()
+{
+    let parse = |s: &str| serde_yaml::from_str::<ScalableRange>(s).unwrap();
+
+    let search = parse("{range: [2.0, 4.0]}");
+    assert_eq!(search, ScalableRange::Search { range: (2.0, 4.0), guess: None });
+    assert_eq!(search.initial_value(None), 3.0);
+    assert_eq!(search.initial_value(Some(3.4)), 3.4);
+
+    let search = parse("{range: [2.0, 4.0], guess: 2.5}");
+    assert_eq!(search.initial_value(None), 2.5);
+    assert_eq!(search.initial_value(Some(3.4)), 2.5);
+
+    assert_eq!(parse("{value: 1.5}").initial_value(Some(3.4)), 1.5);
+}
+
+#[test]
+fn test_eigensolver_which_forms()
+{
+    let parse = |s: &str| serde_yaml::from_str::<PhononEigensolver>(s).unwrap();
+
+    let sparse_seeded = |which, seed| PhononEigensolver::Sparse {
+        shift_invert_attempts: phonon_eigen_solver__sparse__shift_invert_attempts(),
+        how_many: phonon_eigen_solver__sparse__how_many(),
+        which,
+        seed,
+    };
+    let sparse = |which| sparse_seeded(which, None);
+    assert_eq!(parse("sparse: {}"), sparse(EigensolverWhich::MostNegative));
+    assert_eq!(parse("sparse: {which: largest}"), sparse(EigensolverWhich::Largest));
+    assert_eq!(parse("sparse: {which: {near-shift: 1500.0}}"), sparse(EigensolverWhich::NearShift(1500.0)));
+    assert_eq!(parse("sparse: {seed: 42}"), sparse_seeded(EigensolverWhich::MostNegative, Some(42)));
+}
+
+#[test]
+fn test_raman_polarization_forms()
+{
+    let parse = |s: &str| serde_yaml::from_str::<RamanPolarization>(s).unwrap();
+
+    assert_eq!(parse("average"), RamanPolarization::Average);
+    assert_eq!(parse("backscatter-z"), RamanPolarization::BackscatterZ);
+    assert_eq!(
+        parse("polarized: {incident: [0, 0, 2], scattered: [1, 0, 0]}"),
+        RamanPolarization::Polarized { incident: [0.0, 0.0, 2.0], scattered: [1.0, 0.0, 0.0] },
+    );
+    assert_eq!(Raman::default().polarization, RamanPolarization::Average);
+}
+
+#[test]
+fn test_site_masses_form()
+{
+    let masses: SiteMasses = serde_yaml::from_str("{3: 2.014, 10: 13.003}").unwrap();
+    assert_eq!(masses.0.len(), 2);
+    assert_eq!(masses.0[&3], 2.014);
+    assert_eq!(masses.0[&10], 13.003);
+
+    // (the settings are also written as JSON, where keys must be strings)
+    let json = serde_json::to_string(&masses).unwrap();
+    assert_eq!(serde_json::from_str::<SiteMasses>(&json).unwrap(), masses);
+}
+
+#[test]
+fn test_symmetry_tolerance_forms()
+{
+    let parse = |s: &str| serde_yaml::from_str::<SymmetryTolerance>(s).unwrap();
+
+    assert_eq!(parse("1e-3"), SymmetryTolerance::Fixed(1e-3));
+    assert_eq!(parse("0"), SymmetryTolerance::Fixed(0.0));
+    assert_eq!(parse("auto"), SymmetryTolerance::Auto);
+    assert!(serde_yaml::from_str::<SymmetryTolerance>("automatic").is_err());
+
+    let json = serde_json::to_string(&SymmetryTolerance::Auto).unwrap();
+    assert_eq!(serde_json::from_str::<SymmetryTolerance>(&json).unwrap(), SymmetryTolerance::Auto);
+}
+
+#[test]
+fn test_cg_alpha_guess_forms()
+{
+    let parse = |s: &str| serde_yaml::from_str::<CgAlphaGuess>(s).unwrap();
+
+    assert_eq!(parse("previous"), CgAlphaGuess::Previous);
+    assert_eq!(parse("quadratic"), CgAlphaGuess::Quadratic);
+    assert_eq!(parse("{scaled: 1.5}"), CgAlphaGuess::Scaled(1.5));
+    assert!(serde_yaml::from_str::<CgAlphaGuess>("scaled").is_err());
+}
+
+#[test]
+fn test_phonon_disp_finder_phonopy_forms()
+{
+    let parse = |s: &str| serde_yaml::from_str::<PhononDispFinder>(s).unwrap();
+
+    assert_eq!(
+        parse("phonopy: {}"),
+        PhononDispFinder::Phonopy { diag: true, retries: 0, retry_delay: 5.0 },
+    );
+    assert_eq!(
+        parse("phonopy: {diag: false, retries: 3, retry-delay: 0.5}"),
+        PhononDispFinder::Phonopy { diag: false, retries: 3, retry_delay: 0.5 },
+    );
+}
+
+#[test]
+fn test_lammps_update_style_forms()
+{
+    let parse = |s: &str| serde_yaml::from_str::<LammpsUpdateStyle>(s).unwrap();
+
+    assert_eq!(parse("safe"), LammpsUpdateStyle::Safe);
+    assert_eq!(
+        parse("fast: {sync-positions-every: 1}"),
+        LammpsUpdateStyle::Fast { sync_positions_every: 1, warn_rebuild_fraction: 0.5 },
+    );
+    assert_eq!(
+        parse("fast: {sync-positions-every: 10, warn-rebuild-fraction: 0.1}"),
+        LammpsUpdateStyle::Fast { sync_positions_every: 10, warn_rebuild_fraction: 0.1 },
+    );
+}
+
+#[test]
+fn test_processor_axis_mask_forms()
+{
+    let parse = |s: &str| serde_yaml::from_str::<ProcessorAxisMask>(s).unwrap();
+
+    assert_eq!(parse("[true, true, false]"), ProcessorAxisMask::Fixed([true, true, false]));
+    assert_eq!(parse("auto"), ProcessorAxisMask::Auto);
+    assert!(serde_yaml::from_str::<ProcessorAxisMask>("[true, true]").is_err());
+    assert!(serde_yaml::from_str::<ProcessorAxisMask>("automatic").is_err());
+
+    let json = serde_json::to_string(&ProcessorAxisMask::Auto).unwrap();
+    assert_eq!(serde_json::from_str::<ProcessorAxisMask>(&json).unwrap(), ProcessorAxisMask::Auto);
+}
+
+#[test]
+fn test_potential_enabled_forms()
+{
+    let parse = |s: &str| serde_yaml::from_str::<Potential>(s).unwrap();
+    let morse = PotentialKind::Morse(PotentialMorse { pairs: vec![] });
+    let entry = |kind: &PotentialKind, enabled| PotentialEntry { kind: kind.clone(), enabled };
+
+    assert_eq!(parse("test-func-zero"), Potential(vec![entry(&PotentialKind::TestZero, true)]));
+    assert_eq!(parse("morse: {pairs: []}"), Potential(vec![entry(&morse, true)]));
+    assert_eq!(parse("{morse: {pairs: []}, enabled: false}"), Potential(vec![entry(&morse, false)]));
+    assert_eq!(
+        parse("[test-func-zero, {enabled: true, morse: {pairs: []}}, {test-func-zero: ~, enabled: false}]"),
+        Potential(vec![
+            entry(&PotentialKind::TestZero, true),
+            entry(&morse, true),
+            entry(&PotentialKind::TestZero, false),
+        ]),
+    );
+    assert!(serde_yaml::from_str::<Potential>("{enabled: false}").is_err());
+    assert!(serde_yaml::from_str::<Potential>("{morse: {pairs: []}, test-func-zero: ~}").is_err());
+    assert!(serde_yaml::from_str::<Potential>("{morse: {pairs: []}, enabled: false, enabled: true}").is_err());
+
+    let potential = parse("[test-func-zero, {morse: {pairs: []}, enabled: false}]");
+    assert_eq!(potential.enabled_kinds().collect::<Vec<_>>(), vec![&PotentialKind::TestZero]);
+
+    // disabled entries survive a round trip
+    let yaml = serde_yaml::to_string(&potential).unwrap();
+    assert_eq!(parse(&yaml), potential);
+
+    // at least one term must remain enabled
+    let validated = |s: &str| serde_yaml::from_str::<ValidatedPotential>(s);
+    assert!(validated("[test-func-zero, {morse: {pairs: []}, enabled: false}]").is_ok());
+    let err = validated("[{test-func-zero: ~, enabled: false}, {morse: {pairs: []}, enabled: false}]").unwrap_err();
+    assert!(err.to_string().contains("enabled: false"), "{}", err);
+}
+
+#[test]
+fn test_potential_unused_keys_are_collected()
+{
+    use crate::YamlRead;
+
+    // typos inside of a potential must be reported, whether or not the entry has `enabled`
+    let yaml = "[{morse: {pairs: [], paris: []}}, {enabled: false, rebo-nonreactive: {params: brenner, prams: 1}}]";
+    let (_, unused) = ValidatedPotential::from_reader_with_unused(yaml.as_bytes()).unwrap();
+    assert_eq!(unused.len(), 2, "{:?}", unused);
+    assert!(unused[0].ends_with("paris"), "{:?}", unused);
+    assert!(unused[1].ends_with("prams"), "{:?}", unused);
+
+    let yaml = "morse: {pairs: [], paris: []}";
+    let (_, unused) = ValidatedPotential::from_reader_with_unused(yaml.as_bytes()).unwrap();
+    assert_eq!(unused.len(), 1, "{:?}", unused);
 }
 
 fn from_empty_mapping<T: for<'de> serde::Deserialize<'de>>() -> serde_yaml::Result<T> {
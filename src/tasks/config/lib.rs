@@ -36,7 +36,7 @@ extern crate rsp2_util_macros;
 use std::io::Read;
 use failure::Error;
 
-pub use monomorphize::YamlRead;
+pub use monomorphize::{YamlRead, UnusedConfigKeysError};
 #[macro_use]
 mod monomorphize {
     use super::*;
@@ -47,7 +47,31 @@ mod monomorphize {
         fn from_reader(mut r: impl Read) -> Result<Self, Error>
         { YamlRead::from_dyn_reader(&mut r) }
 
+        /// Parse, logging a warning for each unused key.
         fn from_dyn_reader(r: &mut dyn Read) -> Result<Self, Error> {
+            let (out, unused) = YamlRead::from_dyn_reader_with_unused(r)?;
+            for path in unused {
+                warn!("Unused config item (possible typo?): {}", path);
+            }
+            Ok(out)
+        }
+
+        /// Parse, also returning the paths of all unused keys (e.g. `ev-loop.enabel`)
+        /// instead of logging them.
+        fn from_reader_with_unused(mut r: impl Read) -> Result<(Self, Vec<String>), Error>
+        { YamlRead::from_dyn_reader_with_unused(&mut r) }
+
+        /// Parse, failing with an `UnusedConfigKeysError` that lists every unused key
+        /// if there are any.
+        ///
+        /// Type errors still take priority, and get the same error messages as usual.
+        fn from_reader_strict(mut r: impl Read) -> Result<Self, Error> {
+            let (out, unused) = YamlRead::from_dyn_reader_with_unused(&mut r)?;
+            UnusedConfigKeysError::check(unused)?;
+            Ok(out)
+        }
+
+        fn from_dyn_reader_with_unused(r: &mut dyn Read) -> Result<(Self, Vec<String>), Error> {
             // serde_ignored needs a Deserializer.
             // unlike serde_json, serde_yaml doesn't seem to expose a Deserializer that is
             // directly constructable from a Read... but it does impl Deserialize for Value.
@@ -59,12 +83,13 @@ mod monomorphize {
             let mut s = String::new();
             r.read_to_string(&mut s)?;
 
-            // try deserializing from Value, printing warnings on unused keys.
+            // try deserializing from Value, collecting unused keys.
             // (if value_from_dyn_reader fails, that error should be fine)
             let value = value_from_str(&s)?;
 
-            match Self::__serde_ignored__from_value(value) {
-                Ok(out) => Ok(out),
+            let mut unused = vec![];
+            match Self::__serde_ignored__from_value(value, &mut unused) {
+                Ok(out) => Ok((out, unused)),
                 Err(_) => {
                     // That error message was surely garbage. Let's re-parse again
                     // from the string, without serde_ignored:
@@ -77,18 +102,49 @@ mod monomorphize {
         // trait-provided function definitions seem to be lazily monomorphized, so we
         // must put the meat of what we need monomorphized directly into the impls
         #[doc(hidden)]
-        fn __serde_ignored__from_value(value: serde_yaml::Value) -> Result<Self, Error>;
+        fn __serde_ignored__from_value(value: serde_yaml::Value, unused: &mut Vec<String>) -> Result<Self, Error>;
         #[doc(hidden)]
         fn __serde_yaml__from_str(s: &str) -> Result<Self, Error>;
     }
 
+    /// Produced in strict mode when a config contains keys that were not recognized.
+    #[derive(Debug, Clone)]
+    pub struct UnusedConfigKeysError {
+        pub paths: Vec<String>,
+    }
+
+    impl UnusedConfigKeysError {
+        /// Fails if `paths` is nonempty.
+        pub fn check(paths: Vec<String>) -> Result<(), UnusedConfigKeysError> {
+            match paths.is_empty() {
+                true => Ok(()),
+                false => Err(UnusedConfigKeysError { paths }),
+            }
+        }
+    }
+
+    impl std::fmt::Display for UnusedConfigKeysError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{} unrecognized config item(s) (possible typos?):", self.paths.len())?;
+            for path in &self.paths {
+                write!(f, "\n  {}", path)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl failure::Fail for UnusedConfigKeysError {}
+
     macro_rules! derive_yaml_read {
         ($Type:ty) => {
             impl $crate::YamlRead for $Type {
-                fn __serde_ignored__from_value(value: serde_yaml::Value) -> Result<$Type, Error> {
+                fn __serde_ignored__from_value(
+                    value: serde_yaml::Value,
+                    unused: &mut Vec<String>,
+                ) -> Result<$Type, Error> {
                     serde_ignored::deserialize(
                         value,
-                        |path| warn!("Unused config item (possible typo?): {}", path),
+                        |path| unused.push(path.to_string()),
                     ).map_err(Into::into)
                 }
 
@@ -23,13 +23,69 @@ impl Settings {
             &mut self.lammps,
             &mut self._deprecated_lammps_settings,
         );
+        check_lammps(&self.lammps)?;
         fix_version(&mut self.version)?;
+        check_threading(&self.threading, &self.potential)?;
+        check_omp_threads(&self.potential)?;
 
         if let Some(phonons) = &mut self.phonons {
             fix_deprecated_eigensolver(&mut phonons.eigensolver);
             check_phonons(&phonons, &self.potential)?;
+            check_eigensolver_which(&phonons.eigensolver, &self.ev_loop);
         }
 
+        if let Some(gruneisen) = &self.gruneisen {
+            check_gruneisen(gruneisen, self.phonons.as_ref())?;
+        }
+        if let Some(thermal) = &self.thermal {
+            check_thermal(thermal, self.phonons.as_ref())?;
+        }
+        if let Some(ir) = &self.ir {
+            check_ir(ir)?;
+        }
+        if let Some(peak_assignment) = &self.peak_assignment {
+            for &peak in &peak_assignment.reference {
+                if !peak.is_finite() {
+                    bail!("peak-assignment.reference must be finite (got {}).", peak);
+                }
+            }
+        }
+
+        check_frequency_window(self.frequency_window.as_ref(), "frequency-window")?;
+        if let Some(Phonons { eigensolver: PhononEigensolver::Dense { frequency_window }, .. }) = &self.phonons {
+            check_frequency_window(frequency_window.as_ref(), "phonons.eigensolver.dense.frequency-window")?;
+        }
+
+        if let Some(max) = self.acoustic_search.max_acoustic_frequency {
+            if !(max >= 0.0) {
+                bail!("acoustic-search.max-acoustic-frequency must be non-negative (got {}).", max);
+            }
+        }
+
+        if let Some(UnfoldBands::Allen { translation_tolerance }) = self.unfold_bands {
+            if !(translation_tolerance > 0.0) {
+                bail!("unfold-bands.allen.translation-tolerance must be positive (got {}).", translation_tolerance);
+            }
+        }
+
+        check_cg(&self.cg, "cg")?;
+        if let EigenvectorChase::Cg(cg) = &self.ev_chase {
+            check_cg(cg, "ev-chase.cg")?;
+        }
+
+        fix_raman_polarization(&mut self.raman.polarization)?;
+
+        if let Some(SiteMasses(map)) = &self.site_masses {
+            check_site_masses(map)?;
+        }
+
+        check_relax(
+            &self.relax,
+            self.parameters.as_ref(),
+            self.lattice_relax_22.as_ref(),
+            self.variable_cell.as_ref(),
+        )?;
+
         Ok(ValidatedSettings(self))
     }
 }
@@ -40,6 +96,7 @@ impl EnergyPlotSettings {
             &mut self.lammps,
             &mut self._deprecated_lammps_settings,
         );
+        check_lammps(&self.lammps)?;
         fix_version(&mut self.version)?;
 
         Ok(ValidatedEnergyPlotSettings(self))
@@ -52,51 +109,55 @@ impl Potential {
 
         // Replace all deprecated potentials.
         let potentials: Vec<_> = {
-            self.into_vec().into_iter().map(|pot| match pot {
-                PotentialKind::OldLammpsRebo(inner) => {
-                    found_deprecated = true;
-                    PotentialKind::Lammps(LammpsPotentialKind::Rebo(inner))
-                },
-
-                PotentialKind::OldLammpsAirebo(inner) => {
-                    found_deprecated = true;
-                    PotentialKind::Lammps(LammpsPotentialKind::Airebo(inner))
-                },
-
-                PotentialKind::OldLammpsKolmogorovCrespiZ(inner) => {
-                    found_deprecated = true;
-                    PotentialKind::Lammps(LammpsPotentialKind::KolmogorovCrespiZ(inner))
-                },
-
-                PotentialKind::OldLammpsKolmogorovCrespiFull(inner) => {
-                    found_deprecated = true;
-                    PotentialKind::Lammps(LammpsPotentialKind::KolmogorovCrespiFull(inner))
-                },
-
-                PotentialKind::OldReboNew(inner) => {
-                    found_deprecated = true;
-                    PotentialKind::ReboNonreactive(inner)
-                },
-
-                PotentialKind::OldKolmogorovCrespiZ(inner) => {
-                    found_deprecated = true;
-
-                    let OldPotentialKolmogorovCrespiZ {
-                        cutoff_begin, cutoff_transition_dist, skin_depth, skin_check_frequency,
-                    } = inner;
-                    PotentialKind::KolmogorovCrespi(PotentialKolmogorovCrespi {
-                        cutoff_begin, cutoff_transition_dist, skin_depth, skin_check_frequency,
-                        normals: KolmogorovCrespiNormals::Z {},
-                        params: KolmogorovCrespiParams::Original,
-                    })
+            self.into_vec().into_iter().map(|PotentialEntry { kind, enabled }| PotentialEntry {
+                enabled,
+                kind: match kind {
+                    PotentialKind::OldLammpsRebo(inner) => {
+                        found_deprecated = true;
+                        PotentialKind::Lammps(LammpsPotentialKind::Rebo(inner))
+                    },
+
+                    PotentialKind::OldLammpsAirebo(inner) => {
+                        found_deprecated = true;
+                        PotentialKind::Lammps(LammpsPotentialKind::Airebo(inner))
+                    },
+
+                    PotentialKind::OldLammpsKolmogorovCrespiZ(inner) => {
+                        found_deprecated = true;
+                        PotentialKind::Lammps(LammpsPotentialKind::KolmogorovCrespiZ(inner))
+                    },
+
+                    PotentialKind::OldLammpsKolmogorovCrespiFull(inner) => {
+                        found_deprecated = true;
+                        PotentialKind::Lammps(LammpsPotentialKind::KolmogorovCrespiFull(inner))
+                    },
+
+                    PotentialKind::OldReboNew(inner) => {
+                        found_deprecated = true;
+                        PotentialKind::ReboNonreactive(inner)
+                    },
+
+                    PotentialKind::OldKolmogorovCrespiZ(inner) => {
+                        found_deprecated = true;
+
+                        let OldPotentialKolmogorovCrespiZ {
+                            cutoff_begin, cutoff_transition_dist, skin_depth, skin_check_frequency,
+                        } = inner;
+                        PotentialKind::KolmogorovCrespi(PotentialKolmogorovCrespi {
+                            cutoff_begin, cutoff_transition_dist, skin_depth, skin_check_frequency,
+                            normals: KolmogorovCrespiNormals::Z {},
+                            params: KolmogorovCrespiParams::Original,
+                        })
+                    },
+
+                    pot@PotentialKind::ReboNonreactive(..) |
+                    pot@PotentialKind::KolmogorovCrespi(..) |
+                    pot@PotentialKind::Morse(..) |
+                    pot@PotentialKind::DftbPlus(..) |
+                    pot@PotentialKind::Lammps(..) |
+                    pot@PotentialKind::TestZero |
+                    pot@PotentialKind::TestChainify => pot,
                 },
-
-                pot@PotentialKind::ReboNonreactive(..) |
-                pot@PotentialKind::KolmogorovCrespi(..) |
-                pot@PotentialKind::DftbPlus(..) |
-                pot@PotentialKind::Lammps(..) |
-                pot@PotentialKind::TestZero |
-                pot@PotentialKind::TestChainify => pot,
             }).collect()
         };
 
@@ -113,15 +174,23 @@ impl Potential {
             ", ::serde_yaml::to_string(&yaml).expect("should not fail"));
         }
 
-        if out.as_slice().iter().filter(|x| matches!(PotentialKind::DftbPlus(_), x)).count() > 1 {
+        if !out.as_slice().is_empty() && out.enabled_kinds().next().is_none() {
+            bail!("\
+                Every term of the potential has `enabled: false`! \
+                At least one term must be enabled.\
+            ");
+        }
+
+        if out.enabled_kinds().filter(|x| matches!(PotentialKind::DftbPlus(_), x)).count() > 1 {
             bail!("The `dftb+` potential may only be listed at most once!");
         }
 
-        if out.as_slice().iter().filter(|x| matches!(PotentialKind::Lammps(_), x)).count() > 1 {
+        if out.enabled_kinds().filter(|x| matches!(PotentialKind::Lammps(_), x)).count() > 1 {
             bail!("The `lammps` potential may only be listed at most once!");
         }
 
-        if matches!([PotentialKind::KolmogorovCrespi(_)], out.as_slice()) {
+        let enabled_kinds: Vec<_> = out.enabled_kinds().collect();
+        if matches!([PotentialKind::KolmogorovCrespi(_)], &enabled_kinds[..]) {
             warn!("\
                 You are using the Kolmogorov/Crespi potential alone, with no intralayer term. \
                 This is a bit unusual; did you mean to add a REBO term? (e.g. `nonreactive-rebo`)\
@@ -155,17 +224,32 @@ fn fix_deprecated_eigensolver(it: &mut PhononEigensolver) {
         PhononEigensolver::Phonopy(AlwaysFail(never, _)) => match never {},
         PhononEigensolver::Rsp2 { dense: true, .. } => {
             warn!("`phonon.eigensolver: rsp2 {{ dense: true }}` is deprecated. Use the `dense` eigensolver.");
-            *it = PhononEigensolver::Dense {};
+            *it = PhononEigensolver::Dense { frequency_window: None };
         },
-        PhononEigensolver::Rsp2 { dense: false, shift_invert_attempts, how_many } => {
+        PhononEigensolver::Rsp2 { dense: false, shift_invert_attempts, how_many, which, seed } => {
             warn!("`phonon.eigensolver: rsp2 {{ dense: false }}` is deprecated. Use the `sparse` eigensolver.");
-            *it = PhononEigensolver::Sparse { shift_invert_attempts, how_many };
+            *it = PhononEigensolver::Sparse { shift_invert_attempts, how_many, which, seed };
         },
         PhononEigensolver::Dense { .. } => {},
         PhononEigensolver::Sparse { .. } => {},
     };
 }
 
+fn check_eigensolver_which(eigensolver: &PhononEigensolver, ev_loop: &EvLoop) {
+    match eigensolver {
+        PhononEigensolver::Sparse { which: EigensolverWhich::MostNegative, .. } => {},
+        PhononEigensolver::Sparse { .. } => {
+            if ev_loop.enable {
+                warn!("\
+                    phonons.eigensolver.sparse.which is not most-negative, so the \
+                    ev-loop will not be able to find negative modes.\
+                ");
+            }
+        },
+        _ => {},
+    }
+}
+
 fn fill_lammps_from_deprecated(
     new: &mut Lammps,
     old: &mut DeprecatedLammpsSettings,
@@ -177,9 +261,9 @@ fn fill_lammps_from_deprecated(
             `lammps-processor-axis-mask` is deprecated. \
             It now lives at `lammps.processor-axis-mask`.\
         ");
-        processor_axis_mask.0.get_or_insert(value);
+        processor_axis_mask.0.get_or_insert(ProcessorAxisMask::Fixed(value));
     }
-    processor_axis_mask.0.get_or_insert([true; 3]);
+    processor_axis_mask.0.get_or_insert_with(Default::default);
 
     if let Some(value) = old.lammps_update_style.take() {
         warn!("\
@@ -191,11 +275,20 @@ fn fill_lammps_from_deprecated(
     update_style.0.get_or_insert_with(Default::default);
 }
 
+fn check_lammps(lammps: &Lammps) -> Result<(), Error> {
+    if let Some(LammpsUpdateStyle::Fast { warn_rebuild_fraction, .. }) = lammps.update_style.0 {
+        if !(0.0 <= warn_rebuild_fraction && warn_rebuild_fraction <= 1.0) {
+            bail!("lammps.update-style.fast.warn-rebuild-fraction must be between 0 and 1 (got {}).", warn_rebuild_fraction);
+        }
+    }
+    Ok(())
+}
+
 fn check_phonons(phonons: &Phonons, potential: &ValidatedPotential) -> Result<(), Error> {
-    let ValidatedPotential(Potential(kinds)) = potential;
+    let ValidatedPotential(potential) = potential;
 
     if phonons.analytic_hessian {
-        for kind in kinds {
+        for kind in potential.enabled_kinds() {
             match kind {
                 PotentialKind::OldKolmogorovCrespiZ(_) => {},
                 _ => bail!{"The chosen potential does not support analytic-hessian mode."},
@@ -211,5 +304,222 @@ fn check_phonons(phonons: &Phonons, potential: &ValidatedPotential) -> Result<()
         }
     }
 
+    if let PhononDispFinder::Phonopy { retry_delay, .. } = phonons.disp_finder {
+        if !(retry_delay >= 0.0 && retry_delay.is_finite()) {
+            bail!("phonons.disp-finder.phonopy.retry-delay must be non-negative.");
+        }
+    }
+
+    if !phonons.displacement_distance_by_element.is_empty() {
+        match phonons.disp_finder {
+            PhononDispFinder::Rsp2 { .. } => {},
+            _ => bail!("phonons.displacement-distance-by-element requires the rsp2 disp-finder."),
+        }
+        for (element, &distance) in &phonons.displacement_distance_by_element {
+            if let Err(e) = rsp2_structure::Element::from_symbol(element) {
+                bail!("phonons.displacement-distance-by-element: {}", e);
+            }
+            if !(distance > 0.0) {
+                bail!("phonons.displacement-distance-by-element: distance for {} must be positive.", element);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn check_threading(threading: &Threading, potential: &ValidatedPotential) -> Result<(), Error> {
+    let ValidatedPotential(potential) = potential;
+
+    if let Threading::Rayon(RayonThreading { max_concurrent_displacements: Some(n) }) = *threading {
+        if n == 0 {
+            bail!("threading.rayon.max-concurrent-displacements must be positive.");
+        }
+        // Only one LAMMPS instance can exist at a time. (see INSTANCE_LOCK in rsp2_lammps_wrap)
+        let uses_lammps = potential.enabled_kinds().any(|kind| match kind {
+            PotentialKind::Lammps(_) => true,
+            _ => false,
+        });
+        if n > 1 && uses_lammps {
+            bail!("threading.rayon.max-concurrent-displacements cannot exceed 1 with a LAMMPS potential.");
+        }
+    }
+
+    Ok(())
+}
+
+fn check_omp_threads(potential: &ValidatedPotential) -> Result<(), Error> {
+    let ValidatedPotential(potential) = potential;
+
+    for kind in potential.enabled_kinds() {
+        let (name, omp, omp_threads) = match kind {
+            PotentialKind::Lammps(LammpsPotentialKind::Rebo(cfg)) => ("rebo", cfg.omp, cfg.omp_threads),
+            PotentialKind::Lammps(LammpsPotentialKind::Airebo(cfg)) => ("airebo", cfg.omp, cfg.omp_threads),
+            _ => continue,
+        };
+        if let Some(threads) = omp_threads {
+            if threads == 0 {
+                bail!("{}.omp-threads must be positive.", name);
+            }
+            if omp == Some(false) {
+                bail!("{}.omp-threads requires omp to be enabled.", name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn fix_raman_polarization(polarization: &mut RamanPolarization) -> Result<(), Error> {
+    // deviation from unit length that is tolerated without comment
+    const NORM_TOL: f64 = 1e-6;
+
+    if let RamanPolarization::Polarized { incident, scattered } = polarization {
+        for (name, vector) in vec![("incident", incident), ("scattered", scattered)] {
+            let norm = vector.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if !(norm > 0.0) {
+                bail!("raman.polarization.polarized.{} must be nonzero.", name);
+            }
+            if (norm - 1.0).abs() > NORM_TOL {
+                warn!("raman.polarization.polarized.{} is not a unit vector (norm {}); normalizing it.", name, norm);
+            }
+            for x in vector.iter_mut() {
+                *x /= norm;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_frequency_window(window: Option<&FrequencyWindow>, name: &str) -> Result<(), Error> {
+    if let Some(&FrequencyWindow { min: Some(min), max: Some(max) }) = window {
+        if !(min <= max) {
+            bail!("{}.min ({}) must not exceed {}.max ({}).", name, min, name, max);
+        }
+    }
+    Ok(())
+}
+
+fn check_cg(cg: &Cg, name: &str) -> Result<(), Error> {
+    if let CgAlphaGuess::Scaled(factor) = cg.alpha_guess {
+        if !(factor > 0.0) {
+            bail!("{}.alpha-guess.scaled must be positive (got {}).", name, factor);
+        }
+    }
+    Ok(())
+}
+
+fn check_site_masses(map: &HashMap<usize, f64>) -> Result<(), Error> {
+    // (indices can only be checked once the structure is read)
+    for (&index, &mass) in map {
+        if !(mass > 0.0 && mass.is_finite()) {
+            bail!("site-masses.{} must be positive.", index);
+        }
+    }
+    Ok(())
+}
+
+fn check_gruneisen(gruneisen: &Gruneisen, phonons: Option<&Phonons>) -> Result<(), Error> {
+    match phonons {
+        None => bail!("gruneisen requires the phonons section."),
+        Some(Phonons { eigensolver: PhononEigensolver::Dense { .. }, .. }) => {},
+        Some(_) => bail!("gruneisen requires phonons.eigensolver to be dense."),
+    }
+    if !(0.0 < gruneisen.volume_step && gruneisen.volume_step < 1.0) {
+        bail!("gruneisen.volume-step must be between 0 and 1.");
+    }
+    Ok(())
+}
+
+fn check_thermal(thermal: &Thermal, phonons: Option<&Phonons>) -> Result<(), Error> {
+    // (every mode must be found)
+    match phonons {
+        None => bail!("thermal requires the phonons section."),
+        Some(Phonons { eigensolver: PhononEigensolver::Dense { frequency_window: None }, .. }) => {},
+        Some(_) => bail!("thermal requires phonons.eigensolver to be dense, with no frequency-window."),
+    }
+    for &temperature in &thermal.temperatures {
+        if !(temperature >= 0.0 && temperature.is_finite()) {
+            bail!("thermal.temperatures must be non-negative (got {}).", temperature);
+        }
+    }
+    Ok(())
+}
+
+fn check_ir(ir: &Ir) -> Result<(), Error> {
+    for (element, &charge) in &ir.charges {
+        if let Err(e) = rsp2_structure::Element::from_symbol(element) {
+            bail!("ir.charges: {}", e);
+        }
+        if !charge.is_finite() {
+            bail!("ir.charges: charge for {} must be finite (got {}).", element, charge);
+        }
+    }
     Ok(())
 }
+
+fn check_relax(
+    relax: &Relax,
+    parameters: Option<&Parameters>,
+    lattice_relax_22: Option<&LatticeRelax>,
+    variable_cell: Option<&VariableCell>,
+) -> Result<(), Error> {
+    let relaxes_lattice = parameters.is_some() || lattice_relax_22.is_some() || variable_cell.is_some();
+    if !relax.frozen_layers.is_empty() && relaxes_lattice {
+        bail!("relax.frozen-layers cannot be used with `parameters`, `lattice-relax-22`, or `variable-cell`.");
+    }
+    if lattice_relax_22.is_some() && variable_cell.is_some() {
+        bail!("`lattice-relax-22` and `variable-cell` cannot be used together.");
+    }
+    if let Some(max_net_force) = relax.max_net_force {
+        if !(max_net_force >= 0.0) {
+            bail!("relax.max-net-force must be non-negative.");
+        }
+    }
+    if let Some(symmetrize) = &relax.symmetrize {
+        if !(symmetrize.tolerance > 0.0) {
+            bail!("relax.symmetrize.tolerance must be positive (got {}).", symmetrize.tolerance);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_displacement_distance_by_element()
+{
+    let potential: ValidatedPotential = serde_yaml::from_str("test-func-zero").unwrap();
+    let check = |by_element: &str| {
+        let phonons: Phonons = serde_yaml::from_str(&format!(
+            "{{supercell: {{dim: [1, 1, 1]}}, symmetry-tolerance: 1e-3, displacement-distance: 0.01, \
+            disp-finder: {{rsp2: {{}}}}, displacement-distance-by-element: {}}}",
+            by_element,
+        )).unwrap();
+        check_phonons(&phonons, &potential).map(|()| phonons)
+    };
+
+    let phonons = check("{H: 0.005, C: 0.02}").unwrap();
+    let symbols: Vec<_> = phonons.displacement_distance_by_element.keys().cloned().collect();
+    assert_eq!(symbols, vec!["C".to_string(), "H".to_string()]);
+
+    assert!(check("{Xx: 0.005}").is_err());
+    assert!(check("{H: -0.005}").is_err());
+}
+
+#[test]
+fn test_cg_alpha_guess() {
+    let check = |alpha_guess: &str| {
+        let chase: EigenvectorChase = serde_yaml::from_str(&format!(
+            "{{cg: {{stop-condition: {{iterations: 100}}, alpha-guess: {}}}}}",
+            alpha_guess,
+        )).unwrap();
+        match chase {
+            EigenvectorChase::Cg(cg) => check_cg(&cg, "ev-chase.cg"),
+            EigenvectorChase::OneByOne => unreachable!(),
+        }
+    };
+
+    assert!(check("previous").is_ok());
+    assert!(check("{scaled: 1.5}").is_ok());
+    assert!(check("{scaled: 0}").is_err());
+    assert!(check("{scaled: -1.5}").is_err());
+}
@@ -15,11 +15,12 @@
 use crate::FailResult;
 use crate::VersionInfo;
 use crate::cmd::trial::{TrialDir, NewTrialDirArgs};
-use crate::cmd::{StructureFileType, DidEvChasing, StopAfter};
+use crate::cmd::{StructureFileType, DidEvChasing, StopAfter, EvLoopStart};
 use crate::traits::{Save, Load};
-use crate::ui::logging::{init_global_logger, SetGlobalLogfile};
+use crate::ui::logging::{init_global_logger, SetGlobalLogfile, GLOBAL_DIAGNOSTICS};
 use crate::ui::cfg_merging::ConfigSources;
 use crate::ui::cli_deserialize::CliDeserialize;
+use crate::ui::parse_mass_scale::parse_mass_scale;
 use crate::util::ext_traits::{ArgMatchesExt};
 use crate::filetypes::{StoredStructure, Eigensols};
 
@@ -216,6 +217,10 @@ impl CliDeserialize for NewTrialDirArgs {
         let app = app.args(&[
             arg!(*trial_dir [-o][--output]=OUTDIR "output trial directory"),
             arg!( force [-f][--force] "replace existing output directories"),
+            arg!( strict_config [--strict-config] "\
+                fail if the config contains any unrecognized keys, instead of \
+                warning about them.\
+            "),
         ]);
         ConfigArgs::_augment_clap_app(app)
     }
@@ -224,6 +229,7 @@ impl CliDeserialize for NewTrialDirArgs {
     { Ok(NewTrialDirArgs {
         config_sources: ConfigArgs::_resolve_args(m)?.0,
         err_if_existing: !m.is_present("force"),
+        strict_config: m.is_present("strict_config"),
         // FIXME factor out 'absolute()'
         trial_dir: PathDir::current_dir()?.as_path().join(m.expect_value_of("trial_dir")),
     })}
@@ -232,6 +238,7 @@ impl CliDeserialize for NewTrialDirArgs {
 // (not sure why `impl CliDeserialize for Option<StructureFileType>` isn't good enough
 //  but rustc says Option<_> doesn't impl CliDeserialize, even when it ought to be
 //  inferrable that the _ is StructureFileType)
+#[derive(Copy, Clone)]
 pub struct OptionalFileType(Option<StructureFileType>);
 
 impl CliDeserialize for OptionalFileType {
@@ -284,6 +291,30 @@ impl OptionalFileType {
     }
 }
 
+/// Factors from `--scale-mass`, for isotope substitution without editing the inputs.
+struct MassScaleArgs(Vec<(crate::meta::Element, f64)>);
+
+impl CliDeserialize for MassScaleArgs {
+    fn _augment_clap_app<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
+        app.args(&[
+            arg!(?scale_mass [--scale-mass]=SCALE... "\
+                multiply the mass of an element by a factor before computing phonons, \
+                e.g. 'H:2' to deuterate. May be given multiple times; factors for the \
+                same element are multiplied together. This also scales masses that were \
+                given explicitly (through the 'masses' or 'site-masses' config sections, \
+                or stored in a structure directory).\
+            "),
+        ])
+    }
+
+    fn _resolve_args(m: &clap::ArgMatches<'_>) -> FailResult<Self> {
+        Ok(MassScaleArgs(match m.values_of("scale_mass") {
+            Some(args) => args.map(parse_mass_scale).collect::<FailResult<_>>()?,
+            None => vec![],
+        }))
+    }
+}
+
 /// Used by entry points that have no trial directory, but where the user may wish to save
 /// log output to a specified path, at their choosing.
 pub struct AppendLog(pub AppendLogInner);
@@ -344,20 +375,96 @@ fn _rsp2_full_program(
             clap::App::new(bin_name)
                 .about("runs the full eigenvector loop of rsp2")
                 .args(&[
-                    arg!( input=STRUCTURE "input file for structure"),
+                    arg!(?input=STRUCTURE "input file for structure (not needed with --resume)"),
+                    arg!( no_trajectory [--no-trajectory] "\
+                        don't write relaxation.xyz, an animation of the structure \
+                        after each round of CG and eigenvector chasing.\
+                    "),
+                    arg!( resume [--resume] "\
+                        continue a killed run in the existing output directory from its \
+                        ev-loop-checkpoint.json.  The settings and structure recorded in the \
+                        directory are used; the config and input structure (if given) are ignored.\
+                    "),
+                    arg!( dry_run [--dry-run] "\
+                        validate the config and input structure, print a summary, and exit \
+                        without computing anything or creating the output directory.\
+                    "),
+                    arg!( batch [--batch] "\
+                        relax many structures.  The input is either a multi-frame xyz file or \
+                        a directory of structures, and each one is run in its own subdirectory \
+                        of the output directory.  A failure on one structure does not stop the \
+                        others; all failures are reported at the end.\
+                    "),
                 ])
         });
         let matches = app.get_matches();
-        let (dir_args, filetype) = de.resolve_args(&matches)?;
+        let (dir_args, (filetype, MassScaleArgs(mass_scales))) = de.resolve_args(&matches)?;
+
+        let write_trajectory = !matches.is_present("no_trajectory");
+        let resume = matches.is_present("resume");
+        let input = match (matches.value_of("input"), resume) {
+            (_, true) => None,
+            (Some(input), false) => Some(PathAbs::new(input)?),
+            (None, false) => bail!("STRUCTURE is required unless --resume is used"),
+        };
 
-        let input = PathAbs::new(matches.expect_value_of("input"))?;
-        let filetype = OptionalFileType::or_guess(filetype, &input);
+        if matches.is_present("batch") {
+            ensure!(!resume, "--batch cannot be used with --resume");
+            ensure!(!matches.is_present("dry_run"), "--batch cannot be used with --dry-run");
+
+            let input = input.expect("BUG: batch with resume");
+            let frames = crate::cmd::read_batch_frames(&input, |path| filetype.or_guess(path))?;
+            let ValidatedSettings(settings) = TrialDir::dry_run_settings(dir_args.clone())?;
+
+            let batch_dir = crate::cmd::create_batch_dir(&dir_args)?;
+            logfile.start(PathFile::create(batch_dir.join("rsp2.log"))?)?;
+
+            let result = crate::cmd::run_batch_relax_with_eigenvectors(
+                mpi_on_demand, &settings, &batch_dir, dir_args, frames,
+                &mass_scales, stop_after, write_trajectory,
+            );
+            if let Err(e) = GLOBAL_DIAGNOSTICS.save(batch_dir.join("warnings.json")) {
+                warn!("Failed to write warnings.json: {}", e);
+            }
+            return result;
+        }
+
+        let start = match &input {
+            Some(input) => EvLoopStart::Input(input, OptionalFileType::or_guess(filetype, input)),
+            None => {
+                // (the scaled masses were already saved to the structures in the trial directory)
+                ensure!(mass_scales.is_empty(), "--scale-mass cannot be used with --resume");
+                EvLoopStart::Resume
+            },
+        };
 
-        let mut trial = TrialDir::create_new(dir_args)?;
+        if matches.is_present("dry_run") {
+            let (input, filetype) = match start {
+                EvLoopStart::Input(input, filetype) => (input, filetype),
+                EvLoopStart::Resume => bail!("--dry-run cannot be used with --resume"),
+            };
+            let ValidatedSettings(settings) = TrialDir::dry_run_settings(dir_args)?;
+            return crate::cmd::dry_run_relax_with_eigenvectors(&settings, filetype, input, &mass_scales, stop_after);
+        }
+
+        let strict_config = dir_args.strict_config;
+        let mut trial = match resume {
+            true => TrialDir::from_existing(&dir_args.trial_dir)?,
+            false => TrialDir::create_new(dir_args)?,
+        };
+        trial.set_strict_config(strict_config);
         logfile.start(PathFile::new(trial.new_logfile_path()?)?)?;
 
         let ValidatedSettings(settings) = trial.read_base_settings()?;
-        trial.run_relax_with_eigenvectors(mpi_on_demand, &settings, filetype, &input, stop_after)
+        let result = trial.run_relax_with_eigenvectors(
+            mpi_on_demand, &settings, start, &mass_scales, stop_after, write_trajectory,
+        );
+
+        // written even if the run failed, since the warnings may help explain why
+        if let Err(e) = GLOBAL_DIAGNOSTICS.save(trial.join("warnings.json")) {
+            warn!("Failed to write warnings.json: {}", e);
+        }
+        result
     });
 }
 
@@ -489,7 +596,8 @@ pub fn sparse_analysis(bin_name: &str, version: VersionInfo) -> ! {
 
         let analysis = crate::cmd::run_sparse_analysis(structure, &freqs, &evecs)?;
 
-        crate::cmd::write_ev_analysis_output_files(&outdir, &analysis)?;
+        // (this binary takes no config)
+        crate::cmd::write_ev_analysis_output_files(&outdir, &analysis, &Default::default())?;
         Ok(())
     });
 }
@@ -525,7 +633,7 @@ pub fn dynmat_analysis(bin_name: &str, version: VersionInfo) -> ! {
 
         let analysis = crate::cmd::run_dynmat_analysis(&settings, structure, mpi_on_demand, dynmat)?;
 
-        crate::cmd::write_ev_analysis_output_files(&outdir, &analysis)?;
+        crate::cmd::write_ev_analysis_output_files(&outdir, &analysis, &settings.raman)?;
         Ok(())
     });
 }
@@ -548,7 +656,7 @@ pub fn bond_test(bin_name: &str, version: VersionInfo) -> ! {
         let input = PathAbs::new(matches.expect_value_of("input"))?;
         let filetype = OptionalFileType::or_guess(filetype, &input);
 
-        let (coords, _) = crate::cmd::read_optimizable_structure(None, None, filetype, &input)?;
+        let (coords, _) = crate::cmd::read_optimizable_structure(None, None, None, filetype, &input)?;
         let coords = coords.construct(); // reckless
 
         let bonds = rsp2_structure::bonds::FracBonds::compute(&coords, 1.8)?;
@@ -667,6 +775,34 @@ pub fn make_supercell(bin_name: &str, _version: VersionInfo) -> ! {
     });
 }
 
+// %% CRATES: binary: rsp2-convert %%
+pub fn convert(bin_name: &str, _version: VersionInfo) -> ! {
+    wrap_main_just_for_ui(|logfile| {
+        let (app, de) = CliDeserialize::augment_clap_app({
+            clap::App::new(bin_name)
+                .about("Converts a structure between file formats, based on file extensions.")
+                .args(&[
+                    arg!( input=INPUT "\
+                        Input structure. [formats: *.vasp, POSCAR, *.xyz, *.structure, \
+                        *.data, *.lmp (LAMMPS data, atom_style atomic)]\
+                    "),
+                    arg!( output=OUTPUT "\
+                        Output path. Supports the same formats as the input.\
+                    "),
+                ])
+        });
+        let matches = app.get_matches();
+        let () = de.resolve_args(&matches)?;
+
+        logfile.disable();
+
+        let input = matches.expect_value_of("input");
+        let output = matches.expect_value_of("output");
+
+        crate::cmd::run_convert(input, output)
+    });
+}
+
 // %% CRATES: binary: rsp2-compute-for-phonopy %%
 pub fn compute_for_phonopy(bin_name: &str, version: VersionInfo) -> ! {
     wrap_main(version, |logfile, mpi_on_demand| {
@@ -713,7 +849,7 @@ pub fn dynmat_at_q(bin_name: &str, version: VersionInfo) -> ! {
                 ])
         });
         let matches = app.get_matches();
-        let (ConfigArgs(config), AppendLog(append_log)) = de.resolve_args(&matches)?;
+        let (ConfigArgs(config), (AppendLog(append_log), MassScaleArgs(mass_scales))) = de.resolve_args(&matches)?;
         append_log.start(logfile)?;
 
         let ValidatedSettings(settings) = config.deserialize()?;
@@ -721,7 +857,7 @@ pub fn dynmat_at_q(bin_name: &str, version: VersionInfo) -> ! {
         let qpoint_frac = parse_qpoint(&matches.expect_value_of("qpoint"))?;
         let structure = StoredStructure::load(matches.expect_value_of("input"))?;
 
-        let dynmat = crate::cmd::run_dynmat_at_q(mpi_on_demand, &settings, qpoint_frac, structure)?;
+        let dynmat = crate::cmd::run_dynmat_at_q(mpi_on_demand, &settings, qpoint_frac, structure, &mass_scales)?;
 
         dynmat.save(matches.expect_value_of("output"))?;
 
@@ -759,6 +895,32 @@ pub fn layer_mode_frequencies(bin_name: &str, version: VersionInfo) -> ! {
     });
 }
 
+// %% CRATES: binary: rsp2-elastic-constants %%
+pub fn elastic_constants(bin_name: &str, version: VersionInfo) -> ! {
+    wrap_main(version, |logfile, mpi_on_demand| {
+        let (app, de) = CliDeserialize::augment_clap_app({
+            clap::App::new(bin_name)
+                .about("Estimates the elastic tensor from energies at small strains, relaxing atoms at fixed lattice.")
+                .args(&[
+                    arg!( input=STRUCTURE "Input structure, in rsp2 structure directory format."),
+                    arg!( step [--step]=STEP "Finite difference step-size (strain).  Default: 1e-3"),
+                    arg!(*output [-o][--output]=PATH "Output JSON file, with a 6x6 matrix of elastic constants in GPa."),
+                ])
+        });
+        let matches = app.get_matches();
+        let (ConfigArgs(config), AppendLog(append_log)) = de.resolve_args(&matches)?;
+        append_log.start(logfile)?;
+
+        let ValidatedSettings(settings) = config.deserialize()?;
+
+        let step = matches.value_of("step").unwrap_or("1e-3").parse()?;
+        let structure = StoredStructure::load(matches.expect_value_of("input"))?;
+        let output = matches.expect_value_of("output");
+
+        crate::cmd::run_elastic_constants(mpi_on_demand, &settings, structure, step, output)
+    });
+}
+
 // %% CRATES: binary: rsp2-test-rayon %%
 pub fn test_rayon(bin_name: &str, version: VersionInfo) -> ! {
     use rayon::prelude::*;
@@ -13,7 +13,9 @@
 ** ********************************************************************** */
 
 use crate::FailResult;
+use crate::errors::ExternalToolMissing;
 use std::env;
+use std::path::{Path, PathBuf};
 use crate::util::ext_traits::OptionResultExt;
 
 fn var(key: &str) -> FailResult<Option<String>>
@@ -69,6 +71,61 @@ pub fn max_omp_num_threads() -> FailResult<u32>
         .unwrap_or(::num_cpus::get() as u32)
 })}
 
+pub const PHONOPY_EXECUTABLE: &'static str = "RSP2_PHONOPY";
+/// Path to the `phonopy` executable.
+///
+/// This is searched for on `PATH` unless overridden by `RSP2_PHONOPY`.
+pub fn phonopy_executable() -> FailResult<PathBuf>
+{ find_executable("phonopy", PHONOPY_EXECUTABLE) }
+
+pub const PYTHON_EXECUTABLE: &'static str = "RSP2_PYTHON";
+/// Path to the python interpreter used to run python scripts.
+///
+/// This is `python3` from `PATH` unless overridden by `RSP2_PYTHON`.
+pub fn python_executable() -> FailResult<PathBuf>
+{ find_executable("python3", PYTHON_EXECUTABLE) }
+
+fn find_executable(tool: &'static str, env_var: &'static str) -> FailResult<PathBuf>
+{Ok({
+    let name = nonempty_var(env_var)?.unwrap_or_else(|| tool.to_string());
+    locate_executable(tool, env_var, name)?
+})}
+
+// Resolves the name like a shell would; names with more than one path component are
+// used as paths, while bare names are searched for in PATH.
+fn locate_executable(
+    tool: &'static str,
+    env_var: &'static str,
+    name: String,
+) -> Result<PathBuf, ExternalToolMissing>
+{
+    let found = match Path::new(&name).components().count() {
+        1 => {
+            env::var_os("PATH").into_iter()
+                .flat_map(|paths| env::split_paths(&paths).collect::<Vec<_>>())
+                .map(|dir| dir.join(&name))
+                .find(|path| is_executable(path))
+        },
+        _ => Some(PathBuf::from(&name)).filter(|path| is_executable(path)),
+    };
+    found.ok_or_else(|| ExternalToolMissing { tool, env_var, tried: name })
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    match path.metadata() {
+        Ok(meta) => meta.is_file() && meta.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
 #[cfg(feature = "mpi")]
 pub fn num_mpi_processes() -> u32 {
     use mpi::traits::Communicator;
@@ -76,3 +133,22 @@ pub fn num_mpi_processes() -> u32 {
     let world = mpi::topology::SystemCommunicator::world();
     world.size() as _
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_executable() {
+        let err = locate_executable("phonopy", PHONOPY_EXECUTABLE, "/not/a/real/phonopy".into()).unwrap_err();
+        assert_eq!(err.tool, "phonopy");
+        assert_eq!(err.tried, "/not/a/real/phonopy");
+
+        let message = err.to_string();
+        assert!(message.contains("'phonopy'"), "{}", message);
+        assert!(message.contains("RSP2_PHONOPY"), "{}", message);
+
+        let err = locate_executable("phonopy", PHONOPY_EXECUTABLE, "rsp2-not-a-real-phonopy".into()).unwrap_err();
+        assert_eq!(err.tried, "rsp2-not-a-real-phonopy");
+    }
+}
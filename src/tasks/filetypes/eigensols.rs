@@ -20,6 +20,35 @@ const SQRT_EIGENVALUE_TO_THZ: f64 = 15.6333043006705;
 //    = THz / (c / cm)
 const THZ_TO_WAVENUMBER: f64 = 33.3564095198152;
 const SQRT_EIGENVALUE_TO_WAVENUMBER: f64 = SQRT_EIGENVALUE_TO_THZ * THZ_TO_WAVENUMBER;
+//    = h * THz / meV
+const THZ_TO_MEV: f64 = 4.135667696;
+
+/// Units for phonon frequencies.
+///
+/// rsp2 uses cm⁻¹ for frequencies nearly everywhere; this exists for producing
+/// output in the units that other people like to use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FrequencyUnit {
+    Wavenumber,
+    Thz,
+    Mev,
+}
+
+impl FrequencyUnit {
+    /// Size of one of these units, in THz.
+    fn in_thz(self) -> f64 {
+        match self {
+            FrequencyUnit::Wavenumber => 1.0 / THZ_TO_WAVENUMBER,
+            FrequencyUnit::Thz => 1.0,
+            FrequencyUnit::Mev => 1.0 / THZ_TO_MEV,
+        }
+    }
+
+    /// Convert a frequency in cm⁻¹ (as produced by `eigenvalue_to_frequency`) to this unit.
+    pub fn convert_wavenumber(self, freq: Frequency) -> f64 {
+        freq * FrequencyUnit::Wavenumber.in_thz() / self.in_thz()
+    }
+}
 
 // dumb serializable stub type that I initially wrote on a whim for
 // IPC with python for eigsh, and that is now also accepted as an input format
@@ -37,10 +66,23 @@ type Frequency = f64;
 pub struct Raw(Vec<Eigenvalue>, (Vec<Vec<f64>>, Vec<Vec<f64>>));
 
 pub struct Eigensols {
+    /// Frequencies in cm⁻¹.
     pub frequencies: Vec<Frequency>,
     pub eigenvectors: Basis3,
 }
 
+impl Eigensols {
+    /// The frequencies in THz.
+    pub fn frequencies_thz(&self) -> Vec<f64> { self.frequencies_in(FrequencyUnit::Thz) }
+
+    /// The frequencies in meV.
+    pub fn frequencies_mev(&self) -> Vec<f64> { self.frequencies_in(FrequencyUnit::Mev) }
+
+    pub fn frequencies_in(&self, unit: FrequencyUnit) -> Vec<f64> {
+        self.frequencies.iter().map(|&freq| unit.convert_wavenumber(freq)).collect()
+    }
+}
+
 impl Raw {
     pub fn into_eigensols(self) -> FailResult<Eigensols> {
         let Raw(vals, (real, imag)) = self;
@@ -66,6 +108,12 @@ pub fn eigenvalue_to_frequency(val: Eigenvalue) -> Frequency {
     f64::sqrt(f64::abs(val)) * f64::signum(val) * SQRT_EIGENVALUE_TO_WAVENUMBER
 }
 
+/// Inverse of `eigenvalue_to_frequency`.
+pub fn frequency_to_eigenvalue(freq: Frequency) -> Eigenvalue {
+    let sqrt = freq / SQRT_EIGENVALUE_TO_WAVENUMBER;
+    sqrt * sqrt * f64::signum(freq)
+}
+
 impl Load for Eigensols {
     fn load(path: impl AsPath) -> FailResult<Self>
     {
@@ -73,3 +121,30 @@ impl Load for Eigensols {
         raw.into_eigensols()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequency_units() {
+        let raw = Raw(
+            vec![-1e-4, 0.0, 2.5e-3, 1.0],
+            (vec![vec![1.0, 0.0, 0.0]; 4], vec![vec![0.0, 0.0, 0.0]; 4]),
+        );
+        let eigensols = raw.into_eigensols().unwrap();
+        let wavenumber = eigensols.frequencies.clone();
+        let thz = eigensols.frequencies_thz();
+        let mev = eigensols.frequencies_mev();
+        assert_eq!(wavenumber, eigensols.frequencies_in(FrequencyUnit::Wavenumber));
+
+        for ((&wavenumber, &thz), &mev) in wavenumber.iter().zip(&thz).zip(&mev) {
+            assert_close!(abs=1e-10, rel=1e-10, thz * THZ_TO_WAVENUMBER, wavenumber);
+            assert_close!(abs=1e-10, rel=1e-10, thz * THZ_TO_MEV, mev);
+            // 1 meV = 8.0655 cm-1
+            assert_close!(abs=1e-10, rel=1e-4, mev * 8.0655, wavenumber);
+        }
+        assert_close!(rel=1e-10, thz[3], SQRT_EIGENVALUE_TO_THZ);
+        assert!(thz[0] < 0.0);
+    }
+}
@@ -15,6 +15,7 @@ use crate::traits::{Save, Load, AsPath};
 use crate::hlist_aliases::*;
 use rsp2_structure_io::Poscar;
 use rsp2_structure::Coords;
+use rsp2_array_types::V3;
 use crate::traits::save::Json;
 
 use path_abs::PathDir;
@@ -35,6 +36,7 @@ pub type Meta = HList5<
 ///
 /// Encodes as much information as possible, including things derived from config.
 /// It is a suitable format for using the output of one run as input to another.
+#[derive(Clone)]
 pub struct StoredStructure {
     pub title: String,
     pub coords: Coords,
@@ -46,6 +48,10 @@ pub struct StoredStructure {
 }
 
 impl StoredStructure {
+    /// Assemble a structure without checking the metadata.
+    ///
+    /// Prefer `try_from_parts` when the metadata did not come from the same source as the
+    /// coords.
     pub fn from_parts(title: impl Into<String>, coords: Coords, meta: Meta) -> Self {
         let hlist_pat![elements, masses, layers, layer_sc_matrices, frac_bonds] = meta;
         let title = title.into();
@@ -53,6 +59,50 @@ impl StoredStructure {
             title, coords, elements, layers, masses, layer_sc_matrices, frac_bonds,
         }
     }
+
+    /// Assemble a structure, checking that all per-site metadata has one entry per site.
+    pub fn try_from_parts(title: impl Into<String>, coords: Coords, meta: Meta) -> FailResult<Self> {
+        let out = StoredStructure::from_parts(title, coords, meta);
+        out.validate_lengths()?;
+        Ok(out)
+    }
+
+    /// Construct a structure with the same lattice and metadata, but new cartesian positions.
+    ///
+    /// Unlike `Coords::with_carts`, the length must match, as the metadata is still indexed
+    /// by site.
+    pub fn with_carts(&self, carts: Vec<V3>) -> FailResult<Self> {
+        ensure!(
+            carts.len() == self.coords.num_atoms(),
+            "new carts have {} sites, but the structure has {}",
+            carts.len(), self.coords.num_atoms(),
+        );
+        Ok(StoredStructure {
+            coords: self.coords.with_carts(carts),
+            ..self.clone()
+        })
+    }
+
+    fn validate_lengths(&self) -> FailResult<()> {
+        let num_atoms = self.coords.num_atoms();
+        let check = |name: &str, len: usize| -> FailResult<()> {
+            ensure!(
+                len == num_atoms,
+                "structure has {} sites, but {} has {} entries", num_atoms, name, len,
+            );
+            Ok(())
+        };
+        check("elements", self.elements.len())?;
+        check("masses", self.masses.len())?;
+        if let Some(layers) = &self.layers {
+            check("layers", layers.len())?;
+        }
+        if let Some(frac_bonds) = &self.frac_bonds {
+            check("frac-bonds", frac_bonds.num_atoms_per_cell())?;
+        }
+        Ok(())
+    }
+
     pub fn meta(&self) -> Meta { hlist![
         self.elements.clone(), self.masses.clone(), self.layers.clone(),
         self.layer_sc_matrices.clone(), self.frac_bonds.clone(),
@@ -77,7 +127,7 @@ impl Save for StoredStructure {
             title, coords, elements, layers, masses, layer_sc_matrices, frac_bonds,
         } = self;
 
-        Poscar { comment: title, coords, elements }.save(dir.join(FNAME_STRUCTURE))?;
+        Poscar { comment: title, coords, elements, dynamics: None }.save(dir.join(FNAME_STRUCTURE))?;
         let layers = layers.clone();
         let masses = masses.clone();
         let layer_sc_matrices = layer_sc_matrices.clone();
@@ -99,7 +149,7 @@ impl Load for StoredStructure {
     {
         let dir = PathDir::new(dir.as_path())?;
 
-        let Poscar { comment: title, coords, elements } = Load::load(dir.join(FNAME_STRUCTURE))?;
+        let Poscar { comment: title, coords, elements, .. } = Load::load(dir.join(FNAME_STRUCTURE))?;
         let Json(meta) = Load::load(dir.join(FNAME_META))?;
         let MetaJson { layers, masses, layer_sc_matrices } = meta;
         let frac_bonds = if dir.join(FNAME_FRAC_BONDS).exists() {
@@ -108,6 +158,58 @@ impl Load for StoredStructure {
         } else { None };
 
         let elements = elements.into();
-        Ok(StoredStructure { title, coords, masses, elements, layers, layer_sc_matrices, frac_bonds })
+        let out = StoredStructure { title, coords, masses, elements, layers, layer_sc_matrices, frac_bonds };
+        out.validate_lengths()?;
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::Mass;
+    use rsp2_array_types::Envee;
+    use rsp2_structure::{Element, Lattice, CoordsKind};
+
+    fn two_site_parts(num_elements: usize, num_masses: usize) -> (Coords, Meta) {
+        let coords = Coords::new(
+            Lattice::cubic(4.0),
+            CoordsKind::Carts(vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]].envee()),
+        );
+        let meta = hlist![
+            vec![Element::CARBON; num_elements].into(),
+            vec![Mass(12.0); num_masses].into(),
+            None,
+            None,
+            None,
+        ];
+        (coords, meta)
+    }
+
+    #[test]
+    fn try_from_parts() {
+        let (coords, meta) = two_site_parts(2, 2);
+        let valid = StoredStructure::try_from_parts("", coords.clone(), meta.clone()).unwrap();
+        assert_eq!(valid.coords, StoredStructure::from_parts("", coords, meta).coords);
+
+        let (coords, meta) = two_site_parts(3, 2);
+        let err = StoredStructure::try_from_parts("", coords, meta).err().unwrap();
+        assert!(err.to_string().contains("elements has 3 entries"), "{}", err);
+
+        let (coords, meta) = two_site_parts(2, 1);
+        let err = StoredStructure::try_from_parts("", coords, meta).err().unwrap();
+        assert!(err.to_string().contains("masses has 1 entries"), "{}", err);
+    }
+
+    #[test]
+    fn with_carts() {
+        let (coords, meta) = two_site_parts(2, 2);
+        let structure = StoredStructure::from_parts("", coords, meta);
+
+        let moved = structure.with_carts(vec![[0.5, 0.0, 0.0], [1.5, 0.0, 0.0]].envee()).unwrap();
+        assert_eq!(moved.coords.to_carts(), vec![[0.5, 0.0, 0.0], [1.5, 0.0, 0.0]].envee());
+        assert_eq!(moved.elements, structure.elements);
+
+        assert!(structure.with_carts(vec![[0.5, 0.0, 0.0]].envee()).is_err());
     }
 }
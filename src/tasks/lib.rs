@@ -80,6 +80,15 @@ mod errors {
             fmt::Display::fmt(&self.0.as_path().nice(), f)
         }
     }
+
+    /// An external program that rsp2 needs to run could not be found.
+    #[derive(Debug, Fail)]
+    #[fail(display = "could not find the '{}' executable (tried '{}'); make sure it is on your PATH, or set {} to its path", tool, tried, env_var)]
+    pub struct ExternalToolMissing {
+        pub tool: &'static str,
+        pub tried: String,
+        pub env_var: &'static str,
+    }
 }
 
 /// This module only exists to have its name appear in logs.
@@ -136,14 +145,12 @@ mod stderr {
 mod common {
     use crate::FailResult;
     use crate::meta::{Element, Mass};
-    use rsp2_structure::{consts};
 
     pub fn default_element_mass(elem: Element) -> FailResult<Mass>
     {Ok(Mass({
-        match elem {
-            consts::HYDROGEN => 1.00794,
-            consts::CARBON => 12.0107,
-            _ => bail!("No default mass for element {}.", elem.symbol()),
+        match elem.mass() {
+            Some(mass) => mass,
+            None => bail!("No default mass for element {}.", elem.symbol()),
         }
     }))}
 }
@@ -9,12 +9,18 @@
 ** and that the project as a whole is licensed under the GPL 3.0.           **
 ** ************************************************************************ */
 
+use crate::{FailResult, FailOk};
+use crate::meta::Layer;
+use crate::math::basis::Ket3;
 use rsp2_structure::{CoordsKind, Lattice, Coords};
 use rsp2_kets::{Ket, KetRef, Rect};
 use rsp2_array_types::{V3, M33, dot, inv};
+use rsp2_soa_ops::{Perm, Permute};
 use crate::threading::Threading;
 
+use num_complex::Complex64;
 use rayon::prelude::*;
+use std::collections::HashSet;
 use std::f64::consts::PI;
 use itertools::Itertools;
 
@@ -150,6 +156,64 @@ impl ScMatrix {
     }
 }
 
+/// Derive the supercell matrix of each layer in a layered structure.
+///
+/// `layer_primitive_cells[i]` is a primitive cell for the sites of `Layer(i)`; its lattice
+/// and its number of sites are used.  (the positions are not)  Each layer is assumed to be,
+/// taken on its own, a supercell of its primitive cell.  That is:
+///
+/// * The lattice of `structure` must be an integer linear combination of the primitive
+///   lattice vectors, to within `tol` in fractional units.
+/// * The layer must have `abs(det(matrix))` times as many sites as the primitive cell.
+///
+/// Both conditions are checked, as a mistake in either would silently ruin band unfolding.
+/// Periods are chosen along the reciprocal supercell axes, as expected by `GammaUnfolder`.
+pub fn layer_sc_matrices(
+    structure: &Coords,
+    site_layers: &[Layer],
+    layer_primitive_cells: &[Coords],
+    tol: f64,
+) -> FailResult<Vec<ScMatrix>>
+{Ok({
+    ensure!(
+        site_layers.len() == structure.num_atoms(),
+        "structure has {} sites, but {} layer indices were given",
+        structure.num_atoms(), site_layers.len(),
+    );
+
+    let mut layer_counts = vec![0; layer_primitive_cells.len()];
+    for &Layer(layer) in site_layers {
+        match layer_counts.get_mut(layer) {
+            Some(count) => *count += 1,
+            None => bail!("no primitive cell was given for layer {}", layer),
+        }
+    }
+
+    let sc_lattice = structure.lattice().matrix();
+    zip_eq!(layer_primitive_cells, layer_counts).enumerate()
+        .map(|(layer, (primitive, count))| FailOk({
+            let matrix = sc_lattice * primitive.lattice().inverse_matrix();
+            let matrix = matrix.try_map(|x| FailOk({
+                let r = x.round();
+                ensure!(
+                    (x - r).abs() <= tol,
+                    "layer {} does not look like a true supercell of its primitive cell \
+                     (error est: {:e})", layer, (x - r).abs(),
+                );
+                r as i32
+            }))?;
+            let volume_ratio = matrix.det().abs() as usize;
+            ensure!(
+                count == volume_ratio * primitive.num_atoms(),
+                "layer {} has {} sites, but its supercell matrix has determinant {} and \
+                 its primitive cell has {} sites", layer, count, volume_ratio, primitive.num_atoms(),
+            );
+
+            let periods = quotient_group_periods(&matrix.t());
+            ScMatrix::new(&matrix, &periods)
+        })).collect::<FailResult<Vec<_>>>()?
+})}
+
 /// # Output
 ///
 /// Probabilities associated with each image of `eigenvector_q` under the supercell
@@ -335,6 +399,252 @@ fn q_ket(carts: &[V3], q: &V3) -> Ket
 type SuperFracQ = V3;
 type PrimFracQ = V3;
 
+//---------------------------
+
+/// Unfolds phonons using the method of P. B. Allen et al. (2013),
+/// "Recovering hidden Bloch character: Unfolding electrons, phonons, and slabs",
+/// Phys Rev B, 87, 085322.
+///
+/// This is a port of `unfold_lib` from `scripts/unfold.py`.  Like that script, it follows the
+/// sign convention of Phonopy (each site has an `exp(+i Q.x)` factor in the displacements),
+/// which differs from Allen's paper.
+///
+/// Unlike `GammaUnfolder`, the probabilities sum to the squared norm of the input vector
+/// (without any sort of fudge factor), and the eigenvectors may be at any Q point.
+///
+/// The superstructure must approximately have the translational symmetry of the primitive
+/// cell, so that its translations can be represented as permutations of the sites.
+pub struct AllenUnfolder {
+    /// `[g_index] -> G`, in units of the supercell reciprocal lattice.
+    gpoint_indices: Vec<[u32; 3]>,
+    qpoint_sfrac: SuperFracQ,
+    /// `[t_index] -> t`, the primitive lattice translations modulo the superlattice,
+    /// in units of the supercell lattice.
+    translation_sfracs: Vec<V3>,
+    /// `[t_index]`; see `find_perm::translation_deperms`.
+    translation_deperms: Vec<Perm>,
+    /// `[t_index][site]`.  Correction factors for when the deperm maps a site to a
+    /// different image than the translation would.
+    translation_phases: Vec<Vec<Complex64>>,
+    /// `[site]`.  The phase factors in the displacements that are absent from the eigenvector.
+    site_phases: Vec<Complex64>,
+}
+
+impl AllenUnfolder {
+    /// `tol` is the cartesian distance within which translated sites must land on other sites.
+    pub fn new(
+        superstructure: &Coords,
+        sc_matrix: &ScMatrix,
+        qpoint_sfrac: &SuperFracQ,
+        tol: f64,
+    ) -> FailResult<AllenUnfolder>
+    {Ok({
+        let sc_lattice = superstructure.lattice().matrix();
+        let sc_inverse = superstructure.lattice().inverse_matrix();
+        let pc_lattice = &inv(&sc_matrix.matrix.map(|x| x as f64)) * sc_lattice;
+        let qpoint_cart = qpoint_sfrac * &sc_inverse.t();
+
+        // The quotient group of primitive lattice translations modulo the supercell.
+        let translation_carts: Vec<_> = {
+            quotient_group_points(&sc_matrix.matrix).into_iter()
+                .map(|t| t.map(|x| x as f64) * &pc_lattice)
+                .collect()
+        };
+        let translation_sfracs = translation_carts.iter().map(|t| t * sc_inverse).collect();
+
+        // The quotient group of supercell reciprocal lattice points modulo the primitive
+        // reciprocal lattice.
+        let gpoint_indices = recip_quotient_grid(sc_matrix)?;
+
+        let translation_deperms = {
+            rsp2_structure::find_perm::translation_deperms(superstructure, &translation_carts, tol)
+                .map_err(|e| format_err!(
+                    "the structure does not have the translational symmetry of the primitive \
+                    cell (needed for unfolding); {}", e,
+                ))?
+        };
+
+        let carts = superstructure.to_carts();
+        let site_phases = carts.iter().map(|x| exp_i2pi(dot(x, &qpoint_cart))).collect();
+        let translation_phases = {
+            zip_eq!(&translation_carts, &translation_deperms)
+                .map(|(translation, deperm)| {
+                    // `translated.permuted_by(deperm)` in terms of the original indices
+                    let permuted_translated = {
+                        carts.iter().map(|x| x + translation).collect::<Vec<_>>()
+                            .permuted_by(deperm)
+                    };
+                    // superlattice vectors between where each site ought to have been
+                    // translated and the image that the deperm produces
+                    zip_eq!(&carts, permuted_translated)
+                        .map(|(x, image)| exp_i2pi(dot(&(x - image), &qpoint_cart)))
+                        .collect()
+                }).collect()
+        };
+
+        AllenUnfolder {
+            gpoint_indices,
+            qpoint_sfrac: *qpoint_sfrac,
+            translation_sfracs,
+            translation_deperms,
+            translation_phases,
+            site_phases,
+        }
+    })}
+
+    /// The G points onto which vectors are projected, in units of the supercell
+    /// reciprocal lattice.  These are the same as `GammaUnfolder::q_indices` (and the
+    /// `gpoint_sfracs` of `scripts/unfold.py`); the first is always zero.
+    pub fn gpoint_indices(&self) -> &[[u32; 3]]
+    { &self.gpoint_indices }
+
+    /// Probabilities of `eigenvector` projected onto each point `Q + G`, ordered like
+    /// `gpoint_indices()`.
+    ///
+    /// The eigenvector need not be normalized.  (e.g. it may have been projected onto a
+    /// single layer)  The probabilities sum to its squared norm.
+    pub fn unfold_phonon(&self, threading: Threading, eigenvector: &Ket3) -> Vec<f64>
+    {
+        let num_quotient = self.translation_sfracs.len() as f64;
+
+        // Our "Frankenstein bloch function" with the magnitudes of the eigenvector and
+        // the phases of the displacement vector.  (we don't construct the true displacements,
+        // as the sqrt(mass) factors could mess with the normalization)
+        let bloch_function: Vec<V3<Complex64>> = {
+            zip_eq!(&eigenvector.real, &eigenvector.imag, &self.site_phases)
+                .map(|(re, im, &phase)| V3::from_fn(|k| Complex64::new(re[k], im[k]) * phase))
+                .collect()
+        };
+
+        threading.maybe_serial(|| {
+            // Expectation value of each translation operator.
+            let inner_prods: Vec<Complex64> = {
+                self.translation_deperms.par_iter()
+                    .zip_eq(&self.translation_phases)
+                    .map(|(deperm, image_phases)| {
+                        let permuted = bloch_function.clone().permuted_by(deperm);
+                        zip_eq!(&bloch_function, image_phases, permuted)
+                            .map(|(orig, &image_phase, translated)| {
+                                (0..3).map(|k| orig[k].conj() * translated[k] * image_phase).sum::<Complex64>()
+                            })
+                            .sum()
+                    }).collect()
+            };
+
+            // Expectation value of each projection operator P(Q -> Q + G).
+            self.gpoint_indices.par_iter()
+                .map(|g| {
+                    let k_sfrac = self.qpoint_sfrac + V3(*g).map(|x| x as f64);
+                    let prob = {
+                        zip_eq!(&inner_prods, &self.translation_sfracs)
+                            // Phases from Allen Eq 3.  Due to our differing phase conventions,
+                            // we have exp(+i...) rather than exp(-i...).
+                            .map(|(&inner_prod, t)| inner_prod * exp_i2pi(dot(&k_sfrac, t)))
+                            .sum::<Complex64>() / num_quotient
+                    };
+                    // analytically, these are real and non-negative
+                    f64::max(prob.re, 0.0)
+                }).collect()
+        })
+    }
+}
+
+fn exp_i2pi(x: f64) -> Complex64
+{ Complex64::from_polar(&1.0, &(2.0 * PI * x)) }
+
+// The points `[i, j, k]` with `0 <= i < periods[0]` (and etc.), in units of the supercell
+// reciprocal lattice.  This is the same set and order of points as `GammaUnfolder` and
+// `scripts/unfold.py`.
+fn recip_quotient_grid(sc_matrix: &ScMatrix) -> FailResult<Vec<[u32; 3]>>
+{Ok({
+    let [a, b, c] = sc_matrix.periods;
+    let points: Vec<_> = iproduct!(0..a, 0..b, 0..c).map(|(i, j, k)| [i, j, k]).collect();
+
+    // They must be distinct modulo the primitive reciprocal lattice, whose basis (in units of
+    // the SC reciprocal basis) are the rows of the transposed supercell matrix.
+    let (reduce, det) = quotient_reducer(&sc_matrix.matrix.t());
+    let distinct: HashSet<_> = points.iter().map(|&p| reduce(V3(p).map(|x| x as i32))).collect();
+    ensure!(
+        distinct.len() == points.len() && points.len() == det as usize,
+        "supercell periods {:?} do not match the supercell matrix {:?}",
+        sc_matrix.periods, sc_matrix.matrix,
+    );
+    points
+})}
+
+// Representatives of the quotient group `Z^3 / L`, where `L` is the integer lattice whose
+// basis vectors are the rows of `matrix`.  Each is reduced into the unit cell of `L`,
+// and zero comes first.
+fn quotient_group_points(matrix: &M33<i32>) -> Vec<V3<i32>>
+{
+    let (reduce, det) = quotient_reducer(matrix);
+
+    // Breadth-first search of the group generated by the unit vectors.
+    let mut out = vec![V3::zero()];
+    let mut seen: HashSet<_> = out.iter().cloned().collect();
+    let mut index = 0;
+    while index < out.len() {
+        for axis in 0..3 {
+            let next = reduce(out[index] + V3::from_fn(|k| (k == axis) as i32));
+            if seen.insert(next) {
+                out.push(next);
+            }
+        }
+        index += 1;
+    }
+    assert_eq!(out.len(), det as usize);
+    out
+}
+
+/// Periods along each axis such that the points `[i, j, k]` with `0 <= i < periods[0]`
+/// (and etc.) are distinct representatives of every element of `Z^3 / (Z^3 * matrix)`.
+///
+/// Each period is the order of that unit vector modulo the subgroup generated by the
+/// lattice and all previous axes, so their product is always `abs(det(matrix))`.
+fn quotient_group_periods(matrix: &M33<i32>) -> [u32; 3]
+{
+    let (reduce, det) = quotient_reducer(matrix);
+
+    let mut subgroup: HashSet<V3<i32>> = vec![V3::zero()].into_iter().collect();
+    let mut periods = [0; 3];
+    for axis in 0..3 {
+        let unit = V3::from_fn(|k| (k == axis) as i32);
+        let period = (1..).find(|&n| subgroup.contains(&reduce(unit * n))).unwrap();
+        subgroup = {
+            iproduct!(&subgroup, 0..period)
+                .map(|(&h, n)| reduce(h + unit * n))
+                .collect()
+        };
+        periods[axis] = period as u32;
+    }
+    assert_eq!(subgroup.len(), det as usize);
+    periods
+}
+
+/// Returns a function that maps integer vectors to a canonical representative modulo the
+/// integer row space of a nonsingular matrix, along with `abs(det(matrix))`.
+fn quotient_reducer(matrix: &M33<i32>) -> (impl Fn(V3<i32>) -> V3<i32>, i32)
+{
+    let m = *matrix;
+    let cofactors = M33::from_fn(|r, c| {
+        m[(r+1) % 3][(c+1) % 3] * m[(r+2) % 3][(c+2) % 3]
+            - m[(r+1) % 3][(c+2) % 3] * m[(r+2) % 3][(c+1) % 3]
+    });
+    let det = dot(&m[0], &cofactors[0]);
+    assert_ne!(det, 0, "singular supercell matrix");
+
+    // `v * adj / det == v * m^-1`, with `det > 0`
+    let (adj, det) = match det > 0 {
+        true => (cofactors.t(), det),
+        false => (cofactors.t().map(|x| -x), -det),
+    };
+    let reduce = move |v: V3<i32>| {
+        let cell = (v * &adj).map(|x| x.div_euclid(det));
+        v - cell * &m
+    };
+    (reduce, det)
+}
+
 #[cfg(test)]
 #[deny(dead_code)]
 mod tests {
@@ -564,4 +874,168 @@ mod tests {
         // TODO: Test with non-perfect supercell
         // TODO: Test with non-diagonal supercell
     }
+
+    // A normalized Bloch wave of wavevector `k`, written as an eigenvector at `Q`.
+    // (i.e. without the `exp(i Q.x)` factor)
+    fn plane_wave_ket(carts: &[V3], k_minus_q_cart: &V3, polarization: &V3) -> Ket3 {
+        let norm = (carts.len() as f64).sqrt();
+        let (real, imag) = carts.iter().map(|x| {
+            let phase = Complex64::from_polar(&(1.0 / norm), &(2.0 * PI * dot(x, k_minus_q_cart)));
+            (polarization.map(|p| p * phase.re), polarization.map(|p| p * phase.im))
+        }).unzip();
+        Ket3 { real, imag }
+    }
+
+    #[test]
+    fn quotient_group() {
+        let points = quotient_group_points(&mat::from_array([[2, 1, 0], [-1, 1, 0], [0, 0, 1]]));
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0], V3::zero());
+
+        let points = quotient_group_points(&M33::from_diag(V3([2, 3, 1])));
+        let mut sorted = points.clone();
+        sorted.sort_by_key(|v| v.0);
+        let expected: Vec<_> = iproduct!(0..2, 0..3).map(|(i, j)| V3([i, j, 0])).collect();
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn allen_plane_waves() {
+        // Graphene in a non-diagonal supercell, with eigenvectors at an arbitrary Q.
+        let half_r3 = 0.5 * f64::sqrt(3.0);
+        let prim_lattice = mat::from_array([
+            [ 2.46,            0.0,  0.0],
+            [-1.23, 2.46 * half_r3,  0.0],
+            [  0.0,            0.0, 10.0],
+        ]);
+        let prim_fracs = vec![[0.0, 0.0, 0.0], [1.0/3.0, 2.0/3.0, 0.0]].envee();
+        let sc_matrix = ScMatrix {
+            matrix: mat::from_array([[2, 1, 0], [-1, 3, 0], [0, 0, 1]]),
+            periods: [1, 7, 1],
+        };
+        let super_lattice = Lattice::new(&(&sc_matrix.matrix.map(|x| x as f64) * &prim_lattice));
+        let carts = {
+            quotient_group_points(&sc_matrix.matrix).into_iter()
+                .flat_map(|t| prim_fracs.iter().map(move |f| (f + t.map(|x| x as f64)) * &prim_lattice))
+                .collect::<Vec<_>>()
+        };
+        let coords = Coords::new(super_lattice.clone(), CoordsKind::Carts(carts.clone()));
+        assert_eq!(coords.num_atoms(), 14);
+
+        let qpoint_sfrac = V3([0.1, 0.3, 0.0]);
+        let unfolder = AllenUnfolder::new(&coords, &sc_matrix, &qpoint_sfrac, 1e-5).unwrap();
+        assert_eq!(unfolder.gpoint_indices().len(), 7);
+
+        let sc_recip = super_lattice.inverse_matrix().t();
+        for (g_index, g) in unfolder.gpoint_indices().iter().enumerate() {
+            let k_minus_q = V3(*g).map(|x| x as f64) * &sc_recip;
+            let ket = plane_wave_ket(&carts, &k_minus_q, &V3([0.6, 0.0, 0.8]));
+            let probs = unfolder.unfold_phonon(Threading::Serial, &ket);
+            for (i, &prob) in probs.iter().enumerate() {
+                let expected = if i == g_index { 1.0 } else { 0.0 };
+                assert_close!(abs=1e-10, prob, expected, "{:?}", probs);
+            }
+
+            // probabilities sum to the squared norm
+            let half_ket = Ket3 {
+                real: ket.real.iter().map(|v| v * 0.5).collect(),
+                imag: ket.imag.iter().map(|v| v * 0.5).collect(),
+            };
+            let probs = unfolder.unfold_phonon(Threading::Serial, &half_ket);
+            assert_close!(abs=1e-10, probs.iter().sum::<f64>(), 0.25);
+        }
+    }
+
+    #[test]
+    fn allen_reference_probabilities() {
+        // Reference data for a graphene supercell whose sites are shuffled and wrapped into
+        // the cell.
+        //
+        // NOTE: This has NOT been cross-checked against scripts/unfold.py.  The file was made
+        //       by a hand transcription of the python implementation in unfold_lib (numpy was
+        //       unavailable), so it only guards against regressions.  make-allen-reference.py
+        //       regenerates it from unfold.py itself, and should be run to turn this into a
+        //       real comparison.
+        #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Reference {
+            super_lattice: [[f64; 3]; 3],
+            super_fracs: Vec<[f64; 3]>,
+            sc_matrix: [[i32; 3]; 3],
+            qpoint_sfrac: [f64; 3],
+            gpoint_sfracs: Vec<[u32; 3]>,
+            eigenvectors_real: Vec<Vec<[f64; 3]>>,
+            eigenvectors_imag: Vec<Vec<[f64; 3]>>,
+            probs: Vec<Vec<f64>>,
+        }
+        let reference: Reference = serde_json::from_str(include_str!(
+            "../../../tests/resources/unfold/allen-reference.json",
+        )).unwrap();
+
+        let coords = Coords::new(
+            Lattice::new(&mat::from_array(reference.super_lattice)),
+            CoordsKind::Fracs(reference.super_fracs.envee()),
+        );
+        let periods = V3::from_fn(|k| {
+            reference.gpoint_sfracs.iter().map(|g| g[k] + 1).max().unwrap()
+        });
+        let sc_matrix = ScMatrix::new(&mat::from_array(reference.sc_matrix), &periods.0);
+
+        let qpoint_sfrac = V3(reference.qpoint_sfrac);
+        let unfolder = AllenUnfolder::new(&coords, &sc_matrix, &qpoint_sfrac, 1e-5).unwrap();
+        assert_eq!(unfolder.gpoint_indices(), &reference.gpoint_sfracs[..]);
+
+        let evecs = zip_eq!(reference.eigenvectors_real, reference.eigenvectors_imag);
+        for ((real, imag), expected) in zip_eq!(evecs, reference.probs) {
+            let ket = Ket3 { real: real.envee(), imag: imag.envee() };
+            let probs = unfolder.unfold_phonon(Threading::Serial, &ket);
+            assert_close!(rel=1e-9, probs, expected);
+        }
+    }
+
+    #[test]
+    fn twisted_bilayer_layer_sc_matrices() {
+        // Commensurate twisted bilayer graphene at 21.8 degrees.
+        let half_r3 = 0.5 * f64::sqrt(3.0);
+        let lattice_0 = mat::from_array([
+            [ 2.46,            0.0,  0.0],
+            [-1.23, 2.46 * half_r3,  0.0],
+            [  0.0,            0.0, 10.0],
+        ]);
+        let matrix_0 = mat::from_array([[1, 2, 0], [-2, 3, 0], [0, 0, 1]]);
+        let matrix_1 = mat::from_array([[2, 1, 0], [-1, 3, 0], [0, 0, 1]]);
+        let super_lattice = &matrix_0.map(|x| x as f64) * &lattice_0;
+        let lattice_1 = &inv(&matrix_1.map(|x| x as f64)) * &super_lattice;
+
+        let prim_fracs = vec![[0.0, 0.0, 0.0], [1.0/3.0, 2.0/3.0, 0.0]].envee();
+        let layer_carts = |matrix: &M33<i32>, lattice: &M33, z: f64| {
+            quotient_group_points(matrix).into_iter()
+                .flat_map(|t| prim_fracs.iter().map(move |f| f + t.map(|x| x as f64)))
+                .map(|f| f * lattice + V3([0.0, 0.0, z]))
+                .collect::<Vec<_>>()
+        };
+        let mut carts = layer_carts(&matrix_0, &lattice_0, 0.0);
+        carts.extend(layer_carts(&matrix_1, &lattice_1, 3.35));
+        let layers: Vec<_> = (0..2).flat_map(|i| vec![Layer(i); 14]).collect();
+
+        let structure = Coords::new(Lattice::new(&super_lattice), CoordsKind::Carts(carts));
+        let primitive_cells = vec![
+            Coords::new(Lattice::new(&lattice_0), CoordsKind::Fracs(prim_fracs.clone())),
+            Coords::new(Lattice::new(&lattice_1), CoordsKind::Fracs(prim_fracs.clone())),
+        ];
+
+        let sc_mats = layer_sc_matrices(&structure, &layers, &primitive_cells, 1e-6).unwrap();
+        assert_eq!(sc_mats[0].matrix, matrix_0);
+        assert_eq!(sc_mats[1].matrix, matrix_1);
+        for sc_mat in &sc_mats {
+            // 14 sites per layer, 2 per primitive cell
+            assert_eq!(sc_mat.matrix.det().abs(), 7);
+            assert_eq!(sc_mat.periods.iter().product::<u32>(), 7);
+        }
+
+        // a layer with the wrong number of sites
+        let mut bad_layers = layers.clone();
+        bad_layers[0] = Layer(1);
+        assert!(layer_sc_matrices(&structure, &bad_layers, &primitive_cells, 1e-6).is_err());
+    }
 }
@@ -173,6 +173,33 @@ impl Ket3 {
         let imag = imag.iter().map(|&v| v / norm).collect();
         Ket3 { real, imag }
     }
+
+    /// Squared amplitude on each atom, as `(atom index, amplitude)` pairs sorted in
+    /// descending order of amplitude.  (these sum to `self.sqnorm()`)
+    pub fn atom_contributions(&self) -> Vec<(usize, f64)> {
+        let mut out = {
+            zip_eq!(&self.real, &self.imag)
+                .map(|(re, im)| re.sqnorm() + im.sqnorm())
+                .enumerate()
+                .collect::<Vec<_>>()
+        };
+        // (stable sort, so ties stay in index order)
+        out.sort_by(|a, b| b.1.partial_cmp(&a.1).expect("NaN in eigenvector"));
+        out
+    }
+
+    /// Scale so that the largest (complex) 3-vector for any single atom has unit norm.
+    pub fn normalized_per_atom(&self) -> Self {
+        let Ket3 { real, imag } = self;
+        let max_norm = {
+            zip_eq!(real, imag)
+                .map(|(re, im)| f64::sqrt(re.sqnorm() + im.sqnorm()))
+                .fold(0.0, f64::max)
+        };
+        let real = real.iter().map(|&v| v / max_norm).collect();
+        let imag = imag.iter().map(|&v| v / max_norm).collect();
+        Ket3 { real, imag }
+    }
 }
 
 impl std::ops::Deref for EvDirection {
@@ -196,6 +223,21 @@ impl EvDirection {
         EvDirection(Ket3 { real, imag })
     }
 
+    /// The inverse of `from_eigenvector`.
+    ///
+    /// (this will only be normalized if `self` has not been rescaled since it was constructed)
+    pub fn to_eigenvector(&self, meta: HList1<meta::SiteMasses>) -> Ket3 {
+        let masses: meta::SiteMasses = meta.pick();
+        let (real, imag) = {
+            zip_eq!(&self.0.real, &self.0.imag, &masses[..])
+                .map(|(&real, &imag, &Mass(mass)): (&V3, &V3, _)| {
+                    (real * f64::sqrt(mass), imag * f64::sqrt(mass))
+                })
+                .unzip()
+        };
+        Ket3 { real, imag }
+    }
+
     /// A measure from 0 to `self.sqnorm()` of how acoustic the ket is.
     pub fn acousticness(&self) -> f64
     {
@@ -226,3 +268,49 @@ impl EvDirection {
     pub fn normalized(&self) -> Self
     { EvDirection(self.0.normalized()) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ev_direction_round_trip() {
+        let masses: meta::SiteMasses = vec![Mass(12.01), Mass(1.008), Mass(28.0855)].into();
+        let evec = Ket3 {
+            real: vec![V3([0.1, -0.3, 0.2]), V3([0.5, 0.0, -0.4]), V3([0.0, 0.3, 0.1])],
+            imag: vec![V3([0.0, 0.2, 0.0]), V3([-0.1, 0.1, 0.3]), V3([0.4, 0.0, 0.0])],
+        }.normalized();
+
+        let direction = EvDirection::from_eigenvector(&evec, hlist![masses.clone()]);
+        // heavier atoms move less
+        assert!(direction.real[2].norm() < evec.real[2].norm());
+
+        let round_trip = direction.to_eigenvector(hlist![masses]);
+        for (a, b) in ichain!(zip_eq!(&round_trip.real, &evec.real), zip_eq!(&round_trip.imag, &evec.imag),) {
+            assert_close!(abs=1e-14, a.0, b.0);
+        }
+        assert_close!(round_trip.norm(), 1.0);
+
+        let per_atom = evec.normalized_per_atom();
+        let atom_norms = zip_eq!(&per_atom.real, &per_atom.imag)
+            .map(|(re, im)| f64::sqrt(re.sqnorm() + im.sqnorm()))
+            .collect::<Vec<_>>();
+        assert_close!(atom_norms.iter().cloned().fold(0.0, f64::max), 1.0);
+    }
+
+    #[test]
+    fn atom_contributions() {
+        // a mode mostly on atoms 3 and 1, with a little bit of 0, and nothing on 2
+        let evec = Ket3 {
+            real: vec![V3([0.0, 0.1, 0.0]), V3([0.0, 0.0, -0.6]), V3::zero(), V3([0.7, 0.0, 0.0])],
+            imag: vec![V3::zero(), V3([0.0, 0.2, 0.0]), V3::zero(), V3::zero()],
+        };
+        let contributions = evec.atom_contributions();
+        let atoms = contributions.iter().map(|&(atom, _)| atom).collect::<Vec<_>>();
+        assert_eq!(atoms, vec![3, 1, 0, 2]);
+        assert_close!(contributions[0].1, 0.49);
+        assert_close!(contributions[1].1, 0.40);
+        assert_close!(contributions[3].1, 0.0);
+        assert_close!(contributions.iter().map(|&(_, x)| x).sum::<f64>(), evec.sqnorm());
+    }
+}
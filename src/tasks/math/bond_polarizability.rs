@@ -14,12 +14,15 @@ use crate::math::basis::GammaBasis3;
 use crate::meta::{Element, Mass};
 
 use rsp2_structure::bonds::{CartBonds};
+use rsp2_tasks_config as cfg;
+use rsp2_array_types::V3;
 use rsp2_bond_polarizability as imp;  // implementation moved out to separate crate
 
-pub use imp::{RamanTensor, LightPolarization};
+pub use imp::{RamanTensor, LightPolarization, StokesBranch};
 
 pub struct Input<'a> {
     pub temperature: f64,
+    pub branch: StokesBranch,
     pub ev_frequencies: &'a [f64],
     pub ev_eigenvectors: &'a GammaBasis3,
     pub site_elements: &'a [Element],
@@ -32,6 +35,7 @@ impl<'a> Input<'a> {
         let site_masses = self.site_masses.iter().map(|&Mass(m)| m).collect::<Vec<_>>();
         imp::Input {
             temperature: self.temperature,
+            branch: self.branch,
             ev_frequencies: self.ev_frequencies,
             ev_eigenvectors: self.ev_eigenvectors.0.iter().map(|ev| &ev.0[..]),
             site_elements: self.site_elements,
@@ -40,3 +44,107 @@ impl<'a> Input<'a> {
         }.compute_ev_raman_tensors().map_err(Into::into)
     }
 }
+
+/// Get the `LightPolarization` selected by `raman.polarization`.
+pub fn light_polarization(polarization: &cfg::RamanPolarization) -> LightPolarization {
+    match *polarization {
+        cfg::RamanPolarization::Average => LightPolarization::Average,
+        cfg::RamanPolarization::BackscatterZ => LightPolarization::BackscatterZ,
+        cfg::RamanPolarization::Polarized { incident, scattered } => {
+            // (already normalized by config validation)
+            LightPolarization::Polarized { incident: V3(incident), scattered: V3(scattered) }
+        },
+    }
+}
+
+/// A mode (or a set of degenerate modes) ranked by raman activity.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ActiveMode {
+    /// Mean frequency of the modes in the set, in cm^-1.
+    pub frequency: f64,
+    /// Total intensity of the modes in the set.
+    pub intensity: f64,
+    /// Eigenvector indices of the modes in the set.
+    pub ev_indices: Vec<usize>,
+}
+
+/// Get the `count` most raman-active modes, in order of decreasing intensity.
+///
+/// Modes whose frequencies lie within `degeneracy_tol` (in cm^-1) of each other are
+/// regarded as degenerate, and their intensities are summed before ranking.
+/// (the raman intensity of an individual eigenvector from a degenerate subspace is
+///  not meaningful, since the basis of the subspace is arbitrary)
+pub fn most_active_modes(
+    ev_frequencies: &[f64],
+    ev_intensities: &[f64],
+    degeneracy_tol: f64,
+    count: usize,
+) -> Vec<ActiveMode> {
+    assert_eq!(ev_frequencies.len(), ev_intensities.len());
+
+    let mut indices = (0..ev_frequencies.len()).collect::<Vec<_>>();
+    indices.sort_by(|&a, &b| {
+        ev_frequencies[a].partial_cmp(&ev_frequencies[b]).expect("NaN frequency")
+    });
+
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for index in indices {
+        if let Some(group) = groups.last_mut() {
+            let &prev = group.last().expect("(BUG) empty group");
+            if ev_frequencies[index] - ev_frequencies[prev] <= degeneracy_tol {
+                group.push(index);
+                continue;
+            }
+        }
+        groups.push(vec![index]);
+    }
+
+    let mut modes = groups.into_iter().map(|ev_indices| {
+        let frequency = ev_indices.iter().map(|&i| ev_frequencies[i]).sum::<f64>() / ev_indices.len() as f64;
+        // NOTE: intensity can be "negative" for negative modes.  These are not physical,
+        //       so they are simply treated as zero.
+        let intensity = ev_indices.iter().map(|&i| f64::max(0.0, ev_intensities[i])).sum();
+        ActiveMode { frequency, intensity, ev_indices }
+    }).collect::<Vec<_>>();
+
+    modes.sort_by(|a, b| b.intensity.partial_cmp(&a.intensity).expect("NaN intensity"));
+    modes.truncate(count);
+    modes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_active_modes_ranking() {
+        let frequencies = [0.0, 0.0, 0.0, 400.0, 900.0, 900.0 + 1e-4, 1580.0, 1580.0 + 1e-4];
+        let intensities = [0.0, 0.0, 0.0, 1.0, 0.25, 0.25, 0.75, 0.75];
+
+        let modes = most_active_modes(&frequencies, &intensities, 1e-2, 3);
+        assert_eq!(modes.len(), 3);
+
+        // the degenerate pair at 1580 outranks the single strongest eigenvector
+        assert_eq!(modes[0].ev_indices, vec![6, 7]);
+        assert_close!(modes[0].intensity, 1.5);
+        assert_close!(abs=1e-3, modes[0].frequency, 1580.0);
+
+        assert_eq!(modes[1].ev_indices, vec![3]);
+        assert_close!(modes[1].intensity, 1.0);
+
+        assert_eq!(modes[2].ev_indices, vec![4, 5]);
+        assert_close!(modes[2].intensity, 0.5);
+    }
+
+    #[test]
+    fn most_active_modes_negative_intensity() {
+        let frequencies = [-20.0, 100.0];
+        let intensities = [-5.0, 1e-3];
+
+        let modes = most_active_modes(&frequencies, &intensities, 1e-2, 10);
+        assert_eq!(modes.len(), 2);
+        assert_eq!(modes[0].ev_indices, vec![1]);
+        assert_eq!(modes[1].intensity, 0.0);
+    }
+}
@@ -9,13 +9,14 @@
 ** and that the project as a whole is licensed under the GPL 3.0.           **
 ** ************************************************************************ */
 
+use crate::FailResult;
 use crate::math::stars::Stars;
 
 use rsp2_tasks_config as cfg;
 
 use rsp2_newtype_indices::{Idx, Indexed, IndexVec};
 use rsp2_array_types::{V3, M3};
-use rsp2_structure::{Coords, Lattice, IntRot};
+use rsp2_structure::{Coords, Lattice, IntRot, CartOp};
 use rsp2_soa_ops::{Perm, Permute};
 
 lazy_static! {
@@ -29,6 +30,8 @@ lazy_static! {
     static ref DIRECTIONS_DIAG_1: Vec<V3<i32>> = make_nice_directions_list(1);
     /// Experimental "cleverer" list.
     static ref DIRECTIONS_DIAG_2: Vec<V3<i32>> = make_nice_directions_list(2);
+    /// Experimental, even more exhaustive list.
+    static ref DIRECTIONS_DIAG_3: Vec<V3<i32>> = make_nice_directions_list(3);
 }
 
 #[allow(unused)] // useful for debugging
@@ -61,16 +64,113 @@ pub fn compute_displacements(
         cfg::PhononDispFinderRsp2Directions::Axial => go(&DIRECTIONS_AXIAL),
         cfg::PhononDispFinderRsp2Directions::Diag  => go(&DIRECTIONS_DIAG_1),
         cfg::PhononDispFinderRsp2Directions::Diag2 => go(&DIRECTIONS_DIAG_2),
+        cfg::PhononDispFinderRsp2Directions::Diag3 => go(&DIRECTIONS_DIAG_3),
         cfg::PhononDispFinderRsp2Directions::Survey => {
             debug!("Surveying displacement implementations:");
-            debug!("  axial: Produces {}", go(&DIRECTIONS_AXIAL).len());
-            debug!("   diag: Produces {}", go(&DIRECTIONS_DIAG_1).len());
-            debug!(" diag-2: Produces {}", go(&DIRECTIONS_DIAG_2).len());
+            for (name, count) in survey_counts(&go) {
+                debug!("{:>7}: Produces {}", name, count);
+            }
             go(&DIRECTIONS_DIAG_2)
         },
     }
 }
 
+/// The number of displacements produced by each direction list, as reported by `Survey`.
+fn survey_counts<D>(go: impl Fn(&[V3<i32>]) -> Vec<D>) -> Vec<(&'static str, usize)> {
+    vec![
+        ("axial", go(&DIRECTIONS_AXIAL).len()),
+        ("diag", go(&DIRECTIONS_DIAG_1).len()),
+        ("diag-2", go(&DIRECTIONS_DIAG_2).len()),
+        ("diag-3", go(&DIRECTIONS_DIAG_3).len()),
+    ]
+}
+
+/// Record of how the spacegroup was used to reduce the displacements.
+/// (`displacement-symmetry.json`)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DisplacementSymmetryReport {
+    pub num_atoms: usize,
+    /// The spacegroup operators, in the order used by `site-symmetry` below.
+    pub operators: Vec<OperatorReport>,
+    pub stars: Vec<StarReport>,
+    /// Number of displacements needed without symmetry (`+` and `-` along three axes per atom).
+    pub num_full_displacements: usize,
+    /// Number of displacements actually computed.
+    pub num_displacements: usize,
+    /// `num-full-displacements / num-displacements`.
+    pub reduction_factor: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OperatorReport {
+    /// Rotation in fractional units.
+    pub int_rot: IntRot,
+    /// Translation in cartesian units.
+    pub cart_trans: V3,
+    /// The depermutation of sites produced by the operator.
+    pub deperm: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StarReport {
+    /// The only site in the star that gets displaced.
+    pub representative: usize,
+    pub members: Vec<usize>,
+    /// Indices of operators that map the representative into itself.
+    pub site_symmetry: Vec<usize>,
+    pub num_displacements: usize,
+}
+
+impl DisplacementSymmetryReport {
+    pub fn new(
+        cart_ops: &[CartOp],
+        deperms: &[Perm],
+        stars: &Stars,
+        coords: &Coords,
+        displacements: &[(usize, V3)],
+    ) -> FailResult<Self> {
+        assert_eq!(cart_ops.len(), deperms.len());
+
+        let operators = {
+            zip_eq!(cart_ops, deperms)
+                .map(|(op, deperm)| Ok(OperatorReport {
+                    int_rot: op.int_rot(coords.lattice())?,
+                    cart_trans: op.cart_trans(),
+                    deperm: deperm.clone().into_vec(),
+                }))
+                .collect::<FailResult<_>>()?
+        };
+
+        let stars = {
+            stars.iter()
+                .map(|star| {
+                    let rep = star.representative();
+                    StarReport {
+                        representative: rep,
+                        members: star.members().collect(),
+                        site_symmetry: star.opers_from_rep(rep).to_vec(),
+                        num_displacements: displacements.iter().filter(|&&(site, _)| site == rep).count(),
+                    }
+                })
+                .collect()
+        };
+
+        let num_full_displacements = 6 * coords.num_atoms();
+        let num_displacements = displacements.len();
+        Ok(DisplacementSymmetryReport {
+            num_atoms: coords.num_atoms(),
+            operators,
+            stars,
+            num_full_displacements,
+            num_displacements,
+            reduction_factor: num_full_displacements as f64 / num_displacements as f64,
+        })
+    }
+}
+
 fn _compute_displacements<DispI: Idx, SiteI: Idx, OperI: Idx, StarI: Idx>(
     choices: &[V3<i32>], // possible directions in descending order of niceness
     int_rots: &Indexed<OperI, [IntRot]>,
@@ -219,8 +319,8 @@ fn make_nice_directions_list(max_abs: i32) -> Vec<V3<i32>> {
             for c in -max_abs ..= max_abs {
                 // Ultimately only the direction of a point matters, so e.g. [2, 0, 2] is
                 // effectively the same as [1, 0, 1].  Therefore, skip if gcd(a, b, c) != 1.
-                assert!(max_abs == 1 || max_abs == 2, "simplifying assumption of gcd check");
-                if (a & 1) + (b & 1) + (c & 1) == 0 {
+                // (this also skips [0, 0, 0], whose gcd is 0)
+                if gcd(gcd(a.abs(), b.abs()), c.abs()) != 1 {
                     continue
                 }
 
@@ -229,6 +329,7 @@ fn make_nice_directions_list(max_abs: i32) -> Vec<V3<i32>> {
 
                 // Tuple whose lexical ordering places "nicer" directions first.
                 ranks.push((
+                    direction.iter().filter(|&&n| n.abs() == 3).count(), // prefer fewer 3s
                     direction.iter().filter(|&&n| n.abs() == 2).count(), // prefer fewer 2s
                     direction.iter().filter(|&&n| n.abs() == 1).count(), // prefer fewer 1s
                     direction.iter().filter(|&&n| n < 0).count(), // prefer fewer minus signs
@@ -256,6 +357,13 @@ fn make_nice_directions_list(max_abs: i32) -> Vec<V3<i32>> {
     directions
 }
 
+fn gcd(a: i32, b: i32) -> i32 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
 fn is_lindep_with(vs: &[V3<i32>], v: V3<i32>) -> bool {
     match vs {
         &[]     => true,
@@ -293,9 +401,11 @@ mod tests {
 
         let deperms = rsp2_structure::find_perm::spacegroup_deperms(&coords, &cart_ops, 3.0 * TOL)?;
         let stars = crate::math::stars::compute_stars(&deperms);
-        let int_ops = cart_ops.iter().map(|c| {
-            c.int_rot(coords.lattice()).expect("bad operator from spglib!?")
-        });
+        let int_ops = {
+            cart_ops.iter()
+                .map(|c| c.int_rot(coords.lattice()))
+                .collect::<Result<Vec<_>, _>>()?
+        };
         Ok(super::compute_displacements(directions, int_ops, &stars, &coords, DISTANCE))
     }
 
@@ -353,4 +463,94 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn graphene_symmetry_report() -> FailResult<()> {
+        let coords = Coords::new(
+            Lattice::from([
+                [2.46, 0.0, 0.0],
+                [-1.23, 2.130422493309719, 0.0],
+                [0.0, 0.0, 12.0],
+            ]),
+            CoordsKind::Fracs(vec![
+                [0.0, 0.0, 0.0],
+                [2.0/3.0, 1.0/3.0, 0.0],
+            ].envee()),
+        );
+        let cart_ops = {
+            let spg = SpgDataset::compute(&coords, &[6, 6], TOL)?;
+            assert_eq!(spg.spacegroup_number, 191);
+            spg.cart_ops()
+        };
+        let deperms = rsp2_structure::find_perm::spacegroup_deperms(&coords, &cart_ops, 3.0 * TOL)?;
+        let stars = crate::math::stars::compute_stars(&deperms);
+        let int_ops = cart_ops.iter().map(|c| c.int_rot(coords.lattice()).unwrap());
+        let disps = super::compute_displacements(&from_json!("diag"), int_ops, &stars, &coords, DISTANCE);
+
+        let report = DisplacementSymmetryReport::new(&cart_ops, &deperms, &stars, &coords, &disps)?;
+        let json = serde_json::to_value(&report)?;
+
+        // P6/mmm
+        assert_eq!(json["operators"].as_array().unwrap().len(), 24);
+        // both atoms are equivalent; the site symmetry is -6m2
+        assert_eq!(json["stars"].as_array().unwrap().len(), 1);
+        assert_eq!(json["stars"][0]["members"], serde_json::json!([0, 1]));
+        assert_eq!(json["stars"][0]["site-symmetry"].as_array().unwrap().len(), 12);
+
+        let num_displacements = json["num-displacements"].as_u64().unwrap();
+        assert_eq!(num_displacements, disps.len() as u64);
+        assert_eq!(json["num-full-displacements"].as_u64().unwrap(), 12);
+        // at most one in-plane and one out-of-plane displacement, each with its negative
+        assert!(1 <= num_displacements && num_displacements <= 4);
+        assert_close!(
+            json["reduction-factor"].as_f64().unwrap(),
+            12.0 / num_displacements as f64,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn graphene_survey() -> FailResult<()> {
+        let coords = Coords::new(
+            Lattice::from([
+                [2.46, 0.0, 0.0],
+                [-1.23, 2.130422493309719, 0.0],
+                [0.0, 0.0, 12.0],
+            ]),
+            CoordsKind::Fracs(vec![
+                [0.0, 0.0, 0.0],
+                [2.0/3.0, 1.0/3.0, 0.0],
+            ].envee()),
+        );
+        let cart_ops = SpgDataset::compute(&coords, &[6, 6], TOL)?.cart_ops();
+        let deperms = rsp2_structure::find_perm::spacegroup_deperms(&coords, &cart_ops, 3.0 * TOL)?;
+        let stars = crate::math::stars::compute_stars(&deperms);
+        let int_rots = cart_ops.iter().map(|c| c.int_rot(coords.lattice()).unwrap()).collect::<Vec<_>>();
+        let compute = |directions: &cfg::PhononDispFinderRsp2Directions| {
+            super::compute_displacements(directions, int_rots.iter().cloned(), &stars, &coords, DISTANCE)
+        };
+
+        let indexed_rots = int_rots.iter().cloned().collect::<IndexVec<usize, _>>();
+        let survey = survey_counts(|choices| {
+            _compute_displacements::<usize, _, _, _>(
+                choices, &indexed_rots[..], &stars, coords.lattice(), DISTANCE,
+            ).raw
+        });
+
+        // The only star has site symmetry -6m2.  A direction perpendicular to one of the
+        // in-plane 2-fold axes is mapped onto three independent directions and onto its own
+        // negative, so a single displacement suffices; the axial list has no such direction,
+        // and needs one in-plane and one out-of-plane displacement.
+        assert_eq!(survey, vec![
+            ("axial", 2),
+            ("diag", 1),
+            ("diag-2", 1),
+            ("diag-3", 1),
+        ]);
+        for &(name, count) in &survey {
+            let disps = compute(&from_json!(name));
+            assert_eq!(disps.len(), count, "{}", name);
+        }
+        Ok(())
+    }
 }
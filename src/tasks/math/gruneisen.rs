@@ -0,0 +1,308 @@
+/* ************************************************************************ **
+** This file is part of rsp2, and is licensed under EITHER the MIT license  **
+** or the Apache 2.0 license, at your option.                               **
+**                                                                          **
+**     http://www.apache.org/licenses/LICENSE-2.0                           **
+**     http://opensource.org/licenses/MIT                                   **
+**                                                                          **
+** Be aware that not all of rsp2 is provided under this permissive license, **
+** and that the project as a whole is licensed under the GPL 3.0.           **
+** ************************************************************************ */
+
+//! Mode Grüneisen parameters by finite differences in volume.
+//!
+//! The mode Grüneisen parameter is `γ = -d ln(ω) / d ln(V)`.  It is computed from
+//! the eigensolutions at two slightly perturbed volumes, which requires figuring out
+//! which mode at each perturbed volume corresponds to which mode at the original volume.
+//! Sorting by frequency is not good enough for this, because modes can cross;
+//! instead, modes are matched by eigenvector overlap.
+//!
+//! The perturbed structures are made by uniformly scaling the lattice at fixed fractional
+//! coordinates, and are **not** relaxed at the new volume.  (so any contribution from internal
+//! relaxation is left out)  This is what makes the matching cheap: the eigenvectors barely
+//! change, and each frequency can only move by as much as a bounded `γ` allows, so the partner
+//! of a mode is only searched for among the modes of nearby frequency.
+
+use crate::math::basis::GammaKet3;
+use std::cmp::Ordering;
+
+/// Frequencies (cm^-1) and eigenvectors from a single diagonalization.
+#[derive(Debug, Copy, Clone)]
+pub struct Modes<'a> {
+    pub frequencies: &'a [f64],
+    pub eigenvectors: &'a [GammaKet3],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MatchSettings {
+    /// Minimum squared overlap between a mode and its match for the match to be trusted.
+    ///
+    /// The overlap is measured against the entire degenerate subspace of the match,
+    /// since eigenvectors within a degenerate subspace are arbitrary.
+    pub min_overlap: f64,
+    /// Frequency difference (cm^-1) below which modes are considered degenerate.
+    pub degeneracy_tol: f64,
+    /// Largest `|γ|` that can be found.  Partners for a mode are only searched for among the
+    /// modes whose frequency differs by no more than a `γ` of this size would explain.
+    pub max_abs_gruneisen: f64,
+    /// Extra width (cm^-1) of that search window, for modes whose frequencies are dominated
+    /// by numerical noise. (i.e. acoustic modes)
+    pub frequency_slack: f64,
+}
+
+/// Compute `γ = -d ln(ω) / d ln(V)` for each mode of `reference`, using eigensolutions
+/// computed at volumes scaled by `1 - volume_step` and `1 + volume_step`.
+///
+/// The result is `None` for modes that could not be reliably matched, and for modes
+/// whose frequency is not positive at all three volumes.
+pub fn mode_gruneisen(
+    volume_step: f64,
+    reference: Modes<'_>,
+    shrunk: Modes<'_>,
+    expanded: Modes<'_>,
+    settings: MatchSettings,
+) -> Vec<Option<f64>> {
+    assert!(0.0 < volume_step && volume_step < 1.0, "bad volume step: {}", volume_step);
+
+    // (shrinking with the most negative allowed γ produces the largest relative change)
+    let max_relative_shift = (1.0 - volume_step).powf(-settings.max_abs_gruneisen) - 1.0;
+    let shrunk_matches = match_modes(reference, shrunk, max_relative_shift, settings);
+    let expanded_matches = match_modes(reference, expanded, max_relative_shift, settings);
+    let d_ln_volume = f64::ln((1.0 + volume_step) / (1.0 - volume_step));
+
+    zip_eq!(reference.frequencies, shrunk_matches, expanded_matches)
+        .map(|(&freq, shrunk_match, expanded_match)| {
+            let ((i_minus, overlap_minus), (i_plus, overlap_plus)) = match (shrunk_match, expanded_match) {
+                (Some(minus), Some(plus)) => (minus, plus),
+                _ => return None,
+            };
+            if overlap_minus < settings.min_overlap || overlap_plus < settings.min_overlap {
+                return None;
+            }
+            let freq_minus = shrunk.frequencies[i_minus];
+            let freq_plus = expanded.frequencies[i_plus];
+            if !(freq > 0.0 && freq_minus > 0.0 && freq_plus > 0.0) {
+                return None;
+            }
+            Some(-f64::ln(freq_plus / freq_minus) / d_ln_volume)
+        })
+        .collect()
+}
+
+/// Pair each reference mode with a distinct mode in `perturbed`.
+///
+/// The candidates for a reference mode of frequency `ω` are the perturbed modes within
+/// `max_relative_shift * |ω| + settings.frequency_slack` of it.  Pairs are assigned greedily
+/// in order of decreasing overlap.  For each reference mode, the output contains the index
+/// of its partner, and the squared overlap of the reference eigenvector with the partner's
+/// degenerate subspace; or `None` if all of its candidates were taken by other modes.
+pub fn match_modes(
+    reference: Modes<'_>,
+    perturbed: Modes<'_>,
+    max_relative_shift: f64,
+    settings: MatchSettings,
+) -> Vec<Option<(usize, f64)>> {
+    assert_eq!(reference.eigenvectors.len(), perturbed.eigenvectors.len(), "different number of modes");
+    let n = reference.eigenvectors.len();
+
+    // sorted by frequency, so that the modes near any frequency are a contiguous range
+    let sorted = SortedFrequencies::new(perturbed.frequencies);
+
+    let mut pairs: Vec<(usize, usize, f64)> = vec![];
+    for (i, (&freq, ket)) in zip_eq!(reference.frequencies, reference.eigenvectors).enumerate() {
+        let radius = max_relative_shift * freq.abs() + settings.frequency_slack;
+        for &j in sorted.within(freq, radius) {
+            pairs.push((i, j, sq_overlap(ket, &perturbed.eigenvectors[j])));
+        }
+    }
+    pairs.sort_by(|&(_, _, a), &(_, _, b)| b.partial_cmp(&a).expect("NaN overlap"));
+
+    let mut partners = vec![None; n];
+    let mut taken = vec![false; n];
+    for (i, j, _) in pairs {
+        if partners[i].is_none() && !taken[j] {
+            partners[i] = Some(j);
+            taken[j] = true;
+        }
+    }
+
+    partners.into_iter().enumerate().map(|(i, j)| {
+        j.map(|j| {
+            let subspace_overlap = {
+                sorted.within(perturbed.frequencies[j], settings.degeneracy_tol).iter()
+                    .map(|&k| sq_overlap(&reference.eigenvectors[i], &perturbed.eigenvectors[k]))
+                    .sum()
+            };
+            (j, subspace_overlap)
+        })
+    }).collect()
+}
+
+struct SortedFrequencies {
+    frequencies: Vec<f64>,
+    indices: Vec<usize>,
+}
+
+impl SortedFrequencies {
+    fn new(frequencies: &[f64]) -> Self {
+        let mut indices = (0..frequencies.len()).collect::<Vec<_>>();
+        indices.sort_by(|&a, &b| frequencies[a].partial_cmp(&frequencies[b]).expect("NaN frequency"));
+        let frequencies = indices.iter().map(|&i| frequencies[i]).collect();
+        SortedFrequencies { frequencies, indices }
+    }
+
+    /// Indices of the frequencies in `[center - radius, center + radius]`.
+    fn within(&self, center: f64, radius: f64) -> &[usize] {
+        // (the comparators never return Equal, so these always produce Err)
+        let position = |pred: &dyn Fn(f64) -> bool| {
+            self.frequencies.binary_search_by(|&x| match pred(x) {
+                true => Ordering::Less,
+                false => Ordering::Greater,
+            }).unwrap_err()
+        };
+        let start = position(&|x| x < center - radius);
+        let end = position(&|x| x <= center + radius);
+        &self.indices[start..end]
+    }
+}
+
+pub(crate) fn sq_overlap(a: &GammaKet3, b: &GammaKet3) -> f64 {
+    let dot = |a: &GammaKet3, b: &GammaKet3| -> f64 {
+        zip_eq!(&a.0, &b.0).map(|(x, y)| x.dot(y)).sum()
+    };
+    let ab = dot(a, b);
+    ab * ab / (dot(a, a) * dot(b, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsp2_array_types::V3;
+    use std::f64::consts::PI;
+
+    const SETTINGS: MatchSettings = MatchSettings {
+        min_overlap: 0.8,
+        degeneracy_tol: 1e-6,
+        max_abs_gruneisen: 20.0,
+        frequency_slack: 1e-6,
+    };
+
+    fn ket(xs: &[f64]) -> GammaKet3 {
+        GammaKet3(xs.iter().map(|&x| V3([x, 0.0, 0.0])).collect())
+    }
+
+    // Eigensolutions for a ring of `n` identical atoms joined by springs of stiffness `k`,
+    // moving along x.  Each degenerate pair of eigenvectors is rotated by `angle`, since
+    // an eigensolver would be free to return any basis of the degenerate subspace.
+    fn ring_modes(n: usize, k: f64, angle: f64) -> (Vec<f64>, Vec<GammaKet3>) {
+        let wave = |m: usize, f: fn(f64) -> f64| -> Vec<f64> {
+            (0..n).map(|j| f(2.0 * PI * (m * j) as f64 / n as f64)).collect()
+        };
+        let mut freqs = vec![];
+        let mut kets = vec![];
+        for m in 0..=n / 2 {
+            let freq = 2.0 * k.sqrt() * f64::sin(PI * m as f64 / n as f64);
+            if m == 0 || 2 * m == n {
+                freqs.push(freq);
+                kets.push(ket(&wave(m, f64::cos)));
+            } else {
+                let (cos, sin) = (wave(m, f64::cos), wave(m, f64::sin));
+                let (c, s) = (angle.cos(), angle.sin());
+                freqs.extend(vec![freq, freq]);
+                kets.push(ket(&zip_eq!(&cos, &sin).map(|(x, y)| c * x - s * y).collect::<Vec<_>>()));
+                kets.push(ket(&zip_eq!(&cos, &sin).map(|(x, y)| s * x + c * y).collect::<Vec<_>>()));
+            }
+        }
+        (freqs, kets)
+    }
+
+    #[test]
+    fn power_law_chain() {
+        // A chain with pair potential A r^-p has spring constant proportional to
+        // a^-(p+2), so every optical mode has γ = (p + 2) / 2 (V is proportional to a).
+        let p = 6.0;
+        let volume_step = 1e-2;
+        let stiffness = |v: f64| 5.0 * v.powf(-(p + 2.0));
+
+        let (freqs, kets) = ring_modes(6, stiffness(1.0), 0.0);
+        let (freqs_minus, kets_minus) = ring_modes(6, stiffness(1.0 - volume_step), 0.3);
+        let (freqs_plus, kets_plus) = ring_modes(6, stiffness(1.0 + volume_step), -1.1);
+
+        let gammas = mode_gruneisen(
+            volume_step,
+            Modes { frequencies: &freqs, eigenvectors: &kets },
+            Modes { frequencies: &freqs_minus, eigenvectors: &kets_minus },
+            Modes { frequencies: &freqs_plus, eigenvectors: &kets_plus },
+            SETTINGS,
+        );
+        assert_eq!(gammas[0], None); // acoustic
+        for &gamma in &gammas[1..] {
+            assert_close!(rel=1e-10, gamma.unwrap(), (p + 2.0) / 2.0);
+        }
+    }
+
+    #[test]
+    fn crossing_modes() {
+        let volume_step = 0.05;
+        let kets = vec![ket(&[1.0, 0.0]), ket(&[0.0, 1.0])];
+        let (gamma_a, gamma_b) = (3.0, -1.0);
+        let freqs_at = |v: f64| (10.0 * v.powf(-gamma_a), 10.5 * v.powf(-gamma_b));
+
+        let (a, b) = freqs_at(1.0);
+        let (a_minus, b_minus) = freqs_at(1.0 - volume_step);
+        let (a_plus, b_plus) = freqs_at(1.0 + volume_step);
+        assert!(a_plus < b_plus && a_minus > b_minus, "modes should cross");
+
+        // perturbed solutions are sorted by frequency, like an eigensolver would produce
+        let gammas = mode_gruneisen(
+            volume_step,
+            Modes { frequencies: &[a, b], eigenvectors: &kets },
+            Modes { frequencies: &[b_minus, a_minus], eigenvectors: &[kets[1].clone(), kets[0].clone()] },
+            Modes { frequencies: &[a_plus, b_plus], eigenvectors: &kets },
+            SETTINGS,
+        );
+        assert_close!(rel=1e-10, gammas[0].unwrap(), gamma_a);
+        assert_close!(rel=1e-10, gammas[1].unwrap(), gamma_b);
+    }
+
+    #[test]
+    fn poor_overlap() {
+        let kets = vec![ket(&[1.0, 0.0]), ket(&[0.0, 1.0])];
+        let s = 0.5f64.sqrt();
+        let mixed = vec![ket(&[s, s]), ket(&[s, -s])];
+        let gammas = mode_gruneisen(
+            1e-2,
+            Modes { frequencies: &[10.0, 20.0], eigenvectors: &kets },
+            Modes { frequencies: &[10.0, 20.0], eigenvectors: &mixed },
+            Modes { frequencies: &[10.0, 20.0], eigenvectors: &kets },
+            SETTINGS,
+        );
+        assert_eq!(gammas, vec![None, None]);
+    }
+
+    #[test]
+    fn frequency_window() {
+        let kets = vec![ket(&[1.0, 0.0]), ket(&[0.0, 1.0])];
+        let settings = MatchSettings { max_abs_gruneisen: 2.0, ..SETTINGS };
+
+        // the second mode moves further than |γ| <= 2 allows, so it is not even considered
+        // a candidate, despite having the same eigenvector
+        let matches = match_modes(
+            Modes { frequencies: &[10.0, 20.0], eigenvectors: &kets },
+            Modes { frequencies: &[10.1, 40.0], eigenvectors: &kets },
+            0.03,
+            settings,
+        );
+        assert_eq!(matches[0].map(|(j, _)| j), Some(0));
+        assert_eq!(matches[1], None);
+
+        let gammas = mode_gruneisen(
+            1e-2,
+            Modes { frequencies: &[10.0, 20.0], eigenvectors: &kets },
+            Modes { frequencies: &[10.0, 20.0], eigenvectors: &kets },
+            Modes { frequencies: &[10.0, 40.0], eigenvectors: &kets },
+            settings,
+        );
+        assert_eq!(gammas, vec![Some(0.0), None]);
+    }
+}
@@ -0,0 +1,109 @@
+/* ************************************************************************ **
+** This file is part of rsp2, and is licensed under EITHER the MIT license  **
+** or the Apache 2.0 license, at your option.                               **
+**                                                                          **
+**     http://www.apache.org/licenses/LICENSE-2.0                           **
+**     http://opensource.org/licenses/MIT                                   **
+**                                                                          **
+** Be aware that not all of rsp2 is provided under this permissive license, **
+** and that the project as a whole is licensed under the GPL 3.0.           **
+** ************************************************************************ */
+
+//! Infrared intensities of gamma eigenkets from Born effective charges.
+//!
+//! The IR intensity of a mode is `|dμ/dQ|^2`, where `μ` is the dipole moment of the cell
+//! and `Q` is the mass-weighted normal coordinate.  The derivative is given by
+//! `dμ_a/dQ = Σ_{κ,b} Z*_{κ,ab} e_{κb} / sqrt(M_κ)` in terms of the Born effective charge
+//! tensors `Z*`.
+//!
+//! None of the potentials in rsp2 currently provide charges, so the charges must come from
+//! elsewhere; the simplest option is the rigid-ion model (see `point_charges`), which is
+//! what the `ir` config section uses.
+
+use crate::math::basis::GammaKet3;
+use crate::meta::Mass;
+
+use rsp2_array_types::{V3, M33};
+
+/// Born effective charge tensors for a rigid-ion model, in which each site carries
+/// a fixed point charge (in units of the elementary charge) that moves with it.
+pub fn point_charges(charges: &[f64]) -> Vec<M33> {
+    charges.iter().map(|&q| M33::eye() * q).collect()
+}
+
+/// Interface for computing IR intensities.
+///
+/// Essentially, this struct exists to simulate named arguments.
+pub struct Input<'a> {
+    /// Normal mode eigenvectors, normalized.
+    pub ev_eigenvectors: &'a [GammaKet3],
+    pub site_masses: &'a [Mass],
+    /// Born effective charge tensor of each site, in units of the elementary charge.
+    ///
+    /// `born_charges[κ][a][b]` is the derivative of the `a`th component of the dipole moment
+    /// with respect to the `b`th coordinate of site `κ`.
+    pub born_charges: &'a [M33],
+}
+
+impl<'a> Input<'a> {
+    /// Compute `dμ/dQ` for each mode, in `e / sqrt(AMU)`.
+    pub fn compute_ev_dipole_derivatives(self) -> Vec<V3> {
+        let Input { ev_eigenvectors, site_masses, born_charges } = self;
+        assert_eq!(site_masses.len(), born_charges.len());
+
+        let inv_sqrt_masses = site_masses.iter().map(|&Mass(m)| 1.0 / m.sqrt()).collect::<Vec<_>>();
+        ev_eigenvectors.iter().map(|ket| {
+            zip_eq!(&ket.0, born_charges, &inv_sqrt_masses)
+                .map(|(&ev, charge, &inv_sqrt_mass)| charge * ev * inv_sqrt_mass)
+                .fold(V3::zero(), |a, b| a + b)
+        }).collect()
+    }
+
+    /// Compute the IR intensity `|dμ/dQ|^2` of each mode, in `e^2 / AMU`.
+    ///
+    /// These can be ranked with `bond_polarizability::most_active_modes`, just like
+    /// raman intensities.
+    pub fn compute_ev_intensities(self) -> Vec<f64> {
+        self.compute_ev_dipole_derivatives().iter().map(|d| d.sqnorm()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diatomic() {
+        // A heteronuclear diatomic molecule along x with charges +q and -q.
+        // Only the stretching mode is IR active.
+        let (m1, m2, q) = (1.0, 16.0, 0.4);
+        let site_masses = [Mass(m1), Mass(m2)];
+        let born_charges = point_charges(&[q, -q]);
+
+        let axis = |k: usize| V3::from_fn(|i| (i == k) as u32 as f64);
+        let normalized = |a: f64, b: f64| (a / f64::hypot(a, b), b / f64::hypot(a, b));
+
+        let mut ev_eigenvectors = vec![];
+        for k in 0..3 {
+            // translation
+            let (a, b) = normalized(m1.sqrt(), m2.sqrt());
+            ev_eigenvectors.push(GammaKet3(vec![axis(k) * a, axis(k) * b]));
+        }
+        // (rotations are excluded, since they would require a nonzero equilibrium dipole)
+        let (a, b) = normalized(m2.sqrt(), -m1.sqrt());
+        ev_eigenvectors.push(GammaKet3(vec![axis(0) * a, axis(0) * b]));
+
+        let intensities = Input {
+            ev_eigenvectors: &ev_eigenvectors,
+            site_masses: &site_masses,
+            born_charges: &born_charges,
+        }.compute_ev_intensities();
+
+        for &intensity in &intensities[..3] {
+            assert_close!(abs=1e-12, intensity, 0.0);
+        }
+        // dμ/dQ = q / sqrt(reduced mass)
+        let reduced_mass = m1 * m2 / (m1 + m2);
+        assert_close!(rel=1e-12, intensities[3], q * q / reduced_mass);
+    }
+}
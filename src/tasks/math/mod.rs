@@ -1,6 +1,12 @@
 pub(crate) mod bands;
 pub(crate) mod bond_polarizability;
+pub(crate) mod ir_intensity;
+pub(crate) mod raman_spectrum;
+pub(crate) mod gruneisen;
+pub(crate) mod peak_assignment;
+pub(crate) mod thermal;
 pub(crate) mod basis;
 pub(crate) mod stars;
 pub(crate) mod displacements;
+pub(crate) mod structure_change;
 pub(crate) mod frac_bonds_with_skin;
@@ -0,0 +1,102 @@
+/* ************************************************************************ **
+** This file is part of rsp2, and is licensed under EITHER the MIT license  **
+** or the Apache 2.0 license, at your option.                               **
+**                                                                          **
+**     http://www.apache.org/licenses/LICENSE-2.0                           **
+**     http://opensource.org/licenses/MIT                                   **
+**                                                                          **
+** Be aware that not all of rsp2 is provided under this permissive license, **
+** and that the project as a whole is licensed under the GPL 3.0.           **
+** ************************************************************************ */
+
+//! Assignment of computed mode frequencies to peaks of a reference (e.g. experimental)
+//! spectrum, for judging the quality of a potential.
+
+/// A one-to-one assignment between computed frequencies and reference peaks.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PeakAssignment {
+    /// Pairs of `(computed_index, reference_index)`, in order of increasing frequency.
+    pub pairs: Vec<(usize, usize)>,
+    /// Root mean square of `computed - reference` over the assigned pairs, in cm^-1.
+    pub rms_deviation: f64,
+}
+
+/// Assign each reference peak to a distinct computed frequency (or vice versa, if there are
+/// fewer computed frequencies than peaks), minimizing the sum of squared deviations.
+///
+/// Every element of the shorter list is assigned.  For a squared-deviation cost, an optimal
+/// assignment never has two pairs that "cross", so it can be found by dynamic programming
+/// over the two lists in sorted order.
+pub fn assign_peaks(computed: &[f64], reference: &[f64]) -> PeakAssignment {
+    if computed.len() < reference.len() {
+        let PeakAssignment { pairs, rms_deviation } = assign_peaks(reference, computed);
+        let pairs = pairs.into_iter().map(|(r, c)| (c, r)).collect();
+        return PeakAssignment { pairs, rms_deviation };
+    }
+    // from here on, every reference peak gets a partner
+
+    let argsort = |values: &[f64]| {
+        let mut indices = (0..values.len()).collect::<Vec<_>>();
+        indices.sort_by(|&a, &b| values[a].partial_cmp(&values[b]).expect("NaN frequency"));
+        indices
+    };
+    let computed_order = argsort(computed);
+    let reference_order = argsort(reference);
+    let (n_ref, n_comp) = (reference.len(), computed.len());
+
+    // cost[r][c]: best cost of assigning the first r reference peaks using the first c
+    //             computed frequencies. (infinite if c < r)
+    let mut cost = vec![vec![f64::INFINITY; n_comp + 1]; n_ref + 1];
+    cost[0] = vec![0.0; n_comp + 1];
+    for r in 1..=n_ref {
+        for c in r..=n_comp {
+            let diff = computed[computed_order[c - 1]] - reference[reference_order[r - 1]];
+            let matched = cost[r - 1][c - 1] + diff * diff;
+            let skipped = cost[r][c - 1];
+            cost[r][c] = f64::min(matched, skipped);
+        }
+    }
+
+    // trace back
+    let mut pairs = vec![];
+    let (mut r, mut c) = (n_ref, n_comp);
+    while r > 0 {
+        if cost[r][c] == cost[r][c - 1] {
+            c -= 1;
+        } else {
+            pairs.push((computed_order[c - 1], reference_order[r - 1]));
+            r -= 1;
+            c -= 1;
+        }
+    }
+    pairs.reverse();
+
+    let rms_deviation = match n_ref {
+        0 => 0.0,
+        _ => f64::sqrt(cost[n_ref][n_comp] / n_ref as f64),
+    };
+    PeakAssignment { pairs, rms_deviation }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assignment() {
+        // the closest computed frequency to both 870 and 890 is 880, but only one can have it
+        let computed = [1590.0, 880.0, 0.0, 400.0, 860.0, 1200.0];
+        let reference = [1580.0, 890.0, 870.0, 410.0];
+
+        let assignment = assign_peaks(&computed, &reference);
+        assert_eq!(assignment.pairs, vec![(3, 3), (4, 2), (1, 1), (0, 0)]);
+        let expected_rms = f64::sqrt((100.0 + 100.0 + 100.0 + 100.0) / 4.0);
+        assert_close!(assignment.rms_deviation, expected_rms);
+
+        // more peaks than computed frequencies
+        let swapped = assign_peaks(&reference, &computed);
+        assert_eq!(swapped.pairs, vec![(3, 3), (2, 4), (1, 1), (0, 0)]);
+        assert_close!(swapped.rms_deviation, expected_rms);
+    }
+}
@@ -0,0 +1,109 @@
+/* ************************************************************************ **
+** This file is part of rsp2, and is licensed under EITHER the MIT license  **
+** or the Apache 2.0 license, at your option.                               **
+**                                                                          **
+**     http://www.apache.org/licenses/LICENSE-2.0                           **
+**     http://opensource.org/licenses/MIT                                   **
+**                                                                          **
+** Be aware that not all of rsp2 is provided under this permissive license, **
+** and that the project as a whole is licensed under the GPL 3.0.           **
+** ************************************************************************ */
+
+//! Simulated spectra from per-mode frequencies and intensities.
+
+use std::f64::consts::PI;
+
+/// Determines how the intensity of a mode is mapped onto its Lorentzian.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Normalization {
+    /// The area under each peak is equal to the mode's intensity.
+    ///
+    /// With this normalization, the spectrum integrates to the sum of all intensities.
+    Area,
+    /// The height of each peak is equal to the mode's intensity.
+    Height,
+}
+
+/// Broaden a discrete set of `(frequency, intensity)` pairs into a spectrum
+/// by summing Lorentzians.
+///
+/// `linewidth` is the full width at half maximum of each Lorentzian.  The spectrum is
+/// sampled at `n_points` evenly spaced frequencies spanning `range` (inclusive), and
+/// is returned as `(frequency, intensity)` pairs.  Frequencies are in cm^-1.
+pub fn raman_spectrum(
+    modes: &[(f64, f64)],
+    linewidth: f64,
+    normalization: Normalization,
+    range: (f64, f64),
+    n_points: usize,
+) -> Vec<(f64, f64)> {
+    assert!(linewidth > 0.0, "linewidth must be positive");
+    assert!(n_points >= 2, "a spectrum needs at least two points");
+    assert!(range.0 < range.1, "empty range");
+
+    let half_width = 0.5 * linewidth;
+    let scale = match normalization {
+        Normalization::Area => half_width / PI,
+        Normalization::Height => half_width * half_width,
+    };
+
+    let step = (range.1 - range.0) / (n_points - 1) as f64;
+    (0..n_points).map(|i| {
+        let x = range.0 + step * i as f64;
+        let y = modes.iter().map(|&(center, intensity)| {
+            let dx = x - center;
+            intensity * scale / (dx * dx + half_width * half_width)
+        }).sum();
+        (x, y)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // all local maxima of a sampled function
+    fn peak_positions(spectrum: &[(f64, f64)]) -> Vec<f64> {
+        spectrum.windows(3)
+            .filter(|w| w[0].1 < w[1].1 && w[2].1 < w[1].1)
+            .map(|w| w[1].0)
+            .collect()
+    }
+
+    fn trapezoid(spectrum: &[(f64, f64)]) -> f64 {
+        spectrum.windows(2)
+            .map(|w| 0.5 * (w[1].0 - w[0].0) * (w[0].1 + w[1].1))
+            .sum()
+    }
+
+    #[test]
+    fn peaks_and_area() {
+        let modes = [(420.0, 0.5), (870.0, 2.0), (1580.0, 1.0)];
+
+        // wide enough that the heavy tails of the lorentzians barely matter
+        let range = (-1e5, 1e5);
+        let step = 0.05;
+        let n_points = ((range.1 - range.0) / step) as usize + 1;
+        let spectrum = raman_spectrum(&modes, 4.0, Normalization::Area, range, n_points);
+
+        let peaks = peak_positions(&spectrum);
+        assert_eq!(peaks.len(), modes.len());
+        for (&peak, &(frequency, _)) in zip_eq!(&peaks, &modes) {
+            assert_close!(abs=step, peak, frequency);
+        }
+
+        let total_intensity = modes.iter().map(|&(_, intensity)| intensity).sum::<f64>();
+        assert_close!(rel=1e-4, trapezoid(&spectrum), total_intensity);
+    }
+
+    #[test]
+    fn height_normalization() {
+        let modes = [(500.0, 3.0)];
+        let spectrum = raman_spectrum(&modes, 10.0, Normalization::Height, (490.0, 510.0), 5);
+
+        // sampled at 490, 495, 500, 505, 510: the center and the half maxima
+        assert_close!(spectrum[2].1, 3.0);
+        assert_close!(spectrum[1].1, 1.5);
+        assert_close!(spectrum[3].1, 1.5);
+    }
+}
@@ -0,0 +1,84 @@
+/* ************************************************************************ **
+** This file is part of rsp2, and is licensed under EITHER the MIT license  **
+** or the Apache 2.0 license, at your option.                               **
+**                                                                          **
+**     http://www.apache.org/licenses/LICENSE-2.0                           **
+**     http://opensource.org/licenses/MIT                                   **
+**                                                                          **
+** Be aware that not all of rsp2 is provided under this permissive license, **
+** and that the project as a whole is licensed under the GPL 3.0.           **
+** ************************************************************************ */
+
+//! Measures of how far the atoms of a structure have moved, e.g. during one iteration of
+//! the ev-loop.
+
+use rsp2_structure::Coords;
+
+/// How far atoms moved between two versions of a structure. (in angstroms)
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StructureChange {
+    /// The largest distance moved by a single atom.
+    pub max: f64,
+    /// Root mean square of the distance moved by each atom.
+    pub rms: f64,
+}
+
+impl StructureChange {
+    /// Compare two versions of a structure with the same atoms in the same order.
+    ///
+    /// Each atom is compared against the nearest image of its old position under the new
+    /// lattice, so that wrapping across the periodic boundary does not count as motion.
+    /// (changes in the lattice itself *do* count, to the extent that they move atoms)
+    pub fn between(before: &Coords, after: &Coords) -> Self {
+        assert_eq!(before.num_atoms(), after.num_atoms());
+        let lattice = after.lattice();
+
+        let distances: Vec<f64> = {
+            zip_eq!(before.to_carts(), after.to_carts())
+                .map(|(old, new)| {
+                    let frac = (new - old) / lattice;
+                    let frac = frac - frac.map(f64::round);
+                    (frac * lattice).norm()
+                })
+                .collect()
+        };
+
+        let max = distances.iter().cloned().fold(0.0, f64::max);
+        let rms = match distances.len() {
+            0 => 0.0,
+            n => f64::sqrt(distances.iter().map(|d| d * d).sum::<f64>() / n as f64),
+        };
+        StructureChange { max, rms }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsp2_structure::{CoordsKind, Lattice};
+    use rsp2_array_types::Envee;
+
+    #[test]
+    fn structure_change() {
+        let lattice = Lattice::orthorhombic(10.0, 10.0, 10.0);
+        let before = Coords::new(lattice.clone(), CoordsKind::Carts(vec![
+            [0.0, 0.0, 0.0],
+            [5.0, 5.0, 5.0],
+            [9.9, 0.0, 0.0],
+        ].envee()));
+        let after = Coords::new(lattice.clone(), CoordsKind::Carts(vec![
+            [0.0, 0.0, 0.0],
+            [5.0, 5.0, 5.3],
+            // wrapped across the boundary; actually moved by 0.2
+            [0.1, 0.0, 0.0],
+        ].envee()));
+
+        let change = StructureChange::between(&before, &after);
+        assert_close!(change.max, 0.3);
+        assert_close!(change.rms, f64::sqrt((0.0 + 0.09 + 0.04) / 3.0));
+
+        let change = StructureChange::between(&after, &after);
+        assert_eq!(change, StructureChange { max: 0.0, rms: 0.0 });
+    }
+}
@@ -0,0 +1,116 @@
+/* ************************************************************************ **
+** This file is part of rsp2, and is licensed under EITHER the MIT license  **
+** or the Apache 2.0 license, at your option.                               **
+**                                                                          **
+**     http://www.apache.org/licenses/LICENSE-2.0                           **
+**     http://opensource.org/licenses/MIT                                   **
+**                                                                          **
+** Be aware that not all of rsp2 is provided under this permissive license, **
+** and that the project as a whole is licensed under the GPL 3.0.           **
+** ************************************************************************ */
+
+//! Thermodynamic properties of a harmonic crystal.
+//!
+//! Frequencies are in cm^-1, as everywhere else in rsp2.  Energies are in eV and
+//! temperatures in Kelvin; all quantities are per unit cell (i.e. averaged over q-points).
+
+// = h * c / (eV * cm)
+const WAVENUMBER_TO_EV: f64 = 1.239841984e-4;
+// = k_B / (eV / K)
+const BOLTZMANN_EV: f64 = 8.617333262e-5;
+
+/// Modes at or below this frequency (cm^-1) are skipped. This excludes imaginary modes
+/// (which have no harmonic thermodynamics) and acoustic modes at Gamma (whose contribution
+/// vanishes in the limit of a dense q-mesh, but would diverge in the entropy).
+pub const MIN_FREQUENCY: f64 = 1e-1;
+
+/// Thermodynamic properties at a single temperature.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ThermoPoint {
+    /// Kelvin.
+    pub temperature: f64,
+    /// Helmholtz free energy (eV), including the zero-point energy.
+    pub free_energy: f64,
+    /// Vibrational entropy (eV/K).
+    pub entropy: f64,
+    /// Heat capacity at constant volume (eV/K).
+    pub heat_capacity: f64,
+}
+
+/// Compute harmonic thermodynamic properties from the frequencies at each point
+/// of a q-point mesh. (all q-points are weighted equally)
+pub fn thermal_properties(frequencies_by_q: &[Vec<f64>], temperatures: &[f64]) -> Vec<ThermoPoint> {
+    assert!(!frequencies_by_q.is_empty(), "no q-points");
+    let num_qpoints = frequencies_by_q.len() as f64;
+
+    let energies: Vec<f64> = {
+        frequencies_by_q.iter().flatten()
+            .filter(|&&freq| freq > MIN_FREQUENCY)
+            .map(|&freq| freq * WAVENUMBER_TO_EV)
+            .collect()
+    };
+
+    temperatures.iter().map(|&temperature| {
+        assert!(temperature >= 0.0, "negative temperature: {}", temperature);
+
+        let mut point = ThermoPoint { temperature, free_energy: 0.0, entropy: 0.0, heat_capacity: 0.0 };
+        for &energy in &energies {
+            point.free_energy += 0.5 * energy;
+            if temperature == 0.0 {
+                continue;
+            }
+
+            let kt = BOLTZMANN_EV * temperature;
+            let x = energy / kt;
+            // (written in terms of exp(-x) so that large x does not overflow)
+            let boltz = f64::exp(-x);
+            point.free_energy += kt * f64::ln_1p(-boltz);
+            point.entropy += BOLTZMANN_EV * (x * boltz / (1.0 - boltz) - f64::ln_1p(-boltz));
+            point.heat_capacity += BOLTZMANN_EV * x * x * boltz / ((1.0 - boltz) * (1.0 - boltz));
+        }
+        point.free_energy /= num_qpoints;
+        point.entropy /= num_qpoints;
+        point.heat_capacity /= num_qpoints;
+        point
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_oscillator() {
+        let freq = 500.0;
+        let energy = freq * WAVENUMBER_TO_EV;
+
+        // Two q-points, each with the same real mode, plus modes that should be ignored.
+        let frequencies_by_q = vec![
+            vec![-20.0, 0.0, freq],
+            vec![1e-3, freq],
+        ];
+        let temperatures = [0.0, 300.0, 2000.0];
+        let points = thermal_properties(&frequencies_by_q, &temperatures);
+
+        assert_eq!(points[0].free_energy, 0.5 * energy);
+        assert_eq!(points[0].entropy, 0.0);
+        assert_eq!(points[0].heat_capacity, 0.0);
+
+        for point in &points[1..] {
+            // textbook forms for a quantum harmonic oscillator
+            let half_x = 0.5 * energy / (BOLTZMANN_EV * point.temperature);
+            let log_2sinh = f64::ln(2.0 * half_x.sinh());
+            let free_energy = BOLTZMANN_EV * point.temperature * log_2sinh;
+            let entropy = BOLTZMANN_EV * (half_x / half_x.tanh() - log_2sinh);
+            let heat_capacity = BOLTZMANN_EV * (half_x / half_x.sinh()).powi(2);
+
+            assert_close!(rel=1e-10, point.free_energy, free_energy);
+            assert_close!(rel=1e-10, point.entropy, entropy);
+            assert_close!(rel=1e-10, point.heat_capacity, heat_capacity);
+        }
+
+        // classical limit
+        assert_close!(rel=2e-2, points[2].heat_capacity, BOLTZMANN_EV);
+    }
+}
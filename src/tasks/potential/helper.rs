@@ -78,6 +78,14 @@ where
 
     fn _eco_mode(&self, cont: &mut dyn FnMut())
     { (self.0)._eco_mode(&mut || (self.1)._eco_mode(cont)) }
+
+    fn interaction_cutoff(&self) -> Option<f64>
+    {
+        match (self.0.interaction_cutoff(), self.1.interaction_cutoff()) {
+            (Some(a), Some(b)) => Some(f64::max(a, b)),
+            (a, b) => a.or(b),
+        }
+    }
 }
 
 impl_dyn_clone_detail!{
@@ -106,6 +114,12 @@ where
         Ok((value, grad))
     }
 
+    fn compute_stress(&mut self, coords: &Coords, meta: M) -> FailResult<M33> {
+        let a_stress = self.0.compute_stress(coords, meta.clone())?;
+        let b_stress = self.1.compute_stress(coords, meta.clone())?;
+        Ok(a_stress + b_stress)
+    }
+
     fn check(&mut self, coords: &Coords, meta: M) -> FailResult<()> {
         self.0.check(coords, meta.clone())?;
         self.1.check(coords, meta.clone())?;
@@ -394,10 +408,31 @@ where Meta: Clone,
         (potential, out_grad)
     })}
 
+    fn compute_stress(&mut self, coords: &Coords, meta: Meta) -> FailResult<M33>
+    {Ok({
+        let (_, bond_grad) = self.0.compute(coords, meta)?;
+        stress_from_bond_grad(coords.lattice(), bond_grad)
+    })}
+
     fn check(&mut self, coords: &Coords, meta: Meta) -> FailResult<()>
     { self.0.check(coords, meta) }
 }
 
+/// Compute the stress tensor from the virial of the pair terms.
+///
+/// Straining the structure by `(I + eps)` maps each bond vector `r` to `(I + eps) r`,
+/// so `dE/d(eps_ab) = Sum(grad_a r_b)`.
+pub fn stress_from_bond_grad(lattice: &Lattice, bond_grads: impl IntoIterator<Item=BondGrad>) -> M33 {
+    let mut virial = M33::zero();
+    for BondGrad { grad, cart_vector, .. } in bond_grads {
+        virial += M33::from_fn(|a, b| grad[a] * cart_vector[b]);
+    }
+    // (the virial is only symmetric for rotationally invariant potentials,
+    //  but any asymmetry merely describes a rotation and is not a stress)
+    let virial = (&virial + &virial.t()) * 0.5;
+    virial / lattice.volume()
+}
+
 //--------------------------------
 
 pub fn sparse_grad_from_bond_grad(bond_grads: impl IntoIterator<Item=BondGrad>) -> BTreeMap<usize, V3> {
@@ -69,16 +69,16 @@ mod kc {
 
         fn initialize_disp_fn(&self, coords: &Coords, meta: CommonMeta) -> FailResult<Box<dyn DispFn>>
         { Ok(Box::new(self._initialize_disp_fn(coords, meta)?) as Box<_>) }
+
+        fn interaction_cutoff(&self) -> Option<f64>
+        { Some(self.params().cutoff_end()) }
     }
 
     impl Builder {
-        fn _initialize_bond_diff_fn(&self, coords: &Coords, meta: CommonMeta) -> FailResult<Diff>
-        {
+        fn params(&self) -> crespi_imp::Params {
             let cfg::PotentialKolmogorovCrespi {
-                cutoff_begin, cutoff_transition_dist, skin_depth, skin_check_frequency,
-                ref normals, ref params,
+                cutoff_begin, cutoff_transition_dist, ref params, ..
             } = self.cfg;
-            let parallel = self.parallel;
 
             let mut params = match params {
                 cfg::KolmogorovCrespiParams::Original => crespi_imp::Params::original(),
@@ -99,6 +99,16 @@ mod kc {
             } else {
                 // use value from Params::default()
             }
+            params
+        }
+
+        fn _initialize_bond_diff_fn(&self, coords: &Coords, meta: CommonMeta) -> FailResult<Diff>
+        {
+            let cfg::PotentialKolmogorovCrespi {
+                skin_depth, skin_check_frequency, ref normals, ..
+            } = self.cfg;
+            let parallel = self.parallel;
+            let params = self.params();
 
             let layers = self.find_layers(coords, &meta).by_atom();
 
@@ -797,3 +807,344 @@ mod rebo {
         Ok(())
     }
 }
+
+pub use morse::Morse;
+mod morse {
+    use super::*;
+
+    /// Rust implementation of an analytic Morse pair potential.
+    ///
+    /// Unlike the other potentials here, the set of interacting pairs is recomputed on every
+    /// call, so there are no limitations on how the structure may change.
+    #[derive(Debug, Clone)]
+    pub struct Morse {
+        pub(in crate::potential) cfg: cfg::PotentialMorse,
+        pub(in crate::potential) parallel: bool,
+    }
+
+    impl PotentialBuilder<CommonMeta> for Morse {
+        fn initialize_diff_fn(&self, coords: &Coords, meta: CommonMeta) -> FailResult<Box<dyn DiffFn<CommonMeta>>>
+        { Ok(Box::new(DiffFnFromBondDiffFn::new(self.initialize_bond_diff_fn(coords, meta)?.unwrap()))) }
+
+        fn parallel(&self, parallel: bool) -> Box<dyn PotentialBuilder<CommonMeta>> {
+            let mut me = self.clone();
+            me.parallel = parallel;
+            Box::new(me)
+        }
+
+        fn initialize_bond_diff_fn(&self, _: &Coords, _: CommonMeta) -> FailResult<Option<Box<dyn BondDiffFn<CommonMeta>>>>
+        { Ok(Some(Box::new(self._initialize_diff()?) as Box<_>)) }
+
+        fn initialize_pairwise_ddiff_fn(&self, _: &Coords, _: CommonMeta) -> FailResult<Option<Box<dyn PairwiseDDiffFn<CommonMeta>>>>
+        { Ok(Some(Box::new(self._initialize_diff()?) as Box<_>)) }
+
+        fn initialize_disp_fn(&self, coords: &Coords, meta: CommonMeta) -> FailResult<Box<dyn DispFn>>
+        { self._default_initialize_disp_fn(coords, meta) }
+
+        fn interaction_cutoff(&self) -> Option<f64>
+        {
+            self.cfg.pairs.iter()
+                .map(|pair| pair.cutoff)
+                .fold(None, |acc: Option<f64>, x| Some(acc.map_or(x, |acc| acc.max(x))))
+        }
+    }
+
+    impl_dyn_clone_detail!{
+        impl[] DynCloneDetail<CommonMeta> for Morse { ... }
+    }
+
+    impl Morse {
+        fn _initialize_diff(&self) -> FailResult<Diff> {
+            let mut params = BTreeMap::new();
+            for pair in &self.cfg.pairs {
+                let cfg::MorsePair { ref elements, d_e, a, r_e, cutoff } = *pair;
+                let key = pair_key(Element::from_symbol(&elements[0])?, Element::from_symbol(&elements[1])?);
+
+                ensure!(
+                    d_e > 0.0 && a > 0.0 && r_e > 0.0,
+                    "morse: d-e, a, and r-e must be positive (for {}-{})", elements[0], elements[1],
+                );
+                ensure!(
+                    cutoff > r_e,
+                    "morse: cutoff must be larger than r-e (for {}-{})", elements[0], elements[1],
+                );
+                if params.insert(key, Params { d_e, a, r_e, cutoff }).is_some() {
+                    bail!("morse: the pair {}-{} is listed more than once", elements[0], elements[1]);
+                }
+            }
+            Ok(Diff { params, parallel: self.parallel })
+        }
+    }
+
+    fn pair_key(a: Element, b: Element) -> (Element, Element)
+    { (a.min(b), a.max(b)) }
+
+    #[derive(Debug, Copy, Clone)]
+    struct Params {
+        d_e: f64,
+        a: f64,
+        r_e: f64,
+        cutoff: f64,
+    }
+
+    impl Params {
+        /// Value of a single pair term, and its first and second derivatives with respect to `r`.
+        fn compute(&self, r: f64) -> (f64, f64, f64) {
+            let Params { d_e, a, r_e, cutoff: _ } = *self;
+            let exp = f64::exp(-a * (r - r_e));
+            let value = d_e * (exp * exp - 2.0 * exp);
+            let d_value = 2.0 * a * d_e * (exp - exp * exp);
+            let dd_value = 2.0 * a * a * d_e * (2.0 * exp * exp - exp);
+            (value, d_value, dd_value)
+        }
+    }
+
+    struct Diff {
+        params: BTreeMap<(Element, Element), Params>,
+        parallel: bool,
+    }
+
+    impl BondDiffFn<CommonMeta> for Diff {
+        fn compute(&mut self, coords: &Coords, meta: CommonMeta) -> FailResult<(f64, Vec<BondGrad>)> {
+            let (value, terms) = self.compute_terms(coords, meta)?;
+            Ok((value, terms.into_iter().map(|(bond_grad, _)| bond_grad).collect()))
+        }
+    }
+
+    impl PairwiseDDiffFn<CommonMeta> for Diff {
+        fn compute(&mut self, coords: &Coords, meta: CommonMeta) -> FailResult<(f64, Vec<(BondGrad, M33)>)> {
+            self.compute_terms(coords, meta)
+        }
+    }
+
+    impl Diff {
+        /// Compute one term per interacting pair, along with its hessian with respect to the
+        /// bond vector.
+        fn compute_terms(&self, coords: &Coords, meta: CommonMeta) -> FailResult<(f64, Vec<(BondGrad, M33)>)> {
+            let elements: meta::SiteElements = meta.pick();
+            let frac_bonds = FracBonds::compute_with_meta(
+                coords,
+                elements.iter().cloned(),
+                |&a, &b| self.params.get(&pair_key(a, b)).map(|params| params.cutoff),
+            )?;
+
+            let lattice = coords.lattice();
+            let carts = coords.to_carts();
+
+            // HACK: collect to vec so that it implements IntoParallelIterator
+            let frac_bonds = frac_bonds.into_iter().filter(|bond| bond.is_canonical()).collect::<Vec<_>>();
+            // HACK: collect from Rc<[_]> to Vec to impl Send
+            let elements = elements.iter().cloned().collect::<Vec<_>>();
+
+            let (part_values, terms): (Vec<f64>, Vec<_>) = {
+                CondIterator::new(frac_bonds, self.parallel)
+                    .map(|bond| {
+                        debug_assert!(bond.is_canonical());
+                        let params = &self.params[&pair_key(elements[bond.from], elements[bond.to])];
+                        let cart_vector = bond.cart_vector_using_carts(lattice, &carts);
+                        let r = cart_vector.norm();
+                        let (value, d_value, dd_value) = params.compute(r);
+
+                        // For V(|r|), the hessian is V'' (u u^T) + (V' / |r|) (I - u u^T)
+                        let unit = cart_vector / r;
+                        let outer = M33::from_fn(|i, j| unit[i] * unit[j]);
+                        let hessian = &outer * dd_value + (M33::eye() - &outer) * (d_value / r);
+
+                        let bond_grad = BondGrad {
+                            plus_site: bond.to,
+                            minus_site: bond.from,
+                            grad: unit * d_value,
+                            cart_vector,
+                        };
+                        (value, (bond_grad, hessian))
+                    }).unzip()
+            };
+            let value = part_values.iter().sum();
+            Ok((value, terms))
+        }
+    }
+
+    #[cfg(test)]
+    #[deny(unused)]
+    mod tests {
+        use super::*;
+        use crate::FailOk;
+        use crate::filetypes::eigensols::eigenvalue_to_frequency;
+        use rsp2_structure::{Lattice, CoordsKind, supercell};
+        use rsp2_dynmat::ForceConstants;
+        use rsp2_soa_ops::Perm;
+        use rsp2_minimize::numerical;
+        use rsp2_array_types::Unvee;
+        use slice_of_array::prelude::*;
+
+        fn make_potential() -> Box<dyn PotentialBuilder> {
+            // (a zero potential is included to ensure that Morse works inside of Sum)
+            PotentialBuilder::from_config_parts(
+                None,
+                None,
+                &cfg::Threading::Serial,
+                &from_json!({ }),
+                None,
+                &from_json!([
+                    {"morse": {"pairs": [
+                        {"elements": ["C", "C"], "d-e": 6.3, "a": 2.0, "r-e": 1.24, "cutoff": 6.0},
+                        {"elements": ["H", "C"], "d-e": 3.6, "a": 1.8, "r-e": 1.12, "cutoff": 6.0},
+                    ]}},
+                    "test-func-zero",
+                ]),
+            ).unwrap()
+        }
+
+        // an arbitrary, slightly irregular structure with both C-C and H-C pairs in range
+        fn make_ch_structure() -> (Coords, CommonMeta) {
+            let coords = Coords::new(Lattice::cubic(4.0), CoordsKind::Carts(vec![
+                V3([0.1, 0.2, 0.3]),
+                V3([1.4, 0.1, 0.2]),
+                V3([0.5, 1.2, 3.6]),
+            ]));
+            let elements: meta::SiteElements = vec![Element::CARBON, Element::CARBON, Element::HYDROGEN].into();
+            let masses: meta::SiteMasses = vec![meta::Mass(12.0), meta::Mass(12.0), meta::Mass(1.0)].into();
+            (coords, hlist![elements, masses, None])
+        }
+
+        #[test]
+        fn morse_gradient() -> FailResult<()> {
+            let (coords, meta) = make_ch_structure();
+            let pot = make_potential();
+
+            let (_, grad) = pot.one_off().compute(&coords, meta.clone())?;
+            let num_grad = numerical::try_gradient(1e-4, None, coords.to_carts().flat(), |carts| {
+                let coords = coords.clone().with_carts(carts.nest().to_vec());
+                FailOk(pot.one_off().compute(&coords, meta.clone())?.0)
+            })?;
+            assert_close!(rel=1e-7, grad.flat(), &num_grad[..]);
+            Ok(())
+        }
+
+        #[test]
+        fn morse_stress() -> FailResult<()> {
+            let (coords, meta) = make_ch_structure();
+            let pot = make_potential();
+
+            let stress = pot.one_off().compute_stress(&coords, meta.clone())?;
+
+            // dE/d(strain) by finite differences, deforming the lattice with fixed fracs
+            let volume = coords.lattice().volume();
+            let h = 1e-5;
+            let value_at_strain = |a: usize, b: usize, amount: f64| FailOk({
+                // (symmetric strain, so that rotations don't contribute)
+                let strain = M33::from_fn(|r, c| {
+                    let mut x = 0.0;
+                    if (r, c) == (a, b) { x += 0.5 * amount; }
+                    if (r, c) == (b, a) { x += 0.5 * amount; }
+                    x
+                });
+                let lattice = coords.lattice().transformed_by(&(M33::eye() + strain));
+                let strained = Coords::new(lattice, CoordsKind::Fracs(coords.to_fracs()));
+                pot.one_off().compute_value(&strained, meta.clone())?
+            });
+            let mut num_stress = M33::zero();
+            for a in 0..3 {
+                for b in 0..3 {
+                    let plus = value_at_strain(a, b, h)?;
+                    let minus = value_at_strain(a, b, -h)?;
+                    num_stress[a][b] = (plus - minus) / (2.0 * h) / volume;
+                }
+            }
+            assert_close!(rel=1e-6, abs=1e-9, stress.unvee(), num_stress.unvee());
+            Ok(())
+        }
+
+        #[test]
+        fn morse_dimer_frequency() -> FailResult<()> {
+            let (d_e, a, r_e, mass) = (6.3, 2.0, 1.24, 12.0);
+
+            // an isolated dimer along an arbitrary direction
+            let direction = V3([1.0, 2.0, 2.0]) / 3.0;
+            let coords = Coords::new(Lattice::cubic(15.0), CoordsKind::Carts(vec![
+                V3([5.0, 5.0, 5.0]),
+                V3([5.0, 5.0, 5.0]) + direction * r_e,
+            ]));
+            let elements: meta::SiteElements = vec![Element::CARBON; 2].into();
+            let masses: meta::SiteMasses = vec![meta::Mass(mass); 2].into();
+            let meta: CommonMeta = hlist![elements, masses, None];
+            let pot = make_potential();
+
+            // force constants from finite differences, with no symmetry
+            let (super_coords, sc) = supercell::diagonal([1, 1, 1]).build(&coords);
+            let mut disp_fn = pot.initialize_disp_fn(&super_coords, meta.clone())?;
+            let mut displacements = vec![];
+            for atom in 0..2 {
+                for k in 0..3 {
+                    for &sign in &[1.0, -1.0] {
+                        displacements.push((atom, V3::from_fn(|i| if i == k { sign * 1e-4 } else { 0.0 })));
+                    }
+                }
+            }
+            let force_sets = {
+                displacements.iter()
+                    .map(|&disp| disp_fn.compute_sparse_force_delta(disp))
+                    .collect::<FailResult<Vec<_>>>()?
+            };
+            let force_constants = ForceConstants::compute_required_rows(
+                &displacements, &force_sets, &[M33::eye()], &[Perm::eye(2)], &sc,
+            )?;
+
+            let dynmat = force_constants.dynmat_at_cart_q(&super_coords, V3::zero(), &sc, &[mass; 2]).hermitianize();
+            let (eigenvalues, _) = dynmat.compute_eigensolutions_dense_gamma();
+            let mut frequencies = eigenvalues.eigenvalues.into_iter().map(eigenvalue_to_frequency).collect::<Vec<_>>();
+            frequencies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+            // k = 2 D_e a^2, and the reduced mass is m / 2
+            let expected = eigenvalue_to_frequency(2.0 * d_e * a * a / (mass / 2.0));
+            // (the rotational modes pick up a bit of noise from the finite differences)
+            assert_close!(abs=1.0, frequencies[..5].to_vec(), vec![0.0; 5]);
+            assert_close!(rel=1e-6, frequencies[5], expected);
+
+            // the analytic hessian should agree
+            let mut ddiff_fn = pot.initialize_pairwise_ddiff_fn(&coords, meta.clone())?.unwrap();
+            let (_, terms) = ddiff_fn.compute(&coords, meta.clone())?;
+            let hessian = terms.iter().fold(M33::zero(), |acc, (_, hessian)| acc + hessian);
+            let expected_hessian = M33::from_fn(|i, j| 2.0 * d_e * a * a * direction[i] * direction[j]);
+            assert_close!(abs=1e-10, hessian.unvee(), expected_hessian.unvee());
+            Ok(())
+        }
+
+        #[test]
+        fn sum_skips_disabled_terms() -> FailResult<()> {
+            let (coords, meta) = make_ch_structure();
+
+            let build = |potential: serde_json::Value| {
+                PotentialBuilder::from_config_parts(
+                    None,
+                    None,
+                    &cfg::Threading::Serial,
+                    &from_json!({ }),
+                    None,
+                    &from_json!(potential),
+                )
+            };
+            let cc = serde_json::json!({"morse": {"pairs": [
+                {"elements": ["C", "C"], "d-e": 6.3, "a": 2.0, "r-e": 1.24, "cutoff": 6.0},
+            ]}});
+            let hc = serde_json::json!({"morse": {"pairs": [
+                {"elements": ["H", "C"], "d-e": 3.6, "a": 1.8, "r-e": 1.12, "cutoff": 6.0},
+            ]}});
+            let hc_disabled = serde_json::json!({"morse": hc["morse"].clone(), "enabled": false});
+
+            let compute = |potential: serde_json::Value| FailOk({
+                build(potential)?.one_off().compute(&coords, meta.clone())?
+            });
+            let (value_all, _) = compute(serde_json::json!([cc.clone(), hc]))?;
+            let (value_some, grad_some) = compute(serde_json::json!([cc.clone(), hc_disabled]))?;
+            let (value_cc, grad_cc) = compute(serde_json::json!([cc]))?;
+
+            // (make sure the disabled term would have contributed)
+            assert!((value_all - value_cc).abs() > 1e-3);
+
+            assert_close!(rel=1e-12, value_some, value_cc);
+            assert_close!(rel=1e-12, grad_some.flat(), grad_cc.flat());
+            Ok(())
+        }
+    }
+}
@@ -26,10 +26,10 @@ use crate::FailResult;
 use crate::meta::{self, prelude::*};
 #[allow(unused)] // rustc bug
 use rsp2_soa_ops::{Part, Partition};
-use rsp2_structure::{Coords, consts};
+use rsp2_structure::{Coords, Lattice, consts};
 use rsp2_structure::layer::Layers;
 use rsp2_tasks_config as cfg;
-use rsp2_array_types::{V3};
+use rsp2_array_types::{V3, M33};
 use std::collections::BTreeMap;
 use crate::cmd::trial::TrialDir;
 
@@ -37,9 +37,13 @@ use rsp2_lammps_wrap::{InitInfo, AtomType, PairStyle, PairCoeff};
 use rsp2_lammps_wrap::Builder as InnerBuilder;
 use rsp2_lammps_wrap::Potential as LammpsPotential;
 use rsp2_lammps_wrap::UpdateStyle;
+use rsp2_lammps_wrap::NeighborStats;
 use rsp2_lammps_wrap::LammpsOnDemand;
 use rsp2_lammps_wrap::INSTANCE_LOCK;
 
+// LAMMPS' `units metal` reports pressure in bars.
+const BAR_PER_EV_PER_CUBIC_ANGSTROM: f64 = 1.602176634e6;
+
 const DEFAULT_KC_Z_CUTOFF: f64 = 14.0; // (Angstrom?)
 const DEFAULT_KC_Z_MAX_LAYER_SEP: f64 = 4.5; // Angstrom
 const DEFAULT_KC_FULL_CUTOFF: f64 = 14.0; // (Angstrom?)
@@ -59,7 +63,11 @@ pub(crate) struct Builder<P> {
     inner: InnerBuilder,
     pub potential: P,
     allow_blocking: bool,
-    processor_axis_mask: [bool; 3],
+    processor_axis_mask: cfg::ProcessorAxisMask,
+    // axes marked as non-periodic in `parameters`, for `ProcessorAxisMask::Auto`
+    not_periodic: [bool; 3],
+    parallel: bool,
+    openmp_threads: Option<u32>,
 }
 
 fn assert_send_sync<S: Send + Sync>() {}
@@ -76,6 +84,9 @@ impl<P: Clone> Builder<P>
         on_demand: Option<LammpsOnDemand>,
         threading: &cfg::Threading,
         lammps_cfg: &cfg::Lammps,
+        parameters: Option<&cfg::Parameters>,
+        // only meaningful for potentials with an /omp pair style
+        openmp_threads: Option<u32>,
         potential: P,
     ) -> FailResult<Self> {
         let cfg::Lammps { update_style, processor_axis_mask } = lammps_cfg;
@@ -87,17 +98,19 @@ impl<P: Clone> Builder<P>
             inner.append_log(trial_dir.as_path().join("lammps.log"));
             inner.debug_dir(Some(trial_dir.as_path()));
         }
-        inner.update_style(match *update_style {
+        let style = match *update_style {
             cfg::LammpsUpdateStyle::Safe => UpdateStyle::safe(),
             cfg::LammpsUpdateStyle::Run{ n, pre, post, sync_positions_every } => {
                 warn_once!("lammps-update-style: run' is only for debugging purposes");
                 UpdateStyle { n, pre, post, sync_positions_every }
             },
-            cfg::LammpsUpdateStyle::Fast { sync_positions_every } => {
+            cfg::LammpsUpdateStyle::Fast { sync_positions_every, warn_rebuild_fraction } => {
                 warn_once!("'lammps-update-style: fast' is experimental");
+                inner.warn_neighbor_build_fraction(Some(warn_rebuild_fraction));
                 UpdateStyle::fast(sync_positions_every)
             },
-        });
+        };
+        inner.update_style(style);
         if let Some(on_demand) = on_demand {
             inner.on_demand(on_demand);
         }
@@ -121,25 +134,72 @@ impl<P: Clone> Builder<P>
 
         let allow_blocking = false;
         let processor_axis_mask = *processor_axis_mask;
+        let not_periodic = match parameters {
+            Some(parameters) => V3(*parameters).map(|p| p == cfg::Parameter::NotPeriodic).0,
+            None => [false; 3],
+        };
+        let parallel = false; // overwritten below
 
         Ok({
-            Builder { inner, allow_blocking, potential, processor_axis_mask }
+            Builder { inner, allow_blocking, potential, processor_axis_mask, not_periodic, parallel, openmp_threads }
                 .parallel(*threading == cfg::Threading::Lammps)
         })
     }
 
     pub(crate) fn parallel(&self, parallel: bool) -> Self {
-        let processors = match parallel {
-            true => V3(self.processor_axis_mask).map(|flag| if flag { None } else { Some(1) }).0,
+        let mut me = self.clone();
+        me.parallel = parallel;
+        me.inner.openmp_threads(openmp_threads_for(self.openmp_threads, parallel));
+        me
+    }
+
+    // The processor grid can depend on the structure, so it is only set right before building.
+    fn inner_for(&self, coords: &Coords) -> InnerBuilder {
+        let mask = match self.processor_axis_mask {
+            cfg::ProcessorAxisMask::Fixed(mask) => mask,
+            cfg::ProcessorAxisMask::Auto => auto_processor_axis_mask(coords.lattice(), self.not_periodic),
+        };
+        let processors = match self.parallel {
+            true => V3(mask).map(|flag| if flag { None } else { Some(1) }).0,
             false => [Some(1); 3],
         };
 
-        let mut me = self.clone();
-        me.inner.processors(processors);
-        me
+        let mut inner = self.inner.clone();
+        inner.processors(processors);
+        inner
     }
 }
 
+// Decomposing an axis shorter than this (in Angstroms) is unlikely to be worth
+// the extra communication between processes.
+const AUTO_PROCESSOR_AXIS_MIN_LENGTH: f64 = 10.0;
+
+fn auto_processor_axis_mask(lattice: &Lattice, not_periodic: [bool; 3]) -> [bool; 3] {
+    let lengths = lattice.norms();
+    V3::from_fn(|k| !not_periodic[k] && lengths[k] >= AUTO_PROCESSOR_AXIS_MIN_LENGTH).0
+}
+
+#[test]
+fn test_auto_processor_axis_mask() {
+    // 10x10 supercell of graphene, with vacuum along z
+    let lattice = Lattice::from_vectors(&[
+        V3([24.6, 0.0, 0.0]),
+        V3([-12.3, 21.304224933097, 0.0]),
+        V3([0.0, 0.0, 30.0]),
+    ]);
+    assert_eq!(auto_processor_axis_mask(&lattice, [false, false, true]), [true, true, false]);
+    // without `parameters`, only the lengths are considered
+    assert_eq!(auto_processor_axis_mask(&lattice, [false; 3]), [true, true, true]);
+
+    // primitive cell of graphene; too small to be worth dividing up at all
+    let lattice = Lattice::from_vectors(&[
+        V3([2.46, 0.0, 0.0]),
+        V3([-1.23, 2.1304224933097, 0.0]),
+        V3([0.0, 0.0, 30.0]),
+    ]);
+    assert_eq!(auto_processor_axis_mask(&lattice, [false, false, true]), [false, false, false]);
+}
+
 impl<M: Clone + 'static, P: LammpsPotential<Meta=M> + Clone + Send + Sync + 'static> Builder<P>
 {
     /// Initialize Lammps to make a DiffFn.
@@ -161,6 +221,20 @@ impl<M: Clone + 'static, P: LammpsPotential<Meta=M> + Clone + Send + Sync + 'sta
                 let grad = lmp.compute_grad()?;
                 Ok((value, grad))
             }
+
+            fn compute_stress(&mut self, coords: &Coords, meta: Mm) -> FailResult<M33> {
+                let lmp = &mut self.0;
+
+                lmp.set_structure(coords.clone(), meta)?;
+                let [xx, yy, zz, xy, xz, yz] = lmp.compute_pressure()?;
+                let pressure = rsp2_array_types::mat::from_array([
+                    [xx, xy, xz],
+                    [xy, yy, yz],
+                    [xz, yz, zz],
+                ]);
+                // stress is the negative of pressure
+                Ok(pressure * (-1.0 / BAR_PER_EV_PER_CUBIC_ANGSTROM))
+            }
         }
 
         // (panic on lock already acquired; blocking could easily deadlock)
@@ -173,7 +247,7 @@ impl<M: Clone + 'static, P: LammpsPotential<Meta=M> + Clone + Send + Sync + 'sta
         };
 
         let lammps_pot = Box::new(self.potential.clone()) as Box<dyn LammpsPotential<Meta=P::Meta>>;
-        let lmp = self.inner.build(lock, lammps_pot, coords.clone(), meta)?;
+        let lmp = self.inner_for(coords).build(lock, lammps_pot, coords.clone(), meta)?;
         Ok(Box::new(MyDiffFn::<M>(lmp)) as Box<_>)
     }
 
@@ -193,13 +267,33 @@ impl<M: Clone + 'static, P: LammpsPotential<Meta=M> + Clone + Send + Sync + 'sta
         }
 
         // (panic on lock already acquired; blocking could easily deadlock)
+        //
+        // The lock is held for the lifetime of the DispFn, so force sets can only ever be
+        // computed one displacement at a time with LAMMPS.  This is why config validation
+        // forbids `threading.rayon.max-concurrent-displacements > 1` with LAMMPS potentials.
         let lock = INSTANCE_LOCK.try_lock().expect("Tried to construct multiple Lammps instances in parallel");
 
-        let lmp_disp_fn = self.inner.build_disp_fn(lock, self.potential.clone(), coords.clone(), meta)?;
+        let lmp_disp_fn = self.inner_for(coords).build_disp_fn(lock, self.potential.clone(), coords.clone(), meta)?;
         Ok(Box::new(MyDispFn(lmp_disp_fn)) as Box<_>)
     }
 }
 
+// (when no count was configured, LAMMPS is left to use OMP_NUM_THREADS as it always has)
+fn openmp_threads_for(configured: Option<u32>, parallel: bool) -> Option<u32> {
+    match parallel {
+        true => configured,
+        false => configured.map(|_| 1),
+    }
+}
+
+#[test]
+fn test_openmp_threads_for() {
+    assert_eq!(openmp_threads_for(Some(4), true), Some(4));
+    assert_eq!(openmp_threads_for(Some(4), false), Some(1));
+    assert_eq!(openmp_threads_for(None, true), None);
+    assert_eq!(openmp_threads_for(None, false), None);
+}
+
 impl<M: Clone + 'static, P: Clone + LammpsPotential<Meta=M> + Send + Sync + 'static> PotentialBuilder<M> for Builder<P>
 {
     fn parallel(&self, parallel: bool) -> Box<dyn PotentialBuilder<M>>
@@ -230,6 +324,16 @@ impl_dyn_clone_detail!{
     DynCloneDetail<M> for Builder<P> { ... }
 }
 
+/// Neighbor list statistics summed over every LAMMPS instance used so far by this process,
+/// or `None` if LAMMPS was never used.
+pub(crate) fn neighbor_stats() -> Option<NeighborStats> {
+    let stats = rsp2_lammps_wrap::total_neighbor_stats();
+    match stats.runs {
+        0 => None,
+        _ => Some(stats),
+    }
+}
+
 pub use self::overlay::Overlay;
 mod overlay {
     use super::*;
@@ -424,7 +528,7 @@ mod airebo {
 
     impl<'a> From<&'a cfg::LammpsPotentialAirebo> for Airebo {
         fn from(cfg: &'a cfg::LammpsPotentialAirebo) -> Self {
-            let cfg::LammpsPotentialAirebo { lj_sigma, lj_enabled, torsion_enabled, omp } = *cfg;
+            let cfg::LammpsPotentialAirebo { lj_sigma, lj_enabled, torsion_enabled, omp, omp_threads: _ } = *cfg;
             Airebo::Airebo {
                 lj_sigma: lj_sigma.unwrap_or(DEFAULT_AIREBO_LJ_SIGMA),
                 lj_enabled: lj_enabled.unwrap_or(DEFAULT_AIREBO_LJ_ENABLED),
@@ -436,7 +540,7 @@ mod airebo {
 
     impl<'a> From<&'a cfg::LammpsPotentialRebo> for Airebo {
         fn from(cfg: &'a cfg::LammpsPotentialRebo) -> Self {
-            let cfg::LammpsPotentialRebo { omp } = *cfg;
+            let cfg::LammpsPotentialRebo { omp, omp_threads: _ } = *cfg;
             Airebo::Rebo {
                 omp: omp.unwrap_or(DEFAULT_REBO_OMP),
             }
@@ -205,6 +205,13 @@ pub trait PotentialBuilder<Meta = CommonMeta>
     /// The default implementation simply calls `cont`.
     fn _eco_mode(&self, cont: &mut dyn FnMut())
     { cont() }
+
+    /// The largest distance at which two atoms can directly interact, if known.
+    ///
+    /// This is only used for sanity checks (e.g. of the phonon supercell), so the default
+    /// implementation simply returns `None`.
+    fn interaction_cutoff(&self) -> Option<f64>
+    { None }
 }
 
 // On the trait object rather than the trait to dodge Self: Sized issues.
@@ -319,6 +326,9 @@ where Meta: Clone + 'static,
 
     fn _eco_mode(&self, cont: &mut dyn FnMut())
     { (**self)._eco_mode(cont) }
+
+    fn interaction_cutoff(&self) -> Option<f64>
+    { (**self).interaction_cutoff() }
 }
 
 impl_dyn_clone_detail!{
@@ -355,6 +365,15 @@ pub trait DiffFn<Meta> {
         Ok(force)
     }
 
+    /// Compute the stress tensor `(1/V) dE/d(strain)` in `eV/A^3`, which is symmetric.
+    ///
+    /// The sign is such that a negative diagonal element indicates compression
+    /// (i.e. the structure would like to expand along that axis).
+    ///
+    /// Not all potentials support this; the default implementation fails.
+    fn compute_stress(&mut self, _: &Coords, _: Meta) -> FailResult<M33>
+    { bail!("the stress tensor is not implemented for this potential") }
+
     /// Check if a structure is within tolerable limits for the potential.
     ///
     /// For example, the rust reimplementation of REBO does not support bond lengths
@@ -385,6 +404,9 @@ impl<'d, Meta> DiffFn<Meta> for Box<dyn DiffFn<Meta> + 'd> {
     fn compute_force(&mut self, coords: &Coords, meta: Meta) -> FailResult<Vec<V3>>
     { (**self).compute_force(coords, meta) }
 
+    fn compute_stress(&mut self, coords: &Coords, meta: Meta) -> FailResult<M33>
+    { (**self).compute_stress(coords, meta) }
+
     fn check(&mut self, coords: &Coords, meta: Meta) -> FailResult<()>
     { (**self).check(coords, meta) }
 }
@@ -545,6 +567,10 @@ impl<'a, M: Clone + 'static> DiffFn<M> for OneOff<'a, M> {
     fn compute(&mut self, coords: &Coords, meta: M) -> FailResult<(f64, Vec<V3>)> {
         self.0.initialize_diff_fn(coords, meta.clone())?.compute(coords, meta)
     }
+
+    fn compute_stress(&mut self, coords: &Coords, meta: M) -> FailResult<M33> {
+        self.0.initialize_diff_fn(coords, meta.clone())?.compute_stress(coords, meta)
+    }
 }
 
 //-------------------------------------
@@ -561,6 +587,7 @@ impl dyn PotentialBuilder {
             on_demand,
             &cfg.threading,
             &cfg.lammps,
+            cfg.parameters.as_ref(),
             &cfg.potential,
         )
     }
@@ -570,21 +597,36 @@ impl dyn PotentialBuilder {
         mut on_demand: Option<LammpsOnDemand>,
         threading: &cfg::Threading,
         lammps: &cfg::Lammps,
+        parameters: Option<&cfg::Parameters>,
         config: &cfg::ValidatedPotential,
     ) -> FailResult<Box<dyn PotentialBuilder>> {
         let cfg::ValidatedPotential(config) = config;
+        if config.as_slice().len() > 1 {
+            let names = |enabled| {
+                config.as_slice().iter()
+                    .filter(|entry| entry.enabled == enabled)
+                    .map(|entry| entry.kind.name())
+                    .collect::<Vec<_>>()
+            };
+            let (active, disabled) = (names(true), names(false));
+            match disabled.len() {
+                0 => info!("Active potential terms: {}", active.join(", ")),
+                _ => info!("Active potential terms: {} (disabled: {})", active.join(", "), disabled.join(", ")),
+            }
+        }
+
         let mut iter = {
             let mut found_lammps = false;
-            config.as_slice().iter()
+            config.enabled_kinds()
                 .map(|cfg| {
                     match cfg {
                         // (give the LammpsOnDemand to the Lammps potential if there is one)
                         cfg::PotentialKind::Lammps(_) => {
                             assert!(!found_lammps, "(BUG!) more than one lammps potential after validation!?");
                             found_lammps = true;
-                            PotentialBuilder::single_from_config_parts(trial_dir, on_demand.take(), threading, lammps, &cfg)
+                            PotentialBuilder::single_from_config_parts(trial_dir, on_demand.take(), threading, lammps, parameters, &cfg)
                         },
-                        _ => PotentialBuilder::single_from_config_parts(trial_dir, None, threading, lammps, &cfg),
+                        _ => PotentialBuilder::single_from_config_parts(trial_dir, None, threading, lammps, parameters, &cfg),
                     }
                 })
                 .collect::<FailResult<Vec<_>>>()?
@@ -602,6 +644,7 @@ impl dyn PotentialBuilder {
         on_demand: Option<LammpsOnDemand>,
         threading: &cfg::Threading,
         lammps: &cfg::Lammps,
+        parameters: Option<&cfg::Parameters>,
         config: &cfg::PotentialKind,
     ) -> FailResult<Box<dyn PotentialBuilder>> {
         match config {
@@ -616,35 +659,40 @@ impl dyn PotentialBuilder {
             cfg::PotentialKind::Lammps(cfg) => match cfg {
                 cfg::LammpsPotentialKind::Rebo(cfg) => {
                     let lammps_pot = self::lammps::Airebo::from(cfg);
-                    let pot = self::lammps::Builder::new(trial_dir, on_demand, threading, lammps, lammps_pot)?;
+                    let pot = self::lammps::Builder::new(trial_dir, on_demand, threading, lammps, parameters, cfg.omp_threads, lammps_pot)?;
                     Ok(Box::new(pot))
                 },
                 cfg::LammpsPotentialKind::Airebo(cfg) => {
                     let lammps_pot = self::lammps::Airebo::from(cfg);
-                    let pot = self::lammps::Builder::new(trial_dir, on_demand, threading, lammps, lammps_pot)?;
+                    let pot = self::lammps::Builder::new(trial_dir, on_demand, threading, lammps, parameters, cfg.omp_threads, lammps_pot)?;
                     Ok(Box::new(pot))
                 },
                 cfg::LammpsPotentialKind::KolmogorovCrespiZ(cfg) => {
                     let lammps_pot = self::lammps::KolmogorovCrespiZ::from(cfg);
-                    let pot = self::lammps::Builder::new(trial_dir, on_demand, threading, lammps, lammps_pot)?;
+                    let pot = self::lammps::Builder::new(trial_dir, on_demand, threading, lammps, parameters, None, lammps_pot)?;
                     Ok(Box::new(pot))
                 },
                 cfg::LammpsPotentialKind::KolmogorovCrespiFull(cfg) => {
                     let lammps_pot = self::lammps::KolmogorovCrespiFull::from(cfg);
-                    let pot = self::lammps::Builder::new(trial_dir, on_demand, threading, lammps, lammps_pot)?;
+                    let pot = self::lammps::Builder::new(trial_dir, on_demand, threading, lammps, parameters, None, lammps_pot)?;
                     Ok(Box::new(pot))
                 },
             },
             cfg::PotentialKind::KolmogorovCrespi(cfg) => {
                 let cfg = cfg.clone();
-                let parallel = threading == &cfg::Threading::Rayon;
+                let parallel = match threading { cfg::Threading::Rayon(_) => true, _ => false };
                 Ok(Box::new(self::homestyle::KolmogorovCrespi { cfg, parallel }))
             },
             cfg::PotentialKind::ReboNonreactive(cfg) => {
                 let cfg = cfg.clone();
-                let parallel = threading == &cfg::Threading::Rayon;
+                let parallel = match threading { cfg::Threading::Rayon(_) => true, _ => false };
                 Ok(Box::new(self::homestyle::Rebo { cfg, parallel }))
             },
+            cfg::PotentialKind::Morse(cfg) => {
+                let cfg = cfg.clone();
+                let parallel = match threading { cfg::Threading::Rayon(_) => true, _ => false };
+                Ok(Box::new(self::homestyle::Morse { cfg, parallel }))
+            },
             cfg::PotentialKind::DftbPlus(cfg) => {
                 #[cfg(not(feature = "dftbplus-support"))] {
                     let _ = cfg; // suppress warning
@@ -685,6 +733,7 @@ impl<'a> rsp2_minimize::test::n_dee::OnceDifferentiable for DiffFnWorkShim {
 pub mod test_functions;
 
 mod helper;
+pub(crate) use self::helper::stress_from_bond_grad;
 
 mod homestyle;
 
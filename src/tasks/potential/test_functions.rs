@@ -17,7 +17,7 @@
 use super::{DynCloneDetail, PotentialBuilder, DiffFn, DispFn, BondDiffFn, BondGrad};
 use crate::FailResult;
 use rsp2_structure::{Coords, CoordsKind};
-use rsp2_array_types::{V3};
+use rsp2_array_types::{V3, M33};
 
 /// The test Potential `V = 0`.
 #[derive(Debug, Clone)]
@@ -31,6 +31,10 @@ impl<Meta: Clone + 'static> PotentialBuilder<Meta> for Zero {
             fn compute(&mut self, coords: &Coords, _: M) -> FailResult<(f64, Vec<V3>)> {
                 Ok((0.0, vec![V3([0.0; 3]); coords.num_atoms()]))
             }
+
+            fn compute_stress(&mut self, _: &Coords, _: M) -> FailResult<M33> {
+                Ok(M33::zero())
+            }
         }
         Ok(Box::new(Diff) as Box<_>)
     }
@@ -14,6 +14,7 @@ use crate::traits::AsPath;
 use crate::meta::Element;
 
 use rsp2_structure::Coords;
+use rsp2_array_types::V3;
 use rsp2_structure_io::{Poscar, Xyz, v_sim::{self, VSimAscii}};
 use path_abs::{FileRead, FileWrite};
 use std::borrow::Borrow;
@@ -99,6 +100,16 @@ impl Load for Xyz {
     }
 }
 
+impl<Title, Carts, Elements> Save for Xyz<Title, Carts, Elements>
+where
+    Title: AsRef<str>,
+    Carts: AsRef<[V3]>,
+    Elements: AsRef<[Element]>,
+{
+    fn save(&self, path: impl AsPath) -> FailResult<()>
+    { Ok(self.to_writer(FileWrite::create(path.as_path())?)?) }
+}
+
 impl<Comment, Coord, Elements, Metadata> Save for VSimAscii<Comment, Coord, Elements, Metadata>
 where
     Comment: AsRef<str>,
@@ -80,6 +80,10 @@ impl Config {
         let path = PathFile::new(path)?;
         let yaml = YamlRead::from_reader(FileRead::open(&path)?)?;
         let yaml = expand_dot_keys(yaml)?;
+        let yaml = match path.parent() {
+            Some(dir) => resolve_relative_paths(yaml, dir),
+            None => yaml,
+        };
         let yaml = validate_replacements_from_one_config(yaml)?;
 
         let source = ConfigSource::File(path);
@@ -189,6 +193,26 @@ mod resolve_from_arg {
         expect_replace_error!("a: {b1: 1, ~~REPLACE~~: 2}");
         expect_replace_error!(": {a.b1: 1, a.~~REPLACE~~: 2}");
     }
+
+    #[test]
+    fn test_relative_paths() {
+        let resolve = |s: &str| {
+            let yaml = expand_dot_keys(YamlRead::from_reader(s.as_bytes()).unwrap()).unwrap();
+            let DotFree(yaml) = resolve_relative_paths(yaml, Path::new("/configs"));
+            yaml
+        };
+        let file = |path: &str| yaml!{{ "phonons": { "disp-finder": { "file": { "path": path } } } }};
+
+        assert_eq!(resolve("phonons.disp-finder.file.path: disps.yaml"), file("/configs/disps.yaml"));
+        assert_eq!(resolve("phonons.disp-finder.file.path: ../disps.yaml"), file("/configs/../disps.yaml"));
+        assert_eq!(resolve("phonons.disp-finder.file.path: /abs/disps.yaml"), file("/abs/disps.yaml"));
+        assert_eq!(
+            resolve("phonons.disp-finder.~~REPLACE~~: {file: {path: disps.yaml}}"),
+            yaml!{{ "phonons": { "disp-finder": { "~~REPLACE~~": { "file": { "path": "/configs/disps.yaml" } } } } }},
+        );
+        // other strings are left alone
+        assert_eq!(resolve("potential.dftb+.hsd: disps.yaml"), yaml!{{ "potential": { "dftb+": { "hsd": "disps.yaml" } } }});
+    }
 }
 
 impl ConfigSources {
@@ -337,6 +361,44 @@ fn expand_dot_keys(value: Value) -> FailResult<DotFree> {
     }.map(DotFree)
 }
 
+/// Keys (after dot expansion) whose values are filepaths.
+///
+/// When these appear in a config file, relative paths are resolved against the directory
+/// containing that file, rather than the current directory.
+const PATH_KEYS: &[&[&str]] = &[
+    &["phonons", "disp-finder", "file", "path"],
+];
+
+fn resolve_relative_paths(value: DotFree, dir: &Path) -> DotFree {
+    let DotFree(mut value) = value;
+    for keys in PATH_KEYS {
+        resolve_relative_path_at(&mut value, keys, dir);
+    }
+    DotFree(value)
+}
+
+fn resolve_relative_path_at(value: &mut Value, keys: &[&str], dir: &Path) {
+    match value {
+        Value::Mapping(mapping) => {
+            // a REPLACE directive does not count as a key
+            if let Some(child) = mapping.get_mut(&Value::String(REPLACE_DIRECTIVE_KEY.into())) {
+                resolve_relative_path_at(child, keys, dir);
+            }
+            if let Some((&key, rest)) = keys.split_first() {
+                if let Some(child) = mapping.get_mut(&Value::String(key.into())) {
+                    resolve_relative_path_at(child, rest, dir);
+                }
+            }
+        },
+        Value::String(path) => {
+            if keys.is_empty() && Path::new(path.as_str()).is_relative() {
+                *path = dir.join(path.as_str()).display().to_string();
+            }
+        },
+        _ => {},
+    }
+}
+
 // resolve REPLACE directives, assuming that they all came from singletons in the second
 // config file of a dumb merge.
 fn resolve_replacements_from_two_configs(value: DotFree) -> FullyResolved
@@ -17,9 +17,11 @@ use path_abs::{FileWrite, PathFile};
 use fern::colors;
 
 pub use self::loggers::{CapturableStderr, DelayedLogFile, GLOBAL_LOGFILE};
+pub use self::loggers::{Diagnostics, Diagnostic, GLOBAL_DIAGNOSTICS};
 mod loggers {
     use super::*;
-    use std::sync::RwLock;
+    use std::sync::{Mutex, RwLock};
+    use crate::traits::{AsPath, Save, save::Json};
     use std::io::prelude::*;
 
     /// Logger that uses `eprintln!`.
@@ -133,6 +135,87 @@ mod loggers {
             } // ignore PoisonError silently for reasons documented above
         }
     }
+
+    /// Logger that collects warnings and errors, so that they can be written to
+    /// `warnings.json` once a run is finished.
+    ///
+    /// Identical messages from the same module are merged into a single entry.
+    #[derive(Debug, Default)]
+    pub struct Diagnostics {
+        entries: Mutex<Vec<Diagnostic>>,
+    }
+
+    /// A single entry of `warnings.json`.
+    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    pub struct Diagnostic {
+        /// `"warn"` or `"error"`.
+        pub level: String,
+        pub message: String,
+        /// The module that emitted the message.
+        pub target: String,
+        /// `file:line` of the first occurrence, if known.
+        pub location: Option<String>,
+        /// Number of times this exact message was emitted.
+        pub count: u32,
+    }
+
+    lazy_static! {
+        /// The Diagnostics given to fern.
+        pub static ref GLOBAL_DIAGNOSTICS: Diagnostics = Default::default();
+    }
+
+    impl Diagnostics {
+        /// Get all messages collected so far, in order of first occurrence.
+        pub fn entries(&self) -> Vec<Diagnostic> {
+            match self.entries.lock() {
+                Ok(entries) => entries.clone(),
+                Err(_) => vec![], // ignore PoisonError for the same reasons as DelayedLogFile
+            }
+        }
+
+        /// Write `warnings.json`.
+        pub fn save(&self, path: impl AsPath) -> FailResult<()> {
+            Json(self.entries()).save(path)
+        }
+    }
+
+    impl Log for Diagnostics {
+        fn enabled(&self, metadata: &::log::Metadata<'_>) -> bool {
+            metadata.level() <= ::log::Level::Warn
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+
+            if let Ok(mut entries) = self.entries.lock() {
+                let level = record.level().to_string().to_lowercase();
+                let message = record.args().to_string();
+                let target = record.target();
+
+                let existing = entries.iter_mut().find(|entry| {
+                    entry.level == level && entry.message == message && entry.target == target
+                });
+                match existing {
+                    Some(entry) => entry.count += 1,
+                    None => entries.push(Diagnostic {
+                        level, message,
+                        target: target.to_string(),
+                        location: match (record.file(), record.line()) {
+                            (Some(file), Some(line)) => Some(format!("{}:{}", file, line)),
+                            _ => None,
+                        },
+                        count: 1,
+                    }),
+                }
+            } // ignore PoisonError silently for reasons documented above
+        }
+
+        fn flush(&self) {}
+    }
 }
 
 /// Set the global logger, enabling the use of `log!()` macros.
@@ -179,17 +262,23 @@ pub fn init_global_logger() -> FailResult<SetGlobalLogfile>
     };
 
     let start = time::Instant::now();
-    let fern = fern::Dispatch::new()
+    let formatted = fern::Dispatch::new()
         .format(move |out, message, record| {
             let message = fmt_log_message_lines(message, &colors, record, start.elapsed(), log_mod_setting);
 
             out.finish(format_args!("{}", message))
         })
-        .filter(move |metadata| env_filter.enabled(metadata))
         .chain(&*GLOBAL_LOGFILE as &dyn Log)
         .chain(Box::new(CapturableStderr) as Box<dyn Log>)
         ;
 
+    // Diagnostics are recorded without the formatting used for the terminal and logfile.
+    let fern = fern::Dispatch::new()
+        .filter(move |metadata| env_filter.enabled(metadata))
+        .chain(formatted)
+        .chain(&*GLOBAL_DIAGNOSTICS as &dyn Log)
+        ;
+
     fern.apply()?;
 
     Ok(SetGlobalLogfile(()))
@@ -278,3 +367,35 @@ fn fmt_log_message_lines(
     }));
     out.concat()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn diagnostics() {
+        let diagnostics = Diagnostics::default();
+        let emit = |level, target, message: &str| {
+            diagnostics.log(&Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!("{}", message))
+                .build());
+        };
+
+        emit(Level::Warn, "rsp2_tasks::a", "first warning");
+        emit(Level::Info, "rsp2_tasks::a", "not a warning");
+        emit(Level::Warn, "rsp2_tasks::b", "second warning");
+        emit(Level::Warn, "rsp2_tasks::a", "first warning");
+        emit(Level::Error, "rsp2_tasks::a", "first warning");
+
+        let entries = diagnostics.entries();
+        let summary = entries.iter().map(|e| (&e.level[..], &e.target[..], &e.message[..], e.count)).collect::<Vec<_>>();
+        assert_eq!(summary, vec![
+            ("warn", "rsp2_tasks::a", "first warning", 2),
+            ("warn", "rsp2_tasks::b", "second warning", 1),
+            ("error", "rsp2_tasks::a", "first warning", 1),
+        ]);
+    }
+}
@@ -3,3 +3,4 @@ pub(crate) mod logging;
 pub(crate) mod cfg_merging;
 pub(crate) mod cli_deserialize;
 pub(crate) mod parse_qpoint;
+pub(crate) mod parse_mass_scale;
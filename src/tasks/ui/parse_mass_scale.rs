@@ -0,0 +1,21 @@
+use crate::FailResult;
+use crate::meta::Element;
+
+/// Parse an argument like `H:2`, which scales the mass of an element by a factor.
+pub fn parse_mass_scale(s: &str) -> FailResult<(Element, f64)> {
+    let mut iter = s.splitn(2, ':');
+    let symbol = iter.next().unwrap();
+    let factor = match iter.next() {
+        Some(factor) => factor,
+        None => bail!("expected ELEMENT:FACTOR in --scale-mass, got {:?}", s),
+    };
+
+    let element = Element::from_symbol(symbol.trim())?;
+    let factor = factor.trim().parse::<f64>().map_err(|_| {
+        format_err!("{:?} is not a valid mass scale factor", factor)
+    })?;
+    if !(factor.is_finite() && factor > 0.0) {
+        bail!("mass scale factor must be positive (got {} for {})", factor, element.symbol());
+    }
+    Ok((element, factor))
+}
@@ -92,6 +92,12 @@ impl CliTest {
         self
     }
 
+    /// Expect the command to exit with a nonzero status.
+    pub fn expect_failure(mut self) -> Self {
+        self.expect_success = Some(false);
+        self
+    }
+
     pub fn check<F>(mut self, checker: F) -> Self
     where F: Fn(&PathDir) -> Result<()> + 'static,
     {
@@ -100,6 +106,8 @@ impl CliTest {
     }
 
     /// `check` with a standard trait-based implementation.
+    ///
+    /// With `RSP2_BLESS` set, the file is instead copied over `expected_path`.
     pub fn check_file<T: CheckFile>(
         self,
         path_in_trial: &Path,
@@ -109,6 +117,14 @@ impl CliTest {
         let path_in_trial = path_in_trial.to_owned();
         let expected_path = expected_path.to_owned();
         let checker = move |dir: &PathDir| {
+            if crate::blessing() {
+                if let Some(parent) = expected_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::copy(dir.join(&path_in_trial), &expected_path)?;
+                eprintln!("Blessed {}", expected_path.display());
+                return Ok(());
+            }
             let actual = T::read_file(dir.join(&path_in_trial).as_path())?;
             let expected = T::read_file(expected_path.as_path())?;
             check_against_with_diff(&expected, &actual, other.clone());
@@ -188,6 +188,55 @@ impl CheckFile for RamanJson {
     }
 }
 
+/// `summary.json`, written at the end of a relaxation.
+#[derive(Debug, PartialEq)]
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SummaryJson {
+    pub final_energy: f64,
+    pub final_energy_per_atom: f64,
+    pub ev_loop_iterations: u32,
+    pub converged: bool,
+    pub modes: Vec<ModeSummary>,
+}
+impl_json!{ (SummaryJson)[save, load] }
+
+#[derive(Debug, PartialEq)]
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ModeSummary {
+    pub frequency: f64,
+    pub kind: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SummaryJsonTolerances {
+    pub frequency: FrequencyTolerances,
+    /// Used for `final-energy` and `final-energy-per-atom`.
+    pub energy_rel_tol: f64,
+}
+
+impl CheckFile for SummaryJson {
+    type OtherArgs = SummaryJsonTolerances;
+
+    fn read_file(path: &Path) -> Result<Self, Error> { Self::load(path) }
+
+    fn check_against(&self, expected: &SummaryJson, tol: SummaryJsonTolerances) {
+        assert_eq!(self.ev_loop_iterations, expected.ev_loop_iterations);
+        assert_eq!(self.converged, expected.converged);
+
+        assert_close!(rel=tol.energy_rel_tol, self.final_energy, expected.final_energy);
+        assert_close!(rel=tol.energy_rel_tol, self.final_energy_per_atom, expected.final_energy_per_atom);
+
+        // the acoustic modes are located by frequency, so their kinds must agree too
+        let kinds = |modes: &[ModeSummary]| modes.iter().map(|m| m.kind.clone()).collect_vec();
+        assert_eq!(kinds(&self.modes), kinds(&expected.modes));
+
+        let frequencies = |modes: &[ModeSummary]| Frequencies(modes.iter().map(|m| m.frequency).collect());
+        frequencies(&self.modes).check_against(&frequencies(&expected.modes), tol.frequency);
+    }
+}
+
 #[derive(Debug, PartialEq)]
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
@@ -37,3 +37,18 @@ pub fn resource(path: &str) -> PathBuf {
     }
     path.as_path().to_owned()
 }
+
+/// Whether reference files should be overwritten with new output instead of being compared.
+///
+/// Set `RSP2_BLESS=1` to (re)generate the references checked by `CliTest::check_file`.
+pub fn blessing() -> bool { std::env::var_os("RSP2_BLESS").is_some() }
+
+// like `resource`, but for reference output that `RSP2_BLESS` can generate
+pub fn reference(path: &str) -> PathBuf {
+    let dir = path_abs::PathDir::new("tests/resources").unwrap_or_else(|e| panic!("{}", e));
+    let path = dir.as_path().join(path);
+    if !blessing() && !path.exists() {
+        panic!("{}: Reference does not exist (rerun the test with RSP2_BLESS=1 to generate it)", path.display())
+    }
+    path
+}
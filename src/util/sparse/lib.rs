@@ -298,7 +298,7 @@ impl<T, R: Idx, C: Idx> RawCsr<T, R, C> {
     pub fn validate(&self) -> Result<(), SparseMatrixError>  {
         let RawCsr { dim, ref val, ref col, ref row_ptr } = *self;
         ensure!(val.len() == col.len(), "mismatched val/col len");
-        ensure!(col.iter().max().unwrap().index() < dim.1, "col index out of range");
+        ensure!(col.iter().max().map(|x| x.index() < dim.1).unwrap_or(true), "col index out of range");
         ensure!(row_ptr.raw.windows(2).all(|w| w[0] <= w[1]), "row_ptr not sorted");
         ensure!(row_ptr.len() == dim.0 + 1, "row_ptr wrong len");
         ensure!(*row_ptr.raw.first().unwrap() == 0, "row_ptr first value incorrect");
@@ -349,6 +349,40 @@ impl<T, R: Idx, C: Idx> RawCsr<T, R, C> {
         assert_eq!(self.col.len(), 0);
         RawCoo { dim, row, col, val }
     }
+
+    pub fn into_indexed_dense(self) -> Indexed<R, Vec<Indexed<C, Vec<T>>>>
+    where T: Zero,
+    { self.into_indexed_dense_with(Zero::zero) }
+
+    pub fn into_dense(self) -> Vec<Vec<T>>
+    where T: Zero,
+    { self.into_dense_with(Zero::zero) }
+
+    // (unlike RawCoo, there are no duplicates to be summed, so no `add_assign` is needed)
+    pub fn into_indexed_dense_with<Z>(self, mut zero: Z) -> Indexed<R, Vec<Indexed<C, Vec<T>>>>
+    where Z: FnMut() -> T,
+    {
+        let dim = self.dim;
+        let row_ranges = self.row_ranges();
+        let mut zero_row = || (0..dim.1).map(|_| zero()).collect();
+        let mut zero_mat = || (0..dim.0).map(|_| zero_row()).collect();
+
+        let mut out: Indexed<R, Vec<Indexed<C, Vec<T>>>> = zero_mat();
+        let mut iter = zip_eq!(self.col, self.val);
+        for (r, range) in row_ranges.into_iter_enumerated() {
+            for (c, x) in iter.by_ref().take(range.len()) {
+                out[r][c] = x;
+            }
+        }
+        out
+    }
+
+    pub fn into_dense_with<Z>(self, zero: Z) -> Vec<Vec<T>>
+    where Z: FnMut() -> T,
+    {
+        self.into_indexed_dense_with(zero)
+            .into_iter().map(|v| v.raw).collect()
+    }
 }
 
 #[allow(unused)]
@@ -365,6 +399,22 @@ where
 
     pub fn to_coo(&self) -> RawCoo<T, R, C>
     { self.clone().into_coo() }
+
+    pub fn to_indexed_dense(&self) -> Indexed<R, Vec<Indexed<C, Vec<T>>>>
+    where T: Zero,
+    { self.clone().into_indexed_dense() }
+
+    pub fn to_dense(&self) -> Vec<Vec<T>>
+    where T: Zero,
+    { self.clone().into_dense() }
+
+    pub fn to_indexed_dense_with<Z>(&self, zero: Z) -> Indexed<R, Vec<Indexed<C, Vec<T>>>>
+    where Z: FnMut() -> T,
+    { self.clone().into_indexed_dense_with(zero) }
+
+    pub fn to_dense_with<Z>(&self, zero: Z) -> Vec<Vec<T>>
+    where Z: FnMut() -> T,
+    { self.clone().into_dense_with(zero) }
 }
 
 
@@ -392,8 +442,8 @@ impl<T, R: Idx, C: Idx> RawBee<T, R, C> {
     // are not satisfied.
     pub fn validate(&self) -> Result<(), SparseMatrixError>  {
         let RawBee { dim, ref map } = *self;
-        ensure!(map.keys().max().unwrap().index() < dim.0, "row out of range");
-        ensure!(map.values().filter_map(|m| m.keys().max()).max().unwrap().index() < dim.1, "col out of range");
+        ensure!(map.keys().max().map(|x| x.index() < dim.0).unwrap_or(true), "row out of range");
+        ensure!(map.values().filter_map(|m| m.keys().max()).max().map(|x| x.index() < dim.1).unwrap_or(true), "col out of range");
         Ok(())
     }
 
@@ -515,3 +565,24 @@ fn bee_to_csr() {
     assert_eq!(col, vec![2, 3, 2]);
     assert_eq!(row_ptr.raw, vec![0, 0, 0, 2, 2, 2, 3, 3]);
 }
+
+#[test]
+fn csr_to_dense() {
+    // includes a duplicate entry, an empty row, and missing rows at the end
+    let coo = RawCoo {
+        dim: (5, 4),
+        val: vec![1.0f64, 4.0, 3.0, 2.0, 0.5],
+        row: vec![2, 0, 2, 3, 2],
+        col: vec![2, 1, 3, 0, 2],
+    };
+    let from_csr = coo.to_csr().into_dense();
+    let from_coo = coo.into_dense();
+    assert_eq!(from_csr, from_coo);
+    assert_eq!(from_csr, vec![
+        vec![0.0, 4.0, 0.0, 0.0],
+        vec![0.0, 0.0, 0.0, 0.0],
+        vec![0.0, 0.0, 1.5, 3.0],
+        vec![2.0, 0.0, 0.0, 0.0],
+        vec![0.0, 0.0, 0.0, 0.0],
+    ]);
+}
@@ -0,0 +1,67 @@
+#[macro_use]
+extern crate rsp2_assert_close;
+
+use rsp2_integration_test::{CliTest, filetypes, resource, cli_test, Result};
+use path_abs::FileRead;
+use serde_derive::Deserialize;
+
+// Each frame of a multi-frame XYZ file is relaxed in its own trial directory.
+// Both of these frames should relax to the same dimer.
+#[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
+#[test]
+fn batch_xyz_frames() -> Result<()> {
+    let env = cli_test::Environment::init();
+    CliTest::cargo_binary(&env, "rsp2")
+        .arg("-c").arg(resource("defaults.yaml"))
+        .arg("-c").arg(resource("ch-dimer-morse.yaml"))
+        .arg(resource("ch-dimers.xyz").as_path())
+        .arg("-o").arg("out")
+        .arg("--batch")
+        .check(|dir| Ok({
+            assert!(dir.join("out/batch-summary.json").exists());
+
+            let frequency = |frame: &str| -> Result<f64> {
+                let summary = filetypes::SummaryJson::load(dir.join("out").join(frame).join("summary.json"))?;
+                Ok(summary.modes.iter().map(|mode| mode.frequency).fold(std::f64::NEG_INFINITY, f64::max))
+            };
+            assert_close!(rel=1e-5, frequency("frame-0000")?, frequency("frame-0001")?);
+        }))
+        .run()
+}
+
+// A directory in which the second of three frames can't even be read.  The other frames must
+// still be relaxed, and the failure must be reported both in `batch-summary.json` and through
+// the exit status.
+#[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
+#[test]
+fn batch_dir_bad_frame() -> Result<()> {
+    let env = cli_test::Environment::init();
+    CliTest::cargo_binary(&env, "rsp2")
+        .arg("-c").arg(resource("defaults.yaml"))
+        .arg("-c").arg(resource("ch-dimer-morse.yaml"))
+        .arg(resource("ch-dimers-bad-frame").as_path())
+        .arg("-o").arg("out")
+        .arg("--batch")
+        .expect_failure()
+        .check(|dir| Ok({
+            #[derive(Deserialize)]
+            struct FrameOutcome {
+                name: String,
+                error: Option<String>,
+            }
+            let outcomes: Vec<FrameOutcome> = serde_json::from_reader({
+                FileRead::open(dir.join("out/batch-summary.json"))?
+            })?;
+
+            let names = outcomes.iter().map(|x| &x.name[..]).collect::<Vec<_>>();
+            assert_eq!(names, vec!["0-stretched.xyz", "1-truncated.xyz", "2-compressed.xyz"]);
+            assert!(outcomes[0].error.is_none());
+            assert!(outcomes[1].error.is_some());
+            assert!(outcomes[2].error.is_none());
+
+            for good in &["0-stretched.xyz", "2-compressed.xyz"] {
+                assert!(dir.join("out").join(good).join("summary.json").exists());
+            }
+        }))
+        .run()
+}
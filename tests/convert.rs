@@ -0,0 +1,109 @@
+#[macro_use]
+extern crate rsp2_assert_close;
+
+use rsp2_integration_test::{CliTest, resource, cli_test, Result};
+use rsp2_structure_io::{Poscar, Xyz};
+use rsp2_array_types::Unvee;
+use path_abs::{FileRead, PathDir, PathOps};
+use std::path::Path;
+
+#[test]
+fn poscar_to_xyz() -> Result<()> {
+    let env = cli_test::Environment::init();
+    CliTest::cargo_binary(&env, "rsp2-convert")
+        .arg(resource("simple.vasp").as_path())
+        .arg("out.xyz")
+        .check(|dir| Ok({
+            let expected = read_poscar(resource("simple.vasp"))?;
+            let actual = read_xyz(dir.join("out.xyz"))?;
+            assert_eq!(expected.elements, actual.elements);
+            assert_close!(abs=1e-8, expected.coords.to_carts().unvee(), actual.carts.unvee());
+        }))
+        .run()
+}
+
+#[test]
+fn poscar_round_trip_via_structure() -> Result<()> {
+    round_trip("out.structure", |expected, actual| {
+        assert_eq!(expected.elements, actual.elements);
+        assert_close!(abs=1e-8, expected.coords.lattice(), actual.coords.lattice());
+        assert_close!(abs=1e-8, expected.coords.to_carts().unvee(), actual.coords.to_carts().unvee());
+    })
+}
+
+#[test]
+fn poscar_round_trip_via_xyz() -> Result<()> {
+    // XYZ has no lattice, so only the positions survive.
+    round_trip("out.xyz", |expected, actual| {
+        assert_eq!(expected.elements, actual.elements);
+        assert_close!(abs=1e-8, expected.coords.to_carts().unvee(), actual.coords.to_carts().unvee());
+    })
+}
+
+#[test]
+fn poscar_to_lammps_data() -> Result<()> {
+    let env = cli_test::Environment::init();
+    CliTest::cargo_binary(&env, "rsp2-convert")
+        .arg(resource("simple.vasp").as_path())
+        .arg("out.data")
+        .check(|dir| Ok({
+            let text = FileRead::open(dir.join("out.data"))?.read_string()?;
+            let lines = text.lines().map(str::trim).collect::<Vec<_>>();
+            assert!(lines.contains(&"4 atoms"));
+            assert!(lines.contains(&"1 atom types"));
+            assert!(lines.contains(&"1 12.0107 # C"));
+        }))
+        .run()
+}
+
+#[test]
+fn poscar_round_trip_via_lammps_data() -> Result<()> {
+    // The lattice may be rotated, so compare only things that are invariant to that.
+    round_trip("out.data", |expected, actual| {
+        assert_eq!(expected.elements, actual.elements);
+        assert_close!(abs=1e-8, expected.coords.lattice().norms(), actual.coords.lattice().norms());
+        assert_close!(abs=1e-8, expected.coords.to_fracs().unvee(), actual.coords.to_fracs().unvee());
+    })
+}
+
+#[test]
+fn unknown_extension() -> Result<()> {
+    let env = cli_test::Environment::init();
+    CliTest::cargo_binary(&env, "rsp2-convert")
+        .expect_failure()
+        .arg(resource("simple.vasp").as_path())
+        .arg("out.cif")
+        .run()
+}
+
+// Converts simple.vasp into the given intermediate format and then back into a POSCAR.
+fn round_trip(
+    intermediate: &'static str,
+    check: impl Fn(Poscar, Poscar) + 'static,
+) -> Result<()> {
+    let env = cli_test::Environment::init();
+    CliTest::cargo_binary(&env, "rsp2-convert")
+        .arg(resource("simple.vasp").as_path())
+        .arg(intermediate)
+        .after_run(move |dir: &PathDir| {
+            let env = cli_test::Environment::init();
+            CliTest::cargo_binary(&env, "rsp2-convert")
+                .arg(dir.join(intermediate).as_path())
+                .arg(dir.join("back.vasp").as_path())
+                .run()
+        })
+        .check(move |dir| Ok({
+            let expected = read_poscar(resource("simple.vasp"))?;
+            let actual = read_poscar(dir.join("back.vasp"))?;
+            check(expected, actual);
+        }))
+        .run()
+}
+
+fn read_poscar(path: impl AsRef<Path>) -> Result<Poscar> {
+    Ok(Poscar::from_reader(FileRead::open(path)?)?)
+}
+
+fn read_xyz(path: impl AsRef<Path>) -> Result<Xyz> {
+    Ok(Xyz::from_reader(FileRead::open(path)?)?)
+}
@@ -6,11 +6,13 @@
 
 type FailResult<T> = Result<T, ::failure::Error>;
 
+use std::collections::BTreeMap;
+
 use rsp2_integration_test::{resource, filetypes::Primitive};
-use rsp2_dynmat::SuperForceConstants;
+use rsp2_dynmat::{SuperForceConstants, DynamicalMatrix, Cereal, Complex33};
 use rsp2_array_types::{M33, V3, Unvee};
 use rsp2_structure::{Coords, supercell::SupercellToken};
-use rsp2_soa_ops::{Permute};
+use rsp2_soa_ops::{Perm, Permute};
 
 #[derive(Deserialize)]
 pub struct ForceSets {
@@ -48,14 +50,23 @@ struct Tolerances {
     absolute: f64,
 }
 
-fn check(
+// Inputs shared by the force constant checks, permuted into the current supercell convention.
+struct Fixture {
+    prim_masses: Vec<f64>,
+    prim_coords: Coords,
+    super_coords: Coords,
+    sc: SupercellToken,
+    deperm_from_orig: Perm,
+    super_force_sets: Vec<BTreeMap<usize, V3>>, // [disp][super] -> V3
+    super_displacements: Vec<(usize, V3)>, // [disp] -> (super, V3)
+    super_sg_deperms: Vec<Perm>,
+    cart_rots: Vec<M33>,
+}
+
+fn load_fixture(
     prim_info_relpath: &str,
     super_info_relpath: &str,
-    expected_fc_relpath: Option<&str>,
-    expected_dynmat_relpath: &str,
-    qpoint_frac: V3,
-    tol: Tolerances,
-) -> FailResult<()> {
+) -> FailResult<Fixture> {
     let Primitive {
         cart_ops,
         masses: prim_masses,
@@ -66,16 +77,6 @@ fn check(
         sc_dims, orig_super_coords, orig_super_force_sets, orig_displacements,
     } = ForceSets::load(resource(super_info_relpath))?;
 
-    let orig_expected_fcs = expected_fc_relpath.map(|relpath| {
-        let matrix = OutputForceConstants::load(resource(relpath)).unwrap().orig_force_constants;
-        SuperForceConstants::from_dense_matrix(matrix)
-    });
-
-    let OutputDynMat {
-        expected_dynmat_real,
-        expected_dynmat_imag,
-    } = OutputDynMat::load(resource(expected_dynmat_relpath))?;
-
     let (super_coords, sc) = ::rsp2_structure::supercell::diagonal(sc_dims).build(&prim_coords);
 
     // permute expected output into correct order for the current supercell convention
@@ -113,6 +114,35 @@ fn check(
 
     let cart_rots = cart_ops.iter().map(|c| c.cart_rot()).collect::<Vec<_>>();
 
+    Ok(Fixture {
+        prim_masses, prim_coords, super_coords, sc, deperm_from_orig,
+        super_force_sets, super_displacements, super_sg_deperms, cart_rots,
+    })
+}
+
+fn check(
+    prim_info_relpath: &str,
+    super_info_relpath: &str,
+    expected_fc_relpath: Option<&str>,
+    expected_dynmat_relpath: &str,
+    qpoint_frac: V3,
+    tol: Tolerances,
+) -> FailResult<()> {
+    let Fixture {
+        prim_masses, prim_coords, super_coords, sc, deperm_from_orig,
+        super_force_sets, super_displacements, super_sg_deperms, cart_rots,
+    } = load_fixture(prim_info_relpath, super_info_relpath)?;
+
+    let orig_expected_fcs = expected_fc_relpath.map(|relpath| {
+        let matrix = OutputForceConstants::load(resource(relpath)).unwrap().orig_force_constants;
+        SuperForceConstants::from_dense_matrix(matrix)
+    });
+
+    let OutputDynMat {
+        expected_dynmat_real,
+        expected_dynmat_imag,
+    } = OutputDynMat::load(resource(expected_dynmat_relpath))?;
+
     // ---------------------------------
     // ------- Force constants ---------
     // ---------------------------------
@@ -125,6 +155,20 @@ fn check(
         &sc,
     )?;
 
+    // feeding the forces in one at a time should produce the same thing
+    {
+        let mut builder = rsp2_dynmat::ForceConstantsBuilder::new(&cart_rots, &super_sg_deperms, &sc);
+        for (&displacement, force_set) in super_displacements.iter().zip(&super_force_sets) {
+            builder.add_displacement(displacement, force_set.clone())?;
+            builder.build_partial()?;
+        }
+        let incremental = builder.build()?;
+        assert_eq!(
+            incremental.to_super_force_constants_with_zeroed_rows(&sc).to_dense_matrix(),
+            force_constants.to_super_force_constants_with_zeroed_rows(&sc).to_dense_matrix(),
+        );
+    }
+
     if let Some(orig_expected_fcs) = orig_expected_fcs {
         let expected = orig_expected_fcs.permuted_by(&deperm_from_orig);
         let actual = force_constants.to_super_force_constants_with_zeroed_rows(&sc);
@@ -144,8 +188,7 @@ fn check(
     };
 
     {
-        let real = dynamical_matrix.0.to_coo().map(|c| c.0).into_dense();
-        let imag = dynamical_matrix.0.to_coo().map(|c| c.1).into_dense();
+        let (real, imag) = dynamical_matrix.to_dense_parts();
         assert_eq!(real.len(), sc.num_primitive_atoms());
         assert_eq!(real[0].len(), sc.num_primitive_atoms());
         for r in 0..sc.num_primitive_atoms() {
@@ -168,6 +211,32 @@ fn check(
     Ok(())
 }
 
+// Solving for the force constants in parallel over symmetry stars should give the same
+// output as doing it serially.
+fn check_parallel(
+    prim_info_relpath: &str,
+    super_info_relpath: &str,
+) -> FailResult<()> {
+    let fixture = load_fixture(prim_info_relpath, super_info_relpath)?;
+    let serial = compute_force_constants(&fixture, false)?;
+    let parallel = compute_force_constants(&fixture, true)?;
+
+    assert_fcs_close(&fixture.sc, &parallel, &serial, Tolerances { relative: 1e-12, absolute: 1e-12 });
+    Ok(())
+}
+
+fn compute_force_constants(fixture: &Fixture, parallel: bool) -> FailResult<SuperForceConstants> {
+    let force_constants = rsp2_dynmat::ForceConstants::compute_required_rows_maybe_parallel(
+        &fixture.super_displacements,
+        &fixture.super_force_sets,
+        &fixture.cart_rots,
+        &fixture.super_sg_deperms,
+        &fixture.sc,
+        parallel,
+    )?;
+    Ok(force_constants.to_super_force_constants_with_zeroed_rows(&fixture.sc))
+}
+
 fn check_translational_invariance(
     prim_info_relpath: &str,
     initial_fc_relpath: &str,
@@ -327,6 +396,79 @@ fn blg_force_sets_m() {
     ).unwrap()
 }
 
+#[test]
+fn dynmat_dense_from_csr() {
+    let OutputDynMat {
+        expected_dynmat_real: input_real,
+        expected_dynmat_imag: input_imag,
+    } = OutputDynMat::load(resource("force-constants/blg-k.dynmat.json")).unwrap();
+
+    // leave out some blocks so that there is something for densification to fill in
+    let n = input_real.len();
+    let is_stored = |r: usize, c: usize| (r + c) % 3 != 1;
+    let mut cereal = Cereal { dim: (n, n), complex_blocks: vec![], col: vec![], row_ptr: vec![0] };
+    for r in 0..n {
+        for c in (0..n).filter(|&c| is_stored(r, c)) {
+            cereal.complex_blocks.push(Complex33(input_real[r][c], input_imag[r][c]));
+            cereal.col.push(c);
+        }
+        cereal.row_ptr.push(cereal.col.len());
+    }
+    let dynmat = DynamicalMatrix::from_cereal(cereal).unwrap();
+
+    let (real, imag) = dynmat.to_dense_parts();
+
+    // densifying directly from CSR should agree with going through COO
+    assert_eq!(real, dynmat.0.to_coo().map(|c| c.0).into_dense());
+    assert_eq!(imag, dynmat.0.to_coo().map(|c| c.1).into_dense());
+
+    for r in 0..n {
+        for c in 0..n {
+            let (expected_real, expected_imag) = match is_stored(r, c) {
+                true => (input_real[r][c], input_imag[r][c]),
+                false => (M33::zero(), M33::zero()),
+            };
+            assert_eq!(real[r][c], expected_real);
+            assert_eq!(imag[r][c], expected_imag);
+        }
+    }
+}
+
+#[test]
+fn graphene_sparseforce_771_parallel() {
+    check_parallel(
+        "primitive/graphene.json",
+        "force-constants/graphene-771-sparse.super.json",
+    ).unwrap()
+}
+
+#[test]
+fn blg_force_sets_parallel() {
+    check_parallel(
+        "primitive/blg.json",
+        "force-constants/blg.super.json",
+    ).unwrap()
+}
+
+#[test]
+#[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
+fn graphene_denseforce_771_parallel_benchmark() {
+    let fixture = load_fixture(
+        "primitive/graphene.json",
+        "force-constants/graphene-771-dense.super.json",
+    ).unwrap();
+
+    // (use `--nocapture` to see the timings)
+    let num_runs = 20;
+    for &(name, parallel) in &[("serial", false), ("parallel", true)] {
+        let start = std::time::Instant::now();
+        for _ in 0..num_runs {
+            compute_force_constants(&fixture, parallel).unwrap();
+        }
+        println!("{:>8}: {:?} per run", name, start.elapsed() / num_runs);
+    }
+}
+
 //------------------------------------------------------------
 // Translational invariance tests
 
@@ -1,4 +1,5 @@
 use ::rsp2_integration_test::{CliTest, filetypes, resource, cli_test, Result};
+use path_abs::FileRead;
 
 #[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
 #[test]
@@ -20,6 +21,25 @@ fn dynmat_lammps() -> Result<()> {
         .run()
 }
 
+// `omp-threads` must actually reach LAMMPS.  (this needs LAMMPS built with OpenMP, or else
+// LAMMPS will report using a single thread)
+#[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
+#[test]
+fn dynmat_lammps_omp_threads() -> Result<()> {
+    let env = cli_test::Environment::init();
+    CliTest::cargo_binary(&env, "rsp2")
+        .arg("-c").arg(resource("defaults.yaml"))
+        .arg("-c").arg(resource("gamma-dynmat-lammps.yaml"))
+        .arg("-c").arg(resource("lammps-omp-threads.yaml"))
+        .arg(resource("001-a-relaxed-kcz.vasp"))
+        .arg("-o").arg("out")
+        .check(|dir| Ok({
+            let log = FileRead::open(dir.join("out/lammps.log"))?.read_string()?;
+            assert!(log.contains("3 OpenMP thread(s)"), "omp-threads was not applied");
+        }))
+        .run()
+}
+
 // Test custom DispFns on the potentials implemented in Rust
 #[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
 #[test]
@@ -0,0 +1,77 @@
+#!/usr/bin/env python3
+
+# Writes allen-reference.json, the reference data for the `allen_reference_probabilities`
+# test in src/tasks/math/bands.rs, using the implementation in scripts/unfold.py.
+#
+# NOTE: The allen-reference.json currently in the repository was NOT made by this script
+#       (see the comment on the test), and should be replaced by its output.
+#
+# Usage (from the rsp2 source root):
+#
+#     PYTHONPATH=scripts:src/python python3 tests/resources/unfold/make-allen-reference.py \
+#         > tests/resources/unfold/allen-reference.json
+
+import sys
+import json
+import numpy as np
+from pymatgen import Structure, Lattice
+
+import unfold
+import unfold_lib
+
+def main():
+    half_r3 = 0.5 * np.sqrt(3)
+    prim_lattice = np.array([
+        [ 2.46,            0.0,  0.0],
+        [-1.23, 2.46 * half_r3,  0.0],
+        [  0.0,            0.0, 10.0],
+    ])
+    prim_fracs = np.array([[0.0, 0.0, 0.0], [1/3, 2/3, 0.0]])
+    sc_matrix = np.array([[3, 0, 0], [1, 1, 0], [0, 0, 1]])
+    supercell = unfold_lib.Supercell(sc_matrix)
+    super_lattice = sc_matrix @ prim_lattice
+
+    # every image of the primitive cell, reduced into the supercell and shuffled,
+    # so that the translations map some sites to different images
+    carts = np.array([
+        (frac + t) @ prim_lattice
+        for t in supercell.translation_pfracs()
+        for frac in prim_fracs
+    ])
+    super_fracs = (carts @ np.linalg.inv(super_lattice)) % 1.0
+    super_fracs = super_fracs[[3, 0, 5, 1, 4, 2]]
+    structure = Structure(Lattice(super_lattice), ['C'] * len(super_fracs), super_fracs)
+
+    qpoint_sfrac = np.array([0.25, -0.1, 0.0])
+
+    # arbitrary (unnormalized) complex vectors
+    num_evecs = 3
+    n, i = np.indices((num_evecs, 3 * len(super_fracs)))
+    eigenvectors = np.cos(1.7 * i + 0.3 * n + 0.5 * n**2) + 1j * np.sin(2.3 * i - 1.1 * n)
+
+    translation_deperms = unfold.collect_translation_deperms(structure, supercell, tol=1e-5)
+    probs = unfold_lib.unfold_all(
+        superstructure=structure,
+        supercell=supercell,
+        eigenvectors=eigenvectors,
+        qpoint_sfrac=qpoint_sfrac,
+        translation_deperms=translation_deperms,
+        gamma_only=False,
+        implementation='python',
+    )
+
+    json.dump({
+        'prim-lattice': prim_lattice.tolist(),
+        'super-lattice': super_lattice.tolist(),
+        'super-fracs': super_fracs.tolist(),
+        'sc-matrix': sc_matrix.tolist(),
+        'qpoint-sfrac': qpoint_sfrac.tolist(),
+        'gpoint-sfracs': supercell.gpoint_sfracs().astype(int).tolist(),
+        'eigenvectors-real': eigenvectors.real.reshape((num_evecs, -1, 3)).tolist(),
+        'eigenvectors-imag': eigenvectors.imag.reshape((num_evecs, -1, 3)).tolist(),
+        'probs': np.array(probs).tolist(),
+    }, fp=sys.stdout, indent=1)
+    print()
+
+if __name__ == '__main__':
+    main()
@@ -0,0 +1,52 @@
+#[macro_use]
+extern crate rsp2_assert_close;
+
+use rsp2_integration_test::{CliTest, filetypes, resource, cli_test, Result};
+use path_abs::PathDir;
+use std::path::Path;
+
+// Default masses used by rsp2.
+const CARBON_MASS: f64 = 12.0107;
+const HYDROGEN_MASS: f64 = 1.00794;
+
+// Deuterating a C-H dimer should lower its stretching frequency by exactly the square root
+// of the ratio of reduced masses, since the force constants do not depend on the masses.
+#[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
+#[test]
+fn deuterated_ch_dimer() -> Result<()> {
+    let env = cli_test::Environment::init();
+    CliTest::cargo_binary(&env, "rsp2")
+        .arg("-c").arg(resource("defaults.yaml"))
+        .arg("-c").arg(resource("ch-dimer-morse.yaml"))
+        .arg(resource("ch-dimer.xyz").as_path())
+        .arg("-o").arg("out")
+        .after_run(|dir: &PathDir| {
+            let env = cli_test::Environment::init();
+            CliTest::cargo_binary(&env, "rsp2")
+                .arg("-c").arg(resource("defaults.yaml"))
+                .arg("-c").arg(resource("ch-dimer-morse.yaml"))
+                .arg(resource("ch-dimer.xyz").as_path())
+                .arg("-o").arg(dir.join("deuterated").as_path())
+                // these compose into a factor of 2
+                .arg("--scale-mass").arg("H:4")
+                .arg("--scale-mass").arg("H:0.5")
+                .run()
+        })
+        .check(|dir| Ok({
+            let original = stretch_frequency(dir.join("out/summary.json"))?;
+            let deuterated = stretch_frequency(dir.join("deuterated/summary.json"))?;
+
+            let reduced_mass = |m1: f64, m2: f64| m1 * m2 / (m1 + m2);
+            let expected_ratio = f64::sqrt(
+                reduced_mass(CARBON_MASS, HYDROGEN_MASS) / reduced_mass(CARBON_MASS, 2.0 * HYDROGEN_MASS),
+            );
+            assert_close!(rel=1e-6, deuterated / original, expected_ratio);
+        }))
+        .run()
+}
+
+// The highest frequency in a summary.json.
+fn stretch_frequency(path: impl AsRef<Path>) -> Result<f64> {
+    let summary = filetypes::SummaryJson::load(path)?;
+    Ok(summary.modes.iter().map(|mode| mode.frequency).fold(std::f64::NEG_INFINITY, f64::max))
+}
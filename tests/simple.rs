@@ -1,9 +1,11 @@
 #[macro_use]
 extern crate rsp2_assert_close;
 
-use rsp2_integration_test::{CliTest, filetypes, resource, cli_test, Result};
+use rsp2_integration_test::{CliTest, CheckFile, filetypes, resource, reference, cli_test, Result};
 use rsp2_structure_io::Poscar;
+use rsp2_array_types::Unvee;
 use path_abs::{FileRead, PathOps};
+use serde_derive::Deserialize;
 use std::path::Path;
 
 // A single raman output file is used for all of these tests.
@@ -75,6 +77,48 @@ fn simple_test_rust() -> Result<()> {
         .run()
 }
 
+// Used by the full-workflow test, whose references were blessed from a run with the same
+// deterministic config.  Only roundoff differences are expected.
+const PRECISE_SUMMARY_TOL: filetypes::SummaryJsonTolerances = filetypes::SummaryJsonTolerances {
+    frequency: PRECISE_RAMAN_TOL.frequency,
+    energy_rel_tol: 1e-9,
+};
+const PRECISE_STRUCTURE_TOL: StructureTolerances = StructureTolerances {
+    lattice_rel_tol: 1e-10,
+    position_abs_tol: 1e-7,
+};
+
+// Regression test for everything produced by a full relax+phonon run, not just raman.json.
+//
+// The references in `simple-out/full-workflow` are the output of an actual run.
+// To regenerate them after an intentional change, run
+//
+//     RSP2_BLESS=1 cargo test --test simple simple_test_full_workflow -- --ignored
+//
+// and review the diff.
+#[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
+#[test]
+fn simple_test_full_workflow() -> Result<()> {
+    let env = cli_test::Environment::init();
+    CliTest::cargo_binary(&env, "rsp2")
+        .arg("-c").arg(resource("defaults.yaml"))
+        .arg("-c").arg(resource("simple-rust.yaml"))
+        .arg("-c").arg(resource("deterministic.yaml"))
+        .arg(resource("simple.vasp").as_path())
+        .arg("-o").arg("out")
+        .check_file::<filetypes::SummaryJson>(
+            "out/summary.json".as_ref(),
+            reference("simple-out/full-workflow/summary.json").as_ref(),
+            PRECISE_SUMMARY_TOL,
+        )
+        .check_file::<FinalStructure>(
+            "out/final.structure/POSCAR".as_ref(),
+            reference("simple-out/full-workflow/final.vasp").as_ref(),
+            PRECISE_STRUCTURE_TOL,
+        )
+        .run()
+}
+
 #[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
 #[test]
 fn simple_test_lammps() -> Result<()> {
@@ -199,6 +243,138 @@ fn simple_test_scale_ranges() -> Result<()> {
         .run()
 }
 
+// Tests that modes outside of the frequency window are left out of the raman output.
+#[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
+#[test]
+fn simple_test_frequency_window() -> Result<()> {
+    let env = cli_test::Environment::init();
+    CliTest::cargo_binary(&env, "rsp2")
+        .arg("-c").arg(resource("defaults.yaml"))
+        .arg("-c").arg(resource("simple-rust.yaml"))
+        .arg("-c").arg(resource("simple-frequency-window.yaml"))
+        .arg(resource("simple.vasp").as_path())
+        .arg("-o").arg("out")
+        .check(|dir| Ok({
+            #[derive(Deserialize)]
+            #[serde(rename_all = "kebab-case")]
+            struct RamanJson {
+                ev_index: Vec<usize>,
+                frequency: Vec<f64>,
+                average_3d: Vec<f64>,
+            }
+            let expected: filetypes::RamanJson = serde_json::from_reader({
+                FileRead::open(resource("simple-out/raman.json"))?
+            })?;
+            let actual: RamanJson = serde_json::from_reader({
+                FileRead::open(dir.join("out/raman.json"))?
+            })?;
+
+            // only the six modes above 900 cm^-1 remain
+            assert_eq!(actual.ev_index, vec![6, 7, 8, 9, 10, 11]);
+            for (k, &i) in actual.ev_index.iter().enumerate() {
+                assert_close!(rel=1e-7, actual.frequency[k], expected.frequency[i]);
+                assert_close!(rel=1e-6, abs=1e-19, actual.average_3d[k], expected.average_3d[i]);
+            }
+        }))
+        .run()
+}
+
+// Forces extra ev-loop iterations on an already stable structure; after the first iteration
+// relaxes it, there should be less and less left to do.
+#[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
+#[test]
+fn simple_test_convergence() -> Result<()> {
+    let env = cli_test::Environment::init();
+    CliTest::cargo_binary(&env, "rsp2")
+        .arg("-c").arg(resource("defaults.yaml"))
+        .arg("-c").arg(resource("simple-rust.yaml"))
+        .arg("-c").arg("ev-loop:{min-positive-iter: 3, max-iter: 3}")
+        .arg(resource("simple.vasp").as_path())
+        .arg("-o").arg("out")
+        .check(|dir| Ok({
+            #[derive(Deserialize)]
+            #[serde(rename_all = "kebab-case")]
+            struct Entry {
+                iteration: u32,
+                structure_change: StructureChange,
+            }
+            #[derive(Deserialize)]
+            struct StructureChange {
+                rms: f64,
+                max: f64,
+            }
+            let entries: Vec<Entry> = serde_json::from_reader({
+                FileRead::open(dir.join("out/convergence.json"))?
+            })?;
+
+            let iterations: Vec<_> = entries.iter().map(|e| e.iteration).collect();
+            assert_eq!(iterations, vec![1, 2, 3]);
+            for entry in &entries {
+                assert!(entry.structure_change.rms <= entry.structure_change.max);
+            }
+            // (with a little slack for roundoff once the structure has settled)
+            for pair in entries.windows(2) {
+                let (prev, next) = (&pair[0].structure_change, &pair[1].structure_change);
+                assert!(next.rms <= prev.rms + 1e-8, "{} > {}", next.rms, prev.rms);
+            }
+            assert!(entries[2].structure_change.rms < entries[0].structure_change.rms);
+        }))
+        .run()
+}
+
+// Holds the bottom layer fixed while the top layer relaxes.
+#[ignore] // This test is expensive; use `cargo test -- --ignored` to run it!
+#[test]
+fn simple_test_frozen_layers() -> Result<()> {
+    let env = cli_test::Environment::init();
+    CliTest::cargo_binary(&env, "rsp2")
+        .arg("-c").arg(resource("defaults.yaml"))
+        .arg("-c").arg(resource("simple-rust.yaml"))
+        .arg("-c").arg("relax.frozen-layers:[0]")
+        .arg(resource("simple.layers.yaml").as_path())
+        .arg("-o").arg("out")
+        .check(|dir| Ok({
+            let input = read_poscar(dir.join("out/initial.structure/POSCAR"))?;
+            let output = read_poscar(dir.join("out/final.structure/POSCAR"))?;
+            let input_carts = input.coords.to_carts().unvee();
+            let output_carts = output.coords.to_carts().unvee();
+
+            // the atoms of layers.yaml are ordered by layer, with two per layer
+            assert_eq!(output_carts[..2], input_carts[..2]);
+        }))
+        .run()
+}
+
 fn read_poscar(path: impl AsRef<Path>) -> Result<Poscar> {
     Ok(Poscar::from_reader(FileRead::open(path)?)?)
 }
+
+/// A relaxed structure, compared by lattice and cartesian positions.
+#[derive(Debug, PartialEq)]
+struct FinalStructure {
+    lattice_norms: [f64; 3],
+    carts: Vec<[f64; 3]>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StructureTolerances {
+    lattice_rel_tol: f64,
+    position_abs_tol: f64,
+}
+
+impl CheckFile for FinalStructure {
+    type OtherArgs = StructureTolerances;
+
+    fn read_file(path: &Path) -> Result<Self> {
+        let poscar = read_poscar(path)?;
+        Ok(FinalStructure {
+            lattice_norms: poscar.coords.lattice().norms(),
+            carts: poscar.coords.to_carts().unvee(),
+        })
+    }
+
+    fn check_against(&self, expected: &Self, tol: StructureTolerances) {
+        assert_close!(rel=tol.lattice_rel_tol, self.lattice_norms, expected.lattice_norms);
+        assert_close!(abs=tol.position_abs_tol, self.carts, expected.carts);
+    }
+}